@@ -86,6 +86,9 @@ fn build_with_pyoxidizer_native(resolve_target: Option<&str>) {
 */
 
 fn main() {
+    // Set by cargo_metadata.txt when the embedded Python has audit hooks.
+    println!("cargo:rustc-check-cfg=cfg(pyembed_audit_hooks)");
+
     if env::var("CARGO_FEATURE_BUILD_MODE_STANDALONE").is_ok() {
     } else if env::var("CARGO_FEATURE_BUILD_MODE_PYOXIDIZER_EXE").is_ok() {
         let target = if let Ok(target) = env::var("PYOXIDIZER_BUILD_TARGET") {
@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Applying audit policies to PEP 578 audit events.
+
+When `PythonConfig.audit_policy` is not empty, an audit hook is installed
+with `PySys_AddAuditHook()`. The hook and its policy live in Rust, so Python
+code can neither inspect nor alter them, and hooks can't be removed once
+installed.
+
+`PySys_AddAuditHook()` was introduced by Python 3.8. This module is only
+compiled when `pyoxidizer` sets the `pyembed_audit_hooks` cfg, which it does
+when embedding such a Python.
+*/
+
+use {
+    super::config::AuditAction,
+    cpython::{ObjectProtocol, PyObject, Python},
+    libc::{c_char, c_int, c_void},
+    python3_sys as pyffi,
+    std::cell::Cell,
+    std::collections::HashMap,
+    std::ffi::{CStr, CString},
+};
+
+type AuditHookFunction = extern "C" fn(*const c_char, *mut pyffi::PyObject, *mut c_void) -> c_int;
+
+extern "C" {
+    fn PySys_AddAuditHook(hook: AuditHookFunction, user_data: *mut c_void) -> c_int;
+}
+
+/// Actions taken on audit events, held by the audit hook.
+struct AuditPolicy {
+    actions: HashMap<String, AuditAction>,
+    default_action: AuditAction,
+}
+
+thread_local! {
+    /// Whether the audit hook is logging an event on this thread.
+    ///
+    /// Formatting event arguments can run Python code raising audit events
+    /// of its own. Those are still allowed or blocked, but not logged.
+    static LOGGING: Cell<bool> = Cell::new(false);
+}
+
+/// Audit hook applying the `AuditPolicy` passed as `user_data`.
+extern "C" fn audit_hook(
+    event: *const c_char,
+    args: *mut pyffi::PyObject,
+    user_data: *mut c_void,
+) -> c_int {
+    let policy = unsafe { &*(user_data as *const AuditPolicy) };
+    let event = unsafe { CStr::from_ptr(event) }.to_string_lossy();
+
+    match policy
+        .actions
+        .get(event.as_ref())
+        .unwrap_or(&policy.default_action)
+    {
+        AuditAction::Allow => 0,
+        AuditAction::Log => {
+            if !LOGGING.with(|logging| logging.replace(true)) {
+                let py = unsafe { Python::assume_gil_acquired() };
+                let args = unsafe { PyObject::from_borrowed_ptr(py, args) };
+
+                // Writing with Rust bypasses sys.stderr, which Python code
+                // may have replaced.
+                match args.repr(py) {
+                    Ok(args) => eprintln!("audit: {} {}", event, args.to_string_lossy(py)),
+                    Err(_) => eprintln!("audit: {}", event),
+                }
+
+                LOGGING.with(|logging| logging.set(false));
+            }
+
+            0
+        }
+        AuditAction::Block => {
+            let message = CString::new(format!("{} is blocked by the audit policy", event))
+                .unwrap_or_default();
+
+            unsafe {
+                pyffi::PyErr_SetString(pyffi::PyExc_PermissionError, message.as_ptr());
+            }
+
+            -1
+        }
+    }
+}
+
+/// Apply an audit policy to audit events raised by Python.
+///
+/// The `*` event defines the action for events not listed.
+pub fn install_audit_hook(policy: &[(String, AuditAction)]) -> Result<(), &'static str> {
+    let mut actions = policy.iter().cloned().collect::<HashMap<_, _>>();
+    let default_action = actions.remove("*").unwrap_or(AuditAction::Allow);
+
+    // The hook can't be removed, so its policy lives as long as the process.
+    let policy = Box::into_raw(Box::new(AuditPolicy {
+        actions,
+        default_action,
+    }));
+
+    match unsafe { PySys_AddAuditHook(audit_hook, policy as *mut c_void) } {
+        0 => Ok(()),
+        _ => Err("could not install audit hook"),
+    }
+}
@@ -6,6 +6,8 @@
 
 use {python3_sys as pyffi, std::ffi::CString};
 
+pub use python_packed_resources::data::FileEmulation;
+
 /// Defines which allocator to use for the raw domain.
 #[derive(Clone, Debug)]
 pub enum PythonRawAllocator {
@@ -34,6 +36,12 @@ pub enum PythonRunMode {
     /// a char* and we want the constructor of this type to worry about
     /// the type coercion.
     File { path: CString },
+    /// Behave like the `python` executable.
+    ///
+    /// Process arguments are interpreted like `python` would interpret them.
+    /// e.g. `-c <code>` evaluates code, `-m <module>` runs a module, and
+    /// a path runs a script. With no arguments, a REPL is started.
+    PythonCli,
 }
 
 /// Defines `terminfo`` database resolution semantics.
@@ -47,6 +55,59 @@ pub enum TerminfoResolution {
     Static(String),
 }
 
+/// Defines how the integrity of embedded resources is verified.
+#[derive(Clone, Debug)]
+pub enum ResourcesIntegrity {
+    /// Do not verify embedded resources.
+    None,
+    /// Verify the content digest of each resource when it is imported.
+    Import,
+    /// Verify the SHA-256 of all embedded resources data at startup.
+    ///
+    /// Holds the expected SHA-256 digest.
+    Startup(&'static [u8]),
+}
+
+/// Defines what happens when Python raises an audit event.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuditAction {
+    /// Let the operation proceed.
+    Allow,
+    /// Write the event and its arguments to stderr and let the operation proceed.
+    Log,
+    /// Refuse the operation by raising `PermissionError`.
+    Block,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Allow => "allow",
+            AuditAction::Log => "log",
+            AuditAction::Block => "block",
+        }
+    }
+}
+
+/// Defines where an application checks for updates.
+#[derive(Clone, Debug)]
+pub struct UpdateConfig {
+    /// URL of the signed update manifest of the application's channel.
+    pub url: String,
+
+    /// Hex encoded Ed25519 public key update manifests are signed with.
+    pub public_key: String,
+
+    /// Version of the running application.
+    pub version: String,
+
+    /// Name of the running application.
+    pub name: String,
+
+    /// Rust target triple the running application was built for.
+    pub target_triple: String,
+}
+
 /// Defines an extra extension module to load.
 #[derive(Clone, Debug)]
 pub struct ExtensionModule {
@@ -66,11 +127,27 @@ pub struct ExtensionModule {
 #[derive(Clone, Debug)]
 pub struct PythonConfig {
     /// Name of encoding for stdio handles.
+    ///
+    /// If only one of `standard_io_encoding` and `standard_io_errors` is
+    /// set, Python's default is used for the other.
     pub standard_io_encoding: Option<String>,
 
     /// Name of encoding error mode for stdio handles.
     pub standard_io_errors: Option<String>,
 
+    /// Whether to enable Python's UTF-8 mode.
+    ///
+    /// UTF-8 is then used for the filesystem encoding and stdio, regardless
+    /// of the locale. See PEP 540.
+    pub utf8_mode: bool,
+
+    /// Code page to set on the Windows console at startup.
+    ///
+    /// e.g. 65001 for UTF-8. The previous code pages are restored when the
+    /// interpreter is finalized. Has no effect on other platforms or when
+    /// the process has no console.
+    pub windows_console_code_page: Option<u32>,
+
     /// Python optimization level.
     pub opt_level: i32,
 
@@ -86,6 +163,23 @@ pub struct PythonConfig {
     /// run-time.
     pub sys_paths: Vec<String>,
 
+    /// Directories holding plugins to add to sys.path after `sys_paths`.
+    ///
+    /// ``$ORIGIN`` will resolve to the directory of the application and
+    /// ``${NAME}`` to the value of environment variable ``NAME`` at
+    /// run-time. Entries referencing undefined environment variables and
+    /// directories that don't exist are ignored.
+    pub filesystem_import_paths: Vec<String>,
+
+    /// Writable directory packages can be installed into at run-time.
+    ///
+    /// The directory is created if needed, added to sys.path after the
+    /// other paths with `site.addsitedir()`, and `PIP_TARGET` is set to it
+    /// so `pip` run by the application installs into it. ``$USER_DATA``
+    /// resolves to the per-user application data directory of the platform,
+    /// in addition to the tokens supported by `filesystem_import_paths`.
+    pub user_site_packages: Option<String>,
+
     /// Controls whether to detect comparing bytes/bytearray with str.
     ///
     /// If 1, issues a warning. If 2 or greater, raises a BytesWarning
@@ -151,6 +245,73 @@ pub struct PythonConfig {
     /// `include_bytes!(...)` of a file generated by PyOxidizer.
     pub embedded_resources_data: &'static [u8],
 
+    /// Path to a file holding packed resources data.
+    ///
+    /// If set, resources are loaded from this file instead of
+    /// `embedded_resources_data`, allowing several executables to share a
+    /// single copy of their resources. The special token ``$ORIGIN`` is
+    /// expanded to the directory of the executable.
+    pub resources_file: Option<String>,
+
+    /// How to verify the integrity of embedded resources data.
+    ///
+    /// If verification fails, the interpreter fails to initialize or the
+    /// offending module fails to import.
+    pub resources_integrity: ResourcesIntegrity,
+
+    /// Environment variable holding the key to decrypt encrypted resources.
+    ///
+    /// The value is 64 hexadecimal characters. If the variable is set, it
+    /// takes precedence over `resources_key_file`.
+    pub resources_key_env: Option<String>,
+
+    /// Path to a file holding the key to decrypt encrypted resources.
+    ///
+    /// The file contains 32 raw bytes or 64 hexadecimal characters. The
+    /// special token ``$ORIGIN`` is expanded to the directory of the
+    /// executable.
+    ///
+    /// The key is only resolved when an encrypted resource is first loaded.
+    pub resources_key_file: Option<String>,
+
+    /// Whether to make `pkg_resources` aware of in-memory resources.
+    ///
+    /// If set, importing `pkg_resources` with the in-memory importer
+    /// registers a resource provider for in-memory packages and adds
+    /// distributions with embedded metadata to `pkg_resources.working_set`.
+    pub pkg_resources_compat: bool,
+
+    /// How `__file__` is set on modules imported from memory.
+    ///
+    /// Individual resources can override this policy.
+    pub file_emulation: FileEmulation,
+
+    /// Directory to extract modules with the extract-on-access `__file__`
+    /// policy to.
+    ///
+    /// The special token ``$ORIGIN`` is expanded to the directory of the
+    /// executable. If not set, a directory in the system's temporary
+    /// directory named after the executable is used.
+    pub file_extraction_dir: Option<String>,
+
+    /// Whether to import in-memory modules lazily.
+    ///
+    /// Lazily imported modules are only executed when one of their
+    /// attributes is first accessed. See `importlib.util.LazyLoader`.
+    pub lazy_import: bool,
+
+    /// Packages to import lazily when `lazy_import` is set.
+    ///
+    /// Entries match the named module and its submodules. If empty, all
+    /// in-memory modules are imported lazily.
+    pub lazy_import_allow: Vec<String>,
+
+    /// Packages to never import lazily.
+    ///
+    /// Entries match the named module and its submodules. `importlib` is
+    /// never imported lazily.
+    pub lazy_import_deny: Vec<String>,
+
     /// Extra extension modules to make available to the interpreter.
     ///
     /// The values will effectively be passed to ``PyImport_ExtendInitTab()``.
@@ -188,6 +349,126 @@ pub struct PythonConfig {
     /// loaded in ``sys.modules``.
     pub write_modules_directory_env: Option<String>,
 
+    /// Environment variable holding the path to write an import profile to.
+    ///
+    /// If this value is set and the environment variable it refers to is set,
+    /// the time spent importing each module and where it was imported from
+    /// are recorded. On interpreter shutdown, a report in the format of
+    /// ``-X importtime`` is written to the path. ``-`` writes to stderr.
+    pub import_profile_env: Option<String>,
+
+    /// Whether the `--pyoxidizer-self-test` argument runs a self-test.
+    ///
+    /// If enabled and the first process argument is `--pyoxidizer-self-test`,
+    /// every embedded top-level package is imported instead of running `run`.
+    /// Failures are reported and cause a non-zero exit code.
+    pub self_test: bool,
+
+    /// Whether the `--debug` argument attaches a console on Windows.
+    ///
+    /// If enabled and `--debug` is passed to an executable built for the
+    /// `windows` subsystem, the console of the parent process is attached,
+    /// or a new console is created, before the interpreter is initialized,
+    /// so stdout and stderr are visible. The argument remains in `sys.argv`.
+    pub debug_console: bool,
+
+    /// Whether to enable `faulthandler` when the interpreter is initialized.
+    ///
+    /// On fatal errors such as segmentation faults, the Python tracebacks of
+    /// all threads are written to stderr, or to `crash_dir` if set.
+    pub faulthandler: bool,
+
+    /// Directory to write crash reports to.
+    ///
+    /// If set, `faulthandler` is enabled and writes to a `crash-<pid>.txt`
+    /// file in this directory. The file is removed on clean shutdown. The
+    /// special string `$ORIGIN` expands to the directory of the executable.
+    pub crash_dir: Option<String>,
+
+    /// Update channel of the application.
+    ///
+    /// If set, the `oxidized_update` module is made available, providing a
+    /// `check_for_update()` function. Requires the `update` feature.
+    pub update: Option<UpdateConfig>,
+
+    /// BMP image to show as a splash screen while the application starts.
+    ///
+    /// The splash screen is closed when Python calls
+    /// `oxidized_splash.close()` or the interpreter is finalized. Only
+    /// shown on Windows. Requires the `splash` feature.
+    pub splash_image: Option<&'static [u8]>,
+
+    /// Whether to keep the environment variables of the parent process.
+    ///
+    /// If false, the environment is cleared before `environment` and the
+    /// `.env` file are applied.
+    pub inherit_environment: bool,
+
+    /// Environment variables to remove from the inherited environment.
+    ///
+    /// Entries ending with `*` remove every variable starting with the
+    /// preceding text, e.g. `PYTHON*`.
+    pub remove_environment: Vec<String>,
+
+    /// Environment variables to set before the interpreter is initialized.
+    ///
+    /// The special string `$ORIGIN` in values expands to the directory of
+    /// the executable.
+    pub environment: Vec<(String, String)>,
+
+    /// Whether to load environment variables from a `.env` file next to
+    /// the executable.
+    ///
+    /// Variables from the file take precedence over `environment`. A
+    /// missing file is ignored.
+    pub dotenv: bool,
+
+    /// Whether Python installs its signal handlers at initialization.
+    ///
+    /// Python's handlers raise `KeyboardInterrupt` on SIGINT and ignore
+    /// SIGPIPE. Applications handling signals on their own may want to
+    /// disable them.
+    pub install_signal_handlers: bool,
+
+    /// Names of signals raising `SystemExit` in Python code, e.g. `SIGTERM`.
+    ///
+    /// When one of these signals is received, `SystemExit` is raised in the
+    /// main thread with exit code 128 plus the signal number, so `finally`
+    /// blocks and `atexit` handlers run before the process exits. Signals
+    /// not available on the platform are ignored.
+    pub forward_signals: Vec<String>,
+
+    /// Function called before the interpreter is finalized.
+    ///
+    /// It is called once the code defined by `run` has returned, including
+    /// after a forwarded signal, while the interpreter is still usable.
+    /// Applications can use it to stop Rust threads or flush state.
+    pub shutdown_hook: Option<fn()>,
+
+    /// Modules Python code may import, if imports are restricted.
+    ///
+    /// Each name matches the module and its sub-modules. Parent packages of
+    /// allowed modules are allowed too. Modules imported while initializing
+    /// the interpreter remain available.
+    pub import_allow: Option<Vec<String>>,
+
+    /// Modules Python code may not import.
+    ///
+    /// Each name matches the module and its sub-modules, as well as the C
+    /// extension modules implementing them, e.g. `socket` matches `_socket`.
+    /// Denied modules imported while initializing the interpreter are
+    /// removed from `sys.modules` and denied built-in extension modules
+    /// can't be imported at all. Takes precedence over `import_allow`.
+    pub import_deny: Vec<String>,
+
+    /// Actions taken when Python raises audit events, keyed by event name.
+    ///
+    /// When not empty, a PEP 578 audit hook applying these actions is
+    /// installed at initialization, which requires Python 3.8 or newer. The
+    /// `*` event defines the action for events not listed, defaulting to
+    /// allowing them. Audit hooks can't be removed once installed.
+    pub audit_policy: Vec<(String, AuditAction)>,
+
     /// Defines what code to run by default.
     ///
     pub run: PythonRunMode,
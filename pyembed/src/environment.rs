@@ -0,0 +1,159 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Control of the process environment seen by the embedded interpreter.
+
+The environment is adjusted before the interpreter is initialized, so
+Python, `os.environ`, and child processes all observe the same values.
+*/
+
+use {super::config::PythonConfig, super::osutils::expand_origin, std::env, std::path::Path};
+
+/// Parse the content of a `.env` file.
+///
+/// Lines are of the form `NAME=value`, optionally prefixed with `export `.
+/// Empty lines and lines starting with `#` are ignored. Values may be
+/// wrapped in single or double quotes, which are removed.
+pub fn parse_dotenv(data: &str) -> Result<Vec<(String, String)>, String> {
+    let mut res = Vec::new();
+
+    for (i, line) in data.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let line = if line.starts_with("export ") {
+            line["export ".len()..].trim_start()
+        } else {
+            line
+        };
+
+        let pos = line
+            .find('=')
+            .ok_or_else(|| format!("line {}: expected NAME=value", i + 1))?;
+        let name = line[0..pos].trim();
+        let value = line[pos + 1..].trim();
+
+        if name.is_empty() {
+            return Err(format!("line {}: empty variable name", i + 1));
+        }
+
+        let value = if value.len() >= 2
+            && ((value.starts_with('"') && value.ends_with('"'))
+                || (value.starts_with('\'') && value.ends_with('\'')))
+        {
+            &value[1..value.len() - 1]
+        } else {
+            value
+        };
+
+        res.push((name.to_string(), value.to_string()));
+    }
+
+    Ok(res)
+}
+
+/// Whether an environment variable name matches a pattern.
+///
+/// Patterns ending with `*` match names starting with the preceding text.
+fn name_matches(name: &str, pattern: &str) -> bool {
+    if pattern.ends_with('*') {
+        name.starts_with(&pattern[0..pattern.len() - 1])
+    } else {
+        name == pattern
+    }
+}
+
+/// Adjust the process environment according to a config.
+///
+/// The inherited environment is cleared or sanitized first, then the
+/// variables in `PythonConfig.environment` are set, then the variables in the
+/// `.env` file next to the executable, if enabled. Later values win. The
+/// special string `$ORIGIN` in values expands to the directory of the
+/// executable.
+pub fn configure_environment(config: &PythonConfig, origin: &str) -> Result<(), &'static str> {
+    if !config.inherit_environment {
+        for (name, _) in env::vars_os() {
+            env::remove_var(name);
+        }
+    } else if !config.remove_environment.is_empty() {
+        for (name, _) in env::vars_os() {
+            let remove = match name.to_str() {
+                Some(s) => config
+                    .remove_environment
+                    .iter()
+                    .any(|pattern| name_matches(s, pattern)),
+                None => false,
+            };
+
+            if remove {
+                env::remove_var(name);
+            }
+        }
+    }
+
+    for (name, value) in &config.environment {
+        env::set_var(name, expand_origin(value, origin));
+    }
+
+    if config.dotenv {
+        let path = Path::new(origin).join(".env");
+
+        // The .env file is optional.
+        if path.exists() {
+            let data = std::fs::read_to_string(&path).or_else(|_| Err("unable to read .env"))?;
+
+            for (name, value) in parse_dotenv(&data).or_else(|_| Err("unable to parse .env"))? {
+                env::set_var(name, expand_origin(&value, origin));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Expand a path containing `$ORIGIN` and `${NAME}` environment variable references.
+///
+/// Returns `None` if a referenced environment variable is not set.
+pub fn expand_path(path: &str, origin: &str) -> Option<String> {
+    let mut res = String::new();
+    let mut remaining = expand_origin(path, origin);
+
+    while let Some(start) = remaining.find("${") {
+        let end = start + remaining[start..].find('}')?;
+        let value = env::var(&remaining[start + 2..end]).ok()?;
+
+        res.push_str(&remaining[0..start]);
+        res.push_str(&value);
+        remaining = remaining[end + 1..].to_string();
+    }
+
+    res.push_str(&remaining);
+
+    Some(res)
+}
+
+/// Obtain the per-user application data directory of the platform.
+///
+/// This is the directory `platformdirs.user_data_dir()` returns, without an
+/// application name appended.
+pub fn user_data_dir() -> Option<String> {
+    if cfg!(windows) {
+        env::var("LOCALAPPDATA").ok()
+    } else if cfg!(target_os = "macos") {
+        env::var("HOME")
+            .ok()
+            .map(|home| format!("{}/Library/Application Support", home))
+    } else {
+        match env::var("XDG_DATA_HOME") {
+            Ok(value) if !value.is_empty() => Some(value),
+            _ => env::var("HOME")
+                .ok()
+                .map(|home| format!("{}/.local/share", home)),
+        }
+    }
+}
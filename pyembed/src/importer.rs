@@ -10,19 +10,29 @@ for importing Python modules from memory.
 */
 
 use {
+    super::config::{FileEmulation, ResourcesIntegrity},
+    super::osutils::extended_length_path,
     super::pyinterp::PYOXIDIZER_IMPORTER_NAME,
-    super::python_resources::{uses_pyembed_importer, PythonImporterState, ResourceFlavor},
+    super::python_resources::{
+        uses_pyembed_importer, PythonImporterState, ResourceEntry, ResourceFlavor,
+    },
     cpython::exc::{FileNotFoundError, ImportError, RuntimeError, ValueError},
     cpython::{
-        py_class, py_fn, NoArgs, ObjectProtocol, PyClone, PyDict, PyErr, PyList, PyModule,
+        py_class, py_fn, NoArgs, ObjectProtocol, PyBytes, PyClone, PyDict, PyErr, PyList, PyModule,
         PyObject, PyResult, PyString, PyTuple, Python, PythonObject, ToPyObject,
     },
     python3_sys as pyffi,
     python3_sys::{PyBUF_READ, PyMemoryView_FromMemory},
+    python_packed_resources::data::Resource,
+    python_packed_resources::encryption::{decrypt, parse_key},
+    sha2::{Digest, Sha256},
     std::borrow::Cow,
-    std::cell::RefCell,
+    std::cell::{Cell, RefCell},
     std::collections::HashMap,
+    std::io::Write,
+    std::path::{Path, PathBuf},
     std::sync::Arc,
+    std::time::{Duration, Instant},
 };
 #[cfg(windows)]
 use {
@@ -31,6 +41,39 @@ use {
     std::ffi::{c_void, CString},
 };
 
+/// Name of the module implementing parts of importlib interfaces in Python.
+const IMPORTLIB_COMPAT_MODULE: &str = "_pyoxidizer_importlib_compat";
+
+/// Source code of the module implementing parts of importlib interfaces.
+const IMPORTLIB_COMPAT_SOURCE: &str = include_str!("importlib_compat.py");
+
+/// Obtain the module implementing parts of importlib interfaces in Python.
+///
+/// The module is created on first use and cached in `sys.modules`.
+fn importlib_compat_module(py: Python) -> PyResult<PyModule> {
+    let sys_modules = py.import("sys")?.get(py, "modules")?;
+    let sys_modules = sys_modules.cast_into::<PyDict>(py)?;
+
+    if let Some(module) = sys_modules.get_item(py, IMPORTLIB_COMPAT_MODULE) {
+        return Ok(module.cast_into::<PyModule>(py)?);
+    }
+
+    let module = PyModule::new(py, IMPORTLIB_COMPAT_MODULE)?;
+    let builtins = py.import("builtins")?;
+    builtins.call(py, "exec", (IMPORTLIB_COMPAT_SOURCE, module.dict(py)), None)?;
+    sys_modules.set_item(py, IMPORTLIB_COMPAT_MODULE, module.as_object())?;
+
+    Ok(module)
+}
+
+/// Normalize a distribution name for comparison.
+///
+/// Distribution names are case insensitive and `-`, `_`, and `.` are
+/// interchangeable.
+fn normalize_distribution_name(name: &str) -> String {
+    name.to_lowercase().replace('-', "_").replace('.', "_")
+}
+
 /// Obtain a Python memoryview referencing a memory slice.
 ///
 /// New memoryview allows Python to access the underlying memory without
@@ -258,6 +301,509 @@ fn load_dynamic_library(
     }
 }
 
+/// Resolves the key for and decrypts encrypted resources.
+///
+/// The key is resolved on first use so it is only needed if encrypted
+/// resources are loaded.
+#[derive(Debug)]
+struct ResourcesDecryptor {
+    key_env: Option<String>,
+    key_file: Option<String>,
+    key: RefCell<Option<Vec<u8>>>,
+}
+
+impl ResourcesDecryptor {
+    fn resolve_key(&self) -> Result<Vec<u8>, String> {
+        if let Some(env) = &self.key_env {
+            if let Ok(value) = std::env::var(env) {
+                return parse_key(value.as_bytes()).map_err(|e| format!("{}: {}", env, e));
+            }
+        }
+
+        if let Some(path) = &self.key_file {
+            let data =
+                std::fs::read(path).map_err(|e| format!("unable to read {}: {}", path, e))?;
+            return parse_key(&data).map_err(|e| format!("{}: {}", path, e));
+        }
+
+        Err("no resources decryption key available".to_string())
+    }
+
+    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        if self.key.borrow().is_none() {
+            self.key.replace(Some(self.resolve_key()?));
+        }
+
+        let key = self.key.borrow();
+
+        decrypt(key.as_ref().unwrap(), data).map_err(|e| e.to_string())
+    }
+}
+
+/// Defines `__file__` for modules imported from memory.
+#[derive(Debug)]
+struct FileEmulator {
+    /// Policy for resources not defining one.
+    default: FileEmulation,
+
+    /// Directory of the executable.
+    origin: PathBuf,
+
+    /// Directory to extract modules with the extract-on-access policy to.
+    extraction_dir: PathBuf,
+}
+
+impl FileEmulator {
+    fn policy(&self, resource: &Resource<u8>) -> FileEmulation {
+        resource.file_emulation.unwrap_or(self.default)
+    }
+
+    /// Resolve the value of `__file__` for a module.
+    ///
+    /// Returns `None` if `__file__` should not be set.
+    fn module_path(&self, resource: &Resource<u8>) -> Option<PathBuf> {
+        let mut path = match self.policy(resource) {
+            FileEmulation::None => return None,
+            FileEmulation::FakePath => self.origin.clone(),
+            FileEmulation::ExtractOnAccess => self.extraction_dir.clone(),
+        };
+
+        for part in resource.name.split('.') {
+            path.push(part);
+        }
+
+        if resource.is_package {
+            path.push("__init__.py");
+        } else {
+            path.set_extension("py");
+        }
+
+        Some(path)
+    }
+
+    /// Write a module's source and resources next to its `__file__`.
+    ///
+    /// Files are only written if their content differs. Encrypted source
+    /// is never written.
+    fn extract(&self, path: &Path, resource: &Resource<u8>) -> std::io::Result<()> {
+        // Extraction directories of applications installed in deep
+        // directories can exceed MAX_PATH on Windows.
+        let path = extended_length_path(path);
+        let dir = path.parent().expect("module path has parent");
+        std::fs::create_dir_all(dir)?;
+
+        if let Some(source) = &resource.in_memory_source {
+            if !resource.is_encrypted {
+                write_if_changed(&path, source)?;
+            }
+        }
+
+        if let Some(resources) = &resource.in_memory_resources {
+            for (name, data) in resources.iter() {
+                if name
+                    .split('/')
+                    .any(|part| part == ".." || part == "." || part.is_empty())
+                {
+                    continue;
+                }
+
+                let dest = name
+                    .split('/')
+                    .fold(dir.to_path_buf(), |p, part| p.join(part));
+                if let Some(parent) = dest.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+
+                write_if_changed(&dest, data)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn write_if_changed(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Ok(existing) = std::fs::read(path) {
+        if existing.as_slice() == data {
+            return Ok(());
+        }
+    }
+
+    std::fs::write(path, data)
+}
+
+/// Decides which modules are imported lazily.
+#[derive(Debug)]
+struct LazyImportPolicy {
+    enabled: bool,
+    allow: Vec<String>,
+    deny: Vec<String>,
+
+    /// `importlib.util.LazyLoader`, resolved on first use.
+    loader_type: RefCell<Option<PyObject>>,
+
+    /// Whether `importlib.util.LazyLoader` is being resolved.
+    resolving: Cell<bool>,
+}
+
+impl LazyImportPolicy {
+    fn matches(packages: &[String], name: &str) -> bool {
+        packages
+            .iter()
+            .any(|package| name == package || name.starts_with(&format!("{}.", package)))
+    }
+
+    /// Whether a module should be imported lazily.
+    fn is_lazy(&self, name: &str) -> bool {
+        // Modules imported to resolve LazyLoader can't be loaded by it.
+        if !self.enabled || self.resolving.get() {
+            return false;
+        }
+
+        if name == "importlib" || name.starts_with("importlib.") {
+            return false;
+        }
+
+        (self.allow.is_empty() || Self::matches(&self.allow, name))
+            && !Self::matches(&self.deny, name)
+    }
+
+    /// Obtain the `importlib.util.LazyLoader` type.
+    fn loader_type(&self, py: Python) -> PyResult<PyObject> {
+        if let Some(loader_type) = &*self.loader_type.borrow() {
+            return Ok(loader_type.clone_ref(py));
+        }
+
+        self.resolving.set(true);
+        let res = py
+            .import("importlib.util")
+            .and_then(|m| m.get(py, "LazyLoader"));
+        self.resolving.set(false);
+
+        let loader_type = res?;
+        self.loader_type.replace(Some(loader_type.clone_ref(py)));
+
+        Ok(loader_type)
+    }
+}
+
+/// C extension modules implementing the functionality of pure Python modules.
+///
+/// Denying a module denies these too, so the functionality can't be reached
+/// by importing the extension module directly. Allowing a module allows them.
+const C_ACCELERATORS: &[(&str, &[&str])] = &[
+    ("ctypes", &["_ctypes"]),
+    ("multiprocessing", &["_multiprocessing", "_posixshmem"]),
+    ("socket", &["_socket"]),
+    ("sqlite3", &["_sqlite3"]),
+    ("ssl", &["_ssl"]),
+    ("subprocess", &["_posixsubprocess"]),
+];
+
+/// Decides which modules Python code may import.
+///
+/// The gate is enforced by `PyOxidizerFinder` once it is enabled, after the
+/// interpreter is initialized. Its state lives in Rust, so Python code can't
+/// alter it.
+#[derive(Debug)]
+pub struct ImportGate {
+    allow: Option<Vec<String>>,
+    deny: Vec<String>,
+    enabled: Cell<bool>,
+}
+
+impl ImportGate {
+    pub fn new(allow: Option<&[String]>, deny: &[String]) -> Self {
+        Self {
+            allow: allow.map(Self::with_accelerators),
+            deny: Self::with_accelerators(deny),
+            enabled: Cell::new(false),
+        }
+    }
+
+    /// Add the C accelerators of modules to a list of modules.
+    fn with_accelerators(modules: &[String]) -> Vec<String> {
+        let mut res = modules.to_vec();
+
+        for (module, accelerators) in C_ACCELERATORS {
+            if LazyImportPolicy::matches(modules, module) {
+                res.extend(accelerators.iter().map(|s| s.to_string()));
+            }
+        }
+
+        res
+    }
+
+    /// Whether a module is denied.
+    pub fn denies(&self, name: &str) -> bool {
+        LazyImportPolicy::matches(&self.deny, name)
+    }
+
+    /// Whether a module may be imported.
+    ///
+    /// Parent packages of allowed modules are allowed too.
+    pub fn allows(&self, name: &str) -> bool {
+        if self.denies(name) {
+            return false;
+        }
+
+        match &self.allow {
+            Some(allow) => {
+                LazyImportPolicy::matches(allow, name)
+                    || allow
+                        .iter()
+                        .any(|module| module.starts_with(&format!("{}.", name)))
+            }
+            None => true,
+        }
+    }
+}
+
+/// Where a module profiled by `ImportProfiler` was imported from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ImportOrigin {
+    Memory,
+    Builtin,
+    Frozen,
+    Filesystem,
+    Failed,
+}
+
+impl ImportOrigin {
+    fn as_str(self) -> &'static str {
+        match self {
+            ImportOrigin::Memory => "memory",
+            ImportOrigin::Builtin => "builtin",
+            ImportOrigin::Frozen => "frozen",
+            ImportOrigin::Filesystem => "filesystem",
+            ImportOrigin::Failed => "failed",
+        }
+    }
+}
+
+/// Import of a module recorded by `ImportProfiler`.
+#[derive(Debug)]
+struct ImportRecord {
+    name: String,
+    /// Number of imports in progress when this import started.
+    depth: usize,
+    /// Time spent in this import, excluding nested imports.
+    self_time: Duration,
+    /// Time spent in this import, including nested imports.
+    cumulative: Duration,
+    origin: ImportOrigin,
+}
+
+#[allow(unused_doc_comments)]
+/// Python type recording the time spent importing modules.
+///
+/// Instances replace `importlib._bootstrap._find_and_load()`, which the
+/// interpreter calls for every module not yet in `sys.modules`. This is
+/// where `-X importtime` measures imports as well.
+py_class!(class ImportProfiler |py| {
+    data find_and_load: PyObject;
+    data sys_module: PyModule;
+    data origins: HashMap<String, ImportOrigin>;
+    data nested_times: RefCell<Vec<Duration>>;
+    data records: RefCell<Vec<ImportRecord>>;
+
+    def __call__(&self, name: &PyString, import_: &PyObject) -> PyResult<PyObject> {
+        let key = name.to_string(py)?.to_string();
+
+        // Only the import lock is taken for modules already imported.
+        let sys_modules = self.sys_module(py).get(py, "modules")?;
+        if sys_modules.cast_as::<PyDict>(py)?.get_item(py, name).is_some() {
+            return self.find_and_load(py).call(py, (name, import_), None);
+        }
+
+        self.nested_times(py).borrow_mut().push(Duration::default());
+        let start = Instant::now();
+        let res = self.find_and_load(py).call(py, (name, import_), None);
+        let cumulative = start.elapsed();
+
+        let mut nested_times = self.nested_times(py).borrow_mut();
+        let nested = nested_times.pop().unwrap_or_default();
+        if let Some(parent) = nested_times.last_mut() {
+            *parent += cumulative;
+        }
+
+        let origin = if res.is_err() {
+            ImportOrigin::Failed
+        } else {
+            self.origins(py).get(&key).cloned().unwrap_or(ImportOrigin::Filesystem)
+        };
+
+        self.records(py).borrow_mut().push(ImportRecord {
+            name: key,
+            depth: nested_times.len(),
+            self_time: cumulative.checked_sub(nested).unwrap_or_default(),
+            cumulative,
+            origin,
+        });
+
+        res
+    }
+});
+
+impl ImportProfiler {
+    /// Write the recorded imports in the format of `-X importtime`.
+    ///
+    /// Imports are listed in completion order, followed by totals per origin.
+    fn write_report(&self, py: Python, w: &mut dyn Write) -> std::io::Result<()> {
+        let records = self.records(py).borrow();
+
+        writeln!(
+            w,
+            "import time: self [us] | cumulative | origin     | imported package"
+        )?;
+
+        for record in records.iter() {
+            writeln!(
+                w,
+                "import time: {:>9} | {:>10} | {:<10} | {}{}",
+                record.self_time.as_micros(),
+                record.cumulative.as_micros(),
+                record.origin.as_str(),
+                "  ".repeat(record.depth),
+                record.name
+            )?;
+        }
+
+        writeln!(w)?;
+        writeln!(w, "import origin | modules | self [us]")?;
+
+        for origin in &[
+            ImportOrigin::Memory,
+            ImportOrigin::Builtin,
+            ImportOrigin::Frozen,
+            ImportOrigin::Filesystem,
+            ImportOrigin::Failed,
+        ] {
+            let matching = records
+                .iter()
+                .filter(|record| record.origin == *origin)
+                .collect::<Vec<_>>();
+
+            if matching.is_empty() {
+                continue;
+            }
+
+            writeln!(
+                w,
+                "{:<13} | {:>7} | {:>9}",
+                origin.as_str(),
+                matching.len(),
+                matching
+                    .iter()
+                    .map(|record| record.self_time)
+                    .sum::<Duration>()
+                    .as_micros()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Write the report of the import profiler.
+///
+/// `path` is a filesystem path or `-` for stderr. Does nothing if imports
+/// weren't profiled.
+pub(crate) fn write_import_profile(py: Python, path: &str) -> Result<(), String> {
+    let find_and_load = py
+        .import("_frozen_importlib")
+        .and_then(|m| m.get(py, "_find_and_load"))
+        .or_else(|_| Err("could not obtain _frozen_importlib._find_and_load".to_string()))?;
+
+    let profiler = match find_and_load.cast_into::<ImportProfiler>(py) {
+        Ok(profiler) => profiler,
+        Err(_) => return Ok(()),
+    };
+
+    let res = if path == "-" {
+        profiler.write_report(py, &mut std::io::stderr())
+    } else {
+        std::fs::File::create(path).and_then(|mut fh| profiler.write_report(py, &mut fh))
+    };
+
+    res.or_else(|e| Err(format!("{}: {}", path, e)))
+}
+
+/// Obtain the names of top-level packages imported by our importer.
+///
+/// Names are sorted. An error is returned if the importer isn't registered
+/// on `sys.meta_path`.
+pub(crate) fn embedded_top_level_packages(py: Python) -> Result<Vec<String>, String> {
+    let meta_path = py
+        .import("sys")
+        .and_then(|m| m.get(py, "meta_path"))
+        .and_then(|o| Ok(o.cast_into::<PyList>(py)?))
+        .or_else(|_| Err("could not obtain sys.meta_path".to_string()))?;
+
+    let finder = meta_path
+        .iter(py)
+        .filter_map(|finder| finder.cast_into::<PyOxidizerFinder>(py).ok())
+        .next()
+        .ok_or_else(|| "importer not registered on sys.meta_path".to_string())?;
+
+    let mut names = finder
+        .importer_state(py)
+        .entries()?
+        .iter()
+        .filter(|entry| {
+            entry.resource.is_package
+                && !entry.resource.name.contains('.')
+                && uses_pyembed_importer(entry)
+        })
+        .map(|entry| entry.resource.name.to_string())
+        .collect::<Vec<_>>();
+
+    names.sort();
+
+    Ok(names)
+}
+
+/// Start refusing imports not allowed by the import gate.
+///
+/// Denied modules imported while initializing the interpreter are removed
+/// from `sys.modules`. An error is returned if the importer isn't registered
+/// on `sys.meta_path`.
+pub(crate) fn enable_import_gate(py: Python) -> Result<(), String> {
+    let sys_module = py
+        .import("sys")
+        .map_err(|_| "could not import sys".to_string())?;
+
+    let finder = sys_module
+        .get(py, "meta_path")
+        .and_then(|o| Ok(o.cast_into::<PyList>(py)?))
+        .map_err(|_| "could not obtain sys.meta_path".to_string())?
+        .iter(py)
+        .filter_map(|finder| finder.cast_into::<PyOxidizerFinder>(py).ok())
+        .next()
+        .ok_or_else(|| "importer not registered on sys.meta_path".to_string())?;
+
+    let import_gate = finder.import_gate(py);
+
+    let sys_modules = sys_module
+        .get(py, "modules")
+        .and_then(|o| Ok(o.cast_into::<PyDict>(py)?))
+        .map_err(|_| "could not obtain sys.modules".to_string())?;
+
+    for (key, _) in sys_modules.items(py) {
+        let name = key.extract::<String>(py).unwrap_or_default();
+
+        if import_gate.denies(&name) {
+            sys_modules
+                .del_item(py, key)
+                .map_err(|_| format!("could not remove {} from sys.modules", name))?;
+        }
+    }
+
+    import_gate.enabled.set(true);
+
+    Ok(())
+}
+
 #[allow(unused_doc_comments, clippy::too_many_arguments)]
 /// Python type to import modules.
 ///
@@ -276,26 +822,73 @@ py_class!(class PyOxidizerFinder |py| {
     data exec_fn: PyObject;
     data importer_state: PythonImporterState<'static, u8>;
     data resource_readers: RefCell<Box<HashMap<String, PyObject>>>;
+    data verify_resources: bool;
+    data resources_decryptor: ResourcesDecryptor;
+    data pkg_resources_compat: bool;
+    data file_emulator: FileEmulator;
+    data lazy_import: LazyImportPolicy;
+    data import_gate: ImportGate;
 
     // Start of importlib.abc.MetaPathFinder interface.
 
     def find_spec(&self, fullname: &PyString, path: &PyObject, target: Option<PyObject> = None) -> PyResult<PyObject> {
         let key = fullname.to_string(py)?;
 
-        if let Some(module) = self.importer_state(py).resources.get(&*key) {
+        let import_gate = self.import_gate(py);
+        if import_gate.enabled.get() && !import_gate.allows(&key) {
+            let kwargs = PyDict::new(py);
+            kwargs.set_item(py, "name", &key)?;
+
+            let err = py.import("builtins")?.call(
+                py,
+                "ModuleNotFoundError",
+                (format!("import of {} is not allowed", key),),
+                Some(&kwargs),
+            )?;
+
+            return Err(PyErr::from_instance(py, err));
+        }
+
+        if let Some(module) = self.resource_entry(py, &key)? {
             if module.flavor == ResourceFlavor::Builtin {
                 // BuiltinImporter.find_spec() always returns None if `path` is defined.
                 // And it doesn't use `target`. So don't proxy these values.
                 self.builtin_importer(py).call_method(py, "find_spec", (fullname,), None)
             } else if module.flavor == ResourceFlavor::Frozen {
                 self.frozen_importer(py).call_method(py, "find_spec", (fullname, path, target), None)
-            } else if uses_pyembed_importer(module) {
-                // TODO consider setting origin and has_location so __file__ will be
-                // populated.
+            } else if uses_pyembed_importer(&module) {
+                if *self.verify_resources(py) && !module.resource.verify_content_digest() {
+                    return Err(PyErr::new::<ImportError, _>(
+                        py,
+                        (format!("{} failed integrity verification", key), key.to_string()),
+                    ));
+                }
+
                 let kwargs = PyDict::new(py);
                 kwargs.set_item(py, "is_package", module.resource.is_package)?;
 
-                self.module_spec_type(py).call(py, (fullname, self), Some(&kwargs))
+                let path = self.file_emulator(py).module_path(&module.resource);
+                if let Some(path) = &path {
+                    kwargs.set_item(py, "origin", path.display().to_string())?;
+                }
+
+                let spec = self.module_spec_type(py).call(py, (fullname, self), Some(&kwargs))?;
+
+                // has_location makes module initialization set __file__ from origin.
+                if path.is_some() {
+                    spec.setattr(py, "has_location", true)?;
+                }
+
+                // Extension modules can't be loaded lazily. See create_module().
+                let lazy_import = self.lazy_import(py);
+                if module.resource.in_memory_extension_module_shared_library.is_none()
+                    && lazy_import.is_lazy(&key)
+                {
+                    let loader = lazy_import.loader_type(py)?.call(py, (self,), None)?;
+                    spec.setattr(py, "loader", loader)?;
+                }
+
+                Ok(spec)
             } else {
                 Ok(py.None())
             }
@@ -328,7 +921,7 @@ py_class!(class PyOxidizerFinder |py| {
         let name = spec.getattr(py, "name")?;
         let key = name.extract::<String>(py)?;
 
-        if let Some(entry) = self.importer_state(py).resources.get(&*key) {
+        if let Some(entry) = self.resource_entry(py, &key)? {
             // We need a custom implementation of create_module() for in-memory shared
             // library extensions because if we wait until `exec_module()` to
             // initialize the module object, this can confuse some CPython
@@ -359,7 +952,7 @@ py_class!(class PyOxidizerFinder |py| {
         let name = module.getattr(py, "__name__")?;
         let key = name.extract::<String>(py)?;
 
-        if let Some(entry) = self.importer_state(py).resources.get(&*key) {
+        if let Some(entry) = self.resource_entry(py, &key)? {
             if entry.flavor == ResourceFlavor::Builtin {
                 self.builtin_importer(py).call_method(py, "exec_module", (module,), None)
             } else if entry.flavor == ResourceFlavor::Frozen {
@@ -369,15 +962,24 @@ py_class!(class PyOxidizerFinder |py| {
                 let imp_module = self.imp_module(py);
 
                 imp_module.as_object().call_method(py, "exec_dynamic", (module,), None)
-            // TODO service other in-memory bytecode fields.
-            } else if entry.resource.in_memory_bytecode.is_some() {
-                match get_memory_view(py, &entry.resource.in_memory_bytecode) {
+            } else if let Some(bytecode) = self.resolve_bytecode(py, &entry.resource)? {
+                self.extract_files(py, &entry.resource)?;
+
+                match self.resource_data(py, &key, bytecode, entry.resource.is_encrypted)? {
                     Some(value) => {
                         let code = self.marshal_loads(py).call(py, (value,), None)?;
                         let exec_fn = self.exec_fn(py);
                         let dict = module.getattr(py, "__dict__")?;
 
-                        self.call_with_frames_removed(py).call(py, (exec_fn, code, dict), None)
+                        let res = self
+                            .call_with_frames_removed(py)
+                            .call(py, (exec_fn, code, dict), None)?;
+
+                        if key == "pkg_resources" && *self.pkg_resources_compat(py) {
+                            self.register_pkg_resources(py, module)?;
+                        }
+
+                        Ok(res)
                     },
                     None => {
                         Err(PyErr::new::<ImportError, _>(py, ("cannot find code in memory", name)))
@@ -400,7 +1002,7 @@ py_class!(class PyOxidizerFinder |py| {
     def get_code(&self, fullname: &PyString) -> PyResult<PyObject> {
         let key = fullname.to_string(py)?;
 
-        if let Some(module) = self.importer_state(py).resources.get(&*key) {
+        if let Some(module) = self.resource_entry(py, &key)? {
             if module.flavor == ResourceFlavor::Frozen {
                 let imp_module = self.imp_module(py);
 
@@ -409,22 +1011,9 @@ py_class!(class PyOxidizerFinder |py| {
                 Ok(py.None())
             } else {
                 let resource = &module.resource;
-                let sys_module = self.sys_module(py);
-                let flags = sys_module.get(py, "flags")?;
-                let flags: i64 = flags.extract(py)?;
-
-                let bytecode = if flags == 0 && resource.in_memory_bytecode.is_some() {
-                    &resource.in_memory_bytecode
-                } else if flags == 1 && resource.in_memory_bytecode_opt1.is_some() {
-                    &resource.in_memory_bytecode_opt1
-                } else if flags == 2 && resource.in_memory_bytecode_opt2.is_some() {
-                    &resource.in_memory_bytecode_opt2
-                } else {
-                    &None
-                };
 
-                if bytecode.is_some() {
-                    match get_memory_view(py, bytecode) {
+                if let Some(bytecode) = self.resolve_bytecode(py, resource)? {
+                    match self.resource_data(py, &key, bytecode, resource.is_encrypted)? {
                         Some(value) => {
                             self.marshal_loads(py).call(py, (value,), None)
                         }
@@ -444,15 +1033,19 @@ py_class!(class PyOxidizerFinder |py| {
     def get_source(&self, fullname: &PyString) -> PyResult<PyObject> {
         let key = fullname.to_string(py)?;
 
-        if let Some(module) = self.importer_state(py).resources.get(&*key) {
+        if let Some(module) = self.resource_entry(py, &key)? {
             let resource = &module.resource;
             if resource.in_memory_source.is_some() {
-                match get_memory_view(py, &resource.in_memory_source) {
+                match self.resource_data(py, &key, &resource.in_memory_source, resource.is_encrypted)? {
                     Some(value) => {
                         // decode_source (from importlib._bootstrap_external)
                         // can't handle memoryview. So we take the memory hit and
                         // cast to bytes.
-                        let b = value.call_method(py, "tobytes", NoArgs, None)?;
+                        let b = if resource.is_encrypted {
+                            value
+                        } else {
+                            value.call_method(py, "tobytes", NoArgs, None)?
+                        };
                         self.decode_source(py).call(py, (b,), None)
                     },
                     None => {
@@ -488,7 +1081,7 @@ py_class!(class PyOxidizerFinder |py| {
         }
 
         // Only create a reader if the name is a package.
-        if let Some(module) = self.importer_state(py).resources.get(&*key) {
+        if let Some(module) = self.resource_entry(py, &key)? {
             let resource = &module.resource;
             if !resource.is_package {
                 return Ok(py.None())
@@ -510,8 +1103,213 @@ py_class!(class PyOxidizerFinder |py| {
             Ok(py.None())
         }
     }
+
+    // Start of importlib.metadata interface.
+
+    // Resolve distributions with embedded metadata.
+    //
+    // `context` is an `importlib.metadata.DistributionFinder.Context`. Only
+    // its `name` is consulted, as distributions don't have a path.
+    def find_distributions(&self, context: Option<PyObject> = None) -> PyResult<PyObject> {
+        let name = match &context {
+            Some(context) => {
+                let name = context.getattr(py, "name")?;
+
+                if name == py.None() {
+                    None
+                } else {
+                    Some(name.extract::<String>(py)?)
+                }
+            }
+            None => None,
+        };
+
+        let readers = self
+            .distribution_readers(py, name.as_ref().map(|x| x.as_str()))?
+            .into_iter()
+            .map(|(_, reader)| reader)
+            .collect::<Vec<_>>();
+
+        let context = match context {
+            Some(context) => context,
+            None => py.None(),
+        };
+
+        importlib_compat_module(py)?.call(
+            py,
+            "find_distributions",
+            (context, PyList::new(py, &readers)),
+            None,
+        )
+    }
+
+    // End of importlib.metadata interface.
 });
 
+impl PyOxidizerFinder {
+    /// Obtain the resource entry for a module name.
+    fn resource_entry(
+        &self,
+        py: Python,
+        name: &str,
+    ) -> PyResult<Option<ResourceEntry<'static, u8>>> {
+        self.importer_state(py)
+            .get(name)
+            .map_err(|e| PyErr::new::<ValueError, _>(py, e))
+    }
+
+    /// Write files of a module having the extract-on-access `__file__` policy.
+    fn extract_files(&self, py: Python, resource: &Resource<u8>) -> PyResult<()> {
+        let emulator = self.file_emulator(py);
+
+        if emulator.policy(resource) != FileEmulation::ExtractOnAccess {
+            return Ok(());
+        }
+
+        if let Some(path) = emulator.module_path(resource) {
+            emulator.extract(&path, resource).or_else(|e| {
+                Err(PyErr::new::<ImportError, _>(
+                    py,
+                    (
+                        format!(
+                            "unable to extract {} to {}: {}",
+                            resource.name,
+                            path.display(),
+                            e
+                        ),
+                        resource.name.to_string(),
+                    ),
+                ))
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Obtain resource readers over embedded distribution metadata.
+    ///
+    /// Returns `(name, reader)` pairs sorted by distribution name. If `name`
+    /// is defined, only distributions with that name are returned.
+    fn distribution_readers(
+        &self,
+        py: Python,
+        name: Option<&str>,
+    ) -> PyResult<Vec<(String, PyObject)>> {
+        let name = name.map(normalize_distribution_name);
+
+        let entries = self
+            .importer_state(py)
+            .entries()
+            .map_err(|e| PyErr::new::<ValueError, _>(py, e))?;
+
+        let mut distributions = entries
+            .iter()
+            .filter_map(|entry| {
+                if let Some(distribution) = &entry.resource.in_memory_package_distribution {
+                    Some((&entry.resource.name, distribution))
+                } else {
+                    None
+                }
+            })
+            .filter(|(key, _)| match &name {
+                Some(name) => &normalize_distribution_name(key) == name,
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        distributions.sort_by_key(|(key, _)| *key);
+
+        let mut res = Vec::with_capacity(distributions.len());
+        for (key, distribution) in distributions {
+            let reader = PyOxidizerResourceReader::create_instance(
+                py,
+                Arc::new(Box::new(distribution.clone())),
+            )?;
+
+            res.push((key.to_string(), reader.into_object()));
+        }
+
+        Ok(res)
+    }
+
+    /// Make a `pkg_resources` module aware of in-memory resources.
+    fn register_pkg_resources(&self, py: Python, pkg_resources: &PyObject) -> PyResult<PyObject> {
+        let distributions = self.distribution_readers(py, None)?;
+
+        importlib_compat_module(py)?.call(
+            py,
+            "register_pkg_resources",
+            (
+                pkg_resources,
+                self.as_object(),
+                distributions.to_py_object(py),
+            ),
+            None,
+        )
+    }
+
+    /// Obtain the in-memory bytecode field to load a resource from.
+    ///
+    /// Bytecode matching the interpreter's optimization level
+    /// (`sys.flags.optimize`) is preferred. If the resource doesn't have
+    /// bytecode at that level, bytecode at another level is used.
+    fn resolve_bytecode<'r>(
+        &self,
+        py: Python,
+        resource: &'r Resource<'static, u8>,
+    ) -> PyResult<Option<&'r Option<Cow<'static, [u8]>>>> {
+        let flags = self.sys_module(py).get(py, "flags")?;
+        let optimize: i64 = flags.getattr(py, "optimize")?.extract(py)?;
+
+        let preferred = match optimize {
+            0 => &resource.in_memory_bytecode,
+            1 => &resource.in_memory_bytecode_opt1,
+            _ => &resource.in_memory_bytecode_opt2,
+        };
+
+        if preferred.is_some() {
+            return Ok(Some(preferred));
+        }
+
+        Ok([
+            &resource.in_memory_bytecode,
+            &resource.in_memory_bytecode_opt1,
+            &resource.in_memory_bytecode_opt2,
+        ]
+        .iter()
+        .find(|bytecode| bytecode.is_some())
+        .cloned())
+    }
+
+    /// Obtain a Python object holding in-memory module data.
+    ///
+    /// Unencrypted data is exposed as a `memoryview` without copying.
+    /// Encrypted data is decrypted into a new `bytes`.
+    fn resource_data(
+        &self,
+        py: Python,
+        name: &str,
+        data: &Option<Cow<'static, [u8]>>,
+        is_encrypted: bool,
+    ) -> PyResult<Option<PyObject>> {
+        if !is_encrypted {
+            return Ok(get_memory_view(py, data));
+        }
+
+        if let Some(data) = data {
+            let data = self.resources_decryptor(py).decrypt(data).map_err(|e| {
+                PyErr::new::<ImportError, _>(
+                    py,
+                    (format!("unable to decrypt {}: {}", name, e), name),
+                )
+            })?;
+
+            Ok(Some(PyBytes::new(py, &data).into_object()))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
 #[allow(unused_doc_comments)]
 /// Implements in-memory reading of resource data.
 ///
@@ -582,6 +1380,11 @@ py_class!(class PyOxidizerResourceReader |py| {
 
         Ok(names_list.as_object().clone_ref(py))
     }
+
+    /// Returns an importlib.abc.Traversable for the resources.
+    def files(&self) -> PyResult<PyObject> {
+        importlib_compat_module(py)?.call(py, "OxidizedTraversable", (self.as_object(),), None)
+    }
 });
 
 const DOC: &[u8] = b"Binary representation of Python modules\0";
@@ -597,6 +1400,45 @@ pub struct InitModuleState {
 
     /// Raw data describing embedded resources.
     pub embedded_resources_data: &'static [u8],
+
+    /// How to verify the integrity of embedded resources.
+    pub resources_integrity: ResourcesIntegrity,
+
+    /// Environment variable holding the resources decryption key.
+    pub resources_key_env: Option<String>,
+
+    /// Path to file holding the resources decryption key.
+    pub resources_key_file: Option<String>,
+
+    /// Whether to register in-memory resources with `pkg_resources`.
+    pub pkg_resources_compat: bool,
+
+    /// Default policy for setting `__file__`.
+    pub file_emulation: FileEmulation,
+
+    /// Directory of the executable, used for fake `__file__` paths.
+    pub file_emulation_origin: String,
+
+    /// Directory to extract modules with the extract-on-access policy to.
+    pub file_extraction_dir: String,
+
+    /// Whether to import in-memory modules lazily.
+    pub lazy_import: bool,
+
+    /// Packages to import lazily. Empty means all.
+    pub lazy_import_allow: Vec<String>,
+
+    /// Packages to never import lazily.
+    pub lazy_import_deny: Vec<String>,
+
+    /// Whether to record the time spent importing modules.
+    pub import_profile: bool,
+
+    /// Modules Python code may import, if imports are restricted.
+    pub import_allow: Option<Vec<String>>,
+
+    /// Modules Python code may not import.
+    pub import_deny: Vec<String>,
 }
 
 /// Holds reference to next module state struct.
@@ -621,6 +1463,45 @@ struct ModuleState {
     /// Raw data constituting embedded resources.
     embedded_resources_data: &'static [u8],
 
+    /// How to verify the integrity of embedded resources.
+    resources_integrity: ResourcesIntegrity,
+
+    /// Environment variable holding the resources decryption key.
+    resources_key_env: Option<String>,
+
+    /// Path to file holding the resources decryption key.
+    resources_key_file: Option<String>,
+
+    /// Whether to register in-memory resources with `pkg_resources`.
+    pkg_resources_compat: bool,
+
+    /// Default policy for setting `__file__`.
+    file_emulation: FileEmulation,
+
+    /// Directory of the executable, used for fake `__file__` paths.
+    file_emulation_origin: String,
+
+    /// Directory to extract modules with the extract-on-access policy to.
+    file_extraction_dir: String,
+
+    /// Whether to import in-memory modules lazily.
+    lazy_import: bool,
+
+    /// Packages to import lazily. Empty means all.
+    lazy_import_allow: Vec<String>,
+
+    /// Packages to never import lazily.
+    lazy_import_deny: Vec<String>,
+
+    /// Whether to record the time spent importing modules.
+    import_profile: bool,
+
+    /// Modules Python code may import, if imports are restricted.
+    import_allow: Option<Vec<String>>,
+
+    /// Modules Python code may not import.
+    import_deny: Vec<String>,
+
     /// Whether setup() has been called.
     setup_called: bool,
 }
@@ -661,6 +1542,19 @@ fn module_init(py: Python, m: &PyModule) -> PyResult<()> {
         // TODO we could move the value if we wanted to avoid the clone().
         state.sys_paths = (*NEXT_MODULE_STATE).sys_paths.clone();
         state.embedded_resources_data = (*NEXT_MODULE_STATE).embedded_resources_data;
+        state.resources_integrity = (*NEXT_MODULE_STATE).resources_integrity.clone();
+        state.resources_key_env = (*NEXT_MODULE_STATE).resources_key_env.clone();
+        state.resources_key_file = (*NEXT_MODULE_STATE).resources_key_file.clone();
+        state.pkg_resources_compat = (*NEXT_MODULE_STATE).pkg_resources_compat;
+        state.file_emulation = (*NEXT_MODULE_STATE).file_emulation;
+        state.file_emulation_origin = (*NEXT_MODULE_STATE).file_emulation_origin.clone();
+        state.file_extraction_dir = (*NEXT_MODULE_STATE).file_extraction_dir.clone();
+        state.lazy_import = (*NEXT_MODULE_STATE).lazy_import;
+        state.lazy_import_allow = (*NEXT_MODULE_STATE).lazy_import_allow.clone();
+        state.lazy_import_deny = (*NEXT_MODULE_STATE).lazy_import_deny.clone();
+        state.import_profile = (*NEXT_MODULE_STATE).import_profile;
+        state.import_allow = (*NEXT_MODULE_STATE).import_allow.clone();
+        state.import_deny = (*NEXT_MODULE_STATE).import_deny.clone();
     }
 
     state.setup_called = false;
@@ -731,12 +1625,57 @@ fn module_setup(
     let builtin_importer = meta_path.get_item(py, 0);
     let frozen_importer = meta_path.get_item(py, 1);
 
+    if let ResourcesIntegrity::Startup(expected) = &state.resources_integrity {
+        let mut hasher = Sha256::new();
+        hasher.input(state.embedded_resources_data);
+
+        if hasher.result().as_slice() != *expected {
+            return Err(PyErr::new::<ValueError, _>(
+                py,
+                "embedded resources data failed integrity verification",
+            ));
+        }
+    }
+
     let mut importer_state = PythonImporterState::default();
 
     if let Err(e) = importer_state.load(state.embedded_resources_data) {
         return Err(PyErr::new::<ValueError, _>(py, e));
     }
 
+    // Builtin and frozen modules are registered in the importer state, so
+    // anything else imported successfully came from the filesystem.
+    let import_profiler = if state.import_profile {
+        let entries = importer_state
+            .entries()
+            .map_err(|e| PyErr::new::<ValueError, _>(py, e))?;
+
+        let origins = entries
+            .iter()
+            .filter_map(|entry| {
+                let origin = match entry.flavor {
+                    ResourceFlavor::Builtin => ImportOrigin::Builtin,
+                    ResourceFlavor::Frozen => ImportOrigin::Frozen,
+                    ResourceFlavor::Packed if uses_pyembed_importer(entry) => ImportOrigin::Memory,
+                    ResourceFlavor::Packed => return None,
+                };
+
+                Some((entry.resource.name.to_string(), origin))
+            })
+            .collect::<HashMap<_, _>>();
+
+        Some(ImportProfiler::create_instance(
+            py,
+            bootstrap_module.get(py, "_find_and_load")?,
+            sys_module.clone_ref(py),
+            origins,
+            RefCell::new(Vec::new()),
+            RefCell::new(Vec::new()),
+        )?)
+    } else {
+        None
+    };
+
     let marshal_loads = marshal_module.get(py, "loads")?;
     let call_with_frames_removed = bootstrap_module.get(py, "_call_with_frames_removed")?;
     let module_spec_type = bootstrap_module.get(py, "ModuleSpec")?;
@@ -778,10 +1717,38 @@ fn module_setup(
         exec_fn,
         importer_state,
         resource_readers,
+        matches!(state.resources_integrity, ResourcesIntegrity::Import),
+        ResourcesDecryptor {
+            key_env: state.resources_key_env.clone(),
+            key_file: state.resources_key_file.clone(),
+            key: RefCell::new(None),
+        },
+        state.pkg_resources_compat,
+        FileEmulator {
+            default: state.file_emulation,
+            origin: PathBuf::from(&state.file_emulation_origin),
+            extraction_dir: PathBuf::from(&state.file_extraction_dir),
+        },
+        LazyImportPolicy {
+            enabled: state.lazy_import,
+            allow: state.lazy_import_allow.clone(),
+            deny: state.lazy_import_deny.clone(),
+            loader_type: RefCell::new(None),
+            resolving: Cell::new(false),
+        },
+        ImportGate::new(
+            state.import_allow.as_ref().map(|v| v.as_slice()),
+            &state.import_deny,
+        ),
     )?;
     meta_path_object.call_method(py, "clear", NoArgs, None)?;
     meta_path_object.call_method(py, "append", (unified_importer,), None)?;
 
+    // The interpreter looks up _find_and_load() on the module for every import.
+    if let Some(import_profiler) = import_profiler {
+        bootstrap_module.add(py, "_find_and_load", import_profiler)?;
+    }
+
     // At this point the importing mechanism is fully initialized to use our
     // unified importer, which handles built-in, frozen, and in-memory imports.
 
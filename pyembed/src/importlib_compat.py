@@ -0,0 +1,285 @@
+# This Source Code Form is subject to the terms of the Mozilla Public
+# License, v. 2.0. If a copy of the MPL was not distributed with this
+# file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+"""Parts of importlib interfaces for the PyOxidizer importer.
+
+This module is executed by the PyOxidizer importer on first use. It
+implements the importlib.resources and importlib.metadata protocols that
+need Python classes on top of the resource readers provided by the importer.
+It also implements the pkg_resources integration.
+"""
+
+import io
+import os
+import posixpath
+import sys
+
+
+class OxidizedTraversable:
+    """importlib.abc.Traversable over in-memory resources.
+
+    ``reader`` is a resource reader whose ``contents()`` are ``/`` delimited
+    paths. ``path`` is the path within the reader, with ``""`` being the root.
+    """
+
+    def __init__(self, reader, path=""):
+        self._reader = reader
+        self._path = path
+
+    def __repr__(self):
+        return "<OxidizedTraversable %r>" % self._path
+
+    def __str__(self):
+        return self._path
+
+    @property
+    def name(self):
+        return posixpath.basename(self._path)
+
+    @property
+    def parent(self):
+        return OxidizedTraversable(self._reader, posixpath.dirname(self._path))
+
+    def _children(self):
+        prefix = self._path + "/" if self._path else ""
+        children = set()
+
+        for name in self._reader.contents():
+            if name.startswith(prefix) and len(name) > len(prefix):
+                children.add(name[len(prefix) :].split("/")[0])
+
+        return sorted(children)
+
+    def is_file(self):
+        return self._path in self._reader.contents()
+
+    def is_dir(self):
+        return not self._path or bool(self._children())
+
+    def iterdir(self):
+        for child in self._children():
+            yield self.joinpath(child)
+
+    def joinpath(self, *descendants):
+        return OxidizedTraversable(
+            self._reader, posixpath.join(self._path, *descendants)
+        )
+
+    __truediv__ = joinpath
+
+    def open(self, mode="r", *args, **kwargs):
+        if not self.is_file():
+            raise FileNotFoundError(self._path)
+
+        fh = self._reader.open_resource(self._path)
+
+        if mode == "rb":
+            return fh
+        elif mode == "r":
+            return io.TextIOWrapper(fh, *args, **kwargs)
+        else:
+            raise ValueError("unsupported mode: %r" % mode)
+
+    def read_bytes(self):
+        with self.open("rb") as fh:
+            return fh.read()
+
+    def read_text(self, encoding=None):
+        with self.open(encoding=encoding) as fh:
+            return fh.read()
+
+
+def _metadata_module(context):
+    if context is not None:
+        # Use the importlib.metadata implementation asking for distributions,
+        # which may be the importlib_metadata backport.
+        module = sys.modules.get(type(context).__module__)
+
+        if hasattr(module, "PathDistribution"):
+            return module
+
+    try:
+        import importlib.metadata as module
+    except ImportError:
+        import importlib_metadata as module
+
+    return module
+
+
+def find_distributions(context, readers):
+    """Resolve importlib.metadata distributions.
+
+    ``readers`` are resource readers over the files in the metadata directory
+    of each distribution.
+    """
+    metadata = _metadata_module(context)
+
+    return [
+        metadata.PathDistribution(OxidizedTraversable(reader)) for reader in readers
+    ]
+
+
+def _normalize_name(name):
+    return name.lower().replace("-", "_").replace(".", "_")
+
+
+class OxidizedPkgResourcesProvider:
+    """pkg_resources resource and metadata provider for in-memory packages.
+
+    ``resources`` and ``metadata`` are traversables over the resources of a
+    package and the metadata of its distribution. Either can be None.
+    """
+
+    def __init__(self, name, resources, metadata):
+        self._name = name
+        self._resources = resources
+        self._metadata = metadata
+
+    # IMetadataProvider interface.
+
+    def has_metadata(self, name):
+        return self._metadata is not None and self._metadata.joinpath(name).is_file()
+
+    def get_metadata(self, name):
+        if not self.has_metadata(name):
+            raise FileNotFoundError(name)
+
+        return self._metadata.joinpath(name).read_text(encoding="utf-8")
+
+    def get_metadata_lines(self, name):
+        for line in self.get_metadata(name).splitlines():
+            line = line.strip()
+
+            if line and not line.startswith("#"):
+                yield line
+
+    def metadata_isdir(self, name):
+        return self._metadata is not None and self._metadata.joinpath(name).is_dir()
+
+    def metadata_listdir(self, name):
+        if not self.metadata_isdir(name):
+            return []
+
+        return [p.name for p in self._metadata.joinpath(name).iterdir()]
+
+    def run_script(self, script_name, namespace):
+        source = self.get_metadata("scripts/" + script_name)
+        source = source.replace("\r\n", "\n").replace("\r", "\n")
+        exec(compile(source, script_name, "exec"), namespace, namespace)
+
+    # IResourceProvider interface.
+
+    def _resource(self, resource_name):
+        if self._resources is None:
+            raise FileNotFoundError(resource_name)
+
+        return self._resources.joinpath(resource_name)
+
+    def has_resource(self, resource_name):
+        return self._resources is not None and (
+            self._resources.joinpath(resource_name).is_file()
+        )
+
+    def resource_isdir(self, resource_name):
+        return self._resources is not None and (
+            self._resources.joinpath(resource_name).is_dir()
+        )
+
+    def resource_listdir(self, resource_name):
+        if not self.resource_isdir(resource_name):
+            return []
+
+        return [p.name for p in self._resources.joinpath(resource_name).iterdir()]
+
+    def get_resource_stream(self, manager, resource_name):
+        return self._resource(resource_name).open("rb")
+
+    def get_resource_string(self, manager, resource_name):
+        return self._resource(resource_name).read_bytes()
+
+    def get_resource_filename(self, manager, resource_name):
+        """Extract a resource to the pkg_resources extraction cache.
+
+        In-memory resources don't have filenames. So they are materialized
+        like resources in zipped eggs.
+        """
+        resource = self._resource(resource_name)
+
+        if resource.is_dir():
+            for child in resource.iterdir():
+                self.get_resource_filename(
+                    manager, posixpath.join(resource_name, child.name)
+                )
+
+            return manager.get_cache_path(self._name, resource_name.split("/"))
+
+        data = resource.read_bytes()
+        path = manager.get_cache_path(self._name, resource_name.split("/"))
+
+        if os.path.isfile(path):
+            with open(path, "rb") as fh:
+                if fh.read() == data:
+                    return path
+
+        with open(path, "wb") as fh:
+            fh.write(data)
+
+        manager.postprocess(path, path)
+
+        return path
+
+
+def register_pkg_resources(pkg_resources, finder, distributions):
+    """Make pkg_resources aware of in-memory packages.
+
+    ``finder`` is the PyOxidizer importer. ``distributions`` are
+    ``(name, reader)`` tuples for distributions with embedded metadata.
+    """
+    metadatas = [(name, OxidizedTraversable(reader)) for name, reader in distributions]
+
+    def find_metadata(package):
+        top_level = package.partition(".")[0]
+
+        for name, metadata in metadatas:
+            top_level_txt = metadata.joinpath("top_level.txt")
+
+            if top_level_txt.is_file() and top_level in top_level_txt.read_text(
+                encoding="utf-8"
+            ).split():
+                return metadata
+
+        for name, metadata in metadatas:
+            if _normalize_name(name) == _normalize_name(top_level):
+                return metadata
+
+        return None
+
+    def provider_factory(module):
+        if hasattr(module, "__path__"):
+            package = module.__name__
+        else:
+            package = module.__name__.rpartition(".")[0]
+
+        reader = finder.get_resource_reader(package) if package else None
+        resources = OxidizedTraversable(reader) if reader is not None else None
+
+        return OxidizedPkgResourcesProvider(
+            package, resources, find_metadata(package) if package else None
+        )
+
+    pkg_resources.register_loader_type(type(finder), provider_factory)
+
+    for name, metadata in metadatas:
+        provider = OxidizedPkgResourcesProvider(name, None, metadata)
+
+        if provider.has_metadata("METADATA"):
+            cls = pkg_resources.DistInfoDistribution
+        else:
+            cls = pkg_resources.Distribution
+
+        # Distributions need a location to be iterated by the working set.
+        # The metadata is in the executable, so use it.
+        pkg_resources.working_set.add(
+            cls(location=sys.executable, metadata=provider, project_name=name)
+        )
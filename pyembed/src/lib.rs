@@ -40,7 +40,8 @@ interfacing.
 as possible.** This is because we want to minimize bloat in produced binaries.
 At this time, we have required direct dependencies on published versions of the
 `lazy_static`, `libc`, `python-packed-resources`, and `uuid` crates. We also have
-an optional direct dependency on the `jemalloc-sys` crate. Via the `cpython` crate,
+optional direct dependencies on the `jemalloc-sys`, `ed25519-dalek`, and (on Windows)
+`winapi` crates. Via the `cpython` crate,
 we also have an indirect dependency on the `num-traits` crate. Via the
 `python-packed-resources` crate, we have indirect dependencies on the `byteorder`
 and `anyhow` crates.
@@ -57,6 +58,14 @@ from Python is a run-time configuration option controlled by the
 `PythonConfig` type and having `jemalloc` compiled into the binary does not
 mean it is being used!
 
+The optional `update` feature controls support for the `oxidized_update`
+Python module, which checks for signed application updates. It is used when
+`PythonConfig.update` is defined.
+
+The optional `splash` feature controls support for showing a splash screen
+while the interpreter initializes. It is used when `PythonConfig.splash_image`
+is defined.
+
 There exist mutually exclusive `build-mode-*` features to control how the
 `build.rs` build script works.
 
@@ -71,6 +80,10 @@ to build required artifacts.
 environment variable can refer to the directory containing build artifacts
 that this crate needs. If not set, `OUT_DIR` will be used.
 
+Tests running a Python interpreter are only built in this mode and require
+`PYOXIDIZER_ARTIFACT_DIR`, as they use the default configuration of the
+artifacts in it.
+
 The exist mutually exclusive `cpython-link-*` features to control how
 the `cpython`/`python3-sys` crates are built.
 
@@ -84,18 +97,26 @@ That crate's build script will attempt to find a `libpython` from the
 
 */
 
+#[cfg(pyembed_audit_hooks)]
+mod audit;
 mod config;
+mod environment;
 mod importer;
 mod osutils;
 mod pyalloc;
 mod pyinterp;
 mod pystr;
 mod python_resources;
+#[cfg(feature = "splash")]
+mod splash;
 pub mod technotes;
+#[cfg(feature = "update")]
+mod update;
 
 #[allow(unused_imports)]
 pub use crate::config::{
-    ExtensionModule, PythonConfig, PythonRawAllocator, PythonRunMode, TerminfoResolution,
+    AuditAction, ExtensionModule, FileEmulation, PythonConfig, PythonRawAllocator, PythonRunMode,
+    ResourcesIntegrity, TerminfoResolution, UpdateConfig,
 };
 
 #[allow(unused_imports)]
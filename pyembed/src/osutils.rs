@@ -145,3 +145,140 @@ pub fn resolve_terminfo_dirs() -> Option<String> {
         OsVariant::Other => None,
     }
 }
+
+/// Obtain the path of the running executable.
+///
+/// Symlinks are resolved, so the directory of the executable, which
+/// `$ORIGIN` expands to, is the directory holding the executable file even
+/// when it is run through a link, e.g. from a `bin` directory. The path as
+/// reported by the operating system is used if it can't be resolved, e.g.
+/// when the executable was moved while running.
+///
+/// WASI has no notion of executable paths. There, the WebAssembly module is
+/// assumed to live in the current directory, which the runtime must grant
+/// access to (e.g. `wasmtime --dir=.`). So `$ORIGIN` resolves to `.`.
+pub fn current_exe() -> std::io::Result<PathBuf> {
+    if cfg!(target_os = "wasi") {
+        let name = std::env::args_os()
+            .next()
+            .and_then(|arg| Path::new(&arg).file_name().map(|name| name.to_owned()))
+            .unwrap_or_else(|| "python.wasm".into());
+
+        Ok(Path::new(".").join(name))
+    } else {
+        let exe = std::env::current_exe()?;
+
+        Ok(match std::fs::canonicalize(&exe) {
+            Ok(path) => strip_verbatim_prefix(path),
+            Err(_) => exe,
+        })
+    }
+}
+
+/// Remove the `\\?\` prefix of verbatim paths on Windows.
+///
+/// `canonicalize()` returns verbatim paths on Windows. `/` isn't a separator
+/// in them, so paths like `$ORIGIN/lib` expanded from them don't resolve.
+pub fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    let stripped = match path.to_str() {
+        Some(s) if s.starts_with(r"\\?\UNC\") => Some(format!(r"\\{}", &s[8..])),
+        Some(s) if s.starts_with(r"\\?\") && s[4..].chars().nth(1) == Some(':') => {
+            Some(s[4..].to_string())
+        }
+        _ => None,
+    };
+
+    match stripped {
+        Some(s) => PathBuf::from(s),
+        None => path,
+    }
+}
+
+/// Obtain the directory `$ORIGIN` expands to for an executable.
+pub fn exe_origin(exe: &Path) -> Result<String, &'static str> {
+    Ok(exe
+        .parent()
+        .ok_or_else(|| "unable to get exe parent")?
+        .display()
+        .to_string())
+}
+
+/// Expand `$ORIGIN` in a path or value to the directory of the executable.
+pub fn expand_origin(value: &str, origin: &str) -> String {
+    value.replace("$ORIGIN", origin)
+}
+
+/// Obtain a path Windows filesystem APIs accept beyond `MAX_PATH`.
+///
+/// Absolute paths are converted to extended-length paths starting with
+/// `\\?\`, which aren't limited to 260 characters even if long paths aren't
+/// enabled on the system. Windows doesn't normalize these paths, so `/` is
+/// replaced and `.` and `..` components are resolved. Other paths and paths
+/// on other platforms are returned as-is.
+///
+/// The result is meant for filesystem operations only. Paths exposed to
+/// Python, e.g. `__file__`, keep their original form.
+#[cfg(windows)]
+pub fn extended_length_path(path: &Path) -> PathBuf {
+    use std::{
+        ffi::OsString,
+        path::{Component, Prefix},
+    };
+
+    let mut components = path.components();
+
+    let mut result = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(_) => {
+                let mut s = OsString::from(r"\\?\");
+                s.push(prefix.as_os_str());
+                s
+            }
+            Prefix::UNC(server, share) => {
+                let mut s = OsString::from(r"\\?\UNC\");
+                s.push(server);
+                s.push(r"\");
+                s.push(share);
+                s
+            }
+            // Verbatim and device paths are used as-is.
+            _ => return path.to_path_buf(),
+        },
+        _ => return path.to_path_buf(),
+    };
+
+    // Paths relative to the current directory of a drive, e.g. `C:foo`.
+    if components.next() != Some(Component::RootDir) {
+        return path.to_path_buf();
+    }
+
+    let mut parts = Vec::new();
+    for component in components {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::ParentDir => {
+                parts.pop();
+            }
+            Component::CurDir => {}
+            _ => return path.to_path_buf(),
+        }
+    }
+
+    if parts.is_empty() {
+        result.push(r"\");
+    }
+    for part in parts {
+        result.push(r"\");
+        result.push(part);
+    }
+
+    PathBuf::from(result)
+}
+
+/// Obtain a path Windows filesystem APIs accept beyond `MAX_PATH`.
+///
+/// Paths on platforms other than Windows are returned as-is.
+#[cfg(not(windows))]
+pub fn extended_length_path(path: &Path) -> PathBuf {
+    path.to_path_buf()
+}
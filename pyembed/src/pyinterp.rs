@@ -5,24 +5,33 @@
 //! Manage an embedded Python interpreter.
 
 use {
-    super::config::{PythonConfig, PythonRawAllocator, PythonRunMode, TerminfoResolution},
-    super::importer::PyInit__pyoxidizer_importer,
-    super::osutils::resolve_terminfo_dirs,
+    super::config::{
+        AuditAction, PythonConfig, PythonRawAllocator, PythonRunMode, TerminfoResolution,
+        UpdateConfig,
+    },
+    super::environment::{configure_environment, expand_path, user_data_dir},
+    super::importer::{
+        embedded_top_level_packages, enable_import_gate, write_import_profile, ImportGate,
+        PyInit__pyoxidizer_importer,
+    },
+    super::osutils::{
+        current_exe, exe_origin, expand_origin, extended_length_path, resolve_terminfo_dirs,
+    },
     super::pyalloc::{make_raw_rust_memory_allocator, RawAllocator},
     super::pystr::{osstring_to_bytes, osstring_to_str, OwnedPyStr},
-    cpython::exc::{SystemExit, ValueError},
+    cpython::exc::{RuntimeError, SystemExit, ValueError},
     cpython::{
         GILGuard, NoArgs, ObjectProtocol, PyClone, PyDict, PyErr, PyList, PyModule, PyObject,
         PyResult, PyString, Python, PythonObject, ToPyObject,
     },
     libc::c_char,
     python3_sys as pyffi,
-    std::collections::BTreeSet,
+    std::collections::{BTreeSet, VecDeque},
     std::env,
-    std::ffi::{CStr, CString},
+    std::ffi::{CStr, CString, OsString},
     std::fs,
     std::io::Write,
-    std::path::PathBuf,
+    std::path::{Path, PathBuf},
     std::ptr::null,
 };
 
@@ -31,6 +40,50 @@ use super::pyalloc::make_raw_jemalloc_allocator;
 
 pub const PYOXIDIZER_IMPORTER_NAME: &[u8] = b"_pyoxidizer_importer\0";
 
+/// Process argument running a self-test when `PythonConfig.self_test` is set.
+pub const SELF_TEST_ARGUMENT: &str = "--pyoxidizer-self-test";
+
+/// Process argument attaching a console when `PythonConfig.debug_console` is set.
+pub const DEBUG_CONSOLE_ARGUMENT: &str = "--debug";
+
+/// Python code making signals raise `SystemExit`.
+///
+/// Runs with `names`, the signals to forward, and `install_handlers`, whether
+/// Python's signal handlers are installed, defined as globals.
+const FORWARD_SIGNALS_CODE: &str = r#"
+import signal
+
+def handler(signum, frame):
+    raise SystemExit(128 + signum)
+
+for name in names:
+    signum = getattr(signal, name, None)
+    if signum is not None:
+        signal.signal(signum, handler)
+
+# Importing signal installs Python's SIGINT handler on its own.
+if not install_handlers and 'SIGINT' not in names:
+    signal.signal(signal.SIGINT, signal.SIG_DFL)
+"#;
+
+/// Options printed by `-h` when emulating the `python` executable.
+const PYTHON_CLI_OPTIONS: &str = "\
+Options:
+-B     : don't write .pyc files on import
+-c cmd : program passed in as string (terminates option list)
+-E     : ignore PYTHON* environment variables (such as PYTHONPATH)
+-h     : print this help message and exit (also --help)
+-i     : inspect interactively after running script
+-I     : isolate Python from the user's environment (implies -E and -s)
+-m mod : run library module as a script (terminates option list)
+-O     : remove assert and __debug__-dependent statements
+-s     : don't add user site directory to sys.path
+-u     : force the stdout and stderr streams to be unbuffered
+-V     : print the Python version number and exit (also --version)
+file   : program read from script file
+-      : program read from stdin (default; interactive mode if a tty)
+arg ...: arguments passed to program in sys.argv[1:]";
+
 const FROZEN_IMPORTLIB_NAME: &[u8] = b"_frozen_importlib\0";
 const FROZEN_IMPORTLIB_EXTERNAL_NAME: &[u8] = b"_frozen_importlib_external\0";
 
@@ -106,6 +159,93 @@ fn stderr_to_file() -> *mut libc::FILE {
     unsafe { libc::fdopen(libc::STDERR_FILENO, &('w' as libc::c_char)) }
 }
 
+#[cfg(windows)]
+extern "system" {
+    fn AttachConsole(process_id: u32) -> i32;
+    fn AllocConsole() -> i32;
+}
+
+/// Attach to the console of the parent process or create a new one.
+///
+/// Executables built for the `windows` subsystem don't have a console, so
+/// output written to stdout and stderr goes nowhere. The C runtime's standard
+/// streams are reopened against the console so Python picks it up when it
+/// initializes.
+#[cfg(windows)]
+fn attach_console() {
+    // ATTACH_PARENT_PROCESS.
+    const PARENT_PROCESS: u32 = 0xffff_ffff;
+
+    unsafe {
+        if AttachConsole(PARENT_PROCESS) == 0 && AllocConsole() == 0 {
+            return;
+        }
+
+        let conin = b"CONIN$\0".as_ptr() as *const libc::c_char;
+        let conout = b"CONOUT$\0".as_ptr() as *const libc::c_char;
+        let read = b"r\0".as_ptr() as *const libc::c_char;
+        let write = b"w\0".as_ptr() as *const libc::c_char;
+
+        libc::freopen(conin, read, __acrt_iob_func(0));
+        libc::freopen(conout, write, __acrt_iob_func(1));
+        libc::freopen(conout, write, __acrt_iob_func(2));
+    }
+}
+
+/// Consoles only need attaching on Windows.
+#[cfg(unix)]
+fn attach_console() {}
+
+#[cfg(windows)]
+extern "system" {
+    fn GetConsoleCP() -> u32;
+    fn GetConsoleOutputCP() -> u32;
+    fn SetConsoleCP(code_page: u32) -> i32;
+    fn SetConsoleOutputCP(code_page: u32) -> i32;
+}
+
+/// Set the input and output code pages of the console.
+///
+/// Returns the previous code pages, or `None` if there is no console.
+#[cfg(windows)]
+fn set_console_code_page(code_page: u32) -> Option<(u32, u32)> {
+    unsafe {
+        let previous = (GetConsoleCP(), GetConsoleOutputCP());
+
+        // Both are 0 if the process has no console.
+        if previous == (0, 0) {
+            return None;
+        }
+
+        SetConsoleCP(code_page);
+        SetConsoleOutputCP(code_page);
+
+        Some(previous)
+    }
+}
+
+/// Console code pages only exist on Windows.
+#[cfg(unix)]
+fn set_console_code_page(_code_page: u32) -> Option<(u32, u32)> {
+    None
+}
+
+#[cfg(windows)]
+fn restore_console_code_page(previous: (u32, u32)) {
+    unsafe {
+        SetConsoleCP(previous.0);
+        SetConsoleOutputCP(previous.1);
+    }
+}
+
+#[cfg(unix)]
+fn restore_console_code_page(_previous: (u32, u32)) {}
+
+extern "C" {
+    // Not declared by python3-sys. Available since Python 3.7.
+    static mut Py_UTF8Mode: libc::c_int;
+}
+
 #[cfg(feature = "jemalloc-sys")]
 fn raw_jemallocator() -> pyffi::PyMemAllocatorEx {
     make_raw_jemalloc_allocator()
@@ -116,6 +256,56 @@ fn raw_jemallocator() -> pyffi::PyMemAllocatorEx {
     panic!("jemalloc is not available in this build configuration");
 }
 
+#[cfg(pyembed_audit_hooks)]
+fn install_audit_hook(policy: &[(String, AuditAction)]) -> Result<(), &'static str> {
+    super::audit::install_audit_hook(policy)
+}
+
+#[cfg(not(pyembed_audit_hooks))]
+fn install_audit_hook(_policy: &[(String, AuditAction)]) -> Result<(), &'static str> {
+    Err("audit hooks require Python 3.8 or newer")
+}
+
+#[cfg(feature = "update")]
+fn install_update_module(py: Python, config: &UpdateConfig) -> Result<(), &'static str> {
+    super::update::install_update_module(py, config)
+        .or_else(|_| Err("unable to install update module"))
+}
+
+#[cfg(not(feature = "update"))]
+fn install_update_module(_py: Python, _config: &UpdateConfig) -> Result<(), &'static str> {
+    Err("update support is not available in this build configuration")
+}
+
+#[cfg(feature = "splash")]
+fn show_splash(image: &'static [u8]) -> Result<(), &'static str> {
+    super::splash::show_splash(image);
+    Ok(())
+}
+
+#[cfg(not(feature = "splash"))]
+fn show_splash(_image: &'static [u8]) -> Result<(), &'static str> {
+    Err("splash screens are not available in this build configuration")
+}
+
+#[cfg(feature = "splash")]
+fn install_splash_module(py: Python) -> Result<(), &'static str> {
+    super::splash::install_splash_module(py).or_else(|_| Err("unable to install splash module"))
+}
+
+#[cfg(not(feature = "splash"))]
+fn install_splash_module(_py: Python) -> Result<(), &'static str> {
+    Err("splash screens are not available in this build configuration")
+}
+
+#[cfg(feature = "splash")]
+fn close_splash() {
+    super::splash::close_splash();
+}
+
+#[cfg(not(feature = "splash"))]
+fn close_splash() {}
+
 #[cfg(unix)]
 fn set_windows_flags(_config: &PythonConfig) {}
 
@@ -131,6 +321,187 @@ fn set_windows_flags(config: &PythonConfig) {
     }
 }
 
+/// What to do when emulating the `python` executable.
+#[derive(Debug, PartialEq)]
+enum PythonCliAction {
+    /// Start a REPL.
+    Repl,
+    /// Run code from stdin.
+    Stdin,
+    /// Evaluate code (`-c`).
+    Code(String),
+    /// Run a module as `__main__` (`-m`).
+    Module(String),
+    /// Run a script at a filesystem path.
+    Script(OsString),
+}
+
+/// Arguments of the `python` executable.
+#[derive(Debug, PartialEq)]
+struct PythonCliArgs {
+    /// Don't write bytecode files (`-B`).
+    dont_write_bytecode: bool,
+    /// Ignore `PYTHON*` environment variables (`-E`).
+    ignore_environment: bool,
+    /// Start a REPL after running code (`-i`).
+    inspect: bool,
+    /// Isolate Python from the user's environment (`-I`).
+    isolated: bool,
+    /// Don't add the user site directory to `sys.path` (`-s`).
+    no_user_site: bool,
+    /// Optimization level, the number of times `-O` is given.
+    optimize: i32,
+    /// Unbuffered stdout and stderr (`-u`).
+    unbuffered: bool,
+    /// Print usage and exit (`-h`).
+    help: bool,
+    /// Print the Python version and exit (`-V`).
+    version: bool,
+    /// What to run.
+    action: PythonCliAction,
+    /// Arguments following the code, module, or script.
+    args: Vec<OsString>,
+}
+
+impl PythonCliArgs {
+    /// Parse process arguments, excluding the executable, like `python` does.
+    ///
+    /// Short options can be combined (`-ic <code>`) and `--` ends options.
+    /// Returns a message describing invalid arguments.
+    fn parse<I>(args: I) -> Result<PythonCliArgs, String>
+    where
+        I: IntoIterator<Item = OsString>,
+    {
+        let mut args = args.into_iter().collect::<VecDeque<OsString>>();
+        let mut res = PythonCliArgs {
+            dont_write_bytecode: false,
+            ignore_environment: false,
+            inspect: false,
+            isolated: false,
+            no_user_site: false,
+            optimize: 0,
+            unbuffered: false,
+            help: false,
+            version: false,
+            action: PythonCliAction::Repl,
+            args: Vec::new(),
+        };
+
+        while let Some(arg) = args.pop_front() {
+            let option = arg.to_string_lossy().to_string();
+
+            match option.as_str() {
+                "--" => {
+                    if let Some(path) = args.pop_front() {
+                        res.action = if path == "-" {
+                            PythonCliAction::Stdin
+                        } else {
+                            PythonCliAction::Script(path)
+                        };
+                    }
+                    break;
+                }
+                "--help" => {
+                    res.help = true;
+                    continue;
+                }
+                "--version" => {
+                    res.version = true;
+                    continue;
+                }
+                "-" => {
+                    res.action = PythonCliAction::Stdin;
+                    break;
+                }
+                s if s.starts_with("--") => {
+                    return Err(format!("Unknown option: {}", s));
+                }
+                s if !s.starts_with('-') => {
+                    res.action = PythonCliAction::Script(arg);
+                    break;
+                }
+                _ => {}
+            }
+
+            for (i, c) in option.char_indices().skip(1) {
+                match c {
+                    'B' => res.dont_write_bytecode = true,
+                    'E' => res.ignore_environment = true,
+                    'i' => res.inspect = true,
+                    'I' => res.isolated = true,
+                    's' => res.no_user_site = true,
+                    'O' => res.optimize += 1,
+                    'u' => res.unbuffered = true,
+                    'h' | '?' => res.help = true,
+                    'V' => res.version = true,
+                    'c' | 'm' => {
+                        // The value is the rest of the argument or the next one.
+                        let rest = &option[i + 1..];
+                        let value = if !rest.is_empty() {
+                            rest.to_string()
+                        } else {
+                            match args.pop_front().map(|v| v.into_string()) {
+                                Some(Ok(value)) => value,
+                                Some(Err(_)) => {
+                                    return Err(format!(
+                                        "Argument for the -{} option is not valid Unicode",
+                                        c
+                                    ));
+                                }
+                                None => {
+                                    return Err(format!("Argument expected for the -{} option", c));
+                                }
+                            }
+                        };
+
+                        res.action = if c == 'c' {
+                            PythonCliAction::Code(value)
+                        } else {
+                            PythonCliAction::Module(value)
+                        };
+                        res.args = args.into_iter().collect();
+
+                        return Ok(res);
+                    }
+                    c => {
+                        return Err(format!("Unknown option: -{}", c));
+                    }
+                }
+            }
+        }
+
+        res.args = args.into_iter().collect();
+
+        Ok(res)
+    }
+
+    /// Apply options that take effect when the interpreter is initialized.
+    fn apply_to_config(&self, config: &mut PythonConfig) {
+        if self.dont_write_bytecode {
+            config.write_bytecode = false;
+        }
+        if self.ignore_environment || self.isolated {
+            config.ignore_python_env = true;
+        }
+        if self.no_user_site || self.isolated {
+            config.import_user_site = false;
+        }
+        if self.isolated {
+            config.isolated = true;
+        }
+        if self.unbuffered {
+            config.unbuffered_stdio = true;
+        }
+        // Keeps SystemExit from exiting the process before the REPL starts.
+        if self.inspect {
+            config.inspect = true;
+        }
+        if self.optimize > config.opt_level {
+            config.opt_level = self.optimize;
+        }
+    }
+}
+
 /// Manages an embedded Python interpreter.
 ///
 /// **Warning: Python interpreters have global state. There should only be a
@@ -152,13 +523,31 @@ pub struct MainPythonInterpreter<'a> {
     gil: Option<GILGuard>,
     py: Option<Python<'a>>,
     program_name: Option<OwnedPyStr>,
+    inittab: Option<Vec<pyffi::_inittab>>,
+    crash_file: Option<PathBuf>,
+    console_code_pages: Option<(u32, u32)>,
 }
 
 impl<'a> MainPythonInterpreter<'a> {
     /// Construct a Python interpreter from a configuration.
     ///
     /// The Python interpreter is initialized as a side-effect. The GIL is held.
-    pub fn new(config: PythonConfig) -> Result<MainPythonInterpreter<'a>, &'static str> {
+    ///
+    /// If the run mode is `PythonRunMode::PythonCli`, `python` options of the
+    /// process arguments like `-O` are applied to the configuration.
+    pub fn new(mut config: PythonConfig) -> Result<MainPythonInterpreter<'a>, &'static str> {
+        if let PythonRunMode::PythonCli = config.run {
+            // Invalid arguments are reported by run_python_cli().
+            if let Ok(args) = PythonCliArgs::parse(env::args_os().skip(1)) {
+                args.apply_to_config(&mut config);
+            }
+        }
+
+        let exe = current_exe().or_else(|_| Err("could not obtain current exe"))?;
+        let origin = exe_origin(&exe)?;
+
+        configure_environment(&config, &origin)?;
+
         match config.terminfo_resolution {
             TerminfoResolution::Dynamic => {
                 if let Some(v) = resolve_terminfo_dirs() {
@@ -171,6 +560,23 @@ impl<'a> MainPythonInterpreter<'a> {
             TerminfoResolution::None => {}
         }
 
+        if config.debug_console
+            && env::args_os()
+                .skip(1)
+                .any(|arg| arg == OsString::from(DEBUG_CONSOLE_ARGUMENT))
+        {
+            attach_console();
+        }
+
+        let console_code_pages = match config.windows_console_code_page {
+            Some(code_page) => set_console_code_page(code_page),
+            None => None,
+        };
+
+        if let Some(image) = config.splash_image {
+            show_splash(image)?;
+        }
+
         let (raw_allocator, raw_rust_allocator) = match config.raw_allocator {
             PythonRawAllocator::Jemalloc => (Some(raw_jemallocator()), None),
             PythonRawAllocator::Rust => (None, Some(make_raw_rust_memory_allocator())),
@@ -188,6 +594,9 @@ impl<'a> MainPythonInterpreter<'a> {
             gil: None,
             py: None,
             program_name: None,
+            inittab: None,
+            crash_file: None,
+            console_code_pages,
         };
 
         res.init()?;
@@ -214,19 +623,82 @@ impl<'a> MainPythonInterpreter<'a> {
 
         let config = &self.config;
 
-        let exe = env::current_exe().or_else(|_| Err("could not obtain current exe"))?;
-        let origin = exe
-            .parent()
-            .ok_or_else(|| "unable to get exe parent")?
-            .display()
-            .to_string();
+        let exe = current_exe().or_else(|_| Err("could not obtain current exe"))?;
+        let origin = exe_origin(&exe)?;
 
-        let sys_paths: Vec<String> = config
+        let mut sys_paths: Vec<String> = config
             .sys_paths
             .iter()
-            .map(|path| path.replace("$ORIGIN", &origin))
+            .map(|path| expand_origin(path, &origin))
             .collect();
 
+        // Plugin directories are optional: ones referencing undefined
+        // environment variables or not existing are skipped.
+        sys_paths.extend(
+            config
+                .filesystem_import_paths
+                .iter()
+                .filter_map(|path| expand_path(path, &origin))
+                .filter(|path| Path::new(path).is_dir()),
+        );
+
+        // The user site-packages directory is added to sys.path once the
+        // interpreter is initialized, so `.pth` files in it are processed.
+        let user_site_packages = match &config.user_site_packages {
+            Some(path) => {
+                let path = match user_data_dir() {
+                    Some(dir) => path.replace("$USER_DATA", &dir),
+                    None => path.clone(),
+                };
+                let path = expand_path(&path, &origin)
+                    .ok_or_else(|| "unable to resolve user site-packages directory")?;
+
+                fs::create_dir_all(extended_length_path(Path::new(&path)))
+                    .or_else(|_| Err("unable to create user site-packages directory"))?;
+
+                // pip installs into the directory when run by the application.
+                if env::var_os("PIP_TARGET").is_none() {
+                    env::set_var("PIP_TARGET", &path);
+                }
+
+                Some(path)
+            }
+            None => None,
+        };
+
+        // The importer holds a reference to resources data for the lifetime of
+        // the process, so data read from a file is leaked.
+        let embedded_resources_data: &'static [u8] = match &config.resources_file {
+            Some(path) => {
+                let path = PathBuf::from(expand_origin(path, &origin));
+                let data = fs::read(extended_length_path(&path))
+                    .or_else(|_| Err("unable to read resources file"))?;
+
+                Box::leak(data.into_boxed_slice())
+            }
+            None => config.embedded_resources_data,
+        };
+
+        let resources_key_file = config
+            .resources_key_file
+            .as_ref()
+            .map(|path| expand_origin(path, &origin));
+
+        let file_extraction_dir = match &config.file_extraction_dir {
+            Some(path) => expand_origin(path, &origin),
+            None => {
+                let stem = exe
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "python".to_string());
+
+                env::temp_dir()
+                    .join(format!("{}-pyoxidizer-files", stem))
+                    .display()
+                    .to_string()
+            }
+        };
+
         // TODO should we call PyMem::SetupDebugHooks() if enabled?
         if let Some(raw_allocator) = &self.raw_allocator {
             unsafe {
@@ -274,7 +746,23 @@ impl<'a> MainPythonInterpreter<'a> {
         let module_state = super::importer::InitModuleState {
             register_filesystem_importer: self.config.filesystem_importer,
             sys_paths,
-            embedded_resources_data: config.embedded_resources_data,
+            embedded_resources_data,
+            resources_integrity: config.resources_integrity.clone(),
+            resources_key_env: config.resources_key_env.clone(),
+            resources_key_file,
+            pkg_resources_compat: config.pkg_resources_compat,
+            file_emulation: config.file_emulation,
+            file_emulation_origin: origin.clone(),
+            file_extraction_dir,
+            lazy_import: config.lazy_import,
+            lazy_import_allow: config.lazy_import_allow.clone(),
+            lazy_import_deny: config.lazy_import_deny.clone(),
+            import_profile: match &config.import_profile_env {
+                Some(key) => env::var(key).is_ok(),
+                None => false,
+            },
+            import_allow: config.import_allow.clone(),
+            import_deny: config.import_deny.clone(),
         };
 
         if config.use_custom_importlib {
@@ -311,6 +799,11 @@ impl<'a> MainPythonInterpreter<'a> {
             }
         }
 
+        if !config.import_deny.is_empty() {
+            let import_gate = ImportGate::new(None, &config.import_deny);
+            self.inittab = Some(remove_denied_builtins(&import_gate)?);
+        }
+
         let exe_str = exe.to_str().ok_or_else(|| "unable to convert exe to str")?;
 
         let home = OwnedPyStr::from_str(exe_str)?;
@@ -338,18 +831,27 @@ impl<'a> MainPythonInterpreter<'a> {
             pyffi::Py_SetPath(value.as_wchar_ptr());
         }
 
-        if let (Some(ref encoding), Some(ref errors)) =
-            (&config.standard_io_encoding, &config.standard_io_errors)
-        {
-            let cencoding = CString::new(encoding.clone())
-                .or_else(|_| Err("unable to convert encoding to C string"))?;
-            let cerrors = CString::new(errors.clone())
-                .or_else(|_| Err("unable to convert encoding error mode to C string"))?;
+        if config.standard_io_encoding.is_some() || config.standard_io_errors.is_some() {
+            let cencoding = match &config.standard_io_encoding {
+                Some(encoding) => Some(
+                    CString::new(encoding.clone())
+                        .or_else(|_| Err("unable to convert encoding to C string"))?,
+                ),
+                None => None,
+            };
+            let cerrors = match &config.standard_io_errors {
+                Some(errors) => Some(
+                    CString::new(errors.clone())
+                        .or_else(|_| Err("unable to convert encoding error mode to C string"))?,
+                ),
+                None => None,
+            };
 
+            // NULL keeps Python's default.
             let res = unsafe {
                 pyffi::Py_SetStandardStreamEncoding(
-                    cencoding.as_ptr() as *const i8,
-                    cerrors.as_ptr() as *const i8,
+                    cencoding.as_ref().map_or(null(), |s| s.as_ptr()) as *const i8,
+                    cerrors.as_ref().map_or(null(), |s| s.as_ptr()) as *const i8,
                 )
             };
 
@@ -377,6 +879,12 @@ impl<'a> MainPythonInterpreter<'a> {
             pyffi::Py_QuietFlag = if config.quiet { 1 } else { 0 };
             pyffi::Py_UnbufferedStdioFlag = if config.unbuffered_stdio { 1 } else { 0 };
             pyffi::Py_VerboseFlag = config.verbose;
+
+            // Python determines the mode from the environment and locale
+            // unless it is set.
+            if config.utf8_mode {
+                Py_UTF8Mode = 1;
+            }
         }
 
         set_windows_flags(config);
@@ -390,7 +898,7 @@ impl<'a> MainPythonInterpreter<'a> {
          */
 
         unsafe {
-            pyffi::Py_Initialize();
+            pyffi::Py_InitializeEx(if config.install_signal_handlers { 1 } else { 0 });
         }
 
         // We shouldn't be accessing this pointer after Py_Initialize(). And the
@@ -412,22 +920,7 @@ impl<'a> MainPythonInterpreter<'a> {
         // will be derived from wchar_t on Windows and char* on POSIX. We can
         // convert these to Python str instances using a platform-specific
         // mechanism.
-        let args_objs = env::args_os()
-            .map(|os_arg| osstring_to_str(py, os_arg))
-            .collect::<Result<Vec<PyObject>, &'static str>>()?;
-
-        // This will steal the pointer to the elements and mem::forget them.
-        let args = PyList::new(py, &args_objs);
-        let argv = b"argv\0";
-
-        let res = args.with_borrowed_ptr(py, |args_ptr| unsafe {
-            pyffi::PySys_SetObject(argv.as_ptr() as *const i8, args_ptr)
-        });
-
-        match res {
-            0 => (),
-            _ => return Err("unable to set sys.argv"),
-        }
+        set_sys_argv(py, env::args_os())?;
 
         if config.argvb {
             let args_objs: Vec<PyObject> = env::args_os()
@@ -483,6 +976,42 @@ impl<'a> MainPythonInterpreter<'a> {
             }
         }
 
+        if !config.audit_policy.is_empty() {
+            install_audit_hook(&config.audit_policy)?;
+        }
+
+        if config.faulthandler || config.crash_dir.is_some() {
+            let crash_file = config.crash_dir.as_ref().map(|dir| {
+                PathBuf::from(expand_origin(dir, &origin))
+                    .join(format!("crash-{}.txt", std::process::id()))
+            });
+
+            enable_faulthandler(py, crash_file.as_ref())?;
+            self.crash_file = crash_file;
+        }
+
+        if !config.forward_signals.is_empty() {
+            forward_signals(py, &config.forward_signals, config.install_signal_handlers)?;
+        }
+
+        if let Some(update) = &config.update {
+            install_update_module(py, update)?;
+        }
+
+        if config.splash_image.is_some() {
+            install_splash_module(py)?;
+        }
+
+        if let Some(path) = user_site_packages {
+            py.import("site")
+                .and_then(|site| site.call(py, "addsitedir", (path,), None))
+                .or_else(|_| Err("unable to add user site-packages directory"))?;
+        }
+
+        if config.import_allow.is_some() || !config.import_deny.is_empty() {
+            enable_import_gate(py).map_err(|_| "could not enable import restrictions")?;
+        }
+
         Ok(py)
     }
 
@@ -515,6 +1044,12 @@ impl<'a> MainPythonInterpreter<'a> {
     /// The crate was built with settings that configure what should be
     /// executed by default. Those settings will be loaded and executed.
     pub fn run(&mut self) -> PyResult<PyObject> {
+        if self.config.self_test
+            && env::args_os().nth(1) == Some(OsString::from(SELF_TEST_ARGUMENT))
+        {
+            return self.run_self_test();
+        }
+
         // clone() to avoid issues mixing mutable and immutable borrows of self.
         let run = self.config.run.clone();
 
@@ -526,6 +1061,7 @@ impl<'a> MainPythonInterpreter<'a> {
             PythonRunMode::Module { module } => self.run_module_as_main(&module),
             PythonRunMode::Eval { code } => self.run_code(&code),
             PythonRunMode::File { path } => self.run_file(&path),
+            PythonRunMode::PythonCli => self.run_python_cli(),
         }
     }
 
@@ -650,6 +1186,43 @@ impl<'a> MainPythonInterpreter<'a> {
         }
     }
 
+    /// Import every embedded top-level package, reporting failures.
+    ///
+    /// Raises `SystemExit` with exit code 1 if any import fails.
+    ///
+    /// The interpreter is automatically initialized if needed.
+    pub fn run_self_test(&mut self) -> PyResult<PyObject> {
+        let py = self.acquire_gil();
+
+        let names = embedded_top_level_packages(py)
+            .or_else(|msg| Err(PyErr::new::<RuntimeError, _>(py, msg)))?;
+
+        let mut failures = 0;
+
+        for name in &names {
+            match py.import(name) {
+                Ok(_) => println!("import {}: ok", name),
+                Err(err) => {
+                    failures += 1;
+                    println!("import {}: FAILED", name);
+                    err.print(py);
+                }
+            }
+        }
+
+        println!(
+            "{} of {} embedded packages imported",
+            names.len() - failures,
+            names.len()
+        );
+
+        if failures > 0 {
+            Err(PyErr::new::<SystemExit, _>(py, 1))
+        } else {
+            Ok(py.None())
+        }
+    }
+
     /// Calls run() and resolves a suitable exit code.
     pub fn run_as_main(&mut self) -> i32 {
         match self.run_and_handle_error() {
@@ -805,6 +1378,133 @@ impl<'a> MainPythonInterpreter<'a> {
         }
     }
 
+    /// Runs the interpreter like the `python` executable would.
+    ///
+    /// Process arguments are interpreted like `python` interprets them. The
+    /// `-c <code>`, `-m <module>`, `-i`, `-h`, and `-V` options are supported,
+    /// as is running a script from a path (or stdin if the path is `-`).
+    /// Short options can be combined (e.g. `-ic <code>`) and `--` ends
+    /// options. Arguments following the code, module, or script are exposed
+    /// via `sys.argv`.
+    ///
+    /// The `-B`, `-E`, `-I`, `-O`, `-s`, and `-u` options are applied when
+    /// the interpreter is initialized with the `PythonRunMode::PythonCli` run
+    /// mode and are ignored otherwise.
+    ///
+    /// If no code, module, or script is given, a REPL is started. With `-i`,
+    /// a REPL is also started after code runs, even if it raised.
+    pub fn run_python_cli(&mut self) -> PyResult<PyObject> {
+        self.run_python_cli_args(env::args_os().skip(1))
+    }
+
+    /// Runs the interpreter like the `python` executable would with arguments.
+    ///
+    /// See `run_python_cli()`.
+    fn run_python_cli_args<I>(&mut self, args: I) -> PyResult<PyObject>
+    where
+        I: IntoIterator<Item = OsString>,
+    {
+        let py = self.acquire_gil();
+
+        let args = match PythonCliArgs::parse(args) {
+            Ok(args) => args,
+            Err(message) => {
+                eprintln!("{}", message);
+                return Err(PyErr::new::<SystemExit, _>(py, 2));
+            }
+        };
+
+        if args.help {
+            let exe = env::args_os()
+                .next()
+                .map(|a| a.to_string_lossy().to_string())
+                .unwrap_or_else(|| "python".to_string());
+            println!(
+                "usage: {} [option] ... [-c cmd | -m mod | file | -] [arg] ...",
+                exe
+            );
+            println!("{}", PYTHON_CLI_OPTIONS);
+
+            return Ok(py.None());
+        }
+
+        if args.version {
+            let sys = py.import("sys")?;
+            let version = sys.get(py, "version")?.extract::<String>(py)?;
+            println!("Python {}", version.split(' ').next().unwrap_or(""));
+
+            return Ok(py.None());
+        }
+
+        let PythonCliArgs {
+            action,
+            args: extra_args,
+            inspect,
+            isolated,
+            ..
+        } = args;
+
+        let argv0 = match &action {
+            PythonCliAction::Repl => OsString::new(),
+            PythonCliAction::Stdin => OsString::from("-"),
+            PythonCliAction::Code(_) => OsString::from("-c"),
+            PythonCliAction::Module(module) => OsString::from(module),
+            PythonCliAction::Script(path) => path.clone(),
+        };
+        let mut argv = vec![argv0];
+        argv.extend(extra_args);
+        set_sys_argv(py, argv).or_else(|e| Err(PyErr::new::<ValueError, _>(py, e)))?;
+
+        let res = match action {
+            // The REPL reads code from stdin when stdin isn't a TTY.
+            PythonCliAction::Repl | PythonCliAction::Stdin => return self.run_repl(),
+            PythonCliAction::Code(code) => self.run_code(&code),
+            PythonCliAction::Module(module) => self.run_module_as_main(&module),
+            PythonCliAction::Script(path) => {
+                // Like `python`, make modules next to the script importable,
+                // unless isolated.
+                if !isolated {
+                    let script_dir = match PathBuf::from(&path).parent() {
+                        Some(p) => p.display().to_string(),
+                        None => "".to_string(),
+                    };
+                    let sys = py.import("sys")?;
+                    sys.get(py, "path")?
+                        .call_method(py, "insert", (0, script_dir), None)?;
+                }
+
+                let path = path.into_string().or_else(|_| {
+                    Err(PyErr::new::<ValueError, _>(
+                        py,
+                        "script path is not valid Unicode",
+                    ))
+                })?;
+                let path = CString::new(path).or_else(|_| {
+                    Err(PyErr::new::<ValueError, _>(
+                        py,
+                        "script path is not a valid C string",
+                    ))
+                })?;
+
+                self.run_file(&path)
+            }
+        };
+
+        if !inspect {
+            return res;
+        }
+
+        // Like `python -i`, report the error and inspect what was left behind.
+        // Scripts print their errors and fail with SystemExit.
+        if let Err(err) = res {
+            if !err.matches(py, py.get_type::<SystemExit>()) {
+                err.print(py);
+            }
+        }
+
+        self.run_repl()
+    }
+
     /// Print a Python error.
     ///
     /// Under the hood this calls ``PyErr_PrintEx()``, which may call
@@ -815,6 +1515,30 @@ impl<'a> MainPythonInterpreter<'a> {
     }
 }
 
+/// Set `sys.argv` from process arguments.
+fn set_sys_argv<I>(py: Python, args: I) -> Result<(), &'static str>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let args_objs = args
+        .into_iter()
+        .map(|os_arg| osstring_to_str(py, os_arg))
+        .collect::<Result<Vec<PyObject>, &'static str>>()?;
+
+    // This will steal the pointer to the elements and mem::forget them.
+    let args = PyList::new(py, &args_objs);
+    let argv = b"argv\0";
+
+    let res = args.with_borrowed_ptr(py, |args_ptr| unsafe {
+        pyffi::PySys_SetObject(argv.as_ptr() as *const i8, args_ptr)
+    });
+
+    match res {
+        0 => Ok(()),
+        _ => Err("unable to set sys.argv"),
+    }
+}
+
 /// Write loaded Python modules to a directory.
 ///
 /// Given a Python interpreter and a path to a directory, this will create a
@@ -858,8 +1582,97 @@ fn write_modules_to_directory(py: Python, path: &PathBuf) -> Result<(), &'static
     Ok(())
 }
 
+/// Enable `faulthandler` to dump the tracebacks of all threads on fatal errors.
+///
+/// Tracebacks are written to stderr unless a path is given. The file is
+/// created up front, as it can't be opened once the process is crashing.
+fn enable_faulthandler(py: Python, path: Option<&PathBuf>) -> Result<(), &'static str> {
+    let faulthandler = py
+        .import("faulthandler")
+        .or_else(|_| Err("could not import faulthandler"))?;
+
+    let kwargs = PyDict::new(py);
+    kwargs
+        .set_item(py, "all_threads", true)
+        .or_else(|_| Err("could not set faulthandler arguments"))?;
+
+    if let Some(path) = path {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).or_else(|_| Err("could not create crash directory"))?;
+        }
+
+        // faulthandler holds a reference to the file for as long as it is enabled.
+        let f = py
+            .import("io")
+            .and_then(|io| io.call(py, "open", (path.display().to_string(), "w"), None))
+            .or_else(|_| Err("could not open crash file"))?;
+
+        kwargs
+            .set_item(py, "file", f)
+            .or_else(|_| Err("could not set faulthandler arguments"))?;
+    }
+
+    faulthandler
+        .call(py, "enable", NoArgs, Some(&kwargs))
+        .or_else(|_| Err("could not enable faulthandler"))?;
+
+    Ok(())
+}
+
+/// Make signals raise `SystemExit` in the main thread.
+fn forward_signals(
+    py: Python,
+    names: &[String],
+    install_handlers: bool,
+) -> Result<(), &'static str> {
+    let globals = PyDict::new(py);
+    globals
+        .set_item(py, "names", names.to_vec())
+        .and_then(|_| globals.set_item(py, "install_handlers", install_handlers))
+        .or_else(|_| Err("could not set signal forwarding arguments"))?;
+
+    py.run(FORWARD_SIGNALS_CODE, Some(&globals), None)
+        .or_else(|_| Err("could not forward signals"))
+}
+
+/// Remove denied modules from the table of built-in extension modules.
+///
+/// Built-in modules missing from `PyImport_Inittab` can't be imported by any
+/// finder, so they stay unavailable if Python code removes our importer from
+/// `sys.meta_path`. The returned table must outlive the interpreter.
+fn remove_denied_builtins(import_gate: &ImportGate) -> Result<Vec<pyffi::_inittab>, &'static str> {
+    let mut inittab = Vec::new();
+
+    for i in 0.. {
+        let record = unsafe { *pyffi::PyImport_Inittab.offset(i) };
+
+        if record.name.is_null() {
+            inittab.push(record);
+            break;
+        }
+
+        let name = unsafe { CStr::from_ptr(record.name as _) }
+            .to_str()
+            .map_err(|_| "unable to parse PyImport_Inittab")?;
+
+        if !import_gate.denies(name) {
+            inittab.push(record);
+        }
+    }
+
+    unsafe {
+        pyffi::PyImport_Inittab = inittab.as_mut_ptr();
+    }
+
+    Ok(inittab)
+}
+
 impl<'a> Drop for MainPythonInterpreter<'a> {
     fn drop(&mut self) {
+        if let Some(hook) = self.config.shutdown_hook {
+            hook();
+        }
+
         if let Some(key) = &self.config.write_modules_directory_env {
             if let Ok(path) = env::var(key) {
                 let path = PathBuf::from(path);
@@ -871,6 +1684,279 @@ impl<'a> Drop for MainPythonInterpreter<'a> {
             }
         }
 
+        if let Some(key) = &self.config.import_profile_env {
+            if let Ok(path) = env::var(key) {
+                let py = self.acquire_gil();
+
+                if let Err(msg) = write_import_profile(py, &path) {
+                    eprintln!("error writing import profile: {}", msg);
+                }
+            }
+        }
+
         let _ = unsafe { pyffi::Py_FinalizeEx() };
+
+        if self.config.splash_image.is_some() {
+            close_splash();
+        }
+
+        if let Some(previous) = self.console_code_pages {
+            restore_console_code_page(previous);
+        }
+
+        // Nothing was written to the crash file if the process didn't crash.
+        if let Some(path) = &self.crash_file {
+            if fs::metadata(path).map(|m| m.len() == 0).unwrap_or(false) {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "build-mode-prebuilt-artifacts"))]
+mod tests {
+    extern crate self as pyembed;
+
+    use {super::*, std::process::Command};
+
+    include!(concat!(
+        env!("PYOXIDIZER_ARTIFACT_DIR"),
+        "/default_python_config.rs"
+    ));
+
+    /// Environment variable naming the test a child process runs.
+    const CHILD_TEST_ENV: &str = "PYEMBED_TEST_CHILD";
+
+    /// Run a test needing an interpreter in a child process.
+    ///
+    /// An interpreter can only be initialized once per process, so the test
+    /// executable runs itself for test `name`, which calls `f` in the child.
+    fn run_isolated(name: &str, f: fn()) {
+        if env::var(CHILD_TEST_ENV).ok().as_ref().map(|s| s.as_str()) == Some(name) {
+            f();
+            return;
+        }
+
+        let status = Command::new(env::current_exe().unwrap())
+            .arg("--exact")
+            .arg(format!("pyinterp::tests::{}", name))
+            .arg("--test-threads=1")
+            .env(CHILD_TEST_ENV, name)
+            .status()
+            .unwrap();
+
+        assert!(status.success(), "{} failed in child process", name);
+    }
+
+    /// Run Python code in an interpreter, panicking if it raises.
+    fn run_code(config: PythonConfig, code: &str) {
+        let mut interp = MainPythonInterpreter::new(config).unwrap();
+        let py = interp.acquire_gil();
+
+        if let Err(err) = py.run(code, None, None) {
+            err.print(py);
+            panic!("Python code raised an exception");
+        }
+    }
+
+    /// Run an interpreter like the `python` executable, panicking on errors.
+    fn run_python_cli(args: &[&str]) {
+        let args = args.iter().map(OsString::from).collect::<Vec<_>>();
+
+        let mut config = default_python_config();
+        PythonCliArgs::parse(args.clone())
+            .unwrap()
+            .apply_to_config(&mut config);
+
+        let mut interp = MainPythonInterpreter::new(config).unwrap();
+
+        if let Err(err) = interp.run_python_cli_args(args) {
+            interp.print_err(err);
+            panic!("Python CLI failed");
+        }
+    }
+
+    #[test]
+    fn test_python_cli_args() {
+        let parse = |args: &[&str]| PythonCliArgs::parse(args.iter().map(OsString::from));
+
+        let args = parse(&["-ic", "import sys", "-u", "x"]).unwrap();
+        assert!(args.inspect);
+        assert!(!args.unbuffered);
+        assert_eq!(args.action, PythonCliAction::Code("import sys".to_string()));
+        assert_eq!(args.args, vec![OsString::from("-u"), OsString::from("x")]);
+
+        let args = parse(&["-BEsuOO", "-I", "-mjson.tool"]).unwrap();
+        assert!(args.dont_write_bytecode);
+        assert!(args.ignore_environment);
+        assert!(args.no_user_site);
+        assert!(args.unbuffered);
+        assert!(args.isolated);
+        assert_eq!(args.optimize, 2);
+        assert_eq!(
+            args.action,
+            PythonCliAction::Module("json.tool".to_string())
+        );
+
+        let args = parse(&["-i", "--", "-script.py", "-c"]).unwrap();
+        assert!(args.inspect);
+        assert_eq!(
+            args.action,
+            PythonCliAction::Script(OsString::from("-script.py"))
+        );
+        assert_eq!(args.args, vec![OsString::from("-c")]);
+
+        assert_eq!(parse(&["--"]).unwrap().action, PythonCliAction::Repl);
+        assert_eq!(parse(&["--", "-"]).unwrap().action, PythonCliAction::Stdin);
+        assert_eq!(
+            parse(&["script.py", "-i"]).unwrap().args,
+            vec![OsString::from("-i")]
+        );
+
+        assert!(parse(&["-h"]).unwrap().help);
+        assert!(parse(&["--help"]).unwrap().help);
+        assert!(parse(&["-V"]).unwrap().version);
+
+        assert_eq!(parse(&["-x"]), Err("Unknown option: -x".to_string()));
+        assert_eq!(parse(&["--x"]), Err("Unknown option: --x".to_string()));
+        assert_eq!(
+            parse(&["-ic"]),
+            Err("Argument expected for the -c option".to_string())
+        );
+    }
+
+    #[test]
+    fn test_python_cli_flags() {
+        run_isolated("test_python_cli_flags", || {
+            run_python_cli(&[
+                "-BEsuO",
+                "-c",
+                "import sys\n\
+                 f = sys.flags\n\
+                 assert (f.dont_write_bytecode, f.ignore_environment) == (1, 1)\n\
+                 assert (f.no_user_site, f.optimize, f.isolated) == (1, 1, 0)\n\
+                 assert sys.stdout.write_through and sys.stderr.write_through\n\
+                 assert sys.argv == ['-c', 'x']\n",
+                "x",
+            ]);
+        });
+    }
+
+    #[test]
+    fn test_python_cli_isolated() {
+        run_isolated("test_python_cli_isolated", || {
+            run_python_cli(&[
+                "-I",
+                "-c",
+                "import sys\n\
+                 f = sys.flags\n\
+                 assert (f.isolated, f.ignore_environment, f.no_user_site) == (1, 1, 1)\n",
+            ]);
+        });
+    }
+
+    #[test]
+    fn test_python_cli_help() {
+        run_isolated("test_python_cli_help", || {
+            // Usage is printed instead of running code.
+            run_python_cli(&["-h", "-c", "raise SystemExit(3)"]);
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_python_cli_inspect_after_exception() {
+        run_isolated("test_python_cli_inspect_after_exception", || {
+            // The REPL reads code from stdin, which isn't a TTY.
+            let code = b"assert x == 1\ninspected = True\n";
+            unsafe {
+                let mut fds = [0; 2];
+                assert_eq!(libc::pipe(fds.as_mut_ptr()), 0);
+                assert_eq!(
+                    libc::write(fds[1], code.as_ptr() as *const _, code.len()),
+                    code.len() as isize
+                );
+                libc::close(fds[1]);
+                assert_eq!(libc::dup2(fds[0], libc::STDIN_FILENO), libc::STDIN_FILENO);
+                libc::close(fds[0]);
+            }
+
+            let args = ["-ic", "x = 1\nraise ValueError('expected')", "y"];
+            let args = args.iter().map(OsString::from).collect::<Vec<_>>();
+
+            let mut config = default_python_config();
+            PythonCliArgs::parse(args.clone())
+                .unwrap()
+                .apply_to_config(&mut config);
+
+            let mut interp = MainPythonInterpreter::new(config).unwrap();
+            assert!(interp.run_python_cli_args(args).is_ok());
+            assert!(interp.run_code("assert inspected").is_ok());
+        });
+    }
+
+    #[test]
+    fn test_import_deny() {
+        run_isolated("test_import_deny", || {
+            let mut config = default_python_config();
+            config.import_deny = vec!["socket".to_string()];
+
+            run_code(
+                config,
+                "import sys\n\
+                 for name in ('socket', '_socket'):\n    \
+                     try:\n        \
+                         __import__(name)\n    \
+                     except ModuleNotFoundError as e:\n        \
+                         assert e.name == name\n    \
+                     else:\n        \
+                         raise AssertionError('imported ' + name)\n\
+                 import json\n",
+            );
+        });
+    }
+
+    #[test]
+    fn test_import_deny_without_importer() {
+        run_isolated("test_import_deny_without_importer", || {
+            let mut config = default_python_config();
+            config.import_deny = vec!["ctypes".to_string(), "socket".to_string()];
+
+            // Replacing our importer with the standard finders must not make
+            // denied modules or their built-in C accelerators importable.
+            run_code(
+                config,
+                "import sys\n\
+                 from importlib.machinery import BuiltinImporter, FrozenImporter, PathFinder\n\
+                 sys.meta_path[:] = [BuiltinImporter, FrozenImporter, PathFinder]\n\
+                 for name in ('socket', '_socket', 'ctypes', '_ctypes'):\n    \
+                     try:\n        \
+                         __import__(name)\n    \
+                     except ImportError:\n        \
+                         pass\n    \
+                     else:\n        \
+                         raise AssertionError('imported ' + name)\n",
+            );
+        });
+    }
+
+    #[test]
+    fn test_import_allow() {
+        run_isolated("test_import_allow", || {
+            let mut config = default_python_config();
+            config.import_allow = Some(vec!["socket".to_string()]);
+
+            // Allowing a module allows its C accelerator.
+            run_code(
+                config,
+                "import _socket\n\
+                 try:\n    \
+                     import ctypes\n\
+                 except ModuleNotFoundError:\n    \
+                     pass\n\
+                 else:\n    \
+                     raise AssertionError('imported ctypes')\n",
+            );
+        });
     }
 }
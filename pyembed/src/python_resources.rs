@@ -8,7 +8,8 @@ Management of Python resources.
 
 use {
     python3_sys as pyffi,
-    python_packed_resources::data::Resource,
+    python_packed_resources::data::{Resource, HEADER_V2},
+    python_packed_resources::parser::{load_resources_index, ResourceIndex},
     std::borrow::Cow,
     std::collections::{HashMap, HashSet},
     std::ffi::CStr,
@@ -21,7 +22,7 @@ pub(crate) enum ResourceFlavor {
     Packed,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub(crate) struct ResourceEntry<'a, X>
 where
     [X]: ToOwned<Owned = Vec<X>>,
@@ -53,7 +54,11 @@ where
     /// Names of Python packages.
     pub packages: HashSet<&'static str>,
 
+    /// Builtin and frozen modules and resources of unindexed data.
     pub resources: HashMap<Cow<'a, str>, ResourceEntry<'a, X>>,
+
+    /// Index of resources data, whose resources are parsed when looked up.
+    pub index: Option<ResourceIndex<'a>>,
 }
 
 impl<'a> Default for PythonImporterState<'a, u8> {
@@ -61,6 +66,7 @@ impl<'a> Default for PythonImporterState<'a, u8> {
         Self {
             packages: HashSet::new(),
             resources: HashMap::new(),
+            index: None,
         }
     }
 }
@@ -77,6 +83,51 @@ impl<'a> PythonImporterState<'a, u8> {
         Ok(())
     }
 
+    /// Obtain the resource entry for a name.
+    ///
+    /// Resources of indexed data are parsed on every call.
+    pub fn get(&self, name: &str) -> Result<Option<ResourceEntry<'a, u8>>, &'static str> {
+        if let Some(entry) = self.resources.get(name) {
+            return Ok(Some(entry.clone()));
+        }
+
+        Ok(self.get_indexed(name)?.map(|resource| ResourceEntry {
+            flavor: ResourceFlavor::Packed,
+            resource,
+        }))
+    }
+
+    /// Obtain all resource entries.
+    ///
+    /// This parses all resources of indexed data.
+    pub fn entries(&self) -> Result<Vec<ResourceEntry<'a, u8>>, &'static str> {
+        let mut entries = self.resources.values().cloned().collect::<Vec<_>>();
+
+        if let Some(index) = &self.index {
+            for resource in index.iter() {
+                let resource = resource?;
+
+                // Builtin and frozen modules are also in resources.
+                if !self.resources.contains_key(&*resource.name) {
+                    entries.push(ResourceEntry {
+                        flavor: ResourceFlavor::Packed,
+                        resource,
+                    });
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Look up a resource in indexed resources data.
+    fn get_indexed(&self, name: &str) -> Result<Option<Resource<'a, u8>>, &'static str> {
+        match &self.index {
+            Some(index) => index.get(name),
+            None => Ok(None),
+        }
+    }
+
     /// Load `builtin` modules from the Python interpreter.
     fn load_interpreter_builtin_modules(&mut self) -> Result<(), &'static str> {
         for i in 0.. {
@@ -98,6 +149,14 @@ impl<'a> PythonImporterState<'a, u8> {
             // update the big.
             if let Some(mut entry) = self.resources.get_mut(name_str) {
                 entry.flavor = ResourceFlavor::Builtin;
+            } else if let Some(resource) = self.get_indexed(name_str)? {
+                self.resources.insert(
+                    Cow::from(name_str),
+                    ResourceEntry {
+                        flavor: ResourceFlavor::Builtin,
+                        resource,
+                    },
+                );
             } else {
                 self.resources.insert(
                     // This is probably unsafe.
@@ -137,6 +196,14 @@ impl<'a> PythonImporterState<'a, u8> {
             // update the big.
             if let Some(mut entry) = self.resources.get_mut(name_str) {
                 entry.flavor = ResourceFlavor::Frozen;
+            } else if let Some(resource) = self.get_indexed(name_str)? {
+                self.resources.insert(
+                    Cow::from(name_str),
+                    ResourceEntry {
+                        flavor: ResourceFlavor::Frozen,
+                        resource,
+                    },
+                );
             } else {
                 self.resources.insert(
                     // This is probably unsafe.
@@ -157,7 +224,16 @@ impl<'a> PythonImporterState<'a, u8> {
     }
 
     /// Load resources by parsing a blob.
+    ///
+    /// Data having a name index is only indexed. Its resources are parsed when
+    /// looked up, so startup doesn't depend on the number of resources.
     fn load_resources(&mut self, data: &'a [u8]) -> Result<(), &'static str> {
+        if data.starts_with(HEADER_V2) {
+            self.index = Some(load_resources_index(data)?);
+
+            return Ok(());
+        }
+
         let resources = python_packed_resources::parser::load_resources(data)?;
 
         for resource in resources {
@@ -0,0 +1,238 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Splash screens shown while an application starts.
+
+When `PythonConfig.splash_image` is set, a borderless window displaying the
+image is shown in the center of the screen before the interpreter is
+initialized. It is closed when Python calls `oxidized_splash.close()`,
+signaling the application is ready, or when the interpreter is finalized.
+
+Splash screens are only implemented on Windows. Elsewhere the
+`oxidized_splash` module is available but does nothing, so applications
+don't need to special case platforms.
+*/
+
+use cpython::{py_fn, ObjectProtocol, PyModule, PyObject, PyResult, Python, PythonObject};
+
+/// Name of the Python module controlling the splash screen.
+pub const SPLASH_MODULE_NAME: &str = "oxidized_splash";
+
+/// Obtain the width, height, and pixel data offset of a BMP image.
+#[cfg_attr(not(windows), allow(dead_code))]
+fn parse_bitmap(data: &[u8]) -> Option<(i32, i32, usize)> {
+    if data.len() < 26 || &data[0..2] != b"BM" {
+        return None;
+    }
+
+    let read_u32 = |offset: usize| {
+        u32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    };
+
+    let bits_offset = read_u32(10) as usize;
+    let width = read_u32(18) as i32;
+    // Top-down bitmaps have a negative height.
+    let height = (read_u32(22) as i32).abs();
+
+    if bits_offset >= data.len() {
+        None
+    } else {
+        Some((width, height, bits_offset))
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use {
+        super::parse_bitmap,
+        std::ptr::{null, null_mut},
+        std::sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+        winapi::shared::minwindef::{LPARAM, LRESULT, UINT, WPARAM},
+        winapi::shared::windef::HWND,
+        winapi::um::libloaderapi::GetModuleHandleW,
+        winapi::um::wingdi::{SetDIBitsToDevice, BITMAPINFO, DIB_RGB_COLORS},
+        winapi::um::winuser::{
+            BeginPaint, CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, EndPaint,
+            GetMessageW, GetSystemMetrics, LoadCursorW, PostMessageW, PostQuitMessage,
+            RegisterClassW, TranslateMessage, IDC_APPSTARTING, MSG, PAINTSTRUCT, SM_CXSCREEN,
+            SM_CYSCREEN, WM_CLOSE, WM_DESTROY, WM_PAINT, WNDCLASSW, WS_EX_TOOLWINDOW,
+            WS_EX_TOPMOST, WS_POPUP, WS_VISIBLE,
+        },
+    };
+
+    /// Handle of the splash window, or 0 if none is shown.
+    static WINDOW: AtomicUsize = AtomicUsize::new(0);
+
+    /// Whether closing the splash screen was requested.
+    static CLOSE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+    /// Image painted by the splash window.
+    ///
+    /// Only written before the window thread is started.
+    static mut IMAGE: &[u8] = &[];
+
+    unsafe extern "system" fn window_proc(
+        hwnd: HWND,
+        msg: UINT,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        match msg {
+            WM_PAINT => {
+                let mut ps: PAINTSTRUCT = std::mem::zeroed();
+                let hdc = BeginPaint(hwnd, &mut ps);
+
+                if let Some((width, height, bits_offset)) = parse_bitmap(IMAGE) {
+                    // The BITMAPINFO follows the 14 byte file header.
+                    SetDIBitsToDevice(
+                        hdc,
+                        0,
+                        0,
+                        width as u32,
+                        height as u32,
+                        0,
+                        0,
+                        0,
+                        height as u32,
+                        IMAGE[bits_offset..].as_ptr() as *const _,
+                        IMAGE[14..].as_ptr() as *const BITMAPINFO,
+                        DIB_RGB_COLORS,
+                    );
+                }
+
+                EndPaint(hwnd, &ps);
+                0
+            }
+            WM_DESTROY => {
+                PostQuitMessage(0);
+                0
+            }
+            _ => DefWindowProcW(hwnd, msg, wparam, lparam),
+        }
+    }
+
+    /// Create the splash window and run its message loop until it is closed.
+    unsafe fn run_window(width: i32, height: i32) {
+        let instance = GetModuleHandleW(null());
+        let class_name: Vec<u16> = "PyOxidizerSplash\0".encode_utf16().collect();
+
+        let class = WNDCLASSW {
+            style: 0,
+            lpfnWndProc: Some(window_proc),
+            cbClsExtra: 0,
+            cbWndExtra: 0,
+            hInstance: instance,
+            hIcon: null_mut(),
+            hCursor: LoadCursorW(null_mut(), IDC_APPSTARTING),
+            hbrBackground: null_mut(),
+            lpszMenuName: null(),
+            lpszClassName: class_name.as_ptr(),
+        };
+
+        if RegisterClassW(&class) == 0 {
+            return;
+        }
+
+        let hwnd = CreateWindowExW(
+            WS_EX_TOOLWINDOW | WS_EX_TOPMOST,
+            class_name.as_ptr(),
+            null(),
+            WS_POPUP | WS_VISIBLE,
+            (GetSystemMetrics(SM_CXSCREEN) - width) / 2,
+            (GetSystemMetrics(SM_CYSCREEN) - height) / 2,
+            width,
+            height,
+            null_mut(),
+            null_mut(),
+            instance,
+            null_mut(),
+        );
+
+        if hwnd.is_null() {
+            return;
+        }
+
+        WINDOW.store(hwnd as usize, Ordering::SeqCst);
+
+        // The application may have become ready before the window existed.
+        if CLOSE_REQUESTED.load(Ordering::SeqCst) {
+            DestroyWindow(hwnd);
+        }
+
+        let mut msg: MSG = std::mem::zeroed();
+        while GetMessageW(&mut msg, null_mut(), 0, 0) > 0 {
+            TranslateMessage(&msg);
+            DispatchMessageW(&msg);
+        }
+    }
+
+    pub fn show(image: &'static [u8]) {
+        let (width, height, _) = match parse_bitmap(image) {
+            Some(v) => v,
+            None => return,
+        };
+
+        unsafe {
+            IMAGE = image;
+        }
+
+        // The window needs a thread pumping its messages while the
+        // interpreter initializes on the main thread.
+        std::thread::spawn(move || unsafe { run_window(width, height) });
+    }
+
+    pub fn close() {
+        CLOSE_REQUESTED.store(true, Ordering::SeqCst);
+
+        let hwnd = WINDOW.swap(0, Ordering::SeqCst);
+        if hwnd != 0 {
+            unsafe {
+                PostMessageW(hwnd as HWND, WM_CLOSE, 0, 0);
+            }
+        }
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    pub fn show(_image: &'static [u8]) {}
+
+    pub fn close() {}
+}
+
+/// Show a splash screen displaying a BMP image.
+///
+/// Invalid images are ignored, as failing to show a splash screen
+/// shouldn't prevent the application from starting.
+pub fn show_splash(image: &'static [u8]) {
+    platform::show(image)
+}
+
+/// Close the splash screen, if shown.
+pub fn close_splash() {
+    platform::close()
+}
+
+/// oxidized_splash.close()
+fn splash_close(py: Python) -> PyResult<PyObject> {
+    close_splash();
+
+    Ok(py.None())
+}
+
+/// Create the `oxidized_splash` module and register it in `sys.modules`.
+pub fn install_splash_module(py: Python) -> PyResult<()> {
+    let m = PyModule::new(py, SPLASH_MODULE_NAME)?;
+    m.add(py, "close", py_fn!(py, splash_close()))?;
+
+    py.import("sys")?
+        .get(py, "modules")?
+        .set_item(py, SPLASH_MODULE_NAME, m.into_object())
+}
@@ -0,0 +1,181 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Checking for application updates.
+
+When `PythonConfig.update` is set, an `oxidized_update` module is made
+available to Python. Its `check_for_update()` function downloads the update
+manifest published at the channel URL and its `.sig` signature, verifies the
+signature against the embedded Ed25519 public key, and returns the manifest
+if it describes a newer version than the running one. Manifests naming
+another application or target triple are rejected.
+
+The channel URL, public key, and running version are held in Rust and are
+not reachable from Python, so application code can't redirect update checks
+or swap the key manifests are verified with.
+
+Manifests are JSON documents produced by `pyoxidizer` when building an
+executable with an update signing key. Downloading and installing the
+artifacts they describe is left to the application.
+*/
+
+use {
+    super::config::UpdateConfig,
+    cpython::exc::RuntimeError,
+    cpython::{
+        py_fn, NoArgs, ObjectProtocol, PyBytes, PyErr, PyModule, PyObject, PyResult, Python,
+        PythonObject,
+    },
+    ed25519_dalek::{PublicKey, Signature, Verifier},
+    lazy_static::lazy_static,
+    std::sync::Mutex,
+};
+
+/// Name of the Python module exposing update functionality.
+pub const UPDATE_MODULE_NAME: &str = "oxidized_update";
+
+lazy_static! {
+    /// Update configuration of the running interpreter.
+    static ref UPDATE_CONFIG: Mutex<Option<UpdateConfig>> = Mutex::new(None);
+}
+
+/// Decode a hex string.
+fn decode_hex(value: &str) -> Result<Vec<u8>, String> {
+    if value.len() % 2 != 0 || !value.is_ascii() {
+        return Err("invalid hex string".to_string());
+    }
+
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&value[i..i + 2], 16).map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// Verify the hex encoded signature of an update manifest.
+fn verify_signature(public_key: &str, manifest: &[u8], signature: &[u8]) -> Result<(), String> {
+    let public_key = PublicKey::from_bytes(&decode_hex(public_key)?)
+        .map_err(|e| format!("invalid update public key: {}", e))?;
+
+    let signature = std::str::from_utf8(signature)
+        .map_err(|_| "update manifest signature is not hex".to_string())
+        .and_then(|s| decode_hex(s.trim()))?;
+    let signature = Signature::from_bytes(&signature)
+        .map_err(|e| format!("invalid update manifest signature: {}", e))?;
+
+    public_key
+        .verify(manifest, &signature)
+        .map_err(|_| "update manifest signature does not verify".to_string())
+}
+
+/// Parse a version of dot-delimited integers.
+///
+/// Trailing zeros are dropped so `1.2` and `1.2.0` compare equal.
+fn parse_version(version: &str) -> Result<Vec<u64>, String> {
+    let mut parts = version
+        .split('.')
+        .map(|p| p.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("invalid version: {}", version))?;
+
+    while parts.last() == Some(&0) {
+        parts.pop();
+    }
+
+    Ok(parts)
+}
+
+/// Whether version `candidate` is newer than `current`.
+///
+/// Versions must be dot-delimited integers. Versions that don't parse are
+/// rejected rather than guessed at.
+fn is_newer_version(candidate: &str, current: &str) -> Result<bool, String> {
+    Ok(parse_version(candidate)? > parse_version(current)?)
+}
+
+/// Ensure an update manifest describes the running application.
+///
+/// A manifest signed with the same key may describe another application or
+/// the same application built for another target.
+fn check_manifest_target(
+    name: &str,
+    target_triple: &str,
+    config: &UpdateConfig,
+) -> Result<(), String> {
+    if name != config.name || target_triple != config.target_triple {
+        return Err(format!(
+            "update manifest is for {} ({}), not {} ({})",
+            name, target_triple, config.name, config.target_triple
+        ));
+    }
+
+    Ok(())
+}
+
+/// Download the content of a URL with `urllib`.
+///
+/// Using Python's HTTP client honors the proxy and certificate settings
+/// the application may have configured.
+fn fetch_url(py: Python, url: &str) -> PyResult<Vec<u8>> {
+    let response = py
+        .import("urllib.request")?
+        .call(py, "urlopen", (url,), None)?;
+    let data = response.call_method(py, "read", NoArgs, None)?;
+    response.call_method(py, "close", NoArgs, None)?;
+
+    Ok(data.cast_as::<PyBytes>(py)?.data(py).to_vec())
+}
+
+/// oxidized_update.check_for_update()
+///
+/// Returns the verified manifest as a dict if a newer version is available,
+/// or `None` otherwise.
+fn check_for_update(py: Python) -> PyResult<PyObject> {
+    let config = UPDATE_CONFIG
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| PyErr::new::<RuntimeError, _>(py, "update checks are not configured"))?;
+
+    let manifest = fetch_url(py, &config.url)?;
+    let signature = fetch_url(py, &format!("{}.sig", config.url))?;
+
+    verify_signature(&config.public_key, &manifest, &signature)
+        .map_err(|e| PyErr::new::<RuntimeError, _>(py, e))?;
+
+    let manifest = py
+        .import("json")?
+        .call(py, "loads", (PyBytes::new(py, &manifest),), None)?;
+    let name = manifest.get_item(py, "name")?.extract::<String>(py)?;
+    let target_triple = manifest
+        .get_item(py, "target_triple")?
+        .extract::<String>(py)?;
+
+    check_manifest_target(&name, &target_triple, &config)
+        .map_err(|e| PyErr::new::<RuntimeError, _>(py, e))?;
+
+    let available = manifest.get_item(py, "version")?.extract::<String>(py)?;
+
+    let newer = is_newer_version(&available, &config.version)
+        .map_err(|e| PyErr::new::<RuntimeError, _>(py, e))?;
+
+    if newer {
+        Ok(manifest)
+    } else {
+        Ok(py.None())
+    }
+}
+
+/// Create the `oxidized_update` module and register it in `sys.modules`.
+pub fn install_update_module(py: Python, config: &UpdateConfig) -> PyResult<()> {
+    parse_version(&config.version).map_err(|e| PyErr::new::<RuntimeError, _>(py, e))?;
+    UPDATE_CONFIG.lock().unwrap().replace(config.clone());
+
+    let m = PyModule::new(py, UPDATE_MODULE_NAME)?;
+    m.add(py, "check_for_update", py_fn!(py, check_for_update()))?;
+
+    py.import("sys")?
+        .get(py, "modules")?
+        .set_item(py, UPDATE_MODULE_NAME, m.into_object())
+}
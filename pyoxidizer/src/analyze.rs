@@ -27,6 +27,110 @@ const LSB_SHARED_LIBRARIES: &[&str] = &[
     "libutil.so.1",
 ];
 
+/// Shared libraries manylinux2010 and manylinux2014 binaries may link against.
+const MANYLINUX_LIBRARIES: &[&str] = &[
+    "ld-linux-x86-64.so.2",
+    "libc.so.6",
+    "libcrypt.so.1",
+    "libdl.so.2",
+    "libgcc_s.so.1",
+    "libGL.so.1",
+    "libglib-2.0.so.0",
+    "libgobject-2.0.so.0",
+    "libgthread-2.0.so.0",
+    "libICE.so.6",
+    "libm.so.6",
+    "libnsl.so.1",
+    "libpthread.so.0",
+    "libresolv.so.2",
+    "librt.so.1",
+    "libSM.so.6",
+    "libstdc++.so.6",
+    "libutil.so.1",
+    "libX11.so.6",
+    "libXext.so.6",
+    "libXrender.so.1",
+];
+
+/// Shared libraries manylinux1 binaries may link against.
+const MANYLINUX1_LIBRARIES: &[&str] = &[
+    "ld-linux-x86-64.so.2",
+    "libc.so.6",
+    "libcrypt.so.1",
+    "libdl.so.2",
+    "libgcc_s.so.1",
+    "libGL.so.1",
+    "libglib-2.0.so.0",
+    "libgobject-2.0.so.0",
+    "libgthread-2.0.so.0",
+    "libICE.so.6",
+    "libm.so.6",
+    "libncursesw.so.5",
+    "libnsl.so.1",
+    "libpanelw.so.5",
+    "libpthread.so.0",
+    "libresolv.so.2",
+    "librt.so.1",
+    "libSM.so.6",
+    "libstdc++.so.6",
+    "libutil.so.1",
+    "libX11.so.6",
+    "libXext.so.6",
+    "libXrender.so.1",
+];
+
+/// A Linux compatibility baseline, as defined by the manylinux PEPs.
+#[derive(Debug, PartialEq)]
+pub struct ManylinuxPolicy {
+    /// Name of the policy, e.g. `manylinux2014`.
+    pub name: &'static str,
+
+    /// Newest version of each versioned symbol family that may be required.
+    pub symbol_versions: &'static [(&'static str, &'static str)],
+
+    /// Shared libraries that may be linked against.
+    pub libraries: &'static [&'static str],
+}
+
+/// Known manylinux policies, from oldest to newest baseline.
+pub const MANYLINUX_POLICIES: &[ManylinuxPolicy] = &[
+    ManylinuxPolicy {
+        name: "manylinux1",
+        symbol_versions: &[
+            ("CXXABI", "1.3"),
+            ("GCC", "4.2.0"),
+            ("GLIBC", "2.5"),
+            ("GLIBCXX", "3.4.8"),
+        ],
+        libraries: MANYLINUX1_LIBRARIES,
+    },
+    ManylinuxPolicy {
+        name: "manylinux2010",
+        symbol_versions: &[
+            ("CXXABI", "1.3.3"),
+            ("GCC", "4.3.0"),
+            ("GLIBC", "2.12"),
+            ("GLIBCXX", "3.4.13"),
+        ],
+        libraries: MANYLINUX_LIBRARIES,
+    },
+    ManylinuxPolicy {
+        name: "manylinux2014",
+        symbol_versions: &[
+            ("CXXABI", "1.3.7"),
+            ("GCC", "4.8.0"),
+            ("GLIBC", "2.17"),
+            ("GLIBCXX", "3.4.19"),
+        ],
+        libraries: MANYLINUX_LIBRARIES,
+    },
+];
+
+/// Find a manylinux policy by name.
+pub fn find_manylinux_policy(name: &str) -> Option<&'static ManylinuxPolicy> {
+    MANYLINUX_POLICIES.iter().find(|p| p.name == name)
+}
+
 type DistroVersion = Vec<(&'static str, &'static str)>;
 
 lazy_static! {
@@ -459,6 +563,76 @@ pub fn find_undefined_elf_symbols(buffer: &[u8], elf: &goblin::elf::Elf) -> Vec<
     res
 }
 
+/// Find symbol versions required by undefined symbols that are newer than a policy allows.
+///
+/// Returns a description of each offending version. Symbol families the
+/// policy doesn't constrain are ignored.
+fn find_symbol_version_violations(
+    undefined_symbols: &[UndefinedSymbol],
+    policy: &ManylinuxPolicy,
+) -> Vec<String> {
+    // Keyed by version so each is only reported once.
+    let mut res = BTreeMap::new();
+
+    for symbol in undefined_symbols {
+        let version = match &symbol.version {
+            Some(version) => version,
+            None => continue,
+        };
+
+        let parts: Vec<&str> = version.splitn(2, '_').collect();
+        if parts.len() != 2 {
+            continue;
+        }
+
+        let max_version = match policy.symbol_versions.iter().find(|(f, _)| *f == parts[0]) {
+            Some((_, max_version)) => max_version,
+            None => continue,
+        };
+
+        if let (Some(v), Some(max)) = (
+            version_compare::Version::from(parts[1]),
+            version_compare::Version::from(max_version),
+        ) {
+            if v > max {
+                res.entry(version.clone()).or_insert_with(|| {
+                    format!(
+                        "requires {} (e.g. for {}); {} allows up to {}_{}",
+                        version, symbol.symbol, policy.name, parts[0], max_version
+                    )
+                });
+            }
+        }
+    }
+
+    res.into_iter().map(|(_, v)| v).collect()
+}
+
+/// Find reasons an ELF binary doesn't conform to a manylinux policy.
+///
+/// Shared libraries outside the policy and symbol versions newer than it
+/// allows are reported. An empty result means the binary conforms.
+pub fn find_manylinux_violations(buffer: &[u8], policy: &ManylinuxPolicy) -> Result<Vec<String>> {
+    let elf = goblin::elf::Elf::parse(buffer)?;
+
+    let mut res: Vec<String> = itertools::sorted(elf.libraries.iter())
+        .filter(|lib| !policy.libraries.contains(*lib))
+        .map(|lib| {
+            format!(
+                "links against {}, which {} doesn't provide",
+                lib, policy.name
+            )
+        })
+        .collect();
+
+    res.extend(find_symbol_version_violations(
+        &find_undefined_elf_symbols(buffer, &elf),
+        policy,
+    ));
+
+    Ok(res)
+}
+
 pub fn find_pe_dependencies(data: &[u8]) -> Result<Vec<String>> {
     let pe = goblin::pe::PE::parse(data)?;
     Ok(pe.libraries.iter().map(|l| (*l).to_string()).collect())
@@ -468,3 +642,49 @@ pub fn find_pe_dependencies_path(path: &Path) -> Result<Vec<String>> {
     let data = std::fs::read(path)?;
     find_pe_dependencies(&data)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn symbol(name: &str, version: &str) -> UndefinedSymbol {
+        UndefinedSymbol {
+            symbol: name.to_string(),
+            filename: Some("libc.so.6".to_string()),
+            version: Some(version.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_find_manylinux_policy() {
+        assert_eq!(
+            find_manylinux_policy("manylinux2014").map(|p| p.name),
+            Some("manylinux2014")
+        );
+        assert!(find_manylinux_policy("manylinux2000").is_none());
+    }
+
+    #[test]
+    fn test_find_symbol_version_violations() {
+        let policy = find_manylinux_policy("manylinux2010").unwrap();
+
+        let symbols = vec![
+            symbol("memcpy", "GLIBC_2.2.5"),
+            symbol("clock_gettime", "GLIBC_2.17"),
+            symbol("getrandom", "GLIBC_2.25"),
+            symbol("getentropy", "GLIBC_2.25"),
+            symbol("custom", "MYLIB_9.0"),
+        ];
+
+        assert_eq!(
+            find_symbol_version_violations(&symbols, policy),
+            vec![
+                "requires GLIBC_2.17 (e.g. for clock_gettime); manylinux2010 allows up to GLIBC_2.12",
+                "requires GLIBC_2.25 (e.g. for getrandom); manylinux2010 allows up to GLIBC_2.12",
+            ]
+        );
+
+        let policy = find_manylinux_policy("manylinux2014").unwrap();
+        assert_eq!(find_symbol_version_violations(&symbols, policy).len(), 1);
+    }
+}
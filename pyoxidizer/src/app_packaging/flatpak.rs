@@ -0,0 +1,176 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Flatpak manifests packaging installed files.
+
+A manifest is written next to a directory `app` holding the files to
+package. Its single module copies the files to `/app` in the sandbox, so
+`flatpak-builder` builds the application without rebuilding anything.
+Sandbox permissions are granted by the `finish-args` of the manifest.
+*/
+
+use {
+    anyhow::{anyhow, Result},
+    serde::Serialize,
+};
+
+/// Directory next to the manifest holding the packaged files.
+pub const FLATPAK_SOURCE_DIR: &str = "app";
+
+/// Ensure a string is a valid Flatpak application ID.
+///
+/// IDs are reverse DNS names with at least 3 components, e.g.
+/// `org.example.MyApp`.
+pub fn validate_app_id(app_id: &str) -> Result<()> {
+    let components = app_id.split('.').collect::<Vec<_>>();
+
+    let valid = app_id.len() <= 255
+        && components.len() >= 3
+        && components.iter().all(|c| {
+            !c.is_empty()
+                && !c.starts_with(|c: char| c.is_ascii_digit())
+                && c.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        });
+
+    if valid {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "invalid Flatpak application ID {}; expected a reverse DNS name like org.example.MyApp",
+            app_id
+        ))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FlatpakSource {
+    #[serde(rename = "type")]
+    pub source_type: String,
+    pub path: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FlatpakModule {
+    pub name: String,
+    pub buildsystem: String,
+    pub build_commands: Vec<String>,
+    pub sources: Vec<FlatpakSource>,
+}
+
+/// A `flatpak-builder` manifest.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FlatpakManifest {
+    pub app_id: String,
+    pub runtime: String,
+    pub runtime_version: String,
+    pub sdk: String,
+
+    /// Executable run by `flatpak run`.
+    pub command: String,
+
+    /// Sandbox permissions, e.g. `--share=network`.
+    pub finish_args: Vec<String>,
+
+    pub modules: Vec<FlatpakModule>,
+}
+
+impl FlatpakManifest {
+    /// Describe an application made of the files in the source directory.
+    pub fn new(
+        app_id: &str,
+        runtime: &str,
+        runtime_version: &str,
+        sdk: &str,
+        command: &str,
+        finish_args: Vec<String>,
+    ) -> Result<Self> {
+        validate_app_id(app_id)?;
+
+        if let Some(arg) = finish_args.iter().find(|a| !a.starts_with("--")) {
+            return Err(anyhow!(
+                "finish arguments must be options like --share=network; got {}",
+                arg
+            ));
+        }
+
+        Ok(Self {
+            app_id: app_id.to_string(),
+            runtime: runtime.to_string(),
+            runtime_version: runtime_version.to_string(),
+            sdk: sdk.to_string(),
+            command: command.to_string(),
+            finish_args,
+            modules: vec![FlatpakModule {
+                name: app_id.to_string(),
+                buildsystem: "simple".to_string(),
+                build_commands: vec!["cp -a . /app/".to_string()],
+                sources: vec![FlatpakSource {
+                    source_type: "dir".to_string(),
+                    path: FLATPAK_SOURCE_DIR.to_string(),
+                }],
+            }],
+        })
+    }
+
+    /// Filename of the manifest.
+    pub fn filename(&self) -> String {
+        format!("{}.json", self.app_id)
+    }
+
+    /// Obtain the content of the manifest.
+    pub fn render(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)? + "\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_app_id() {
+        assert!(validate_app_id("org.example.MyApp").is_ok());
+        assert!(validate_app_id("io.github.some-user.my_app").is_ok());
+        assert!(validate_app_id("org.example").is_err());
+        assert!(validate_app_id("org.1example.App").is_err());
+        assert!(validate_app_id("org..App").is_err());
+    }
+
+    #[test]
+    fn test_manifest() -> Result<()> {
+        let manifest = FlatpakManifest::new(
+            "org.example.MyApp",
+            "org.freedesktop.Platform",
+            "20.08",
+            "org.freedesktop.Sdk",
+            "/app/bin/myapp",
+            vec!["--share=network".to_string()],
+        )?;
+
+        assert_eq!(manifest.filename(), "org.example.MyApp.json");
+
+        let value: serde_json::Value = serde_json::from_str(&manifest.render()?)?;
+        assert_eq!(value["app-id"], "org.example.MyApp");
+        assert_eq!(value["runtime-version"], "20.08");
+        assert_eq!(value["finish-args"][0], "--share=network");
+        assert_eq!(value["modules"][0]["build-commands"][0], "cp -a . /app/");
+        assert_eq!(value["modules"][0]["sources"][0]["path"], "app");
+
+        assert!(FlatpakManifest::new(
+            "org.example.MyApp",
+            "org.freedesktop.Platform",
+            "20.08",
+            "org.freedesktop.Sdk",
+            "/app/bin/myapp",
+            vec!["share=network".to_string()],
+        )
+        .is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,380 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Layouts of installed applications.
+
+A layout defines the directories executables (`bin`), application files
+(`lib`), data files (`share`), and configuration files are installed to,
+relative to the root of a package. They depend on the target platform and
+on whether the application is installed for all users or a single user.
+
+On Linux and other Unix platforms, system installs follow the Filesystem
+Hierarchy Standard: the application lives in `usr/lib/<name>`, launchers
+in `usr/bin` run its executables, and configuration goes to `etc/<name>`.
+User installs mirror this in `.local` and `.config` of the home directory.
+On Windows, executables and application files share the install directory.
+*/
+
+use {
+    super::resource::{FileContent, FileManifest},
+    anyhow::{anyhow, Result},
+    std::path::{Component, Path, PathBuf},
+};
+
+/// Who an application is installed for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InstallScope {
+    /// All users, typically requiring administrator privileges.
+    System,
+
+    /// The installing user, in their home directory.
+    User,
+}
+
+impl InstallScope {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "system" => Ok(InstallScope::System),
+            "user" => Ok(InstallScope::User),
+            _ => Err(anyhow!(
+                "install scope must be system or user; got {}",
+                value
+            )),
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            InstallScope::System => "system",
+            InstallScope::User => "user",
+        }
+    }
+}
+
+/// Directories an application is installed to.
+///
+/// Directories are relative to the root of the package. Empty paths denote
+/// the root.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InstallLayout {
+    /// Name of the application.
+    pub name: String,
+
+    pub scope: InstallScope,
+
+    /// Directory holding launchers of executables.
+    pub bin_dir: PathBuf,
+
+    /// Directory holding the application files.
+    pub lib_dir: PathBuf,
+
+    /// Directory holding architecture independent data.
+    pub share_dir: PathBuf,
+
+    /// Directory holding configuration files.
+    pub config_dir: PathBuf,
+}
+
+impl InstallLayout {
+    /// Resolve the layout of an application on a target platform.
+    ///
+    /// `prefix` is the directory `bin`, `lib`, and `share` are in. It
+    /// defaults to `usr` for system installs and `.local` for user installs
+    /// on Unix platforms, and to the root on Windows.
+    pub fn for_target(
+        name: &str,
+        target_triple: &str,
+        scope: InstallScope,
+        prefix: Option<&str>,
+    ) -> Result<Self> {
+        if name.is_empty() || name.contains(|c: char| c == '/' || c == '\\') {
+            return Err(anyhow!("invalid application name {}", name));
+        }
+
+        let layout = if target_triple.contains("pc-windows") {
+            let prefix = PathBuf::from(prefix.unwrap_or(""));
+
+            Self {
+                name: name.to_string(),
+                scope,
+                bin_dir: prefix.clone(),
+                lib_dir: prefix.clone(),
+                share_dir: prefix.join("share"),
+                config_dir: prefix.join("config"),
+            }
+        } else {
+            let (default_prefix, config_dir) = match scope {
+                InstallScope::System => ("usr", Path::new("etc").join(name)),
+                InstallScope::User => (".local", Path::new(".config").join(name)),
+            };
+            let prefix = PathBuf::from(prefix.unwrap_or(default_prefix));
+
+            Self {
+                name: name.to_string(),
+                scope,
+                bin_dir: prefix.join("bin"),
+                lib_dir: prefix.join("lib").join(name),
+                share_dir: prefix.join("share").join(name),
+                config_dir,
+            }
+        };
+
+        for dir in &[
+            &layout.bin_dir,
+            &layout.lib_dir,
+            &layout.share_dir,
+            &layout.config_dir,
+        ] {
+            if dir.is_absolute()
+                || dir
+                    .components()
+                    .any(|c| c != Component::Normal(c.as_os_str()))
+            {
+                return Err(anyhow!(
+                    "install directories must be relative without ..; got {}",
+                    dir.display()
+                ));
+            }
+        }
+
+        Ok(layout)
+    }
+
+    /// Whether executables are run by launchers in a separate directory.
+    pub fn has_launchers(&self) -> bool {
+        self.bin_dir != self.lib_dir
+    }
+
+    /// Obtain the path an executable of the application is run from.
+    ///
+    /// `exe` is relative to the application files. Executables at their
+    /// root are run by launchers.
+    pub fn installed_exe_path(&self, exe: &Path) -> PathBuf {
+        match exe.file_name() {
+            Some(filename) if self.has_launchers() && exe.parent() == Some(Path::new("")) => {
+                self.bin_dir.join(filename)
+            }
+            _ => self.lib_dir.join(exe),
+        }
+    }
+
+    /// Install application files.
+    ///
+    /// Files are installed to the `lib` directory. Launchers are added to
+    /// the `bin` directory for executables at the root of the application
+    /// files, so executables find files relative to their own path.
+    pub fn install(&self, files: &FileManifest) -> Result<FileManifest> {
+        let mut installed = FileManifest::default();
+
+        for (path, content) in files.entries() {
+            let installed_path = self.lib_dir.join(path);
+            installed.add_file(&installed_path, content)?;
+
+            let launcher = self.installed_exe_path(path);
+            if content.executable && launcher != installed_path {
+                installed.add_file(
+                    &launcher,
+                    &FileContent {
+                        data: render_launcher(&self.bin_dir, &installed_path).into_bytes(),
+                        executable: true,
+                    },
+                )?;
+            }
+        }
+
+        Ok(installed)
+    }
+}
+
+/// Obtain the relative path from a directory to a path.
+fn relative_path(from_dir: &Path, to: &Path) -> PathBuf {
+    let from = from_dir.components().collect::<Vec<_>>();
+    let to = to.components().collect::<Vec<_>>();
+
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut result = PathBuf::new();
+    for _ in common..from.len() {
+        result.push("..");
+    }
+    for c in &to[common..] {
+        result.push(c.as_os_str());
+    }
+
+    result
+}
+
+/// Obtain a shell script running an executable.
+///
+/// The executable is found relative to the script, so installs can be
+/// moved.
+fn render_launcher(bin_dir: &Path, exe: &Path) -> String {
+    let target = relative_path(bin_dir, exe)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    format!(
+        "#!/bin/sh\nexec \"$(dirname \"$0\")/{}\" \"$@\"\n",
+        target
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('$', "\\$")
+            .replace('`', "\\`")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_target() -> Result<()> {
+        let layout = InstallLayout::for_target(
+            "myapp",
+            "x86_64-unknown-linux-gnu",
+            InstallScope::System,
+            None,
+        )?;
+        assert_eq!(layout.bin_dir, PathBuf::from("usr/bin"));
+        assert_eq!(layout.lib_dir, PathBuf::from("usr/lib/myapp"));
+        assert_eq!(layout.share_dir, PathBuf::from("usr/share/myapp"));
+        assert_eq!(layout.config_dir, PathBuf::from("etc/myapp"));
+
+        let layout =
+            InstallLayout::for_target("myapp", "x86_64-apple-darwin", InstallScope::User, None)?;
+        assert_eq!(layout.bin_dir, PathBuf::from(".local/bin"));
+        assert_eq!(layout.lib_dir, PathBuf::from(".local/lib/myapp"));
+        assert_eq!(layout.config_dir, PathBuf::from(".config/myapp"));
+
+        let layout = InstallLayout::for_target(
+            "myapp",
+            "x86_64-unknown-linux-gnu",
+            InstallScope::System,
+            Some(""),
+        )?;
+        assert_eq!(layout.bin_dir, PathBuf::from("bin"));
+        assert_eq!(layout.lib_dir, PathBuf::from("lib/myapp"));
+
+        let layout =
+            InstallLayout::for_target("myapp", "x86_64-pc-windows-msvc", InstallScope::User, None)?;
+        assert_eq!(layout.bin_dir, PathBuf::new());
+        assert_eq!(layout.lib_dir, PathBuf::new());
+        assert_eq!(layout.share_dir, PathBuf::from("share"));
+        assert!(!layout.has_launchers());
+
+        assert!(InstallLayout::for_target(
+            "myapp",
+            "x86_64-unknown-linux-gnu",
+            InstallScope::System,
+            Some("../usr")
+        )
+        .is_err());
+        assert!(InstallLayout::for_target(
+            "my/app",
+            "x86_64-unknown-linux-gnu",
+            InstallScope::System,
+            None
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_install() -> Result<()> {
+        let mut files = FileManifest::default();
+        files.add_file(
+            Path::new("myapp"),
+            &FileContent {
+                data: b"exe".to_vec(),
+                executable: true,
+            },
+        )?;
+        files.add_file(
+            Path::new("lib/foo.py"),
+            &FileContent {
+                data: vec![],
+                executable: false,
+            },
+        )?;
+
+        let layout = InstallLayout::for_target(
+            "myapp",
+            "x86_64-unknown-linux-gnu",
+            InstallScope::System,
+            None,
+        )?;
+        let installed = layout.install(&files)?;
+
+        assert_eq!(
+            installed
+                .entries()
+                .map(|(p, _)| p.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                PathBuf::from("usr/bin/myapp"),
+                PathBuf::from("usr/lib/myapp/lib/foo.py"),
+                PathBuf::from("usr/lib/myapp/myapp"),
+            ]
+        );
+
+        let launcher = installed.get(Path::new("usr/bin/myapp")).unwrap();
+        assert!(launcher.executable);
+        assert_eq!(
+            String::from_utf8(launcher.data.clone())?,
+            "#!/bin/sh\nexec \"$(dirname \"$0\")/../lib/myapp/myapp\" \"$@\"\n"
+        );
+        assert_eq!(
+            layout.installed_exe_path(Path::new("myapp")),
+            PathBuf::from("usr/bin/myapp")
+        );
+        assert_eq!(
+            layout.installed_exe_path(Path::new("tools/helper")),
+            PathBuf::from("usr/lib/myapp/tools/helper")
+        );
+
+        let layout = InstallLayout::for_target(
+            "myapp",
+            "x86_64-pc-windows-msvc",
+            InstallScope::System,
+            None,
+        )?;
+        assert_eq!(layout.install(&files)?, files);
+
+        let mut files = FileManifest::default();
+        files.add_file(
+            Path::new("アプリ"),
+            &FileContent {
+                data: b"exe".to_vec(),
+                executable: true,
+            },
+        )?;
+        let layout = InstallLayout::for_target(
+            "アプリ",
+            "x86_64-unknown-linux-gnu",
+            InstallScope::User,
+            None,
+        )?;
+        let installed = layout.install(&files)?;
+        assert_eq!(
+            String::from_utf8(
+                installed
+                    .get(Path::new(".local/bin/アプリ"))
+                    .unwrap()
+                    .data
+                    .clone()
+            )?,
+            "#!/bin/sh\nexec \"$(dirname \"$0\")/../lib/アプリ/アプリ\" \"$@\"\n"
+        );
+
+        Ok(())
+    }
+}
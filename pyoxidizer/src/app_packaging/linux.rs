@@ -0,0 +1,386 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Integration files of Linux applications.
+
+Services are run by systemd as defined by unit files installed to
+`lib/systemd/system`. Desktop applications are shown in menus as defined by
+`.desktop` files installed to `share/applications`, with icons installed to
+the `hicolor` icon theme in `share/icons`.
+*/
+
+use {
+    anyhow::{anyhow, Result},
+    std::collections::BTreeMap,
+    std::path::PathBuf,
+};
+
+/// Directory systemd unit files are installed to, relative to the prefix.
+pub const SYSTEMD_UNIT_DIR: &str = "lib/systemd/system";
+
+/// Directory desktop entries are installed to, relative to the prefix.
+pub const DESKTOP_ENTRY_DIR: &str = "share/applications";
+
+/// Directory of the default icon theme, relative to the prefix.
+pub const ICON_THEME_DIR: &str = "share/icons/hicolor";
+
+/// Whether a string is a valid systemd unit name, without its suffix.
+pub fn is_valid_unit_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() < 250
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ":-_.\\@".contains(c))
+}
+
+/// Quote an argument of a command line in a systemd unit.
+fn quote_systemd_arg(arg: &str) -> String {
+    if !arg.is_empty()
+        && !arg
+            .chars()
+            .any(|c| c.is_whitespace() || "\"'\\;$%".contains(c))
+    {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '$' => quoted.push_str("$$"),
+            '%' => quoted.push_str("%%"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+/// A systemd service running an executable.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SystemdService {
+    /// Name of the unit, without the `.service` suffix.
+    pub name: String,
+
+    /// Description shown by `systemctl status`.
+    pub description: String,
+
+    /// Command line of the service.
+    pub exec_start: Vec<String>,
+
+    /// User the service runs as.
+    ///
+    /// Services run as root if `None`.
+    pub user: Option<String>,
+
+    /// Environment variables of the service.
+    pub environment: BTreeMap<String, String>,
+
+    /// Working directory of the service.
+    pub working_directory: Option<String>,
+
+    /// When the service is restarted, e.g. `on-failure`.
+    pub restart: String,
+
+    /// Target the service is started by when enabled.
+    pub wanted_by: String,
+}
+
+impl SystemdService {
+    /// Filename of the unit file.
+    pub fn filename(&self) -> String {
+        format!("{}.service", self.name)
+    }
+
+    /// Obtain the content of the unit file.
+    pub fn render(&self) -> String {
+        let mut lines = vec![
+            "[Unit]".to_string(),
+            format!("Description={}", self.description),
+            "After=network.target".to_string(),
+            "".to_string(),
+            "[Service]".to_string(),
+            "Type=simple".to_string(),
+            format!(
+                "ExecStart={}",
+                self.exec_start
+                    .iter()
+                    .map(|arg| quote_systemd_arg(arg))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        ];
+
+        if let Some(user) = &self.user {
+            lines.push(format!("User={}", user));
+        }
+        if let Some(working_directory) = &self.working_directory {
+            lines.push(format!(
+                "WorkingDirectory={}",
+                quote_systemd_arg(working_directory)
+            ));
+        }
+        for (key, value) in &self.environment {
+            lines.push(format!(
+                "Environment={}",
+                quote_systemd_arg(&format!("{}={}", key, value))
+            ));
+        }
+
+        lines.push(format!("Restart={}", self.restart));
+        lines.push("".to_string());
+        lines.push("[Install]".to_string());
+        lines.push(format!("WantedBy={}", self.wanted_by));
+        lines.push("".to_string());
+
+        lines.join("\n")
+    }
+}
+
+/// Escape a string value of a desktop entry.
+fn escape_desktop_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+        .replace('\r', "\\r")
+}
+
+/// Quote an argument of the `Exec` key of a desktop entry.
+///
+/// The result is escaped as a string value too.
+fn quote_desktop_exec_arg(arg: &str) -> String {
+    let arg = arg.replace('%', "%%");
+
+    let quoted = if !arg.is_empty()
+        && !arg
+            .chars()
+            .any(|c| c.is_whitespace() || "\"'\\><~|&;$*?#()`".contains(c))
+    {
+        arg
+    } else {
+        let mut quoted = String::from("\"");
+        for c in arg.chars() {
+            if "\"`$\\".contains(c) {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        quoted.push('"');
+        quoted
+    };
+
+    escape_desktop_value(&quoted)
+}
+
+/// A desktop entry launching an application.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DesktopEntry {
+    /// Name of the desktop entry file and icon, without suffix.
+    pub id: String,
+
+    /// Name of the application shown in menus.
+    pub name: String,
+
+    /// Tooltip of the application.
+    pub comment: Option<String>,
+
+    /// Command line launching the application.
+    pub exec: Vec<String>,
+
+    /// Name of the icon in the icon theme.
+    pub icon: Option<String>,
+
+    /// Menu categories, e.g. `Utility`.
+    pub categories: Vec<String>,
+
+    /// Whether the application runs in a terminal.
+    pub terminal: bool,
+}
+
+impl DesktopEntry {
+    /// Filename of the desktop entry.
+    pub fn filename(&self) -> String {
+        format!("{}.desktop", self.id)
+    }
+
+    /// Obtain the content of the desktop entry file.
+    pub fn render(&self) -> String {
+        let mut lines = vec![
+            "[Desktop Entry]".to_string(),
+            "Type=Application".to_string(),
+            format!("Name={}", escape_desktop_value(&self.name)),
+        ];
+
+        if let Some(comment) = &self.comment {
+            lines.push(format!("Comment={}", escape_desktop_value(comment)));
+        }
+
+        lines.push(format!(
+            "Exec={}",
+            self.exec
+                .iter()
+                .map(|arg| quote_desktop_exec_arg(arg))
+                .collect::<Vec<_>>()
+                .join(" ")
+        ));
+
+        if let Some(icon) = &self.icon {
+            lines.push(format!("Icon={}", escape_desktop_value(icon)));
+        }
+
+        if !self.categories.is_empty() {
+            lines.push(format!(
+                "Categories={};",
+                self.categories
+                    .iter()
+                    .map(|c| escape_desktop_value(c).replace(';', "\\;"))
+                    .collect::<Vec<_>>()
+                    .join(";")
+            ));
+        }
+
+        lines.push(format!("Terminal={}", self.terminal));
+        lines.push("".to_string());
+
+        lines.join("\n")
+    }
+}
+
+/// Obtain the path an icon is installed to in the icon theme.
+///
+/// The path is relative to the prefix, e.g.
+/// `share/icons/hicolor/48x48/apps/myapp.png`. SVG icons are scalable.
+/// The size of PNG icons is read from their header.
+pub fn icon_install_path(name: &str, filename: &str, data: &[u8]) -> Result<PathBuf> {
+    let extension = filename
+        .rsplit('.')
+        .next()
+        .filter(|_| filename.contains('.'))
+        .map(|e| e.to_lowercase())
+        .unwrap_or_default();
+
+    let size = match extension.as_str() {
+        "svg" => "scalable".to_string(),
+        "png" => {
+            // Width and height are the first fields of the IHDR chunk.
+            if data.len() < 24 || &data[..8] != b"\x89PNG\r\n\x1a\n" || &data[12..16] != b"IHDR" {
+                return Err(anyhow!("{} is not a PNG image", filename));
+            }
+
+            let width = u32::from_be_bytes([data[16], data[17], data[18], data[19]]);
+            let height = u32::from_be_bytes([data[20], data[21], data[22], data[23]]);
+
+            format!("{}x{}", width, height)
+        }
+        _ => {
+            return Err(anyhow!(
+                "unsupported icon {}; expected a .png or .svg file",
+                filename
+            ))
+        }
+    };
+
+    Ok(PathBuf::from(ICON_THEME_DIR)
+        .join(size)
+        .join("apps")
+        .join(format!("{}.{}", name, extension)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_systemd_service() {
+        let mut environment = BTreeMap::new();
+        environment.insert("GREETING".to_string(), "hello world".to_string());
+
+        let service = SystemdService {
+            name: "myapp".to_string(),
+            description: "My App".to_string(),
+            exec_start: vec![
+                "/opt/myapp/myapp".to_string(),
+                "--serve".to_string(),
+                "100%".to_string(),
+            ],
+            user: Some("myapp".to_string()),
+            environment,
+            working_directory: None,
+            restart: "on-failure".to_string(),
+            wanted_by: "multi-user.target".to_string(),
+        };
+
+        assert_eq!(service.filename(), "myapp.service");
+        assert_eq!(
+            service.render(),
+            "[Unit]\n\
+             Description=My App\n\
+             After=network.target\n\
+             \n\
+             [Service]\n\
+             Type=simple\n\
+             ExecStart=/opt/myapp/myapp --serve \"100%%\"\n\
+             User=myapp\n\
+             Environment=\"GREETING=hello world\"\n\
+             Restart=on-failure\n\
+             \n\
+             [Install]\n\
+             WantedBy=multi-user.target\n"
+        );
+
+        assert!(is_valid_unit_name("my-app@instance"));
+        assert!(!is_valid_unit_name("my app"));
+        assert!(!is_valid_unit_name(""));
+    }
+
+    #[test]
+    fn test_desktop_entry() {
+        let entry = DesktopEntry {
+            id: "myapp".to_string(),
+            name: "My App".to_string(),
+            comment: Some("Does things".to_string()),
+            exec: vec!["/opt/my app/myapp".to_string(), "--gui".to_string()],
+            icon: Some("myapp".to_string()),
+            categories: vec!["Utility".to_string(), "Development".to_string()],
+            terminal: false,
+        };
+
+        assert_eq!(entry.filename(), "myapp.desktop");
+        assert_eq!(
+            entry.render(),
+            "[Desktop Entry]\n\
+             Type=Application\n\
+             Name=My App\n\
+             Comment=Does things\n\
+             Exec=\"/opt/my app/myapp\" --gui\n\
+             Icon=myapp\n\
+             Categories=Utility;Development;\n\
+             Terminal=false\n"
+        );
+    }
+
+    #[test]
+    fn test_icon_install_path() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend_from_slice(&48u32.to_be_bytes());
+        png.extend_from_slice(&48u32.to_be_bytes());
+
+        assert_eq!(
+            icon_install_path("myapp", "icon.png", &png).unwrap(),
+            PathBuf::from("share/icons/hicolor/48x48/apps/myapp.png")
+        );
+        assert_eq!(
+            icon_install_path("myapp", "icon.SVG", b"<svg/>").unwrap(),
+            PathBuf::from("share/icons/hicolor/scalable/apps/myapp.svg")
+        );
+        assert!(icon_install_path("myapp", "icon.png", b"GIF89a").is_err());
+        assert!(icon_install_path("myapp", "icon.ico", b"").is_err());
+    }
+}
@@ -2,5 +2,10 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+pub mod flatpak;
 pub mod glob;
+pub mod install_layout;
+pub mod linux;
+pub mod nsis;
 pub mod resource;
+pub mod snapcraft;
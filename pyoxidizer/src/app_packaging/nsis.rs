@@ -0,0 +1,379 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Windows installers built with NSIS.
+
+Installed files are described by an include file, `pyoxidizer.nsh`,
+defining the product and macros installing and uninstalling the files.
+Installers are built by `makensis` from a generated script using it, or from
+a user-provided script including it, so existing NSIS based install flows
+with custom pages and upgrade logic can install the files.
+*/
+
+use {
+    super::resource::FileManifest,
+    anyhow::{anyhow, Result},
+    std::collections::BTreeMap,
+    std::path::{Path, PathBuf},
+};
+
+/// Filename of the include file describing installed files.
+pub const NSIS_INCLUDE_FILENAME: &str = "pyoxidizer.nsh";
+
+/// Filename of the generated installer script.
+pub const NSIS_SCRIPT_FILENAME: &str = "installer.nsi";
+
+/// Escape a string so NSIS doesn't expand variables in it.
+fn escape_nsis(value: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in value.chars() {
+        match c {
+            '$' => escaped.push_str("$$"),
+            '"' => escaped.push_str("$\\\""),
+            '\n' => escaped.push_str("$\\n"),
+            '\r' => escaped.push_str("$\\r"),
+            '\t' => escaped.push_str("$\\t"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Quote a string for NSIS scripts.
+pub fn nsis_string(value: &str) -> String {
+    format!("\"{}\"", escape_nsis(value))
+}
+
+/// Quote a relative path as a Windows path below `$INSTDIR`.
+fn install_path(path: &Path) -> String {
+    let mut result = "\"$INSTDIR".to_string();
+
+    for component in path.components() {
+        result.push('\\');
+        result.push_str(&escape_nsis(&component.as_os_str().to_string_lossy()));
+    }
+
+    result.push('"');
+    result
+}
+
+/// Obtain the NSIS variable holding the program files directory.
+pub fn program_files_variable(target_triple: &str) -> Result<&'static str> {
+    if !target_triple.contains("pc-windows") {
+        return Err(anyhow!(
+            "NSIS installers require a Windows target triple; got {}",
+            target_triple
+        ));
+    }
+
+    if target_triple.starts_with("i686-") {
+        Ok("$PROGRAMFILES")
+    } else {
+        Ok("$PROGRAMFILES64")
+    }
+}
+
+/// Whether a string is a valid name of a `!define` symbol.
+pub fn is_valid_define_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// A Windows installer of a product.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NsisInstaller {
+    pub product_name: String,
+    pub product_version: String,
+    pub publisher: Option<String>,
+
+    /// Path of the executable shortcuts launch, relative to the install
+    /// directory.
+    pub exe: Option<PathBuf>,
+
+    /// Additional `!define` symbols for custom scripts.
+    pub defines: BTreeMap<String, String>,
+
+    /// Whether the product is installed for the installing user only.
+    ///
+    /// Per-user installs don't require administrator privileges.
+    pub per_user: bool,
+}
+
+impl NsisInstaller {
+    /// Obtain the content of the include file.
+    ///
+    /// Installed files are read from `files_path`, where `files` are
+    /// written.
+    pub fn render_include(&self, files: &FileManifest, files_path: &Path) -> String {
+        let mut lines = vec![
+            format!("!define PRODUCT_NAME {}", nsis_string(&self.product_name)),
+            format!(
+                "!define PRODUCT_VERSION {}",
+                nsis_string(&self.product_version)
+            ),
+        ];
+
+        if let Some(publisher) = &self.publisher {
+            lines.push(format!(
+                "!define PRODUCT_PUBLISHER {}",
+                nsis_string(publisher)
+            ));
+        }
+        if let Some(exe) = &self.exe {
+            lines.push(format!("!define PRODUCT_EXE {}", install_path(exe)));
+        }
+        lines.push(
+            "!define PRODUCT_UNINSTALL_KEY \"Software\\Microsoft\\Windows\\CurrentVersion\\Uninstall\\${PRODUCT_NAME}\""
+                .to_string(),
+        );
+        for (name, value) in &self.defines {
+            lines.push(format!("!define {} {}", name, nsis_string(value)));
+        }
+
+        lines.push("".to_string());
+        lines.push("!macro PYOXIDIZER_INSTALL_FILES".to_string());
+
+        let mut current_dir = None;
+        for (path, _) in files.entries() {
+            let parent = path.parent().unwrap_or_else(|| Path::new(""));
+            if current_dir != Some(parent) {
+                lines.push(format!("  SetOutPath {}", install_path(parent)));
+                current_dir = Some(parent);
+            }
+
+            lines.push(format!(
+                "  File {}",
+                nsis_string(&files_path.join(path).display().to_string())
+            ));
+        }
+
+        lines.push("!macroend".to_string());
+        lines.push("".to_string());
+        lines.push("!macro PYOXIDIZER_UNINSTALL_FILES".to_string());
+
+        for (path, _) in files.entries() {
+            lines.push(format!("  Delete {}", install_path(path)));
+        }
+
+        // Remove the deepest directories first, so parents are empty.
+        let mut dirs = files.relative_directories();
+        dirs.sort_by_key(|d| std::cmp::Reverse(d.components().count()));
+        for dir in dirs {
+            lines.push(format!("  RMDir {}", install_path(&dir)));
+        }
+
+        lines.push("!macroend".to_string());
+        lines.push("".to_string());
+
+        lines.join("\r\n")
+    }
+
+    /// Obtain the content of the generated installer script.
+    ///
+    /// The installer installs to a directory in `program_files` for all
+    /// users, or in the programs directory of the user for per-user
+    /// installs. It registers an uninstaller, and adds a start menu shortcut
+    /// to the executable, if defined.
+    pub fn render_script(&self, program_files: &str) -> String {
+        // SHCTX is the registry root of the shell context set by
+        // SetShellVarContext.
+        let (install_root, context, hive, level) = if self.per_user {
+            ("$LOCALAPPDATA\\Programs", "current", "HKCU", "user")
+        } else {
+            (program_files, "all", "HKLM", "admin")
+        };
+
+        let mut lines = vec![
+            "Unicode True".to_string(),
+            "".to_string(),
+            "!include \"MUI2.nsh\"".to_string(),
+            format!("!include \"${{PYOXIDIZER_DIR}}\\{}\"", NSIS_INCLUDE_FILENAME),
+            "".to_string(),
+            "Name \"${PRODUCT_NAME}\"".to_string(),
+            "OutFile \"${PYOXIDIZER_OUTFILE}\"".to_string(),
+            format!("InstallDir \"{}\\${{PRODUCT_NAME}}\"", install_root),
+            format!(
+                "InstallDirRegKey {} \"Software\\${{PRODUCT_NAME}}\" \"InstallDir\"",
+                hive
+            ),
+            format!("RequestExecutionLevel {}", level),
+            "".to_string(),
+            "!insertmacro MUI_PAGE_DIRECTORY".to_string(),
+            "!insertmacro MUI_PAGE_INSTFILES".to_string(),
+            "!insertmacro MUI_UNPAGE_CONFIRM".to_string(),
+            "!insertmacro MUI_UNPAGE_INSTFILES".to_string(),
+            "!insertmacro MUI_LANGUAGE \"English\"".to_string(),
+            "".to_string(),
+            "Section \"Install\"".to_string(),
+            format!("  SetShellVarContext {}", context),
+            "  !insertmacro PYOXIDIZER_INSTALL_FILES".to_string(),
+            "  SetOutPath \"$INSTDIR\"".to_string(),
+            "  WriteUninstaller \"$INSTDIR\\uninstall.exe\"".to_string(),
+            "  WriteRegStr SHCTX \"Software\\${PRODUCT_NAME}\" \"InstallDir\" \"$INSTDIR\""
+                .to_string(),
+            "  WriteRegStr SHCTX \"${PRODUCT_UNINSTALL_KEY}\" \"DisplayName\" \"${PRODUCT_NAME}\""
+                .to_string(),
+            "  WriteRegStr SHCTX \"${PRODUCT_UNINSTALL_KEY}\" \"DisplayVersion\" \"${PRODUCT_VERSION}\""
+                .to_string(),
+            "  WriteRegStr SHCTX \"${PRODUCT_UNINSTALL_KEY}\" \"UninstallString\" '\"$INSTDIR\\uninstall.exe\"'"
+                .to_string(),
+        ];
+
+        if self.publisher.is_some() {
+            lines.push(
+                "  WriteRegStr SHCTX \"${PRODUCT_UNINSTALL_KEY}\" \"Publisher\" \"${PRODUCT_PUBLISHER}\""
+                    .to_string(),
+            );
+        }
+        if self.exe.is_some() {
+            lines.push(
+                "  CreateShortCut \"$SMPROGRAMS\\${PRODUCT_NAME}.lnk\" \"${PRODUCT_EXE}\""
+                    .to_string(),
+            );
+        }
+
+        lines.extend(vec![
+            "SectionEnd".to_string(),
+            "".to_string(),
+            "Section \"Uninstall\"".to_string(),
+            format!("  SetShellVarContext {}", context),
+        ]);
+
+        if self.exe.is_some() {
+            lines.push("  Delete \"$SMPROGRAMS\\${PRODUCT_NAME}.lnk\"".to_string());
+        }
+
+        lines.extend(vec![
+            "  !insertmacro PYOXIDIZER_UNINSTALL_FILES".to_string(),
+            "  Delete \"$INSTDIR\\uninstall.exe\"".to_string(),
+            "  RMDir \"$INSTDIR\"".to_string(),
+            "  DeleteRegKey SHCTX \"${PRODUCT_UNINSTALL_KEY}\"".to_string(),
+            "  DeleteRegKey SHCTX \"Software\\${PRODUCT_NAME}\"".to_string(),
+            "SectionEnd".to_string(),
+            "".to_string(),
+        ]);
+
+        lines.join("\r\n")
+    }
+}
+
+/// Obtain the arguments of `makensis` building an installer.
+///
+/// `PYOXIDIZER_DIR` is defined as the directory holding the include file
+/// and `PYOXIDIZER_OUTFILE` as the path of the installer. Scripts are read
+/// as UTF-8, as generated scripts are, instead of the system code page, so
+/// non-ASCII file names and paths survive.
+pub fn makensis_args(script: &Path, include_dir: &Path, outfile: &Path) -> Vec<String> {
+    vec![
+        "-V2".to_string(),
+        "-INPUTCHARSET".to_string(),
+        "UTF8".to_string(),
+        format!("-DPYOXIDIZER_DIR={}", include_dir.display()),
+        format!("-DPYOXIDIZER_OUTFILE={}", outfile.display()),
+        script.display().to_string(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::app_packaging::resource::FileContent};
+
+    #[test]
+    fn test_nsis_string() {
+        assert_eq!(nsis_string("My App"), "\"My App\"");
+        assert_eq!(nsis_string("$5 \"deal\""), "\"$$5 $\\\"deal$\\\"\"");
+    }
+
+    #[test]
+    fn test_program_files_variable() {
+        assert_eq!(
+            program_files_variable("x86_64-pc-windows-msvc").unwrap(),
+            "$PROGRAMFILES64"
+        );
+        assert_eq!(
+            program_files_variable("i686-pc-windows-msvc").unwrap(),
+            "$PROGRAMFILES"
+        );
+        assert!(program_files_variable("x86_64-unknown-linux-gnu").is_err());
+    }
+
+    #[test]
+    fn test_is_valid_define_name() {
+        assert!(is_valid_define_name("LEGACY_KEY2"));
+        assert!(!is_valid_define_name("2KEY"));
+        assert!(!is_valid_define_name("MY-KEY"));
+        assert!(!is_valid_define_name(""));
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        let content = FileContent {
+            data: vec![],
+            executable: false,
+        };
+        let mut files = FileManifest::default();
+        files.add_file(Path::new("myapp.exe"), &content)?;
+        files.add_file(Path::new("lib/foo/bar.py"), &content)?;
+        files.add_file(Path::new("lib/foo.py"), &content)?;
+        files.add_file(Path::new("données/файл.txt"), &content)?;
+
+        let mut defines = BTreeMap::new();
+        defines.insert("LEGACY_KEY".to_string(), "Software\\Old".to_string());
+
+        let installer = NsisInstaller {
+            product_name: "My App".to_string(),
+            product_version: "1.0".to_string(),
+            publisher: None,
+            exe: Some(PathBuf::from("myapp.exe")),
+            defines,
+            per_user: false,
+        };
+
+        let include = installer.render_include(&files, Path::new("/build/files"));
+        let lines = include.split("\r\n").collect::<Vec<_>>();
+        assert_eq!(lines[0], "!define PRODUCT_NAME \"My App\"");
+        assert!(lines.contains(&"!define PRODUCT_EXE \"$INSTDIR\\myapp.exe\""));
+        assert!(lines.contains(&"!define LEGACY_KEY \"Software\\Old\""));
+        assert!(lines.contains(&"  SetOutPath \"$INSTDIR\\lib\\foo\""));
+        assert!(lines.contains(&"  File \"/build/files/lib/foo/bar.py\""));
+        assert!(lines.contains(&"  Delete \"$INSTDIR\\lib\\foo\\bar.py\""));
+        assert!(lines.contains(&"  File \"/build/files/données/файл.txt\""));
+        assert!(lines.contains(&"  Delete \"$INSTDIR\\données\\файл.txt\""));
+
+        let rmdirs = lines
+            .iter()
+            .filter(|l| l.starts_with("  RMDir"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rmdirs,
+            vec![
+                &"  RMDir \"$INSTDIR\\lib\\foo\"",
+                &"  RMDir \"$INSTDIR\\données\"",
+                &"  RMDir \"$INSTDIR\\lib\""
+            ]
+        );
+
+        let script = installer.render_script("$PROGRAMFILES64");
+        assert!(script.contains("InstallDir \"$PROGRAMFILES64\\${PRODUCT_NAME}\"\r\n"));
+        assert!(script.contains("!include \"${PYOXIDIZER_DIR}\\pyoxidizer.nsh\"\r\n"));
+        assert!(script.contains("CreateShortCut"));
+        assert!(script.contains("  SetShellVarContext all\r\n"));
+        assert!(!script.contains("Publisher"));
+
+        let installer = NsisInstaller {
+            per_user: true,
+            ..installer
+        };
+        let script = installer.render_script("$PROGRAMFILES64");
+        assert!(script.contains("InstallDir \"$LOCALAPPDATA\\Programs\\${PRODUCT_NAME}\"\r\n"));
+        assert!(script.contains("InstallDirRegKey HKCU "));
+        assert!(script.contains("RequestExecutionLevel user\r\n"));
+
+        Ok(())
+    }
+}
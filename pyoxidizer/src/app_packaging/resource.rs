@@ -132,6 +132,11 @@ impl FileManifest {
         self.files.iter()
     }
 
+    /// Obtain the content of a file in this manifest.
+    pub fn get(&self, path: &Path) -> Option<&FileContent> {
+        self.files.get(path)
+    }
+
     /// Whether this manifest contains the specified file path.
     pub fn has_path(&self, path: &Path) -> bool {
         self.files.contains_key(path)
@@ -246,4 +251,27 @@ mod tests {
             ]
         )
     }
+
+    #[test]
+    fn test_write_to_non_ascii_path() -> Result<()> {
+        let td = tempdir::TempDir::new("pyoxidizer-test")?;
+        let dest = td.path().join("Usuário 用户").join("Приложение");
+
+        let mut v = FileManifest::default();
+        v.add_file(
+            &PathBuf::from("données/файл.txt"),
+            &FileContent {
+                data: "données".as_bytes().to_vec(),
+                executable: false,
+            },
+        )?;
+        v.write_to_path(&dest)?;
+
+        assert_eq!(
+            std::fs::read(dest.join("données").join("файл.txt"))?,
+            "données".as_bytes().to_vec()
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,225 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Snapcraft projects packaging installed files as snaps.
+
+A project is a directory holding a `snapcraft.yaml` and the files to
+package in `app`. The files are copied into the snap as-is by the `dump`
+plugin, so running `snapcraft` in the directory builds the snap without
+rebuilding anything.
+*/
+
+use {
+    anyhow::{anyhow, Result},
+    std::path::Path,
+};
+
+/// Filename of the project definition.
+pub const SNAPCRAFT_YAML_FILENAME: &str = "snapcraft.yaml";
+
+/// Directory of a project holding the packaged files.
+pub const SNAP_SOURCE_DIR: &str = "app";
+
+/// Values of `confinement` accepted by snapcraft.
+pub const SNAP_CONFINEMENTS: &[&str] = &["strict", "classic", "devmode"];
+
+/// Values of `grade` accepted by snapcraft.
+pub const SNAP_GRADES: &[&str] = &["stable", "devel"];
+
+/// Ensure a string is a valid snap name.
+///
+/// Names are up to 40 lowercase letters, digits, and hyphens, with at least
+/// one letter and no leading, trailing, or consecutive hyphens.
+pub fn validate_snap_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name.len() > 40
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        || !name.chars().any(|c| c.is_ascii_lowercase())
+        || name.starts_with('-')
+        || name.ends_with('-')
+        || name.contains("--")
+    {
+        Err(anyhow!(
+            "invalid snap name {}; names are up to 40 lowercase letters, digits, and hyphens",
+            name
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Quote a string as a YAML scalar.
+///
+/// JSON strings are valid YAML double-quoted scalars.
+fn yaml_string(value: &str) -> String {
+    serde_json::Value::from(value).to_string()
+}
+
+/// A snap running an executable.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapcraft {
+    /// Name of the snap and of its app.
+    pub name: String,
+
+    pub version: String,
+
+    /// Single line summary, up to 79 characters.
+    pub summary: String,
+
+    pub description: String,
+
+    /// Path of the executable run by the app, relative to the snap.
+    pub command: String,
+
+    /// Interfaces the app connects to, e.g. `network`.
+    pub plugs: Vec<String>,
+
+    pub confinement: String,
+
+    pub grade: String,
+
+    /// Base snap providing the runtime environment, e.g. `core20`.
+    pub base: String,
+}
+
+impl Snapcraft {
+    /// Ensure the definition is accepted by snapcraft.
+    pub fn validate(&self) -> Result<()> {
+        validate_snap_name(&self.name)?;
+
+        if self.summary.is_empty()
+            || self.summary.chars().count() > 79
+            || self.summary.contains('\n')
+        {
+            return Err(anyhow!(
+                "snap summary must be a single line of up to 79 characters"
+            ));
+        }
+
+        if !SNAP_CONFINEMENTS.contains(&self.confinement.as_str()) {
+            return Err(anyhow!(
+                "confinement must be one of {}; got {}",
+                SNAP_CONFINEMENTS.join(", "),
+                self.confinement
+            ));
+        }
+
+        if !SNAP_GRADES.contains(&self.grade.as_str()) {
+            return Err(anyhow!(
+                "grade must be one of {}; got {}",
+                SNAP_GRADES.join(", "),
+                self.grade
+            ));
+        }
+
+        if Path::new(&self.command).is_absolute() {
+            return Err(anyhow!(
+                "snap command must be relative to the snap; got {}",
+                self.command
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Obtain the content of `snapcraft.yaml`.
+    pub fn render(&self) -> String {
+        let mut lines = vec![
+            format!("name: {}", self.name),
+            format!("base: {}", self.base),
+            format!("version: {}", yaml_string(&self.version)),
+            format!("summary: {}", yaml_string(&self.summary)),
+            format!("description: {}", yaml_string(&self.description)),
+            format!("grade: {}", self.grade),
+            format!("confinement: {}", self.confinement),
+            "".to_string(),
+            "apps:".to_string(),
+            format!("  {}:", self.name),
+            format!("    command: {}", yaml_string(&self.command)),
+        ];
+
+        if !self.plugs.is_empty() {
+            lines.push("    plugs:".to_string());
+            for plug in &self.plugs {
+                lines.push(format!("      - {}", yaml_string(plug)));
+            }
+        }
+
+        lines.extend(vec![
+            "".to_string(),
+            "parts:".to_string(),
+            format!("  {}:", self.name),
+            "    plugin: dump".to_string(),
+            format!("    source: {}", SNAP_SOURCE_DIR),
+            "".to_string(),
+        ]);
+
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_snap_name() {
+        assert!(validate_snap_name("my-app2").is_ok());
+        assert!(validate_snap_name("MyApp").is_err());
+        assert!(validate_snap_name("my--app").is_err());
+        assert!(validate_snap_name("-app").is_err());
+        assert!(validate_snap_name("123").is_err());
+        assert!(validate_snap_name(&"a".repeat(41)).is_err());
+    }
+
+    #[test]
+    fn test_render() {
+        let snap = Snapcraft {
+            name: "myapp".to_string(),
+            version: "1.0".to_string(),
+            summary: "Does things".to_string(),
+            description: "Does things: well.".to_string(),
+            command: "bin/myapp".to_string(),
+            plugs: vec!["network".to_string(), "home".to_string()],
+            confinement: "strict".to_string(),
+            grade: "stable".to_string(),
+            base: "core20".to_string(),
+        };
+        assert!(snap.validate().is_ok());
+
+        assert_eq!(
+            snap.render(),
+            "name: myapp\n\
+             base: core20\n\
+             version: \"1.0\"\n\
+             summary: \"Does things\"\n\
+             description: \"Does things: well.\"\n\
+             grade: stable\n\
+             confinement: strict\n\
+             \n\
+             apps:\n  \
+               myapp:\n    \
+                 command: \"bin/myapp\"\n    \
+                 plugs:\n      \
+                   - \"network\"\n      \
+                   - \"home\"\n\
+             \n\
+             parts:\n  \
+               myapp:\n    \
+                 plugin: dump\n    \
+                 source: app\n"
+        );
+
+        let mut invalid = snap.clone();
+        invalid.confinement = "loose".to_string();
+        assert!(invalid.validate().is_err());
+
+        let mut invalid = snap;
+        invalid.command = "/bin/myapp".to_string();
+        assert!(invalid.validate().is_err());
+    }
+}
@@ -0,0 +1,554 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Inspection of built artifacts.
+
+Executables embed their Python resources as packed resources data, or load
+them from a resources file installed next to them. This module locates that
+data in built artifacts, compares the resources of artifacts, e.g. to
+review what a dependency upgrade actually changed, and extracts resources
+back to files for auditing.
+*/
+
+use {
+    crate::app_packaging::resource::{FileContent, FileManifest},
+    crate::size_budget::format_size,
+    anyhow::{anyhow, Context, Result},
+    python_packed_resources::data::{Resource, HEADER_V1, HEADER_V2},
+    python_packed_resources::parser::load_resources,
+    std::collections::BTreeMap,
+    std::path::{Path, PathBuf},
+};
+
+/// Whether data is valid packed resources data.
+fn is_resources_data(data: &[u8]) -> bool {
+    match load_resources(data) {
+        Ok(mut resources) => resources.all(|r| r.is_ok()),
+        Err(_) => false,
+    }
+}
+
+/// Find packed resources data in a file.
+///
+/// The file is either packed resources data or a binary embedding it. As
+/// binaries also contain the headers of resources data as constants,
+/// occurrences of headers are tried until one starts valid resources data.
+pub fn find_resources_data(data: &[u8]) -> Option<&[u8]> {
+    let header_prefix = &HEADER_V1[0..HEADER_V1.len() - 1];
+
+    let mut offset = 0;
+    while let Some(pos) = data[offset..]
+        .windows(header_prefix.len())
+        .position(|w| w == header_prefix)
+    {
+        let candidate = &data[offset + pos..];
+
+        if (candidate.starts_with(HEADER_V1) || candidate.starts_with(HEADER_V2))
+            && is_resources_data(candidate)
+        {
+            return Some(candidate);
+        }
+
+        offset += pos + 1;
+    }
+
+    None
+}
+
+/// Read the packed resources data of an artifact.
+pub fn read_resources_data(path: &Path) -> Result<Vec<u8>> {
+    let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+
+    let resources = find_resources_data(&data).ok_or_else(|| {
+        anyhow!(
+            "no Python resources found in {}; if the executable loads resources from a file, pass that file instead",
+            path.display()
+        )
+    })?;
+
+    Ok(resources.to_vec())
+}
+
+/// Total size of the in-memory data of a resource.
+pub fn resource_size(resource: &Resource<u8>) -> u64 {
+    let mut size = 0;
+
+    for data in [
+        &resource.in_memory_source,
+        &resource.in_memory_bytecode,
+        &resource.in_memory_bytecode_opt1,
+        &resource.in_memory_bytecode_opt2,
+        &resource.in_memory_extension_module_shared_library,
+        &resource.in_memory_shared_library,
+    ]
+    .iter()
+    {
+        if let Some(data) = data {
+            size += data.len() as u64;
+        }
+    }
+
+    if let Some(resources) = &resource.in_memory_resources {
+        size += resources.values().map(|d| d.len() as u64).sum::<u64>();
+    }
+
+    if let Some(metadata) = &resource.in_memory_package_distribution {
+        size += metadata.values().map(|d| d.len() as u64).sum::<u64>();
+    }
+
+    size
+}
+
+/// Obtain the name and version of the package distribution described by metadata.
+///
+/// `data` is the content of a `METADATA` or `PKG-INFO` file.
+pub fn distribution_name_version(data: &[u8]) -> Option<(String, String)> {
+    let data = String::from_utf8_lossy(data);
+    let mut name = None;
+    let mut version = None;
+
+    // Headers end at the first empty line.
+    for line in data.lines().take_while(|l| !l.is_empty()) {
+        if line.starts_with("Name: ") {
+            name = Some(line["Name: ".len()..].trim().to_string());
+        } else if line.starts_with("Version: ") {
+            version = Some(line["Version: ".len()..].trim().to_string());
+        }
+    }
+
+    match (name, version) {
+        (Some(name), Some(version)) => Some((name, version)),
+        _ => None,
+    }
+}
+
+/// Summary of the Python resources in an artifact.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ResourcesSummary {
+    /// Size and content digest of each resource, by name.
+    pub resources: BTreeMap<String, (u64, Vec<u8>)>,
+
+    /// Version of each package distribution with metadata, by name.
+    pub distributions: BTreeMap<String, String>,
+}
+
+impl ResourcesSummary {
+    /// Summarize packed resources data.
+    pub fn from_resources_data(data: &[u8]) -> Result<Self> {
+        let mut res = ResourcesSummary::default();
+
+        for resource in load_resources(data).map_err(|e| anyhow!(e))? {
+            let resource = resource.map_err(|e| anyhow!(e))?;
+
+            let digest = match &resource.content_digest {
+                Some(digest) => digest.to_vec(),
+                None => resource.compute_content_digest(),
+            };
+
+            if let Some(metadata) = &resource.in_memory_package_distribution {
+                for (filename, data) in metadata.iter() {
+                    if filename == "METADATA" || filename == "PKG-INFO" {
+                        if let Some((name, version)) = distribution_name_version(data) {
+                            res.distributions.insert(name, version);
+                        }
+                    }
+                }
+            }
+
+            res.resources.insert(
+                resource.name.to_string(),
+                (resource_size(&resource), digest),
+            );
+        }
+
+        Ok(res)
+    }
+
+    /// Summarize the resources of an artifact.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        Self::from_resources_data(&read_resources_data(path)?)
+    }
+
+    /// Obtain the size of resources of each top-level package.
+    pub fn package_sizes(&self) -> BTreeMap<String, u64> {
+        let mut res = BTreeMap::new();
+
+        for (name, (size, _)) in &self.resources {
+            let package = name.split('.').next().unwrap_or(name).to_string();
+            *res.entry(package).or_insert(0) += *size;
+        }
+
+        res
+    }
+}
+
+/// Format a size difference for humans.
+fn format_size_delta(old: u64, new: u64) -> String {
+    if new >= old {
+        format!("+{}", format_size(new - old))
+    } else {
+        format!("-{}", format_size(old - new))
+    }
+}
+
+/// Describe the differences between the resources of two artifacts.
+pub fn diff_resources(old: &ResourcesSummary, new: &ResourcesSummary) -> String {
+    let mut lines = Vec::new();
+
+    let mut distributions = Vec::new();
+    for (name, version) in &old.distributions {
+        match new.distributions.get(name) {
+            Some(new_version) if new_version != version => {
+                distributions.push(format!("  ~ {} {} -> {}", name, version, new_version))
+            }
+            Some(_) => {}
+            None => distributions.push(format!("  - {} {}", name, version)),
+        }
+    }
+    for (name, version) in &new.distributions {
+        if !old.distributions.contains_key(name) {
+            distributions.push(format!("  + {} {}", name, version));
+        }
+    }
+
+    if !distributions.is_empty() {
+        lines.push("Package distributions:".to_string());
+        lines.extend(distributions);
+        lines.push(String::new());
+    }
+
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut changed = Vec::new();
+
+    for (name, (size, digest)) in &old.resources {
+        match new.resources.get(name) {
+            Some((new_size, new_digest)) if new_digest != digest => changed.push(format!(
+                "  ~ {} ({})",
+                name,
+                format_size_delta(*size, *new_size)
+            )),
+            Some(_) => {}
+            None => removed.push(format!("  - {} ({})", name, format_size(*size))),
+        }
+    }
+    for (name, (size, _)) in &new.resources {
+        if !old.resources.contains_key(name) {
+            added.push(format!("  + {} ({})", name, format_size(*size)));
+        }
+    }
+
+    lines.push(format!(
+        "Resources: {} added, {} removed, {} changed",
+        added.len(),
+        removed.len(),
+        changed.len()
+    ));
+    lines.extend(added);
+    lines.extend(removed);
+    lines.extend(changed);
+
+    let old_sizes = old.package_sizes();
+    let new_sizes = new.package_sizes();
+    let mut packages: Vec<&String> = old_sizes.keys().chain(new_sizes.keys()).collect();
+    packages.sort();
+    packages.dedup();
+
+    let mut sizes = Vec::new();
+    for package in packages {
+        let old_size = old_sizes.get(package).cloned().unwrap_or(0);
+        let new_size = new_sizes.get(package).cloned().unwrap_or(0);
+
+        if old_size != new_size {
+            sizes.push(format!(
+                "  {}: {} -> {} ({})",
+                package,
+                format_size(old_size),
+                format_size(new_size),
+                format_size_delta(old_size, new_size)
+            ));
+        }
+    }
+
+    if !sizes.is_empty() {
+        lines.push(String::new());
+        lines.push("Package sizes:".to_string());
+        lines.extend(sizes);
+    }
+
+    lines.join("\n")
+}
+
+/// Compare the resources of two built artifacts and print the differences.
+pub fn diff_artifacts(old_path: &Path, new_path: &Path) -> Result<()> {
+    let old = ResourcesSummary::from_path(old_path)?;
+    let new = ResourcesSummary::from_path(new_path)?;
+
+    println!("{}", diff_resources(&old, &new));
+
+    Ok(())
+}
+
+/// Filename suffix of a shared library, derived from its content.
+fn shared_library_suffix(data: &[u8]) -> &'static str {
+    if data.starts_with(b"MZ") {
+        ".pyd"
+    } else {
+        ".so"
+    }
+}
+
+/// Convert resources to the files they were derived from.
+///
+/// Module source is written to `.py` files and package data and
+/// distribution metadata to their original paths. Bytecode is written to
+/// `.bytecode` files next to sources, as marshalled code objects without
+/// the header of `.pyc` files. Extension modules and shared libraries are
+/// written to `.so` or `.pyd` files.
+///
+/// Returns the files and the names of encrypted modules, whose source and
+/// bytecode can't be extracted.
+pub fn resources_to_files(data: &[u8]) -> Result<(FileManifest, Vec<String>)> {
+    let mut manifest = FileManifest::default();
+    let mut encrypted = Vec::new();
+
+    let mut add = |path: PathBuf, data: &[u8]| {
+        manifest.add_file(
+            &path,
+            &FileContent {
+                data: data.to_vec(),
+                executable: false,
+            },
+        )
+    };
+
+    for resource in load_resources(data).map_err(|e| anyhow!(e))? {
+        let resource = resource.map_err(|e| anyhow!(e))?;
+
+        let module_path = if resource.is_package {
+            resource
+                .name
+                .split('.')
+                .collect::<PathBuf>()
+                .join("__init__")
+        } else {
+            resource.name.split('.').collect::<PathBuf>()
+        };
+        let package_path = resource.name.split('.').collect::<PathBuf>();
+
+        if resource.is_encrypted {
+            encrypted.push(resource.name.to_string());
+        } else {
+            if let Some(source) = &resource.in_memory_source {
+                add(module_path.with_extension("py"), source)?;
+            }
+
+            for (suffix, bytecode) in &[
+                ("bytecode", &resource.in_memory_bytecode),
+                ("opt-1.bytecode", &resource.in_memory_bytecode_opt1),
+                ("opt-2.bytecode", &resource.in_memory_bytecode_opt2),
+            ] {
+                if let Some(bytecode) = bytecode {
+                    add(module_path.with_extension(suffix), bytecode)?;
+                }
+            }
+        }
+
+        if let Some(library) = &resource.in_memory_extension_module_shared_library {
+            let path = format!(
+                "{}{}",
+                module_path.display(),
+                shared_library_suffix(library)
+            );
+            add(PathBuf::from(path), library)?;
+        }
+
+        if let Some(resources) = &resource.in_memory_resources {
+            for (name, data) in resources.iter() {
+                add(package_path.join(name.as_ref()), data)?;
+            }
+        }
+
+        if let Some(metadata) = &resource.in_memory_package_distribution {
+            let dist_info = metadata
+                .get("METADATA")
+                .and_then(|data| distribution_name_version(data))
+                .map(|(name, version)| format!("{}-{}.dist-info", name, version))
+                .unwrap_or_else(|| format!("{}.dist-info", resource.name));
+
+            for (name, data) in metadata.iter() {
+                add(Path::new(&dist_info).join(name.as_ref()), data)?;
+            }
+        }
+
+        if let Some(library) = &resource.in_memory_shared_library {
+            add(PathBuf::from(resource.name.as_ref()), library)?;
+        }
+    }
+
+    Ok((manifest, encrypted))
+}
+
+/// Write the Python resources of a built artifact to a directory.
+pub fn extract_artifact(path: &Path, dest_path: &Path) -> Result<()> {
+    let (manifest, encrypted) = resources_to_files(&read_resources_data(path)?)?;
+
+    manifest
+        .write_to_path(dest_path)
+        .with_context(|| format!("writing {}", dest_path.display()))?;
+
+    println!(
+        "extracted {} files to {}",
+        manifest.entries().count(),
+        dest_path.display()
+    );
+
+    if !encrypted.is_empty() {
+        println!(
+            "source and bytecode of encrypted modules were not extracted: {}",
+            encrypted.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*, python_packed_resources::writer::write_embedded_resources_v2, std::borrow::Cow,
+        std::collections::HashMap,
+    };
+
+    fn resources_data(resources: &[Resource<u8>]) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_embedded_resources_v2(resources, &mut data, None).unwrap();
+        data
+    }
+
+    fn module(name: &str, source: &'static [u8]) -> Resource<'static, u8> {
+        Resource {
+            name: Cow::Owned(name.to_string()),
+            in_memory_source: Some(Cow::Borrowed(source)),
+            ..Resource::default()
+        }
+    }
+
+    #[test]
+    fn test_find_resources_data() {
+        let resources = resources_data(&[module("foo", b"print('hello')")]);
+
+        // Binaries contain headers as constants before the actual data.
+        let mut binary = b"\x7fELF".to_vec();
+        binary.extend_from_slice(HEADER_V2);
+        binary.extend_from_slice(b"garbage");
+        binary.extend_from_slice(&resources);
+        binary.extend_from_slice(b"trailer");
+
+        let found = find_resources_data(&binary).unwrap();
+        assert!(found.starts_with(&resources));
+        assert_eq!(find_resources_data(&resources), Some(resources.as_slice()));
+        assert!(find_resources_data(b"\x7fELF").is_none());
+    }
+
+    #[test]
+    fn test_distribution_name_version() {
+        assert_eq!(
+            distribution_name_version(
+                b"Metadata-Version: 2.1\nName: six\nVersion: 1.14.0\n\nVersion: 2\n"
+            ),
+            Some(("six".to_string(), "1.14.0".to_string()))
+        );
+        assert_eq!(distribution_name_version(b"Name: six\n"), None);
+    }
+
+    #[test]
+    fn test_diff_resources() -> Result<()> {
+        let mut metadata = HashMap::new();
+        metadata.insert(
+            Cow::Borrowed("METADATA"),
+            Cow::Borrowed(&b"Name: six\nVersion: 1.14.0\n"[..]),
+        );
+        let mut six = module("six", b"import sys");
+        six.in_memory_package_distribution = Some(metadata);
+
+        let old = ResourcesSummary::from_resources_data(&resources_data(&[
+            module("foo", b"print('hello')"),
+            module("foo.bar", b"pass"),
+            six.clone(),
+        ]))?;
+
+        let mut metadata = HashMap::new();
+        metadata.insert(
+            Cow::Borrowed("METADATA"),
+            Cow::Borrowed(&b"Name: six\nVersion: 1.15.0\n"[..]),
+        );
+        six.in_memory_package_distribution = Some(metadata);
+
+        let new = ResourcesSummary::from_resources_data(&resources_data(&[
+            module("foo", b"print('hello world')"),
+            module("baz", b"pass"),
+            six,
+        ]))?;
+
+        let diff = diff_resources(&old, &new);
+        assert!(diff.contains("  ~ six 1.14.0 -> 1.15.0\n"));
+        assert!(diff.contains("Resources: 1 added, 1 removed, 2 changed\n"));
+        assert!(diff.contains("  + baz (4 B)\n"));
+        assert!(diff.contains("  - foo.bar (4 B)\n"));
+        assert!(diff.contains("  ~ foo (+6 B)\n"));
+        assert!(diff.contains("  foo: 18 B -> 20 B (+2 B)"));
+
+        assert_eq!(
+            diff_resources(&old, &old),
+            "Resources: 0 added, 0 removed, 0 changed"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resources_to_files() -> Result<()> {
+        let mut package = module("foo", b"# package");
+        package.is_package = true;
+        package.in_memory_bytecode = Some(Cow::Borrowed(&b"code"[..]));
+        let mut resources = HashMap::new();
+        resources.insert(Cow::Borrowed("data/config.json"), Cow::Borrowed(&b"{}"[..]));
+        package.in_memory_resources = Some(std::sync::Arc::new(Box::new(resources)));
+        let mut metadata = HashMap::new();
+        metadata.insert(
+            Cow::Borrowed("METADATA"),
+            Cow::Borrowed(&b"Name: foo\nVersion: 1.0\n"[..]),
+        );
+        package.in_memory_package_distribution = Some(metadata);
+
+        let mut secret = module("foo.secret", b"encrypted");
+        secret.is_encrypted = true;
+
+        let (manifest, encrypted) = resources_to_files(&resources_data(&[
+            package,
+            module("foo.bar", b"pass"),
+            secret,
+        ]))?;
+
+        let paths = manifest
+            .entries()
+            .map(|(p, _)| p.display().to_string().replace('\\', "/"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                "foo/__init__.bytecode",
+                "foo/__init__.py",
+                "foo/bar.py",
+                "foo/data/config.json",
+                "foo-1.0.dist-info/METADATA",
+            ]
+        );
+        assert_eq!(encrypted, vec!["foo.secret"]);
+
+        Ok(())
+    }
+}
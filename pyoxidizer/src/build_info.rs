@@ -0,0 +1,216 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Build information embedded in executables.
+
+`PythonExecutable.set_build_info()` embeds a generated Python module, named
+`__pyoxidizer__` by default, describing how the executable was built. Packaged
+applications can import it to report their exact provenance, e.g. in
+`--version` output.
+
+The build time honors `SOURCE_DATE_EPOCH`, so builds stay reproducible.
+*/
+
+use {
+    crate::environment::PYOXIDIZER_VERSION,
+    crate::py_packaging::binary::PythonBinaryBuilder,
+    crate::py_packaging::bytecode::PycInvalidationMode,
+    crate::py_packaging::resource::{
+        BytecodeModule, BytecodeOptimizationLevel, DataLocation, SourceModule,
+    },
+    crate::reproducible::format_timestamp,
+    crate::starlark::build_manifest::ResourceCounts,
+    std::collections::BTreeMap,
+    std::path::Path,
+};
+
+/// Default name of the build information module.
+pub const DEFAULT_BUILD_INFO_MODULE: &str = "__pyoxidizer__";
+
+/// Whether a value is a valid dotted Python module name.
+pub fn is_valid_module_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.split('.').all(|part| {
+            !part.is_empty()
+                && !part.starts_with(|c: char| c.is_ascii_digit())
+                && part.chars().all(|c| c.is_alphanumeric() || c == '_')
+        })
+}
+
+/// Obtain the commit checked out in the git repository containing a path.
+///
+/// Returns `None` if the path isn't in a git repository or `git` isn't
+/// available.
+pub fn git_commit(path: &Path) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .arg("rev-parse")
+        .arg("HEAD")
+        .current_dir(path)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let commit = String::from_utf8(output.stdout).ok()?.trim().to_string();
+
+    if commit.is_empty() {
+        None
+    } else {
+        Some(commit)
+    }
+}
+
+/// Render a string as a Python string literal.
+pub fn python_str(value: &str) -> String {
+    // JSON string literals are valid Python string literals.
+    serde_json::to_string(value).unwrap()
+}
+
+/// Describes the build information module of an executable.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuildInfo {
+    /// Name of the Python module holding build information.
+    pub module_name: String,
+
+    /// Commit of the git repository the executable was built from.
+    pub git_commit: Option<String>,
+
+    /// Rust target triple the executable is built for.
+    pub target_triple: String,
+
+    /// Arbitrary values defined by the configuration file.
+    pub variables: BTreeMap<String, String>,
+}
+
+impl BuildInfo {
+    /// Render the source code of the build information module.
+    pub fn module_source(
+        &self,
+        build_time: u64,
+        python_version: Option<&str>,
+        counts: &ResourceCounts,
+    ) -> String {
+        let optional_str = |value: Option<&str>| match value {
+            Some(value) => python_str(value),
+            None => "None".to_string(),
+        };
+
+        let variables = self
+            .variables
+            .iter()
+            .map(|(k, v)| format!("    {}: {},\n", python_str(k), python_str(v)))
+            .collect::<Vec<_>>()
+            .join("");
+
+        format!(
+            "\"\"\"Build information of this application, generated by PyOxidizer.\"\"\"\n\
+             \n\
+             build_time = {}\n\
+             build_timestamp = {}\n\
+             git_commit = {}\n\
+             target_triple = {}\n\
+             python_version = {}\n\
+             pyoxidizer_version = {}\n\
+             \n\
+             variables = {{\n{}}}\n\
+             \n\
+             resource_counts = {{\n    \
+             \"source_modules\": {},\n    \
+             \"bytecode_modules\": {},\n    \
+             \"resources\": {},\n    \
+             \"extension_modules\": {},\n\
+             }}\n",
+            python_str(&format_timestamp(build_time)),
+            build_time,
+            optional_str(self.git_commit.as_ref().map(|s| s.as_str())),
+            python_str(&self.target_triple),
+            optional_str(python_version),
+            python_str(PYOXIDIZER_VERSION),
+            variables,
+            counts.source_modules,
+            counts.bytecode_modules,
+            counts.resources,
+            counts.extension_modules,
+        )
+    }
+
+    /// Add the build information module to an executable.
+    ///
+    /// Resource counts describe the executable before the module is added.
+    pub fn add_to_binary(&self, exe: &mut dyn PythonBinaryBuilder, build_time: u64) {
+        let counts = ResourceCounts::from_binary_builder(exe);
+        let python_version = exe.python_version();
+        let source = self
+            .module_source(
+                build_time,
+                python_version.as_ref().map(|s| s.as_str()),
+                &counts,
+            )
+            .into_bytes();
+
+        exe.add_source_module(&SourceModule {
+            name: self.module_name.clone(),
+            source: DataLocation::Memory(source.clone()),
+            is_package: false,
+        });
+        exe.add_bytecode_module(&BytecodeModule {
+            name: self.module_name.clone(),
+            source: DataLocation::Memory(source),
+            optimize_level: BytecodeOptimizationLevel::Zero,
+            is_package: false,
+            source_stripped: false,
+            invalidation_mode: PycInvalidationMode::default(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_module_name() {
+        assert!(is_valid_module_name("__pyoxidizer__"));
+        assert!(is_valid_module_name("myapp._build"));
+        assert!(!is_valid_module_name(""));
+        assert!(!is_valid_module_name("myapp."));
+        assert!(!is_valid_module_name("1info"));
+        assert!(!is_valid_module_name("my-app"));
+    }
+
+    #[test]
+    fn test_module_source() {
+        let mut variables = BTreeMap::new();
+        variables.insert("channel".to_string(), "stable \"beta\"".to_string());
+
+        let info = BuildInfo {
+            module_name: DEFAULT_BUILD_INFO_MODULE.to_string(),
+            git_commit: None,
+            target_triple: "x86_64-unknown-linux-gnu".to_string(),
+            variables,
+        };
+
+        let source = info.module_source(
+            1_589_500_800,
+            Some("3.7.7"),
+            &ResourceCounts {
+                source_modules: 1,
+                bytecode_modules: 2,
+                resources: 3,
+                extension_modules: 4,
+            },
+        );
+
+        assert!(source.contains("build_time = \"2020-05-15T00:00:00Z\"\n"));
+        assert!(source.contains("build_timestamp = 1589500800\n"));
+        assert!(source.contains("git_commit = None\n"));
+        assert!(source.contains("target_triple = \"x86_64-unknown-linux-gnu\"\n"));
+        assert!(source.contains("python_version = \"3.7.7\"\n"));
+        assert!(source.contains("    \"channel\": \"stable \\\"beta\\\"\",\n"));
+        assert!(source.contains("    \"extension_modules\": 4,\n"));
+    }
+}
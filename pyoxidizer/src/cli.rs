@@ -4,11 +4,20 @@
 
 use {
     super::analyze,
+    super::artifact,
+    super::doctor,
     super::environment::BUILD_SEMVER_LIGHTWEIGHT,
     super::logging,
+    super::output_layout,
+    super::progress,
     super::project_building,
     super::project_layout,
     super::projectmgmt,
+    super::py_packaging::distribution_signature,
+    super::rust_toolchain,
+    super::scratch,
+    super::size_budget,
+    super::update,
     anyhow::{anyhow, Result},
     clap::{App, AppSettings, Arg, SubCommand},
     std::path::{Path, PathBuf},
@@ -40,6 +49,171 @@ existing PyOxidizer enabled project.
 
 This command will invoke Rust's build system tool (Cargo) to build
 the project.
+
+TARGET arguments name the targets to build. If none are given, the default
+target is built. --all builds every target registered by the config file.
+
+--jobs sets how many targets are built concurrently. Each concurrently
+built target evaluates the config file separately, resolving its
+dependencies again. Python distributions are cached on disk and shared
+between them.
+
+--output-layout sets the directory targets are written to, overriding
+set_output_layout() in the config file. It is a path template containing
+{target} and optionally {build_path}, {target_triple}, and {build_mode}.
+Relative paths are resolved from the directory containing the config
+file. It defaults to {build_path}/{target_triple}/{build_mode}/{target}.
+
+--artifact-dir copies the files at the root of the output directories of
+built targets, e.g. executables and installers, and the build manifest to
+a flat directory, e.g. for uploading them from CI.
+";
+
+const BUILD_APP_ABOUT: &str = "\
+Build an application from a Python package without a config file.
+
+The PACKAGE argument is a requirement specifier passed to `pip install`.
+
+The --entry argument defines what the built executable runs. It is either
+a module name, which is run as __main__, or a `module:callable` string,
+naming a function to call.
+
+This command synthesizes a default PyOxidizer configuration in memory and
+builds it. Built files are written to a `build` directory under --path.
+Pass --emit-config to print the synthesized configuration instead of
+building it. The printed configuration can be saved to a `pyoxidizer.bzl`
+file and customized.
+";
+
+const VERIFY_REPRODUCIBLE_ABOUT: &str = "\
+Verify that building a PyOxidizer project is reproducible.
+
+The PATH argument is a filesystem path to a directory containing an
+existing PyOxidizer enabled project.
+
+This command builds the requested targets (or the default target) twice
+and compares the SHA-256 of every file they produce. Files that differ
+between builds are printed and the command fails if there are any.
+
+If the SOURCE_DATE_EPOCH environment variable isn't set, it is set to
+the current time so both builds record the same timestamps.
+";
+
+const CHECK_ABOUT: &str = "\
+Validate a PyOxidizer configuration file.
+
+The PATH argument is a filesystem path to a directory containing an
+existing PyOxidizer enabled project.
+
+This command evaluates the configuration file and resolves targets in
+dry run mode: target functions are called, but nothing is compiled or
+linked. A report of every target, including what it resolves to, its
+entry point, and the Python resources it contains, is printed.
+
+By default, all targets are resolved. Pass target names to only
+resolve specific targets. Python distributions referenced by the
+configuration file are fetched, if not already cached.
+
+Errors are reported with the location of the offending code and, where
+possible, hints for fixing them.
+";
+
+const CLEAN_ABOUT: &str = "\
+Remove build state of a PyOxidizer project.
+
+Build outputs in the project's build path and scratch directories left
+behind by interrupted builds are removed. Downloaded Python distributions,
+Rust toolchains, wheels built from source distributions, and pulled container
+image layers are kept unless --all is given. Distribution caches shared with other projects are never
+removed.
+
+Scratch directories are created in the system's temporary directory, or
+in the directory given by --scratch-path, the PYOXIDIZER_SCRATCH_PATH
+environment variable, or set_scratch_path() in the config file. Don't run
+this command while builds using the same scratch path are running.
+";
+
+const DIFF_ABOUT: &str = "\
+Compare the Python resources of two built executables.
+
+Each argument is a path to an executable embedding its Python resources or
+to a packed resources file installed next to an executable.
+
+Added, removed, and changed resources are listed along with size changes
+of top-level packages and version changes of package distributions whose
+metadata is embedded. This is useful for reviewing what a dependency
+upgrade actually changed in a shipped application.
+";
+
+const EXTRACT_ABOUT: &str = "\
+Write the Python resources of a built executable to a directory.
+
+The PATH argument is a path to an executable embedding its Python resources
+or to a packed resources file installed next to an executable.
+
+Module source is written to .py files and package data and distribution
+metadata to their original paths, so the shipped content can be audited.
+Bytecode is written to .bytecode files holding marshalled code objects,
+which lack the header of .pyc files.
+";
+
+const MIGRATE_CONFIG_ABOUT: &str = "\
+Update a configuration file to the current configuration API.
+
+Functions, methods, and arguments renamed since the configuration API
+version the config file declares with set_config_version() are rewritten
+to their current names, and the declared version is updated. Config files
+not declaring a version are assumed to target version 1.
+
+The config file is found as by other commands. With --dry-run, the changes
+are printed but the config file isn't modified.
+";
+
+const DOCTOR_ABOUT: &str = "\
+Check the environment for problems building executables.
+
+This command checks for the Rust toolchain, a C compiler, and the linker
+and SDK needed to build executables for the target triple. It also checks
+the hosts Python distributions and Rust are downloaded from can be reached.
+
+Each problem found is printed along with how to fix it. The command exits
+with an error if any check fails.
+";
+
+const VENDOR_ABOUT: &str = "\
+Download everything needed to build a project without network access.
+
+The PATH argument is a filesystem path to a directory containing an
+existing PyOxidizer enabled project.
+
+This command resolves targets in dry run mode, downloading the Python
+distributions and pip packages they use to the DEST directory. The Rust
+crates needed to build executables are then fetched into DEST as well.
+
+If DEST isn't given, the directory passed to set_offline() in the
+configuration file is used. Calling set_offline() makes builds use the
+vendored artifacts and fail instead of accessing the network.
+";
+
+const GENERATE_UPDATE_KEY_ABOUT: &str = "\
+Generate a key for signing update manifests.
+
+The secret key is written to PATH, which must not exist. Keep it private:
+whoever has it can publish updates. The public key is printed. Pass it as
+the update_public_key argument of PythonInterpreterConfig() so built
+executables accept manifests signed with this key.
+";
+
+const INIT_CONFIG_FILE_ABOUT: &str = "\
+Create a new PyOxidizer configuration file.
+
+The PATH argument is the directory to create the pyoxidizer.bzl file in.
+The name of the directory is used as the name of the built executable.
+
+--template selects a configuration preconfigured for a class of
+application. Templates other than `default` run the package named after
+the directory, with `-` replaced by `_`, and embed the application from
+the directory. The available templates are:
 ";
 
 const INIT_RUST_PROJECT_ABOUT: &str = "\
@@ -61,6 +235,29 @@ they were created with.
 On success, instructions on potential next steps are printed.
 ";
 
+const RELEASE_ABOUT: &str = "\
+Build release targets and publish their artifacts.
+
+The PATH argument is a filesystem path to a directory containing an
+existing PyOxidizer enabled project.
+
+Release targets are registered with register_release_target() in the
+config file. For each release target, the targets it names are built in
+release mode and their deliverables are copied to the release target's
+output directory, or to a directory named after it in DEST. A SHA256SUMS
+file and a RELEASE_NOTES.md fragment listing the artifacts, the version of
+the application, and the version of Python it embeds are written next to
+them.
+
+With --upload, the artifacts are uploaded to the GitHub or GitLab release
+of the release target's tag, using the API token held by the environment
+variable named by the release target. GitLab releases require the tag to
+exist in the repository.
+
+TARGET arguments name the release targets to release. If none are given,
+every release target is released.
+";
+
 const RUN_BUILD_SCRIPT_ABOUT: &str = "\
 Runs a crate build script to generate Python artifacts.
 
@@ -73,6 +270,20 @@ emits special lines that tell the Rust build system how to consume them.
 ";
 
 pub fn run_cli() -> Result<()> {
+    let config_templates = project_layout::CONFIG_TEMPLATES
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>();
+    let init_config_file_about = format!(
+        "{}\n{}\n",
+        INIT_CONFIG_FILE_ABOUT,
+        project_layout::CONFIG_TEMPLATES
+            .iter()
+            .map(|(name, description)| format!("  {:<10}{}", name, description))
+            .collect::<Vec<_>>()
+            .join("\n")
+    );
+
     let matches = App::new("PyOxidizer")
         .setting(AppSettings::ArgRequiredElseHelp)
         .version(BUILD_SEMVER_LIGHTWEIGHT)
@@ -81,7 +292,48 @@ pub fn run_cli() -> Result<()> {
         .arg(
             Arg::with_name("verbose")
                 .long("verbose")
-                .help("Enable verbose output"),
+                .short("v")
+                .multiple(true)
+                .help("Enable verbose output (repeat for more)"),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .long("quiet")
+                .short("q")
+                .conflicts_with("verbose")
+                .help("Only print errors"),
+        )
+        .arg(
+            Arg::with_name("system_rust")
+                .long("system-rust")
+                .help("Build with the Rust toolchain in PATH instead of downloading one"),
+        )
+        .arg(
+            Arg::with_name("locked")
+                .long("locked")
+                .help("Refuse Python distributions not verified with a pinned signing key"),
+        )
+        .arg(
+            Arg::with_name("scratch_path")
+                .long("scratch-path")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Directory to create temporary build directories in"),
+        )
+        .arg(
+            Arg::with_name("scratch_max_size")
+                .long("scratch-max-size")
+                .takes_value(true)
+                .value_name("SIZE")
+                .help("Remove the oldest leftover temporary build directories beyond this size"),
+        )
+        .arg(
+            Arg::with_name("log_format")
+                .long("log-format")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .help("Format of emitted log messages"),
         )
         .subcommand(
             SubCommand::with_name("add")
@@ -118,10 +370,158 @@ pub fn run_cli() -> Result<()> {
                         .help("The config file target to resolve"),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Validate a PyOxidizer configuration file")
+                .long_about(CHECK_ABOUT)
+                .arg(
+                    Arg::with_name("target_triple")
+                        .long("target-triple")
+                        .takes_value(true)
+                        .help("Rust target triple to evaluate for"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .takes_value(true)
+                        .default_value(".")
+                        .value_name("PATH")
+                        .help("Directory containing project to check"),
+                )
+                .arg(
+                    Arg::with_name("targets")
+                        .value_name("TARGET")
+                        .multiple(true)
+                        .help("Target to resolve"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("clean")
+                .about("Remove build outputs and temporary build state")
+                .long_about(CLEAN_ABOUT)
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .help("Also remove downloaded Python distributions, Rust toolchains, built wheels, and image layers"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .takes_value(true)
+                        .default_value(".")
+                        .value_name("PATH")
+                        .help("Directory containing project to clean"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .about("Compare the Python resources of two built executables")
+                .long_about(DIFF_ABOUT)
+                .arg(
+                    Arg::with_name("old")
+                        .required(true)
+                        .value_name("OLD")
+                        .help("Path to the executable or resources file to compare against"),
+                )
+                .arg(
+                    Arg::with_name("new")
+                        .required(true)
+                        .value_name("NEW")
+                        .help("Path to the executable or resources file to compare"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("extract")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .about("Write the Python resources of a built executable to a directory")
+                .long_about(EXTRACT_ABOUT)
+                .arg(
+                    Arg::with_name("path")
+                        .required(true)
+                        .value_name("PATH")
+                        .help("Path to the executable or resources file to extract"),
+                )
+                .arg(
+                    Arg::with_name("dest_path")
+                        .required(true)
+                        .value_name("DEST_PATH")
+                        .help("Directory to write resources to"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("doctor")
+                .about("Check the environment for problems building executables")
+                .long_about(DOCTOR_ABOUT)
+                .arg(
+                    Arg::with_name("target_triple")
+                        .long("target-triple")
+                        .takes_value(true)
+                        .help("Rust target triple to check building for"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("vendor")
+                .about("Download everything needed to build a project offline")
+                .long_about(VENDOR_ABOUT)
+                .arg(
+                    Arg::with_name("target_triple")
+                        .long("target-triple")
+                        .takes_value(true)
+                        .help("Rust target triple to vendor for"),
+                )
+                .arg(
+                    Arg::with_name("release")
+                        .long("release")
+                        .help("Vendor for release builds"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .takes_value(true)
+                        .default_value(".")
+                        .value_name("PATH")
+                        .help("Directory containing project to vendor"),
+                )
+                .arg(
+                    Arg::with_name("dest")
+                        .long("dest")
+                        .takes_value(true)
+                        .value_name("DEST")
+                        .help("Directory to download build artifacts to"),
+                )
+                .arg(
+                    Arg::with_name("targets")
+                        .value_name("TARGET")
+                        .multiple(true)
+                        .help("Target to resolve"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("generate-update-key")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .about("Generate a key for signing update manifests")
+                .long_about(GENERATE_UPDATE_KEY_ABOUT)
+                .arg(
+                    Arg::with_name("path")
+                        .required(true)
+                        .value_name("PATH")
+                        .help("Path to write the secret key to"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("init-config-file")
                 .setting(AppSettings::ArgRequiredElseHelp)
                 .about("Create a new PyOxidizer configuration file.")
+                .long_about(init_config_file_about.as_str())
+                .arg(
+                    Arg::with_name("template")
+                        .long("template")
+                        .takes_value(true)
+                        .possible_values(&config_templates)
+                        .default_value("default")
+                        .help("Template of the configuration file"),
+                )
                 .arg(
                     Arg::with_name("python-code")
                         .long("python-code")
@@ -190,6 +590,116 @@ pub fn run_cli() -> Result<()> {
                         .value_name("PATH")
                         .help("Directory containing project to build"),
                 )
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .conflicts_with("targets")
+                        .help("Build all registered targets"),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .long("jobs")
+                        .short("j")
+                        .takes_value(true)
+                        .default_value("1")
+                        .value_name("N")
+                        .help("Number of targets to build concurrently"),
+                )
+                .arg(
+                    Arg::with_name("output_layout")
+                        .long("output-layout")
+                        .takes_value(true)
+                        .value_name("LAYOUT")
+                        .help("Template of the directories targets are written to"),
+                )
+                .arg(
+                    Arg::with_name("artifact_dir")
+                        .long("artifact-dir")
+                        .takes_value(true)
+                        .value_name("PATH")
+                        .help("Directory to copy built deliverables to"),
+                )
+                .arg(
+                    Arg::with_name("targets")
+                        .value_name("TARGET")
+                        .multiple(true)
+                        .help("Target to resolve"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("build-app")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .about("Build an application from a Python package without a config file")
+                .long_about(BUILD_APP_ABOUT)
+                .arg(
+                    Arg::with_name("entry")
+                        .long("entry")
+                        .takes_value(true)
+                        .required(true)
+                        .value_name("ENTRY")
+                        .help("Module or module:callable to run"),
+                )
+                .arg(
+                    Arg::with_name("name")
+                        .long("name")
+                        .takes_value(true)
+                        .help("Name of the built executable"),
+                )
+                .arg(
+                    Arg::with_name("emit-config")
+                        .long("emit-config")
+                        .help("Print the synthesized configuration file instead of building"),
+                )
+                .arg(
+                    Arg::with_name("target_triple")
+                        .long("target-triple")
+                        .takes_value(true)
+                        .help("Rust target triple to build for"),
+                )
+                .arg(
+                    Arg::with_name("release")
+                        .long("release")
+                        .help("Build a release binary"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .takes_value(true)
+                        .default_value(".")
+                        .value_name("PATH")
+                        .help("Directory to write build output to"),
+                )
+                .arg(
+                    Arg::with_name("package")
+                        .required(true)
+                        .value_name("PACKAGE")
+                        .help("Python package to install via `pip install`"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("verify-reproducible")
+                .setting(AppSettings::ArgRequiredElseHelp)
+                .about("Build a project twice and verify the builds are identical")
+                .long_about(VERIFY_REPRODUCIBLE_ABOUT)
+                .arg(
+                    Arg::with_name("target_triple")
+                        .long("target-triple")
+                        .takes_value(true)
+                        .help("Rust target triple to build for"),
+                )
+                .arg(
+                    Arg::with_name("release")
+                        .long("release")
+                        .help("Build a release binary"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .takes_value(true)
+                        .default_value(".")
+                        .value_name("PATH")
+                        .help("Directory containing project to build"),
+                )
                 .arg(
                     Arg::with_name("targets")
                         .value_name("TARGET")
@@ -227,6 +737,144 @@ pub fn run_cli() -> Result<()> {
                 )
                 .arg(Arg::with_name("extra").multiple(true)),
         )
+        .subcommand(
+            SubCommand::with_name("run-module-test")
+                .about("Build and run test targets in a PyOxidizer configuration file")
+                .arg(
+                    Arg::with_name("target_triple")
+                        .long("target-triple")
+                        .takes_value(true)
+                        .help("Rust target triple to build for"),
+                )
+                .arg(
+                    Arg::with_name("release")
+                        .long("release")
+                        .help("Test a release binary"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .takes_value(true)
+                        .default_value(".")
+                        .value_name("PATH")
+                        .help("Directory containing project to test"),
+                )
+                .arg(
+                    Arg::with_name("targets")
+                        .value_name("TARGET")
+                        .multiple(true)
+                        .help("Test target to run. All test targets run by default"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("release")
+                .about("Build release targets and publish their artifacts")
+                .long_about(RELEASE_ABOUT)
+                .arg(
+                    Arg::with_name("target_triple")
+                        .long("target-triple")
+                        .takes_value(true)
+                        .help("Rust target triple to build for"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .takes_value(true)
+                        .default_value(".")
+                        .value_name("PATH")
+                        .help("Directory containing project to release"),
+                )
+                .arg(
+                    Arg::with_name("dest")
+                        .long("dest")
+                        .takes_value(true)
+                        .value_name("DEST")
+                        .help("Directory to write releases to"),
+                )
+                .arg(
+                    Arg::with_name("upload")
+                        .long("upload")
+                        .help("Upload releases to their GitHub or GitLab repository"),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .long("jobs")
+                        .short("j")
+                        .takes_value(true)
+                        .default_value("1")
+                        .value_name("N")
+                        .help("Number of targets to build concurrently"),
+                )
+                .arg(
+                    Arg::with_name("targets")
+                        .value_name("TARGET")
+                        .multiple(true)
+                        .help("Release target to release. All release targets by default"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("devserver")
+                .setting(AppSettings::TrailingVarArg)
+                .about("Run a target, re-running it when Python source changes")
+                .arg(
+                    Arg::with_name("target_triple")
+                        .long("target-triple")
+                        .takes_value(true)
+                        .help("Rust target triple to build for"),
+                )
+                .arg(
+                    Arg::with_name("release")
+                        .long("release")
+                        .help("Run a release binary"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .long("path")
+                        .default_value(".")
+                        .value_name("PATH")
+                        .help("Directory containing project to build"),
+                )
+                .arg(
+                    Arg::with_name("target")
+                        .long("target")
+                        .takes_value(true)
+                        .help("Build target to run"),
+                )
+                .arg(
+                    Arg::with_name("source")
+                        .long("source")
+                        .takes_value(true)
+                        .value_name("DIR")
+                        .help("Directory containing Python source to watch"),
+                )
+                .arg(
+                    Arg::with_name("package")
+                        .long("package")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required(true)
+                        .value_name("PACKAGE")
+                        .help("Package to import from the source directory instead of embedding"),
+                )
+                .arg(Arg::with_name("extra").multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("migrate-config")
+                .about("Update a configuration file to the current configuration API")
+                .long_about(MIGRATE_CONFIG_ABOUT)
+                .arg(
+                    Arg::with_name("dry_run")
+                        .long("dry-run")
+                        .help("Print changes without writing them"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .default_value(".")
+                        .value_name("PATH")
+                        .help("Directory containing project to migrate"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("python-distribution-extract")
                 .about("Extract a Python distribution archive to a directory")
@@ -267,13 +915,41 @@ pub fn run_cli() -> Result<()> {
 
     let verbose = matches.is_present("verbose");
 
-    let log_level = if verbose {
-        slog::Level::Info
+    let log_level = if matches.is_present("quiet") {
+        slog::Level::Error
     } else {
-        slog::Level::Warning
+        match matches.occurrences_of("verbose") {
+            0 => slog::Level::Warning,
+            1 => slog::Level::Info,
+            _ => slog::Level::Debug,
+        }
     };
 
-    let logger_context = logging::logger_from_env(log_level);
+    let log_format = matches.value_of("log_format").unwrap().parse()?;
+
+    if matches.is_present("system_rust") {
+        std::env::set_var(rust_toolchain::SYSTEM_RUST_ENV, "1");
+    }
+
+    if matches.is_present("locked") {
+        std::env::set_var(distribution_signature::LOCKED_ENV, "1");
+    }
+
+    if let Some(path) = matches.value_of("scratch_path") {
+        std::env::set_var(scratch::SCRATCH_PATH_ENV, path);
+    }
+
+    if let Some(size) = matches.value_of("scratch_max_size") {
+        size_budget::parse_size(size).map_err(|e| anyhow!(e))?;
+        std::env::set_var(scratch::SCRATCH_MAX_SIZE_ENV, size);
+    }
+
+    // Progress bars would interleave with JSON records.
+    progress::set_bars_enabled(
+        log_format == logging::LogFormat::Text && !matches.is_present("quiet"),
+    );
+
+    let logger_context = logging::logger_with_format(log_level, log_format);
 
     match matches.subcommand() {
         ("add", Some(args)) => {
@@ -300,16 +976,129 @@ pub fn run_cli() -> Result<()> {
                 None
             };
 
+            let all = args.is_present("all");
+            let jobs = args.value_of("jobs").unwrap().parse::<usize>()?;
+
+            if let Some(layout) = args.value_of("output_layout") {
+                output_layout::OutputLayout::parse(layout)?;
+                std::env::set_var(output_layout::OUTPUT_LAYOUT_ENV, layout);
+            }
+
             projectmgmt::build(
                 &logger_context.logger,
                 Path::new(path),
                 target_triple,
                 resolve_targets,
+                all,
+                jobs,
                 release,
+                args.value_of("artifact_dir").map(Path::new),
                 verbose,
             )
         }
 
+        ("build-app", Some(args)) => {
+            let release = args.is_present("release");
+            let target_triple = args.value_of("target_triple");
+            let path = args.value_of("path").unwrap();
+            let package = args.value_of("package").unwrap();
+            let entry = args.value_of("entry").unwrap();
+            let name = args.value_of("name");
+            let emit_config = args.is_present("emit-config");
+
+            projectmgmt::build_app(
+                &logger_context.logger,
+                Path::new(path),
+                package,
+                entry,
+                name,
+                target_triple,
+                release,
+                emit_config,
+                verbose,
+            )
+        }
+
+        ("check", Some(args)) => {
+            let target_triple = args.value_of("target_triple");
+            let path = args.value_of("path").unwrap();
+
+            let resolve_targets = if let Some(values) = args.values_of("targets") {
+                Some(values.map(|x| x.to_string()).collect())
+            } else {
+                None
+            };
+
+            projectmgmt::check(
+                &logger_context.logger,
+                Path::new(path),
+                target_triple,
+                resolve_targets,
+            )
+        }
+
+        ("clean", Some(args)) => {
+            let path = args.value_of("path").unwrap();
+
+            projectmgmt::clean(
+                &logger_context.logger,
+                Path::new(path),
+                args.is_present("all"),
+            )
+        }
+
+        ("diff", Some(args)) => {
+            let old = args.value_of("old").unwrap();
+            let new = args.value_of("new").unwrap();
+
+            artifact::diff_artifacts(Path::new(old), Path::new(new))
+        }
+
+        ("extract", Some(args)) => {
+            let path = args.value_of("path").unwrap();
+            let dest_path = args.value_of("dest_path").unwrap();
+
+            artifact::extract_artifact(Path::new(path), Path::new(dest_path))
+        }
+
+        ("doctor", Some(args)) => {
+            let target_triple = projectmgmt::resolve_target(args.value_of("target_triple"))?;
+
+            doctor::doctor(project_building::HOST, &target_triple)
+        }
+
+        ("vendor", Some(args)) => {
+            let release = args.is_present("release");
+            let target_triple = args.value_of("target_triple");
+            let path = args.value_of("path").unwrap();
+            let dest = args.value_of("dest").map(Path::new);
+
+            let resolve_targets = if let Some(values) = args.values_of("targets") {
+                Some(values.map(|x| x.to_string()).collect())
+            } else {
+                None
+            };
+
+            projectmgmt::vendor(
+                &logger_context.logger,
+                Path::new(path),
+                dest,
+                target_triple,
+                release,
+                resolve_targets,
+            )
+        }
+
+        ("generate-update-key", Some(args)) => {
+            let path = args.value_of("path").unwrap();
+
+            let public_key = update::generate_signing_key(Path::new(path))?;
+            println!("wrote update signing key to {}", path);
+            println!("public key: {}", public_key);
+
+            Ok(())
+        }
+
         ("init-config-file", Some(args)) => {
             let code = args.value_of("python-code");
             let pip_install = if args.is_present("pip-install") {
@@ -319,8 +1108,15 @@ pub fn run_cli() -> Result<()> {
             };
             let path = args.value_of("path").unwrap();
             let config_path = Path::new(path);
+            let template = args.value_of("template").unwrap();
 
-            projectmgmt::init_config_file(&config_path, code, &pip_install)
+            projectmgmt::init_config_file(
+                &logger_context.logger,
+                &config_path,
+                template,
+                code,
+                &pip_install,
+            )
         }
 
         ("list-targets", Some(args)) => {
@@ -333,14 +1129,21 @@ pub fn run_cli() -> Result<()> {
             let path = args.value_of("path").unwrap();
             let project_path = Path::new(path);
 
-            projectmgmt::init_rust_project(&project_path)
+            projectmgmt::init_rust_project(&logger_context.logger, &project_path)
+        }
+
+        ("migrate-config", Some(args)) => {
+            let path = args.value_of("path").unwrap();
+            let dry_run = args.is_present("dry_run");
+
+            projectmgmt::migrate_config(&logger_context.logger, Path::new(path), dry_run)
         }
 
         ("python-distribution-extract", Some(args)) => {
             let dist_path = args.value_of("dist_path").unwrap();
             let dest_path = args.value_of("dest_path").unwrap();
 
-            projectmgmt::python_distribution_extract(dist_path, dest_path)
+            projectmgmt::python_distribution_extract(&logger_context.logger, dist_path, dest_path)
         }
 
         ("python-distribution-info", Some(args)) => {
@@ -362,6 +1165,68 @@ pub fn run_cli() -> Result<()> {
             project_building::run_from_build(&logger_context.logger, build_script, target)
         }
 
+        ("verify-reproducible", Some(args)) => {
+            let release = args.is_present("release");
+            let target_triple = args.value_of("target_triple");
+            let path = args.value_of("path").unwrap();
+            let resolve_targets = if let Some(values) = args.values_of("targets") {
+                Some(values.map(|x| x.to_string()).collect())
+            } else {
+                None
+            };
+
+            projectmgmt::verify_reproducible(
+                &logger_context.logger,
+                Path::new(path),
+                target_triple,
+                resolve_targets,
+                release,
+                verbose,
+            )
+        }
+
+        ("run-module-test", Some(args)) => {
+            let target_triple = args.value_of("target_triple");
+            let release = args.is_present("release");
+            let path = args.value_of("path").unwrap();
+            let targets = if let Some(values) = args.values_of("targets") {
+                Some(values.map(|x| x.to_string()).collect())
+            } else {
+                None
+            };
+
+            projectmgmt::run_module_tests(
+                &logger_context.logger,
+                Path::new(path),
+                target_triple,
+                release,
+                targets,
+                verbose,
+            )
+        }
+
+        ("release", Some(args)) => {
+            let target_triple = args.value_of("target_triple");
+            let path = args.value_of("path").unwrap();
+            let targets = if let Some(values) = args.values_of("targets") {
+                Some(values.map(|x| x.to_string()).collect())
+            } else {
+                None
+            };
+            let jobs = args.value_of("jobs").unwrap().parse::<usize>()?;
+
+            projectmgmt::release(
+                &logger_context.logger,
+                Path::new(path),
+                target_triple,
+                targets,
+                args.value_of("dest").map(Path::new),
+                args.is_present("upload"),
+                jobs,
+                verbose,
+            )
+        }
+
         ("run", Some(args)) => {
             let target_triple = args.value_of("target_triple");
             let release = args.is_present("release");
@@ -380,6 +1245,32 @@ pub fn run_cli() -> Result<()> {
             )
         }
 
+        ("devserver", Some(args)) => {
+            let target_triple = args.value_of("target_triple");
+            let release = args.is_present("release");
+            let path = args.value_of("path").unwrap();
+            let target = args.value_of("target");
+            let source = args.value_of("source").unwrap_or(path);
+            let packages: Vec<String> = args
+                .values_of("package")
+                .unwrap()
+                .map(|x| x.to_string())
+                .collect();
+            let extra: Vec<&str> = args.values_of("extra").unwrap_or_default().collect();
+
+            projectmgmt::devserver(
+                &logger_context.logger,
+                Path::new(path),
+                target_triple,
+                release,
+                target,
+                Path::new(source),
+                &packages,
+                &extra,
+                verbose,
+            )
+        }
+
         _ => Err(anyhow!("invalid sub-command")),
     }
 }
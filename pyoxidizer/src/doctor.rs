@@ -0,0 +1,227 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Diagnose problems with the environment executables are built in.
+
+Missing compilers, linkers, and SDKs otherwise surface as cryptic errors
+deep into a build. The checks here detect them up front and describe how
+to fix them.
+*/
+
+use {
+    crate::py_packaging::distribution::{get_http_client, DistributionFlavor},
+    crate::python_distributions::find_python_distribution,
+    crate::rust_toolchain::{
+        rust_archive_url, use_system_rust, RustToolchain, RUST_TOOLCHAIN_VERSION,
+    },
+    anyhow::{anyhow, Result},
+};
+
+/// The outcome of a check.
+#[derive(Clone, Debug)]
+pub enum CheckStatus {
+    /// The check passed. Holds details on what was found.
+    Ok(String),
+
+    /// The check failed.
+    Failed {
+        /// What is wrong.
+        problem: String,
+
+        /// How to fix it.
+        fix: String,
+    },
+}
+
+/// A named check of the build environment.
+#[derive(Clone, Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl ToString) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Ok(detail.to_string()),
+        }
+    }
+
+    fn failed(name: &'static str, problem: impl ToString, fix: impl ToString) -> Self {
+        Self {
+            name,
+            status: CheckStatus::Failed {
+                problem: problem.to_string(),
+                fix: fix.to_string(),
+            },
+        }
+    }
+}
+
+/// How to install a C toolchain for a target.
+fn c_toolchain_fix(target: &str) -> &'static str {
+    if target.contains("-apple-") {
+        "install the Xcode command line tools by running `xcode-select --install`"
+    } else if target.contains("-pc-windows-msvc") {
+        "install Visual Studio or the Visual Studio Build Tools with the \"Desktop development with C++\" workload"
+    } else {
+        "install gcc or clang, e.g. `apt install build-essential` or `dnf install gcc`"
+    }
+}
+
+/// Check the Rust toolchain executables are built with.
+fn check_rust(target: &str) -> Check {
+    if !use_system_rust() {
+        return Check::ok(
+            "Rust",
+            format!(
+                "Rust {} will be downloaded when building; pass --system-rust to use the Rust in PATH",
+                RUST_TOOLCHAIN_VERSION
+            ),
+        );
+    }
+
+    match RustToolchain::system().verify(target) {
+        Ok(version) => Check::ok("Rust", format!("rustc {}", version)),
+        Err(e) => Check::failed(
+            "Rust",
+            e,
+            "install Rust with rustup from https://rustup.rs/ or omit --system-rust",
+        ),
+    }
+}
+
+/// Check a C compiler targeting `target` is available.
+fn check_c_compiler(host: &str, target: &str) -> Check {
+    let compiler = match cc::Build::new()
+        .host(host)
+        .target(target)
+        .opt_level(0)
+        .debug(false)
+        .cargo_metadata(false)
+        .try_get_compiler()
+    {
+        Ok(compiler) => compiler,
+        Err(e) => return Check::failed("C compiler", e, c_toolchain_fix(target)),
+    };
+
+    // MSVC's cl.exe doesn't have a version flag. Finding it is enough.
+    if compiler.is_like_msvc() {
+        return Check::ok("C compiler", compiler.path().display());
+    }
+
+    match compiler.to_command().arg("--version").output() {
+        Ok(output) if output.status.success() => Check::ok("C compiler", compiler.path().display()),
+        _ => Check::failed(
+            "C compiler",
+            format!("unable to run {}", compiler.path().display()),
+            c_toolchain_fix(target),
+        ),
+    }
+}
+
+/// Check the linker and SDK needed to link executables for `target` are available.
+fn check_linker(target: &str) -> Check {
+    if target.contains("-pc-windows-msvc") {
+        match cc::windows_registry::find_tool(target, "link.exe") {
+            Some(tool) => Check::ok("linker", tool.path().display()),
+            None => Check::failed("linker", "link.exe not found", c_toolchain_fix(target)),
+        }
+    } else if target.contains("-apple-") {
+        match std::process::Command::new("xcrun")
+            .arg("--show-sdk-path")
+            .output()
+        {
+            Ok(output) if output.status.success() => {
+                Check::ok("macOS SDK", String::from_utf8_lossy(&output.stdout).trim())
+            }
+            _ => Check::failed("macOS SDK", "macOS SDK not found", c_toolchain_fix(target)),
+        }
+    } else {
+        // Rust links with the `cc` driver by default.
+        match std::process::Command::new("cc").arg("--version").output() {
+            Ok(output) if output.status.success() => Check::ok("linker", "cc"),
+            _ => Check::failed("linker", "cc not found in PATH", c_toolchain_fix(target)),
+        }
+    }
+}
+
+/// Check a URL can be reached.
+fn check_url(name: &'static str, url: &str) -> Check {
+    let result = get_http_client()
+        .and_then(|client| client.head(url).send())
+        .and_then(|response| response.error_for_status());
+
+    match result {
+        Ok(_) => Check::ok(name, url),
+        Err(e) => Check::failed(
+            name,
+            format!("unable to reach {}: {}", url, e),
+            "check network access, or define HTTPS_PROXY if a proxy is required; use `pyoxidizer vendor` to build without network access",
+        ),
+    }
+}
+
+/// Run all checks of the environment for building executables for `target`.
+pub fn run_checks(host: &str, target: &str) -> Vec<Check> {
+    let mut checks = vec![
+        check_rust(target),
+        check_c_compiler(host, target),
+        check_linker(target),
+    ];
+
+    match find_python_distribution(&DistributionFlavor::Standalone, None, target) {
+        Ok(record) => checks.push(check_url("Python distribution", &record.location.url)),
+        Err(e) => checks.push(Check::failed(
+            "Python distribution",
+            e,
+            "define a distribution with PythonDistribution() in the configuration file",
+        )),
+    }
+
+    if !use_system_rust() {
+        checks.push(check_url(
+            "Rust download",
+            &rust_archive_url(&format!("rust-{}-{}", RUST_TOOLCHAIN_VERSION, host)),
+        ));
+    }
+
+    checks
+}
+
+/// Check the environment and print a report, failing if any check failed.
+pub fn doctor(host: &str, target: &str) -> Result<()> {
+    println!("checking environment for building {} executables", target);
+    println!();
+
+    let checks = run_checks(host, target);
+
+    for check in &checks {
+        match &check.status {
+            CheckStatus::Ok(detail) => println!("[ok]   {}: {}", check.name, detail),
+            CheckStatus::Failed { problem, fix } => {
+                println!("[FAIL] {}: {}", check.name, problem);
+                println!("       fix: {}", fix);
+            }
+        }
+    }
+
+    let failed = checks
+        .iter()
+        .filter(|c| match c.status {
+            CheckStatus::Failed { .. } => true,
+            CheckStatus::Ok(_) => false,
+        })
+        .count();
+
+    println!();
+    if failed > 0 {
+        Err(anyhow!("{} of {} checks failed", failed, checks.len()))
+    } else {
+        println!("all {} checks passed", checks.len());
+        Ok(())
+    }
+}
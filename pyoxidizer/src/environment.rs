@@ -54,9 +54,12 @@ pub fn canonicalize_path(path: &Path) -> Result<PathBuf, std::io::Error> {
     let mut p = path.canonicalize()?;
 
     // Strip \\?\ prefix on Windows and replace \ with /, which is valid.
+    // Verbatim UNC paths (\\?\UNC\server\share) become //server/share.
     if cfg!(windows) {
         let mut s = p.display().to_string().replace("\\", "/");
-        if s.starts_with("//?/") {
+        if s.starts_with("//?/UNC/") {
+            s = format!("//{}", &s[8..]);
+        } else if s.starts_with("//?/") {
             s = s[4..].to_string();
         }
 
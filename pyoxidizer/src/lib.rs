@@ -8,21 +8,40 @@ Functionality for embedding and packaging Python.
 PyOxidizer provides a myriad of functionality for packaging a Python
 distribution and embedding it in a larger binary, oftentimes an executable.
 
-This library exposes that functionality to other tools.
+This library exposes that functionality to other tools. The [`project`]
+module is the main entry point for building PyOxidizer projects
+programmatically.
+
+[`project`]: project/index.html
 */
 
 pub mod analyze;
 pub mod app_packaging;
+pub mod artifact;
+pub mod build_info;
+pub mod doctor;
 //pub mod distribution;
 pub mod environment;
-mod licensing;
+pub mod licensing;
+pub mod oci;
 pub mod logging;
+pub mod output_layout;
+pub mod progress;
+pub mod project;
 pub mod project_building;
 pub mod project_layout;
 pub mod projectmgmt;
 pub mod py_packaging;
 pub mod python_distributions;
+pub mod release;
+pub mod reproducible;
+pub mod rust_toolchain;
+pub mod sbom;
+pub mod scratch;
+pub mod size_budget;
 pub mod starlark;
+pub mod timing;
+pub mod update;
 
 #[cfg(test)]
 mod testutil;
@@ -2,6 +2,16 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use {
+    crate::py_packaging::standalone_distribution::LicenseInfo,
+    anyhow::{anyhow, Context, Result},
+    serde::Serialize,
+    sha2::{Digest, Sha256},
+    slog::warn,
+    std::collections::BTreeMap,
+    std::path::Path,
+};
+
 /// SPDX licenses in Python distributions that are not GPL.
 ///
 /// We store an allow list of licenses rather than trying to deny GPL licenses
@@ -15,3 +25,419 @@ pub const NON_GPL_LICENSES: &[&str] = &[
     "X11",
     "Zlib",
 ];
+
+/// Filename stem of generated third party notices files.
+pub const THIRD_PARTY_NOTICES_STEM: &str = "THIRD-PARTY-NOTICES";
+
+/// Describes the licensing of a software component.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct LicensedComponent {
+    /// Name of the component.
+    pub name: String,
+
+    /// Version of the component, if known.
+    pub version: Option<String>,
+
+    /// Names of licenses that apply to this component.
+    ///
+    /// These are SPDX shortnames when known. Otherwise they are free-form
+    /// strings obtained from package metadata.
+    pub licenses: Vec<String>,
+
+    /// Full text of license files for this component.
+    pub license_texts: Vec<String>,
+
+    /// Hex encoded SHA-256 of the package's `RECORD` file, if present.
+    ///
+    /// `RECORD` holds hashes of all files installed by a package, so this
+    /// identifies the installed package content.
+    pub sha256: Option<String>,
+}
+
+/// Parse licensing metadata from a `METADATA` or `PKG-INFO` file.
+///
+/// Returns `None` if the content doesn't define a package name.
+pub fn parse_package_metadata(data: &str) -> Option<LicensedComponent> {
+    let mut component = LicensedComponent::default();
+
+    for line in data.lines() {
+        // Headers end at the first empty line. The body is the long description.
+        if line.is_empty() {
+            break;
+        }
+
+        let mut parts = line.splitn(2, ':');
+        let (key, value) = match (parts.next(), parts.next()) {
+            (Some(key), Some(value)) => (key.trim(), value.trim()),
+            _ => continue,
+        };
+
+        match key {
+            "Name" => component.name = value.to_string(),
+            "Version" => component.version = Some(value.to_string()),
+            "License" => {
+                if !value.is_empty() && value != "UNKNOWN" {
+                    component.licenses.push(value.to_string());
+                }
+            }
+            // e.g. `License :: OSI Approved :: MIT License`.
+            "Classifier" if value.starts_with("License ::") => {
+                let license = value.rsplit(" :: ").next().unwrap().to_string();
+
+                if license != "OSI Approved" && !component.licenses.contains(&license) {
+                    component.licenses.push(license);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if component.name.is_empty() {
+        None
+    } else {
+        Some(component)
+    }
+}
+
+fn is_license_filename(name: &str) -> bool {
+    let name = name.to_uppercase();
+
+    ["LICENSE", "LICENCE", "COPYING", "NOTICE"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+/// Find licensing metadata of Python packages installed in a directory.
+///
+/// `path` is a directory like `site-packages` holding `.dist-info` or
+/// `.egg-info` directories.
+pub fn find_package_licenses(path: &Path) -> Result<Vec<LicensedComponent>> {
+    let mut res = Vec::new();
+
+    if !path.is_dir() {
+        return Ok(res);
+    }
+
+    let mut entries = std::fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let dir_path = entry.path();
+        let dir_name = entry.file_name().to_string_lossy().to_string();
+
+        let metadata_path = if dir_name.ends_with(".dist-info") {
+            dir_path.join("METADATA")
+        } else if dir_name.ends_with(".egg-info") {
+            dir_path.join("PKG-INFO")
+        } else {
+            continue;
+        };
+
+        if !metadata_path.is_file() {
+            continue;
+        }
+
+        let metadata = std::fs::read(&metadata_path)
+            .with_context(|| format!("reading {}", metadata_path.display()))?;
+
+        let mut component = match parse_package_metadata(&String::from_utf8_lossy(&metadata)) {
+            Some(component) => component,
+            None => continue,
+        };
+
+        let record_path = dir_path.join("RECORD");
+        if record_path.is_file() {
+            let data = std::fs::read(&record_path)
+                .with_context(|| format!("reading {}", record_path.display()))?;
+            let mut hasher = Sha256::new();
+            hasher.input(&data);
+            component.sha256 = Some(hex::encode(hasher.result()));
+        }
+
+        for f in walkdir::WalkDir::new(&dir_path).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+            let f = f?;
+
+            if f.file_type().is_file() && is_license_filename(&f.file_name().to_string_lossy()) {
+                let text = std::fs::read(f.path())
+                    .with_context(|| format!("reading {}", f.path().display()))?;
+                component
+                    .license_texts
+                    .push(String::from_utf8_lossy(&text).to_string());
+            }
+        }
+
+        res.push(component);
+    }
+
+    Ok(res)
+}
+
+/// Resolve licensed components from a Python distribution's license metadata.
+///
+/// `license_infos` is keyed by `python` or an extension module name. Only
+/// Python itself and the extension modules in `extension_modules` are
+/// emitted.
+pub fn distribution_licensed_components(
+    license_infos: &BTreeMap<String, Vec<LicenseInfo>>,
+    python_version: &str,
+    extension_modules: &[String],
+) -> Vec<LicensedComponent> {
+    let mut res = Vec::new();
+
+    let mut add = |name: String, version: Option<String>, infos: &[LicenseInfo]| {
+        let mut component = LicensedComponent {
+            name,
+            version,
+            ..LicensedComponent::default()
+        };
+
+        for info in infos {
+            for license in &info.licenses {
+                if !component.licenses.contains(license) {
+                    component.licenses.push(license.clone());
+                }
+            }
+            component.license_texts.push(info.license_text.clone());
+        }
+
+        res.push(component);
+    };
+
+    if let Some(infos) = license_infos.get("python") {
+        add(
+            "Python".to_string(),
+            Some(python_version.to_string()),
+            infos,
+        );
+    }
+
+    for name in extension_modules {
+        if let Some(infos) = license_infos.get(name) {
+            add(format!("Python extension module {}", name), None, infos);
+        }
+    }
+
+    res
+}
+
+/// Render licensed components as a human readable notices document.
+pub fn render_third_party_notices(components: &[LicensedComponent]) -> String {
+    let mut s = String::new();
+
+    s.push_str("This software contains the following third party components.\n");
+
+    for component in components {
+        let title = match &component.version {
+            Some(version) => format!("{} {}", component.name, version),
+            None => component.name.clone(),
+        };
+
+        s.push('\n');
+        s.push_str(&"=".repeat(79));
+        s.push('\n');
+        s.push_str(&title);
+        s.push('\n');
+        s.push_str(&"=".repeat(79));
+        s.push_str("\n\n");
+
+        if component.licenses.is_empty() {
+            s.push_str("Licenses: unknown\n");
+        } else {
+            s.push_str(&format!("Licenses: {}\n", component.licenses.join(", ")));
+        }
+
+        for text in &component.license_texts {
+            s.push('\n');
+            s.push_str(text.trim_end());
+            s.push('\n');
+        }
+    }
+
+    s
+}
+
+/// Restrictions on the licenses of components in built artifacts.
+///
+/// Entries are license names as they appear in package metadata or the
+/// Python distribution's annotations (typically SPDX identifiers) and may
+/// contain `*` wildcards.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LicensePolicy {
+    /// Licenses that cause the build to fail.
+    pub deny: Vec<String>,
+
+    /// Licenses that emit a warning.
+    pub warn: Vec<String>,
+}
+
+impl LicensePolicy {
+    /// Construct an instance, validating license patterns.
+    pub fn new(deny: Vec<String>, warn: Vec<String>) -> Result<Self> {
+        for pattern in deny.iter().chain(warn.iter()) {
+            glob::Pattern::new(pattern)
+                .with_context(|| format!("parsing license pattern {}", pattern))?;
+        }
+
+        Ok(LicensePolicy { deny, warn })
+    }
+
+    fn matching(patterns: &[String], component: &LicensedComponent) -> Vec<String> {
+        component
+            .licenses
+            .iter()
+            .filter(|license| {
+                patterns
+                    .iter()
+                    .any(|pattern| match glob::Pattern::new(pattern) {
+                        Ok(p) => p.matches(license),
+                        Err(_) => pattern == *license,
+                    })
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Evaluate licensed components against the policy.
+    ///
+    /// Warnings are logged for components with warned licenses. An error
+    /// describing every component with a denied license is returned if there
+    /// are any.
+    pub fn check(&self, logger: &slog::Logger, components: &[LicensedComponent]) -> Result<()> {
+        let mut denied = Vec::new();
+
+        for component in components {
+            let licenses = Self::matching(&self.warn, component);
+            if !licenses.is_empty() {
+                warn!(
+                    logger,
+                    "{} is licensed under {}",
+                    component.name,
+                    licenses.join(", ")
+                );
+            }
+
+            let licenses = Self::matching(&self.deny, component);
+            if !licenses.is_empty() {
+                denied.push(format!("{} ({})", component.name, licenses.join(", ")));
+            }
+        }
+
+        if denied.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "license policy denies components: {}",
+                denied.join("; ")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::testutil::get_logger};
+
+    #[test]
+    fn test_parse_package_metadata() {
+        let metadata = "Metadata-Version: 2.1\n\
+                        Name: foo\n\
+                        Version: 1.0\n\
+                        License: MIT\n\
+                        Classifier: Programming Language :: Python\n\
+                        Classifier: License :: OSI Approved :: BSD License\n\
+                        Classifier: License :: OSI Approved :: BSD License\n\
+                        \n\
+                        License: not a header\n";
+
+        assert_eq!(
+            parse_package_metadata(metadata),
+            Some(LicensedComponent {
+                name: "foo".to_string(),
+                version: Some("1.0".to_string()),
+                licenses: vec!["MIT".to_string(), "BSD License".to_string()],
+                license_texts: vec![],
+                sha256: None,
+            })
+        );
+
+        assert_eq!(parse_package_metadata("License: UNKNOWN\n"), None);
+    }
+
+    #[test]
+    fn test_find_package_licenses() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+        let root = temp_dir.path();
+
+        let dist_info = root.join("foo-1.0.dist-info");
+        std::fs::create_dir(&dist_info)?;
+        std::fs::write(
+            dist_info.join("METADATA"),
+            "Name: foo\nVersion: 1.0\nLicense: UNKNOWN\n",
+        )?;
+        std::fs::write(dist_info.join("LICENSE.txt"), "foo license\n")?;
+        std::fs::write(dist_info.join("RECORD"), "")?;
+
+        std::fs::create_dir(root.join("foo"))?;
+        std::fs::write(root.join("foo").join("LICENSE"), "ignored")?;
+
+        let components = find_package_licenses(root)?;
+        assert_eq!(
+            components,
+            vec![LicensedComponent {
+                name: "foo".to_string(),
+                version: Some("1.0".to_string()),
+                licenses: vec![],
+                license_texts: vec!["foo license\n".to_string()],
+                // SHA-256 of empty content.
+                sha256: Some(
+                    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_string()
+                ),
+            }]
+        );
+
+        let notices = render_third_party_notices(&components);
+        assert!(notices.contains("foo 1.0"));
+        assert!(notices.contains("Licenses: unknown"));
+        assert!(notices.contains("foo license"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_license_policy() -> Result<()> {
+        let logger = get_logger()?;
+
+        let components = vec![
+            LicensedComponent {
+                name: "Python extension module readline".to_string(),
+                licenses: vec!["GPL-3.0".to_string()],
+                ..LicensedComponent::default()
+            },
+            LicensedComponent {
+                name: "foo".to_string(),
+                licenses: vec!["LGPL-2.1".to_string(), "MIT".to_string()],
+                ..LicensedComponent::default()
+            },
+        ];
+
+        assert!(LicensePolicy::new(vec!["[".to_string()], vec![]).is_err());
+
+        let policy = LicensePolicy::new(vec![], vec!["LGPL-*".to_string()])?;
+        policy.check(&logger, &components)?;
+
+        let policy = LicensePolicy::new(vec!["GPL-3.0".to_string()], vec![])?;
+        let err = policy.check(&logger, &components).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "license policy denies components: Python extension module readline (GPL-3.0)"
+        );
+
+        let policy = LicensePolicy::new(vec!["*GPL*".to_string()], vec![])?;
+        assert!(policy.check(&logger, &components).is_err());
+
+        let policy = LicensePolicy::new(vec!["MIT-0".to_string()], vec![])?;
+        policy.check(&logger, &components[..1])?;
+
+        Ok(())
+    }
+}
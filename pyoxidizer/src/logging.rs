@@ -2,9 +2,37 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use slog::Drain;
+use {
+    anyhow::{anyhow, Result},
+    slog::{Drain, KV},
+    std::str::FromStr,
+};
+
+/// Format to emit log records in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// Human readable messages.
+    Text,
+
+    /// One JSON object per record.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow!("invalid log format: {}", s)),
+        }
+    }
+}
 
 /// A slog Drain that uses println!.
+///
+/// Records are printed above the progress bar being drawn, if any.
 pub struct PrintlnDrain {
     /// Minimum logging level that we're emitting.
     pub min_level: slog::Level,
@@ -21,9 +49,75 @@ impl slog::Drain for PrintlnDrain {
         _values: &slog::OwnedKVList,
     ) -> Result<Self::Ok, Self::Err> {
         if record.level().is_at_least(self.min_level) {
-            println!("{}", record.msg());
+            crate::progress::println(&record.msg().to_string());
+        }
+
+        Ok(())
+    }
+}
+
+/// slog Serializer collecting key-value pairs into a JSON object.
+struct JsonSerializer {
+    fields: serde_json::Map<String, serde_json::Value>,
+}
+
+impl slog::Serializer for JsonSerializer {
+    fn emit_arguments(&mut self, key: slog::Key, val: &std::fmt::Arguments) -> slog::Result {
+        self.fields
+            .insert(key.to_string(), serde_json::Value::from(val.to_string()));
+
+        Ok(())
+    }
+}
+
+/// A slog Drain that prints records as JSON objects, one per line.
+///
+/// Key-value pairs attached to records and loggers are emitted as fields
+/// on the object. Records are written to stderr, so they don't mix with
+/// output of commands on stdout.
+pub struct JsonDrain {
+    /// Minimum logging level that we're emitting.
+    pub min_level: slog::Level,
+}
+
+impl slog::Drain for JsonDrain {
+    type Ok = ();
+    type Err = std::io::Error;
+
+    fn log(
+        &self,
+        record: &slog::Record,
+        values: &slog::OwnedKVList,
+    ) -> Result<Self::Ok, Self::Err> {
+        if !record.level().is_at_least(self.min_level) {
+            return Ok(());
         }
 
+        let mut serializer = JsonSerializer {
+            fields: serde_json::Map::new(),
+        };
+
+        values
+            .serialize(record, &mut serializer)
+            .and_then(|_| record.kv().serialize(record, &mut serializer))
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", e)))?;
+
+        let mut fields = serializer.fields;
+        fields.insert(
+            "level".to_string(),
+            serde_json::Value::from(record.level().as_str()),
+        );
+        fields.insert(
+            "msg".to_string(),
+            serde_json::Value::from(record.msg().to_string()),
+        );
+        fields.insert(
+            "module".to_string(),
+            serde_json::Value::from(record.module()),
+        );
+
+        eprintln!("{}", serde_json::Value::Object(fields));
+
         Ok(())
     }
 }
@@ -35,9 +129,17 @@ pub struct LoggerContext {
 
 /// Construct a slog::Logger from settings in environment.
 pub fn logger_from_env(min_level: slog::Level) -> LoggerContext {
-    LoggerContext {
-        logger: slog::Logger::root(PrintlnDrain { min_level }.fuse(), slog::o!()),
-    }
+    logger_with_format(min_level, LogFormat::Text)
+}
+
+/// Construct a slog::Logger emitting records in a given format.
+pub fn logger_with_format(min_level: slog::Level, format: LogFormat) -> LoggerContext {
+    let logger = match format {
+        LogFormat::Text => slog::Logger::root(PrintlnDrain { min_level }.fuse(), slog::o!()),
+        LogFormat::Json => slog::Logger::root(JsonDrain { min_level }.fuse(), slog::o!()),
+    };
+
+    LoggerContext { logger }
 }
 
 impl Default for LoggerContext {
@@ -30,18 +30,34 @@ a rather effective and powerful tool.
 mod analyze;
 #[allow(unused)]
 pub mod app_packaging;
+mod artifact;
+mod build_info;
 mod cli;
+mod doctor;
 //mod distribution;
 mod environment;
 mod licensing;
+mod oci;
 mod logging;
+mod output_layout;
+mod progress;
+#[allow(unused)]
+mod project;
 mod project_building;
 mod project_layout;
 mod projectmgmt;
 #[allow(unused)]
 mod py_packaging;
 mod python_distributions;
+mod release;
+mod reproducible;
+mod rust_toolchain;
+mod sbom;
+mod scratch;
+mod size_budget;
 pub mod starlark;
+mod timing;
+mod update;
 #[cfg(test)]
 mod testutil;
 
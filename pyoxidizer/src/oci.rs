@@ -0,0 +1,1264 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Container images in the OCI format.
+
+An image is a list of layers, which are tar archives of files, and a JSON
+configuration describing how to run a container, e.g. its entrypoint and
+environment. Built executables and the files installed next to them are
+written to a single layer, stacked on top of the layers of a base image or
+on an empty filesystem (`scratch`).
+
+Base images are read from OCI image layouts on disk or pulled from
+registries implementing the OCI distribution API. Built images are written
+as OCI image layouts and can be pushed to registries. Registries requiring
+authentication are authenticated to with the credentials defined by the
+`PYOXIDIZER_REGISTRY_USERNAME` and `PYOXIDIZER_REGISTRY_PASSWORD`
+environment variables, if any.
+*/
+
+use {
+    crate::app_packaging::resource::FileManifest,
+    anyhow::{anyhow, Context, Result},
+    reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, LOCATION, WWW_AUTHENTICATE},
+    serde::{Deserialize, Serialize},
+    sha2::{Digest, Sha256},
+    slog::warn,
+    std::borrow::Cow,
+    std::collections::BTreeMap,
+    std::io::{Read, Write},
+    std::path::{Path, PathBuf},
+};
+
+pub const MEDIA_TYPE_INDEX: &str = "application/vnd.oci.image.index.v1+json";
+pub const MEDIA_TYPE_MANIFEST: &str = "application/vnd.oci.image.manifest.v1+json";
+pub const MEDIA_TYPE_CONFIG: &str = "application/vnd.oci.image.config.v1+json";
+pub const MEDIA_TYPE_LAYER_GZIP: &str = "application/vnd.oci.image.layer.v1.tar+gzip";
+
+const DOCKER_MEDIA_TYPE_MANIFEST_LIST: &str =
+    "application/vnd.docker.distribution.manifest.list.v2+json";
+const DOCKER_MEDIA_TYPE_MANIFEST: &str = "application/vnd.docker.distribution.manifest.v2+json";
+const DOCKER_MEDIA_TYPE_CONFIG: &str = "application/vnd.docker.container.image.v1+json";
+const DOCKER_MEDIA_TYPE_LAYER_GZIP: &str = "application/vnd.docker.image.rootfs.diff.tar.gzip";
+
+/// Annotation holding the tag of an image in an image layout.
+const ANNOTATION_REF_NAME: &str = "org.opencontainers.image.ref.name";
+
+/// Environment variables holding credentials to authenticate to registries with.
+pub const REGISTRY_USERNAME_ENV: &str = "PYOXIDIZER_REGISTRY_USERNAME";
+pub const REGISTRY_PASSWORD_ENV: &str = "PYOXIDIZER_REGISTRY_PASSWORD";
+
+/// Registry images without a registry name are pulled from.
+const DEFAULT_REGISTRY: &str = "registry-1.docker.io";
+
+/// The operating system and CPU architecture an image runs on.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Platform {
+    pub architecture: String,
+    pub os: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+}
+
+/// Obtain the platform of images running executables built for a target triple.
+pub fn platform_for_triple(target_triple: &str) -> Result<Platform> {
+    if !target_triple.contains("-linux-") {
+        return Err(anyhow!(
+            "container images require a Linux target triple; got {}",
+            target_triple
+        ));
+    }
+
+    let (architecture, variant) = match target_triple.split('-').next().unwrap_or("") {
+        "x86_64" => ("amd64", None),
+        "aarch64" => ("arm64", Some("v8")),
+        "i586" | "i686" => ("386", None),
+        "armv7" => ("arm", Some("v7")),
+        "powerpc64le" => ("ppc64le", None),
+        "s390x" => ("s390x", None),
+        arch => {
+            return Err(anyhow!(
+                "unable to determine the container platform of architecture {}",
+                arch
+            ))
+        }
+    };
+
+    Ok(Platform {
+        architecture: architecture.to_string(),
+        os: "linux".to_string(),
+        variant: variant.map(|v| v.to_string()),
+    })
+}
+
+/// Describes content referenced by an image.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Descriptor {
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub digest: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub platform: Option<Platform>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub annotations: Option<BTreeMap<String, String>>,
+}
+
+/// Describes the configuration and layers of an image.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Manifest {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    #[serde(rename = "mediaType", default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    pub config: Descriptor,
+    pub layers: Vec<Descriptor>,
+}
+
+/// Lists manifests, e.g. of an image built for several platforms.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Index {
+    #[serde(rename = "schemaVersion")]
+    pub schema_version: u32,
+    #[serde(rename = "mediaType", default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    pub manifests: Vec<Descriptor>,
+}
+
+/// Compute the digest of content.
+pub fn sha256_digest(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+
+    format!("sha256:{}", hex::encode(hasher.result()))
+}
+
+/// Translate Docker media types to their OCI equivalent.
+fn oci_media_type(media_type: &str) -> &str {
+    match media_type {
+        DOCKER_MEDIA_TYPE_MANIFEST => MEDIA_TYPE_MANIFEST,
+        DOCKER_MEDIA_TYPE_CONFIG => MEDIA_TYPE_CONFIG,
+        DOCKER_MEDIA_TYPE_LAYER_GZIP => MEDIA_TYPE_LAYER_GZIP,
+        media_type => media_type,
+    }
+}
+
+/// Find the manifest for a platform in an index.
+///
+/// An index holding a single manifest without platform matches any platform.
+fn select_platform<'a>(manifests: &'a [Descriptor], platform: &Platform) -> Result<&'a Descriptor> {
+    if let [manifest] = manifests {
+        if manifest.platform.is_none() {
+            return Ok(manifest);
+        }
+    }
+
+    manifests
+        .iter()
+        .find(|m| match &m.platform {
+            Some(p) => {
+                p.os == platform.os
+                    && p.architecture == platform.architecture
+                    && (p.variant.is_none()
+                        || platform.variant.is_none()
+                        || p.variant == platform.variant)
+            }
+            None => false,
+        })
+        .ok_or_else(|| {
+            anyhow!(
+                "image has no manifest for {}/{}",
+                platform.os,
+                platform.architecture
+            )
+        })
+}
+
+/// Content of an image, along with its descriptor.
+#[derive(Clone, Debug)]
+pub struct Blob {
+    pub descriptor: Descriptor,
+    pub data: Vec<u8>,
+}
+
+impl Blob {
+    pub fn new(media_type: &str, data: Vec<u8>) -> Self {
+        Self {
+            descriptor: Descriptor {
+                media_type: media_type.to_string(),
+                digest: sha256_digest(&data),
+                size: data.len() as u64,
+                platform: None,
+                annotations: None,
+            },
+            data,
+        }
+    }
+
+    /// Ensure the content matches the digest of its descriptor.
+    fn verify(&self) -> Result<()> {
+        let digest = sha256_digest(&self.data);
+
+        if digest == self.descriptor.digest {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "content of {} has digest {}",
+                self.descriptor.digest,
+                digest
+            ))
+        }
+    }
+}
+
+/// Obtain the path of a blob in an image layout, e.g. `blobs/sha256/<hex>`.
+fn blob_path(digest: &str) -> Result<String> {
+    let (algorithm, hex) = match digest.find(':') {
+        Some(pos) => (&digest[..pos], &digest[pos + 1..]),
+        None => return Err(anyhow!("invalid digest {}", digest)),
+    };
+
+    if algorithm != "sha256" || hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("unsupported digest {}", digest));
+    }
+
+    Ok(format!("blobs/{}/{}", algorithm, hex))
+}
+
+/// Append a file or directory to a deterministic tar archive.
+fn append_tar_entry<W: Write>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    data: Option<&[u8]>,
+    mode: u32,
+    mtime: u64,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_mode(mode);
+    header.set_mtime(mtime);
+    header.set_uid(0);
+    header.set_gid(0);
+
+    match data {
+        Some(data) => {
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_size(data.len() as u64);
+            builder.append_data(&mut header, path, data)?;
+        }
+        None => {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_size(0);
+            builder.append_data(&mut header, format!("{}/", path), std::io::empty())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// A layer of files added to an image.
+#[derive(Clone, Debug)]
+pub struct Layer {
+    /// The compressed layer.
+    pub blob: Blob,
+
+    /// Digest of the uncompressed layer.
+    pub diff_id: String,
+}
+
+impl Layer {
+    /// Create a layer holding the files of a manifest.
+    ///
+    /// Paths are relative to the root of the filesystem. Files and
+    /// directories are owned by root and have their modification time set
+    /// to `mtime`, so identical manifests produce identical layers.
+    pub fn from_manifest(manifest: &FileManifest, mtime: u64) -> Result<Self> {
+        let mut builder = tar::Builder::new(Vec::new());
+
+        for dir in manifest.relative_directories() {
+            append_tar_entry(&mut builder, &tar_path(&dir), None, 0o755, mtime)?;
+        }
+
+        for (path, content) in manifest.entries() {
+            let mode = if content.executable { 0o755 } else { 0o644 };
+            append_tar_entry(
+                &mut builder,
+                &tar_path(path),
+                Some(content.data.as_slice()),
+                mode,
+                mtime,
+            )?;
+        }
+
+        let data = builder.into_inner()?;
+        let diff_id = sha256_digest(&data);
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&data)?;
+
+        Ok(Self {
+            blob: Blob::new(MEDIA_TYPE_LAYER_GZIP, encoder.finish()?),
+            diff_id,
+        })
+    }
+}
+
+/// Normalize a relative path to the form used in tar archives.
+fn tar_path(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Settings of containers running an image.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImageSettings {
+    /// Command line run by containers.
+    pub entrypoint: Vec<String>,
+
+    /// Environment variables, overriding those of the base image.
+    pub env: BTreeMap<String, String>,
+
+    /// Labels, overriding those of the base image.
+    pub labels: BTreeMap<String, String>,
+
+    /// Working directory of the entrypoint.
+    pub working_dir: Option<String>,
+}
+
+/// The configuration and layers of an image new layers are added to.
+#[derive(Clone, Debug)]
+pub struct BaseImage {
+    /// The image configuration, as JSON.
+    pub config: serde_json::Value,
+
+    pub layers: Vec<Blob>,
+}
+
+impl BaseImage {
+    /// An image without layers.
+    pub fn scratch(platform: &Platform) -> Self {
+        let mut config = serde_json::json!({
+            "architecture": platform.architecture,
+            "os": platform.os,
+            "config": {},
+            "rootfs": {
+                "type": "layers",
+                "diff_ids": [],
+            },
+            "history": [],
+        });
+        if let Some(variant) = &platform.variant {
+            config["variant"] = serde_json::Value::from(variant.clone());
+        }
+
+        Self {
+            config,
+            layers: Vec::new(),
+        }
+    }
+
+    /// Construct an instance from a manifest and a function fetching blobs.
+    fn from_manifest<F>(manifest: &Manifest, mut fetch: F) -> Result<Self>
+    where
+        F: FnMut(&Descriptor) -> Result<Vec<u8>>,
+    {
+        let config = serde_json::from_slice(&fetch(&manifest.config)?)
+            .context("parsing base image configuration")?;
+
+        let mut layers = Vec::new();
+        for descriptor in &manifest.layers {
+            let mut descriptor = descriptor.clone();
+            descriptor.media_type = oci_media_type(&descriptor.media_type).to_string();
+
+            layers.push(Blob {
+                data: fetch(&descriptor)?,
+                descriptor,
+            });
+        }
+
+        Ok(Self { config, layers })
+    }
+
+    /// Read an image from an OCI image layout.
+    ///
+    /// The layout must hold a single image or an image for `platform`.
+    pub fn from_layout(path: &Path, platform: &Platform) -> Result<Self> {
+        let read_blob = |descriptor: &Descriptor| -> Result<Vec<u8>> {
+            let blob_path = path.join(blob_path(&descriptor.digest)?);
+            let blob = Blob {
+                descriptor: descriptor.clone(),
+                data: std::fs::read(&blob_path)
+                    .with_context(|| format!("reading {}", blob_path.display()))?,
+            };
+            blob.verify()?;
+
+            Ok(blob.data)
+        };
+
+        let index_path = path.join("index.json");
+        let index: Index = serde_json::from_slice(
+            &std::fs::read(&index_path)
+                .with_context(|| format!("reading {}", index_path.display()))?,
+        )
+        .with_context(|| format!("parsing {}", index_path.display()))?;
+
+        let mut descriptor = select_platform(&index.manifests, platform)?.clone();
+
+        // Images built for several platforms are referenced by nested indexes.
+        while descriptor.media_type == MEDIA_TYPE_INDEX {
+            let index: Index = serde_json::from_slice(&read_blob(&descriptor)?)?;
+            descriptor = select_platform(&index.manifests, platform)?.clone();
+        }
+
+        let manifest: Manifest = serde_json::from_slice(&read_blob(&descriptor)?)
+            .with_context(|| format!("parsing manifest {}", descriptor.digest))?;
+
+        Self::from_manifest(&manifest, read_blob)
+    }
+}
+
+/// An image ready to be written or pushed.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub config: Blob,
+    pub layers: Vec<Blob>,
+    pub manifest: Blob,
+}
+
+impl Image {
+    /// Add a layer to a base image.
+    ///
+    /// `created` is the ISO 8601 time recorded as the creation time of the
+    /// image.
+    pub fn assemble(
+        base: BaseImage,
+        layer: Layer,
+        settings: &ImageSettings,
+        created: &str,
+    ) -> Result<Self> {
+        let mut config = base.config;
+
+        if !config.is_object() {
+            return Err(anyhow!("base image configuration is not an object"));
+        }
+        if !config["config"].is_object() {
+            config["config"] = serde_json::json!({});
+        }
+
+        {
+            let container = &mut config["config"];
+
+            if !settings.entrypoint.is_empty() {
+                container["Entrypoint"] = serde_json::json!(settings.entrypoint);
+                // The command of the base image is meant for its own entrypoint.
+                container
+                    .as_object_mut()
+                    .expect("config is an object")
+                    .remove("Cmd");
+            }
+
+            if !settings.env.is_empty() {
+                let mut env = container["Env"]
+                    .as_array()
+                    .map(|values| {
+                        values
+                            .iter()
+                            .filter_map(|v| v.as_str())
+                            .filter(|v| {
+                                let name = v.split('=').next().unwrap_or("");
+                                !settings.env.contains_key(name)
+                            })
+                            .map(|v| v.to_string())
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                env.extend(
+                    settings
+                        .env
+                        .iter()
+                        .map(|(name, value)| format!("{}={}", name, value)),
+                );
+                container["Env"] = serde_json::json!(env);
+            }
+
+            if !settings.labels.is_empty() {
+                if !container["Labels"].is_object() {
+                    container["Labels"] = serde_json::json!({});
+                }
+                for (name, value) in &settings.labels {
+                    container["Labels"][name] = serde_json::Value::from(value.clone());
+                }
+            }
+
+            if let Some(working_dir) = &settings.working_dir {
+                container["WorkingDir"] = serde_json::Value::from(working_dir.clone());
+            }
+        }
+
+        if !config["rootfs"]["diff_ids"].is_array() {
+            config["rootfs"] = serde_json::json!({"type": "layers", "diff_ids": []});
+        }
+        config["rootfs"]["diff_ids"]
+            .as_array_mut()
+            .expect("diff_ids is an array")
+            .push(serde_json::Value::from(layer.diff_id.clone()));
+
+        if !config["history"].is_array() {
+            config["history"] = serde_json::json!([]);
+        }
+        config["history"]
+            .as_array_mut()
+            .expect("history is an array")
+            .push(serde_json::json!({
+                "created": created,
+                "created_by": "pyoxidizer",
+            }));
+        config["created"] = serde_json::Value::from(created);
+
+        let config = Blob::new(MEDIA_TYPE_CONFIG, serde_json::to_vec(&config)?);
+
+        let mut layers = base.layers;
+        layers.push(layer.blob);
+
+        let manifest = Manifest {
+            schema_version: 2,
+            media_type: Some(MEDIA_TYPE_MANIFEST.to_string()),
+            config: config.descriptor.clone(),
+            layers: layers.iter().map(|l| l.descriptor.clone()).collect(),
+        };
+        let manifest = Blob::new(MEDIA_TYPE_MANIFEST, serde_json::to_vec(&manifest)?);
+
+        Ok(Self {
+            config,
+            layers,
+            manifest,
+        })
+    }
+
+    /// Obtain the files of an OCI image layout holding this image.
+    ///
+    /// The image is tagged `tag` in the layout.
+    fn layout_files(&self, tag: &str) -> Result<BTreeMap<String, Cow<[u8]>>> {
+        let mut descriptor = self.manifest.descriptor.clone();
+        let mut annotations = BTreeMap::new();
+        annotations.insert(ANNOTATION_REF_NAME.to_string(), tag.to_string());
+        descriptor.annotations = Some(annotations);
+
+        let index = Index {
+            schema_version: 2,
+            media_type: Some(MEDIA_TYPE_INDEX.to_string()),
+            manifests: vec![descriptor],
+        };
+
+        let mut files = BTreeMap::new();
+        files.insert(
+            "oci-layout".to_string(),
+            Cow::Owned(br#"{"imageLayoutVersion":"1.0.0"}"#.to_vec()),
+        );
+        files.insert(
+            "index.json".to_string(),
+            Cow::Owned(serde_json::to_vec(&index)?),
+        );
+
+        for blob in self
+            .layers
+            .iter()
+            .chain(std::iter::once(&self.config))
+            .chain(std::iter::once(&self.manifest))
+        {
+            files.insert(
+                blob_path(&blob.descriptor.digest)?,
+                Cow::Borrowed(blob.data.as_slice()),
+            );
+        }
+
+        Ok(files)
+    }
+
+    /// Write the image to a directory as an OCI image layout.
+    ///
+    /// The directory is replaced if it exists.
+    pub fn write_layout(&self, path: &Path, tag: &str) -> Result<()> {
+        if path.exists() {
+            std::fs::remove_dir_all(path)
+                .with_context(|| format!("removing {}", path.display()))?;
+        }
+
+        for (name, data) in self.layout_files(tag)? {
+            let file_path = path.join(&name);
+            if let Some(parent) = file_path.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("creating {}", parent.display()))?;
+            }
+            std::fs::write(&file_path, data)
+                .with_context(|| format!("writing {}", file_path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the image as a tar archive of an OCI image layout.
+    ///
+    /// Such archives can be loaded by `docker load` and `podman load`.
+    pub fn write_archive(&self, path: &Path, tag: &str, mtime: u64) -> Result<()> {
+        let files = self.layout_files(tag)?;
+
+        let fh =
+            std::fs::File::create(path).with_context(|| format!("creating {}", path.display()))?;
+        let mut builder = tar::Builder::new(fh);
+
+        append_tar_entry(&mut builder, "blobs", None, 0o755, mtime)?;
+        append_tar_entry(&mut builder, "blobs/sha256", None, 0o755, mtime)?;
+        for (name, data) in &files {
+            append_tar_entry(&mut builder, name, Some(&data[..]), 0o644, mtime)?;
+        }
+
+        builder.finish()?;
+
+        Ok(())
+    }
+}
+
+/// A reference to an image in a registry, e.g. `ghcr.io/org/app:1.0`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageReference {
+    /// Host name of the registry, with an optional port.
+    pub registry: String,
+
+    /// Name of the repository in the registry.
+    pub repository: String,
+
+    /// Tag or digest of the image.
+    pub reference: String,
+}
+
+impl std::fmt::Display for ImageReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let separator = if self.reference.starts_with("sha256:") {
+            "@"
+        } else {
+            ":"
+        };
+
+        write!(
+            f,
+            "{}/{}{}{}",
+            self.registry, self.repository, separator, self.reference
+        )
+    }
+}
+
+impl ImageReference {
+    /// Parse a reference like `docker` does.
+    ///
+    /// References without a registry name refer to Docker Hub. References
+    /// without a tag or digest refer to the `latest` tag.
+    pub fn parse(value: &str) -> Result<Self> {
+        let (name, reference) = match value.find('@') {
+            Some(pos) => (&value[..pos], value[pos + 1..].to_string()),
+            None => {
+                let last_slash = value.rfind('/').map(|pos| pos + 1).unwrap_or(0);
+                match value[last_slash..].find(':') {
+                    Some(pos) => (
+                        &value[..last_slash + pos],
+                        value[last_slash + pos + 1..].to_string(),
+                    ),
+                    None => (value, "latest".to_string()),
+                }
+            }
+        };
+
+        let (registry, repository) = match name.find('/') {
+            Some(pos)
+                if name[..pos].contains('.')
+                    || name[..pos].contains(':')
+                    || &name[..pos] == "localhost" =>
+            {
+                (name[..pos].to_string(), name[pos + 1..].to_string())
+            }
+            _ => (DEFAULT_REGISTRY.to_string(), name.to_string()),
+        };
+
+        let registry = if registry == "docker.io" {
+            DEFAULT_REGISTRY.to_string()
+        } else {
+            registry
+        };
+
+        let repository = if registry == DEFAULT_REGISTRY && !repository.contains('/') {
+            format!("library/{}", repository)
+        } else {
+            repository
+        };
+
+        if repository.is_empty()
+            || reference.is_empty()
+            || repository
+                .chars()
+                .any(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || "._-/".contains(c)))
+        {
+            return Err(anyhow!("invalid image reference {}", value));
+        }
+
+        Ok(Self {
+            registry,
+            repository,
+            reference,
+        })
+    }
+}
+
+/// Parse the parameters of a `WWW-Authenticate` header.
+///
+/// Returns the authentication scheme, lowercased, and its parameters.
+pub fn parse_auth_challenge(header: &str) -> (String, BTreeMap<String, String>) {
+    let header = header.trim();
+    let (scheme, mut rest) = match header.find(' ') {
+        Some(pos) => (&header[..pos], &header[pos + 1..]),
+        None => (header, ""),
+    };
+
+    let mut params = BTreeMap::new();
+
+    while let Some(pos) = rest.find('=') {
+        let name = rest[..pos]
+            .trim()
+            .trim_start_matches(',')
+            .trim()
+            .to_string();
+        rest = &rest[pos + 1..];
+
+        let value = if rest.starts_with('"') {
+            let end = rest[1..].find('"').map(|end| end + 1).unwrap_or(rest.len());
+            let value = rest[1..end].to_string();
+            rest = &rest[std::cmp::min(end + 1, rest.len())..];
+            value
+        } else {
+            let end = rest.find(',').unwrap_or(rest.len());
+            let value = rest[..end].trim().to_string();
+            rest = &rest[end..];
+            value
+        };
+
+        params.insert(name.to_lowercase(), value);
+    }
+
+    (scheme.to_lowercase(), params)
+}
+
+/// How requests to a registry are authenticated.
+#[derive(Clone, Debug)]
+enum RegistryAuth {
+    Basic(String, String),
+    Bearer(String),
+}
+
+/// A client of the API of an image registry.
+pub struct RegistryClient {
+    logger: slog::Logger,
+    client: reqwest::Client,
+    reference: ImageReference,
+    credentials: Option<(String, String)>,
+    auth: Option<RegistryAuth>,
+}
+
+impl RegistryClient {
+    pub fn new(logger: &slog::Logger, reference: &ImageReference) -> Result<Self> {
+        let credentials = match (
+            std::env::var(REGISTRY_USERNAME_ENV),
+            std::env::var(REGISTRY_PASSWORD_ENV),
+        ) {
+            (Ok(username), Ok(password)) => Some((username, password)),
+            _ => None,
+        };
+
+        Ok(Self {
+            logger: logger.clone(),
+            client: crate::py_packaging::distribution::get_http_client()?,
+            reference: reference.clone(),
+            credentials,
+            auth: None,
+        })
+    }
+
+    /// URL of the API of the repository.
+    fn repository_url(&self) -> String {
+        // Local registries typically don't use TLS.
+        let scheme = if self.reference.registry.starts_with("localhost")
+            || self.reference.registry.starts_with("127.0.0.1")
+        {
+            "http"
+        } else {
+            "https"
+        };
+
+        format!(
+            "{}://{}/v2/{}",
+            scheme, self.reference.registry, self.reference.repository
+        )
+    }
+
+    /// Obtain the authentication requested by a challenge.
+    fn authenticate(&self, challenge: &str, actions: &str) -> Result<RegistryAuth> {
+        let (scheme, params) = parse_auth_challenge(challenge);
+
+        match scheme.as_str() {
+            "basic" => {
+                let (username, password) = self.credentials.clone().ok_or_else(|| {
+                    anyhow!(
+                        "{} requires authentication; define {} and {}",
+                        self.reference.registry,
+                        REGISTRY_USERNAME_ENV,
+                        REGISTRY_PASSWORD_ENV
+                    )
+                })?;
+
+                Ok(RegistryAuth::Basic(username, password))
+            }
+            "bearer" => {
+                let realm = params
+                    .get("realm")
+                    .ok_or_else(|| anyhow!("authentication challenge has no realm"))?;
+                let mut url = reqwest::Url::parse(realm)?;
+                {
+                    let mut query = url.query_pairs_mut();
+                    if let Some(service) = params.get("service") {
+                        query.append_pair("service", service);
+                    }
+                    query.append_pair(
+                        "scope",
+                        &format!("repository:{}:{}", self.reference.repository, actions),
+                    );
+                }
+
+                let mut request = self.client.get(url);
+                if let Some((username, password)) = &self.credentials {
+                    request = request.basic_auth(username, Some(password));
+                }
+
+                let mut response = request.send()?;
+                let text = response.text()?;
+                if !response.status().is_success() {
+                    return Err(anyhow!(
+                        "obtaining token for {} failed with HTTP {}: {}",
+                        self.reference.registry,
+                        response.status(),
+                        text
+                    ));
+                }
+
+                let value: serde_json::Value = serde_json::from_str(&text)?;
+                let token = value["token"]
+                    .as_str()
+                    .or_else(|| value["access_token"].as_str())
+                    .ok_or_else(|| anyhow!("token response has no token"))?;
+
+                Ok(RegistryAuth::Bearer(token.to_string()))
+            }
+            _ => Err(anyhow!(
+                "unsupported authentication scheme {} of {}",
+                scheme,
+                self.reference.registry
+            )),
+        }
+    }
+
+    /// Send a request, authenticating if the registry requests it.
+    ///
+    /// `actions` are the repository actions the request needs, e.g. `pull`.
+    fn send<F>(&mut self, actions: &str, request: F) -> Result<reqwest::Response>
+    where
+        F: Fn(&reqwest::Client) -> reqwest::RequestBuilder,
+    {
+        let apply_auth = |builder: reqwest::RequestBuilder, auth: &Option<RegistryAuth>| match auth
+        {
+            Some(RegistryAuth::Basic(username, password)) => {
+                builder.basic_auth(username, Some(password))
+            }
+            Some(RegistryAuth::Bearer(token)) => {
+                builder.header(AUTHORIZATION, format!("Bearer {}", token))
+            }
+            None => builder,
+        };
+
+        let response = apply_auth(request(&self.client), &self.auth).send()?;
+        if response.status() != reqwest::StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+
+        let challenge = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| anyhow!("{} denied access", self.reference.registry))?
+            .to_string();
+        self.auth = Some(self.authenticate(&challenge, actions)?);
+
+        Ok(apply_auth(request(&self.client), &self.auth).send()?)
+    }
+
+    /// Fail with the content of a response if it isn't successful.
+    fn check_response(mut response: reqwest::Response, action: &str) -> Result<reqwest::Response> {
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            Err(anyhow!(
+                "{} failed with HTTP {}: {}",
+                action,
+                response.status(),
+                response.text().unwrap_or_default()
+            ))
+        }
+    }
+
+    /// Fetch the manifest of the image for a platform.
+    pub fn fetch_manifest(&mut self, platform: &Platform) -> Result<Manifest> {
+        let accept = [
+            MEDIA_TYPE_MANIFEST,
+            MEDIA_TYPE_INDEX,
+            DOCKER_MEDIA_TYPE_MANIFEST,
+            DOCKER_MEDIA_TYPE_MANIFEST_LIST,
+        ]
+        .join(", ");
+
+        let mut reference = self.reference.reference.clone();
+
+        loop {
+            let url = format!("{}/manifests/{}", self.repository_url(), reference);
+            let mut response = Self::check_response(
+                self.send("pull", |c| c.get(&url).header(ACCEPT, accept.as_str()))?,
+                &format!("fetching manifest of {}", self.reference),
+            )?;
+
+            let mut data = Vec::new();
+            response.read_to_end(&mut data)?;
+            let value: serde_json::Value = serde_json::from_slice(&data)
+                .with_context(|| format!("parsing manifest of {}", self.reference))?;
+
+            if value.get("manifests").is_some() {
+                let index: Index = serde_json::from_value(value)?;
+                reference = select_platform(&index.manifests, platform)?.digest.clone();
+                continue;
+            }
+
+            return Ok(serde_json::from_value(value)
+                .with_context(|| format!("parsing manifest of {}", self.reference))?);
+        }
+    }
+
+    /// Fetch a blob, verifying its digest.
+    ///
+    /// Blobs are read from and written to `cache_dir`, if defined.
+    pub fn fetch_blob(
+        &mut self,
+        descriptor: &Descriptor,
+        cache_dir: Option<&Path>,
+    ) -> Result<Vec<u8>> {
+        let cache_path = match cache_dir {
+            Some(cache_dir) => Some(cache_dir.join(blob_path(&descriptor.digest)?)),
+            None => None,
+        };
+
+        if let Some(cache_path) = &cache_path {
+            if let Ok(data) = std::fs::read(cache_path) {
+                let blob = Blob {
+                    descriptor: descriptor.clone(),
+                    data,
+                };
+                if blob.verify().is_ok() {
+                    return Ok(blob.data);
+                }
+            }
+        }
+
+        warn!(
+            self.logger,
+            "downloading {} of {}", descriptor.digest, self.reference
+        );
+        let url = format!("{}/blobs/{}", self.repository_url(), descriptor.digest);
+        let mut response = Self::check_response(
+            self.send("pull", |c| c.get(&url))?,
+            &format!("fetching {}", descriptor.digest),
+        )?;
+
+        let mut data = Vec::new();
+        response.read_to_end(&mut data)?;
+        let blob = Blob {
+            descriptor: descriptor.clone(),
+            data,
+        };
+        blob.verify()?;
+
+        if let Some(cache_path) = &cache_path {
+            let parent = cache_path.parent().expect("blob path has a parent");
+            let partial_path = cache_path.with_extension("partial");
+
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("creating {}", parent.display()))?;
+            std::fs::write(&partial_path, &blob.data)
+                .with_context(|| format!("writing {}", partial_path.display()))?;
+            std::fs::rename(&partial_path, cache_path)
+                .with_context(|| format!("renaming {}", partial_path.display()))?;
+        }
+
+        Ok(blob.data)
+    }
+
+    /// Pull the image for a platform.
+    ///
+    /// Blobs are cached in `cache_dir`, if defined.
+    pub fn pull(&mut self, platform: &Platform, cache_dir: Option<&Path>) -> Result<BaseImage> {
+        let manifest = self.fetch_manifest(platform)?;
+
+        BaseImage::from_manifest(&manifest, |descriptor| {
+            self.fetch_blob(descriptor, cache_dir)
+        })
+    }
+
+    /// Upload a blob, unless the registry already has it.
+    fn push_blob(&mut self, blob: &Blob) -> Result<()> {
+        let digest = &blob.descriptor.digest;
+        let blob_url = format!("{}/blobs/{}", self.repository_url(), digest);
+
+        if self
+            .send("pull,push", |c| c.head(&blob_url))?
+            .status()
+            .is_success()
+        {
+            return Ok(());
+        }
+
+        warn!(self.logger, "uploading {}", digest);
+        let uploads_url = format!("{}/blobs/uploads/", self.repository_url());
+        let response = Self::check_response(
+            self.send("pull,push", |c| c.post(&uploads_url))?,
+            &format!("starting upload of {}", digest),
+        )?;
+
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| anyhow!("upload of {} has no location", digest))?;
+        // Locations can be relative to the registry.
+        let mut url = reqwest::Url::parse(&uploads_url)?.join(location)?;
+        url.query_pairs_mut().append_pair("digest", digest);
+
+        Self::check_response(
+            self.send("pull,push", |c| {
+                c.put(url.clone())
+                    .header(CONTENT_TYPE, "application/octet-stream")
+                    .body(blob.data.clone())
+            })?,
+            &format!("uploading {}", digest),
+        )?;
+
+        Ok(())
+    }
+
+    /// Push an image to the repository, tagging it with the reference.
+    pub fn push(&mut self, image: &Image) -> Result<()> {
+        for blob in image.layers.iter().chain(std::iter::once(&image.config)) {
+            self.push_blob(blob)?;
+        }
+
+        let url = format!(
+            "{}/manifests/{}",
+            self.repository_url(),
+            self.reference.reference
+        );
+        Self::check_response(
+            self.send("pull,push", |c| {
+                c.put(&url)
+                    .header(CONTENT_TYPE, MEDIA_TYPE_MANIFEST)
+                    .body(image.manifest.data.clone())
+            })?,
+            &format!("pushing manifest of {}", self.reference),
+        )?;
+
+        Ok(())
+    }
+}
+
+/// Whether a path is an OCI image layout.
+pub fn is_image_layout(path: &Path) -> bool {
+    path.join("oci-layout").is_file()
+}
+
+/// Obtain a base image from an image layout on disk or a registry.
+///
+/// `base` is a path to an OCI image layout, relative to `cwd`, or a
+/// reference to an image in a registry. Blobs pulled from registries are
+/// cached in `cache_dir`.
+pub fn resolve_base_image(
+    logger: &slog::Logger,
+    base: &str,
+    cwd: &Path,
+    platform: &Platform,
+    cache_dir: &Path,
+) -> Result<BaseImage> {
+    let path: PathBuf = cwd.join(base);
+
+    if is_image_layout(&path) {
+        warn!(logger, "reading base image from {}", path.display());
+        BaseImage::from_layout(&path, platform)
+    } else {
+        let reference = ImageReference::parse(base)?;
+        warn!(logger, "pulling base image {}", reference);
+        RegistryClient::new(logger, &reference)?.pull(platform, Some(cache_dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::app_packaging::resource::FileContent};
+
+    #[test]
+    fn test_platform_for_triple() -> Result<()> {
+        assert_eq!(
+            platform_for_triple("x86_64-unknown-linux-musl")?,
+            Platform {
+                architecture: "amd64".to_string(),
+                os: "linux".to_string(),
+                variant: None,
+            }
+        );
+        assert_eq!(
+            platform_for_triple("aarch64-unknown-linux-gnu")?.architecture,
+            "arm64"
+        );
+        assert!(platform_for_triple("x86_64-pc-windows-msvc").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_image_reference() -> Result<()> {
+        assert_eq!(
+            ImageReference::parse("debian")?,
+            ImageReference {
+                registry: "registry-1.docker.io".to_string(),
+                repository: "library/debian".to_string(),
+                reference: "latest".to_string(),
+            }
+        );
+        assert_eq!(
+            ImageReference::parse("ghcr.io/org/app:1.0")?,
+            ImageReference {
+                registry: "ghcr.io".to_string(),
+                repository: "org/app".to_string(),
+                reference: "1.0".to_string(),
+            }
+        );
+        assert_eq!(
+            ImageReference::parse("localhost:5000/app")?.to_string(),
+            "localhost:5000/app:latest"
+        );
+        assert_eq!(
+            ImageReference::parse("org/app@sha256:abcd")?.to_string(),
+            "registry-1.docker.io/org/app@sha256:abcd"
+        );
+        assert!(ImageReference::parse("Org/App").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_auth_challenge() {
+        let (scheme, params) = parse_auth_challenge(
+            r#"Bearer realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:library/debian:pull""#,
+        );
+        assert_eq!(scheme, "bearer");
+        assert_eq!(params["realm"], "https://auth.docker.io/token");
+        assert_eq!(params["service"], "registry.docker.io");
+        assert_eq!(params["scope"], "repository:library/debian:pull");
+
+        let (scheme, params) = parse_auth_challenge(r#"Basic realm="Registry""#);
+        assert_eq!(scheme, "basic");
+        assert_eq!(params["realm"], "Registry");
+    }
+
+    #[test]
+    fn test_image_layout() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+
+        let mut manifest = FileManifest::default();
+        manifest.add_file(
+            Path::new("app/myapp"),
+            &FileContent {
+                data: b"exe".to_vec(),
+                executable: true,
+            },
+        )?;
+        manifest.add_file(
+            Path::new("app/lib/data.txt"),
+            &FileContent {
+                data: vec![],
+                executable: false,
+            },
+        )?;
+
+        let layer = Layer::from_manifest(&manifest, 0)?;
+        assert_eq!(
+            layer.blob.descriptor.digest,
+            Layer::from_manifest(&manifest, 0)?.blob.descriptor.digest
+        );
+
+        let mut settings = ImageSettings::default();
+        settings.entrypoint = vec!["/app/myapp".to_string()];
+        settings
+            .env
+            .insert("LANG".to_string(), "C.UTF-8".to_string());
+
+        let platform = platform_for_triple("x86_64-unknown-linux-musl")?;
+        let image = Image::assemble(
+            BaseImage::scratch(&platform),
+            layer,
+            &settings,
+            "1970-01-01T00:00:00Z",
+        )?;
+
+        let config: serde_json::Value = serde_json::from_slice(&image.config.data)?;
+        assert_eq!(config["architecture"], "amd64");
+        assert_eq!(config["config"]["Entrypoint"][0], "/app/myapp");
+        assert_eq!(config["config"]["Env"][0], "LANG=C.UTF-8");
+        assert_eq!(config["rootfs"]["diff_ids"].as_array().unwrap().len(), 1);
+
+        let layout_path = temp_dir.path().join("layout");
+        image.write_layout(&layout_path, "latest")?;
+        assert!(is_image_layout(&layout_path));
+
+        // Built images can be used as base images.
+        let base = BaseImage::from_layout(&layout_path, &platform)?;
+        assert_eq!(base.layers.len(), 1);
+        assert_eq!(base.config["config"]["Entrypoint"][0], "/app/myapp");
+
+        let mut settings = ImageSettings::default();
+        settings.env.insert("LANG".to_string(), "C".to_string());
+        let image = Image::assemble(
+            base,
+            Layer::from_manifest(&FileManifest::default(), 0)?,
+            &settings,
+            "1970-01-01T00:00:00Z",
+        )?;
+        let config: serde_json::Value = serde_json::from_slice(&image.config.data)?;
+        assert_eq!(config["config"]["Env"], serde_json::json!(["LANG=C"]));
+        assert_eq!(image.layers.len(), 2);
+
+        let archive_path = temp_dir.path().join("image.tar");
+        image.write_archive(&archive_path, "latest", 0)?;
+        let mut archive = tar::Archive::new(std::fs::File::open(&archive_path)?);
+        let names = archive
+            .entries()?
+            .map(|e| Ok(e?.path()?.display().to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        assert!(names.contains(&"index.json".to_string()));
+        assert!(names.contains(&"oci-layout".to_string()));
+        assert!(names.contains(&blob_path(&image.manifest.descriptor.digest)?));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Layout of the directories targets are written to.
+
+Every target is built in its own output directory. Its path is derived from
+a template with the following placeholders:
+
+* `{build_path}`: the build path of the project.
+* `{target_triple}`: the Rust target triple being built for.
+* `{build_mode}`: `debug` or `release`.
+* `{target}`: the name of the target.
+
+Relative paths are resolved from the directory containing the configuration
+file. The default layout writes targets to
+`{build_path}/{target_triple}/{build_mode}/{target}`. Templates can be set
+in configuration files or overridden with the `--output-layout` argument of
+`pyoxidizer build`, e.g. to write targets to `dist/{target_triple}/{target}`.
+*/
+
+use {
+    anyhow::{anyhow, Result},
+    std::path::{Path, PathBuf},
+};
+
+/// Environment variable overriding the output layout of configuration files.
+///
+/// The `--output-layout` argument of `pyoxidizer build` sets it.
+pub const OUTPUT_LAYOUT_ENV: &str = "PYOXIDIZER_OUTPUT_LAYOUT";
+
+/// Template of the default output layout.
+pub const DEFAULT_OUTPUT_LAYOUT: &str = "{build_path}/{target_triple}/{build_mode}/{target}";
+
+/// Placeholders valid in output layout templates.
+const PLACEHOLDERS: &[&str] = &["build_path", "target_triple", "build_mode", "target"];
+
+/// A template defining the output directories of targets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OutputLayout {
+    template: String,
+}
+
+impl Default for OutputLayout {
+    fn default() -> Self {
+        Self {
+            template: DEFAULT_OUTPUT_LAYOUT.to_string(),
+        }
+    }
+}
+
+impl OutputLayout {
+    /// Parse and validate a template.
+    ///
+    /// The template must contain `{target}`, so targets don't overwrite
+    /// each other.
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut rest = template;
+        let mut has_target = false;
+
+        while let Some(start) = rest.find(|c: char| c == '{' || c == '}') {
+            if rest[start..].starts_with('}') {
+                return Err(anyhow!("unmatched }} in output layout {}", template));
+            }
+
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| anyhow!("unmatched {{ in output layout {}", template))?;
+            let name = &rest[start + 1..start + end];
+
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(anyhow!(
+                    "unknown placeholder {{{}}} in output layout {}; expected one of {}",
+                    name,
+                    template,
+                    PLACEHOLDERS
+                        .iter()
+                        .map(|p| format!("{{{}}}", p))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+            }
+
+            has_target |= name == "target";
+            rest = &rest[start + end + 1..];
+        }
+
+        if !has_target {
+            return Err(anyhow!(
+                "output layout {} must contain {{target}}",
+                template
+            ));
+        }
+
+        Ok(Self {
+            template: template.to_string(),
+        })
+    }
+
+    /// Obtain the layout defined by the environment, if any.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(OUTPUT_LAYOUT_ENV) {
+            Ok(value) if !value.is_empty() => {
+                Ok(Some(Self::parse(&value).map_err(|e| {
+                    anyhow!("invalid {}: {}", OUTPUT_LAYOUT_ENV, e)
+                })?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Resolve the output directory of a target.
+    ///
+    /// Relative paths are resolved from `cwd`.
+    pub fn target_path(
+        &self,
+        cwd: &Path,
+        build_path: &Path,
+        target_triple: &str,
+        release: bool,
+        target: &str,
+    ) -> PathBuf {
+        let path = self
+            .template
+            .replace("{build_path}", &build_path.display().to_string())
+            .replace("{target_triple}", target_triple)
+            .replace("{build_mode}", if release { "release" } else { "debug" })
+            .replace("{target}", target);
+
+        cwd.join(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert!(OutputLayout::parse(DEFAULT_OUTPUT_LAYOUT).is_ok());
+        assert!(OutputLayout::parse("dist/{target_triple}/{target}").is_ok());
+
+        assert_eq!(
+            OutputLayout::parse("dist/{target_triple}")
+                .unwrap_err()
+                .to_string(),
+            "output layout dist/{target_triple} must contain {target}"
+        );
+        assert!(OutputLayout::parse("dist/{triple}/{target}")
+            .unwrap_err()
+            .to_string()
+            .starts_with("unknown placeholder {triple}"));
+        assert!(OutputLayout::parse("dist/{target").is_err());
+        assert!(OutputLayout::parse("dist/target}").is_err());
+    }
+
+    #[test]
+    fn test_target_path() {
+        let cwd = PathBuf::from("/project");
+        let build_path = cwd.join("build");
+
+        assert_eq!(
+            OutputLayout::default().target_path(
+                &cwd,
+                &build_path,
+                "x86_64-unknown-linux-gnu",
+                false,
+                "exe"
+            ),
+            build_path
+                .join("x86_64-unknown-linux-gnu")
+                .join("debug")
+                .join("exe")
+        );
+        assert_eq!(
+            OutputLayout::parse("dist/{target_triple}/{target}")
+                .unwrap()
+                .target_path(&cwd, &build_path, "x86_64-unknown-linux-gnu", true, "exe"),
+            cwd.join("dist/x86_64-unknown-linux-gnu/exe")
+        );
+    }
+}
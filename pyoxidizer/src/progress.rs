@@ -0,0 +1,352 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Progress reporting for long running operations.
+
+When progress bars are enabled and stderr is a terminal, a progress bar is
+drawn while an operation runs. Otherwise, progress is periodically logged so
+logs of builds running in CI show they haven't hung.
+*/
+
+use {
+    crate::size_budget::format_size,
+    indicatif::{ProgressBar, ProgressStyle},
+    lazy_static::lazy_static,
+    slog::{info, warn},
+    std::sync::atomic::{AtomicBool, Ordering},
+    std::sync::mpsc::{channel, RecvTimeoutError, Sender},
+    std::sync::Mutex,
+    std::time::{Duration, Instant},
+};
+
+/// How often progress is logged when no progress bar is drawn.
+const LOG_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Whether progress bars may be drawn.
+static BARS_ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// The progress bar being drawn, if any.
+    static ref ACTIVE_BAR: Mutex<Option<ProgressBar>> = Mutex::new(None);
+}
+
+/// Enable or disable drawing progress bars.
+///
+/// Progress bars are disabled by default. They are only drawn when stderr
+/// is a terminal.
+pub fn set_bars_enabled(enabled: bool) {
+    BARS_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn draw_bars() -> bool {
+    BARS_ENABLED.load(Ordering::Relaxed) && atty::is(atty::Stream::Stderr)
+}
+
+/// Print a line of output.
+///
+/// The line is printed above the progress bar being drawn, if any, so the
+/// bar isn't garbled.
+pub fn println(line: &str) {
+    match ACTIVE_BAR.lock().unwrap().as_ref() {
+        Some(bar) => bar.println(line),
+        None => println!("{}", line),
+    }
+}
+
+/// Format a duration for humans.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+
+    if secs >= 3600 {
+        format!("{}h{:02}m{:02}s", secs / 3600, secs % 3600 / 60, secs % 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Estimate the time remaining of an operation progressing at a constant rate.
+fn estimate_remaining(elapsed: Duration, position: u64, total: u64) -> Option<Duration> {
+    if position == 0 || position > total {
+        None
+    } else {
+        let millis = elapsed.as_millis() * u128::from(total - position) / u128::from(position);
+
+        Some(Duration::from_millis(millis as u64))
+    }
+}
+
+/// Units progress is counted in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Unit {
+    Items,
+    Bytes,
+}
+
+impl Unit {
+    fn format(self, count: u64) -> String {
+        match self {
+            Unit::Items => count.to_string(),
+            Unit::Bytes => format_size(count),
+        }
+    }
+}
+
+/// Reports progress of a long running operation.
+///
+/// The progress bar is cleared when the instance is dropped.
+pub struct Progress {
+    logger: slog::Logger,
+    message: String,
+    unit: Unit,
+    total: Option<u64>,
+    position: u64,
+    start: Instant,
+    last_logged: Instant,
+    bar: Option<ProgressBar>,
+
+    /// Stops the thread logging the elapsed time of operations without a
+    /// total when dropped.
+    _heartbeat: Option<Sender<()>>,
+}
+
+impl Progress {
+    /// Report progress of an operation on `total` items.
+    pub fn new(logger: &slog::Logger, message: &str, total: u64) -> Self {
+        Self::start(logger, message, Unit::Items, Some(total), true)
+    }
+
+    /// Report progress of transferring bytes, whose total may be unknown.
+    pub fn bytes(logger: &slog::Logger, message: &str, total: Option<u64>) -> Self {
+        Self::start(logger, message, Unit::Bytes, total, true)
+    }
+
+    /// Report progress of an operation whose amount of work is unknown.
+    ///
+    /// A spinner and the elapsed time are drawn.
+    pub fn spinner(logger: &slog::Logger, message: &str) -> Self {
+        Self::start(logger, message, Unit::Items, None, true)
+    }
+
+    /// Report progress of a process drawing its own progress output.
+    ///
+    /// Nothing is drawn, as it would garble the output of the process.
+    /// The elapsed time is periodically logged when stderr isn't a terminal,
+    /// since processes like `cargo` only report progress to terminals.
+    pub fn external(logger: &slog::Logger, message: &str) -> Self {
+        Self::start(logger, message, Unit::Items, None, false)
+    }
+
+    fn start(
+        logger: &slog::Logger,
+        message: &str,
+        unit: Unit,
+        total: Option<u64>,
+        draw: bool,
+    ) -> Self {
+        let terminal = draw_bars();
+
+        // Only one bar is drawn at a time. Other operations log progress.
+        let bar = if draw && terminal {
+            let mut active = ACTIVE_BAR.lock().unwrap();
+
+            if active.is_none() {
+                let bar = new_bar(message, unit, total);
+                active.replace(bar.clone());
+                Some(bar)
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        let heartbeat =
+            if bar.is_none() && unit == Unit::Items && total.is_none() && (draw || !terminal) {
+                Some(start_heartbeat(logger, message))
+            } else {
+                None
+            };
+
+        let now = Instant::now();
+
+        Progress {
+            logger: logger.clone(),
+            message: message.to_string(),
+            unit,
+            total,
+            position: 0,
+            start: now,
+            last_logged: now,
+            bar,
+            _heartbeat: heartbeat,
+        }
+    }
+
+    /// Advance progress.
+    pub fn inc(&mut self, delta: u64) {
+        self.position += delta;
+
+        if let Some(bar) = &self.bar {
+            bar.inc(delta);
+        } else if self.last_logged.elapsed() >= LOG_INTERVAL {
+            self.last_logged = Instant::now();
+            warn!(self.logger, "{}", self.status());
+        }
+    }
+
+    /// Describe the progress made, as logged when no progress bar is drawn.
+    fn status(&self) -> String {
+        let elapsed = self.start.elapsed();
+
+        match self.total {
+            Some(total) => {
+                let percent = if total == 0 {
+                    100
+                } else {
+                    self.position * 100 / total
+                };
+
+                let mut status = format!(
+                    "{}: {}/{} ({}%)",
+                    self.message,
+                    self.unit.format(self.position),
+                    self.unit.format(total),
+                    percent
+                );
+
+                if let Some(remaining) = estimate_remaining(elapsed, self.position, total) {
+                    status.push_str(&format!(", about {} remaining", format_duration(remaining)));
+                }
+
+                status
+            }
+            None => format!(
+                "{}: {} after {}",
+                self.message,
+                self.unit.format(self.position),
+                format_duration(elapsed)
+            ),
+        }
+    }
+
+    /// Mark the operation as completed.
+    pub fn finish(self) {
+        info!(
+            self.logger,
+            "{}: done in {}",
+            self.message,
+            format_duration(self.start.elapsed())
+        );
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(bar) = self.bar.take() {
+            bar.finish_and_clear();
+            ACTIVE_BAR.lock().unwrap().take();
+        }
+    }
+}
+
+fn new_bar(message: &str, unit: Unit, total: Option<u64>) -> ProgressBar {
+    let (bar, template) = match (unit, total) {
+        (Unit::Items, Some(total)) => (
+            ProgressBar::new(total),
+            "{msg} [{elapsed_precise}] {wide_bar} {pos}/{len} ETA {eta}",
+        ),
+        (Unit::Bytes, Some(total)) => (
+            ProgressBar::new(total),
+            "{msg} [{elapsed_precise}] {wide_bar} {bytes}/{total_bytes} ETA {eta}",
+        ),
+        (Unit::Items, None) => (
+            ProgressBar::new_spinner(),
+            "{spinner} {msg} [{elapsed_precise}]",
+        ),
+        (Unit::Bytes, None) => (
+            ProgressBar::new_spinner(),
+            "{spinner} {msg} [{elapsed_precise}] {bytes}",
+        ),
+    };
+
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template(template)
+            .progress_chars("=> "),
+    );
+    bar.set_message(message);
+
+    if total.is_none() {
+        bar.enable_steady_tick(100);
+    }
+
+    bar
+}
+
+/// Periodically log the elapsed time of an operation until the returned
+/// sender is dropped.
+fn start_heartbeat(logger: &slog::Logger, message: &str) -> Sender<()> {
+    let (sender, receiver) = channel::<()>();
+    let logger = logger.clone();
+    let message = message.to_string();
+    let start = Instant::now();
+
+    std::thread::spawn(move || {
+        while let Err(RecvTimeoutError::Timeout) = receiver.recv_timeout(LOG_INTERVAL) {
+            warn!(
+                logger,
+                "{}: still running after {}",
+                message,
+                format_duration(start.elapsed())
+            );
+        }
+    });
+
+    sender
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(7500)), "7s");
+        assert_eq!(format_duration(Duration::from_secs(125)), "2m05s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h02m03s");
+    }
+
+    #[test]
+    fn test_estimate_remaining() {
+        assert_eq!(estimate_remaining(Duration::from_secs(10), 0, 100), None);
+        assert_eq!(
+            estimate_remaining(Duration::from_secs(10), 25, 100),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            estimate_remaining(Duration::from_secs(10), 100, 100),
+            Some(Duration::from_secs(0))
+        );
+    }
+
+    #[test]
+    fn test_progress_status() {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+
+        let mut progress = Progress::bytes(&logger, "downloading", Some(2_000_000));
+        assert!(progress.bar.is_none());
+        progress.inc(500_000);
+        assert!(progress
+            .status()
+            .starts_with("downloading: 500.0 KB/2.0 MB (25%)"));
+
+        let mut progress = Progress::new(&logger, "compiling", 4);
+        progress.inc(1);
+        assert!(progress.status().starts_with("compiling: 1/4 (25%)"));
+        progress.finish();
+    }
+}
@@ -0,0 +1,617 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Programmatic interface to PyOxidizer projects.
+
+This module provides a high-level API for evaluating PyOxidizer
+configuration files and building their targets without going through the
+`pyoxidizer` command line interface. e.g.
+
+```no_run
+use pyoxidizerlib::project::Project;
+
+let output = Project::from_config(std::path::Path::new("pyoxidizer.bzl"))?.build(None)?;
+
+for target in &output.manifest.targets {
+    println!("{} written to {}", target.name, target.output_path.display());
+}
+# Ok::<(), anyhow::Error>(())
+```
+*/
+
+use {
+    crate::logging::LoggerContext,
+    crate::project_building::find_pyoxidizer_config_file,
+    crate::projectmgmt::default_target,
+    crate::scratch::enforce_scratch_max_size,
+    crate::starlark::build_manifest::BuildManifest,
+    crate::starlark::env::EnvironmentContext,
+    crate::starlark::eval::{eval_starlark_config_file, EvalResult},
+    crate::timing::{TimingReport, TIMINGS_FILENAME},
+    anyhow::{anyhow, Context, Result},
+    std::collections::{BTreeMap, BTreeSet},
+    std::panic::AssertUnwindSafe,
+    std::path::{Path, PathBuf},
+    std::sync::mpsc,
+};
+
+/// The result of building a project.
+#[derive(Clone, Debug)]
+pub struct BuildOutput {
+    /// Describes the targets that were built and the files they produced.
+    pub manifest: BuildManifest,
+
+    /// Path to the JSON serialized `manifest`.
+    pub manifest_path: PathBuf,
+
+    /// Wall time of the phases of the build.
+    pub timings: TimingReport,
+
+    /// Path to the trace of `timings`, viewable in `chrome://tracing`.
+    pub timings_path: PathBuf,
+}
+
+impl BuildOutput {
+    /// Copy the deliverables of built targets to a flat directory.
+    ///
+    /// Deliverables are the files at the root of the output directories of
+    /// targets, e.g. executables and installers, and the build manifest.
+    /// Files in sub-directories of output directories are build state or
+    /// support files and aren't copied. An error is returned if targets
+    /// produce files with the same name.
+    ///
+    /// Returns the paths of the copied files.
+    pub fn copy_to_artifact_dir(&self, dest_dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut sources = BTreeMap::new();
+
+        for target in &self.manifest.targets {
+            for artifact in &target.artifacts {
+                if artifact.path.contains('/') {
+                    continue;
+                }
+
+                if let Some((other, _)) = sources.insert(
+                    artifact.path.clone(),
+                    (target.name.clone(), target.output_path.join(&artifact.path)),
+                ) {
+                    return Err(anyhow!(
+                        "targets {} and {} both produce {}; they can't be copied to the same artifact directory",
+                        other,
+                        target.name,
+                        artifact.path
+                    ));
+                }
+            }
+        }
+
+        let manifest_name = self
+            .manifest_path
+            .file_name()
+            .ok_or_else(|| anyhow!("build manifest path has no file name"))?
+            .to_string_lossy()
+            .to_string();
+        if let Some((target, _)) = sources.get(&manifest_name) {
+            return Err(anyhow!(
+                "target {} produces {}, which conflicts with the build manifest",
+                target,
+                manifest_name
+            ));
+        }
+        sources.insert(
+            manifest_name,
+            ("build manifest".to_string(), self.manifest_path.clone()),
+        );
+
+        std::fs::create_dir_all(dest_dir)
+            .with_context(|| format!("creating {}", dest_dir.display()))?;
+
+        let mut res = Vec::new();
+        for (name, (_, source_path)) in sources {
+            let dest_path = dest_dir.join(&name);
+            std::fs::copy(&source_path, &dest_path)
+                .with_context(|| format!("copying {}", source_path.display()))?;
+            res.push(dest_path);
+        }
+
+        Ok(res)
+    }
+}
+
+/// A PyOxidizer project defined by a configuration file.
+#[derive(Clone)]
+pub struct Project {
+    logger: slog::Logger,
+    config_path: PathBuf,
+    target_triple: String,
+    release: bool,
+    verbose: bool,
+    jobs: usize,
+}
+
+impl Project {
+    /// Construct an instance from a configuration file.
+    ///
+    /// `path` can be the path to a configuration file or a directory. If a
+    /// directory, a `pyoxidizer.bzl` is searched for in it and its ancestors.
+    ///
+    /// The project is built for the default target triple in debug mode,
+    /// logging warnings to stdout. Use the `set_*` methods to change this.
+    pub fn from_config(path: &Path) -> Result<Self> {
+        let config_path = if path.is_dir() {
+            find_pyoxidizer_config_file(path).ok_or_else(|| {
+                anyhow!(
+                    "unable to find PyOxidizer config file at {}",
+                    path.display()
+                )
+            })?
+        } else if path.exists() {
+            path.to_path_buf()
+        } else {
+            return Err(anyhow!("{} does not exist", path.display()));
+        };
+
+        Ok(Project {
+            logger: LoggerContext::default().logger,
+            config_path,
+            target_triple: default_target()?,
+            release: false,
+            verbose: false,
+            jobs: 1,
+        })
+    }
+
+    /// Path of the configuration file defining this project.
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
+
+    /// Set the logger that build messages are written to.
+    pub fn set_logger(&mut self, logger: slog::Logger) {
+        self.logger = logger;
+    }
+
+    /// Set the Rust target triple to build for.
+    pub fn set_target_triple(&mut self, target_triple: &str) {
+        self.target_triple = target_triple.to_string();
+    }
+
+    /// Set whether to build in release mode.
+    pub fn set_release(&mut self, release: bool) {
+        self.release = release;
+    }
+
+    /// Set whether to emit verbose output.
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
+
+    /// Set the maximum number of targets to build concurrently.
+    ///
+    /// Defaults to 1, which builds targets sequentially.
+    pub fn set_jobs(&mut self, jobs: usize) {
+        self.jobs = std::cmp::max(jobs, 1);
+    }
+
+    fn evaluate(&self, resolve_targets: Option<Vec<String>>) -> Result<EvalResult> {
+        eval_starlark_config_file(
+            &self.logger,
+            &self.config_path,
+            &self.target_triple,
+            self.release,
+            self.verbose,
+            resolve_targets,
+            false,
+        )
+    }
+
+    /// Obtain the names of targets defined by the configuration file.
+    ///
+    /// Names are returned in the order they were registered.
+    pub fn targets(&self) -> Result<Vec<String>> {
+        let res = self.evaluate(Some(Vec::new()))?;
+
+        Ok(res.context.targets_order.clone())
+    }
+
+    /// Obtain the name of the default target, if there is one.
+    pub fn default_target(&self) -> Result<Option<String>> {
+        let res = self.evaluate(Some(Vec::new()))?;
+
+        Ok(res.context.default_target.clone())
+    }
+
+    /// Build a named target or the default target if `None`.
+    pub fn build(&self, target: Option<&str>) -> Result<BuildOutput> {
+        self.build_targets(target.map(|t| vec![t.to_string()]))
+    }
+
+    /// Build multiple named targets or the default target if `None`.
+    ///
+    /// Dependencies of requested targets are resolved before them. A build
+    /// manifest describing all built targets is written and returned.
+    ///
+    /// The configuration file is evaluated once, resolving every target
+    /// once. Requested targets are built once, after the requested targets
+    /// they depend on. If more than 1 job is allowed, targets not depending
+    /// on each other are built concurrently. See `build_resolved_targets()`.
+    pub fn build_targets(&self, targets: Option<Vec<String>>) -> Result<BuildOutput> {
+        let mut res = self.evaluate(targets)?;
+        enforce_scratch_max_size(&self.logger)?;
+
+        let targets = res.context.targets_to_resolve();
+        let depends = target_depends(&res.context, &targets)?;
+
+        self.build_resolved_targets(&mut res.context, &targets, &depends)?;
+
+        let manifest = BuildManifest::from_context(&res.context)?;
+        let manifest_path = manifest.write(&res.context)?;
+
+        build_output(manifest, manifest_path)
+    }
+
+    /// Build resolved targets once they no longer wait on targets they depend on.
+    ///
+    /// Starlark values can't be sent to other threads. So most targets are
+    /// built on this thread. The expensive builds of Python executables are
+    /// detached and run on other threads, up to `jobs` builds at once.
+    /// Every target writes to its own output directory.
+    ///
+    /// `depends` holds the targets in `targets` each target waits on. No
+    /// more targets are built once a build failed.
+    fn build_resolved_targets(
+        &self,
+        context: &mut EnvironmentContext,
+        targets: &[String],
+        depends: &BTreeMap<String, BTreeSet<String>>,
+    ) -> Result<()> {
+        if self.jobs > 1 && targets.len() > 1 {
+            slog::warn!(
+                self.logger,
+                "building {} targets with {} jobs",
+                targets.len(),
+                self.jobs
+            );
+        }
+
+        let mut pending: Vec<String> = Vec::new();
+        for target in targets {
+            if !pending.contains(target) {
+                pending.push(target.clone());
+            }
+        }
+
+        let mut built = BTreeSet::new();
+        let mut running = 0;
+        let mut errors = Vec::new();
+        let (sender, receiver) = mpsc::channel();
+
+        while !pending.is_empty() || running > 0 {
+            let ready = if errors.is_empty() && running < self.jobs {
+                pending
+                    .iter()
+                    .position(|target| depends[target].iter().all(|d| built.contains(d)))
+            } else {
+                None
+            };
+
+            if let Some(index) = ready {
+                let target = pending.remove(index);
+                slog::warn!(self.logger, "building target {}", target);
+
+                if let Some(build) = context.detach_resolved_target_build(&target)? {
+                    let build_context = context.build_context(&target)?;
+                    let sender = sender.clone();
+                    running += 1;
+
+                    std::thread::spawn(move || {
+                        let res =
+                            std::panic::catch_unwind(AssertUnwindSafe(|| build(&build_context)))
+                                .unwrap_or_else(|_| Err(anyhow!("target build thread panicked")));
+
+                        // The receiver is gone if this function returned early
+                        // on an error. There is nobody to report to then.
+                        let _ = sender.send((target, res));
+                    });
+                } else {
+                    match context.build_resolved_target(&target) {
+                        Ok(_) => {
+                            built.insert(target);
+                        }
+                        Err(e) => {
+                            errors.push((target, e));
+                        }
+                    }
+                }
+            } else if running > 0 {
+                let (target, res) = receiver.recv()?;
+                running -= 1;
+
+                match res {
+                    Ok(resolved) => {
+                        context.set_built_target(&target, resolved)?;
+                        built.insert(target);
+                    }
+                    Err(e) => {
+                        errors.push((target, e));
+                    }
+                }
+            } else {
+                // Targets left waiting on a failed build.
+                break;
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else if pending.len() + built.len() + errors.len() == 1 {
+            Err(errors.remove(0).1)
+        } else {
+            Err(anyhow!(
+                "failed to build targets:\n{}",
+                errors
+                    .iter()
+                    .map(|(target, e)| format!("{}: {}", target, e))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ))
+        }
+    }
+}
+
+/// Resolve the targets in `targets` each target of `targets` depends on.
+///
+/// Dependencies are followed through targets that aren't in `targets`.
+fn target_depends(
+    context: &EnvironmentContext,
+    targets: &[String],
+) -> Result<BTreeMap<String, BTreeSet<String>>> {
+    let mut res = BTreeMap::new();
+
+    for target in targets {
+        let mut seen = BTreeSet::new();
+        let mut stack = vec![target.clone()];
+
+        while let Some(name) = stack.pop() {
+            let entry = context
+                .targets
+                .get(&name)
+                .ok_or_else(|| anyhow!("target {} is not registered", name))?;
+
+            for depend in &entry.depends {
+                if seen.insert(depend.clone()) {
+                    stack.push(depend.clone());
+                }
+            }
+        }
+
+        res.insert(
+            target.clone(),
+            seen.into_iter().filter(|t| targets.contains(t)).collect(),
+        );
+    }
+
+    Ok(res)
+}
+
+/// Collect the phases timed during a build and write their trace next to
+/// the build manifest.
+fn build_output(manifest: BuildManifest, manifest_path: PathBuf) -> Result<BuildOutput> {
+    let timings = TimingReport::take();
+    let timings_path = manifest_path.with_file_name(TIMINGS_FILENAME);
+    timings.write_chrome_trace(&timings_path)?;
+
+    Ok(BuildOutput {
+        manifest,
+        manifest_path,
+        timings,
+        timings_path,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::testutil::*, slog::Drain};
+
+    #[test]
+    fn test_build_file_manifest() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+
+        let config_path = temp_dir.path().join("pyoxidizer.bzl");
+        std::fs::write(
+            &config_path,
+            "def make_files():\n    return FileManifest()\n\n\
+             def make_other():\n    return FileManifest()\n\n\
+             register_target('files', make_files, default=True)\n\
+             register_target('other', make_other)\n\
+             resolve_targets()\n",
+        )?;
+
+        assert!(Project::from_config(&temp_dir.path().join("missing.bzl")).is_err());
+
+        let mut project = Project::from_config(temp_dir.path())?;
+        project.set_logger(get_logger()?);
+        assert_eq!(project.config_path(), config_path.as_path());
+
+        assert_eq!(
+            project.targets()?,
+            vec!["files".to_string(), "other".to_string()]
+        );
+        assert_eq!(project.default_target()?, Some("files".to_string()));
+
+        let output = project.build(None)?;
+        assert!(output.manifest_path.exists());
+        assert_eq!(
+            output.timings_path,
+            output.manifest_path.with_file_name("build-timings.json")
+        );
+        assert!(output.timings_path.exists());
+        assert_eq!(output.manifest.targets.len(), 1);
+        assert_eq!(output.manifest.targets[0].name, "files");
+        assert_eq!(output.manifest.targets[0].target_type, "FileManifest");
+
+        let output = project.build(Some("other"))?;
+        assert_eq!(output.manifest.targets.len(), 1);
+        assert_eq!(output.manifest.targets[0].name, "other");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_copy_to_artifact_dir() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+        let path = temp_dir.path();
+
+        std::fs::create_dir_all(path.join("src").join("lib"))?;
+        std::fs::write(path.join("src").join("app.txt"), b"app")?;
+        std::fs::write(path.join("src").join("lib").join("data.txt"), b"data")?;
+
+        std::fs::write(
+            path.join("pyoxidizer.bzl"),
+            "def make_files():\n    return glob([CWD + '/src/**/*'], strip_prefix=CWD + '/src/')\n\n\
+             def make_other():\n    return glob([CWD + '/src/*'], strip_prefix=CWD + '/src/')\n\n\
+             set_output_layout('dist/{target}')\n\
+             register_target('files', make_files, default=True)\n\
+             register_target('other', make_other)\n\
+             resolve_targets()\n",
+        )?;
+
+        let mut project = Project::from_config(path)?;
+        project.set_logger(get_logger()?);
+
+        let output = project.build(None)?;
+        assert_eq!(
+            output.manifest.targets[0].output_path,
+            path.join("dist/files")
+        );
+        assert!(path.join("dist/files/lib/data.txt").is_file());
+
+        let artifact_dir = path.join("artifacts");
+        assert_eq!(
+            output.copy_to_artifact_dir(&artifact_dir)?,
+            vec![
+                artifact_dir.join("app.txt"),
+                artifact_dir.join("build-manifest.json")
+            ]
+        );
+
+        let output = project.build_targets(Some(vec!["files".to_string(), "other".to_string()]))?;
+        assert!(output.copy_to_artifact_dir(&artifact_dir).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_targets_parallel() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+
+        std::fs::write(
+            temp_dir.path().join("pyoxidizer.bzl"),
+            "def make_files():\n    return FileManifest()\n\n\
+             def make_other(files):\n    return FileManifest()\n\n\
+             def make_third():\n    return FileManifest()\n\n\
+             register_target('files', make_files, default=True)\n\
+             register_target('other', make_other, depends=['files'])\n\
+             register_target('third', make_third)\n\
+             resolve_targets()\n",
+        )?;
+
+        let mut project = Project::from_config(temp_dir.path())?;
+        project.set_logger(get_logger()?);
+        project.set_jobs(2);
+
+        let output = project.build_targets(Some(vec![
+            "third".to_string(),
+            "other".to_string(),
+            "files".to_string(),
+        ]))?;
+        assert!(output.manifest_path.exists());
+        assert_eq!(
+            output
+                .manifest
+                .targets
+                .iter()
+                .map(|t| t.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["files", "other", "third"]
+        );
+
+        assert!(project
+            .build_targets(Some(vec!["files".to_string(), "missing".to_string()]))
+            .is_err());
+
+        Ok(())
+    }
+
+    /// Records messages logged during a build.
+    struct CaptureDrain {
+        messages: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl slog::Drain for CaptureDrain {
+        type Ok = ();
+        type Err = slog::Never;
+
+        fn log(
+            &self,
+            record: &slog::Record,
+            _values: &slog::OwnedKVList,
+        ) -> std::result::Result<Self::Ok, Self::Err> {
+            self.messages.lock().unwrap().push(record.msg().to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_build_targets_shared_dependency() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+
+        std::fs::write(
+            temp_dir.path().join("pyoxidizer.bzl"),
+            "def make_base():\n    return FileManifest()\n\n\
+             def make_a(base):\n    return FileManifest()\n\n\
+             def make_b(base):\n    return FileManifest()\n\n\
+             register_target('base', make_base)\n\
+             register_target('a', make_a, depends=['base'], default=True)\n\
+             register_target('b', make_b, depends=['base'])\n\
+             resolve_targets()\n",
+        )?;
+
+        let messages = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+
+        let mut project = Project::from_config(temp_dir.path())?;
+        project.set_logger(slog::Logger::root(
+            CaptureDrain {
+                messages: messages.clone(),
+            }
+            .fuse(),
+            slog::o!(),
+        ));
+        project.set_jobs(2);
+
+        let output = project.build_targets(Some(vec![
+            "a".to_string(),
+            "b".to_string(),
+            "base".to_string(),
+        ]))?;
+        assert_eq!(
+            output
+                .manifest
+                .targets
+                .iter()
+                .map(|t| t.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["base", "a", "b"]
+        );
+
+        let messages = messages.lock().unwrap();
+        let count = |message: &str| messages.iter().filter(|m| *m == message).count();
+        assert_eq!(count("resolving target base"), 1);
+        assert_eq!(count("building target base"), 1);
+        assert_eq!(count("building target a"), 1);
+        assert_eq!(count("building target b"), 1);
+
+        Ok(())
+    }
+}
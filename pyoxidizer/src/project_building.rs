@@ -3,13 +3,22 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
-    crate::environment::{canonicalize_path, MINIMUM_RUST_VERSION},
-    crate::project_layout::initialize_project,
-    crate::py_packaging::binary::PythonBinaryBuilder,
+    crate::analyze::{find_manylinux_policy, find_manylinux_violations},
+    crate::app_packaging::resource::{FileContent, FileManifest},
+    crate::environment::canonicalize_path,
+    crate::progress::Progress,
+    crate::project_layout::{copy_rust_sources, initialize_project, write_custom_main_rs},
+    crate::py_packaging::binary::{LibpythonLinkMode, PythonBinaryBuilder},
+    crate::py_packaging::resource::ExtensionModuleData,
+    crate::rust_toolchain::{resolve_rust_toolchain, RustToolchain},
+    crate::sbom::SbomFormat,
+    crate::size_budget::{check_size_budget, resource_sizes, SizeBudget},
     crate::starlark::eval::{eval_starlark_config_file, EvalResult},
     crate::starlark::target::ResolvedTarget,
+    crate::timing,
     anyhow::{anyhow, Context, Result},
     slog::warn,
+    std::convert::TryFrom,
     std::env,
     std::fs::create_dir_all,
     std::path::{Path, PathBuf},
@@ -17,6 +26,378 @@ use {
 
 pub const HOST: &str = env!("HOST");
 
+/// Values accepted for a Cargo profile's `opt-level`.
+pub const RUST_OPT_LEVELS: &[&str] = &["0", "1", "2", "3", "s", "z"];
+
+/// Values accepted for the Windows subsystem of an executable.
+pub const WINDOWS_SUBSYSTEMS: &[&str] = &["console", "windows"];
+
+/// Application manifest embedded in Windows executables.
+///
+/// Declares the executable long path aware, so paths longer than `MAX_PATH`
+/// work on Windows 10 systems with long paths enabled, e.g. when
+/// applications are installed in deep user directories.
+pub const WINDOWS_APPLICATION_MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<assembly xmlns="urn:schemas-microsoft-com:asm.v1" manifestVersion="1.0">
+  <application xmlns="urn:schemas-microsoft-com:asm.v3">
+    <windowsSettings xmlns:ws2="http://schemas.microsoft.com/SMI/2016/WindowsSettings">
+      <ws2:longPathAware>true</ws2:longPathAware>
+    </windowsSettings>
+  </application>
+</assembly>
+"#;
+
+/// Whether a target triple is a WebAssembly System Interface target.
+///
+/// Support for these targets is experimental.
+pub fn is_wasi_target(target: &str) -> bool {
+    target.starts_with("wasm32-wasi")
+}
+
+/// Whether a target triple links against musl libc.
+pub fn is_musl_target(target: &str) -> bool {
+    target.ends_with("-linux-musl")
+}
+
+/// Whether an extension module filename suffix denotes a glibc build.
+///
+/// Extension modules from manylinux wheels have suffixes like
+/// `.cpython-37m-x86_64-linux-gnu.so`.
+pub fn is_glibc_extension_suffix(suffix: &str) -> bool {
+    suffix.contains("-linux-gnu")
+}
+
+/// Obtain the extension modules only available as shared libraries.
+///
+/// These can't be linked into executables, only loaded at run-time.
+fn shared_library_extension_modules(exe: &dyn PythonBinaryBuilder) -> Vec<ExtensionModuleData> {
+    exe.extension_module_datas()
+        .values()
+        .filter(|em| em.object_file_data.is_empty())
+        .cloned()
+        .collect()
+}
+
+/// Filename of the executable named `bin_name` built for a target.
+pub fn exe_filename(bin_name: &str, target: &str) -> String {
+    if target.contains("pc-windows") {
+        format!("{}.exe", bin_name)
+    } else if is_wasi_target(target) {
+        format!("{}.wasm", bin_name)
+    } else {
+        bin_name.to_string()
+    }
+}
+
+/// Ensure an executable can be built for a WASI target.
+///
+/// WebAssembly modules can't load shared libraries or use jemalloc, so
+/// everything must be statically linked and use the system allocator.
+pub fn verify_wasi_compatible(exe: &dyn PythonBinaryBuilder) -> Result<()> {
+    if exe.requires_jemalloc() {
+        return Err(anyhow!(
+            "jemalloc is not supported on WASI; use raw_allocator=\"system\""
+        ));
+    }
+
+    let shared: Vec<String> = shared_library_extension_modules(exe)
+        .into_iter()
+        .map(|em| em.name)
+        .collect();
+
+    if !shared.is_empty() {
+        return Err(anyhow!(
+            "extension modules only available as shared libraries can't be loaded on WASI: {}",
+            shared.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Ensure an executable can be built for a musl libc target.
+///
+/// musl executables are fully statically linked and can't load shared
+/// libraries, so every extension module must be linked from object files.
+/// Extension modules from manylinux wheels are reported separately, as they
+/// target glibc and would need to be rebuilt from source against musl anyway.
+pub fn verify_musl_compatible(exe: &dyn PythonBinaryBuilder) -> Result<()> {
+    let (glibc, other): (Vec<_>, Vec<_>) = shared_library_extension_modules(exe)
+        .into_iter()
+        .partition(|em| is_glibc_extension_suffix(&em.extension_file_suffix));
+
+    if !glibc.is_empty() {
+        return Err(anyhow!(
+            "extension modules built for glibc can't be used by musl executables: {}; \
+             these likely come from manylinux wheels, which only support glibc; \
+             build the packages from source in a musl environment instead",
+            glibc
+                .iter()
+                .map(|em| em.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    if !other.is_empty() {
+        return Err(anyhow!(
+            "extension modules only available as shared libraries can't be loaded by statically linked musl executables: {}",
+            other
+                .iter()
+                .map(|em| em.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Audit ELF binaries against the manylinux policy of build options.
+///
+/// `binaries` holds the name and data of each binary to audit.
+fn audit_linux_compat(
+    logger: &slog::Logger,
+    build_options: &RustBuildOptions,
+    binaries: &[(String, Vec<u8>)],
+) -> Result<()> {
+    let policy = match &build_options.linux_compat_level {
+        Some(level) => find_manylinux_policy(level)
+            .ok_or_else(|| anyhow!("unknown Linux compatibility level: {}", level))?,
+        None => return Ok(()),
+    };
+
+    let mut violations = Vec::new();
+
+    for (name, data) in binaries {
+        for violation in find_manylinux_violations(data, policy)
+            .with_context(|| format!("analyzing {}", name))?
+        {
+            violations.push(format!("{}: {}", name, violation));
+        }
+    }
+
+    if violations.is_empty() {
+        return Ok(());
+    }
+
+    if build_options.linux_compat_fail {
+        Err(anyhow!(
+            "binaries are not compatible with {}:\n{}",
+            policy.name,
+            violations.join("\n")
+        ))
+    } else {
+        for violation in violations {
+            warn!(logger, "not compatible with {}: {}", policy.name, violation);
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether debug info split from executables of a target is linked via `.gnu_debuglink`.
+fn uses_gnu_debuglink(target: &str) -> bool {
+    !target.contains("pc-windows") && !target.contains("apple") && !is_wasi_target(target)
+}
+
+/// Resolve the `strip` program that understands executables of a target.
+///
+/// The host's `strip` only understands executables of the host. When cross
+/// compiling, the target toolchain's `strip` is read from `STRIP_<target>`,
+/// like `cc` does for other tools.
+fn resolve_strip(target: &str) -> Option<String> {
+    for key in &[
+        format!("STRIP_{}", target),
+        format!("STRIP_{}", target.replace('-', "_")),
+    ] {
+        if let Ok(strip) = env::var(key) {
+            return Some(strip);
+        }
+    }
+
+    // Apple's `strip` understands every Apple architecture.
+    if target == HOST || (target.contains("-apple-") && HOST.contains("-apple-")) {
+        Some("strip".to_string())
+    } else {
+        None
+    }
+}
+
+/// Strip symbols from an executable built for a target.
+pub fn strip_executable(logger: &slog::Logger, exe_path: &Path, target: &str) -> Result<()> {
+    // MSVC keeps debug symbols in separate .pdb files. So there is nothing
+    // to strip on Windows. `strip` doesn't understand WebAssembly modules.
+    if target.contains("pc-windows") || is_wasi_target(target) {
+        return Ok(());
+    }
+
+    let strip = match resolve_strip(target) {
+        Some(strip) => strip,
+        None => {
+            warn!(
+                logger,
+                "not stripping {}: no strip known for {}; set STRIP_{} to the strip of its toolchain",
+                exe_path.display(),
+                target,
+                target.replace('-', "_")
+            );
+            return Ok(());
+        }
+    };
+
+    warn!(logger, "stripping {}", exe_path.display());
+
+    let status = std::process::Command::new(&strip)
+        .arg(exe_path)
+        .status()
+        .context("running strip")?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("strip failed"))
+    }
+}
+
+/// Run `objcopy` in a directory.
+fn run_objcopy(dir: &Path, args: &[&str]) -> Result<()> {
+    let status = std::process::Command::new("objcopy")
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .context("running objcopy")?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(anyhow!("objcopy failed"))
+    }
+}
+
+/// Write the debug info of a built executable to separate files.
+///
+/// Returns the paths of the written files and directories, which are next to
+/// the executable.
+fn split_debug_info(
+    logger: &slog::Logger,
+    exe_path: &Path,
+    bin_name: &str,
+    target: &str,
+) -> Result<Vec<PathBuf>> {
+    let dir = exe_path
+        .parent()
+        .ok_or_else(|| anyhow!("unable to resolve parent directory"))?;
+
+    let path = if target.contains("pc-windows") {
+        // Cargo names program databases after the crate, with underscores.
+        dir.join(format!("{}.pdb", bin_name.replace('-', "_")))
+    } else if target.contains("apple") {
+        let path = PathBuf::from(format!("{}.dSYM", exe_path.display()));
+        warn!(logger, "writing debug info to {}", path.display());
+
+        let status = std::process::Command::new("dsymutil")
+            .arg(exe_path)
+            .arg("-o")
+            .arg(&path)
+            .status()
+            .context("running dsymutil")?;
+
+        if !status.success() {
+            return Err(anyhow!("dsymutil failed"));
+        }
+
+        path
+    } else if is_wasi_target(target) {
+        warn!(logger, "debug info can't be split for {}", target);
+        return Ok(Vec::new());
+    } else {
+        let debug_filename = format!("{}.debug", exe_filename(bin_name, target));
+        let path = dir.join(&debug_filename);
+        warn!(logger, "writing debug info to {}", path.display());
+
+        run_objcopy(
+            dir,
+            &[
+                "--only-keep-debug",
+                &exe_filename(bin_name, target),
+                &debug_filename,
+            ],
+        )?;
+
+        path
+    };
+
+    if !path.exists() {
+        return Err(anyhow!("{} does not exist", path.display()));
+    }
+
+    Ok(vec![path])
+}
+
+/// Options controlling how the Rust project embedding Python is compiled.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RustBuildOptions {
+    /// `opt-level` of the Cargo profile. One of `RUST_OPT_LEVELS`.
+    pub opt_level: Option<String>,
+
+    /// Whether to enable link-time optimization.
+    pub lto: Option<bool>,
+
+    /// Whether to strip symbols from the built executable.
+    pub strip: bool,
+
+    /// Whether to write debug info to files next to the executable.
+    ///
+    /// These are `.pdb` files on Windows, `.dSYM` bundles on macOS, and
+    /// `.debug` files linked via `.gnu_debuglink` elsewhere.
+    pub split_debug_info: bool,
+
+    /// Extra flags to pass to `rustc`.
+    pub rustflags: Vec<String>,
+
+    /// Extra Cargo features to enable.
+    pub cargo_features: Vec<String>,
+
+    /// Handlebars template to render into the project's `main.rs`.
+    pub main_rs_template: Option<PathBuf>,
+
+    /// Directory of additional Rust source files to copy into the project.
+    pub rust_sources_path: Option<PathBuf>,
+
+    /// Format of a software bill of materials to write next to the executable.
+    pub sbom: Option<SbomFormat>,
+
+    /// Cargo home directory holding vendored crates.
+    ///
+    /// If set, Cargo runs in offline mode and only uses crates from this directory.
+    pub offline_cargo_home: Option<PathBuf>,
+
+    /// Directory to install the pinned Rust toolchain to.
+    ///
+    /// If not set, the Rust toolchain in `PATH` is used.
+    pub rust_toolchains_path: Option<PathBuf>,
+
+    /// Subsystem of executables built for Windows. One of `WINDOWS_SUBSYSTEMS`.
+    ///
+    /// Executables for the `windows` subsystem don't open a console window.
+    /// Ignored when not targeting Windows.
+    pub windows_subsystem: Option<String>,
+
+    /// Name of the manylinux policy Linux binaries are audited against.
+    ///
+    /// Ignored when not targeting glibc Linux.
+    pub linux_compat_level: Option<String>,
+
+    /// Whether violations of `linux_compat_level` fail the build.
+    ///
+    /// If not set, violations are reported as warnings.
+    pub linux_compat_fail: bool,
+
+    /// Size limits the built executable must fit in.
+    pub size_budget: SizeBudget,
+}
+
 /// Find a pyoxidizer.toml configuration file by walking directory ancestry.
 pub fn find_pyoxidizer_config_file(start_dir: &Path) -> Option<PathBuf> {
     for test_dir in start_dir.ancestors() {
@@ -70,11 +451,13 @@ pub fn find_pyoxidizer_config_file_env(logger: &slog::Logger, start_dir: &Path)
 
 /// Build an executable embedding Python using an existing Rust project.
 ///
-/// The path to the produced executable is returned.
+/// Returns the path to the produced executable and, if the interpreter loads
+/// resources from a file, the path to the resources data to install there.
 #[allow(clippy::too_many_arguments)]
 pub fn build_executable_with_rust_project(
     logger: &slog::Logger,
     project_path: &Path,
+    toolchain: &RustToolchain,
     bin_name: &str,
     exe: &dyn PythonBinaryBuilder,
     build_path: &Path,
@@ -82,23 +465,51 @@ pub fn build_executable_with_rust_project(
     target: &str,
     opt_level: &str,
     release: bool,
-) -> Result<PathBuf> {
+    build_options: &RustBuildOptions,
+) -> Result<(PathBuf, Option<PathBuf>, Vec<PathBuf>)> {
     create_dir_all(&artifacts_path)
         .with_context(|| "creating directory for PyOxidizer build artifacts")?;
 
+    if is_wasi_target(target) {
+        warn!(logger, "support for {} is experimental", target);
+        verify_wasi_compatible(exe)?;
+    }
+
+    if is_musl_target(target) {
+        verify_musl_compatible(exe)?;
+    }
+
+    if target.contains("-linux-gnu") && build_options.linux_compat_level.is_some() {
+        let extension_modules: Vec<(String, Vec<u8>)> = exe
+            .extension_module_datas()
+            .values()
+            .filter_map(|em| {
+                em.extension_data
+                    .as_ref()
+                    .map(|data| (em.file_name(), data.clone()))
+            })
+            .collect();
+
+        audit_linux_compat(logger, build_options, &extension_modules)?;
+    }
+
     // Derive and write the artifacts needed to build a binary embedding Python.
     let embedded_data = exe.as_embedded_python_binary_data(logger, opt_level)?;
-    embedded_data.write_files(&artifacts_path)?;
+    let paths = embedded_data.write_files(&artifacts_path)?;
+
+    let rust_version = toolchain.version()?;
 
-    let rust_version = rustc_version::version()?;
-    if rust_version.lt(&MINIMUM_RUST_VERSION) {
+    // Cargo only reads profile settings from the environment as of 1.43.
+    if (build_options.opt_level.is_some()
+        || build_options.lto.is_some()
+        || build_options.split_debug_info)
+        && rust_version.lt(&semver::Version::new(1, 43, 0))
+    {
         return Err(anyhow!(
-            "PyOxidizer requires Rust {}; version {} found",
-            *MINIMUM_RUST_VERSION,
+            "opt_level, lto, and split_debug_info build options require Rust 1.43; version {} found",
             rust_version
         ));
     }
-    warn!(logger, "building with Rust {}", rust_version);
 
     let target_base_path = build_path.join("target");
     let target_triple_base_path =
@@ -122,21 +533,39 @@ pub fn build_executable_with_rust_project(
         args.push("--release");
     }
 
+    if build_options.offline_cargo_home.is_some() {
+        args.push("--offline");
+    }
+
     args.push("--no-default-features");
     let mut features = vec!["build-mode-prebuilt-artifacts"];
 
-    // If we have a real libpython, let cpython crate link against it. Otherwise
+    // If we link a real libpython, let cpython crate link against it. Otherwise
     // leave symbols unresolved, as we'll provide them.
-    features.push(if embedded_data.linking_info.libpython_filename.is_some() {
-        "cpython-link-default"
-    } else {
-        "cpython-link-unresolved-static"
-    });
+    features.push(
+        if embedded_data.linking_info.link_mode == LibpythonLinkMode::Dynamic {
+            "cpython-link-default"
+        } else {
+            "cpython-link-unresolved-static"
+        },
+    );
 
     if exe.requires_jemalloc() {
         features.push("jemalloc");
     }
 
+    if exe.config().update.is_some() {
+        features.push("update");
+    }
+
+    if exe.config().splash_image.is_some() {
+        features.push("splash");
+    }
+
+    for feature in &build_options.cargo_features {
+        features.push(feature.as_str());
+    }
+
     let features = features.join(" ");
 
     if !features.is_empty() {
@@ -149,6 +578,17 @@ pub fn build_executable_with_rust_project(
         "PYOXIDIZER_ARTIFACT_DIR",
         artifacts_path.display().to_string(),
     ));
+
+    if let Some(cargo_home) = &build_options.offline_cargo_home {
+        if !cargo_home.is_dir() {
+            return Err(anyhow!(
+                "vendored crates not found at {}; run `pyoxidizer vendor` first",
+                cargo_home.display()
+            ));
+        }
+
+        envs.push(("CARGO_HOME", cargo_home.display().to_string()));
+    }
     envs.push(("PYOXIDIZER_REUSE_ARTIFACTS", "1".to_string()));
 
     // Set PYTHON_SYS_EXECUTABLE so python3-sys uses our distribution's Python to configure
@@ -178,13 +618,119 @@ pub fn build_executable_with_rust_project(
         }
     }
 
+    let (opt_level_var, lto_var, debug_var) = if release {
+        (
+            "CARGO_PROFILE_RELEASE_OPT_LEVEL",
+            "CARGO_PROFILE_RELEASE_LTO",
+            "CARGO_PROFILE_RELEASE_DEBUG",
+        )
+    } else {
+        (
+            "CARGO_PROFILE_DEV_OPT_LEVEL",
+            "CARGO_PROFILE_DEV_LTO",
+            "CARGO_PROFILE_DEV_DEBUG",
+        )
+    };
+
+    if let Some(level) = &build_options.opt_level {
+        envs.push((opt_level_var, level.clone()));
+    }
+
+    if let Some(lto) = build_options.lto {
+        envs.push((lto_var, lto.to_string()));
+    }
+
+    // Release builds have no debug info to split by default.
+    if build_options.split_debug_info {
+        envs.push((debug_var, "true".to_string()));
+    }
+
+    let mut rustflags = Vec::new();
+
+    if let Ok(flags) = std::env::var("RUSTFLAGS") {
+        rustflags.push(flags);
+    }
+
+    // The CRT linkage of the Rust code needs to match what we compiled
+    // libpython and its support code with.
+    if target.contains("pc-windows-msvc") {
+        rustflags.push(exe.windows_crt_linkage().rustflags().to_string());
+    }
+
+    // Libraries are statically linked on musl by default. Making it explicit
+    // guards against toolchains configured otherwise.
+    if is_musl_target(target) {
+        rustflags.push("-C target-feature=+crt-static".to_string());
+    }
+
+    // Paths of the directories we build in would otherwise be embedded in
+    // the binary, e.g. in panic messages, making builds non-reproducible.
+    for (path, replacement) in &[
+        (project_path, "project"),
+        (artifacts_path, "artifacts"),
+        (build_path, "build"),
+    ] {
+        let path = path.display().to_string();
+
+        // RUSTFLAGS is split on whitespace.
+        if !path.contains(char::is_whitespace) {
+            rustflags.push(format!(
+                "--remap-path-prefix={}=/pyoxidizer/{}",
+                path, replacement
+            ));
+        }
+    }
+
+    if target.contains("pc-windows")
+        && build_options.windows_subsystem == Some("windows".to_string())
+    {
+        if target.contains("pc-windows-msvc") {
+            rustflags.push("-C link-arg=/SUBSYSTEM:WINDOWS".to_string());
+            // Keep main() as the entry point instead of WinMain().
+            rustflags.push("-C link-arg=/ENTRY:mainCRTStartup".to_string());
+        } else {
+            rustflags.push("-C link-arg=-mwindows".to_string());
+        }
+    }
+
+    // link.exe merges the manifest with the one it generates.
+    if target.contains("pc-windows-msvc") {
+        let manifest_path = project_path.join("pyoxidizer.exe.manifest");
+        std::fs::write(&manifest_path, WINDOWS_APPLICATION_MANIFEST)
+            .with_context(|| format!("writing {}", manifest_path.display()))?;
+
+        let manifest_path = manifest_path.display().to_string();
+        if manifest_path.contains(char::is_whitespace) {
+            warn!(
+                logger,
+                "not embedding application manifest: {} contains whitespace", manifest_path
+            );
+        } else {
+            rustflags.push("-C link-arg=/MANIFEST:EMBED".to_string());
+            rustflags.push(format!("-C link-arg=/MANIFESTINPUT:{}", manifest_path));
+        }
+    }
+
+    rustflags.extend(build_options.rustflags.iter().cloned());
+
+    if !rustflags.is_empty() {
+        envs.push(("RUSTFLAGS", rustflags.join(" ")));
+    }
+
     // static-nobundle link kind requires nightly Rust compiler until
     // https://github.com/rust-lang/rust/issues/37403 is resolved.
     if cfg!(windows) {
         envs.push(("RUSTC_BOOTSTRAP", "1".to_string()));
     }
 
-    let status = std::process::Command::new("cargo")
+    envs.extend(toolchain.cargo_envs());
+
+    let _phase = timing::phase("cargo build");
+
+    // Linking can take minutes without cargo printing anything.
+    let progress = Progress::external(logger, "building with cargo");
+
+    let status = std::process::Command::new(&toolchain.cargo_path)
         .args(args)
         .current_dir(&project_path)
         .envs(envs)
@@ -194,11 +740,9 @@ pub fn build_executable_with_rust_project(
         return Err(anyhow!("cargo build failed"));
     }
 
-    let exe_name = if target.contains("pc-windows") {
-        format!("{}.exe", bin_name)
-    } else {
-        bin_name.to_string()
-    };
+    progress.finish();
+
+    let exe_name = exe_filename(bin_name, target);
 
     let exe_path = target_triple_base_path.join(&exe_name);
 
@@ -206,12 +750,53 @@ pub fn build_executable_with_rust_project(
         return Err(anyhow!("{} does not exist", exe_path.display()));
     }
 
-    Ok(exe_path)
+    let debug_paths = if build_options.split_debug_info {
+        split_debug_info(logger, &exe_path, bin_name, target)?
+    } else {
+        Vec::new()
+    };
+
+    if build_options.strip {
+        strip_executable(logger, &exe_path, target)?;
+    }
+
+    // The link is added after stripping, which would otherwise remove it.
+    if build_options.split_debug_info && uses_gnu_debuglink(target) {
+        run_objcopy(
+            &target_triple_base_path,
+            &[
+                &format!("--add-gnu-debuglink={}.debug", exe_name),
+                &exe_name,
+            ],
+        )?;
+    }
+
+    if target.contains("-linux-gnu") && build_options.linux_compat_level.is_some() {
+        let data =
+            std::fs::read(&exe_path).with_context(|| format!("reading {}", exe_path.display()))?;
+
+        audit_linux_compat(logger, build_options, &[(exe_name.clone(), data)])?;
+    }
+
+    Ok((exe_path, paths.resources_file, debug_paths))
+}
+
+/// A Python executable built using a temporary Rust project.
+pub struct BuiltPythonExecutable {
+    /// Filename of the executable.
+    pub filename: String,
+
+    /// Binary data constituting the executable.
+    pub data: Vec<u8>,
+
+    /// Resources data to install where the interpreter loads resources from, if a file.
+    pub resources_data: Option<Vec<u8>>,
+
+    /// Files holding debug info split from the executable, relative to its directory.
+    pub debug_files: FileManifest,
 }
 
 /// Build a Python executable using a temporary Rust project.
-///
-/// Returns the binary data constituting the built executable.
 pub fn build_python_executable(
     logger: &slog::Logger,
     bin_name: &str,
@@ -219,22 +804,49 @@ pub fn build_python_executable(
     target: &str,
     opt_level: &str,
     release: bool,
-) -> Result<(String, Vec<u8>)> {
+    build_options: &RustBuildOptions,
+) -> Result<BuiltPythonExecutable> {
     let env = crate::environment::resolve_environment()?;
     let pyembed_location = env.as_pyembed_location();
 
-    let temp_dir = tempdir::TempDir::new("pyoxidizer")?;
+    let toolchain = resolve_rust_toolchain(
+        logger,
+        build_options
+            .rust_toolchains_path
+            .as_ref()
+            .map(|p| p.as_path()),
+        HOST,
+        target,
+        build_options.offline_cargo_home.is_none(),
+    )?;
+
+    let temp_dir = crate::scratch::scratch_dir("pyoxidizer")?;
 
     // Directory needs to have name of project.
     let project_path = temp_dir.path().join(bin_name);
     let build_path = temp_dir.path().join("build");
     let artifacts_path = temp_dir.path().join("artifacts");
 
-    initialize_project(&project_path, &pyembed_location, None, &[])?;
+    initialize_project(
+        &project_path,
+        &toolchain.cargo_path,
+        &pyembed_location,
+        None,
+        &[],
+    )?;
+
+    if let Some(path) = &build_options.rust_sources_path {
+        copy_rust_sources(path, &project_path)?;
+    }
+
+    if let Some(path) = &build_options.main_rs_template {
+        write_custom_main_rs(&project_path.join("src").join("main.rs"), path, bin_name)?;
+    }
 
-    let exe_path = build_executable_with_rust_project(
+    let (exe_path, resources_path, debug_paths) = build_executable_with_rust_project(
         logger,
         &project_path,
+        &toolchain,
         bin_name,
         exe,
         &build_path,
@@ -242,12 +854,228 @@ pub fn build_python_executable(
         target,
         opt_level,
         release,
+        build_options,
     )?;
 
     let data = std::fs::read(&exe_path)?;
     let filename = exe_path.file_name().unwrap().to_string_lossy().to_string();
 
-    Ok((filename, data))
+    let resources_data = match resources_path {
+        Some(path) => Some(std::fs::read(&path)?),
+        None => None,
+    };
+
+    if !build_options.size_budget.is_empty() {
+        let total = data.len() + resources_data.as_ref().map_or(0, |d| d.len());
+        check_size_budget(
+            &build_options.size_budget,
+            total as u64,
+            &resource_sizes(exe)?,
+        )?;
+    }
+
+    let mut debug_files = FileManifest::default();
+    for path in debug_paths {
+        let parent = path
+            .parent()
+            .ok_or_else(|| anyhow!("unable to resolve parent directory"))?;
+
+        // .dSYM bundles are directories.
+        for entry in walkdir::WalkDir::new(&path) {
+            let entry = entry?;
+
+            if entry.file_type().is_file() {
+                debug_files.add_file(
+                    entry.path().strip_prefix(parent)?,
+                    &FileContent::try_from(entry.path())?,
+                )?;
+            }
+        }
+    }
+
+    Ok(BuiltPythonExecutable {
+        filename,
+        data,
+        resources_data,
+        debug_files,
+    })
+}
+
+/// Download the crates needed to build executables to a Cargo home directory.
+///
+/// The Rust toolchain is resolved, installing the pinned toolchain into
+/// `rust_toolchains_path` if defined, and verified to be able to build for
+/// `target`. Crates of the project PyOxidizer generates for executables are
+/// fetched, as are those of the Rust project at `project_path`, if it has a
+/// `Cargo.toml`.
+pub fn vendor_rust_dependencies(
+    logger: &slog::Logger,
+    project_path: &Path,
+    rust_toolchains_path: Option<&Path>,
+    target: &str,
+    cargo_home: &Path,
+) -> Result<()> {
+    let toolchain = resolve_rust_toolchain(logger, rust_toolchains_path, HOST, target, true)?;
+
+    let env = crate::environment::resolve_environment()?;
+    let pyembed_location = env.as_pyembed_location();
+
+    let temp_dir = crate::scratch::scratch_dir("pyoxidizer")?;
+    let generated_path = temp_dir.path().join("vendor");
+
+    initialize_project(
+        &generated_path,
+        &toolchain.cargo_path,
+        &pyembed_location,
+        None,
+        &[],
+    )?;
+
+    let mut projects = vec![generated_path];
+    if project_path.join("Cargo.toml").exists() {
+        projects.push(project_path.to_path_buf());
+    }
+
+    create_dir_all(cargo_home)?;
+
+    for path in projects {
+        warn!(logger, "fetching crates of {}", path.display());
+
+        let status = std::process::Command::new(&toolchain.cargo_path)
+            .args(&["fetch", "--target", target])
+            .current_dir(&path)
+            .env("CARGO_HOME", cargo_home)
+            .envs(toolchain.cargo_envs())
+            .status()?;
+
+        if !status.success() {
+            return Err(anyhow!("cargo fetch failed"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Artifacts allowing an existing Rust crate to embed Python.
+#[derive(Clone, Debug)]
+pub struct CargoArtifacts {
+    /// Directory containing files needed to link Python.
+    pub artifacts_path: PathBuf,
+
+    /// Rust source file defining `pyoxidizer_build()` for use in build scripts.
+    pub build_rs_path: PathBuf,
+
+    /// Features of the `pyembed` crate that must be enabled.
+    pub pyembed_features: Vec<String>,
+
+    /// Environment variables that must be set when invoking `cargo`.
+    pub cargo_env: Vec<(String, String)>,
+
+    /// Files and directories written to the destination directory.
+    pub files: Vec<PathBuf>,
+}
+
+/// Write artifacts for embedding Python in an existing Rust crate.
+///
+/// Instead of building an executable from a temporary Rust project, this
+/// writes files into `dest_dir` that an existing binary crate depending on
+/// `pyembed` (with its default `build-mode-standalone` feature) can consume
+/// from its build script:
+///
+/// * `artifacts/` holds libraries and resources to embed.
+/// * `pyoxidizer_build.rs` defines a `pyoxidizer_build()` function which
+///   emits the `cargo:` lines to link Python and defines the
+///   `PYOXIDIZER_DEFAULT_PYTHON_CONFIG_RS` environment variable.
+/// * `pyembed_features.txt` lists the `pyembed` features to enable.
+/// * `cargo_env.txt` lists `KEY=VALUE` environment variables to set when
+///   running `cargo`.
+pub fn write_cargo_artifacts(
+    logger: &slog::Logger,
+    exe: &dyn PythonBinaryBuilder,
+    opt_level: &str,
+    dest_dir: &Path,
+) -> Result<CargoArtifacts> {
+    let artifacts_path = dest_dir.join("artifacts");
+    create_dir_all(&artifacts_path)
+        .with_context(|| "creating directory for PyOxidizer build artifacts")?;
+    let artifacts_path = canonicalize_path(&artifacts_path)?;
+
+    let embedded_data = exe.as_embedded_python_binary_data(logger, opt_level)?;
+    let paths = embedded_data.write_files(&artifacts_path)?;
+
+    let mut lines = embedded_data.linking_info.cargo_metadata.clone();
+    lines.push(format!(
+        "cargo:rustc-link-search=native={}",
+        artifacts_path.display()
+    ));
+    lines.push(format!(
+        "cargo:rustc-env=PYOXIDIZER_DEFAULT_PYTHON_CONFIG_RS={}",
+        paths.config_rs.display()
+    ));
+    lines.push(format!(
+        "cargo:rerun-if-changed={}",
+        paths.cargo_metadata.display()
+    ));
+
+    let mut build_rs = String::new();
+    build_rs.push_str("// Generated by PyOxidizer. Do not edit.\n");
+    build_rs.push_str("//\n");
+    build_rs.push_str("// include!() this file from a build script and call\n");
+    build_rs.push_str("// pyoxidizer_build() from its main().\n\n");
+    build_rs.push_str("#[allow(dead_code)]\n");
+    build_rs.push_str("fn pyoxidizer_build() {\n");
+    for line in lines {
+        build_rs.push_str(&format!("    println!(\"{{}}\", {:?});\n", line));
+    }
+    build_rs.push_str("}\n");
+
+    let build_rs_path = dest_dir.join("pyoxidizer_build.rs");
+    std::fs::write(&build_rs_path, build_rs)
+        .with_context(|| format!("writing {}", build_rs_path.display()))?;
+
+    let mut pyembed_features = vec![if embedded_data.linking_info.link_mode
+        == LibpythonLinkMode::Dynamic
+    {
+        "cpython-link-default".to_string()
+    } else {
+        "cpython-link-unresolved-static".to_string()
+    }];
+    if exe.requires_jemalloc() {
+        pyembed_features.push("jemalloc".to_string());
+    }
+    if exe.config().update.is_some() {
+        pyembed_features.push("update".to_string());
+    }
+    if exe.config().splash_image.is_some() {
+        pyembed_features.push("splash".to_string());
+    }
+
+    let features_path = dest_dir.join("pyembed_features.txt");
+    std::fs::write(&features_path, pyembed_features.join("\n"))?;
+
+    // python3-sys uses this Python to configure itself.
+    let cargo_env = vec![(
+        "PYTHON_SYS_EXECUTABLE".to_string(),
+        exe.python_exe_path().display().to_string(),
+    )];
+
+    let env_path = dest_dir.join("cargo_env.txt");
+    std::fs::write(
+        &env_path,
+        cargo_env
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    )?;
+
+    Ok(CargoArtifacts {
+        artifacts_path: artifacts_path.clone(),
+        build_rs_path: build_rs_path.clone(),
+        pyembed_features,
+        cargo_env,
+        files: vec![artifacts_path, build_rs_path, features_path, env_path],
+    })
 }
 
 /// Build artifacts needed by the pyembed crate.
@@ -496,12 +1324,54 @@ mod tests {
         crate::testutil::*,
     };
 
+    #[test]
+    fn test_exe_filename() {
+        assert_eq!(exe_filename("myapp", "x86_64-unknown-linux-gnu"), "myapp");
+        assert_eq!(exe_filename("myapp", "x86_64-pc-windows-msvc"), "myapp.exe");
+        assert_eq!(exe_filename("myapp", "wasm32-wasi"), "myapp.wasm");
+    }
+
+    #[test]
+    fn test_resolve_strip() {
+        assert_eq!(resolve_strip(HOST), Some("strip".to_string()));
+        assert_eq!(resolve_strip("riscv64gc-unknown-none-elf"), None);
+    }
+
+    #[test]
+    fn test_uses_gnu_debuglink() {
+        assert!(uses_gnu_debuglink("x86_64-unknown-linux-gnu"));
+        assert!(!uses_gnu_debuglink("x86_64-pc-windows-msvc"));
+        assert!(!uses_gnu_debuglink("x86_64-apple-darwin"));
+        assert!(!uses_gnu_debuglink("wasm32-wasi"));
+    }
+
+    #[test]
+    fn test_is_glibc_extension_suffix() {
+        assert!(is_glibc_extension_suffix(
+            ".cpython-37m-x86_64-linux-gnu.so"
+        ));
+        assert!(!is_glibc_extension_suffix(
+            ".cpython-37m-x86_64-linux-musl.so"
+        ));
+        assert!(!is_glibc_extension_suffix(".so"));
+        assert!(is_musl_target("x86_64-unknown-linux-musl"));
+        assert!(!is_musl_target("x86_64-unknown-linux-gnu"));
+    }
+
     #[test]
     fn test_empty_project() -> Result<()> {
         let logger = get_logger()?;
         let pre_built = get_standalone_executable_builder(&logger)?;
 
-        build_python_executable(&logger, "myapp", &pre_built, env!("HOST"), "0", false)?;
+        build_python_executable(
+            &logger,
+            "myapp",
+            &pre_built,
+            env!("HOST"),
+            "0",
+            false,
+            &RustBuildOptions::default(),
+        )?;
 
         Ok(())
     }
@@ -4,7 +4,7 @@
 
 //! Handle file layout of PyOxidizer projects.
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use handlebars::Handlebars;
 use lazy_static::lazy_static;
 use serde::Serialize;
@@ -14,6 +14,7 @@ use std::path::{Path, PathBuf};
 
 use crate::environment::{PyOxidizerSource, BUILD_GIT_COMMIT, PYOXIDIZER_VERSION};
 use crate::py_packaging::fsscan::walk_tree_files;
+use crate::py_packaging::zipapp::parse_entry_point;
 
 lazy_static! {
     static ref HANDLEBARS: Handlebars = {
@@ -31,11 +32,50 @@ lazy_static! {
                 include_str!("templates/new-pyoxidizer.bzl"),
             )
             .unwrap();
+        handlebars
+            .register_template_string(
+                "new-pyoxidizer-batch.bzl",
+                include_str!("templates/new-pyoxidizer-batch.bzl"),
+            )
+            .unwrap();
+        handlebars
+            .register_template_string(
+                "new-pyoxidizer-cli.bzl",
+                include_str!("templates/new-pyoxidizer-cli.bzl"),
+            )
+            .unwrap();
+        handlebars
+            .register_template_string(
+                "new-pyoxidizer-flask.bzl",
+                include_str!("templates/new-pyoxidizer-flask.bzl"),
+            )
+            .unwrap();
+        handlebars
+            .register_template_string(
+                "new-pyoxidizer-pyqt.bzl",
+                include_str!("templates/new-pyoxidizer-pyqt.bzl"),
+            )
+            .unwrap();
+        handlebars
+            .register_template_string("build-app.bzl", include_str!("templates/build-app.bzl"))
+            .unwrap();
 
         handlebars
     };
 }
 
+/// Templates for new configuration files and the applications they are for.
+pub const CONFIG_TEMPLATES: &[(&str, &str)] = &[
+    (
+        "default",
+        "a Python REPL, with comments describing common settings",
+    ),
+    ("batch", "a data science batch job using NumPy and pandas"),
+    ("cli", "a command line tool"),
+    ("flask", "a web service, such as a Flask application"),
+    ("pyqt", "a Qt GUI application using PyQt5"),
+];
+
 #[derive(Serialize)]
 struct PythonDistribution {
     build_target: String,
@@ -54,7 +94,9 @@ struct TemplateData {
 
     python_distributions: Vec<PythonDistribution>,
     program_name: Option<String>,
+    module_name: Option<String>,
     code: Option<String>,
+    run_module: Option<String>,
     pip_install_simple: Vec<String>,
 }
 
@@ -69,7 +111,9 @@ impl TemplateData {
             pyoxidizer_git_tag: None,
             python_distributions: Vec::new(),
             program_name: None,
+            module_name: None,
             code: None,
+            run_module: None,
             pip_install_simple: Vec::new(),
         }
     }
@@ -136,18 +180,92 @@ pub fn write_new_main_rs(path: &Path) -> Result<()> {
     Ok(())
 }
 
-/// Writes default PyOxidizer config files into a project directory.
-pub fn write_new_pyoxidizer_config_file(
-    project_dir: &Path,
+/// Variables available to user-provided main.rs templates.
+#[derive(Serialize)]
+struct MainRsTemplateData {
+    program_name: String,
+    pyoxidizer_version: String,
+    python_config_rs_env: String,
+    include_default_python_config: String,
+}
+
+/// Write a main.rs file rendered from a user-provided Handlebars template.
+///
+/// Templates can reference `program_name`, `pyoxidizer_version`,
+/// `python_config_rs_env`, and `include_default_python_config`. The latter
+/// expands to the Rust statement defining `default_python_config()`.
+pub fn write_custom_main_rs(path: &Path, template_path: &Path, program_name: &str) -> Result<()> {
+    let template = std::fs::read_to_string(template_path)
+        .with_context(|| format!("reading main.rs template {}", template_path.display()))?;
+
+    let mut handlebars = Handlebars::new();
+    // We're emitting Rust source code, not HTML.
+    handlebars.register_escape_fn(handlebars::no_escape);
+
+    let data = MainRsTemplateData {
+        program_name: program_name.to_string(),
+        pyoxidizer_version: PYOXIDIZER_VERSION.to_string(),
+        python_config_rs_env: "PYOXIDIZER_DEFAULT_PYTHON_CONFIG_RS".to_string(),
+        include_default_python_config: "include!(env!(\"PYOXIDIZER_DEFAULT_PYTHON_CONFIG_RS\"));"
+            .to_string(),
+    };
+
+    let t = handlebars
+        .render_template(&template, &data)
+        .with_context(|| format!("rendering main.rs template {}", template_path.display()))?;
+
+    println!("writing {}", path.display());
+    std::fs::write(path, t)?;
+
+    Ok(())
+}
+
+/// Copy a directory of Rust source files into a project's `src` directory.
+///
+/// Relative paths are preserved. Files in `source_dir` replace files of the
+/// same name, including `main.rs`.
+pub fn copy_rust_sources(source_dir: &Path, project_path: &Path) -> Result<()> {
+    let src_path = project_path.join("src");
+
+    for f in walk_tree_files(source_dir) {
+        let rel_path = f
+            .path()
+            .strip_prefix(source_dir)
+            .expect("unable to strip prefix");
+        let dest_path = src_path.join(rel_path);
+
+        if let Some(parent) = dest_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        println!("copying {}", f.path().display());
+        std::fs::copy(f.path(), &dest_path).with_context(|| {
+            format!("copying {} to {}", f.path().display(), dest_path.display())
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Render a new PyOxidizer config file from a template in `CONFIG_TEMPLATES`.
+pub fn render_new_pyoxidizer_config_file(
+    template: &str,
     name: &str,
     code: Option<&str>,
     pip_install: &[&str],
-) -> Result<()> {
-    let path = project_dir.join("pyoxidizer.bzl");
+) -> Result<String> {
+    let template_name = match template {
+        "default" => "new-pyoxidizer.bzl".to_string(),
+        _ if CONFIG_TEMPLATES.iter().any(|(t, _)| *t == template) => {
+            format!("new-pyoxidizer-{}.bzl", template)
+        }
+        _ => return Err(anyhow!("unknown config template: {}", template)),
+    };
 
     let mut data = TemplateData::new();
     populate_template_data(&mut data);
     data.program_name = Some(name.to_string());
+    data.module_name = Some(name.replace("-", "_"));
 
     if let Some(code) = code {
         // Replace " with \" to work around
@@ -157,7 +275,22 @@ pub fn write_new_pyoxidizer_config_file(
 
     data.pip_install_simple = pip_install.iter().map(|v| (*v).to_string()).collect();
 
-    let t = HANDLEBARS.render("new-pyoxidizer.bzl", &data)?;
+    Ok(HANDLEBARS.render(&template_name, &data)?)
+}
+
+/// Writes PyOxidizer config files into a project directory.
+///
+/// `template` is the name of a template in `CONFIG_TEMPLATES`.
+pub fn write_new_pyoxidizer_config_file(
+    project_dir: &Path,
+    name: &str,
+    template: &str,
+    code: Option<&str>,
+    pip_install: &[&str],
+) -> Result<()> {
+    let path = project_dir.join("pyoxidizer.bzl");
+
+    let t = render_new_pyoxidizer_config_file(template, name, code, pip_install)?;
 
     println!("writing {}", path.to_str().unwrap());
     let mut fh = std::fs::File::create(path)?;
@@ -166,6 +299,52 @@ pub fn write_new_pyoxidizer_config_file(
     Ok(())
 }
 
+/// Render a string as a Starlark string literal.
+fn starlark_str(value: &str) -> String {
+    let mut literal = String::with_capacity(value.len() + 2);
+    literal.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            c => literal.push(c),
+        }
+    }
+
+    literal.push('"');
+    literal
+}
+
+/// Render a PyOxidizer config file for a pip installable application.
+///
+/// `entry_point` is either a module to run as `__main__` or a
+/// `module:callable` string naming a function to call.
+pub fn render_build_app_config(name: &str, package: &str, entry_point: &str) -> Result<String> {
+    let (module, function) = parse_entry_point(entry_point)?;
+
+    // The template inserts values verbatim, so they are rendered as string
+    // literals here.
+    let mut data = TemplateData::new();
+    populate_template_data(&mut data);
+    data.program_name = Some(starlark_str(name));
+    data.pip_install_simple = vec![starlark_str(package)];
+
+    if let Some(function) = function {
+        data.code = Some(starlark_str(&format!(
+            "import sys; import {}; sys.exit({}.{}())",
+            module, module, function
+        )));
+    } else {
+        data.run_module = Some(starlark_str(&module));
+    }
+
+    Ok(HANDLEBARS.render("build-app.bzl", &data)?)
+}
+
 /// Add PyOxidizer to an existing Rust project on the filesystem.
 ///
 /// The target directory must not already have PyOxidizer files. This
@@ -255,6 +434,8 @@ pub fn update_new_cargo_toml(path: &Path, pyembed_location: &PyembedLocation) ->
     content.push_str("[features]\n");
     content.push_str("default = [\"build-mode-pyoxidizer-exe\"]\n");
     content.push_str("jemalloc = [\"jemallocator-global\", \"pyembed/jemalloc\"]\n");
+    content.push_str("update = [\"pyembed/update\"]\n");
+    content.push_str("splash = [\"pyembed/splash\"]\n");
     content.push_str("build-mode-pyoxidizer-exe = [\"pyembed/build-mode-pyoxidizer-exe\"]\n");
     content
         .push_str("build-mode-prebuilt-artifacts = [\"pyembed/build-mode-prebuilt-artifacts\"]\n");
@@ -271,14 +452,15 @@ pub fn update_new_cargo_toml(path: &Path, pyembed_location: &PyembedLocation) ->
 /// Initialize a new Rust project using PyOxidizer.
 ///
 /// The created binary application will have the name of the final
-/// path component.
+/// path component. `cargo_path` is the `cargo` executable used to create it.
 pub fn initialize_project(
     project_path: &Path,
+    cargo_path: &Path,
     pyembed_location: &PyembedLocation,
     code: Option<&str>,
     pip_install: &[&str],
 ) -> Result<()> {
-    let status = std::process::Command::new("cargo")
+    let status = std::process::Command::new(cargo_path)
         .arg("init")
         .arg("--bin")
         .arg(project_path)
@@ -294,7 +476,83 @@ pub fn initialize_project(
     update_new_cargo_toml(&path.join("Cargo.toml"), pyembed_location)?;
     write_new_build_rs(&path.join("build.rs"))?;
     write_new_main_rs(&path.join("src").join("main.rs"))?;
-    write_new_pyoxidizer_config_file(&path, &name, code, pip_install)?;
+    write_new_pyoxidizer_config_file(&path, &name, "default", code, pip_install)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::starlark::eval::eval_starlark_config_source, crate::testutil::*};
+
+    #[test]
+    fn test_config_templates() -> Result<()> {
+        let logger = get_logger()?;
+        let config_path = std::env::current_dir()?.join("pyoxidizer.bzl");
+
+        for (template, _) in CONFIG_TEMPLATES {
+            let source = render_new_pyoxidizer_config_file(template, "my-app", None, &["six"])?;
+
+            // Evaluate without resolving targets, which would build them.
+            let res = eval_starlark_config_source(
+                &logger,
+                &config_path,
+                &source,
+                crate::project_building::HOST,
+                false,
+                false,
+                Some(Vec::new()),
+                false,
+            )?;
+
+            assert_eq!(res.context.config_version, 2, "{}", template);
+            assert!(res.context.targets.contains_key("exe"), "{}", template);
+
+            if *template != "default" {
+                assert!(source.contains("run_module=\"my_app\""), "{}", template);
+            }
+        }
+
+        assert!(render_new_pyoxidizer_config_file("django", "my-app", None, &[]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_app_config() -> Result<()> {
+        let logger = get_logger()?;
+        let config_path = std::env::current_dir()?.join("pyoxidizer.bzl");
+
+        for (name, package, entry_point) in &[
+            ("my-app", "my-app==1.0", "my_app"),
+            ("my-app", "my-app", "my_app.cli:main"),
+            ("my \"app\"", "./path\\with \"quotes\"", "my_app"),
+        ] {
+            let source = render_build_app_config(name, package, entry_point)?;
+
+            // Evaluate without resolving targets, which would build them.
+            let res = eval_starlark_config_source(
+                &logger,
+                &config_path,
+                &source,
+                crate::project_building::HOST,
+                false,
+                false,
+                Some(Vec::new()),
+                false,
+            )?;
+
+            assert!(res.context.targets.contains_key("exe"), "{}", name);
+        }
+
+        let source = render_build_app_config("my \"app\"", "./a\\b", "my_app.cli:main")?;
+        assert!(source.contains("name=\"my \\\"app\\\"\","));
+        assert!(source.contains("dist.pip_install([\"./a\\\\b\"])"));
+        assert!(source
+            .contains("run_eval=\"import sys; import my_app.cli; sys.exit(my_app.cli.main())\","));
+
+        assert!(render_build_app_config("my-app", "my-app", "my-app").is_err());
+
+        Ok(())
+    }
+}
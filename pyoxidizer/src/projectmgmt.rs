@@ -5,14 +5,30 @@
 //! Manage PyOxidizer projects.
 
 use {
-    crate::project_building::find_pyoxidizer_config_file_env,
-    crate::project_layout::{initialize_project, write_new_pyoxidizer_config_file},
+    crate::project::Project,
+    crate::project_building::{find_pyoxidizer_config_file_env, vendor_rust_dependencies},
+    crate::project_layout::{
+        initialize_project, render_build_app_config, write_new_pyoxidizer_config_file,
+    },
+    crate::py_packaging::config::RunMode,
     crate::py_packaging::standalone_distribution::StandaloneDistribution,
-    crate::starlark::eval::{eval_starlark_config_file, EvalResult},
-    anyhow::{anyhow, Result},
+    crate::reproducible::source_date_epoch,
+    crate::rust_toolchain::RustToolchain,
+    crate::scratch::{find_scratch_entries, remove_scratch_entries, scratch_path, ScratchEntry},
+    crate::size_budget::format_size,
+    crate::starlark::build_manifest::{BuildManifest, ResourceCounts},
+    crate::starlark::config_version::migrate_source,
+    crate::starlark::eval::{eval_starlark_config_file, eval_starlark_config_source, EvalResult},
+    crate::starlark::python_executable::PythonExecutable,
+    crate::starlark::python_zipapp::PythonZipApp,
+    anyhow::{anyhow, Context, Result},
+    slog::warn,
+    starlark::values::Value,
     std::fs::create_dir_all,
     std::io::{Cursor, Read},
+    std::ops::Deref,
     std::path::Path,
+    std::time::{Duration, SystemTime},
 };
 
 /// Attempt to resolve the default Rust target for a build.
@@ -73,16 +89,224 @@ pub fn list_targets(logger: &slog::Logger, project_path: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Describe what a `RunMode` will do.
+fn describe_run_mode(run_mode: &RunMode) -> String {
+    match run_mode {
+        RunMode::Noop => "do nothing".to_string(),
+        RunMode::Repl => "start a Python REPL".to_string(),
+        RunMode::Module { module } => format!("run module {}", module),
+        RunMode::Eval { code } => format!("evaluate code: {}", code),
+        RunMode::File { path } => format!("run file {}", path),
+        RunMode::PythonCli => "behave like the python executable".to_string(),
+    }
+}
+
+/// Print a description of a resolved target value.
+fn describe_target_value(value: &Value) {
+    println!("  type: {}", value.get_type());
+
+    let (entry_point, counts) = match value.get_type() {
+        "PythonExecutable" => value.downcast_apply(|x: &PythonExecutable| {
+            println!("  executable: {}", x.exe.name());
+            if let Some(version) = x.exe.python_version() {
+                println!("  Python version: {}", version);
+            }
+
+            (
+                Some(describe_run_mode(&x.exe.config().run_mode)),
+                Some(ResourceCounts::from_binary_builder(x.exe.deref())),
+            )
+        }),
+        "PythonZipApp" => value.downcast_apply(|x: &PythonZipApp| {
+            println!("  zip application: {}", x.zipapp.filename());
+
+            (
+                x.zipapp.main.clone(),
+                Some(ResourceCounts::from_zipapp(&x.zipapp)),
+            )
+        }),
+        _ => (None, None),
+    };
+
+    if let Some(entry_point) = entry_point {
+        println!("  entry point: {}", entry_point);
+    }
+
+    if let Some(counts) = counts {
+        println!(
+            "  resources: {} source modules, {} bytecode modules, {} resource files, {} extension modules",
+            counts.source_modules,
+            counts.bytecode_modules,
+            counts.resources,
+            counts.extension_modules
+        );
+    }
+}
+
+/// Validate a PyOxidizer config file without building anything.
+///
+/// The config file is evaluated and the requested targets (or all targets)
+/// are resolved in dry run mode: target functions are called but nothing is
+/// compiled or linked. A report of what would be built is printed.
+pub fn check(
+    logger: &slog::Logger,
+    project_path: &Path,
+    target_triple: Option<&str>,
+    resolve_targets: Option<Vec<String>>,
+) -> Result<()> {
+    let config_path = find_pyoxidizer_config_file_env(logger, project_path).ok_or_else(|| {
+        anyhow!(
+            "unable to find PyOxidizer config file at {}",
+            project_path.display()
+        )
+    })?;
+    let target_triple = resolve_target(target_triple)?;
+
+    let mut res = eval_starlark_config_file(
+        logger,
+        &config_path,
+        &target_triple,
+        false,
+        false,
+        Some(Vec::new()),
+        false,
+    )?;
+
+    if res.context.targets.is_empty() {
+        return Err(anyhow!(
+            "{} does not register any targets",
+            config_path.display()
+        ));
+    }
+
+    let resolve_targets = resolve_targets.unwrap_or_else(|| res.context.targets_order.clone());
+
+    res.set_dry_run(true)?;
+    for target in &resolve_targets {
+        res.resolve_target(target)?;
+    }
+
+    for name in &res.context.targets_order {
+        let target = &res.context.targets[name];
+
+        let mut flags = Vec::new();
+        if Some(name) == res.context.default_target.as_ref() {
+            flags.push("default");
+        }
+        if Some(name) == res.context.default_build_script_target.as_ref() {
+            flags.push("default build script");
+        }
+
+        if flags.is_empty() {
+            println!("{}", name);
+        } else {
+            println!("{} ({})", name, flags.join(", "));
+        }
+
+        if !target.depends.is_empty() {
+            println!("  depends: {}", target.depends.join(", "));
+        }
+
+        if let Some(value) = &target.resolved_value {
+            describe_target_value(value);
+        } else {
+            println!("  (not resolved)");
+        }
+    }
+
+    println!();
+    println!(
+        "{} is valid; {} targets defined",
+        config_path.display(),
+        res.context.targets.len()
+    );
+
+    Ok(())
+}
+
+/// Download everything needed to build a project without network access.
+///
+/// The config file is evaluated and targets are resolved in dry run mode.
+/// Python distributions and packages installed with pip are downloaded to
+/// `dest_path` along the way. Then the Rust crates needed to build
+/// executables are fetched. If `dest_path` isn't defined, the directory
+/// passed to `set_offline()` in the config file is used.
+pub fn vendor(
+    logger: &slog::Logger,
+    project_path: &Path,
+    dest_path: Option<&Path>,
+    target_triple: Option<&str>,
+    release: bool,
+    resolve_targets: Option<Vec<String>>,
+) -> Result<()> {
+    let config_path = find_pyoxidizer_config_file_env(logger, project_path).ok_or_else(|| {
+        anyhow!(
+            "unable to find PyOxidizer config file at {}",
+            project_path.display()
+        )
+    })?;
+    let target_triple = resolve_target(target_triple)?;
+
+    let mut res = eval_starlark_config_file(
+        logger,
+        &config_path,
+        &target_triple,
+        release,
+        false,
+        Some(Vec::new()),
+        false,
+    )?;
+
+    let dest_path = res.set_vendoring(dest_path)?;
+    create_dir_all(&dest_path)?;
+
+    let resolve_targets = resolve_targets.unwrap_or_else(|| res.context.targets_order.clone());
+
+    res.set_dry_run(true)?;
+    for target in &resolve_targets {
+        res.resolve_target(target)?;
+    }
+
+    let cargo_home = res
+        .context
+        .cargo_vendor_path()
+        .ok_or_else(|| anyhow!("vendor directory should be defined"))?;
+    let rust_toolchains_path = res.context.rust_toolchains_path();
+    vendor_rust_dependencies(
+        logger,
+        project_path,
+        rust_toolchains_path.as_ref().map(|p| p.as_path()),
+        &target_triple,
+        &cargo_home,
+    )?;
+
+    warn!(
+        logger,
+        "build artifacts vendored to {}",
+        dest_path.display()
+    );
+
+    Ok(())
+}
+
 /// Build a PyOxidizer enabled project.
 ///
 /// This is a glorified wrapper around `cargo build`. Our goal is to get the
 /// output from repackaging to give the user something for debugging.
+///
+/// If `all` is true, every registered target is built. Up to `jobs` targets
+/// are built concurrently. If `artifact_dir` is defined, deliverables of
+/// built targets are copied to it.
+#[allow(clippy::too_many_arguments)]
 pub fn build(
     logger: &slog::Logger,
     project_path: &Path,
     target_triple: Option<&str>,
     resolve_targets: Option<Vec<String>>,
+    all: bool,
+    jobs: usize,
     release: bool,
+    artifact_dir: Option<&Path>,
     verbose: bool,
 ) -> Result<()> {
     let config_path = find_pyoxidizer_config_file_env(logger, project_path).ok_or_else(|| {
@@ -93,20 +317,166 @@ pub fn build(
     })?;
     let target_triple = resolve_target(target_triple)?;
 
-    let mut res: EvalResult = eval_starlark_config_file(
+    let mut project = Project::from_config(&config_path)?;
+    project.set_logger(logger.clone());
+    project.set_target_triple(&target_triple);
+    project.set_release(release);
+    project.set_verbose(verbose);
+    project.set_jobs(jobs);
+
+    let resolve_targets = if all {
+        Some(project.targets()?)
+    } else {
+        resolve_targets
+    };
+
+    let output = project.build_targets(resolve_targets)?;
+    warn!(logger, "{}", output.timings.summary());
+    warn!(
         logger,
-        &config_path,
+        "build manifest written to {}",
+        output.manifest_path.display()
+    );
+    warn!(
+        logger,
+        "build timings trace written to {}",
+        output.timings_path.display()
+    );
+
+    if let Some(artifact_dir) = artifact_dir {
+        let paths = output.copy_to_artifact_dir(artifact_dir)?;
+        warn!(
+            logger,
+            "{} artifacts copied to {}",
+            paths.len(),
+            artifact_dir.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Build a project twice and verify the produced files are identical.
+pub fn verify_reproducible(
+    logger: &slog::Logger,
+    project_path: &Path,
+    target_triple: Option<&str>,
+    resolve_targets: Option<Vec<String>>,
+    release: bool,
+    verbose: bool,
+) -> Result<()> {
+    let config_path = find_pyoxidizer_config_file_env(logger, project_path).ok_or_else(|| {
+        anyhow!(
+            "unable to find PyOxidizer config file at {}",
+            project_path.display()
+        )
+    })?;
+    let target_triple = resolve_target(target_triple)?;
+
+    // Both builds need to record the same timestamps.
+    if source_date_epoch().is_none() {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?;
+        std::env::set_var("SOURCE_DATE_EPOCH", now.as_secs().to_string());
+    }
+
+    let mut project = Project::from_config(&config_path)?;
+    project.set_logger(logger.clone());
+    project.set_target_triple(&target_triple);
+    project.set_release(release);
+    project.set_verbose(verbose);
+
+    let differences = crate::reproducible::verify_reproducible(&project, resolve_targets)?;
+
+    if differences.is_empty() {
+        warn!(logger, "builds are reproducible");
+        return Ok(());
+    }
+
+    for difference in &differences {
+        warn!(
+            logger,
+            "{}: {} differs ({} != {})",
+            difference.target,
+            difference.path,
+            difference
+                .first_sha256
+                .clone()
+                .unwrap_or_else(|| "missing".to_string()),
+            difference
+                .second_sha256
+                .clone()
+                .unwrap_or_else(|| "missing".to_string())
+        );
+    }
+
+    Err(anyhow!("{} files differ between builds", differences.len()))
+}
+
+/// Build an application from a pip installable package without a config file.
+///
+/// A default configuration is synthesized in memory. If `emit_config` is
+/// set, the configuration is printed instead of being built.
+#[allow(clippy::too_many_arguments)]
+pub fn build_app(
+    logger: &slog::Logger,
+    project_path: &Path,
+    package: &str,
+    entry_point: &str,
+    name: Option<&str>,
+    target_triple: Option<&str>,
+    release: bool,
+    emit_config: bool,
+    verbose: bool,
+) -> Result<()> {
+    let name = if let Some(name) = name {
+        name.to_string()
+    } else {
+        // Default to the top-level module of the entry point.
+        entry_point
+            .split(|c: char| c == ':' || c == '.')
+            .next()
+            .unwrap()
+            .to_string()
+    };
+
+    let config = render_build_app_config(&name, package, entry_point)?;
+
+    if emit_config {
+        print!("{}", config);
+        return Ok(());
+    }
+
+    let target_triple = resolve_target(target_triple)?;
+
+    let mut res: EvalResult = eval_starlark_config_source(
+        logger,
+        &project_path.join("pyoxidizer.bzl"),
+        &config,
         &target_triple,
         release,
         verbose,
-        resolve_targets,
+        None,
         false,
     )?;
 
     for target in res.context.targets_to_resolve() {
-        res.context.build_resolved_target(&target)?;
+        let resolved = res.context.build_resolved_target(&target)?;
+
+        warn!(
+            logger,
+            "application {} written to {}",
+            name,
+            resolved.output_path.display()
+        );
     }
 
+    let manifest_path = BuildManifest::from_context(&res.context)?.write(&res.context)?;
+    warn!(
+        logger,
+        "build manifest written to {}",
+        manifest_path.display()
+    );
+
     Ok(())
 }
 
@@ -146,9 +516,293 @@ pub fn run(
     res.context.run_target(target)
 }
 
+/// Build and run test targets in a PyOxidizer configuration file.
+///
+/// All registered test targets are run if `targets` is `None`. An error is
+/// returned if any test target fails.
+pub fn run_module_tests(
+    logger: &slog::Logger,
+    project_path: &Path,
+    target_triple: Option<&str>,
+    release: bool,
+    targets: Option<Vec<String>>,
+    verbose: bool,
+) -> Result<()> {
+    let config_path = find_pyoxidizer_config_file_env(logger, project_path).ok_or_else(|| {
+        anyhow!(
+            "unable to find PyOxidizer config file at {}",
+            project_path.display()
+        )
+    })?;
+    let target_triple = resolve_target(target_triple)?;
+
+    // Only the targets of executables being tested are resolved.
+    let mut res: EvalResult = eval_starlark_config_file(
+        logger,
+        &config_path,
+        &target_triple,
+        release,
+        verbose,
+        Some(Vec::new()),
+        false,
+    )?;
+
+    let targets = match targets {
+        Some(targets) => targets,
+        None => res.context.test_targets.keys().cloned().collect(),
+    };
+
+    if targets.is_empty() {
+        return Err(anyhow!("no test targets registered"));
+    }
+
+    let mut failed = Vec::new();
+
+    for target in &targets {
+        let executable_target = res
+            .context
+            .test_targets
+            .get(target)
+            .ok_or_else(|| anyhow!("test target {} is not registered", target))?
+            .executable_target
+            .clone();
+
+        res.resolve_target(&executable_target)?;
+
+        if let Err(e) = res.context.run_test_target(target) {
+            slog::error!(logger, "{}", e);
+            failed.push(target.clone());
+        }
+    }
+
+    if failed.is_empty() {
+        warn!(logger, "{} test targets passed", targets.len());
+        Ok(())
+    } else {
+        Err(anyhow!("test targets failed: {}", failed.join(", ")))
+    }
+}
+
+/// Build release targets in a PyOxidizer configuration file and publish them.
+///
+/// All registered release targets are released if `targets` is `None`. The
+/// targets of each release are built in release mode and their deliverables
+/// are written to the release target's output directory, or to a directory
+/// named after the release target in `dest_dir`. If `upload` is true, the
+/// release is uploaded to the provider of the release target.
+#[allow(clippy::too_many_arguments)]
+pub fn release(
+    logger: &slog::Logger,
+    project_path: &Path,
+    target_triple: Option<&str>,
+    targets: Option<Vec<String>>,
+    dest_dir: Option<&Path>,
+    upload: bool,
+    jobs: usize,
+    verbose: bool,
+) -> Result<()> {
+    let config_path = find_pyoxidizer_config_file_env(logger, project_path).ok_or_else(|| {
+        anyhow!(
+            "unable to find PyOxidizer config file at {}",
+            project_path.display()
+        )
+    })?;
+    let target_triple = resolve_target(target_triple)?;
+
+    let res: EvalResult = eval_starlark_config_file(
+        logger,
+        &config_path,
+        &target_triple,
+        true,
+        verbose,
+        Some(Vec::new()),
+        false,
+    )?;
+
+    let targets = match targets {
+        Some(targets) => targets,
+        None => res.context.release_targets.keys().cloned().collect(),
+    };
+
+    if targets.is_empty() {
+        return Err(anyhow!("no release targets registered"));
+    }
+
+    let mut project = Project::from_config(&config_path)?;
+    project.set_logger(logger.clone());
+    project.set_target_triple(&target_triple);
+    project.set_release(true);
+    project.set_verbose(verbose);
+    project.set_jobs(jobs);
+
+    for target in &targets {
+        let release = res
+            .context
+            .release_targets
+            .get(target)
+            .ok_or_else(|| anyhow!("release target {} is not registered", target))?;
+
+        if upload && release.provider.is_none() {
+            return Err(anyhow!(
+                "release target {} does not define a provider to upload to",
+                target
+            ));
+        }
+
+        let output = project.build_targets(Some(release.targets.clone()))?;
+
+        let path = match dest_dir {
+            Some(dest_dir) => dest_dir.join(target),
+            None => res.context.target_output_path(target),
+        };
+        let prepared = crate::release::prepare_release(target, release, &output, &path)?;
+        warn!(
+            logger,
+            "release {} written to {} ({} artifacts)",
+            prepared.title,
+            path.display(),
+            prepared.artifacts.len()
+        );
+
+        if upload {
+            let url = crate::release::upload_release(logger, release, &prepared)
+                .with_context(|| format!("uploading release {}", target))?;
+            warn!(logger, "release {} uploaded to {}", prepared.title, url);
+        }
+    }
+
+    Ok(())
+}
+
+/// Obtain the most recent modification time of Python source files in a directory.
+fn newest_python_source_mtime(path: &Path) -> Result<Option<SystemTime>> {
+    let mut newest = None;
+
+    for entry in walkdir::WalkDir::new(path) {
+        let entry = entry?;
+
+        if entry.path().extension().and_then(|e| e.to_str()) != Some("py") {
+            continue;
+        }
+
+        let mtime = entry.metadata()?.modified()?;
+        if newest.map_or(true, |newest| mtime > newest) {
+            newest = Some(mtime);
+        }
+    }
+
+    Ok(newest)
+}
+
+/// Build an executable once and re-run it whenever Python source changes.
+///
+/// `packages` are imported from `source_path` on the filesystem instead of
+/// being embedded, so source changes take effect without rebuilding. Other
+/// resources remain embedded. The executable is re-run whenever a `.py` file
+/// under `source_path` is modified.
+#[allow(clippy::too_many_arguments)]
+pub fn devserver(
+    logger: &slog::Logger,
+    project_path: &Path,
+    target_triple: Option<&str>,
+    release: bool,
+    target: Option<&str>,
+    source_path: &Path,
+    packages: &[String],
+    extra_args: &[&str],
+    verbose: bool,
+) -> Result<()> {
+    let config_path = find_pyoxidizer_config_file_env(logger, project_path).ok_or_else(|| {
+        anyhow!(
+            "unable to find PyOxidizer config file at {}",
+            project_path.display()
+        )
+    })?;
+    let target_triple = resolve_target(target_triple)?;
+
+    let resolve_targets = if let Some(target) = target {
+        Some(vec![target.to_string()])
+    } else {
+        None
+    };
+
+    let res: EvalResult = eval_starlark_config_file(
+        logger,
+        &config_path,
+        &target_triple,
+        release,
+        verbose,
+        resolve_targets,
+        false,
+    )?;
+
+    let target = if let Some(t) = target {
+        t.to_string()
+    } else if let Some(t) = &res.context.default_target {
+        t.to_string()
+    } else {
+        return Err(anyhow!("unable to determine target to run"));
+    };
+
+    let source_path = std::fs::canonicalize(source_path)
+        .with_context(|| format!("resolving {}", source_path.display()))?;
+    // Backslashes would be interpreted as escapes by the generated Rust code.
+    let sys_path = source_path.display().to_string().replace('\\', "/");
+
+    let exe_path = res
+        .context
+        .build_devserver_target(&target, packages, &[sys_path])?;
+
+    let mut mtime = newest_python_source_mtime(&source_path)?;
+
+    loop {
+        warn!(logger, "running {}", exe_path.display());
+        let mut child = std::process::Command::new(&exe_path)
+            .args(extra_args)
+            .spawn()
+            .with_context(|| format!("running {}", exe_path.display()))?;
+        let mut running = true;
+
+        loop {
+            std::thread::sleep(Duration::from_millis(500));
+
+            if running {
+                if let Some(status) = child.try_wait()? {
+                    warn!(
+                        logger,
+                        "process exited with {}; waiting for changes", status
+                    );
+                    running = false;
+                }
+            }
+
+            let current = newest_python_source_mtime(&source_path)?;
+            if current != mtime {
+                mtime = current;
+                break;
+            }
+        }
+
+        if running {
+            child.kill()?;
+            child.wait()?;
+        }
+
+        warn!(
+            logger,
+            "change detected in {}; restarting",
+            source_path.display()
+        );
+    }
+}
+
 /// Initialize a PyOxidizer configuration file in a given directory.
+///
+/// `template` is the name of a template in `project_layout::CONFIG_TEMPLATES`.
 pub fn init_config_file(
+    logger: &slog::Logger,
     project_dir: &Path,
+    template: &str,
     code: Option<&str>,
     pip_install: &[&str],
 ) -> Result<()> {
@@ -165,51 +819,209 @@ pub fn init_config_file(
 
     let name = project_dir.iter().last().unwrap().to_str().unwrap();
 
-    write_new_pyoxidizer_config_file(project_dir, name, code, pip_install)?;
+    write_new_pyoxidizer_config_file(project_dir, name, template, code, pip_install)?;
 
-    println!();
-    println!("A new PyOxidizer configuration file has been created.");
-    println!("This configuration file can be used by various `pyoxidizer`");
-    println!("commands");
-    println!();
-    println!("For example, to build and run the default Python application:");
-    println!();
-    println!("  $ cd {}", project_dir.display());
-    println!("  $ pyoxidizer run");
-    println!();
-    println!("The default configuration is to invoke a Python REPL. You can");
-    println!("edit the configuration file to change behavior.");
+    let next_steps = if template == "default" {
+        "The default configuration is to invoke a Python REPL. You can\n\
+         edit the configuration file to change behavior."
+            .to_string()
+    } else {
+        format!(
+            "The configuration was created from the {} template. Review the\n\
+             comments in the configuration file to adapt it to your application.",
+            template
+        )
+    };
+
+    warn!(
+        logger,
+        "\n\
+         A new PyOxidizer configuration file has been created.\n\
+         This configuration file can be used by various `pyoxidizer`\n\
+         commands\n\
+         \n\
+         For example, to build and run the default Python application:\n\
+         \n  \
+         $ cd {}\n  \
+         $ pyoxidizer run\n\
+         \n\
+         {}",
+        project_dir.display(),
+        next_steps
+    );
+
+    Ok(())
+}
+
+/// Rewrite a configuration file to use the current configuration file API.
+///
+/// With `dry_run`, changes are printed but not written.
+pub fn migrate_config(logger: &slog::Logger, project_path: &Path, dry_run: bool) -> Result<()> {
+    let config_path = find_pyoxidizer_config_file_env(logger, project_path).ok_or_else(|| {
+        anyhow!(
+            "unable to find PyOxidizer config file at {}",
+            project_path.display()
+        )
+    })?;
+
+    let source = std::fs::read_to_string(&config_path)
+        .context(format!("reading {}", config_path.display()))?;
+    let (migrated, changes) = migrate_source(&source);
+
+    if changes.is_empty() {
+        warn!(logger, "{} is up to date", config_path.display());
+        return Ok(());
+    }
+
+    for change in &changes {
+        warn!(logger, "{}: {}", config_path.display(), change);
+    }
+
+    if !dry_run {
+        std::fs::write(&config_path, migrated)
+            .context(format!("writing {}", config_path.display()))?;
+        warn!(logger, "updated {}", config_path.display());
+    }
+
+    Ok(())
+}
+
+/// Directories in the build path caching downloads across builds.
+const BUILD_PATH_CACHE_DIRS: &[&str] = &[
+    "python_distributions",
+    "rust_toolchains",
+    "sdist_wheels",
+    "oci_blobs",
+];
+
+/// Remove build state of a project.
+///
+/// Build outputs in the project's build path, output directories of targets
+/// outside of it, and leftover scratch directories are removed. Downloaded
+/// Python distributions, Rust toolchains, wheels built from sdists, and
+/// pulled container image layers are kept unless `all` is true. Distribution
+/// caches shared with other projects are never removed.
+pub fn clean(logger: &slog::Logger, project_path: &Path, all: bool) -> Result<()> {
+    let config_path = find_pyoxidizer_config_file_env(logger, project_path).ok_or_else(|| {
+        anyhow!(
+            "unable to find PyOxidizer config file at {}",
+            project_path.display()
+        )
+    })?;
+    let target_triple = default_target()?;
+
+    // Evaluating the config file applies its build and scratch paths.
+    let res = eval_starlark_config_file(
+        logger,
+        &config_path,
+        &target_triple,
+        false,
+        false,
+        Some(Vec::new()),
+        false,
+    )?;
+
+    let mut entries = Vec::new();
+
+    let build_path = &res.context.build_path;
+    if build_path.is_dir() {
+        for entry in std::fs::read_dir(build_path)? {
+            let path = entry?.path();
+
+            let is_cache = path
+                .file_name()
+                .map(|name| BUILD_PATH_CACHE_DIRS.contains(&name.to_string_lossy().as_ref()))
+                .unwrap_or(false);
+            let is_vendored = res
+                .context
+                .vendor_path
+                .as_ref()
+                .map(|vendor_path| vendor_path.starts_with(&path))
+                .unwrap_or(false);
+
+            if (is_cache && !all) || is_vendored {
+                continue;
+            }
+
+            entries.push(ScratchEntry::from_path(&path)?);
+        }
+    }
+
+    // Output directories of targets can be outside the build path with
+    // custom output layouts.
+    let context = &res.context;
+    for release in &[false, true] {
+        for target in context
+            .targets_order
+            .iter()
+            .chain(context.release_targets.keys())
+        {
+            let path = context.output_layout.target_path(
+                &context.cwd,
+                build_path,
+                &context.build_target_triple,
+                *release,
+                target,
+            );
+
+            if !path.starts_with(build_path) && path.exists() {
+                entries.push(ScratchEntry::from_path(&path)?);
+            }
+        }
+    }
+
+    entries.extend(find_scratch_entries(&scratch_path())?);
+
+    let freed = remove_scratch_entries(logger, &entries)?;
+    warn!(
+        logger,
+        "removed {} entries, freeing {}",
+        entries.len(),
+        format_size(freed)
+    );
 
     Ok(())
 }
 
 /// Initialize a new Rust project with PyOxidizer support.
-pub fn init_rust_project(project_path: &Path) -> Result<()> {
+pub fn init_rust_project(logger: &slog::Logger, project_path: &Path) -> Result<()> {
     let env = crate::environment::resolve_environment()?;
     let pyembed_location = env.as_pyembed_location();
 
-    initialize_project(project_path, &pyembed_location, None, &[])?;
-    println!();
-    println!(
-        "A new Rust binary application has been created in {}",
+    initialize_project(
+        project_path,
+        &RustToolchain::system().cargo_path,
+        &pyembed_location,
+        None,
+        &[],
+    )?;
+    warn!(
+        logger,
+        "\n\
+         A new Rust binary application has been created in {}\n\
+         \n\
+         This application can be built by doing the following:\n\
+         \n  \
+         $ cd {}\n  \
+         $ pyoxidizer build\n  \
+         $ pyoxidizer run\n\
+         \n\
+         The default configuration is to invoke a Python REPL. You can\n\
+         edit the various pyoxidizer.*.bzl config files or the main.rs\n\
+         file to change behavior. The application will need to be rebuilt\n\
+         for configuration changes to take effect.",
+        project_path.display(),
         project_path.display()
     );
-    println!();
-    println!("This application can be built by doing the following:");
-    println!();
-    println!("  $ cd {}", project_path.display());
-    println!("  $ pyoxidizer build");
-    println!("  $ pyoxidizer run");
-    println!();
-    println!("The default configuration is to invoke a Python REPL. You can");
-    println!("edit the various pyoxidizer.*.bzl config files or the main.rs ");
-    println!("file to change behavior. The application will need to be rebuilt ");
-    println!("for configuration changes to take effect.");
 
     Ok(())
 }
 
-pub fn python_distribution_extract(dist_path: &str, dest_path: &str) -> Result<()> {
+pub fn python_distribution_extract(
+    logger: &slog::Logger,
+    dist_path: &str,
+    dest_path: &str,
+) -> Result<()> {
     let mut fh = std::fs::File::open(Path::new(dist_path))?;
     let mut data = Vec::new();
     fh.read_to_end(&mut data)?;
@@ -217,7 +1029,7 @@ pub fn python_distribution_extract(dist_path: &str, dest_path: &str) -> Result<(
     let dctx = zstd::stream::Decoder::new(cursor)?;
     let mut tf = tar::Archive::new(dctx);
 
-    println!("extracting archive to {}", dest_path);
+    warn!(logger, "extracting archive to {}", dest_path);
     tf.unpack(dest_path)?;
 
     Ok(())
@@ -227,7 +1039,7 @@ pub fn python_distribution_info(dist_path: &str) -> Result<()> {
     let fh = std::fs::File::open(Path::new(dist_path))?;
     let reader = std::io::BufReader::new(fh);
 
-    let temp_dir = tempdir::TempDir::new("python-distribution")?;
+    let temp_dir = crate::scratch::scratch_dir("python-distribution")?;
     let temp_dir_path = temp_dir.path();
 
     let dist = StandaloneDistribution::from_tar_zst(reader, temp_dir_path)?;
@@ -297,7 +1109,7 @@ pub fn python_distribution_licenses(path: &str) -> Result<()> {
     let fh = std::fs::File::open(Path::new(path))?;
     let reader = std::io::BufReader::new(fh);
 
-    let temp_dir = tempdir::TempDir::new("python-distribution")?;
+    let temp_dir = crate::scratch::scratch_dir("python-distribution")?;
     let temp_dir_path = temp_dir.path();
 
     let dist = StandaloneDistribution::from_tar_zst(reader, temp_dir_path)?;
@@ -363,3 +1175,41 @@ pub fn python_distribution_licenses(path: &str) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*, crate::project_building::HOST, crate::python_distributions::PYTHON_DISTRIBUTIONS,
+        crate::testutil::*,
+    };
+
+    #[test]
+    fn test_check_without_network() -> Result<()> {
+        let logger = get_logger()?;
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+
+        let record = PYTHON_DISTRIBUTIONS
+            .iter()
+            .find(|record| record.target_triple == HOST)
+            .ok_or_else(|| anyhow!("no Python distribution for {}", HOST))?;
+
+        // Nothing listens on port 1. So the distribution and packages can't
+        // be obtained and the check fails if anything is downloaded.
+        std::fs::write(
+            temp_dir.path().join("pyoxidizer.bzl"),
+            format!(
+                "def make_exe():\n    \
+                 dist = PythonDistribution(sha256='{}', url='http://127.0.0.1:1/python.tar.zst')\n    \
+                 exe = dist.to_python_executable(name='app')\n    \
+                 exe.add_python_resources(dist.pip_install(['--index-url', 'http://127.0.0.1:1/simple', 'pyflakes']))\n    \
+                 exe.add_python_resources(dist.read_virtualenv('does-not-exist'))\n    \
+                 return exe\n\n\
+                 register_target('exe', make_exe, default=True)\n\
+                 resolve_targets()\n",
+                record.location.sha256
+            ),
+        )?;
+
+        check(&logger, temp_dir.path(), Some(HOST), None)
+    }
+}
@@ -7,21 +7,142 @@ Defining and manipulating binaries embedding Python.
 */
 
 use {
-    super::config::EmbeddedPythonConfig,
-    super::embedded_resource::EmbeddedPythonResources,
+    super::bytecode::SourceTransforms,
+    super::config::{EmbeddedPythonConfig, RunMode},
+    super::embedded_resource::{ConflictPolicy, EmbeddedPythonResources, ResourceConflict},
     super::libpython::ImportlibBytecode,
     super::pyembed::{derive_python_config, write_default_python_config_rs},
-    super::resource::{BytecodeModule, ExtensionModuleData, ResourceData, SourceModule},
+    super::resource::{
+        BytecodeModule, BytecodeOptimizationLevel, DistributionResource, ExtensionModuleData,
+        ResourceData, SourceModule,
+    },
     super::standalone_distribution::ExtensionModule,
     crate::app_packaging::resource::FileManifest,
-    anyhow::Result,
-    std::collections::BTreeMap,
+    crate::licensing::LicensedComponent,
+    crate::timing,
+    anyhow::{anyhow, Result},
+    python_packed_resources::data::FileEmulation,
+    python_packed_resources::writer::write_embedded_resources_v2,
+    sha2::{Digest, Sha256},
+    std::collections::{BTreeMap, BTreeSet},
     std::convert::TryFrom,
     std::fs::File,
-    std::io::Write,
+    std::io::{BufWriter, Read, Write},
     std::path::{Path, PathBuf},
 };
 
+/// How a binary links against `libpython`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LibpythonLinkMode {
+    /// `libpython` is statically linked into the binary.
+    Static,
+    /// The binary links against a `libpython` shared library (e.g. `python3.dll`).
+    Dynamic,
+}
+
+impl LibpythonLinkMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LibpythonLinkMode::Static => "static",
+            LibpythonLinkMode::Dynamic => "dynamic",
+        }
+    }
+}
+
+impl TryFrom<&str> for LibpythonLinkMode {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "static" => Ok(LibpythonLinkMode::Static),
+            "dynamic" => Ok(LibpythonLinkMode::Dynamic),
+            t => Err(format!(
+                "{} is not a valid libpython link mode; expected static or dynamic",
+                t
+            )),
+        }
+    }
+}
+
+/// How a binary links against the C runtime on Windows.
+///
+/// This is the difference between compiling with `/MT` and `/MD`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowsCrtLinkage {
+    /// The CRT is statically linked (`/MT`).
+    Static,
+    /// The CRT is loaded from `vcruntime` DLLs at run-time (`/MD`).
+    Dynamic,
+}
+
+impl WindowsCrtLinkage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WindowsCrtLinkage::Static => "static",
+            WindowsCrtLinkage::Dynamic => "dynamic",
+        }
+    }
+
+    /// The `rustc` flags that select this linkage.
+    pub fn rustflags(&self) -> &'static str {
+        match self {
+            WindowsCrtLinkage::Static => "-C target-feature=+crt-static",
+            WindowsCrtLinkage::Dynamic => "-C target-feature=-crt-static",
+        }
+    }
+
+    /// The system library providing the CRT entry points.
+    pub fn system_library(&self) -> &'static str {
+        match self {
+            WindowsCrtLinkage::Static => "libcmt",
+            WindowsCrtLinkage::Dynamic => "msvcrt",
+        }
+    }
+
+    /// Ensure Python can be linked into a binary for `target_triple` with this linkage.
+    ///
+    /// Object files of Python distributions are compiled against the dynamic
+    /// CRT. MSVC refuses to link them with objects compiled against the static
+    /// CRT (LNK2038). So statically linking `libpython` requires the dynamic CRT.
+    pub fn ensure_compatible(
+        &self,
+        target_triple: &str,
+        libpython_link_mode: LibpythonLinkMode,
+    ) -> Result<()> {
+        if target_triple.contains("pc-windows-msvc")
+            && *self == WindowsCrtLinkage::Static
+            && libpython_link_mode == LibpythonLinkMode::Static
+        {
+            Err(anyhow!(
+                "Python distribution object files are compiled with /MD and can't be linked into an executable with a static C runtime (/MT); use windows_crt_linkage=\"dynamic\" or link libpython dynamically"
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for WindowsCrtLinkage {
+    fn default() -> Self {
+        WindowsCrtLinkage::Dynamic
+    }
+}
+
+impl TryFrom<&str> for WindowsCrtLinkage {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "static" => Ok(WindowsCrtLinkage::Static),
+            "dynamic" => Ok(WindowsCrtLinkage::Dynamic),
+            t => Err(format!(
+                "{} is not a valid Windows CRT linkage; expected static or dynamic",
+                t
+            )),
+        }
+    }
+}
+
 /// Describes a generic way to build a Python binary.
 ///
 /// Binary here means an executable or library containing or linking to a
@@ -29,8 +150,9 @@ use {
 /// binary.
 ///
 /// Concrete implementations can be turned into build artifacts or binaries
-/// themselves.
-pub trait PythonBinaryBuilder {
+/// themselves. They can be sent to other threads, so they can be built
+/// concurrently.
+pub trait PythonBinaryBuilder: Send {
     /// Clone self into a Box'ed trait object.
     fn clone_box(&self) -> Box<dyn PythonBinaryBuilder>;
 
@@ -43,12 +165,40 @@ pub trait PythonBinaryBuilder {
     /// returned executable.
     fn python_exe_path(&self) -> &Path;
 
+    /// The version of Python being embedded, if known.
+    fn python_version(&self) -> Option<String>;
+
+    /// Configuration of the embedded Python interpreter.
+    fn config(&self) -> &EmbeddedPythonConfig;
+
+    /// Set what the embedded interpreter runs when the binary starts.
+    fn set_run_mode(&mut self, run_mode: RunMode);
+
+    /// How the binary links against the C runtime when targeting Windows.
+    fn windows_crt_linkage(&self) -> WindowsCrtLinkage;
+
+    /// Set how the binary links against the C runtime when targeting Windows.
+    fn set_windows_crt_linkage(&mut self, linkage: WindowsCrtLinkage);
+
+    /// How the binary links against `libpython`.
+    fn libpython_link_mode(&self) -> LibpythonLinkMode;
+
+    /// Set how the binary links against `libpython`.
+    ///
+    /// Building fails if the distribution can't provide this link mode.
+    fn set_libpython_link_mode(&mut self, mode: LibpythonLinkMode);
+
     /// Obtain source modules to be embedded in this instance.
     fn source_modules(&self) -> BTreeMap<String, SourceModule>;
 
     /// Obtain bytecode modules to be embedded in this instance.
     fn bytecode_modules(&self) -> BTreeMap<String, BytecodeModule>;
 
+    /// Obtain the optimization levels bytecode of each module is embedded at.
+    ///
+    /// `bytecode_modules()` only reports one level per module.
+    fn bytecode_optimize_levels(&self) -> BTreeMap<String, Vec<BytecodeOptimizationLevel>>;
+
     /// Obtain resource data to be embedded in this instance.
     fn resources(&self) -> BTreeMap<String, BTreeMap<String, Vec<u8>>>;
 
@@ -67,12 +217,45 @@ pub trait PythonBinaryBuilder {
     /// Add resource data to the collection of embedded resource data.
     fn add_resource(&mut self, resource: &ResourceData);
 
+    /// Add a file in a Python package's distribution metadata.
+    fn add_distribution_resource(&mut self, resource: &DistributionResource);
+
+    /// Define how `__file__` is set for an embedded module or package.
+    fn set_file_emulation(&mut self, name: &str, policy: FileEmulation);
+
+    /// Define source transforms for modules in a package.
+    fn set_source_transforms(&mut self, package: &str, transforms: SourceTransforms);
+
+    /// Obtain how modules and resources added from different sources are handled.
+    fn resource_conflict_policy(&self) -> ConflictPolicy;
+
+    /// Define how modules and resources added from different sources are handled.
+    fn set_resource_conflict_policy(&mut self, policy: ConflictPolicy);
+
+    /// Obtain resource conflicts that occurred since this was last called.
+    fn take_resource_conflicts(&mut self) -> Vec<ResourceConflict>;
+
     /// Add an extension module to be embedded in the binary.
     fn add_extension_module(&mut self, extension_module: &ExtensionModule);
 
     /// Add an extension module to be embedded in the binary.
     fn add_extension_module_data(&mut self, extension_module_data: &ExtensionModuleData);
 
+    /// Record licensing metadata of a Python package embedded in the binary.
+    fn add_package_license(&mut self, license: &LicensedComponent);
+
+    /// Obtain licensing metadata of third party components in the binary.
+    ///
+    /// This includes Python itself, extension modules and the libraries
+    /// they link, and recorded Python packages.
+    fn licensed_components(&self) -> Vec<LicensedComponent>;
+
+    /// Obtain licensing metadata of recorded Python packages.
+    fn package_licenses(&self) -> Vec<LicensedComponent>;
+
+    /// Names of native libraries linked into the binary.
+    fn linked_libraries(&self) -> BTreeSet<String>;
+
     /// Filter embedded resources against names in files.
     ///
     /// `files` is files to read names from.
@@ -85,6 +268,23 @@ pub trait PythonBinaryBuilder {
         glob_patterns: &[&str],
     ) -> Result<()>;
 
+    /// Import packages from the filesystem instead of embedding them.
+    ///
+    /// Embedded resources of `packages` are removed and `sys_paths` are
+    /// prepended to `sys.path`. The filesystem importer is enabled.
+    fn import_packages_from_filesystem(&mut self, packages: &[String], sys_paths: &[String]);
+
+    /// Remove embedded modules and extension modules by name.
+    ///
+    /// Source, bytecode, and resource data of each named module are removed.
+    fn remove_modules(&mut self, names: &[String]);
+
+    /// Restrict the modules Python code can import.
+    ///
+    /// Embedded resources not allowed to be imported are removed and the
+    /// interpreter refuses to import them.
+    fn restrict_imports(&mut self, allow: Option<&[String]>, deny: &[String]);
+
     /// Whether the binary requires the jemalloc library.
     fn requires_jemalloc(&self) -> bool;
 
@@ -101,6 +301,9 @@ pub trait PythonBinaryBuilder {
 
 /// Describes how to link a binary against Python.
 pub struct PythonLinkingInfo {
+    /// How `libpython` is linked.
+    pub link_mode: LibpythonLinkMode,
+
     /// Path to a `pythonXY` library to link against.
     pub libpythonxy_filename: PathBuf,
 
@@ -123,27 +326,58 @@ pub struct PythonLinkingInfo {
 }
 
 /// Represents serialized data embedded in binaries for loading Python resources.
+///
+/// Packed resources data can be large, so it is kept in a file in a scratch
+/// directory instead of memory.
 pub struct EmbeddedResourcesBlobs {
     pub module_names: Vec<u8>,
-    pub resources: Vec<u8>,
+
+    /// Path to file containing packed resources data.
+    pub resources_path: PathBuf,
+
+    /// Scratch directory holding `resources_path`, removed when dropped.
+    _scratch: tempdir::TempDir,
 }
 
 impl<'a> TryFrom<EmbeddedPythonResources<'a>> for EmbeddedResourcesBlobs {
     type Error = anyhow::Error;
 
     fn try_from(value: EmbeddedPythonResources) -> Result<Self, Self::Error> {
+        let scratch = crate::scratch::scratch_dir("resources")?;
+        let resources_path = scratch.path().join("packed-resources");
+
         let mut module_names = Vec::new();
-        let mut resources = Vec::new();
+        let mut resources = BufWriter::new(File::create(&resources_path)?);
 
-        value.write_blobs(&mut module_names, &mut resources);
+        value.write_blobs(&mut module_names, &mut resources, scratch.path())?;
+        resources.flush()?;
 
         Ok(Self {
             module_names,
-            resources,
+            resources_path,
+            _scratch: scratch,
         })
     }
 }
 
+/// Compute the SHA-256 of a file, reading it in chunks.
+fn sha256_file(path: &Path) -> Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    let mut fh = File::open(path)?;
+
+    let mut buffer = [0; 32768];
+
+    loop {
+        let count = fh.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.input(&buffer[..count]);
+    }
+
+    Ok(hasher.result().to_vec())
+}
+
 /// Holds filesystem paths to resources required to build a binary embedding Python.
 pub struct EmbeddedPythonBinaryPaths {
     /// File containing bytecode for `importlib._bootstrap` module.
@@ -158,6 +392,10 @@ pub struct EmbeddedPythonBinaryPaths {
     /// File containing embedded resources data.
     pub embedded_resources: PathBuf,
 
+    /// File containing resources data to install at the interpreter's
+    /// `resources_file` path.
+    pub resources_file: Option<PathBuf>,
+
     /// Path to library containing libpython.
     pub libpython: PathBuf,
 
@@ -171,6 +409,34 @@ pub struct EmbeddedPythonBinaryPaths {
     pub cargo_metadata: PathBuf,
 }
 
+impl EmbeddedPythonBinaryPaths {
+    /// All files that were written.
+    pub fn files(&self) -> Vec<PathBuf> {
+        let mut res = vec![
+            self.importlib_bootstrap.clone(),
+            self.importlib_bootstrap_external.clone(),
+            self.module_names.clone(),
+            self.embedded_resources.clone(),
+        ];
+        res.extend(self.resources_file.clone());
+        res.push(self.libpython.clone());
+        res.extend(self.libpyembeddedconfig.clone());
+        res.push(self.config_rs.clone());
+        res.push(self.cargo_metadata.clone());
+
+        res
+    }
+}
+
+/// Whether a Python version is at least a given major and minor version.
+fn python_version_at_least(version: &str, minimum: (u32, u32)) -> bool {
+    let mut parts = version.split('.').map(|p| p.parse::<u32>().unwrap_or(0));
+    let major = parts.next().unwrap_or(0);
+    let minor = parts.next().unwrap_or(0);
+
+    (major, minor) >= minimum
+}
+
 /// Represents resources to embed Python in a binary.
 pub struct EmbeddedPythonBinaryData {
     /// The configuration for the embedded interpreter.
@@ -185,6 +451,12 @@ pub struct EmbeddedPythonBinaryData {
     /// Python resources to embed in the binary.
     pub resources: EmbeddedResourcesBlobs,
 
+    /// Version of the embedded Python, if known.
+    pub python_version: Option<String>,
+
+    /// Name of the binary being built.
+    pub name: String,
+
     /// Rust target triple for the host we are running on.
     pub host: String,
 
@@ -195,6 +467,8 @@ pub struct EmbeddedPythonBinaryData {
 impl EmbeddedPythonBinaryData {
     /// Write out files needed to link a binary.
     pub fn write_files(&self, dest_dir: &Path) -> Result<EmbeddedPythonBinaryPaths> {
+        let _phase = timing::phase("embed resources");
+
         let importlib_bootstrap = dest_dir.join("importlib_bootstrap");
         let mut fh = File::create(&importlib_bootstrap)?;
         fh.write_all(&self.importlib.bootstrap)?;
@@ -207,9 +481,25 @@ impl EmbeddedPythonBinaryData {
         let mut fh = File::create(&module_names)?;
         fh.write_all(&self.resources.module_names)?;
 
+        // Resources loaded from a file at run-time are written to their own
+        // file and the binary embeds an empty resources blob.
         let embedded_resources = dest_dir.join("pyembed-resources");
-        let mut fh = File::create(&embedded_resources)?;
-        fh.write_all(&self.resources.resources)?;
+        let resources_file = if self.config.resources_file.is_some() {
+            let path = dest_dir.join("packed-resources");
+            std::fs::copy(&self.resources.resources_path, &path)?;
+
+            let mut empty_resources = Vec::new();
+            write_embedded_resources_v2(&[], &mut empty_resources, None)?;
+
+            let mut fh = File::create(&embedded_resources)?;
+            fh.write_all(&empty_resources)?;
+
+            Some(path)
+        } else {
+            std::fs::copy(&self.resources.resources_path, &embedded_resources)?;
+
+            None
+        };
 
         let libpython = dest_dir.join(&self.linking_info.libpythonxy_filename);
         let mut fh = File::create(&libpython)?;
@@ -229,11 +519,16 @@ impl EmbeddedPythonBinaryData {
             None
         };
 
+        let embedded_resources_digest = sha256_file(&embedded_resources)?;
+
         let config_rs_data = derive_python_config(
             &self.config,
+            &self.name,
+            &self.target,
             &importlib_bootstrap,
             &importlib_bootstrap_external,
             &embedded_resources,
+            &embedded_resources_digest,
         );
         let config_rs = dest_dir.join("default_python_config.rs");
         write_default_python_config_rs(&config_rs, &config_rs_data)?;
@@ -253,6 +548,13 @@ impl EmbeddedPythonBinaryData {
             config_rs.display()
         ));
 
+        // pyembed only calls C APIs introduced by Python 3.8 when told to.
+        if let Some(version) = &self.python_version {
+            if python_version_at_least(version, (3, 8)) {
+                cargo_metadata_lines.push("cargo:rustc-cfg=pyembed_audit_hooks".to_string());
+            }
+        }
+
         let cargo_metadata = dest_dir.join("cargo_metadata.txt");
         let mut fh = File::create(&cargo_metadata)?;
         fh.write_all(cargo_metadata_lines.join("\n").as_bytes())?;
@@ -262,6 +564,7 @@ impl EmbeddedPythonBinaryData {
             importlib_bootstrap_external,
             module_names,
             embedded_resources,
+            resources_file,
             libpython,
             libpyembeddedconfig,
             config_rs,
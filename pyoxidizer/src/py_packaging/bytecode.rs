@@ -6,8 +6,9 @@
 Working with Python bytecode.
 */
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use lazy_static::lazy_static;
+use std::convert::TryFrom;
 use std::fs::File;
 use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
@@ -49,19 +50,96 @@ pub struct BytecodeCompiler {
     command: process::Child,
 }
 
+/// How a `.pyc` file is validated against its source at import time.
+///
+/// See PEP 552 and `py_compile.PycInvalidationMode`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PycInvalidationMode {
+    /// Compare the source modification time and size recorded in the header.
+    Timestamp,
+    /// Compare a hash of the source recorded in the header.
+    CheckedHash,
+    /// Record a hash of the source but don't validate it.
+    UncheckedHash,
+}
+
+impl PycInvalidationMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PycInvalidationMode::Timestamp => "timestamp",
+            PycInvalidationMode::CheckedHash => "checked-hash",
+            PycInvalidationMode::UncheckedHash => "unchecked-hash",
+        }
+    }
+}
+
+impl Default for PycInvalidationMode {
+    fn default() -> Self {
+        PycInvalidationMode::UncheckedHash
+    }
+}
+
+impl TryFrom<&str> for PycInvalidationMode {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &str) -> Result<Self> {
+        match value {
+            "timestamp" => Ok(PycInvalidationMode::Timestamp),
+            "checked-hash" => Ok(PycInvalidationMode::CheckedHash),
+            "unchecked-hash" => Ok(PycInvalidationMode::UncheckedHash),
+            _ => Err(anyhow!(
+                "{} is not a valid pyc invalidation mode; expected timestamp, checked-hash, or unchecked-hash",
+                value
+            )),
+        }
+    }
+}
+
 /// Output mode for BytecodeCompiler.
 pub enum CompileMode {
     /// Emit just Python bytecode.
     Bytecode,
+    /// Emit .pyc header with source modification time and size.
+    PycTimestamp,
     /// Emit .pyc header with hash verification.
     PycCheckedHash,
     /// Emit .pyc header with no hash verification.
     PycUncheckedHash,
 }
 
+impl From<PycInvalidationMode> for CompileMode {
+    fn from(mode: PycInvalidationMode) -> Self {
+        match mode {
+            PycInvalidationMode::Timestamp => CompileMode::PycTimestamp,
+            PycInvalidationMode::CheckedHash => CompileMode::PycCheckedHash,
+            PycInvalidationMode::UncheckedHash => CompileMode::PycUncheckedHash,
+        }
+    }
+}
+
+/// Build-time transformations of Python source code.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SourceTransforms {
+    /// Remove comments.
+    pub strip_comments: bool,
+    /// Remove docstrings and other statements consisting only of strings.
+    pub strip_docstrings: bool,
+    /// Remove comments, blank lines and whitespace that isn't significant.
+    pub minify: bool,
+    /// Share identical string constants between code objects in bytecode.
+    pub intern_strings: bool,
+}
+
+impl SourceTransforms {
+    /// Whether any transform rewrites source code.
+    pub fn transforms_source(&self) -> bool {
+        self.strip_comments || self.strip_docstrings || self.minify
+    }
+}
+
 impl BytecodeCompiler {
     pub fn new(python: &Path) -> Result<BytecodeCompiler> {
-        let temp_dir = tempdir::TempDir::new("bytecode-compiler")?;
+        let temp_dir = crate::scratch::scratch_dir("bytecode-compiler")?;
 
         let script_path = PathBuf::from(temp_dir.path()).join("bytecodecompiler.py");
 
@@ -70,8 +148,11 @@ impl BytecodeCompiler {
             fh.write_all(BYTECODE_COMPILER)?;
         }
 
+        // A fixed hash seed makes the order of set and frozenset constants
+        // in emitted bytecode deterministic.
         let command = process::Command::new(python)
             .arg(script_path)
+            .env("PYTHONHASHSEED", "0")
             .stdin(process::Stdio::piped())
             .stdout(process::Stdio::piped())
             .spawn()?;
@@ -91,9 +172,6 @@ impl BytecodeCompiler {
         output_mode: CompileMode,
     ) -> Result<Vec<u8>> {
         let stdin = self.command.stdin.as_mut().expect("failed to get stdin");
-        let stdout = self.command.stdout.as_mut().expect("failed to get stdout");
-
-        let mut reader = BufReader::new(stdout);
 
         stdin.write_all(b"compile\n")?;
         stdin.write_all(filename.len().to_string().as_bytes())?;
@@ -104,6 +182,7 @@ impl BytecodeCompiler {
         stdin.write_all(b"\n")?;
         stdin.write_all(match output_mode {
             CompileMode::Bytecode => b"bytecode",
+            CompileMode::PycTimestamp => b"pyc-timestamp",
             CompileMode::PycCheckedHash => b"pyc-checked-hash",
             CompileMode::PycUncheckedHash => b"pyc-unchecked-hash",
         })?;
@@ -112,16 +191,72 @@ impl BytecodeCompiler {
         stdin.write_all(source)?;
         stdin.flush()?;
 
+        self.read_output()
+    }
+
+    /// Apply source transforms to Python source code.
+    pub fn transform_source(
+        self: &mut BytecodeCompiler,
+        source: &[u8],
+        filename: &str,
+        transforms: &SourceTransforms,
+    ) -> Result<Vec<u8>> {
+        let mut flags = Vec::new();
+        if transforms.strip_comments {
+            flags.push("strip-comments");
+        }
+        if transforms.strip_docstrings {
+            flags.push("strip-docstrings");
+        }
+        if transforms.minify {
+            flags.push("minify");
+        }
+
+        let stdin = self.command.stdin.as_mut().expect("failed to get stdin");
+
+        stdin.write_all(b"transform\n")?;
+        stdin.write_all(filename.len().to_string().as_bytes())?;
+        stdin.write_all(b"\n")?;
+        stdin.write_all(source.len().to_string().as_bytes())?;
+        stdin.write_all(b"\n")?;
+        stdin.write_all(flags.join(",").as_bytes())?;
+        stdin.write_all(b"\n")?;
+        stdin.write_all(filename.as_bytes())?;
+        stdin.write_all(source)?;
+        stdin.flush()?;
+
+        self.read_output()
+    }
+
+    /// Share identical string constants between code objects in bytecode.
+    pub fn intern_constants(self: &mut BytecodeCompiler, bytecode: &[u8]) -> Result<Vec<u8>> {
+        let stdin = self.command.stdin.as_mut().expect("failed to get stdin");
+
+        stdin.write_all(b"intern\n")?;
+        stdin.write_all(bytecode.len().to_string().as_bytes())?;
+        stdin.write_all(b"\n")?;
+        stdin.write_all(bytecode)?;
+        stdin.flush()?;
+
+        self.read_output()
+    }
+
+    /// Read the length prefixed output of a command.
+    fn read_output(self: &mut BytecodeCompiler) -> Result<Vec<u8>> {
+        let stdout = self.command.stdout.as_mut().expect("failed to get stdout");
+
+        let mut reader = BufReader::new(stdout);
+
         let mut len_s = String::new();
         reader.read_line(&mut len_s)?;
 
         let len_s = len_s.trim_end();
-        let bytecode_len = len_s.parse::<u64>().unwrap();
+        let output_len = len_s.parse::<u64>().unwrap();
 
-        let mut bytecode: Vec<u8> = Vec::new();
-        reader.take(bytecode_len).read_to_end(&mut bytecode)?;
+        let mut output: Vec<u8> = Vec::new();
+        reader.take(output_len).read_to_end(&mut output)?;
 
-        Ok(bytecode)
+        Ok(output)
     }
 }
 
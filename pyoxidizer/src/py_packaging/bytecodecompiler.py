@@ -9,10 +9,13 @@
 
 import importlib._bootstrap_external
 import importlib.util
+import io
 import marshal
 import os
 import re
 import sys
+import tokenize
+import types
 
 
 RE_CODING = re.compile(b'^[ \t\f]*#.*?coding[:=][ \t]*([-_.a-zA-Z0-9]+)')
@@ -25,6 +28,226 @@ stdin = sys.__stdin__.buffer
 stdout = sys.__stdout__.buffer
 
 
+def decode_source(source):
+    """Decode Python source bytes.
+
+    Returns the source encoding, the decoded source and the source bytes
+    without a BOM.
+    """
+    # Default source encoding is UTF-8. But per PEP 263, the first or second
+    # line of source can match a regular expression to define a custom
+    # encoding. We need to detect custom encodings and use it to decode
+    # the passed bytes to str.
+    encoding = 'utf-8'
+
+    for line in source.splitlines()[0:2]:
+        m = RE_CODING.match(line)
+        if m:
+            encoding = m.group(1).decode('ascii')
+            break
+
+    # Someone has set us up the BOM! According to PEP 263 the file should
+    # be interpreted as UTF-8.
+    if source.startswith(b'\xef\xbb\xbf'):
+        encoding = 'utf-8'
+        source = source[3:]
+
+    return encoding, source.decode(encoding), source
+
+
+def is_plain_string(s):
+    """Whether a STRING token is a literal without side effects."""
+    prefix = s[:s.index(s[-1])]
+    return 'f' not in prefix.lower()
+
+
+def find_string_statements(tokens):
+    """Find statements consisting only of string literals.
+
+    These have no effect other than defining docstrings. Returns a dict
+    mapping the index of the first token of each statement to the index of
+    its last token and the text to replace the statement with.
+    """
+    skip = (tokenize.NL, tokenize.COMMENT, tokenize.INDENT, tokenize.DEDENT)
+
+    lines = []
+    current = []
+    for i, tok in enumerate(tokens):
+        if tok.type in skip:
+            continue
+        elif tok.type == tokenize.NEWLINE:
+            lines.append((current, i))
+            current = []
+        elif tok.type != tokenize.ENDMARKER:
+            current.append(i)
+
+    statements = {}
+
+    for indices, newline in lines:
+        if not indices or not all(
+            tokens[i].type == tokenize.STRING and is_plain_string(tokens[i].string)
+            for i in indices
+        ):
+            continue
+
+        # A block can't be empty, so a removed statement ending a block is
+        # replaced by `pass`.
+        j = newline + 1
+        while tokens[j].type in (tokenize.NL, tokenize.COMMENT):
+            j += 1
+
+        replacement = 'pass' if tokens[j].type == tokenize.DEDENT else ''
+        statements[indices[0]] = (indices[-1], replacement)
+
+    return statements
+
+
+def needs_space(prev, cur):
+    """Whether tokens must be separated by whitespace."""
+    a = prev.string[-1]
+    b = cur[0]
+
+    if (a.isalnum() or a == '_') and (b.isalnum() or b == '_'):
+        return True
+
+    # `1.real` would be parsed as a float followed by a name.
+    return prev.type == tokenize.NUMBER and prev.string.isdigit() and b == '.'
+
+
+def minify(tokens, removed):
+    """Emit source from tokens with minimal whitespace and no comments."""
+    out = []
+    depth = 0
+    line = []
+    prev = None
+
+    i = 0
+    while i < len(tokens):
+        tok = tokens[i]
+
+        if i in removed:
+            last, replacement = removed[i]
+            if replacement:
+                line.append(replacement)
+                prev = tokenize.TokenInfo(tokenize.NAME, replacement, tok.start, tok.end, tok.line)
+            i = last + 1
+            continue
+
+        if tok.type == tokenize.INDENT:
+            depth += 1
+        elif tok.type == tokenize.DEDENT:
+            depth -= 1
+        elif tok.type == tokenize.NEWLINE:
+            if line:
+                out.append(' ' * depth + ''.join(line))
+            line = []
+            prev = None
+        elif tok.type not in (tokenize.NL, tokenize.COMMENT, tokenize.ENDMARKER):
+            if prev is not None and needs_space(prev, tok.string):
+                line.append(' ')
+            line.append(tok.string)
+            prev = tok
+
+        i += 1
+
+    if line:
+        out.append(' ' * depth + ''.join(line))
+
+    return ''.join('%s\n' % l for l in out)
+
+
+def remove_spans(text, tokens, removed, strip_comments):
+    """Remove statements and comments while preserving layout."""
+    edits = [
+        (tokens[first].start, tokens[last].end, replacement)
+        for first, (last, replacement) in removed.items()
+    ]
+
+    if strip_comments:
+        edits.extend(
+            (tok.start, tok.end, '') for tok in tokens if tok.type == tokenize.COMMENT
+        )
+
+    offsets = [0]
+    for line in text.split('\n'):
+        offsets.append(offsets[-1] + len(line) + 1)
+
+    def offset(pos):
+        return offsets[pos[0] - 1] + pos[1]
+
+    for start, end, replacement in sorted(edits, reverse=True):
+        text = text[:offset(start)] + replacement + text[offset(end):]
+
+    return text
+
+
+def transform_source(source, flags):
+    """Apply source transforms to Python source bytes."""
+    encoding, text, _ = decode_source(source)
+
+    tokens = list(tokenize.generate_tokens(io.StringIO(text).readline))
+
+    if 'strip-docstrings' in flags:
+        removed = find_string_statements(tokens)
+    else:
+        removed = {}
+
+    if 'minify' in flags:
+        text = minify(tokens, removed)
+    else:
+        text = remove_spans(text, tokens, removed, 'strip-comments' in flags)
+
+    # Removing comments removes any encoding declaration.
+    if 'minify' in flags or 'strip-comments' in flags:
+        encoding = 'utf-8'
+
+    return text.encode(encoding)
+
+
+def intern_constants(code, seen):
+    """Share identical string constants between code objects.
+
+    marshal writes objects referenced multiple times once, so this shrinks
+    the serialized bytecode.
+    """
+    consts = []
+    for const in code.co_consts:
+        if isinstance(const, types.CodeType):
+            const = intern_constants(const, seen)
+        elif type(const) in (str, bytes):
+            const = seen.setdefault((type(const), const), const)
+
+        consts.append(const)
+
+    # Rebuilding a code object can lose sharing of its other fields, so
+    # only do it if a constant changed.
+    if all(a is b for a, b in zip(consts, code.co_consts)):
+        return code
+
+    consts = tuple(consts)
+
+    if hasattr(code, 'replace'):
+        return code.replace(co_consts=consts)
+
+    return types.CodeType(
+        code.co_argcount,
+        code.co_kwonlyargcount,
+        code.co_nlocals,
+        code.co_stacksize,
+        code.co_flags,
+        code.co_code,
+        consts,
+        code.co_names,
+        code.co_varnames,
+        code.co_filename,
+        code.co_name,
+        code.co_firstlineno,
+        code.co_lnotab,
+        code.co_freevars,
+        code.co_cellvars,
+    )
+
+
 while True:
     command = stdin.readline().rstrip()
 
@@ -45,32 +268,22 @@ while True:
 
         name = os.fsdecode(name)
 
-        # Default source encoding is UTF-8. But per PEP 263, the first or second
-        # line of source can match a regular expression to define a custom
-        # encoding. We need to detect custom encodings and use it to decode
-        # the passed bytes to str.
-        encoding = 'utf-8'
-
-        for line in source.splitlines()[0:2]:
-            m = RE_CODING.match(line)
-            if m:
-                encoding = m.group(1).decode('ascii')
-                break
-
-        # Someone has set us up the BOM! According to PEP 263 the file should
-        # be interpreted as UTF-8.
-        if source.startswith(b'\xef\xbb\xbf'):
-            encoding = 'utf-8'
-            source = source[3:]
-
-        source_bytes = source
-        source = source.decode(encoding)
+        _, source, source_bytes = decode_source(source)
 
         code = compile(source, name, 'exec', optimize=optimize_level)
         bytecode = marshal.dumps(code)
 
         if output_mode == b'bytecode':
             out = bytecode
+        elif output_mode == b'pyc-timestamp':
+            # There is no source file to take a modification time from. So
+            # use SOURCE_DATE_EPOCH like py_compile does for reproducibility.
+            mtime = int(os.environ.get('SOURCE_DATE_EPOCH', '0'))
+            out = importlib._bootstrap_external._code_to_timestamp_pyc(
+                code,
+                mtime,
+                len(source_bytes),
+            )
         elif output_mode == b'pyc-checked-hash':
             source_hash = importlib.util.source_hash(source_bytes)
             out = importlib._bootstrap_external._code_to_hash_pyc(
@@ -88,6 +301,29 @@ while True:
         else:
             raise Exception('unknown output mode: %s' % output_mode)
 
+        stdout.write(b'%d\n' % len(out))
+        stdout.write(out)
+        stdout.flush()
+    elif command == b'transform':
+        name_len = int(stdin.readline().rstrip())
+        source_len = int(stdin.readline().rstrip())
+        flags = stdin.readline().rstrip().decode('ascii').split(',')
+
+        name = stdin.read(name_len)
+        source = stdin.read(source_len)
+
+        out = transform_source(source, flags)
+
+        stdout.write(b'%d\n' % len(out))
+        stdout.write(out)
+        stdout.flush()
+    elif command == b'intern':
+        bytecode_len = int(stdin.readline().rstrip())
+        bytecode = stdin.read(bytecode_len)
+
+        code = intern_constants(marshal.loads(bytecode), {})
+        out = marshal.dumps(code)
+
         stdout.write(b'%d\n' % len(out))
         stdout.write(out)
         stdout.flush()
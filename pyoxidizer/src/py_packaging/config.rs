@@ -6,6 +6,14 @@
 Configuring a Python interpreter.
 */
 
+use {
+    anyhow::{anyhow, Result},
+    python_packed_resources::data::FileEmulation,
+    python_packed_resources::encryption::parse_key,
+    std::convert::TryFrom,
+    std::path::PathBuf,
+};
+
 /// Determine the default raw allocator for a target triple.
 pub fn default_raw_allocator(target_triple: &str) -> RawAllocator {
     // Jemalloc doesn't work on Windows.
@@ -33,6 +41,7 @@ pub enum RunMode {
     Module { module: String },
     Eval { code: String },
     File { path: String },
+    PythonCli,
 }
 
 /// How the `terminfo` database is resolved at run-time.
@@ -43,6 +52,51 @@ pub enum TerminfoResolution {
     Static(String),
 }
 
+/// How the integrity of embedded resources is verified at run-time.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResourcesIntegrity {
+    None,
+    Import,
+    Startup,
+}
+
+/// What happens when Python raises an audit event at run-time.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AuditAction {
+    Allow,
+    Log,
+    Block,
+}
+
+impl TryFrom<&str> for AuditAction {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "allow" => Ok(AuditAction::Allow),
+            "log" => Ok(AuditAction::Log),
+            "block" => Ok(AuditAction::Block),
+            t => Err(format!(
+                "{} is not a valid audit action; expected allow, log, or block",
+                t
+            )),
+        }
+    }
+}
+
+/// Where an application checks for updates at run-time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct UpdateChannel {
+    /// URL of the signed update manifest.
+    pub url: String,
+
+    /// Hex encoded Ed25519 public key update manifests are signed with.
+    pub public_key: String,
+
+    /// Version of the application.
+    pub version: String,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct EmbeddedPythonConfig {
     pub bytes_warning: i32,
@@ -54,23 +108,53 @@ pub struct EmbeddedPythonConfig {
     pub legacy_windows_stdio: bool,
     pub optimize_level: i64,
     pub parser_debug: bool,
+    pub pkg_resources_compat: bool,
+    pub file_emulation: FileEmulation,
+    pub file_extraction_dir: Option<String>,
+    pub lazy_import: bool,
+    pub lazy_import_allow: Vec<String>,
+    pub lazy_import_deny: Vec<String>,
     pub stdio_encoding_name: Option<String>,
     pub stdio_encoding_errors: Option<String>,
+    pub utf8_mode: bool,
+    pub windows_console_code_page: Option<u32>,
     pub unbuffered_stdio: bool,
     pub filesystem_importer: bool,
     pub quiet: bool,
     pub raw_allocator: RawAllocator,
+    pub resources_file: Option<String>,
+    pub resources_integrity: ResourcesIntegrity,
+    pub resources_key_env: Option<String>,
+    pub resources_key_file: Option<String>,
     pub run_mode: RunMode,
     pub site_import: bool,
     pub sys_frozen: bool,
     pub sys_meipass: bool,
     pub sys_paths: Vec<String>,
+    pub filesystem_import_paths: Vec<String>,
+    pub user_site_packages: Option<String>,
     pub terminfo_resolution: TerminfoResolution,
     pub use_hash_seed: bool,
     pub user_site_directory: bool,
     pub verbose: i32,
     pub write_bytecode: bool,
     pub write_modules_directory_env: Option<String>,
+    pub import_profile_env: Option<String>,
+    pub self_test: bool,
+    pub debug_console: bool,
+    pub faulthandler: bool,
+    pub crash_dir: Option<String>,
+    pub update: Option<UpdateChannel>,
+    pub splash_image: Option<PathBuf>,
+    pub inherit_environment: bool,
+    pub remove_environment: Vec<String>,
+    pub environment: Vec<(String, String)>,
+    pub dotenv: bool,
+    pub install_signal_handlers: bool,
+    pub forward_signals: Vec<String>,
+    pub import_allow: Option<Vec<String>>,
+    pub import_deny: Vec<String>,
+    pub audit_policy: Vec<(String, AuditAction)>,
 }
 
 impl Default for EmbeddedPythonConfig {
@@ -85,9 +169,17 @@ impl Default for EmbeddedPythonConfig {
             legacy_windows_stdio: false,
             optimize_level: 0,
             parser_debug: false,
+            pkg_resources_compat: false,
+            file_emulation: FileEmulation::None,
+            file_extraction_dir: None,
+            lazy_import: false,
+            lazy_import_allow: Vec::new(),
+            lazy_import_deny: Vec::new(),
             quiet: false,
             stdio_encoding_name: None,
             stdio_encoding_errors: None,
+            utf8_mode: false,
+            windows_console_code_page: None,
             unbuffered_stdio: false,
             use_hash_seed: false,
             verbose: 0,
@@ -96,12 +188,156 @@ impl Default for EmbeddedPythonConfig {
             sys_frozen: false,
             sys_meipass: false,
             sys_paths: Vec::new(),
+            filesystem_import_paths: Vec::new(),
+            user_site_packages: None,
             raw_allocator: RawAllocator::System,
+            resources_file: None,
+            resources_integrity: ResourcesIntegrity::None,
+            resources_key_env: None,
+            resources_key_file: None,
             run_mode: RunMode::Repl,
             terminfo_resolution: TerminfoResolution::None,
             user_site_directory: false,
             write_bytecode: false,
             write_modules_directory_env: None,
+            import_profile_env: None,
+            self_test: false,
+            debug_console: false,
+            faulthandler: false,
+            crash_dir: None,
+            update: None,
+            splash_image: None,
+            inherit_environment: true,
+            remove_environment: Vec::new(),
+            environment: Vec::new(),
+            dotenv: false,
+            install_signal_handlers: true,
+            forward_signals: Vec::new(),
+            import_allow: None,
+            import_deny: Vec::new(),
+            audit_policy: Vec::new(),
+        }
+    }
+}
+
+impl EmbeddedPythonConfig {
+    /// Resolve the key to encrypt embedded resources with at build time.
+    ///
+    /// Resources are encrypted if a run-time key source is defined. The key
+    /// is read from the environment variable or file that will provide it at
+    /// run-time. Returns `None` if resources aren't encrypted.
+    pub fn resolve_resources_key(&self) -> Result<Option<Vec<u8>>> {
+        if let Some(env) = &self.resources_key_env {
+            if let Ok(value) = std::env::var(env) {
+                return Ok(Some(
+                    parse_key(value.as_bytes()).map_err(|e| anyhow!("{}: {}", env, e))?,
+                ));
+            }
+        }
+
+        if let Some(path) = &self.resources_key_file {
+            if path.contains("$ORIGIN") {
+                return Err(anyhow!(
+                    "resources key file {} can only be resolved at run-time; define the key in the environment variable named by resources_key_env when building",
+                    path
+                ));
+            }
+
+            let data = std::fs::read(path).map_err(|e| anyhow!("reading {}: {}", path, e))?;
+
+            return Ok(Some(
+                parse_key(&data).map_err(|e| anyhow!("{}: {}", path, e))?,
+            ));
         }
+
+        if let Some(env) = &self.resources_key_env {
+            Err(anyhow!(
+                "environment variable {} must define the resources encryption key",
+                env
+            ))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Resolve the path of the resources file relative to the executable.
+    ///
+    /// Returns `None` if resources are embedded in the executable. The
+    /// resources file is installed next to the executable, so its path must
+    /// be relative to ``$ORIGIN``.
+    pub fn resources_file_relative_path(&self) -> Result<Option<PathBuf>> {
+        let path = match &self.resources_file {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        let prefix = "$ORIGIN/";
+
+        let relative = if path.starts_with(prefix) {
+            PathBuf::from(&path[prefix.len()..])
+        } else {
+            PathBuf::new()
+        };
+
+        if relative.as_os_str().is_empty() || relative.is_absolute() || path.contains("..") {
+            return Err(anyhow!(
+                "resources file {} must be a path under $ORIGIN",
+                path
+            ));
+        }
+
+        Ok(Some(relative))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_resources_key() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+
+        let mut config = EmbeddedPythonConfig::default();
+        assert_eq!(config.resolve_resources_key()?, None);
+
+        let path = temp_dir.path().join("key");
+        std::fs::write(&path, "01".repeat(32))?;
+        config.resources_key_file = Some(path.display().to_string());
+        assert_eq!(config.resolve_resources_key()?, Some(vec![1; 32]));
+
+        config.resources_key_env = Some("PYOXIDIZER_TEST_UNDEFINED_KEY".to_string());
+        assert_eq!(config.resolve_resources_key()?, Some(vec![1; 32]));
+
+        config.resources_key_file = Some("$ORIGIN/key".to_string());
+        assert!(config.resolve_resources_key().is_err());
+
+        config.resources_key_file = None;
+        assert!(config.resolve_resources_key().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resources_file_relative_path() -> Result<()> {
+        let mut config = EmbeddedPythonConfig::default();
+        assert_eq!(config.resources_file_relative_path()?, None);
+
+        config.resources_file = Some("$ORIGIN/lib/app.pyres".to_string());
+        assert_eq!(
+            config.resources_file_relative_path()?,
+            Some(PathBuf::from("lib/app.pyres"))
+        );
+
+        config.resources_file = Some("/opt/app.pyres".to_string());
+        assert!(config.resources_file_relative_path().is_err());
+
+        config.resources_file = Some("$ORIGIN/".to_string());
+        assert!(config.resources_file_relative_path().is_err());
+
+        config.resources_file = Some("$ORIGIN/../app.pyres".to_string());
+        assert!(config.resources_file_relative_path().is_err());
+
+        Ok(())
     }
 }
@@ -10,27 +10,26 @@ use {
     super::binary::PythonBinaryBuilder,
     super::bytecode::BytecodeCompiler,
     super::config::EmbeddedPythonConfig,
+    super::distribution_mirror::extracted_distributions_path,
+    super::download::{download_to_path, DownloadOptions},
     super::libpython::ImportlibBytecode,
+    super::pypy_distribution::PyPyDistribution,
     super::resource::{PythonResource, ResourceData, SourceModule},
     super::standalone_distribution::{ExtensionModule, StandaloneDistribution},
     super::windows_embeddable_distribution::WindowsEmbeddableDistribution,
-    crate::python_distributions::{
-        CPYTHON_STANDALONE_DYNAMIC_BY_TRIPLE, CPYTHON_STANDALONE_STATIC_BY_TRIPLE,
-        CPYTHON_WINDOWS_EMBEDDABLE_BY_TRIPLE,
-    },
+    crate::python_distributions::find_python_distribution,
+    crate::timing,
     anyhow::{anyhow, Context, Result},
     fs2::FileExt,
     serde::Deserialize,
     sha2::{Digest, Sha256},
-    slog::warn,
+    slog::{info, warn},
     std::collections::HashMap,
     std::convert::TryFrom,
-    std::fs,
     std::fs::{create_dir_all, File},
     std::io::{BufReader, Read},
     std::path::{Path, PathBuf},
     url::Url,
-    uuid::Uuid,
 };
 
 /// importlib._bootstrap source code for Python 3.7.
@@ -125,7 +124,7 @@ print(json.dumps({
 impl PythonModuleSuffixes {
     /// Resolve an instance by invoking a Python executable.
     pub fn resolve_from_python_exe(python_exe: &Path) -> Result<Self> {
-        let temp_dir = tempdir::TempDir::new("python-suffixes")?;
+        let temp_dir = crate::scratch::scratch_dir("python-suffixes")?;
         let script = temp_dir.path().join("resolve.py");
         std::fs::write(&script, PRINT_SUFFIXES)?;
 
@@ -153,6 +152,59 @@ impl PythonModuleSuffixes {
     }
 }
 
+/// Denotes an implementation of the Python language.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PythonImplementation {
+    CPython,
+    PyPy,
+}
+
+impl PythonImplementation {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PythonImplementation::CPython => "cpython",
+            PythonImplementation::PyPy => "pypy",
+        }
+    }
+}
+
+impl TryFrom<&str> for PythonImplementation {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "cpython" => Ok(PythonImplementation::CPython),
+            "pypy" => Ok(PythonImplementation::PyPy),
+            t => Err(format!(
+                "{} is not a valid Python implementation; expected cpython or pypy",
+                t
+            )),
+        }
+    }
+}
+
+/// Describes what a Python distribution can be used for.
+///
+/// Packaging code should consult these flags instead of assuming all
+/// distributions behave like CPython.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DistributionCapabilities {
+    /// The Python implementation the distribution provides.
+    pub implementation: PythonImplementation,
+
+    /// Whether the distribution can be embedded in a PyOxidizer built executable.
+    pub embeddable: bool,
+
+    /// Whether the distribution provides a statically linkable `libpython`.
+    pub static_libpython: bool,
+
+    /// Whether extension modules can be statically linked into a binary.
+    pub static_extension_modules: bool,
+
+    /// Whether extension modules can be loaded from standalone shared libraries.
+    pub dynamic_extension_modules: bool,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum PythonDistributionLocation {
     Local { local_path: String, sha256: String },
@@ -167,6 +219,9 @@ pub trait PythonDistribution {
     /// Obtain the filesystem path to a `python` executable for this distribution.
     fn python_exe_path(&self) -> &Path;
 
+    /// Describe what this distribution is capable of.
+    fn capabilities(&self) -> DistributionCapabilities;
+
     /// Obtain the X.Y Python version component. e.g. `3.7`.
     fn python_major_minor_version(&self) -> String;
 
@@ -209,16 +264,25 @@ pub trait PythonDistribution {
         preferred_variants: Option<HashMap<String, String>>,
     ) -> Result<Vec<ExtensionModule>>;
 
+    /// Obtain all variants of an extension module in this distribution.
+    ///
+    /// Returns an empty list if the distribution doesn't provide the module.
+    fn extension_module_variants(&self, name: &str) -> Vec<ExtensionModule>;
+
     /// Obtain `SourceModule` instances present in this distribution.
     fn source_modules(&self) -> Result<Vec<SourceModule>>;
 
     /// Obtain `ResourceData` instances present in this distribution.
     fn resource_datas(&self) -> Result<Vec<ResourceData>>;
 
-    /// Ensure pip is available to run in the distribution.
+    /// Ensure pip is available to run with the distribution.
     ///
-    /// Returns the path to a `pip` executable.
-    fn ensure_pip(&self, logger: &slog::Logger) -> Result<PathBuf>;
+    /// Distributions are shared with other builds, so they aren't modified. If
+    /// the distribution doesn't provide pip, it is installed in a virtualenv or
+    /// a copy of the distribution created in `dest_dir`.
+    ///
+    /// Returns the path to a Python executable able to run `pip`.
+    fn ensure_pip(&self, logger: &slog::Logger, dest_dir: &Path) -> Result<PathBuf>;
 
     /// Resolve a `distutils` installation used for building Python packages.
     ///
@@ -245,20 +309,24 @@ pub trait PythonDistribution {
     ) -> Result<Vec<PythonResource>>;
 }
 
-/// Multiple threads or processes could race to extract the archive.
-/// So we use a lock file to ensure exclusive access.
-/// TODO use more granular lock based on the output directory (possibly
-/// by putting lock in output directory itself).
+/// Multiple threads or processes could race to extract the archive or to
+/// create a directory derived from it. So we use a lock file next to the
+/// directory to ensure exclusive access.
 pub struct DistributionExtractLock {
     file: std::fs::File,
 }
 
 impl DistributionExtractLock {
     pub fn new(extract_dir: &Path) -> Result<Self> {
-        let lock_path = extract_dir
+        let parent = extract_dir
             .parent()
-            .unwrap()
-            .join("distribution-extract-lock");
+            .ok_or_else(|| anyhow!("could not resolve parent directory"))?;
+        let name = extract_dir
+            .file_name()
+            .ok_or_else(|| anyhow!("could not resolve directory name"))?;
+
+        create_dir_all(parent)?;
+        let lock_path = parent.join(format!("{}.lock", name.to_string_lossy()));
 
         let file = File::create(&lock_path)
             .context(format!("could not create {}", lock_path.display()))?;
@@ -276,6 +344,87 @@ impl Drop for DistributionExtractLock {
     }
 }
 
+/// Extract a distribution archive to `extract_dir` unless already extracted.
+///
+/// `extract` writes the content of the archive to the directory it is given.
+/// It is a temporary directory which is moved to `extract_dir` once
+/// extraction completes, so an interrupted extraction is never mistaken for
+/// an extracted distribution.
+///
+/// Extracted distributions are shared by builds of all projects. Builds must
+/// not modify them.
+pub fn extract_distribution<F>(
+    logger: &slog::Logger,
+    archive_path: &Path,
+    extract_dir: &Path,
+    extract: F,
+) -> Result<()>
+where
+    F: FnOnce(&Path) -> Result<()>,
+{
+    if extract_dir.exists() {
+        return Ok(());
+    }
+
+    let _lock = DistributionExtractLock::new(extract_dir)?;
+
+    // Another process may have extracted the archive while we waited for the lock.
+    if extract_dir.exists() {
+        return Ok(());
+    }
+
+    let _phase = timing::phase("extract Python distribution");
+    warn!(
+        logger,
+        "extracting {} to {}",
+        archive_path.display(),
+        extract_dir.display()
+    );
+
+    let parent = extract_dir
+        .parent()
+        .ok_or_else(|| anyhow!("could not resolve parent directory"))?;
+    let temp_dir = tempdir::TempDir::new_in(parent, ".extract")?;
+
+    extract(temp_dir.path()).with_context(|| format!("extracting {}", archive_path.display()))?;
+
+    std::fs::rename(temp_dir.path(), extract_dir)
+        .with_context(|| format!("moving extracted distribution to {}", extract_dir.display()))?;
+
+    Ok(())
+}
+
+/// Create a virtualenv with pip installed in `venv_dir`.
+///
+/// Returns the path to the Python executable of the virtualenv.
+pub fn create_pip_virtualenv(
+    logger: &slog::Logger,
+    python_exe: &Path,
+    venv_dir: &Path,
+) -> Result<PathBuf> {
+    warn!(
+        logger,
+        "creating virtualenv {} to run pip",
+        venv_dir.display()
+    );
+
+    let status = std::process::Command::new(python_exe)
+        .args(&["-m", "venv"])
+        .arg(venv_dir)
+        .status()
+        .with_context(|| format!("running {}", python_exe.display()))?;
+
+    if !status.success() {
+        return Err(anyhow!("error creating virtualenv {}", venv_dir.display()));
+    }
+
+    Ok(if cfg!(windows) {
+        venv_dir.join("Scripts").join("python.exe")
+    } else {
+        venv_dir.join("bin").join("python")
+    })
+}
+
 fn sha256_path(path: &PathBuf) -> Vec<u8> {
     let mut hasher = Sha256::new();
     let fh = File::open(&path).unwrap();
@@ -295,6 +444,11 @@ fn sha256_path(path: &PathBuf) -> Vec<u8> {
 }
 
 pub fn get_http_client() -> reqwest::Result<reqwest::Client> {
+    http_client_builder().build()
+}
+
+/// Obtain a builder of HTTP clients using proxies defined by environment variables.
+pub fn http_client_builder() -> reqwest::ClientBuilder {
     let mut builder = reqwest::ClientBuilder::new();
 
     for (key, value) in std::env::vars() {
@@ -317,72 +471,108 @@ pub fn get_http_client() -> reqwest::Result<reqwest::Client> {
         }
     }
 
-    builder.build()
+    builder
 }
 
-/// Ensure a Python distribution at a URL is available in a local directory.
-///
-/// The path to the downloaded and validated file is returned.
-pub fn download_distribution(url: &str, sha256: &str, cache_dir: &Path) -> Result<PathBuf> {
-    let expected_hash = hex::decode(sha256)?;
-    let u = Url::parse(url)?;
-
-    let basename = u
+/// Path a distribution downloaded from a URL is stored at in a local directory.
+fn download_cache_path(url: &Url, cache_dir: &Path) -> PathBuf {
+    let basename = url
         .path_segments()
         .expect("cannot be base path")
         .last()
         .unwrap()
         .to_string();
 
-    let cache_path = cache_dir.join(basename);
+    cache_dir.join(basename)
+}
 
-    if cache_path.exists() {
-        let file_hash = sha256_path(&cache_path);
+/// Whether a Python distribution can be resolved without network access.
+///
+/// Remote distributions must have been downloaded to `cache_dir` and match
+/// their expected SHA-256.
+pub fn is_distribution_archive_cached(
+    location: &PythonDistributionLocation,
+    cache_dir: &Path,
+) -> bool {
+    match location {
+        PythonDistributionLocation::Local { local_path, .. } => Path::new(local_path).exists(),
+        PythonDistributionLocation::Url { url, sha256 } => {
+            let url = match Url::parse(url) {
+                Ok(url) => url,
+                Err(_) => return false,
+            };
+            let cache_path = download_cache_path(&url, cache_dir);
 
-        // We don't care about timing side-channels from the string compare.
-        if file_hash == expected_hash {
-            return Ok(cache_path);
+            cache_path.exists() && hex::decode(sha256).ok() == Some(sha256_path(&cache_path))
         }
     }
+}
 
-    let mut data: Vec<u8> = Vec::new();
-
-    println!("downloading {}", u);
-    let client = get_http_client()?;
-    let mut response = client.get(u.as_str()).send()?;
-    response.read_to_end(&mut data)?;
-
-    let mut hasher = Sha256::new();
-    hasher.input(&data);
+/// Resolve the path of a distribution archive that is available without network access.
+///
+/// Local archives are used in place. Archives at URLs must have been
+/// downloaded to `cache_dir`.
+pub fn cached_distribution_archive_path(
+    location: &PythonDistributionLocation,
+    cache_dir: &Path,
+) -> Option<PathBuf> {
+    if !is_distribution_archive_cached(location, cache_dir) {
+        return None;
+    }
 
-    let url_hash = hasher.result().to_vec();
-    if url_hash != expected_hash {
-        return Err(anyhow!("sha256 of Python distribution does not validate"));
+    match location {
+        PythonDistributionLocation::Local { local_path, .. } => Some(PathBuf::from(local_path)),
+        PythonDistributionLocation::Url { url, .. } => Url::parse(url)
+            .ok()
+            .map(|url| download_cache_path(&url, cache_dir)),
     }
+}
 
-    let mut temp_cache_path = cache_path.clone();
-    temp_cache_path.set_file_name(format!("{}.tmp", Uuid::new_v4()));
+/// Ensure a Python distribution at a URL is available in a local directory.
+///
+/// The path to the downloaded and validated file is returned.
+pub fn download_distribution(
+    logger: &slog::Logger,
+    url: &str,
+    sha256: &str,
+    cache_dir: &Path,
+) -> Result<PathBuf> {
+    let expected_hash = hex::decode(sha256)?;
+    let u = Url::parse(url)?;
+    let cache_path = download_cache_path(&u, cache_dir);
 
-    fs::write(&temp_cache_path, data).context("unable to write distribution file")?;
+    if cache_path.exists() {
+        let file_hash = sha256_path(&cache_path);
 
-    fs::rename(&temp_cache_path, &cache_path)
-        .or_else(|e| -> Result<()> {
-            fs::remove_file(&temp_cache_path)
-                .context("unable to remove temporary distribution file")?;
+        // We don't care about timing side-channels from the string compare.
+        if file_hash == expected_hash {
+            return Ok(cache_path);
+        }
+    }
 
-            if cache_path.exists() {
-                download_distribution(url, sha256, cache_dir)?;
-                return Ok(());
-            }
+    let _phase = timing::phase("download Python distribution");
 
-            Err(e.into())
-        })
-        .context("unable to rename downloaded distribution file")?;
+    warn!(logger, "downloading {}", u);
+    let options = DownloadOptions::from_env()?;
+    download_to_path(
+        logger,
+        &options.http_client()?,
+        u.as_str(),
+        &expected_hash,
+        &cache_path,
+        &options,
+    )
+    .context("downloading Python distribution")?;
 
     Ok(cache_path)
 }
 
-pub fn copy_local_distribution(path: &PathBuf, sha256: &str, cache_dir: &Path) -> Result<PathBuf> {
+pub fn copy_local_distribution(
+    logger: &slog::Logger,
+    path: &PathBuf,
+    sha256: &str,
+    cache_dir: &Path,
+) -> Result<PathBuf> {
     let expected_hash = hex::decode(sha256)?;
     let basename = path.file_name().unwrap().to_str().unwrap().to_string();
     let cache_path = cache_dir.join(basename);
@@ -391,7 +581,8 @@ pub fn copy_local_distribution(path: &PathBuf, sha256: &str, cache_dir: &Path) -
         let file_hash = sha256_path(&cache_path);
 
         if file_hash == expected_hash {
-            println!(
+            info!(
+                logger,
                 "existing {} passes SHA-256 integrity check",
                 cache_path.display()
             );
@@ -405,7 +596,7 @@ pub fn copy_local_distribution(path: &PathBuf, sha256: &str, cache_dir: &Path) -
         return Err(anyhow!("sha256 of Python distribution does not validate"));
     }
 
-    println!("copying {}", path.display());
+    warn!(logger, "copying {}", path.display());
     std::fs::copy(path, &cache_path)?;
 
     Ok(cache_path)
@@ -421,6 +612,7 @@ pub fn copy_local_distribution(path: &PathBuf, sha256: &str, cache_dir: &Path) -
 ///
 /// Local filesystem paths are preferred over remote URLs if both are defined.
 pub fn resolve_python_distribution_archive(
+    logger: &slog::Logger,
     dist: &PythonDistributionLocation,
     cache_dir: &Path,
 ) -> Result<PathBuf> {
@@ -431,24 +623,25 @@ pub fn resolve_python_distribution_archive(
     match dist {
         PythonDistributionLocation::Local { local_path, sha256 } => {
             let p = PathBuf::from(local_path);
-            copy_local_distribution(&p, sha256, cache_dir)
+            copy_local_distribution(logger, &p, sha256, cache_dir)
         }
         PythonDistributionLocation::Url { url, sha256 } => {
-            download_distribution(url, sha256, cache_dir)
+            download_distribution(logger, url, sha256, cache_dir)
         }
     }
 }
 
 /// Resolve a Python distribution archive.
 ///
-/// Returns a tuple of (archive path, extract directory).
+/// Returns a tuple of (archive path, extract directory). The extract directory
+/// is keyed by the SHA-256 of the archive and shared by all projects.
 pub fn resolve_python_distribution_from_location(
     logger: &slog::Logger,
     location: &PythonDistributionLocation,
     distributions_dir: &Path,
 ) -> Result<(PathBuf, PathBuf)> {
     warn!(logger, "resolving Python distribution {:?}", location);
-    let path = resolve_python_distribution_archive(location, distributions_dir)?;
+    let path = resolve_python_distribution_archive(logger, location, distributions_dir)?;
     warn!(
         logger,
         "Python distribution available at {}",
@@ -460,13 +653,14 @@ pub fn resolve_python_distribution_from_location(
         PythonDistributionLocation::Url { sha256, .. } => sha256,
     };
 
-    let distribution_path = distributions_dir.join(format!("python.{}", distribution_hash));
+    let distribution_path = extracted_distributions_path(distributions_dir)
+        .join(format!("python.{}", distribution_hash.to_lowercase()));
 
     Ok((path, distribution_path))
 }
 
 /// Describes the flavor of a distribution.
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum DistributionFlavor {
     /// Distributions coming from the `python-build-standalone` project.
     Standalone,
@@ -479,6 +673,19 @@ pub enum DistributionFlavor {
 
     /// "Embeddable" zip-file based distributions that work on Windows.
     WindowsEmbeddable,
+
+    /// Distributions published by the PyPy project.
+    PyPy,
+}
+
+impl DistributionFlavor {
+    /// The Python implementation distributions of this flavor provide.
+    pub fn implementation(&self) -> PythonImplementation {
+        match self {
+            DistributionFlavor::PyPy => PythonImplementation::PyPy,
+            _ => PythonImplementation::CPython,
+        }
+    }
 }
 
 impl Default for DistributionFlavor {
@@ -496,6 +703,8 @@ pub fn resolve_distribution(
     location: &PythonDistributionLocation,
     dest_dir: &Path,
 ) -> Result<Box<dyn PythonDistribution>> {
+    let _phase = timing::phase("resolve Python distribution");
+
     // TODO is there a way we can define PythonDistribution::from_location()
     Ok(match flavor {
         DistributionFlavor::Standalone => Box::new(StandaloneDistribution::from_location(
@@ -513,6 +722,10 @@ pub fn resolve_distribution(
         DistributionFlavor::WindowsEmbeddable => Box::new(
             WindowsEmbeddableDistribution::from_location(logger, &location, dest_dir)?,
         ) as Box<dyn PythonDistribution>,
+
+        DistributionFlavor::PyPy => Box::new(PyPyDistribution::from_location(
+            logger, &location, dest_dir,
+        )?) as Box<dyn PythonDistribution>,
     })
 }
 
@@ -520,24 +733,13 @@ pub fn resolve_distribution(
 pub fn default_distribution_location(
     flavor: &DistributionFlavor,
     target: &str,
+    python_version: Option<&str>,
 ) -> Result<PythonDistributionLocation> {
-    let dist = match flavor {
-        DistributionFlavor::Standalone => {
-            if let Some(dist) = CPYTHON_STANDALONE_STATIC_BY_TRIPLE.get(target) {
-                Some(dist)
-            } else {
-                CPYTHON_STANDALONE_DYNAMIC_BY_TRIPLE.get(target)
-            }
-        }
-        DistributionFlavor::StandaloneStatic => CPYTHON_STANDALONE_STATIC_BY_TRIPLE.get(target),
-        DistributionFlavor::StandaloneDynamic => CPYTHON_STANDALONE_DYNAMIC_BY_TRIPLE.get(target),
-        DistributionFlavor::WindowsEmbeddable => CPYTHON_WINDOWS_EMBEDDABLE_BY_TRIPLE.get(target),
-    }
-    .ok_or_else(|| anyhow!("could not find default Python distribution for {}", target))?;
+    let dist = find_python_distribution(flavor, python_version, target)?;
 
     Ok(PythonDistributionLocation::Url {
-        url: dist.url.clone(),
-        sha256: dist.sha256.clone(),
+        url: dist.location.url.clone(),
+        sha256: dist.location.sha256.clone(),
     })
 }
 
@@ -545,15 +747,17 @@ pub fn default_distribution_location(
 ///
 /// `flavor` is the high-level type of distribution.
 /// `target` is a Rust target triple the distribution should target.
+/// `python_version` is an optional Python version prefix the distribution must match.
 /// `dest_dir` is a directory to extract the distribution to. The distribution will
 /// be extracted to a child directory of this path.
 pub fn default_distribution(
     logger: &slog::Logger,
     flavor: &DistributionFlavor,
     target: &str,
+    python_version: Option<&str>,
     dest_dir: &Path,
 ) -> Result<Box<dyn PythonDistribution>> {
-    let location = default_distribution_location(flavor, target)?;
+    let location = default_distribution_location(flavor, target, python_version)?;
 
     resolve_distribution(logger, flavor, &location, dest_dir)
 }
@@ -615,12 +819,48 @@ mod tests {
             &logger,
             &DistributionFlavor::Standalone,
             target,
+            None,
             temp_dir.path(),
         )?;
 
         Ok(())
     }
 
+    #[test]
+    fn test_extract_distribution() -> Result<()> {
+        let logger = get_logger()?;
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+        let archive_path = temp_dir.path().join("python.tar.zst");
+        let extract_dir = temp_dir.path().join("python.abc");
+
+        // Interrupted extractions leave nothing behind.
+        let res = extract_distribution(&logger, &archive_path, &extract_dir, |dest_dir| {
+            std::fs::write(dest_dir.join("partial"), b"")?;
+            Err(anyhow!("interrupted"))
+        });
+        assert!(res.is_err());
+        assert!(!extract_dir.exists());
+
+        extract_distribution(&logger, &archive_path, &extract_dir, |dest_dir| {
+            std::fs::write(dest_dir.join("PYTHON.json"), b"{}")?;
+            Ok(())
+        })?;
+        assert!(extract_dir.join("PYTHON.json").exists());
+        assert!(!extract_dir.join("partial").exists());
+
+        // Extracted distributions are reused.
+        extract_distribution(&logger, &archive_path, &extract_dir, |_| {
+            panic!("distribution extracted again")
+        })?;
+
+        let entries = std::fs::read_dir(temp_dir.path())?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        assert!(entries.iter().all(|name| !name.starts_with(".extract")));
+
+        Ok(())
+    }
+
     #[test]
     fn test_resolve_suffixes() -> Result<()> {
         let distribution = get_default_distribution()?;
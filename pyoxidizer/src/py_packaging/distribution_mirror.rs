@@ -0,0 +1,240 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Mirroring and caching of Python distribution downloads.
+
+Build machines without access to the internet can obtain Python
+distributions from an internal artifact server. Mirror rules rewrite the
+URL of a distribution before it is downloaded. Archives obtained from a
+mirror are verified against the SHA-256 of the original distribution, so a
+mirror can't substitute different content.
+
+Rules and a directory caching distributions across projects can be defined
+globally through environment variables and per project in configuration
+files. Rules defined by a project take precedence over global rules.
+
+Archives are extracted once to a directory shared by all projects, where
+extracted distributions are keyed by the SHA-256 of their archive.
+*/
+
+use {
+    super::distribution::PythonDistributionLocation,
+    anyhow::{anyhow, Result},
+    std::path::{Path, PathBuf},
+};
+
+/// Environment variable defining global distribution mirror rules.
+///
+/// Its value is a whitespace separated list of `PREFIX=REPLACEMENT` rules.
+pub const DISTRIBUTION_MIRRORS_ENV: &str = "PYOXIDIZER_DISTRIBUTION_MIRRORS";
+
+/// Environment variable defining a directory caching Python distributions.
+pub const DISTRIBUTION_CACHE_ENV: &str = "PYOXIDIZER_DISTRIBUTION_CACHE";
+
+/// Environment variable defining the directory Python distributions are extracted to.
+pub const EXTRACTED_DISTRIBUTIONS_ENV: &str = "PYOXIDIZER_EXTRACTED_DISTRIBUTIONS";
+
+/// Rewrites URLs starting with a prefix.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MirrorRule {
+    /// URL prefix the rule applies to.
+    pub prefix: String,
+
+    /// String the prefix is replaced with.
+    pub replacement: String,
+}
+
+impl MirrorRule {
+    /// Rewrite a URL, if the rule applies to it.
+    pub fn rewrite(&self, url: &str) -> Option<String> {
+        if url.starts_with(&self.prefix) {
+            Some(format!("{}{}", self.replacement, &url[self.prefix.len()..]))
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse mirror rules of the form `PREFIX=REPLACEMENT`, separated by whitespace.
+pub fn parse_mirror_rules(value: &str) -> Result<Vec<MirrorRule>> {
+    value
+        .split_whitespace()
+        .map(|rule| {
+            let pos = rule.find('=').ok_or_else(|| {
+                anyhow!("mirror rule {} is not of the form PREFIX=REPLACEMENT", rule)
+            })?;
+
+            let (prefix, replacement) = (&rule[0..pos], &rule[pos + 1..]);
+            if prefix.is_empty() || replacement.is_empty() {
+                return Err(anyhow!("mirror rule {} has an empty URL", rule));
+            }
+
+            Ok(MirrorRule {
+                prefix: prefix.to_string(),
+                replacement: replacement.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Mirror rules applying to Python distribution downloads.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DistributionMirrors {
+    /// Rules defined by the project being built.
+    project: Vec<MirrorRule>,
+
+    /// Rules defined by the environment.
+    global: Vec<MirrorRule>,
+}
+
+impl DistributionMirrors {
+    /// Construct an instance holding the global rules defined by the environment.
+    pub fn from_env() -> Result<Self> {
+        let global = match std::env::var(DISTRIBUTION_MIRRORS_ENV) {
+            Ok(value) => parse_mirror_rules(&value)
+                .map_err(|e| anyhow!("invalid {}: {}", DISTRIBUTION_MIRRORS_ENV, e))?,
+            Err(_) => Vec::new(),
+        };
+
+        Ok(Self {
+            project: Vec::new(),
+            global,
+        })
+    }
+
+    /// Add a rule defined by the project being built.
+    pub fn add_project_rule(&mut self, rule: MirrorRule) {
+        self.project.push(rule);
+    }
+
+    /// Rewrite a URL according to the first matching rule.
+    ///
+    /// Project rules are consulted before global rules.
+    pub fn rewrite_url(&self, url: &str) -> Option<String> {
+        self.project
+            .iter()
+            .chain(self.global.iter())
+            .filter_map(|rule| rule.rewrite(url))
+            .next()
+    }
+
+    /// Resolve the location a distribution should be obtained from.
+    ///
+    /// The SHA-256 of the distribution is preserved.
+    pub fn rewrite_location(
+        &self,
+        location: &PythonDistributionLocation,
+    ) -> PythonDistributionLocation {
+        match location {
+            PythonDistributionLocation::Url { url, sha256 } => match self.rewrite_url(url) {
+                Some(url) => PythonDistributionLocation::Url {
+                    url,
+                    sha256: sha256.clone(),
+                },
+                None => location.clone(),
+            },
+            PythonDistributionLocation::Local { .. } => location.clone(),
+        }
+    }
+}
+
+/// Obtain the directory caching Python distributions defined by the environment.
+pub fn distribution_cache_path_from_env() -> Option<PathBuf> {
+    match std::env::var_os(DISTRIBUTION_CACHE_ENV) {
+        Some(value) if !value.is_empty() => Some(PathBuf::from(value)),
+        _ => None,
+    }
+}
+
+/// Obtain the directory Python distribution archives are extracted to.
+///
+/// This is the directory defined by the environment, or a directory in the
+/// user's cache directory. `fallback` is used if the user doesn't have a
+/// cache directory.
+pub fn extracted_distributions_path(fallback: &Path) -> PathBuf {
+    match std::env::var_os(EXTRACTED_DISTRIBUTIONS_ENV) {
+        Some(value) if !value.is_empty() => PathBuf::from(value),
+        _ => match dirs::cache_dir() {
+            Some(path) => path.join("pyoxidizer").join("python_distributions"),
+            None => fallback.to_path_buf(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PBS_URL: &str = "https://github.com/indygreg/python-build-standalone/releases/download/";
+
+    #[test]
+    fn test_parse_mirror_rules() -> Result<()> {
+        let rules = parse_mirror_rules(&format!(
+            "  {}=https://artifacts.example.com/pbs/\n https://a/=https://b/ ",
+            PBS_URL
+        ))?;
+        assert_eq!(
+            rules,
+            vec![
+                MirrorRule {
+                    prefix: PBS_URL.to_string(),
+                    replacement: "https://artifacts.example.com/pbs/".to_string(),
+                },
+                MirrorRule {
+                    prefix: "https://a/".to_string(),
+                    replacement: "https://b/".to_string(),
+                },
+            ]
+        );
+
+        assert!(parse_mirror_rules("").unwrap().is_empty());
+        assert!(parse_mirror_rules("https://a/").is_err());
+        assert!(parse_mirror_rules("=https://b/").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewrite_location() {
+        let mut mirrors = DistributionMirrors {
+            project: Vec::new(),
+            global: vec![MirrorRule {
+                prefix: PBS_URL.to_string(),
+                replacement: "https://global.example.com/".to_string(),
+            }],
+        };
+
+        let location = PythonDistributionLocation::Url {
+            url: format!("{}20200517/cpython-3.7.7.tar.zst", PBS_URL),
+            sha256: "deadbeef".to_string(),
+        };
+
+        assert_eq!(
+            mirrors.rewrite_location(&location),
+            PythonDistributionLocation::Url {
+                url: "https://global.example.com/20200517/cpython-3.7.7.tar.zst".to_string(),
+                sha256: "deadbeef".to_string(),
+            }
+        );
+
+        mirrors.add_project_rule(MirrorRule {
+            prefix: PBS_URL.to_string(),
+            replacement: "https://project.example.com/".to_string(),
+        });
+        assert_eq!(
+            mirrors.rewrite_location(&location),
+            PythonDistributionLocation::Url {
+                url: "https://project.example.com/20200517/cpython-3.7.7.tar.zst".to_string(),
+                sha256: "deadbeef".to_string(),
+            }
+        );
+
+        let other = PythonDistributionLocation::Url {
+            url: "https://example.com/python.tar.zst".to_string(),
+            sha256: "deadbeef".to_string(),
+        };
+        assert_eq!(mirrors.rewrite_location(&other), other);
+    }
+}
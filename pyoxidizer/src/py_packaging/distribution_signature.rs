@@ -0,0 +1,276 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Signature verification of Python distribution archives.
+
+The SHA-256 of every distribution archive is always verified. In addition,
+configuration files can pin GPG or minisign public keys. Archives are then
+verified against a detached signature next to them, named after the archive
+with an `.asc` (GPG) or `.minisig` (minisign) suffix.
+
+Signatures are verified with the `gpg` and `minisign` programs, which must
+be in `PATH`.
+
+When keys are pinned, archives that can't be verified with one of them are
+refused. In locked mode, archives are refused when no keys are pinned too.
+*/
+
+use {
+    super::distribution::{get_http_client, PythonDistributionLocation},
+    anyhow::{anyhow, Context, Result},
+    slog::warn,
+    std::io::Read,
+    std::path::{Path, PathBuf},
+};
+
+/// Environment variable enabling locked mode.
+///
+/// The `--locked` argument of `pyoxidizer` sets it.
+pub const LOCKED_ENV: &str = "PYOXIDIZER_LOCKED";
+
+/// Whether builds must only use verified Python distributions.
+pub fn use_locked_mode() -> bool {
+    std::env::var_os(LOCKED_ENV).is_some()
+}
+
+/// A public key Python distributions can be signed with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SigningKey {
+    /// A file holding an exported GPG public key.
+    Gpg { path: PathBuf },
+
+    /// A base64 encoded minisign public key.
+    Minisign { public_key: String },
+}
+
+impl SigningKey {
+    /// Suffix of the filename of detached signatures made with this key.
+    pub fn signature_suffix(&self) -> &'static str {
+        match self {
+            SigningKey::Gpg { .. } => ".asc",
+            SigningKey::Minisign { .. } => ".minisig",
+        }
+    }
+
+    /// Verify a detached signature of a file with this key.
+    pub fn verify(&self, path: &Path, signature_path: &Path) -> Result<()> {
+        let output = match self {
+            SigningKey::Gpg { path: key_path } => {
+                // Verify against a keyring holding only the pinned key.
+                let home = crate::scratch::scratch_dir("pyoxidizer-gpg")?;
+
+                let output = std::process::Command::new("gpg")
+                    .arg("--batch")
+                    .arg("--homedir")
+                    .arg(home.path())
+                    .arg("--import")
+                    .arg(key_path)
+                    .output()
+                    .context("running gpg")?;
+
+                if !output.status.success() {
+                    return Err(anyhow!(
+                        "unable to import GPG key {}: {}",
+                        key_path.display(),
+                        String::from_utf8_lossy(&output.stderr)
+                    ));
+                }
+
+                std::process::Command::new("gpg")
+                    .arg("--batch")
+                    .arg("--homedir")
+                    .arg(home.path())
+                    .arg("--verify")
+                    .arg(signature_path)
+                    .arg(path)
+                    .output()
+                    .context("running gpg")?
+            }
+            SigningKey::Minisign { public_key } => std::process::Command::new("minisign")
+                .arg("-V")
+                .arg("-q")
+                .arg("-P")
+                .arg(public_key)
+                .arg("-m")
+                .arg(path)
+                .arg("-x")
+                .arg(signature_path)
+                .output()
+                .context("running minisign")?,
+        };
+
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "signature {} does not verify: {}",
+                signature_path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+}
+
+/// Verifies Python distribution archives against pinned keys.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DistributionVerifier {
+    /// Keys distributions may be signed with.
+    pub keys: Vec<SigningKey>,
+
+    /// Whether archives that can't be verified are refused.
+    pub locked: bool,
+}
+
+impl DistributionVerifier {
+    /// Obtain the detached signature of an archive, if there is one.
+    ///
+    /// Signatures of remote distributions are downloaded next to the
+    /// archive in the cache directory, unless `offline`.
+    fn resolve_signature(
+        &self,
+        logger: &slog::Logger,
+        location: &PythonDistributionLocation,
+        archive_path: &Path,
+        suffix: &str,
+        offline: bool,
+    ) -> Result<Option<PathBuf>> {
+        match location {
+            PythonDistributionLocation::Local { local_path, .. } => {
+                let path = PathBuf::from(format!("{}{}", local_path, suffix));
+
+                Ok(if path.exists() { Some(path) } else { None })
+            }
+            PythonDistributionLocation::Url { url, .. } => {
+                let mut path = archive_path.as_os_str().to_os_string();
+                path.push(suffix);
+                let path = PathBuf::from(path);
+
+                if path.exists() {
+                    return Ok(Some(path));
+                } else if offline {
+                    return Ok(None);
+                }
+
+                let url = format!("{}{}", url, suffix);
+                warn!(logger, "downloading {}", url);
+                let mut response = get_http_client()?.get(&url).send()?;
+
+                if !response.status().is_success() {
+                    return Ok(None);
+                }
+
+                let mut data = Vec::new();
+                response.read_to_end(&mut data)?;
+                std::fs::write(&path, data)
+                    .with_context(|| format!("writing {}", path.display()))?;
+
+                Ok(Some(path))
+            }
+        }
+    }
+
+    /// Verify the signature of a distribution archive.
+    ///
+    /// `archive_path` is the local copy of the archive, whose SHA-256 has been
+    /// verified. When keys are pinned, the archive must have a signature
+    /// verifying with one of them. When none are, only locked mode is an
+    /// error.
+    pub fn verify(
+        &self,
+        logger: &slog::Logger,
+        location: &PythonDistributionLocation,
+        archive_path: &Path,
+        offline: bool,
+    ) -> Result<()> {
+        if self.keys.is_empty() {
+            return if self.locked {
+                Err(anyhow!(
+                    "refusing to use unverified Python distribution {} in locked mode; pin signing keys with set_distribution_signing_keys()",
+                    archive_path.display()
+                ))
+            } else {
+                Ok(())
+            };
+        }
+
+        let mut errors = Vec::new();
+
+        for key in &self.keys {
+            let signature_path = match self.resolve_signature(
+                logger,
+                location,
+                archive_path,
+                key.signature_suffix(),
+                offline,
+            )? {
+                Some(path) => path,
+                None => continue,
+            };
+
+            match key.verify(archive_path, &signature_path) {
+                Ok(()) => {
+                    warn!(logger, "verified signature {}", signature_path.display());
+                    return Ok(());
+                }
+                Err(e) => errors.push(e.to_string()),
+            }
+        }
+
+        if errors.is_empty() {
+            Err(anyhow!(
+                "no signature found for Python distribution {}; it must be signed by a pinned key",
+                archive_path.display()
+            ))
+        } else {
+            Err(anyhow!(
+                "Python distribution {} is not signed by a pinned key:\n{}",
+                archive_path.display(),
+                errors.join("\n")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::testutil::*};
+
+    #[test]
+    fn test_verify_unsigned() -> Result<()> {
+        let logger = get_logger()?;
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+        let archive_path = temp_dir.path().join("python.tar.zst");
+        std::fs::write(&archive_path, b"archive")?;
+
+        let location = PythonDistributionLocation::Local {
+            local_path: archive_path.display().to_string(),
+            sha256: "deadbeef".to_string(),
+        };
+
+        let mut verifier = DistributionVerifier::default();
+        assert!(verifier
+            .verify(&logger, &location, &archive_path, true)
+            .is_ok());
+
+        verifier.locked = true;
+        assert!(verifier
+            .verify(&logger, &location, &archive_path, true)
+            .is_err());
+
+        verifier.keys.push(SigningKey::Minisign {
+            public_key: "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3".to_string(),
+        });
+        assert!(verifier
+            .verify(&logger, &location, &archive_path, true)
+            .is_err());
+
+        verifier.locked = false;
+        assert!(verifier
+            .verify(&logger, &location, &archive_path, true)
+            .is_err());
+
+        Ok(())
+    }
+}
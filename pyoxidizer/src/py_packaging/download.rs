@@ -0,0 +1,675 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Downloading files over flaky networks.
+
+Downloads are retried with exponential backoff when the network fails or the
+server reports a transient error. Data received before a failure is kept in
+a `.partial` file next to the destination and the download resumes from it
+with an HTTP range request, so large Python distributions don't restart from
+scratch. Downloads that failed for good aren't attempted again by the same
+process, so builds resolving a distribution several times fail fast.
+
+Errors are `DownloadError` values, which tell network failures apart from
+content not matching its expected SHA-256.
+*/
+
+use {
+    super::distribution::http_client_builder,
+    crate::progress::Progress,
+    anyhow::{anyhow, Context, Result},
+    fs2::FileExt,
+    lazy_static::lazy_static,
+    reqwest::header::RANGE,
+    reqwest::StatusCode,
+    sha2::{Digest, Sha256},
+    slog::warn,
+    std::collections::HashMap,
+    std::fmt::{Display, Formatter},
+    std::fs::{File, OpenOptions},
+    std::io::{Read, Seek, SeekFrom, Write},
+    std::path::{Path, PathBuf},
+    std::sync::Mutex,
+    std::time::Duration,
+};
+
+/// Environment variable defining how many times failed downloads are retried.
+pub const DOWNLOAD_RETRIES_ENV: &str = "PYOXIDIZER_DOWNLOAD_RETRIES";
+
+/// Environment variable defining the network timeout of downloads, in seconds.
+pub const DOWNLOAD_TIMEOUT_ENV: &str = "PYOXIDIZER_DOWNLOAD_TIMEOUT";
+
+/// Longest delay between attempts to download a file.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    /// Downloads that failed for good, by URL.
+    static ref FAILED_DOWNLOADS: Mutex<HashMap<String, DownloadError>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Controls how files are downloaded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DownloadOptions {
+    /// How many times a failed download is retried.
+    pub retries: u32,
+
+    /// How long to wait for connecting to a server or receiving data.
+    pub timeout: Duration,
+
+    /// Delay before the first retry. It doubles with every retry.
+    pub backoff: Duration,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            retries: 4,
+            timeout: Duration::from_secs(60),
+            backoff: Duration::from_secs(1),
+        }
+    }
+}
+
+impl DownloadOptions {
+    /// Obtain options from environment variables, falling back to defaults.
+    pub fn from_env() -> Result<Self> {
+        let mut options = DownloadOptions::default();
+
+        if let Ok(value) = std::env::var(DOWNLOAD_RETRIES_ENV) {
+            options.retries = value.parse().map_err(|_| {
+                anyhow!(
+                    "{} must be a number of retries; got {}",
+                    DOWNLOAD_RETRIES_ENV,
+                    value
+                )
+            })?;
+        }
+
+        if let Ok(value) = std::env::var(DOWNLOAD_TIMEOUT_ENV) {
+            let seconds = value
+                .parse()
+                .ok()
+                .filter(|seconds| *seconds > 0)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "{} must be a positive number of seconds; got {}",
+                        DOWNLOAD_TIMEOUT_ENV,
+                        value
+                    )
+                })?;
+            options.timeout = Duration::from_secs(seconds);
+        }
+
+        Ok(options)
+    }
+
+    /// Delay before retrying after a number of failed attempts.
+    pub fn backoff_delay(&self, failed_attempts: u32) -> Duration {
+        let factor = 1u32 << std::cmp::min(failed_attempts.saturating_sub(1), 16);
+
+        std::cmp::min(self.backoff * factor, MAX_BACKOFF)
+    }
+
+    /// Obtain an HTTP client honoring the timeout.
+    pub fn http_client(&self) -> Result<reqwest::Client> {
+        Ok(http_client_builder().timeout(self.timeout).build()?)
+    }
+
+    /// Environment variables making pip honor these options.
+    pub fn pip_envs(&self) -> HashMap<String, String> {
+        let mut envs = HashMap::new();
+
+        envs.insert("PIP_RETRIES".to_string(), self.retries.to_string());
+        envs.insert(
+            "PIP_DEFAULT_TIMEOUT".to_string(),
+            self.timeout.as_secs().to_string(),
+        );
+
+        envs
+    }
+}
+
+/// Why a download failed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DownloadError {
+    /// The server couldn't be reached or the transfer failed, even after retrying.
+    Network {
+        url: String,
+        attempts: u32,
+        message: String,
+    },
+
+    /// The server responded with an error that retrying won't fix.
+    Status { url: String, status: u16 },
+
+    /// The downloaded content doesn't have the expected SHA-256.
+    Checksum {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+}
+
+impl Display for DownloadError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            DownloadError::Network {
+                url,
+                attempts,
+                message,
+            } => write!(
+                f,
+                "network error downloading {} (gave up after {} attempts): {}",
+                url, attempts, message
+            ),
+            DownloadError::Status { url, status } => {
+                write!(f, "server responded with HTTP {} for {}", status, url)
+            }
+            DownloadError::Checksum {
+                url,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "content downloaded from {} has SHA-256 {}, expected {}; the file changed or was tampered with",
+                url, actual, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+/// The result of an attempt to download a file.
+enum Attempt {
+    /// All data was received.
+    Complete,
+
+    /// The attempt failed, but another may succeed.
+    Retry(String),
+
+    /// The download can't succeed.
+    Fail(DownloadError),
+}
+
+/// Compute the SHA-256 of a file.
+fn sha256_file(path: &Path) -> Result<Vec<u8>> {
+    let mut hasher = Sha256::new();
+    let mut fh = File::open(path)?;
+
+    let mut buffer = [0; 32768];
+
+    loop {
+        let count = fh.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.input(&buffer[..count]);
+    }
+
+    Ok(hasher.result().to_vec())
+}
+
+/// Whether an HTTP status indicates a transient error.
+fn is_transient_status(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Attempt to download a URL, appending to the data in `partial`.
+fn attempt_download(
+    logger: &slog::Logger,
+    client: &reqwest::Client,
+    url: &str,
+    partial: &mut File,
+) -> Result<Attempt> {
+    let offset = partial.seek(SeekFrom::End(0))?;
+
+    let mut request = client.get(url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    }
+
+    let mut response = match request.send() {
+        Ok(response) => response,
+        Err(e) => return Ok(Attempt::Retry(e.to_string())),
+    };
+
+    let status = response.status();
+
+    let offset = if status == StatusCode::PARTIAL_CONTENT && offset > 0 {
+        warn!(logger, "resuming download of {} at byte {}", url, offset);
+        offset
+    } else if status.is_success() {
+        // The server sent the whole file.
+        partial.set_len(0)?;
+        partial.seek(SeekFrom::Start(0))?;
+        0
+    } else if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        // The partial data doesn't belong to the file being served.
+        partial.set_len(0)?;
+        return Ok(Attempt::Retry(format!("HTTP {}", status.as_u16())));
+    } else if is_transient_status(status) {
+        return Ok(Attempt::Retry(format!("HTTP {}", status.as_u16())));
+    } else {
+        return Ok(Attempt::Fail(DownloadError::Status {
+            url: url.to_string(),
+            status: status.as_u16(),
+        }));
+    };
+
+    let mut progress = Progress::bytes(
+        logger,
+        "downloading",
+        response.content_length().map(|l| l + offset),
+    );
+    progress.inc(offset);
+
+    let mut buffer = [0; 32768];
+    loop {
+        let count = match response.read(&mut buffer) {
+            Ok(count) => count,
+            Err(e) => {
+                partial.flush()?;
+                return Ok(Attempt::Retry(e.to_string()));
+            }
+        };
+        if count == 0 {
+            break;
+        }
+
+        partial.write_all(&buffer[..count])?;
+        progress.inc(count as u64);
+    }
+
+    partial.flush()?;
+    progress.finish();
+
+    Ok(Attempt::Complete)
+}
+
+/// Download a URL to a path, verifying its SHA-256.
+///
+/// Failed attempts are retried as defined by `options`, resuming from the
+/// data received so far. `dest` is only written once all data was received
+/// and verified. Errors are `DownloadError` values, unless writing files
+/// failed.
+pub fn download_to_path(
+    logger: &slog::Logger,
+    client: &reqwest::Client,
+    url: &str,
+    sha256: &[u8],
+    dest: &Path,
+    options: &DownloadOptions,
+) -> Result<()> {
+    if let Some(error) = FAILED_DOWNLOADS.lock().unwrap().get(url) {
+        warn!(
+            logger,
+            "not retrying download of {}, which failed earlier", url
+        );
+        return Err(error.clone().into());
+    }
+
+    let partial_path = path_with_suffix(dest, ".partial");
+    let lock_path = path_with_suffix(dest, ".lock");
+
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("creating {}", parent.display()))?;
+    }
+
+    // Other processes may be downloading the same file.
+    let lock = File::create(&lock_path)
+        .with_context(|| format!("could not create {}", lock_path.display()))?;
+    lock.lock_exclusive()
+        .with_context(|| format!("failed to obtain lock for {}", lock_path.display()))?;
+
+    let res = download_locked(logger, client, url, sha256, dest, &partial_path, options);
+
+    lock.unlock()?;
+
+    if let Err(e) = &res {
+        if let Some(error) = e.downcast_ref::<DownloadError>() {
+            FAILED_DOWNLOADS
+                .lock()
+                .unwrap()
+                .insert(url.to_string(), error.clone());
+        }
+    }
+
+    res
+}
+
+fn download_locked(
+    logger: &slog::Logger,
+    client: &reqwest::Client,
+    url: &str,
+    sha256: &[u8],
+    dest: &Path,
+    partial_path: &Path,
+    options: &DownloadOptions,
+) -> Result<()> {
+    // Another process may have downloaded the file while we waited for the lock.
+    if dest.exists() && sha256_file(dest)? == sha256 {
+        return Ok(());
+    }
+
+    let mut partial = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(partial_path)
+        .with_context(|| format!("opening {}", partial_path.display()))?;
+
+    let mut attempts = 0;
+    let mut restarted = false;
+
+    loop {
+        attempts += 1;
+
+        let offset = partial.metadata()?.len();
+
+        let error = match attempt_download(logger, client, url, &mut partial)? {
+            Attempt::Complete => {
+                let actual = sha256_file(partial_path)?;
+
+                if actual == sha256 {
+                    break;
+                }
+
+                partial.set_len(0)?;
+
+                // Data received before a failure may not belong to the file
+                // served now. Download it once more from scratch.
+                if offset > 0 && !restarted {
+                    warn!(
+                        logger,
+                        "SHA-256 of {} doesn't match after resuming at byte {}; restarting download",
+                        url,
+                        offset
+                    );
+                    restarted = true;
+                    continue;
+                }
+
+                std::fs::remove_file(partial_path)?;
+
+                return Err(DownloadError::Checksum {
+                    url: url.to_string(),
+                    expected: hex::encode(sha256),
+                    actual: hex::encode(actual),
+                }
+                .into());
+            }
+            Attempt::Retry(message) => message,
+            Attempt::Fail(error) => return Err(error.into()),
+        };
+
+        if attempts > options.retries {
+            return Err(DownloadError::Network {
+                url: url.to_string(),
+                attempts,
+                message: error,
+            }
+            .into());
+        }
+
+        let delay = options.backoff_delay(attempts);
+        warn!(
+            logger,
+            "download of {} failed (attempt {} of {}): {}; retrying in {}s",
+            url,
+            attempts,
+            options.retries + 1,
+            error,
+            delay.as_secs()
+        );
+        std::thread::sleep(delay);
+    }
+
+    drop(partial);
+
+    std::fs::rename(partial_path, dest)
+        .with_context(|| format!("renaming {} to {}", partial_path.display(), dest.display()))?;
+
+    Ok(())
+}
+
+fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut s = path.as_os_str().to_owned();
+    s.push(suffix);
+
+    PathBuf::from(s)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::io::{BufRead, BufReader},
+        std::net::TcpListener,
+    };
+
+    /// Serve canned responses, one per connection.
+    ///
+    /// Returns the URL to request and a handle resolving to the received
+    /// request headers.
+    fn serve(
+        name: &str,
+        responses: Vec<Vec<u8>>,
+    ) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/{}", listener.local_addr().unwrap(), name);
+
+        let handle = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    request.push_str(&line.to_lowercase());
+                }
+                requests.push(request);
+
+                stream.write_all(&response).unwrap();
+            }
+
+            requests
+        });
+
+        (url, handle)
+    }
+
+    fn response(status: &str, headers: &str, body: &[u8]) -> Vec<u8> {
+        let mut res = format!(
+            "HTTP/1.1 {}\r\n{}Connection: close\r\n\r\n",
+            status, headers
+        )
+        .into_bytes();
+        res.extend_from_slice(body);
+
+        res
+    }
+
+    fn sha256(data: &[u8]) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.input(data);
+        hasher.result().to_vec()
+    }
+
+    fn options() -> DownloadOptions {
+        DownloadOptions {
+            retries: 2,
+            timeout: Duration::from_secs(10),
+            backoff: Duration::from_millis(1),
+        }
+    }
+
+    fn client() -> reqwest::Client {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_backoff_delay() {
+        let options = DownloadOptions::default();
+
+        assert_eq!(options.backoff_delay(1), Duration::from_secs(1));
+        assert_eq!(options.backoff_delay(2), Duration::from_secs(2));
+        assert_eq!(options.backoff_delay(4), Duration::from_secs(8));
+        assert_eq!(options.backoff_delay(100), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_pip_envs() {
+        let envs = DownloadOptions::default().pip_envs();
+
+        assert_eq!(envs.get("PIP_RETRIES"), Some(&"4".to_string()));
+        assert_eq!(envs.get("PIP_DEFAULT_TIMEOUT"), Some(&"60".to_string()));
+    }
+
+    #[test]
+    fn test_download_resumes() -> Result<()> {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+        let dest = temp_dir.path().join("python.tar.zst");
+
+        // The connection is closed before all data is sent.
+        let (url, server) = serve(
+            "resume.tar.zst",
+            vec![
+                response("200 OK", "Content-Length: 10\r\n", b"01234"),
+                response(
+                    "206 Partial Content",
+                    "Content-Length: 5\r\nContent-Range: bytes 5-9/10\r\n",
+                    b"56789",
+                ),
+            ],
+        );
+
+        download_to_path(
+            &logger,
+            &client(),
+            &url,
+            &sha256(b"0123456789"),
+            &dest,
+            &options(),
+        )?;
+
+        let requests = server.join().unwrap();
+        assert!(!requests[0].contains("range:"));
+        assert!(requests[1].contains("range: bytes=5-"));
+
+        assert_eq!(std::fs::read(&dest)?, b"0123456789");
+        assert!(!path_with_suffix(&dest, ".partial").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_download_errors() -> Result<()> {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+        let dest = temp_dir.path().join("python.tar.zst");
+
+        let (url, server) = serve(
+            "checksum.tar.zst",
+            vec![response("200 OK", "Content-Length: 3\r\n", b"bad")],
+        );
+        let err = download_to_path(
+            &logger,
+            &client(),
+            &url,
+            &sha256(b"good"),
+            &dest,
+            &options(),
+        )
+        .unwrap_err();
+        server.join().unwrap();
+
+        match err.downcast_ref::<DownloadError>() {
+            Some(DownloadError::Checksum { actual, .. }) => {
+                assert_eq!(actual, &hex::encode(sha256(b"bad")));
+            }
+            _ => panic!("unexpected error: {}", err),
+        }
+        assert!(!dest.exists());
+        assert!(!path_with_suffix(&dest, ".partial").exists());
+
+        // Errors that retrying won't fix aren't retried.
+        let (url, server) = serve(
+            "missing.tar.zst",
+            vec![response("404 Not Found", "Content-Length: 0\r\n", b"")],
+        );
+        let err = download_to_path(
+            &logger,
+            &client(),
+            &url,
+            &sha256(b"good"),
+            &dest,
+            &options(),
+        )
+        .unwrap_err();
+        server.join().unwrap();
+
+        assert_eq!(
+            err.downcast_ref::<DownloadError>(),
+            Some(&DownloadError::Status {
+                url: url.clone(),
+                status: 404
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_download_not_retried() -> Result<()> {
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+        let dest = temp_dir.path().join("python.tar.zst");
+
+        let unavailable = response("503 Service Unavailable", "Content-Length: 0\r\n", b"");
+        let (url, server) = serve("unavailable.tar.zst", vec![unavailable; 3]);
+
+        let err = download_to_path(&logger, &client(), &url, b"", &dest, &options()).unwrap_err();
+        server.join().unwrap();
+
+        match err.downcast_ref::<DownloadError>() {
+            Some(DownloadError::Network {
+                attempts, message, ..
+            }) => {
+                assert_eq!(*attempts, 3);
+                assert_eq!(message, "HTTP 503");
+            }
+            _ => panic!("unexpected error: {}", err),
+        }
+
+        // The server is gone. The failure is reported without connecting.
+        let mut more_retries = options();
+        more_retries.retries = 10;
+
+        let err =
+            download_to_path(&logger, &client(), &url, b"", &dest, &more_retries).unwrap_err();
+        match err.downcast_ref::<DownloadError>() {
+            Some(DownloadError::Network { attempts, .. }) => assert_eq!(*attempts, 3),
+            _ => panic!("unexpected error: {}", err),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,720 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Python distributions resolved from metadata in dry run mode.
+
+Dry runs, like `pyoxidizer check`, evaluate config files without downloading
+or extracting Python distributions. The distributions config files refer to
+are stood in for by a `DryRunDistribution`, which knows what the index of
+known distributions says about the distribution archive.
+
+When a standalone distribution is available locally, because it is a local
+archive or was downloaded or extracted by a previous build, its modules,
+resources and extension modules are resolved from its `PYTHON.json` and file
+listing, without extracting it. So executables report the resources they
+would hold. The content of the resources isn't read.
+
+A `DryRunDistribution` has no Python interpreter. Executables built from it
+can't be built.
+*/
+
+use {
+    super::binary::{
+        EmbeddedPythonBinaryData, LibpythonLinkMode, PythonBinaryBuilder, WindowsCrtLinkage,
+    },
+    super::bytecode::{BytecodeCompiler, SourceTransforms},
+    super::config::{EmbeddedPythonConfig, RawAllocator, RunMode},
+    super::distribution::{
+        cached_distribution_archive_path, is_stdlib_test_package, DistributionCapabilities,
+        DistributionFlavor, ExtensionModuleFilter, PythonDistribution, PythonDistributionLocation,
+        PythonImplementation, PythonModuleSuffixes,
+    },
+    super::distribution_mirror::extracted_distributions_path,
+    super::embedded_resource::{
+        ConflictPolicy, EmbeddedPythonResourcesPrePackaged, ResourceConflict,
+    },
+    super::libpython::ImportlibBytecode,
+    super::resource::{
+        BytecodeModule, BytecodeOptimizationLevel, DataLocation, DistributionResource,
+        ExtensionModuleData, PythonResource, ResourceData, SourceModule,
+    },
+    super::standalone_distribution::{
+        filter_distribution_extension_modules, ExtensionModule, StandaloneDistributionLinkMode,
+        StandaloneDistributionManifest,
+    },
+    crate::app_packaging::resource::FileManifest,
+    crate::licensing::LicensedComponent,
+    crate::python_distributions::find_python_distribution_by_sha256,
+    anyhow::{anyhow, Result},
+    python_packed_resources::data::FileEmulation,
+    std::collections::{BTreeMap, BTreeSet, HashMap},
+    std::path::{Path, PathBuf},
+};
+
+/// A Python distribution known from its metadata only.
+#[derive(Clone, Debug)]
+pub struct DryRunDistribution {
+    /// The flavor of the distribution.
+    ///
+    /// Distributions in the index resolve `DistributionFlavor::Standalone`
+    /// to the link mode of the archive.
+    pub flavor: DistributionFlavor,
+
+    /// Full version of Python, if the distribution is in the index.
+    pub python_version: Option<String>,
+
+    /// Resources of the distribution, if it is available locally.
+    pub manifest: Option<StandaloneDistributionManifest>,
+
+    /// Always empty, as the distribution isn't extracted.
+    python_exe: PathBuf,
+}
+
+/// Resolve the resources of a standalone distribution available locally.
+///
+/// An extracted copy in the cache of extracted distributions is preferred.
+/// Otherwise, a local archive or an archive downloaded to `distributions_dir`
+/// is read.
+fn resolve_manifest(
+    location: &PythonDistributionLocation,
+    sha256: &str,
+    distributions_dir: &Path,
+) -> Result<Option<StandaloneDistributionManifest>> {
+    let extract_dir = extracted_distributions_path(distributions_dir)
+        .join(format!("python.{}", sha256.to_lowercase()));
+
+    if extract_dir.join("python").join("PYTHON.json").exists() {
+        return Ok(Some(StandaloneDistributionManifest::from_directory(
+            &extract_dir,
+        )?));
+    }
+
+    match cached_distribution_archive_path(location, distributions_dir) {
+        Some(path) if path.to_string_lossy().ends_with(".tar.zst") => Ok(Some(
+            StandaloneDistributionManifest::from_tar_zst_file(&path)?,
+        )),
+        _ => Ok(None),
+    }
+}
+
+impl DryRunDistribution {
+    /// Resolve a distribution from its location, without obtaining it.
+    ///
+    /// Local archives must exist. Archives at URLs aren't accessed, but
+    /// archives previously downloaded to `distributions_dir` are read.
+    pub fn from_location(
+        flavor: &DistributionFlavor,
+        location: &PythonDistributionLocation,
+        distributions_dir: &Path,
+    ) -> Result<Self> {
+        let sha256 = match location {
+            PythonDistributionLocation::Local { local_path, sha256 } => {
+                if !Path::new(local_path).exists() {
+                    return Err(anyhow!("{} does not exist", local_path));
+                }
+
+                sha256
+            }
+            PythonDistributionLocation::Url { sha256, .. } => sha256,
+        };
+
+        let (flavor, python_version) = match find_python_distribution_by_sha256(sha256) {
+            Some(record) => (record.flavor.clone(), Some(record.python_version.clone())),
+            None => (flavor.clone(), None),
+        };
+
+        let manifest = match flavor {
+            DistributionFlavor::Standalone
+            | DistributionFlavor::StandaloneStatic
+            | DistributionFlavor::StandaloneDynamic => {
+                resolve_manifest(location, sha256, distributions_dir)?
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            flavor,
+            python_version: python_version.or_else(|| {
+                manifest
+                    .as_ref()
+                    .map(|manifest| manifest.python_version.clone())
+            }),
+            manifest,
+            python_exe: PathBuf::new(),
+        })
+    }
+
+    /// Whether extension modules are linked into executables.
+    fn static_extension_modules(&self) -> bool {
+        match &self.manifest {
+            Some(manifest) => manifest.link_mode == StandaloneDistributionLinkMode::Static,
+            None => false,
+        }
+    }
+
+    fn not_available(&self, what: &str) -> anyhow::Error {
+        anyhow!(
+            "{} is not available in dry run mode, as the Python distribution is not obtained",
+            what
+        )
+    }
+}
+
+impl PythonDistribution for DryRunDistribution {
+    fn clone_box(&self) -> Box<dyn PythonDistribution> {
+        Box::new(self.clone())
+    }
+
+    fn python_exe_path(&self) -> &Path {
+        &self.python_exe
+    }
+
+    fn capabilities(&self) -> DistributionCapabilities {
+        match self.flavor {
+            DistributionFlavor::Standalone | DistributionFlavor::StandaloneStatic => {
+                DistributionCapabilities {
+                    implementation: PythonImplementation::CPython,
+                    embeddable: true,
+                    static_libpython: true,
+                    static_extension_modules: true,
+                    dynamic_extension_modules: false,
+                }
+            }
+            DistributionFlavor::StandaloneDynamic | DistributionFlavor::WindowsEmbeddable => {
+                DistributionCapabilities {
+                    implementation: PythonImplementation::CPython,
+                    embeddable: true,
+                    static_libpython: false,
+                    static_extension_modules: false,
+                    dynamic_extension_modules: true,
+                }
+            }
+            DistributionFlavor::PyPy => DistributionCapabilities {
+                implementation: PythonImplementation::PyPy,
+                embeddable: false,
+                static_libpython: false,
+                static_extension_modules: false,
+                dynamic_extension_modules: true,
+            },
+        }
+    }
+
+    fn python_major_minor_version(&self) -> String {
+        match &self.python_version {
+            Some(version) => version.split('.').take(2).collect::<Vec<_>>().join("."),
+            None => String::new(),
+        }
+    }
+
+    fn python_module_suffixes(&self) -> Result<PythonModuleSuffixes> {
+        Err(self.not_available("Python module suffixes"))
+    }
+
+    fn create_bytecode_compiler(&self) -> Result<BytecodeCompiler> {
+        Err(self.not_available("bytecode compilation"))
+    }
+
+    fn resolve_importlib_bytecode(&self) -> Result<ImportlibBytecode> {
+        Err(self.not_available("importlib bytecode"))
+    }
+
+    fn as_python_executable_builder(
+        &self,
+        logger: &slog::Logger,
+        _host_triple: &str,
+        _target_triple: &str,
+        name: &str,
+        config: &EmbeddedPythonConfig,
+        extension_module_filter: &ExtensionModuleFilter,
+        preferred_extension_module_variants: Option<HashMap<String, String>>,
+        include_sources: bool,
+        include_resources: bool,
+        include_test: bool,
+    ) -> Result<Box<dyn PythonBinaryBuilder>> {
+        let mut resources = EmbeddedPythonResourcesPrePackaged::default();
+
+        // Mirrors what `StandaloneDistribution` embeds.
+        if self.static_extension_modules() {
+            for ext in self.filter_extension_modules(
+                logger,
+                extension_module_filter,
+                preferred_extension_module_variants,
+            )? {
+                resources.add_extension_module(&ext);
+            }
+
+            for ext in
+                self.filter_extension_modules(logger, &ExtensionModuleFilter::Minimal, None)?
+            {
+                if !resources.get_extension_modules().contains_key(&ext.module) {
+                    resources.add_extension_module(&ext);
+                }
+            }
+        }
+
+        for source in self.source_modules()? {
+            if !include_test && is_stdlib_test_package(&source.package()) {
+                continue;
+            }
+
+            if include_sources {
+                resources.add_source_module(&source);
+            }
+
+            resources
+                .add_bytecode_module(&source.as_bytecode_module(BytecodeOptimizationLevel::Zero));
+        }
+
+        if include_resources {
+            for resource in self.resource_datas()? {
+                if !include_test && is_stdlib_test_package(&resource.package) {
+                    continue;
+                }
+
+                resources.add_resource(&resource);
+            }
+        }
+
+        Ok(Box::new(DryRunPythonExecutableBuilder {
+            exe_name: name.to_string(),
+            python_exe: self.python_exe.clone(),
+            python_version: self.python_version.clone(),
+            resources,
+            config: config.clone(),
+            windows_crt_linkage: WindowsCrtLinkage::default(),
+            libpython_link_mode: if self.capabilities().static_libpython {
+                LibpythonLinkMode::Static
+            } else {
+                LibpythonLinkMode::Dynamic
+            },
+            package_licenses: Vec::new(),
+        }))
+    }
+
+    fn filter_extension_modules(
+        &self,
+        logger: &slog::Logger,
+        filter: &ExtensionModuleFilter,
+        preferred_variants: Option<HashMap<String, String>>,
+    ) -> Result<Vec<ExtensionModule>> {
+        match &self.manifest {
+            Some(manifest) => filter_distribution_extension_modules(
+                logger,
+                &manifest.extension_modules,
+                filter,
+                preferred_variants,
+            ),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn extension_module_variants(&self, name: &str) -> Vec<ExtensionModule> {
+        if let Some(manifest) = &self.manifest {
+            return manifest
+                .extension_modules
+                .get(name)
+                .cloned()
+                .unwrap_or_else(Vec::new);
+        }
+
+        // Extension modules of the distribution aren't known. So any is
+        // assumed to exist, with a single variant.
+        vec![ExtensionModule {
+            module: name.to_string(),
+            init_fn: None,
+            builtin_default: false,
+            disableable: true,
+            object_paths: Vec::new(),
+            static_library: None,
+            shared_library: None,
+            links: Vec::new(),
+            required: false,
+            variant: "default".to_string(),
+            licenses: None,
+            license_paths: None,
+            license_public_domain: None,
+        }]
+    }
+
+    fn source_modules(&self) -> Result<Vec<SourceModule>> {
+        Ok(match &self.manifest {
+            Some(manifest) => manifest
+                .py_modules
+                .iter()
+                .map(|(name, is_package)| SourceModule {
+                    name: name.clone(),
+                    source: DataLocation::Memory(Vec::new()),
+                    is_package: *is_package,
+                })
+                .collect(),
+            None => Vec::new(),
+        })
+    }
+
+    fn resource_datas(&self) -> Result<Vec<ResourceData>> {
+        Ok(match &self.manifest {
+            Some(manifest) => manifest
+                .resources
+                .iter()
+                .flat_map(|(package, names)| {
+                    names.iter().map(move |name| ResourceData {
+                        package: package.clone(),
+                        name: name.clone(),
+                        data: DataLocation::Memory(Vec::new()),
+                    })
+                })
+                .collect(),
+            None => Vec::new(),
+        })
+    }
+
+    fn ensure_pip(&self, _logger: &slog::Logger, _dest_dir: &Path) -> Result<PathBuf> {
+        Err(self.not_available("pip"))
+    }
+
+    fn resolve_distutils(
+        &self,
+        _logger: &slog::Logger,
+        _dest_dir: &Path,
+        _extra_python_paths: &[&Path],
+    ) -> Result<HashMap<String, String>> {
+        Err(self.not_available("distutils"))
+    }
+
+    fn filter_compatible_python_resources(
+        &self,
+        _logger: &slog::Logger,
+        resources: &[PythonResource],
+    ) -> Result<Vec<PythonResource>> {
+        Ok(resources.to_vec())
+    }
+}
+
+/// A `PythonBinaryBuilder` of executables using a `DryRunDistribution`.
+///
+/// Resources are collected, but the executable can't be built.
+#[derive(Clone, Debug)]
+pub struct DryRunPythonExecutableBuilder {
+    /// The name of the executable to build.
+    exe_name: String,
+
+    /// Always empty, as the distribution isn't extracted.
+    python_exe: PathBuf,
+
+    /// Full version of Python, if known.
+    python_version: Option<String>,
+
+    /// Python resources to be embedded in the binary.
+    resources: EmbeddedPythonResourcesPrePackaged,
+
+    /// Configuration for embedded Python interpreter.
+    config: EmbeddedPythonConfig,
+
+    /// How to link against the C runtime.
+    windows_crt_linkage: WindowsCrtLinkage,
+
+    /// How to link against `libpython`.
+    libpython_link_mode: LibpythonLinkMode,
+
+    /// Licensing metadata of embedded Python packages.
+    package_licenses: Vec<LicensedComponent>,
+}
+
+impl PythonBinaryBuilder for DryRunPythonExecutableBuilder {
+    fn clone_box(&self) -> Box<dyn PythonBinaryBuilder> {
+        Box::new(self.clone())
+    }
+
+    fn name(&self) -> String {
+        self.exe_name.clone()
+    }
+
+    fn python_exe_path(&self) -> &Path {
+        &self.python_exe
+    }
+
+    fn python_version(&self) -> Option<String> {
+        self.python_version.clone()
+    }
+
+    fn config(&self) -> &EmbeddedPythonConfig {
+        &self.config
+    }
+
+    fn set_run_mode(&mut self, run_mode: RunMode) {
+        self.config.run_mode = run_mode;
+    }
+
+    fn windows_crt_linkage(&self) -> WindowsCrtLinkage {
+        self.windows_crt_linkage
+    }
+
+    fn set_windows_crt_linkage(&mut self, linkage: WindowsCrtLinkage) {
+        self.windows_crt_linkage = linkage;
+    }
+
+    fn libpython_link_mode(&self) -> LibpythonLinkMode {
+        self.libpython_link_mode
+    }
+
+    fn set_libpython_link_mode(&mut self, mode: LibpythonLinkMode) {
+        self.libpython_link_mode = mode;
+    }
+
+    fn source_modules(&self) -> BTreeMap<String, SourceModule> {
+        self.resources.get_source_modules()
+    }
+
+    fn bytecode_modules(&self) -> BTreeMap<String, BytecodeModule> {
+        self.resources.get_bytecode_modules()
+    }
+
+    fn bytecode_optimize_levels(&self) -> BTreeMap<String, Vec<BytecodeOptimizationLevel>> {
+        self.resources.get_bytecode_optimize_levels()
+    }
+
+    fn resources(&self) -> BTreeMap<String, BTreeMap<String, Vec<u8>>> {
+        self.resources.get_resources()
+    }
+
+    fn extension_modules(&self) -> BTreeMap<String, ExtensionModule> {
+        self.resources.get_extension_modules()
+    }
+
+    fn extension_module_datas(&self) -> BTreeMap<String, ExtensionModuleData> {
+        self.resources.get_extension_module_datas()
+    }
+
+    fn add_source_module(&mut self, module: &SourceModule) {
+        self.resources.add_source_module(module)
+    }
+
+    fn add_bytecode_module(&mut self, module: &BytecodeModule) {
+        self.resources.add_bytecode_module(module)
+    }
+
+    fn add_resource(&mut self, resource: &ResourceData) {
+        self.resources.add_resource(resource)
+    }
+
+    fn add_distribution_resource(&mut self, resource: &DistributionResource) {
+        self.resources.add_distribution_resource(resource)
+    }
+
+    fn set_file_emulation(&mut self, name: &str, policy: FileEmulation) {
+        self.resources.set_file_emulation(name, policy)
+    }
+
+    fn set_source_transforms(&mut self, package: &str, transforms: SourceTransforms) {
+        self.resources.set_source_transforms(package, transforms)
+    }
+
+    fn resource_conflict_policy(&self) -> ConflictPolicy {
+        self.resources.conflict_policy()
+    }
+
+    fn set_resource_conflict_policy(&mut self, policy: ConflictPolicy) {
+        self.resources.set_conflict_policy(policy)
+    }
+
+    fn take_resource_conflicts(&mut self) -> Vec<ResourceConflict> {
+        self.resources.take_conflicts()
+    }
+
+    fn add_extension_module(&mut self, extension_module: &ExtensionModule) {
+        self.resources.add_extension_module(extension_module)
+    }
+
+    fn add_extension_module_data(&mut self, extension_module_data: &ExtensionModuleData) {
+        self.resources
+            .add_extension_module_data(extension_module_data)
+    }
+
+    fn add_package_license(&mut self, license: &LicensedComponent) {
+        self.package_licenses.retain(|l| l.name != license.name);
+        self.package_licenses.push(license.clone());
+    }
+
+    fn licensed_components(&self) -> Vec<LicensedComponent> {
+        self.package_licenses.clone()
+    }
+
+    fn package_licenses(&self) -> Vec<LicensedComponent> {
+        self.package_licenses.clone()
+    }
+
+    fn linked_libraries(&self) -> BTreeSet<String> {
+        BTreeSet::new()
+    }
+
+    fn filter_resources_from_files(
+        &mut self,
+        logger: &slog::Logger,
+        files: &[&Path],
+        glob_patterns: &[&str],
+    ) -> Result<()> {
+        self.resources
+            .filter_from_files(logger, files, glob_patterns)
+    }
+
+    fn import_packages_from_filesystem(&mut self, packages: &[String], sys_paths: &[String]) {
+        self.resources.remove_packages(packages);
+        self.config.filesystem_importer = true;
+
+        let mut paths = sys_paths.to_vec();
+        paths.append(&mut self.config.sys_paths);
+        self.config.sys_paths = paths;
+    }
+
+    fn remove_modules(&mut self, names: &[String]) {
+        self.resources.remove_modules(names);
+    }
+
+    fn restrict_imports(&mut self, allow: Option<&[String]>, deny: &[String]) {
+        self.resources.restrict_imports(allow, deny);
+        self.config.import_allow = allow.map(|modules| modules.to_vec());
+        self.config.import_deny = deny.to_vec();
+    }
+
+    fn requires_jemalloc(&self) -> bool {
+        self.config.raw_allocator == RawAllocator::Jemalloc
+    }
+
+    fn as_embedded_python_binary_data(
+        &self,
+        _logger: &slog::Logger,
+        _opt_level: &str,
+    ) -> Result<EmbeddedPythonBinaryData> {
+        Err(anyhow!(
+            "executable {} can't be built in dry run mode",
+            self.exe_name
+        ))
+    }
+
+    fn extra_install_files(&self, _logger: &slog::Logger, _prefix: &str) -> Result<FileManifest> {
+        Ok(FileManifest::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::python_distributions::{CPYTHON_STANDALONE_STATIC_BY_TRIPLE, PYTHON_DISTRIBUTIONS},
+        crate::testutil::*,
+    };
+
+    #[test]
+    fn test_from_location() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+
+        let record = PYTHON_DISTRIBUTIONS
+            .iter()
+            .find(|record| record.flavor == DistributionFlavor::StandaloneDynamic)
+            .unwrap();
+
+        let dist = DryRunDistribution::from_location(
+            &DistributionFlavor::Standalone,
+            &PythonDistributionLocation::Url {
+                url: "https://example.com/does-not-exist.tar.zst".to_string(),
+                sha256: record.location.sha256.to_uppercase(),
+            },
+            temp_dir.path(),
+        )?;
+        assert_eq!(dist.flavor, DistributionFlavor::StandaloneDynamic);
+        assert_eq!(dist.python_version, Some(record.python_version.clone()));
+        assert!(!dist.capabilities().static_libpython);
+
+        let dist = DryRunDistribution::from_location(
+            &DistributionFlavor::Standalone,
+            &PythonDistributionLocation::Url {
+                url: "https://example.com/does-not-exist.tar.zst".to_string(),
+                sha256: "00".to_string(),
+            },
+            temp_dir.path(),
+        )?;
+        assert!(dist.manifest.is_none());
+        assert_eq!(dist.flavor, DistributionFlavor::Standalone);
+        assert_eq!(dist.python_version, None);
+
+        let err = DryRunDistribution::from_location(
+            &DistributionFlavor::Standalone,
+            &PythonDistributionLocation::Local {
+                local_path: "/does-not-exist/python.tar.zst".to_string(),
+                sha256: "00".to_string(),
+            },
+            temp_dir.path(),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+
+        Ok(())
+    }
+    fn resource_names(dist: &dyn PythonDistribution) -> Result<(Vec<String>, Vec<String>)> {
+        Ok((
+            dist.source_modules()?
+                .into_iter()
+                .map(|module| module.name)
+                .collect(),
+            dist.resource_datas()?
+                .into_iter()
+                .map(|resource| resource.full_name())
+                .collect(),
+        ))
+    }
+
+    #[test]
+    fn test_resources_from_obtained_distribution() -> Result<()> {
+        let logger = get_logger()?;
+        let distribution = get_default_distribution()?;
+        let expected = resource_names(&**distribution)?;
+        assert!(!expected.0.is_empty());
+
+        let hosted_distribution = CPYTHON_STANDALONE_STATIC_BY_TRIPLE
+            .get(env!("HOST"))
+            .unwrap();
+        let location = PythonDistributionLocation::Url {
+            url: hosted_distribution.url.clone(),
+            sha256: hosted_distribution.sha256.clone(),
+        };
+
+        let dist = DryRunDistribution::from_location(
+            &DistributionFlavor::Standalone,
+            &location,
+            DEFAULT_DISTRIBUTION_TEMP_DIR.path(),
+        )?;
+        assert!(dist.manifest.is_some());
+        assert_eq!(resource_names(&dist)?, expected);
+
+        // The downloaded archive resolves to the same resources.
+        let archive_path =
+            cached_distribution_archive_path(&location, DEFAULT_DISTRIBUTION_TEMP_DIR.path())
+                .unwrap();
+        let dist = DryRunDistribution {
+            manifest: Some(StandaloneDistributionManifest::from_tar_zst_file(
+                &archive_path,
+            )?),
+            ..dist
+        };
+        assert_eq!(resource_names(&dist)?, expected);
+
+        let builder = dist.as_python_executable_builder(
+            &logger,
+            env!("HOST"),
+            env!("HOST"),
+            "myapp",
+            &EmbeddedPythonConfig::default(),
+            &ExtensionModuleFilter::All,
+            None,
+            true,
+            true,
+            false,
+        )?;
+        assert!(!builder.source_modules().is_empty());
+        assert!(!builder.extension_modules().is_empty());
+
+        Ok(())
+    }
+}
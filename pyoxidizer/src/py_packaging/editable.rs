@@ -0,0 +1,251 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Editable installs of Python packages.
+
+PEP 660 editable installs make a package importable from the developer's
+working tree. Some backends simply add the source directory to `sys.path`
+through a `.pth` file. Others install an import hook: a `.pth` file
+imports a finder module installed in `site-packages`, which maps package
+names to their location in the working tree. These mappings are read from
+the finder modules of setuptools (`__editable___*_finder.py`, defining a
+`MAPPING` dict) and of the `editables` library used by hatchling
+(`map_module()` calls).
+*/
+
+use {
+    anyhow::{Context, Result},
+    std::path::{Path, PathBuf},
+};
+
+/// A module or package of an editable install.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EditableMapping {
+    /// Name of the top-level module or package.
+    pub name: String,
+
+    /// Path to the module's file or the package's directory.
+    pub path: PathBuf,
+}
+
+/// Parse a Python string literal at the start of a string.
+///
+/// Returns the value and the remainder of the string. Only the escapes
+/// emitted by `repr()` for paths are supported.
+fn parse_python_str(s: &str) -> Option<(String, &str)> {
+    let s = s.trim_start();
+    let quote = s.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+
+    let mut value = String::new();
+    let mut chars = s[1..].char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                c => value.push(c),
+            },
+            c if c == quote => return Some((value, &s[i + 2..])),
+            c => value.push(c),
+        }
+    }
+
+    None
+}
+
+/// Parse a Python dict literal of strings to strings.
+fn parse_python_str_dict(s: &str) -> Option<Vec<(String, String)>> {
+    let mut s = s.trim_start();
+    if !s.starts_with('{') {
+        return None;
+    }
+    s = &s[1..];
+
+    let mut res = Vec::new();
+
+    loop {
+        s = s.trim_start();
+
+        if s.starts_with('}') {
+            return Some(res);
+        }
+
+        let (key, rest) = parse_python_str(s)?;
+        let rest = rest.trim_start();
+        if !rest.starts_with(':') {
+            return None;
+        }
+
+        let (value, rest) = parse_python_str(&rest[1..])?;
+        res.push((key, value));
+
+        s = rest.trim_start();
+        if s.starts_with(',') {
+            s = &s[1..];
+        }
+    }
+}
+
+/// Obtain the name of the module imported by an executable `.pth` file line.
+///
+/// e.g. `import __editable___foo_1_0_finder; __editable___foo_1_0_finder.install()`.
+pub fn pth_line_imported_module(line: &str) -> Option<String> {
+    if !line.starts_with("import ") && !line.starts_with("import\t") {
+        return None;
+    }
+
+    let name = line[6..]
+        .trim_start()
+        .split(|c: char| c == ';' || c == ',' || c.is_whitespace())
+        .next()?;
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Read the mappings defined by the source of an editable install finder module.
+///
+/// Returns `None` if the module isn't a finder module.
+pub fn parse_editable_finder(source: &str) -> Option<Vec<EditableMapping>> {
+    let mut mappings = Vec::new();
+    let mut found = false;
+
+    for line in source.lines() {
+        // setuptools: MAPPING: dict[str, str] = {'foo': '/src/foo'}
+        if line.starts_with("MAPPING") {
+            let pos = line.find('=')?;
+            for (name, path) in parse_python_str_dict(&line[pos + 1..])? {
+                mappings.push(EditableMapping {
+                    name,
+                    path: PathBuf::from(path),
+                });
+            }
+            found = true;
+        }
+        // editables: F.map_module('foo', '/src/foo/__init__.py')
+        else if let Some(pos) = line.find(".map_module(") {
+            let (name, rest) = parse_python_str(&line[pos + 12..])?;
+            let rest = rest.trim_start();
+            if !rest.starts_with(',') {
+                return None;
+            }
+            let (path, _) = parse_python_str(&rest[1..])?;
+            let mut path = PathBuf::from(path);
+
+            if path.file_stem().and_then(|s| s.to_str()) == Some("__init__") {
+                path.pop();
+            }
+
+            mappings.push(EditableMapping { name, path });
+            found = true;
+        }
+    }
+
+    if found {
+        Some(mappings)
+    } else {
+        None
+    }
+}
+
+/// Read the mappings of the finder module imported by a `.pth` file line.
+///
+/// `site_packages` is the directory holding the `.pth` file. Returns the name
+/// of the finder module and its mappings, or `None` if the line doesn't
+/// install an editable install finder.
+pub fn read_editable_finder(
+    site_packages: &Path,
+    line: &str,
+) -> Result<Option<(String, Vec<EditableMapping>)>> {
+    let module = match pth_line_imported_module(line) {
+        Some(module) => module,
+        None => return Ok(None),
+    };
+
+    let path = site_packages.join(format!("{}.py", module));
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let source =
+        std::fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?;
+
+    Ok(parse_editable_finder(&source).map(|mappings| (module, mappings)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_python_str() {
+        assert_eq!(
+            parse_python_str("'foo', 1"),
+            Some(("foo".to_string(), ", 1"))
+        );
+        assert_eq!(
+            parse_python_str(r#" "it's" "#),
+            Some(("it's".to_string(), " "))
+        );
+        assert_eq!(
+            parse_python_str(r"'C:\\src\\foo'"),
+            Some((r"C:\src\foo".to_string(), ""))
+        );
+        assert_eq!(parse_python_str("'foo"), None);
+        assert_eq!(parse_python_str("foo"), None);
+    }
+
+    #[test]
+    fn test_pth_line_imported_module() {
+        assert_eq!(
+            pth_line_imported_module(
+                "import __editable___foo_1_0_finder; __editable___foo_1_0_finder.install()"
+            ),
+            Some("__editable___foo_1_0_finder".to_string())
+        );
+        assert_eq!(
+            pth_line_imported_module("import\t_foo"),
+            Some("_foo".to_string())
+        );
+        assert_eq!(pth_line_imported_module("../src"), None);
+    }
+
+    #[test]
+    fn test_parse_editable_finder() {
+        let setuptools = "import sys\n\
+                          MAPPING: dict[str, str] = {'foo': '/src/foo', 'bar': '/src/bar.py'}\n\
+                          NAMESPACES: dict[str, list[str]] = {}\n";
+        assert_eq!(
+            parse_editable_finder(setuptools),
+            Some(vec![
+                EditableMapping {
+                    name: "foo".to_string(),
+                    path: PathBuf::from("/src/foo"),
+                },
+                EditableMapping {
+                    name: "bar".to_string(),
+                    path: PathBuf::from("/src/bar.py"),
+                },
+            ])
+        );
+
+        let editables = "from editables.redirector import RedirectingFinder as F\n\
+                         F.install()\n\
+                         F.map_module('foo', '/src/foo/__init__.py')\n";
+        assert_eq!(
+            parse_editable_finder(editables),
+            Some(vec![EditableMapping {
+                name: "foo".to_string(),
+                path: PathBuf::from("/src/foo"),
+            }])
+        );
+
+        assert_eq!(parse_editable_finder("import sys\n"), None);
+    }
+}
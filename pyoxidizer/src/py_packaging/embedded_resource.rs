@@ -7,24 +7,29 @@ Embedded Python resources in a binary.
 */
 
 use {
-    super::bytecode::{BytecodeCompiler, CompileMode},
-    super::filtering::{filter_btreemap, resolve_resource_names_from_files},
+    super::bytecode::{BytecodeCompiler, CompileMode, PycInvalidationMode, SourceTransforms},
+    super::filtering::{filter_btreemap, is_import_allowed, resolve_resource_names_from_files},
     super::resource::{
-        has_dunder_file, packages_from_module_name, packages_from_module_names, BytecodeModule,
-        BytecodeOptimizationLevel, DataLocation, ExtensionModuleData, ResourceData, SourceModule,
+        find_dynamic_imports, has_dunder_file, packages_from_module_name,
+        packages_from_module_names, BytecodeModule, BytecodeOptimizationLevel, DataLocation,
+        DistributionResource, DynamicImport, ExtensionModuleData, ResourceData, SourceModule,
     },
     super::standalone_distribution::ExtensionModule,
-    anyhow::{Error, Result},
+    crate::progress::Progress,
+    crate::timing,
+    anyhow::{anyhow, Error, Result},
     lazy_static::lazy_static,
-    python_packed_resources::data::Resource as EmbeddedResource,
-    python_packed_resources::writer::write_embedded_resources_v1,
-    slog::warn,
+    python_packed_resources::data::{FileEmulation, Resource as EmbeddedResource},
+    python_packed_resources::parser::load_resources,
+    python_packed_resources::writer::{write_embedded_resources_v2, ResourcesWriter},
+    slog::{info, warn},
     std::borrow::Cow,
     std::collections::{BTreeMap, BTreeSet, HashMap},
     std::convert::TryFrom,
+    std::fmt::{Display, Formatter},
     std::io::Write,
     std::iter::FromIterator,
-    std::path::Path,
+    std::path::{Path, PathBuf},
     std::sync::Arc,
 };
 
@@ -75,6 +80,7 @@ pub struct EmbeddedResourcePythonModulePrePackaged {
     pub in_memory_package_distribution: Option<BTreeMap<String, DataLocation>>,
     pub in_memory_shared_library: Option<DataLocation>,
     pub shared_library_dependency_names: Option<Vec<String>>,
+    pub file_emulation: Option<FileEmulation>,
 }
 
 impl<'a> TryFrom<&EmbeddedResourcePythonModulePrePackaged> for EmbeddedResource<'a, u8> {
@@ -134,14 +140,141 @@ impl<'a> TryFrom<&EmbeddedResourcePythonModulePrePackaged> for EmbeddedResource<
             } else {
                 None
             },
+            content_digest: None,
+            is_encrypted: false,
+            file_emulation: value.file_emulation,
         })
     }
 }
 
+/// How a module or resource added again from a different source is handled.
+///
+/// Adding identical content again is never a conflict.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConflictPolicy {
+    /// Keep the resource added first and fail the build.
+    Error,
+    /// Keep the resource added first.
+    FirstWins,
+    /// Replace the resource with the one added last.
+    LastWins,
+    /// Keep the module compiled at the highest bytecode optimization level.
+    ///
+    /// Source modules rank below bytecode. Resources and extension modules
+    /// added first are kept.
+    PreferHigherOptimize,
+}
+
+impl ConflictPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Error => "error",
+            ConflictPolicy::FirstWins => "first-wins",
+            ConflictPolicy::LastWins => "last-wins",
+            ConflictPolicy::PreferHigherOptimize => "prefer-higher-optimize",
+        }
+    }
+}
+
+impl Default for ConflictPolicy {
+    fn default() -> Self {
+        ConflictPolicy::LastWins
+    }
+}
+
+impl TryFrom<&str> for ConflictPolicy {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, String> {
+        match value {
+            "error" => Ok(ConflictPolicy::Error),
+            "first-wins" => Ok(ConflictPolicy::FirstWins),
+            "last-wins" => Ok(ConflictPolicy::LastWins),
+            "prefer-higher-optimize" => Ok(ConflictPolicy::PreferHigherOptimize),
+            t => Err(format!(
+                "{} is not a valid resource conflict policy; expected error, first-wins, last-wins, or prefer-higher-optimize",
+                t
+            )),
+        }
+    }
+}
+
+/// A module or resource added from different sources.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResourceConflict {
+    /// What conflicts, e.g. `source module`.
+    pub kind: &'static str,
+
+    /// Name of the module or resource.
+    pub name: String,
+
+    /// Where the previously added content comes from.
+    pub existing: String,
+
+    /// Where the newly added content comes from.
+    pub added: String,
+
+    /// Whether the newly added content replaced the previous one.
+    pub replaced: bool,
+}
+
+impl Display for ResourceConflict {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} from {} conflicts with {}",
+            self.kind, self.name, self.added, self.existing
+        )
+    }
+}
+
+/// Describe where data comes from.
+fn describe_location(location: &DataLocation) -> String {
+    match location {
+        DataLocation::Path(path) => path.display().to_string(),
+        DataLocation::Memory(data) => format!("{} bytes in memory", data.len()),
+    }
+}
+
+/// Whether two locations hold the same data.
+fn same_data(a: &DataLocation, b: &DataLocation) -> bool {
+    if a == b {
+        return true;
+    }
+
+    match (a.resolve(), b.resolve()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+impl EmbeddedResourcePythonModulePrePackaged {
+    /// Locations of the Python source this module was added from.
+    ///
+    /// Each location is paired with the rank of the optimization level it is
+    /// compiled at. Empty placeholders of parent packages are ignored.
+    fn module_sources(&self) -> Vec<(&DataLocation, Option<i32>)> {
+        vec![
+            (&self.in_memory_source, None),
+            (&self.in_memory_bytecode, Some(0)),
+            (&self.in_memory_bytecode_opt1, Some(1)),
+            (&self.in_memory_bytecode_opt2, Some(2)),
+        ]
+        .into_iter()
+        .filter_map(|(location, rank)| match location {
+            Some(DataLocation::Memory(data)) if data.is_empty() => None,
+            Some(location) => Some((location, rank)),
+            None => None,
+        })
+        .collect()
+    }
+}
+
 /// Represents Python resources to embed in a binary.
 ///
 /// This collection holds resources before packaging. This type is
-/// transformed to `EmbeddedPythonResources` as part of packaging.
+/// transformed to `EmbeddedPythonResources`, which refers to it for the
+/// data of resources, as part of packaging.
 #[derive(Debug, Default, Clone)]
 pub struct EmbeddedPythonResourcesPrePackaged {
     modules: BTreeMap<String, EmbeddedResourcePythonModulePrePackaged>,
@@ -149,6 +282,38 @@ pub struct EmbeddedPythonResourcesPrePackaged {
     // TODO combine into single extension module type.
     extension_modules: BTreeMap<String, ExtensionModule>,
     extension_module_datas: BTreeMap<String, ExtensionModuleData>,
+
+    /// Source transforms keyed by the package they apply to.
+    source_transforms: BTreeMap<String, SourceTransforms>,
+
+    /// How modules and resources added from different sources are handled.
+    conflict_policy: ConflictPolicy,
+
+    /// Conflicts not yet reported.
+    conflicts: Vec<ResourceConflict>,
+}
+
+/// Compile Python source to bytecode, applying source transforms.
+fn compile_module_bytecode(
+    compiler: &mut BytecodeCompiler,
+    source: &[u8],
+    name: &str,
+    optimize_level: BytecodeOptimizationLevel,
+    transforms: Option<&SourceTransforms>,
+) -> Result<Vec<u8>> {
+    let source = match transforms {
+        Some(transforms) if transforms.transforms_source() => {
+            Cow::Owned(compiler.transform_source(source, name, transforms)?)
+        }
+        _ => Cow::Borrowed(source),
+    };
+
+    let bytecode = compiler.compile(&source, name, optimize_level, CompileMode::Bytecode)?;
+
+    match transforms {
+        Some(transforms) if transforms.intern_strings => compiler.intern_constants(&bytecode),
+        _ => Ok(bytecode),
+    }
 }
 
 impl EmbeddedPythonResourcesPrePackaged {
@@ -181,6 +346,8 @@ impl EmbeddedPythonResourcesPrePackaged {
                         is_package: module.is_package,
                         source: location.clone(),
                         optimize_level: BytecodeOptimizationLevel::Zero,
+                        source_stripped: module.in_memory_source.is_none(),
+                        invalidation_mode: PycInvalidationMode::default(),
                     },
                 ))
             } else if let Some(location) = &module.in_memory_bytecode_opt1 {
@@ -191,6 +358,8 @@ impl EmbeddedPythonResourcesPrePackaged {
                         is_package: module.is_package,
                         source: location.clone(),
                         optimize_level: BytecodeOptimizationLevel::One,
+                        source_stripped: module.in_memory_source.is_none(),
+                        invalidation_mode: PycInvalidationMode::default(),
                     },
                 ))
             } else if let Some(location) = &module.in_memory_bytecode_opt2 {
@@ -201,6 +370,8 @@ impl EmbeddedPythonResourcesPrePackaged {
                         is_package: module.is_package,
                         source: location.clone(),
                         optimize_level: BytecodeOptimizationLevel::Two,
+                        source_stripped: module.in_memory_source.is_none(),
+                        invalidation_mode: PycInvalidationMode::default(),
                     },
                 ))
             } else {
@@ -209,6 +380,32 @@ impl EmbeddedPythonResourcesPrePackaged {
         }))
     }
 
+    /// Obtain the optimization levels bytecode of each module is embedded at.
+    pub fn get_bytecode_optimize_levels(&self) -> BTreeMap<String, Vec<BytecodeOptimizationLevel>> {
+        BTreeMap::from_iter(self.modules.iter().filter_map(|(name, module)| {
+            let levels = vec![
+                (&module.in_memory_bytecode, BytecodeOptimizationLevel::Zero),
+                (
+                    &module.in_memory_bytecode_opt1,
+                    BytecodeOptimizationLevel::One,
+                ),
+                (
+                    &module.in_memory_bytecode_opt2,
+                    BytecodeOptimizationLevel::Two,
+                ),
+            ]
+            .into_iter()
+            .filter_map(|(location, level)| location.as_ref().map(|_| level))
+            .collect::<Vec<_>>();
+
+            if levels.is_empty() {
+                None
+            } else {
+                Some((name.clone(), levels))
+            }
+        }))
+    }
+
     /// Obtain resource files in this instance.
     pub fn get_resources(&self) -> BTreeMap<String, BTreeMap<String, Vec<u8>>> {
         BTreeMap::from_iter(self.modules.iter().filter_map(|(name, module)| {
@@ -239,8 +436,136 @@ impl EmbeddedPythonResourcesPrePackaged {
         self.extension_module_datas.clone()
     }
 
+    /// Obtain how modules and resources added from different sources are handled.
+    pub fn conflict_policy(&self) -> ConflictPolicy {
+        self.conflict_policy
+    }
+
+    /// Define how modules and resources added from different sources are handled.
+    pub fn set_conflict_policy(&mut self, policy: ConflictPolicy) {
+        self.conflict_policy = policy;
+    }
+
+    /// Obtain conflicts that occurred since this was last called.
+    pub fn take_conflicts(&mut self) -> Vec<ResourceConflict> {
+        self.conflicts.drain(..).collect()
+    }
+
+    /// Resolve a conflict between a module being added and its previous sources.
+    ///
+    /// Returns whether the module should be added. The previous sources of
+    /// the module are removed when it replaces them, so a module never mixes
+    /// source and bytecode of different origins.
+    fn resolve_module_conflict(
+        &mut self,
+        kind: &'static str,
+        name: &str,
+        location: &DataLocation,
+        optimize_level: Option<BytecodeOptimizationLevel>,
+    ) -> bool {
+        let entry = match self.modules.get_mut(name) {
+            Some(entry) => entry,
+            None => return true,
+        };
+
+        let (existing, existing_rank) = match entry
+            .module_sources()
+            .into_iter()
+            .filter(|(existing, _)| !same_data(existing, location))
+            .max_by_key(|(_, rank)| *rank)
+        {
+            Some((existing, rank)) => (describe_location(existing), rank),
+            None => return true,
+        };
+
+        let replaced = match self.conflict_policy {
+            ConflictPolicy::Error | ConflictPolicy::FirstWins => false,
+            ConflictPolicy::LastWins => true,
+            ConflictPolicy::PreferHigherOptimize => optimize_level.map(i32::from) > existing_rank,
+        };
+
+        if replaced {
+            entry.in_memory_source = None;
+            entry.in_memory_bytecode = None;
+            entry.in_memory_bytecode_opt1 = None;
+            entry.in_memory_bytecode_opt2 = None;
+        }
+
+        self.conflicts.push(ResourceConflict {
+            kind,
+            name: name.to_string(),
+            existing,
+            added: describe_location(location),
+            replaced,
+        });
+
+        replaced
+    }
+
+    /// Resolve a conflict between resource data being added and a previous one.
+    ///
+    /// Returns whether the resource should be added.
+    fn resolve_resource_conflict(&mut self, resource: &ResourceData) -> bool {
+        let existing = match self
+            .modules
+            .get(&resource.package)
+            .and_then(|entry| entry.in_memory_resources.as_ref())
+            .and_then(|resources| resources.get(&resource.name))
+        {
+            Some(existing) if !same_data(existing, &resource.data) => describe_location(existing),
+            _ => return true,
+        };
+
+        let replaced = self.conflict_policy == ConflictPolicy::LastWins;
+
+        self.conflicts.push(ResourceConflict {
+            kind: "resource",
+            name: format!("{}:{}", resource.package, resource.name),
+            existing,
+            added: describe_location(&resource.data),
+            replaced,
+        });
+
+        replaced
+    }
+
+    /// Resolve a conflict between an extension module being added and a previous one.
+    ///
+    /// Returns whether the extension module should be added. The previous
+    /// extension module is removed when it is replaced.
+    fn resolve_extension_module_conflict(&mut self, name: &str, added: String) -> bool {
+        let existing = if let Some(existing) = self.extension_modules.get(name) {
+            format!("{} variant", existing.variant)
+        } else if self.extension_module_datas.contains_key(name) {
+            "extension module data".to_string()
+        } else {
+            return true;
+        };
+
+        let replaced = self.conflict_policy == ConflictPolicy::LastWins;
+
+        if replaced {
+            self.extension_modules.remove(name);
+            self.extension_module_datas.remove(name);
+        }
+
+        self.conflicts.push(ResourceConflict {
+            kind: "extension module",
+            name: name.to_string(),
+            existing,
+            added,
+            replaced,
+        });
+
+        replaced
+    }
+
     /// Add a source module to the collection of embedded source modules.
     pub fn add_source_module(&mut self, module: &SourceModule) {
+        if !self.resolve_module_conflict("source module", &module.name, &module.source, None) {
+            return;
+        }
+
         if !self.modules.contains_key(&module.name) {
             self.modules.insert(
                 module.name.clone(),
@@ -274,6 +599,15 @@ impl EmbeddedPythonResourcesPrePackaged {
 
     /// Add a bytecode module to the collection of embedded bytecode modules.
     pub fn add_bytecode_module(&mut self, module: &BytecodeModule) {
+        if !self.resolve_module_conflict(
+            "bytecode module",
+            &module.name,
+            &module.source,
+            Some(module.optimize_level),
+        ) {
+            return;
+        }
+
         if !self.modules.contains_key(&module.name) {
             self.modules.insert(
                 module.name.clone(),
@@ -329,6 +663,10 @@ impl EmbeddedPythonResourcesPrePackaged {
     ///
     /// Resource data belongs to a Python package and has a name and bytes data.
     pub fn add_resource(&mut self, resource: &ResourceData) {
+        if !self.resolve_resource_conflict(resource) {
+            return;
+        }
+
         if !self.modules.contains_key(&resource.package) {
             self.modules.insert(
                 resource.package.clone(),
@@ -353,8 +691,74 @@ impl EmbeddedPythonResourcesPrePackaged {
             .insert(resource.name.clone(), resource.data.clone());
     }
 
+    /// Add a file in a Python package's distribution metadata.
+    ///
+    /// Files are stored on the entry named after the distribution, keyed by
+    /// their path in the metadata directory.
+    pub fn add_distribution_resource(&mut self, resource: &DistributionResource) {
+        if !self.modules.contains_key(&resource.package) {
+            self.modules.insert(
+                resource.package.clone(),
+                EmbeddedResourcePythonModulePrePackaged {
+                    name: resource.package.clone(),
+                    ..EmbeddedResourcePythonModulePrePackaged::default()
+                },
+            );
+        }
+
+        let entry = self.modules.get_mut(&resource.package).unwrap();
+
+        if entry.in_memory_package_distribution.is_none() {
+            entry.in_memory_package_distribution = Some(BTreeMap::new());
+        }
+
+        entry
+            .in_memory_package_distribution
+            .as_mut()
+            .unwrap()
+            .insert(resource.name.clone(), resource.data.clone());
+    }
+
+    /// Define how `__file__` is set for a module or package.
+    ///
+    /// Does nothing if no module with the name has been added.
+    pub fn set_file_emulation(&mut self, name: &str, policy: FileEmulation) {
+        if let Some(entry) = self.modules.get_mut(name) {
+            entry.file_emulation = Some(policy);
+        }
+    }
+
+    /// Define source transforms for modules in a package.
+    ///
+    /// Transforms apply to the package and its sub-packages. Transforms of
+    /// the most specific package apply when packages are nested.
+    pub fn set_source_transforms(&mut self, package: &str, transforms: SourceTransforms) {
+        self.source_transforms
+            .insert(package.to_string(), transforms);
+    }
+
+    /// Resolve the source transforms applying to a module.
+    fn resolve_source_transforms(&self, name: &str) -> Option<&SourceTransforms> {
+        self.source_transforms
+            .iter()
+            .filter(|(package, _)| {
+                name == package.as_str() || name.starts_with(&format!("{}.", package))
+            })
+            .max_by_key(|(package, _)| package.len())
+            .map(|(_, transforms)| transforms)
+    }
+
     /// Add an extension module.
     pub fn add_extension_module(&mut self, module: &ExtensionModule) {
+        if self.extension_modules.get(&module.module) != Some(module)
+            && !self.resolve_extension_module_conflict(
+                &module.module,
+                format!("{} variant", module.variant),
+            )
+        {
+            return;
+        }
+
         self.extension_modules
             .insert(module.module.clone(), module.clone());
 
@@ -380,6 +784,15 @@ impl EmbeddedPythonResourcesPrePackaged {
 
     /// Add an extension module.
     pub fn add_extension_module_data(&mut self, module: &ExtensionModuleData) {
+        if self.extension_module_datas.get(&module.name) != Some(module)
+            && !self.resolve_extension_module_conflict(
+                &module.name,
+                "extension module data".to_string(),
+            )
+        {
+            return;
+        }
+
         self.extension_module_datas
             .insert(module.name.clone(), module.clone());
 
@@ -465,6 +878,63 @@ impl EmbeddedPythonResourcesPrePackaged {
         Ok(())
     }
 
+    /// Remove modules, extension modules, and resources of packages.
+    ///
+    /// Entries belonging to each package or any of its sub-packages are removed.
+    pub fn remove_packages(&mut self, packages: &[String]) {
+        let in_packages = |name: &str| {
+            packages
+                .iter()
+                .any(|p| name == p || name.starts_with(&format!("{}.", p)))
+        };
+
+        self.modules.retain(|name, _| !in_packages(name));
+        self.extension_modules.retain(|name, _| !in_packages(name));
+        self.extension_module_datas
+            .retain(|name, _| !in_packages(name));
+    }
+
+    /// Remove modules and extension modules by name.
+    ///
+    /// Unlike `remove_packages()`, sub-packages are kept.
+    pub fn remove_modules(&mut self, names: &[String]) {
+        self.modules.retain(|name, _| !names.contains(name));
+        self.extension_modules
+            .retain(|name, _| !names.contains(name));
+    }
+
+    /// Remove modules, extension modules, and resources not allowed to be imported.
+    ///
+    /// Distribution metadata of removed packages is kept, as it isn't imported.
+    pub fn restrict_imports(&mut self, allow: Option<&[String]>, deny: &[String]) {
+        let removed = self
+            .modules
+            .keys()
+            .filter(|name| !is_import_allowed(name, allow, deny))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for name in removed {
+            let entry = self.modules.remove(&name).unwrap();
+
+            if entry.in_memory_package_distribution.is_some() {
+                self.modules.insert(
+                    name.clone(),
+                    EmbeddedResourcePythonModulePrePackaged {
+                        name,
+                        in_memory_package_distribution: entry.in_memory_package_distribution,
+                        ..EmbeddedResourcePythonModulePrePackaged::default()
+                    },
+                );
+            }
+        }
+
+        self.extension_modules
+            .retain(|name, _| is_import_allowed(name, allow, deny));
+        self.extension_module_datas
+            .retain(|name, _| is_import_allowed(name, allow, deny));
+    }
+
     /// Searches for embedded module sources for references to __file__.
     ///
     /// __file__ usage can be problematic for in-memory modules. This method searches
@@ -501,6 +971,43 @@ impl EmbeddedPythonResourcesPrePackaged {
         Ok(res)
     }
 
+    /// Searches for embedded module sources for calls that import modules dynamically.
+    ///
+    /// Modules imported dynamically can't be discovered by packaging tools and
+    /// may be missing at run-time. Returns the calls found keyed by the name
+    /// of the module containing them.
+    pub fn find_dynamic_imports(&self) -> Result<BTreeMap<String, Vec<DynamicImport>>> {
+        let mut res = BTreeMap::new();
+
+        for (name, module) in &self.modules {
+            let location = if let Some(location) = &module.in_memory_source {
+                location
+            } else if let Some(location) = &module.in_memory_bytecode {
+                location
+            } else if let Some(location) = &module.in_memory_bytecode_opt1 {
+                location
+            } else if let Some(location) = &module.in_memory_bytecode_opt2 {
+                location
+            } else {
+                continue;
+            };
+
+            let imports = find_dynamic_imports(&location.resolve()?)?;
+            if !imports.is_empty() {
+                res.insert(name.clone(), imports);
+            }
+        }
+
+        Ok(res)
+    }
+
+    /// Whether a module is embedded as Python or as an extension module.
+    fn has_module(&self, name: &str) -> bool {
+        self.modules.contains_key(name)
+            || self.extension_modules.contains_key(name)
+            || self.extension_module_datas.contains_key(name)
+    }
+
     /// Transform this instance into embedded resources data.
     ///
     /// This method performs actions necessary to produce entities which will allow the
@@ -520,7 +1027,7 @@ impl EmbeddedPythonResourcesPrePackaged {
             warn!(logger, "__file__ was encountered in some embedded modules");
             warn!(
                 logger,
-                "PyOxidizer does not set __file__ and this may create problems at run-time"
+                "PyOxidizer does not set __file__ unless a file_emulation policy is defined and this may create problems at run-time"
             );
             warn!(
                 logger,
@@ -528,42 +1035,68 @@ impl EmbeddedPythonResourcesPrePackaged {
             );
         }
 
-        let mut modules = BTreeMap::new();
-
-        let mut compiler = BytecodeCompiler::new(&python_exe)?;
-        {
-            for (name, module) in &self.modules {
-                let mut entry = EmbeddedResource::try_from(module)?;
-
-                if let Some(location) = &module.in_memory_bytecode {
-                    entry.in_memory_bytecode = Some(Cow::Owned(compiler.compile(
-                        &location.resolve()?,
-                        &name,
-                        BytecodeOptimizationLevel::Zero,
-                        CompileMode::Bytecode,
-                    )?));
-                }
-
-                if let Some(location) = &module.in_memory_bytecode_opt1 {
-                    entry.in_memory_bytecode_opt1 = Some(Cow::Owned(compiler.compile(
-                        &location.resolve()?,
-                        &name,
-                        BytecodeOptimizationLevel::One,
-                        CompileMode::Bytecode,
-                    )?));
+        let mut missing_seen = false;
+        for (module, imports) in self.find_dynamic_imports()? {
+            for import in imports {
+                match import.module {
+                    Some(name) if !self.has_module(&name) => {
+                        missing_seen = true;
+                        warn!(
+                            logger,
+                            "warning: {} imports {} via {}() but {} is not embedded",
+                            module,
+                            name,
+                            import.function,
+                            name
+                        );
+                    }
+                    Some(_) => {}
+                    None => {
+                        info!(
+                            logger,
+                            "{} may import modules dynamically via {}()", module, import.function
+                        );
+                    }
                 }
+            }
+        }
 
-                if let Some(location) = &module.in_memory_bytecode_opt2 {
-                    entry.in_memory_bytecode_opt2 = Some(Cow::Owned(compiler.compile(
-                        &location.resolve()?,
-                        &name,
-                        BytecodeOptimizationLevel::Two,
-                        CompileMode::Bytecode,
-                    )?));
-                }
+        if missing_seen {
+            warn!(
+                logger,
+                "some modules imported dynamically are not embedded and will fail to import at run-time"
+            );
+            warn!(
+                logger,
+                "add these modules to the executable explicitly, e.g. with PythonExecutable.add_python_resource()"
+            );
+        }
 
-                modules.insert(name.clone(), entry);
+        // Bytecode is compiled now so errors surface before anything is
+        // built. It is spooled to files, so it isn't held in memory until
+        // resources are written.
+        let bytecode_dir = crate::scratch::scratch_dir("bytecode")?;
+        let mut modules = BTreeMap::new();
+        {
+            let _phase = timing::phase("compile bytecode");
+
+            let mut progress =
+                Progress::new(logger, "compiling bytecode", self.modules.len() as u64);
+
+            let mut compiler = None;
+            for (i, (name, module)) in self.modules.iter().enumerate() {
+                let resource = self.compile_module(
+                    &mut compiler,
+                    python_exe,
+                    name,
+                    module,
+                    &bytecode_dir.path().join(i.to_string()),
+                )?;
+                modules.insert(name.clone(), resource);
+                progress.inc(1);
             }
+
+            progress.finish();
         }
 
         let ignored = OS_IGNORE_EXTENSIONS
@@ -578,13 +1111,7 @@ impl EmbeddedPythonResourcesPrePackaged {
             }
 
             if !modules.contains_key(name) {
-                modules.insert(
-                    name.clone(),
-                    EmbeddedResource {
-                        name: Cow::Owned(name.clone()),
-                        ..EmbeddedResource::default()
-                    },
-                );
+                modules.insert(name.clone(), PackagedResource::default());
             }
 
             extension_modules.insert(name.clone(), em.clone());
@@ -597,13 +1124,7 @@ impl EmbeddedPythonResourcesPrePackaged {
             }
 
             if !modules.contains_key(name) {
-                modules.insert(
-                    name.clone(),
-                    EmbeddedResource {
-                        name: Cow::Owned(name.clone()),
-                        ..EmbeddedResource::default()
-                    },
-                );
+                modules.insert(name.clone(), PackagedResource::default());
             }
 
             let mut entry = modules.get_mut(name).unwrap();
@@ -619,13 +1140,7 @@ impl EmbeddedPythonResourcesPrePackaged {
 
         for package in derived_package_names {
             if !modules.contains_key(&package) {
-                modules.insert(
-                    package.clone(),
-                    EmbeddedResource {
-                        name: Cow::Owned(package.clone()),
-                        ..EmbeddedResource::default()
-                    },
-                );
+                modules.insert(package.clone(), PackagedResource::default());
             }
 
             let mut entry = modules.get_mut(&package).unwrap();
@@ -644,41 +1159,256 @@ impl EmbeddedPythonResourcesPrePackaged {
             modules,
             extension_modules,
             built_extension_modules,
+            encryption_key: None,
+            _bytecode_dir: Arc::new(bytecode_dir),
         })
     }
+
+    /// Apply source transforms to a module and compile its bytecode.
+    ///
+    /// Results are written to files whose paths start with `dest_prefix`.
+    /// The compiler is started when first needed.
+    fn compile_module<'a>(
+        &self,
+        compiler: &mut Option<BytecodeCompiler>,
+        python_exe: &Path,
+        name: &str,
+        module: &'a EmbeddedResourcePythonModulePrePackaged,
+        dest_prefix: &Path,
+    ) -> Result<PackagedResource<'a>> {
+        let mut resource = PackagedResource {
+            module: Some(module),
+            is_package: module.is_package,
+            ..PackagedResource::default()
+        };
+
+        let transforms = self.resolve_source_transforms(name);
+        let transforms_source = transforms.map_or(false, |t| t.transforms_source());
+
+        if !transforms_source
+            && module.in_memory_bytecode.is_none()
+            && module.in_memory_bytecode_opt1.is_none()
+            && module.in_memory_bytecode_opt2.is_none()
+        {
+            return Ok(resource);
+        }
+
+        if compiler.is_none() {
+            *compiler = Some(BytecodeCompiler::new(python_exe)?);
+        }
+        let compiler = compiler.as_mut().unwrap();
+
+        let spool = |suffix: &str, data: Vec<u8>| -> Result<Option<DataLocation>> {
+            let path = PathBuf::from(format!("{}.{}", dest_prefix.display(), suffix));
+            std::fs::write(&path, data)?;
+
+            Ok(Some(DataLocation::Path(path)))
+        };
+
+        if transforms_source {
+            if let Some(location) = &module.in_memory_source {
+                let source =
+                    compiler.transform_source(&location.resolve()?, name, transforms.unwrap())?;
+                resource.in_memory_source = spool("py", source)?;
+            }
+        }
+
+        if let Some(location) = &module.in_memory_bytecode {
+            let bytecode = compile_module_bytecode(
+                compiler,
+                &location.resolve()?,
+                name,
+                BytecodeOptimizationLevel::Zero,
+                transforms,
+            )?;
+            resource.in_memory_bytecode = spool("pyc", bytecode)?;
+        }
+
+        if let Some(location) = &module.in_memory_bytecode_opt1 {
+            let bytecode = compile_module_bytecode(
+                compiler,
+                &location.resolve()?,
+                name,
+                BytecodeOptimizationLevel::One,
+                transforms,
+            )?;
+            resource.in_memory_bytecode_opt1 = spool("opt-1.pyc", bytecode)?;
+        }
+
+        if let Some(location) = &module.in_memory_bytecode_opt2 {
+            let bytecode = compile_module_bytecode(
+                compiler,
+                &location.resolve()?,
+                name,
+                BytecodeOptimizationLevel::Two,
+                transforms,
+            )?;
+            resource.in_memory_bytecode_opt2 = spool("opt-2.pyc", bytecode)?;
+        }
+
+        Ok(resource)
+    }
 }
 
-/// Represents Python resources to embed in a binary.
+/// A resource to embed in a binary, whose data is resolved when written.
 #[derive(Debug, Default, Clone)]
+pub struct PackagedResource<'a> {
+    /// The module holding the data of the resource, if any.
+    ///
+    /// Resources of extension modules and packages derived from module
+    /// names hold no data.
+    pub module: Option<&'a EmbeddedResourcePythonModulePrePackaged>,
+
+    pub is_package: bool,
+
+    /// Module source with source transforms applied, if any apply.
+    pub in_memory_source: Option<DataLocation>,
+
+    /// Compiled bytecode of the module, for each optimization level.
+    pub in_memory_bytecode: Option<DataLocation>,
+    pub in_memory_bytecode_opt1: Option<DataLocation>,
+    pub in_memory_bytecode_opt2: Option<DataLocation>,
+}
+
+/// Represents Python resources to embed in a binary.
+///
+/// Resources don't hold their data. Bytecode is compiled when resources are
+/// packaged and kept in files. When resources are written, the data of each
+/// resource is resolved and it is written before moving to the next, so
+/// only one resource is held in memory at a time.
+#[derive(Debug, Clone)]
 pub struct EmbeddedPythonResources<'a> {
-    /// Python modules described by an embeddable resource.
-    pub modules: BTreeMap<String, EmbeddedResource<'a, u8>>,
+    /// Python modules and resources to embed, by name.
+    pub modules: BTreeMap<String, PackagedResource<'a>>,
 
     // TODO combine the extension module types.
     pub extension_modules: BTreeMap<String, ExtensionModule>,
     pub built_extension_modules: BTreeMap<String, ExtensionModuleData>,
+
+    /// Key encrypting Python module source and bytecode.
+    encryption_key: Option<Vec<u8>>,
+
+    /// Directory holding compiled bytecode, removed when dropped.
+    _bytecode_dir: Arc<tempdir::TempDir>,
 }
 
 impl<'a> EmbeddedPythonResources<'a> {
-    pub fn write_blobs<W: Write>(&self, module_names: &mut W, resources: &mut W) {
+    /// Encrypt Python module source and bytecode with a key.
+    pub fn encrypt(&mut self, key: &[u8]) {
+        self.encryption_key = Some(key.to_vec());
+    }
+
+    /// Resolve the data of a resource.
+    fn resolve_resource(
+        &self,
+        name: &str,
+        resource: &PackagedResource,
+    ) -> Result<EmbeddedResource<'static, u8>> {
+        let resolve = |location: &Option<DataLocation>| -> Result<Option<Cow<'static, [u8]>>> {
+            Ok(match location {
+                Some(location) => Some(Cow::Owned(location.resolve()?)),
+                None => None,
+            })
+        };
+
+        let mut entry = match resource.module {
+            Some(module) => {
+                let mut entry = EmbeddedResource::try_from(module)?;
+
+                if resource.in_memory_source.is_some() {
+                    entry.in_memory_source = resolve(&resource.in_memory_source)?;
+                }
+                entry.in_memory_bytecode = resolve(&resource.in_memory_bytecode)?;
+                entry.in_memory_bytecode_opt1 = resolve(&resource.in_memory_bytecode_opt1)?;
+                entry.in_memory_bytecode_opt2 = resolve(&resource.in_memory_bytecode_opt2)?;
+
+                entry
+            }
+            None => EmbeddedResource {
+                name: Cow::Owned(name.to_string()),
+                ..EmbeddedResource::default()
+            },
+        };
+
+        if resource.is_package {
+            entry.is_package = true;
+        }
+
+        if let Some(key) = &self.encryption_key {
+            entry.encrypt(key);
+        }
+
+        entry.content_digest = Some(Cow::Owned(entry.compute_content_digest()));
+
+        Ok(entry)
+    }
+
+    /// Write the list of module names and packed resources data.
+    ///
+    /// Blob sections of the packed resources data are spooled to files in
+    /// `spool_dir` while resources are written.
+    pub fn write_blobs<W: Write, R: Write>(
+        &self,
+        module_names: &mut W,
+        resources: &mut R,
+        spool_dir: &Path,
+    ) -> Result<()> {
         for name in self.modules.keys() {
-            module_names
-                .write_all(name.as_bytes())
-                .expect("failed to write");
-            module_names.write_all(b"\n").expect("failed to write");
-        }
-
-        write_embedded_resources_v1(
-            &self
-                .modules
-                .values()
-                .cloned()
-                .collect::<Vec<EmbeddedResource<'a, u8>>>(),
-            resources,
-            None,
-        )
-        .unwrap();
+            module_names.write_all(name.as_bytes())?;
+            module_names.write_all(b"\n")?;
+        }
+
+        let spool_dir = spool_dir.to_path_buf();
+        let mut writer = ResourcesWriter::new(2, None, move |field| {
+            let field: u8 = field.into();
+
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(spool_dir.join(format!("section-{}", field)))
+        })?;
+
+        for (name, resource) in &self.modules {
+            writer.add(&self.resolve_resource(name, resource)?)?;
+        }
+
+        writer.finish(resources)
+    }
+}
+
+/// Merge packed resources data from executables sharing a resources file.
+///
+/// Resources present in both inputs must be identical.
+pub fn merge_packed_resources(a: &[u8], b: &[u8]) -> Result<Vec<u8>> {
+    let mut resources: BTreeMap<String, EmbeddedResource<u8>> = BTreeMap::new();
+
+    for data in [a, b].iter() {
+        for resource in load_resources(data).map_err(|e| anyhow!(e))? {
+            let resource = resource.map_err(|e| anyhow!(e))?;
+
+            if let Some(existing) = resources.get(resource.name.as_ref()) {
+                if existing != &resource {
+                    return Err(anyhow!(
+                        "resource {} differs between executables sharing a resources file",
+                        resource.name
+                    ));
+                }
+            } else {
+                resources.insert(resource.name.to_string(), resource);
+            }
+        }
     }
+
+    let mut merged = Vec::new();
+    write_embedded_resources_v2(
+        &resources.values().cloned().collect::<Vec<_>>(),
+        &mut merged,
+        None,
+    )?;
+
+    Ok(merged)
 }
 
 #[cfg(test)]
@@ -747,6 +1477,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_remove_packages() {
+        let mut r = EmbeddedPythonResourcesPrePackaged::default();
+        for name in &["foo", "foo.bar", "foobar", "baz"] {
+            r.add_source_module(&SourceModule {
+                name: name.to_string(),
+                source: DataLocation::Memory(vec![42]),
+                is_package: false,
+            });
+        }
+
+        r.remove_packages(&["foo".to_string()]);
+
+        assert_eq!(
+            r.modules.keys().cloned().collect::<Vec<_>>(),
+            vec!["baz".to_string(), "foobar".to_string()]
+        );
+    }
+
     #[test]
     fn test_add_bytecode_module() {
         let mut r = EmbeddedPythonResourcesPrePackaged::default();
@@ -755,6 +1504,8 @@ mod tests {
             source: DataLocation::Memory(vec![42]),
             optimize_level: BytecodeOptimizationLevel::Zero,
             is_package: false,
+            source_stripped: false,
+            invalidation_mode: PycInvalidationMode::default(),
         });
 
         assert!(r.modules.contains_key("foo"));
@@ -777,6 +1528,8 @@ mod tests {
             source: DataLocation::Memory(vec![42]),
             optimize_level: BytecodeOptimizationLevel::One,
             is_package: true,
+            source_stripped: false,
+            invalidation_mode: PycInvalidationMode::default(),
         });
 
         assert_eq!(r.modules.len(), 3);
@@ -834,6 +1587,243 @@ mod tests {
         );
     }
 
+    fn conflicting_module(r: &mut EmbeddedPythonResourcesPrePackaged, level: i32) {
+        r.add_source_module(&SourceModule {
+            name: "foo".to_string(),
+            source: DataLocation::Memory(vec![42]),
+            is_package: false,
+        });
+        r.add_bytecode_module(&BytecodeModule {
+            name: "foo".to_string(),
+            source: DataLocation::Memory(vec![43]),
+            optimize_level: BytecodeOptimizationLevel::from(level),
+            is_package: false,
+            source_stripped: false,
+            invalidation_mode: PycInvalidationMode::default(),
+        });
+    }
+
+    #[test]
+    fn test_conflict_policy() {
+        assert_eq!(
+            ConflictPolicy::try_from("prefer-higher-optimize"),
+            Ok(ConflictPolicy::PreferHigherOptimize)
+        );
+        assert!(ConflictPolicy::try_from("newest").is_err());
+
+        let mut r = EmbeddedPythonResourcesPrePackaged::default();
+        conflicting_module(&mut r, 0);
+        let conflicts = r.take_conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(
+            conflicts[0].to_string(),
+            "bytecode module foo from 1 bytes in memory conflicts with 1 bytes in memory"
+        );
+        assert!(conflicts[0].replaced);
+        let entry = r.modules.get("foo").unwrap();
+        assert_eq!(entry.in_memory_source, None);
+        assert_eq!(
+            entry.in_memory_bytecode,
+            Some(DataLocation::Memory(vec![43]))
+        );
+        assert!(r.take_conflicts().is_empty());
+
+        for policy in &[ConflictPolicy::Error, ConflictPolicy::FirstWins] {
+            let mut r = EmbeddedPythonResourcesPrePackaged::default();
+            r.set_conflict_policy(*policy);
+            conflicting_module(&mut r, 0);
+            assert!(!r.take_conflicts()[0].replaced);
+            let entry = r.modules.get("foo").unwrap();
+            assert_eq!(entry.in_memory_source, Some(DataLocation::Memory(vec![42])));
+            assert_eq!(entry.in_memory_bytecode, None);
+        }
+
+        let mut r = EmbeddedPythonResourcesPrePackaged::default();
+        r.set_conflict_policy(ConflictPolicy::PreferHigherOptimize);
+        conflicting_module(&mut r, 1);
+        assert!(r.take_conflicts()[0].replaced);
+        r.add_source_module(&SourceModule {
+            name: "foo".to_string(),
+            source: DataLocation::Memory(vec![42]),
+            is_package: false,
+        });
+        assert!(!r.take_conflicts()[0].replaced);
+        assert_eq!(
+            r.modules.get("foo").unwrap().in_memory_bytecode_opt1,
+            Some(DataLocation::Memory(vec![43]))
+        );
+    }
+
+    #[test]
+    fn test_add_identical_module() {
+        let mut r = EmbeddedPythonResourcesPrePackaged::default();
+        r.add_source_module(&SourceModule {
+            name: "foo.bar".to_string(),
+            source: DataLocation::Memory(vec![42]),
+            is_package: false,
+        });
+        r.add_source_module(&SourceModule {
+            name: "foo".to_string(),
+            source: DataLocation::Memory(vec![1]),
+            is_package: true,
+        });
+        r.add_bytecode_module(&BytecodeModule {
+            name: "foo.bar".to_string(),
+            source: DataLocation::Memory(vec![42]),
+            optimize_level: BytecodeOptimizationLevel::Two,
+            is_package: false,
+            source_stripped: false,
+            invalidation_mode: PycInvalidationMode::default(),
+        });
+        r.add_resource(&ResourceData {
+            package: "foo".to_string(),
+            name: "resource.txt".to_string(),
+            data: DataLocation::Memory(vec![42]),
+        });
+        r.add_resource(&ResourceData {
+            package: "foo".to_string(),
+            name: "resource.txt".to_string(),
+            data: DataLocation::Memory(vec![42]),
+        });
+
+        assert!(r.take_conflicts().is_empty());
+
+        r.add_resource(&ResourceData {
+            package: "foo".to_string(),
+            name: "resource.txt".to_string(),
+            data: DataLocation::Memory(vec![43]),
+        });
+        let conflicts = r.take_conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].name, "foo:resource.txt");
+    }
+
+    #[test]
+    fn test_restrict_imports() {
+        let mut r = EmbeddedPythonResourcesPrePackaged::default();
+        for name in &[
+            "_ctypes",
+            "ctypes.util",
+            "json.decoder",
+            "myapp.cli",
+            "socket",
+        ] {
+            r.add_source_module(&SourceModule {
+                name: name.to_string(),
+                source: DataLocation::Memory(vec![42]),
+                is_package: false,
+            });
+        }
+        r.add_distribution_resource(&DistributionResource {
+            package: "ctypes".to_string(),
+            version: "1.0".to_string(),
+            name: "METADATA".to_string(),
+            data: DataLocation::Memory(vec![42]),
+        });
+
+        r.restrict_imports(None, &["ctypes".to_string()]);
+        assert_eq!(
+            r.modules.keys().collect::<Vec<_>>(),
+            vec![
+                "ctypes",
+                "json",
+                "json.decoder",
+                "myapp",
+                "myapp.cli",
+                "socket"
+            ]
+        );
+        assert_eq!(r.modules.get("ctypes").unwrap().in_memory_source, None);
+
+        r.restrict_imports(Some(&["myapp.cli".to_string()]), &[]);
+        assert_eq!(
+            r.modules.keys().collect::<Vec<_>>(),
+            vec!["ctypes", "myapp", "myapp.cli"]
+        );
+    }
+
+    #[test]
+    fn test_restrict_imports_accelerators() {
+        let mut r = EmbeddedPythonResourcesPrePackaged::default();
+        for name in &["_socket", "_ssl", "socket", "ssl"] {
+            r.add_source_module(&SourceModule {
+                name: name.to_string(),
+                source: DataLocation::Memory(vec![42]),
+                is_package: false,
+            });
+        }
+
+        r.restrict_imports(Some(&["ssl".to_string()]), &["socket".to_string()]);
+        assert_eq!(r.modules.keys().collect::<Vec<_>>(), vec!["_ssl", "ssl"]);
+    }
+
+    #[test]
+    fn test_add_distribution_resource() {
+        let mut r = EmbeddedPythonResourcesPrePackaged::default();
+        r.add_distribution_resource(&DistributionResource {
+            package: "foo".to_string(),
+            version: "1.0".to_string(),
+            name: "METADATA".to_string(),
+            data: DataLocation::Memory(vec![42]),
+        });
+
+        assert_eq!(r.modules.len(), 1);
+        assert_eq!(
+            r.modules.get("foo"),
+            Some(&EmbeddedResourcePythonModulePrePackaged {
+                name: "foo".to_string(),
+                in_memory_package_distribution: Some(BTreeMap::from_iter(
+                    [("METADATA".to_string(), DataLocation::Memory(vec![42]))]
+                        .iter()
+                        .cloned()
+                )),
+                ..EmbeddedResourcePythonModulePrePackaged::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_set_file_emulation() {
+        let mut r = EmbeddedPythonResourcesPrePackaged::default();
+        r.add_source_module(&SourceModule {
+            name: "foo".to_string(),
+            source: DataLocation::Memory(vec![42]),
+            is_package: false,
+        });
+        r.set_file_emulation("foo", FileEmulation::FakePath);
+        r.set_file_emulation("missing", FileEmulation::FakePath);
+
+        assert_eq!(r.modules.len(), 1);
+        assert_eq!(
+            r.modules.get("foo").unwrap().file_emulation,
+            Some(FileEmulation::FakePath)
+        );
+
+        let resource = EmbeddedResource::try_from(r.modules.get("foo").unwrap()).unwrap();
+        assert_eq!(resource.file_emulation, Some(FileEmulation::FakePath));
+    }
+
+    #[test]
+    fn test_resolve_source_transforms() {
+        let mut r = EmbeddedPythonResourcesPrePackaged::default();
+        let minify = SourceTransforms {
+            minify: true,
+            ..SourceTransforms::default()
+        };
+        let strip = SourceTransforms {
+            strip_docstrings: true,
+            ..SourceTransforms::default()
+        };
+        r.set_source_transforms("foo", minify);
+        r.set_source_transforms("foo.bar", strip);
+
+        assert_eq!(r.resolve_source_transforms("foo"), Some(&minify));
+        assert_eq!(r.resolve_source_transforms("foo.baz"), Some(&minify));
+        assert_eq!(r.resolve_source_transforms("foo.bar"), Some(&strip));
+        assert_eq!(r.resolve_source_transforms("foo.bar.baz"), Some(&strip));
+        assert_eq!(r.resolve_source_transforms("foobar"), None);
+    }
+
     #[test]
     fn test_add_extension_module() {
         let mut r = EmbeddedPythonResourcesPrePackaged::default();
@@ -924,10 +1914,123 @@ mod tests {
             source: DataLocation::Memory(Vec::from("import foo; if __file__")),
             optimize_level: BytecodeOptimizationLevel::Zero,
             is_package: false,
+            source_stripped: false,
+            invalidation_mode: PycInvalidationMode::default(),
         });
         assert_eq!(r.find_dunder_file()?.len(), 2);
         assert!(r.find_dunder_file()?.contains("bytecode"));
 
         Ok(())
     }
+
+    #[test]
+    fn test_find_dynamic_imports() -> Result<()> {
+        let mut r = EmbeddedPythonResourcesPrePackaged::default();
+        assert!(r.find_dynamic_imports()?.is_empty());
+
+        r.add_source_module(&SourceModule {
+            name: "foo".to_string(),
+            source: DataLocation::Memory(Vec::from("import bar")),
+            is_package: false,
+        });
+        assert!(r.find_dynamic_imports()?.is_empty());
+
+        r.add_source_module(&SourceModule {
+            name: "baz".to_string(),
+            source: DataLocation::Memory(Vec::from("m = __import__('foo')")),
+            is_package: false,
+        });
+
+        let imports = r.find_dynamic_imports()?;
+        assert_eq!(imports.len(), 1);
+        assert_eq!(
+            imports.get("baz").unwrap(),
+            &vec![DynamicImport {
+                function: "__import__".to_string(),
+                module: Some("foo".to_string()),
+            }]
+        );
+        assert!(r.has_module("foo"));
+        assert!(!r.has_module("bar"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_packed_resources() -> Result<()> {
+        let resource = |name: &'static str, source: &'static [u8]| EmbeddedResource {
+            name: Cow::Borrowed(name),
+            in_memory_source: Some(Cow::Borrowed(source)),
+            ..EmbeddedResource::default()
+        };
+
+        let mut a = Vec::new();
+        write_embedded_resources_v2(
+            &[resource("foo", b"foo"), resource("bar", b"bar")],
+            &mut a,
+            None,
+        )?;
+        let mut b = Vec::new();
+        write_embedded_resources_v2(
+            &[resource("foo", b"foo"), resource("baz", b"baz")],
+            &mut b,
+            None,
+        )?;
+
+        let merged = merge_packed_resources(&a, &b)?;
+        let names = load_resources(&merged)
+            .map_err(|e| anyhow!(e))?
+            .map(|r| r.unwrap().name.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["bar", "baz", "foo"]);
+
+        let mut c = Vec::new();
+        write_embedded_resources_v2(&[resource("foo", b"other")], &mut c, None)?;
+        assert!(merge_packed_resources(&a, &c).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_blobs_matches_v2() -> Result<()> {
+        let mut r = EmbeddedPythonResourcesPrePackaged::default();
+        r.add_source_module(&SourceModule {
+            name: "foo".to_string(),
+            source: DataLocation::Memory(b"import bar".to_vec()),
+            is_package: false,
+        });
+        r.add_source_module(&SourceModule {
+            name: "bar.baz".to_string(),
+            source: DataLocation::Memory(b"pass".to_vec()),
+            is_package: false,
+        });
+        r.add_resource(&ResourceData {
+            package: "foo".to_string(),
+            name: "data.txt".to_string(),
+            data: DataLocation::Memory(b"data".to_vec()),
+        });
+
+        // No bytecode is compiled, so the Python executable isn't run.
+        let logger = crate::testutil::get_logger()?;
+        let resources = r.package(&logger, Path::new("python-not-run"))?;
+
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+        let mut module_names = Vec::new();
+        let mut streamed = Vec::new();
+        resources.write_blobs(&mut module_names, &mut streamed, temp_dir.path())?;
+
+        assert_eq!(module_names, b"bar\nbar.baz\nfoo\n".to_vec());
+
+        let entries = resources
+            .modules
+            .iter()
+            .map(|(name, resource)| resources.resolve_resource(name, resource))
+            .collect::<Result<Vec<_>>>()?;
+        let mut expected = Vec::new();
+        write_embedded_resources_v2(&entries, &mut expected, None)?;
+
+        assert_eq!(streamed, expected);
+
+        Ok(())
+    }
 }
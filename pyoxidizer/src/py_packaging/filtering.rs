@@ -75,3 +75,70 @@ pub fn filter_btreemap<V>(
         }
     }
 }
+
+/// Modules imported while initializing the interpreter.
+///
+/// They are always allowed to be imported, as the interpreter can't start
+/// without them.
+pub const INTERPRETER_MODULES: &[&str] = &[
+    "_collections_abc",
+    "_sitebuiltins",
+    "abc",
+    "codecs",
+    "encodings",
+    "genericpath",
+    "io",
+    "ntpath",
+    "os",
+    "posixpath",
+    "site",
+    "stat",
+];
+
+/// Whether a module is one of the given packages or in one of them.
+fn in_packages(name: &str, packages: &[String]) -> bool {
+    packages
+        .iter()
+        .any(|p| name == p || name.starts_with(&format!("{}.", p)))
+}
+
+/// C extension modules implementing the functionality of pure Python modules.
+///
+/// Keep in sync with the table of the `pyembed` crate's import gate.
+const C_ACCELERATORS: &[(&str, &[&str])] = &[
+    ("ctypes", &["_ctypes"]),
+    ("multiprocessing", &["_multiprocessing", "_posixshmem"]),
+    ("socket", &["_socket"]),
+    ("sqlite3", &["_sqlite3"]),
+    ("ssl", &["_ssl"]),
+    ("subprocess", &["_posixsubprocess"]),
+];
+
+/// Whether a module is in one of the given packages or is the C accelerator
+/// of one of them.
+fn in_packages_or_accelerators(name: &str, packages: &[String]) -> bool {
+    in_packages(name, packages)
+        || C_ACCELERATORS.iter().any(|(module, accelerators)| {
+            accelerators.contains(&name) && in_packages(module, packages)
+        })
+}
+
+/// Whether importing a module is allowed by allow and deny lists.
+///
+/// Names in the lists match the module and its sub-modules, as well as the
+/// C extension modules implementing them, e.g. `socket` matches `_socket`.
+/// Parent packages of allowed modules are allowed too. Denied modules are
+/// never allowed.
+pub fn is_import_allowed(name: &str, allow: Option<&[String]>, deny: &[String]) -> bool {
+    if in_packages_or_accelerators(name, deny) {
+        return false;
+    }
+
+    match allow {
+        Some(allow) => {
+            in_packages_or_accelerators(name, allow)
+                || allow.iter().any(|m| m.starts_with(&format!("{}.", name)))
+        }
+        None => true,
+    }
+}
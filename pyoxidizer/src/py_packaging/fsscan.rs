@@ -8,9 +8,11 @@ Scanning the filesystem for Python resources.
 
 use {
     super::distribution::PythonModuleSuffixes,
+    crate::build_info::is_valid_module_name,
     anyhow::{Context, Result},
     itertools::Itertools,
-    std::collections::{BTreeMap, HashSet},
+    rayon::prelude::*,
+    std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque},
     std::ffi::OsStr,
     std::fs,
     std::path::{Path, PathBuf},
@@ -39,6 +41,46 @@ pub fn walk_tree_files(path: &Path) -> Box<dyn Iterator<Item = walkdir::DirEntry
     Box::new(filtered)
 }
 
+/// Find the files in a directory tree, walking sub-directories in parallel.
+///
+/// Files are returned in the order `walk_tree_files()` finds them: entries
+/// of each directory sorted by file name. As with `walk_tree_files()`,
+/// symlinks to directories below `path` aren't followed and are skipped.
+fn walk_tree_files_parallel(path: &Path) -> Vec<PathBuf> {
+    if path.is_dir() {
+        walk_dir_parallel(path)
+    } else {
+        vec![path.to_path_buf()]
+    }
+}
+
+fn walk_dir_parallel(path: &Path) -> Vec<PathBuf> {
+    let mut entries = fs::read_dir(path)
+        .expect("unable to read directory")
+        .map(|entry| {
+            let entry = entry.expect("unable to get directory entry");
+            let file_type = entry.file_type().expect("unable to get file type");
+
+            (entry.path(), file_type)
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.0.file_name().cmp(&b.0.file_name()));
+
+    entries
+        .par_iter()
+        .map(|(entry_path, file_type)| {
+            if file_type.is_dir() {
+                walk_dir_parallel(entry_path)
+            } else if file_type.is_symlink() && entry_path.is_dir() {
+                vec![]
+            } else {
+                vec![entry_path.clone()]
+            }
+        })
+        .collect::<Vec<_>>()
+        .concat()
+}
+
 #[derive(Debug, PartialEq)]
 pub struct FileBasedResource {
     pub package: String,
@@ -149,334 +191,370 @@ pub enum PythonFileResource {
     },
 }
 
+impl PythonFileResource {
+    /// The package of a module, which is a package on the import path.
+    ///
+    /// Resources and other files don't define packages.
+    fn module_package(&self) -> Option<&str> {
+        match self {
+            PythonFileResource::Source { package, .. }
+            | PythonFileResource::Bytecode { package, .. }
+            | PythonFileResource::BytecodeOpt1 { package, .. }
+            | PythonFileResource::BytecodeOpt2 { package, .. }
+            | PythonFileResource::ExtensionModule { package, .. } => Some(package),
+            _ => None,
+        }
+    }
+}
+
+/// Iterates over Python resources found in a directory.
+///
+/// The directory is walked and its files are classified in parallel when
+/// the iterator is constructed. Modules are emitted in the order they are
+/// found, followed by resources, whose package is resolved once all
+/// packages are known.
 pub struct PythonResourceIterator {
-    root_path: PathBuf,
-    suffixes: PythonModuleSuffixes,
-    walkdir_result: Box<dyn Iterator<Item = walkdir::DirEntry>>,
+    modules: std::vec::IntoIter<PythonFileResource>,
     seen_packages: HashSet<String>,
-    resources: Vec<FileBasedResource>,
+    resources: VecDeque<FileBasedResource>,
 }
 
 impl PythonResourceIterator {
     fn new(path: &Path, suffixes: &PythonModuleSuffixes) -> PythonResourceIterator {
-        let res = walkdir::WalkDir::new(path).sort_by(|a, b| a.file_name().cmp(b.file_name()));
+        Self::new_in(path, path, suffixes)
+    }
 
-        let filtered = res.into_iter().filter_map(|entry| {
-            let entry = entry.expect("unable to get directory entry");
+    /// Construct an instance finding resources under `walk_path`.
+    ///
+    /// Names of resources are relative to `path`.
+    fn new_in(
+        path: &Path,
+        walk_path: &Path,
+        suffixes: &PythonModuleSuffixes,
+    ) -> PythonResourceIterator {
+        Self::from_paths(path, walk_tree_files_parallel(walk_path), suffixes)
+    }
 
-            let path = entry.path();
+    /// Construct an instance classifying files under `path`.
+    ///
+    /// The files are classified by their paths alone and don't need to exist.
+    fn from_paths(
+        path: &Path,
+        file_paths: Vec<PathBuf>,
+        suffixes: &PythonModuleSuffixes,
+    ) -> PythonResourceIterator {
+        let resolved = file_paths
+            .par_iter()
+            .map(|file_path| resolve_file_path(path, suffixes, file_path))
+            .collect::<Vec<_>>();
 
-            if path.is_dir() {
-                None
-            } else {
-                Some(entry)
+        let mut it = PythonResourceIterator {
+            modules: Vec::new().into_iter(),
+            seen_packages: HashSet::new(),
+            resources: VecDeque::new(),
+        };
+
+        // Resources are buffered until all packages are known.
+        let mut modules = Vec::new();
+        for resource in resolved.into_iter().flatten() {
+            if let Some(package) = resource.module_package() {
+                it.add_seen_package(package);
             }
-        });
 
-        PythonResourceIterator {
-            root_path: path.to_path_buf(),
-            suffixes: suffixes.clone(),
-            walkdir_result: Box::new(filtered),
-            seen_packages: HashSet::new(),
-            resources: Vec::new(),
+            match resource {
+                PythonFileResource::Resource(resource) => it.resources.push_back(resource),
+                resource => modules.push(resource),
+            }
         }
+        it.modules = modules.into_iter();
+
+        it
     }
 
-    fn resolve_dir_entry(&mut self, entry: walkdir::DirEntry) -> Option<PythonFileResource> {
-        let path = entry.path();
+    /// Record a package and its parents as seen.
+    ///
+    /// Parents of packages are packages too, even if their directory has no
+    /// `__init__.py` file, as is the case for namespace packages.
+    fn add_seen_package(&mut self, package: &str) {
+        let mut name = package;
 
-        let mut rel_path = path
-            .strip_prefix(&self.root_path)
-            .expect("unable to strip path prefix");
-        let mut rel_str = rel_path.to_str().expect("could not convert path to str");
-        let mut components = rel_path
-            .iter()
-            .map(|p| p.to_str().expect("unable to get path as str"))
-            .collect::<Vec<_>>();
+        loop {
+            if !self.seen_packages.insert(name.to_string()) {
+                break;
+            }
 
-        // .dist-info directories contain packaging metadata. They aren't interesting to us.
-        // We /could/ emit these files if we wanted to. But until there is a need, exclude them.
-        if components[0].ends_with(".dist-info") {
-            return None;
+            match name.rfind('.') {
+                Some(pos) => name = &name[0..pos],
+                None => break,
+            }
         }
+    }
+}
 
-        // Ditto for .egg-info directories.
-        if components[0].ends_with(".egg-info") {
-            return None;
-        }
+/// Classify a file found in a directory of Python resources.
+///
+/// Names of resources are relative to `root_path`.
+fn resolve_file_path(
+    root_path: &Path,
+    suffixes: &PythonModuleSuffixes,
+    path: &Path,
+) -> Option<PythonFileResource> {
+    let mut rel_path = path
+        .strip_prefix(root_path)
+        .expect("unable to strip path prefix");
+    let mut rel_str = rel_path.to_str().expect("could not convert path to str");
+    let mut components = rel_path
+        .iter()
+        .map(|p| p.to_str().expect("unable to get path as str"))
+        .collect::<Vec<_>>();
+
+    // .dist-info directories contain packaging metadata. They aren't interesting to us.
+    // We /could/ emit these files if we wanted to. But until there is a need, exclude them.
+    if components[0].ends_with(".dist-info") {
+        return None;
+    }
 
-        // site-packages directories are package roots within package roots. Treat them as
-        // such.
-        let in_site_packages = if components[0] == "site-packages" {
-            let sp_path = self.root_path.join("site-packages");
-            rel_path = path
-                .strip_prefix(sp_path)
-                .expect("unable to strip site-packages prefix");
-
-            rel_str = rel_path.to_str().expect("could not convert path to str");
-            components = rel_path
-                .iter()
-                .map(|p| p.to_str().expect("unable to get path as str"))
-                .collect::<Vec<_>>();
-
-            true
-        } else {
-            false
-        };
+    // Ditto for .egg-info directories.
+    if components[0].ends_with(".egg-info") {
+        return None;
+    }
 
-        // It looks like we're in an unpacked egg. This is similar to the site-packages
-        // scenario: we essentially have a new package root that corresponds to the
-        // egg's extraction directory.
-        if (&components[0..components.len() - 1])
+    // site-packages directories are package roots within package roots. Treat them as
+    // such.
+    let in_site_packages = if components[0] == "site-packages" {
+        let sp_path = root_path.join("site-packages");
+        rel_path = path
+            .strip_prefix(sp_path)
+            .expect("unable to strip site-packages prefix");
+
+        rel_str = rel_path.to_str().expect("could not convert path to str");
+        components = rel_path
             .iter()
-            .any(|p| p.ends_with(".egg"))
-        {
-            let mut egg_root_path = self.root_path.clone();
+            .map(|p| p.to_str().expect("unable to get path as str"))
+            .collect::<Vec<_>>();
 
-            if in_site_packages {
-                egg_root_path = egg_root_path.join("site-packages");
-            }
+        true
+    } else {
+        false
+    };
 
-            for p in &components[0..components.len() - 1] {
-                egg_root_path = egg_root_path.join(p);
+    // It looks like we're in an unpacked egg. This is similar to the site-packages
+    // scenario: we essentially have a new package root that corresponds to the
+    // egg's extraction directory.
+    if (&components[0..components.len() - 1])
+        .iter()
+        .any(|p| p.ends_with(".egg"))
+    {
+        let mut egg_root_path = root_path.to_path_buf();
+
+        if in_site_packages {
+            egg_root_path = egg_root_path.join("site-packages");
+        }
 
-                if p.ends_with(".egg") {
-                    break;
-                }
-            }
+        for p in &components[0..components.len() - 1] {
+            egg_root_path = egg_root_path.join(p);
 
-            rel_path = path
-                .strip_prefix(egg_root_path)
-                .expect("unable to strip egg prefix");
-            components = rel_path
-                .iter()
-                .map(|p| p.to_str().expect("unable to get path as str"))
-                .collect::<Vec<_>>();
-
-            // Ignore EGG-INFO directory, as it is just packaging metadata.
-            if components[0] == "EGG-INFO" {
-                return None;
+            if p.ends_with(".egg") {
+                break;
             }
         }
 
-        let file_name = rel_path.file_name().unwrap().to_string_lossy();
+        rel_path = path
+            .strip_prefix(egg_root_path)
+            .expect("unable to strip egg prefix");
+        components = rel_path
+            .iter()
+            .map(|p| p.to_str().expect("unable to get path as str"))
+            .collect::<Vec<_>>();
 
-        for ext_suffix in &self.suffixes.extension {
-            if file_name.ends_with(ext_suffix) {
-                let package_parts = &components[0..components.len() - 1];
-                let mut package = itertools::join(package_parts, ".");
+        // Ignore EGG-INFO directory, as it is just packaging metadata.
+        if components[0] == "EGG-INFO" {
+            return None;
+        }
+    }
 
-                let module_name = &file_name[0..file_name.len() - ext_suffix.len()];
+    let file_name = rel_path.file_name().unwrap().to_string_lossy();
 
-                let mut full_module_name: Vec<&str> = package_parts.to_vec();
+    for ext_suffix in &suffixes.extension {
+        if file_name.ends_with(ext_suffix) {
+            let package_parts = &components[0..components.len() - 1];
+            let mut package = itertools::join(package_parts, ".");
 
-                let stem = if module_name == "__init__" {
-                    "".to_string()
-                } else {
-                    full_module_name.push(module_name);
-                    module_name.to_string()
-                };
+            let module_name = &file_name[0..file_name.len() - ext_suffix.len()];
 
-                let full_module_name = itertools::join(full_module_name, ".");
+            let mut full_module_name: Vec<&str> = package_parts.to_vec();
 
-                if package.is_empty() {
-                    package = full_module_name.clone();
-                }
+            let stem = if module_name == "__init__" {
+                "".to_string()
+            } else {
+                full_module_name.push(module_name);
+                module_name.to_string()
+            };
 
-                self.seen_packages.insert(package.clone());
+            let full_module_name = itertools::join(full_module_name, ".");
 
-                return Some(PythonFileResource::ExtensionModule {
-                    package,
-                    stem,
-                    full_name: full_module_name,
-                    path: path.to_path_buf(),
-                    extension_file_suffix: ext_suffix.clone(),
-                });
+            if package.is_empty() {
+                package = full_module_name.clone();
             }
+
+            return Some(PythonFileResource::ExtensionModule {
+                package,
+                stem,
+                full_name: full_module_name,
+                path: path.to_path_buf(),
+                extension_file_suffix: ext_suffix.clone(),
+            });
         }
+    }
 
-        // TODO use registered suffixes for source and bytecode detection.
-        let resource = match rel_path.extension().and_then(OsStr::to_str) {
-            Some("py") => {
-                let package_parts = &components[0..components.len() - 1];
-                let mut package = itertools::join(package_parts, ".");
+    // TODO use registered suffixes for source and bytecode detection.
+    let resource = match rel_path.extension().and_then(OsStr::to_str) {
+        Some("py") => {
+            let package_parts = &components[0..components.len() - 1];
+            let mut package = itertools::join(package_parts, ".");
 
-                let module_name = rel_path
-                    .file_stem()
-                    .expect("unable to get file stem")
-                    .to_str()
-                    .expect("unable to convert path to str");
+            let module_name = rel_path
+                .file_stem()
+                .expect("unable to get file stem")
+                .to_str()
+                .expect("unable to convert path to str");
 
-                let mut full_module_name: Vec<&str> = package_parts.to_vec();
+            let mut full_module_name: Vec<&str> = package_parts.to_vec();
 
-                let stem = if module_name == "__init__" {
-                    "".to_string()
-                } else {
-                    full_module_name.push(module_name);
-                    module_name.to_string()
-                };
+            let stem = if module_name == "__init__" {
+                "".to_string()
+            } else {
+                full_module_name.push(module_name);
+                module_name.to_string()
+            };
 
-                let full_module_name = itertools::join(full_module_name, ".");
+            let full_module_name = itertools::join(full_module_name, ".");
 
-                if package.is_empty() {
-                    package = full_module_name.clone();
-                }
+            if package.is_empty() {
+                package = full_module_name.clone();
+            }
+
+            PythonFileResource::Source {
+                package,
+                stem,
+                full_name: full_module_name,
+                path: path.to_path_buf(),
+            }
+        }
+        Some("pyc") => {
+            // .pyc files should be in a __pycache__ directory.
+            if components.len() < 2 {
+                panic!("encountered .pyc file with invalid path: {}", rel_str);
+            }
 
-                self.seen_packages.insert(package.clone());
+            // Possibly from Python 2?
+            if components[components.len() - 2] != "__pycache__" {
+                let package_parts = &components[0..components.len() - 1];
+                let package = itertools::join(package_parts, ".");
+                let full_name = itertools::join(&components, ".");
+                let stem = components[components.len() - 1].to_string();
 
-                PythonFileResource::Source {
+                return Some(PythonFileResource::Other {
                     package,
                     stem,
-                    full_name: full_module_name,
+                    full_name,
                     path: path.to_path_buf(),
-                }
+                });
             }
-            Some("pyc") => {
-                // .pyc files should be in a __pycache__ directory.
-                if components.len() < 2 {
-                    panic!("encountered .pyc file with invalid path: {}", rel_str);
-                }
-
-                // Possibly from Python 2?
-                if components[components.len() - 2] != "__pycache__" {
-                    let package_parts = &components[0..components.len() - 1];
-                    let package = itertools::join(package_parts, ".");
-                    let full_name = itertools::join(&components, ".");
-                    let stem = components[components.len() - 1].to_string();
-
-                    return Some(PythonFileResource::Other {
-                        package,
-                        stem,
-                        full_name,
-                        path: path.to_path_buf(),
-                    });
-                }
 
-                let package_parts = &components[0..components.len() - 2];
-                let mut package = itertools::join(package_parts, ".");
+            let package_parts = &components[0..components.len() - 2];
+            let mut package = itertools::join(package_parts, ".");
 
-                // Files have format <package>/__pycache__/<module>.cpython-37.opt-1.pyc
-                let module_name = rel_path
-                    .file_stem()
-                    .expect("unable to get file stem")
-                    .to_str()
-                    .expect("unable to convert file stem to str");
-                let module_name_parts = module_name.split('.').collect_vec();
-                let module_name =
-                    itertools::join(&module_name_parts[0..module_name_parts.len() - 1], ".");
+            // Files have format <package>/__pycache__/<module>.cpython-37.opt-1.pyc
+            let module_name = rel_path
+                .file_stem()
+                .expect("unable to get file stem")
+                .to_str()
+                .expect("unable to convert file stem to str");
+            let module_name_parts = module_name.split('.').collect_vec();
+            let module_name =
+                itertools::join(&module_name_parts[0..module_name_parts.len() - 1], ".");
 
-                let mut full_module_name: Vec<&str> = package_parts.to_vec();
+            let mut full_module_name: Vec<&str> = package_parts.to_vec();
 
-                let stem = if module_name == "__init__" {
-                    ""
-                } else {
-                    full_module_name.push(&module_name);
-                    &module_name
-                };
-
-                let full_module_name = itertools::join(full_module_name, ".");
-
-                if package.is_empty() {
-                    package = full_module_name.clone();
-                }
+            let stem = if module_name == "__init__" {
+                ""
+            } else {
+                full_module_name.push(&module_name);
+                &module_name
+            };
 
-                self.seen_packages.insert(package.clone());
+            let full_module_name = itertools::join(full_module_name, ".");
 
-                if rel_str.ends_with(".opt-1.pyc") {
-                    PythonFileResource::BytecodeOpt1 {
-                        package,
-                        stem: stem.to_string(),
-                        full_name: full_module_name,
-                        path: path.to_path_buf(),
-                    }
-                } else if rel_str.ends_with(".opt-2.pyc") {
-                    PythonFileResource::BytecodeOpt2 {
-                        package,
-                        stem: stem.to_string(),
-                        full_name: full_module_name,
-                        path: path.to_path_buf(),
-                    }
-                } else {
-                    PythonFileResource::Bytecode {
-                        package,
-                        stem: stem.to_string(),
-                        full_name: full_module_name,
-                        path: path.to_path_buf(),
-                    }
-                }
+            if package.is_empty() {
+                package = full_module_name.clone();
             }
-            Some("egg") => PythonFileResource::EggFile {
-                path: path.to_path_buf(),
-            },
-            Some("pth") => PythonFileResource::PthFile {
-                path: path.to_path_buf(),
-            },
-            _ => {
-                // If it isn't a .py or a .pyc file, it is a resource file.
-                let package_parts = &components[0..components.len() - 1];
-                let mut package = itertools::join(package_parts, ".");
 
-                let name = itertools::join(&components, ".");
-                let stem = components[components.len() - 1].to_string();
-
-                if package.is_empty() {
-                    package = name.clone();
+            if rel_str.ends_with(".opt-1.pyc") {
+                PythonFileResource::BytecodeOpt1 {
+                    package,
+                    stem: stem.to_string(),
+                    full_name: full_module_name,
+                    path: path.to_path_buf(),
                 }
-
-                PythonFileResource::Resource(FileBasedResource {
+            } else if rel_str.ends_with(".opt-2.pyc") {
+                PythonFileResource::BytecodeOpt2 {
                     package,
-                    stem,
-                    full_name: name,
+                    stem: stem.to_string(),
+                    full_name: full_module_name,
+                    path: path.to_path_buf(),
+                }
+            } else {
+                PythonFileResource::Bytecode {
+                    package,
+                    stem: stem.to_string(),
+                    full_name: full_module_name,
                     path: path.to_path_buf(),
-                })
+                }
+            }
+        }
+        Some("egg") => PythonFileResource::EggFile {
+            path: path.to_path_buf(),
+        },
+        Some("pth") => PythonFileResource::PthFile {
+            path: path.to_path_buf(),
+        },
+        _ => {
+            // If it isn't a .py or a .pyc file, it is a resource file.
+            let package_parts = &components[0..components.len() - 1];
+            let mut package = itertools::join(package_parts, ".");
+
+            let name = itertools::join(&components, ".");
+            let stem = components[components.len() - 1].to_string();
+
+            if package.is_empty() {
+                package = name.clone();
             }
-        };
 
-        Some(resource)
-    }
+            PythonFileResource::Resource(FileBasedResource {
+                package,
+                stem,
+                full_name: name,
+                path: path.to_path_buf(),
+            })
+        }
+    };
+
+    Some(resource)
 }
 
 impl Iterator for PythonResourceIterator {
     type Item = PythonFileResource;
 
     fn next(&mut self) -> Option<PythonFileResource> {
-        // Our strategy is to walk directory entries and buffer resource files locally.
-        // We then emit those at the end, perhaps doing some post-processing along the
-        // way.
-        loop {
-            let res = self.walkdir_result.next();
-
-            // We're out of directory entries;
-            if res.is_none() {
-                break;
-            }
-
-            let entry = res.unwrap();
-            let python_resource = self.resolve_dir_entry(entry);
-
-            // Try the next directory entry.
-            if python_resource.is_none() {
-                continue;
-            }
-
-            let python_resource = python_resource.unwrap();
-
-            // Buffer Resource entries until later.
-            if let PythonFileResource::Resource(resource) = python_resource {
-                self.resources.push(resource);
-                continue;
-            }
-
+        // Resource files are buffered and emitted after modules, perhaps doing some
+        // post-processing along the way.
+        if let Some(python_resource) = self.modules.next() {
             return Some(python_resource);
         }
 
         loop {
-            if self.resources.is_empty() {
-                return None;
-            }
-
-            // This isn't efficient. But we shouldn't care.
-            let resource = self.resources.remove(0);
+            let resource = self.resources.pop_front()?;
 
             // We initially resolve the package name from the relative filesystem path.
             // But not all directories are Python packages! When we encountered Python
@@ -545,6 +623,107 @@ pub fn find_python_resources(
     PythonResourceIterator::new(root_path, suffixes)
 }
 
+/// Find Python resources in a file or directory under a root directory.
+///
+/// Like `find_python_resources()`, except only `path` is walked.
+pub fn find_python_resources_in(
+    root_path: &Path,
+    path: &Path,
+    suffixes: &PythonModuleSuffixes,
+) -> PythonResourceIterator {
+    PythonResourceIterator::new_in(root_path, path, suffixes)
+}
+
+/// Find Python resources in a list of files under a root directory.
+///
+/// Like `find_python_resources()`, except the files are given instead of
+/// found by walking the directory. Files are classified by their paths and
+/// don't need to exist, e.g. when they are members of an archive.
+pub fn find_python_resources_in_paths(
+    root_path: &Path,
+    paths: Vec<PathBuf>,
+    suffixes: &PythonModuleSuffixes,
+) -> PythonResourceIterator {
+    PythonResourceIterator::from_paths(root_path, paths, suffixes)
+}
+
+/// Entries of a `.pth` file.
+#[derive(Debug, Default, PartialEq)]
+pub struct PthFileEntries {
+    /// Existing paths the file adds to `sys.path`.
+    pub paths: Vec<PathBuf>,
+
+    /// Lines executed when the `site` module processes the file.
+    pub executable_lines: Vec<String>,
+}
+
+/// Read the entries of a `.pth` file, as the `site` module does.
+///
+/// Lines starting with `import` are executed. Other lines are paths, relative
+/// to the directory holding the file, which are added to `sys.path` if they
+/// exist. Comments and blank lines are ignored.
+pub fn read_pth_file(path: &Path) -> Result<PthFileEntries> {
+    let data = fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut entries = PthFileEntries::default();
+
+    for line in String::from_utf8_lossy(&data).lines() {
+        if line.starts_with('#') {
+            continue;
+        } else if line.starts_with("import ") || line.starts_with("import\t") {
+            entries.executable_lines.push(line.to_string());
+            continue;
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+
+        let entry_path = dir.join(line);
+        if entry_path.exists() && !entries.paths.contains(&entry_path) {
+            entries.paths.push(entry_path);
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Find the names of top-level packages in a directory.
+///
+/// A directory is a package if it holds Python modules, directly or in a
+/// sub-directory. It doesn't need an `__init__.py` file, so namespace packages
+/// are found. Modules at the root of the directory, such as `setup.py`, aren't
+/// packages and are ignored.
+pub fn find_top_level_packages(root_path: &Path, suffixes: &PythonModuleSuffixes) -> Vec<String> {
+    let mut packages = BTreeSet::new();
+
+    for resource in find_python_resources(root_path, suffixes) {
+        let (stem, full_name) = match &resource {
+            PythonFileResource::Source {
+                stem, full_name, ..
+            } => (stem, full_name),
+            PythonFileResource::ExtensionModule {
+                stem, full_name, ..
+            } => (stem, full_name),
+            _ => continue,
+        };
+
+        if !stem.is_empty() && !full_name.contains('.') {
+            continue;
+        }
+
+        let package = full_name.split('.').next().unwrap();
+
+        if is_valid_module_name(package) {
+            packages.insert(package.to_string());
+        }
+    }
+
+    packages.into_iter().collect()
+}
+
 pub fn find_python_modules(
     root_path: &Path,
     suffixes: &PythonModuleSuffixes,
@@ -872,4 +1051,112 @@ mod tests {
 
         assert_eq!(resources[0], PythonFileResource::PthFile { path: pth_path });
     }
+
+    #[test]
+    fn test_read_pth_file() -> Result<()> {
+        let td = tempdir::TempDir::new("pyoxidizer-test")?;
+        let tp = td.path();
+
+        create_dir_all(tp.join("src"))?;
+
+        let pth_path = tp.join("site-packages").join("foo.pth");
+        create_dir_all(pth_path.parent().unwrap())?;
+        write(
+            &pth_path,
+            format!(
+                "# comment\n\n../src\n{}\nmissing\nimport sys; sys.foo = 1\n../src  \n",
+                tp.join("src").display()
+            ),
+        )?;
+
+        let entries = read_pth_file(&pth_path)?;
+        assert_eq!(
+            entries.paths,
+            vec![tp.join("site-packages").join("../src"), tp.join("src")]
+        );
+        assert_eq!(entries.executable_lines, vec!["import sys; sys.foo = 1"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_namespace_package() -> Result<()> {
+        let td = tempdir::TempDir::new("pyoxidizer-test")?;
+        let tp = td.path();
+
+        let widgets_path = tp.join("acme").join("widgets");
+        let assets_path = tp.join("acme").join("assets");
+
+        create_dir_all(&widgets_path)?;
+        create_dir_all(&assets_path)?;
+        create_dir_all(tp.join("docs"))?;
+        create_dir_all(tp.join("my-scripts"))?;
+
+        write(widgets_path.join("__init__.py"), "")?;
+        write(assets_path.join("logo.png"), "")?;
+        write(tp.join("docs").join("index.rst"), "")?;
+        write(tp.join("my-scripts").join("run.py"), "")?;
+        write(tp.join("setup.py"), "")?;
+
+        let suffixes = PythonModuleSuffixes {
+            source: vec![],
+            bytecode: vec![],
+            debug_bytecode: vec![],
+            optimized_bytecode: vec![],
+            extension: vec![],
+        };
+
+        let resources = PythonResourceIterator::new(tp, &suffixes).collect_vec();
+        assert!(
+            resources.contains(&PythonFileResource::Resource(FileBasedResource {
+                package: "acme".to_string(),
+                stem: "assets/logo.png".to_string(),
+                full_name: "acme.assets.logo.png".to_string(),
+                path: assets_path.join("logo.png"),
+            }))
+        );
+
+        assert_eq!(find_top_level_packages(tp, &suffixes), vec!["acme"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_walk_tree_files_parallel() -> Result<()> {
+        let td = tempdir::TempDir::new("pyoxidizer-test")?;
+        let tp = td.path();
+
+        for dir in &["b/d", "b/c", "a", "e"] {
+            create_dir_all(tp.join(dir))?;
+        }
+        for file in &["z.py", "b/d/x.py", "b/c/y.py", "b/a.txt", "a/__init__.py"] {
+            write(tp.join(file), "")?;
+        }
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(tp.join("b"), tp.join("e").join("link"))?;
+
+        let serial = walk_tree_files(tp)
+            .map(|entry| entry.path().to_path_buf())
+            .collect_vec();
+        let parallel = walk_tree_files_parallel(tp);
+
+        assert_eq!(parallel, serial);
+        assert_eq!(
+            parallel,
+            vec![
+                tp.join("a/__init__.py"),
+                tp.join("b/a.txt"),
+                tp.join("b/c/y.py"),
+                tp.join("b/d/x.py"),
+                tp.join("z.py"),
+            ]
+        );
+        assert_eq!(
+            walk_tree_files_parallel(&tp.join("z.py")),
+            vec![tp.join("z.py")]
+        );
+
+        Ok(())
+    }
 }
@@ -15,6 +15,7 @@ use std::fs;
 use std::fs::create_dir_all;
 use std::path::{Path, PathBuf};
 
+use super::binary::WindowsCrtLinkage;
 use super::bytecode::{BytecodeCompiler, CompileMode};
 use super::embedded_resource::EmbeddedPythonResources;
 use super::resource::{BytecodeOptimizationLevel, ExtensionModuleData};
@@ -160,10 +161,13 @@ pub fn link_libpython(
     host: &str,
     target: &str,
     opt_level: &str,
+    windows_crt_linkage: WindowsCrtLinkage,
 ) -> Result<LibpythonInfo> {
+    let _phase = crate::timing::phase("link libpython");
+
     let mut cargo_metadata: Vec<String> = Vec::new();
 
-    let temp_dir = tempdir::TempDir::new("libpython")?;
+    let temp_dir = crate::scratch::scratch_dir("libpython")?;
     let temp_dir_path = temp_dir.path();
 
     let extension_modules = &resources.extension_modules;
@@ -215,6 +219,7 @@ pub fn link_libpython(
         .define("NDEBUG", None)
         .define("Py_BUILD_CORE", None)
         .flag("-std=c99")
+        .static_crt(windows_crt_linkage == WindowsCrtLinkage::Static)
         .cargo_metadata(false)
         .compile("pyembeddedconfig");
 
@@ -233,6 +238,7 @@ pub fn link_libpython(
     build.host(host);
     build.target(target);
     build.opt_level_str(opt_level);
+    build.static_crt(windows_crt_linkage == WindowsCrtLinkage::Static);
     // We handle this ourselves.
     build.cargo_metadata(false);
 
@@ -298,9 +304,10 @@ pub fn link_libpython(
 
         info!(
             logger,
-            "adding {} object files for {} extension module: {:#?}",
+            "adding {} object files for {} extension module (variant {}): {:#?}",
             em.object_paths.len(),
             name,
+            em.variant,
             em.object_paths
         );
         for path in &em.object_paths {
@@ -355,12 +362,13 @@ pub fn link_libpython(
         // TODO do something with library_dirs.
     }
 
-    // Windows requires dynamic linking against msvcrt. Ensure that happens.
+    // Windows requires explicit linking against the CRT matching the requested
+    // linkage. Ensure that happens.
     // TODO this workaround feels like a bug in the Python distribution not
     // advertising a dependency on the CRT linkage type. Consider adding this
     // to the distribution metadata.
     if windows {
-        needed_system_libraries.insert("msvcrt");
+        needed_system_libraries.insert(windows_crt_linkage.system_library());
     }
 
     let mut extra_library_paths = BTreeSet::new();
@@ -0,0 +1,370 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Reading pinned packages from lockfiles of Python project management tools.
+
+Poetry (`poetry.lock`) and Pipenv (`Pipfile.lock`) record the exact version
+and content hashes of every package of a project. This module converts these
+lockfiles into pip requirements so the locked packages can be installed
+without maintaining a separate requirements file.
+*/
+
+use {
+    anyhow::{anyhow, Context, Result},
+    std::path::Path,
+};
+
+/// A package pinned by a lockfile.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LockedPackage {
+    /// Name of the package.
+    pub name: String,
+
+    /// Exact version of the package.
+    pub version: String,
+
+    /// Content hashes of the package's distributions, e.g. `sha256:<hex>`.
+    pub hashes: Vec<String>,
+
+    /// PEP 508 environment markers restricting where the package is installed.
+    pub markers: Option<String>,
+}
+
+impl LockedPackage {
+    /// Render a pip requirements file line installing this package.
+    pub fn requirement(&self) -> String {
+        let mut line = format!("{}=={}", self.name, self.version);
+
+        if let Some(markers) = &self.markers {
+            line.push_str(&format!(" ; {}", markers));
+        }
+
+        for hash in &self.hashes {
+            line.push_str(&format!(" --hash={}", hash));
+        }
+
+        line
+    }
+}
+
+/// Render a pip requirements file installing locked packages.
+///
+/// Every package must have hashes, as pip requires hashes for all
+/// requirements once any requirement has them.
+pub fn render_requirements(packages: &[LockedPackage]) -> Result<String> {
+    let mut lines = Vec::new();
+
+    for package in packages {
+        if package.hashes.is_empty() {
+            return Err(anyhow!(
+                "locked package {} {} has no hashes",
+                package.name,
+                package.version
+            ));
+        }
+
+        lines.push(package.requirement());
+    }
+
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Obtain a string value from a TOML table.
+fn toml_str<'a>(table: &'a toml::value::Table, key: &str) -> Option<&'a str> {
+    table.get(key).and_then(|v| v.as_str())
+}
+
+/// Obtain hashes from a poetry `files` array.
+fn poetry_file_hashes(files: &toml::Value) -> Vec<String> {
+    files
+        .as_array()
+        .map(|files| {
+            files
+                .iter()
+                .filter_map(|f| f.get("hash").and_then(|h| h.as_str()))
+                .map(|h| h.to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parse the content of a `poetry.lock` file.
+///
+/// Packages in the `dev` category are only returned if `include_dev` is set.
+/// Packages installed from version control, directories, or URLs are not
+/// supported, as they can't be pinned by hash.
+pub fn parse_poetry_lock(data: &str, include_dev: bool) -> Result<Vec<LockedPackage>> {
+    let doc: toml::Value = data.parse().context("parsing poetry.lock")?;
+
+    // Older lockfiles record hashes in a table keyed by package name.
+    let metadata_files = doc
+        .get("metadata")
+        .and_then(|m| m.get("files"))
+        .and_then(|f| f.as_table());
+
+    let packages = match doc.get("package") {
+        Some(packages) => packages
+            .as_array()
+            .ok_or_else(|| anyhow!("package must be an array of tables"))?
+            .as_slice(),
+        None => &[][..],
+    };
+
+    let mut res = Vec::new();
+
+    for package in packages {
+        let table = package
+            .as_table()
+            .ok_or_else(|| anyhow!("package must be a table"))?;
+
+        let name = toml_str(table, "name").ok_or_else(|| anyhow!("package has no name"))?;
+        let version =
+            toml_str(table, "version").ok_or_else(|| anyhow!("package {} has no version", name))?;
+
+        if toml_str(table, "category") == Some("dev") && !include_dev {
+            continue;
+        }
+
+        if let Some(source_type) = table
+            .get("source")
+            .and_then(|s| s.get("type"))
+            .and_then(|t| t.as_str())
+        {
+            // Packages from alternate indexes are still pinned by hash.
+            if source_type != "legacy" {
+                return Err(anyhow!(
+                    "package {} is installed from a {} source, which is not supported",
+                    name,
+                    source_type
+                ));
+            }
+        }
+
+        let hashes = if let Some(files) = table.get("files") {
+            poetry_file_hashes(files)
+        } else if let Some(files) = metadata_files.and_then(|m| m.get(name)) {
+            poetry_file_hashes(files)
+        } else {
+            Vec::new()
+        };
+
+        res.push(LockedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            hashes,
+            markers: None,
+        });
+    }
+
+    Ok(res)
+}
+
+/// Parse the content of a `Pipfile.lock` file.
+///
+/// Packages in the `develop` section are only returned if `include_dev` is
+/// set. Packages installed from version control or paths are not supported,
+/// as they can't be pinned by hash.
+pub fn parse_pipfile_lock(data: &str, include_dev: bool) -> Result<Vec<LockedPackage>> {
+    let doc: serde_json::Value = serde_json::from_str(data).context("parsing Pipfile.lock")?;
+
+    let mut sections = vec!["default"];
+    if include_dev {
+        sections.push("develop");
+    }
+
+    let mut res = Vec::new();
+
+    for section in sections {
+        let packages = match doc.get(section).and_then(|s| s.as_object()) {
+            Some(packages) => packages,
+            None => continue,
+        };
+
+        for (name, entry) in packages {
+            let version = entry
+                .get("version")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "package {} is not installed from a package index, which is not supported",
+                        name
+                    )
+                })?;
+
+            let version = version.trim_start_matches("==");
+
+            let hashes = entry
+                .get("hashes")
+                .and_then(|h| h.as_array())
+                .map(|hashes| {
+                    hashes
+                        .iter()
+                        .filter_map(|h| h.as_str())
+                        .map(|h| h.to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let markers = entry
+                .get("markers")
+                .and_then(|m| m.as_str())
+                .map(|m| m.to_string());
+
+            // A package in both sections only needs to be installed once.
+            if res.iter().any(|p: &LockedPackage| &p.name == name) {
+                continue;
+            }
+
+            res.push(LockedPackage {
+                name: name.clone(),
+                version: version.to_string(),
+                hashes,
+                markers,
+            });
+        }
+    }
+
+    Ok(res)
+}
+
+/// Read locked packages from a `poetry.lock` file.
+pub fn read_poetry_lock(path: &Path, include_dev: bool) -> Result<Vec<LockedPackage>> {
+    let data = std::fs::read_to_string(path).context(format!("reading {}", path.display()))?;
+
+    parse_poetry_lock(&data, include_dev)
+}
+
+/// Read locked packages from a `Pipfile.lock` file.
+pub fn read_pipfile_lock(path: &Path, include_dev: bool) -> Result<Vec<LockedPackage>> {
+    let data = std::fs::read_to_string(path).context(format!("reading {}", path.display()))?;
+
+    parse_pipfile_lock(&data, include_dev)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POETRY_LOCK: &str = indoc::indoc!(
+        r#"
+        [[package]]
+        category = "main"
+        name = "six"
+        optional = false
+        python-versions = ">=2.7"
+        version = "1.14.0"
+
+        [[package]]
+        category = "dev"
+        name = "pytest"
+        optional = false
+        python-versions = ">=3.5"
+        version = "5.4.1"
+
+        [metadata]
+        content-hash = "abcd"
+
+        [metadata.files]
+        six = [
+            {file = "six-1.14.0-py2.py3-none-any.whl", hash = "sha256:aaaa"},
+            {file = "six-1.14.0.tar.gz", hash = "sha256:bbbb"},
+        ]
+        pytest = [
+            {file = "pytest-5.4.1-py3-none-any.whl", hash = "sha256:cccc"},
+        ]
+        "#
+    );
+
+    const PIPFILE_LOCK: &str = indoc::indoc!(
+        r#"
+        {
+            "_meta": {"hash": {"sha256": "abcd"}},
+            "default": {
+                "six": {
+                    "hashes": ["sha256:aaaa", "sha256:bbbb"],
+                    "version": "==1.14.0"
+                },
+                "colorama": {
+                    "hashes": ["sha256:dddd"],
+                    "markers": "sys_platform == 'win32'",
+                    "version": "==0.4.3"
+                }
+            },
+            "develop": {
+                "pytest": {
+                    "hashes": ["sha256:cccc"],
+                    "version": "==5.4.1"
+                }
+            }
+        }
+        "#
+    );
+
+    #[test]
+    fn test_parse_poetry_lock() -> Result<()> {
+        let packages = parse_poetry_lock(POETRY_LOCK, false)?;
+        assert_eq!(
+            packages,
+            vec![LockedPackage {
+                name: "six".to_string(),
+                version: "1.14.0".to_string(),
+                hashes: vec!["sha256:aaaa".to_string(), "sha256:bbbb".to_string()],
+                markers: None,
+            }]
+        );
+
+        let packages = parse_poetry_lock(POETRY_LOCK, true)?;
+        assert_eq!(packages.len(), 2);
+        assert_eq!(packages[1].hashes, vec!["sha256:cccc".to_string()]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_poetry_lock_unsupported_source() {
+        let data = indoc::indoc!(
+            r#"
+            [[package]]
+            name = "foo"
+            version = "0.1.0"
+
+            [package.source]
+            type = "git"
+            url = "https://example.com/foo.git"
+            "#
+        );
+
+        assert!(parse_poetry_lock(data, false).is_err());
+    }
+
+    #[test]
+    fn test_parse_pipfile_lock() -> Result<()> {
+        let packages = parse_pipfile_lock(PIPFILE_LOCK, false)?;
+        assert_eq!(packages.len(), 2);
+
+        let requirements = render_requirements(&packages)?;
+        assert!(requirements.contains("six==1.14.0 --hash=sha256:aaaa --hash=sha256:bbbb\n"));
+        assert!(
+            requirements.contains("colorama==0.4.3 ; sys_platform == 'win32' --hash=sha256:dddd\n")
+        );
+
+        let packages = parse_pipfile_lock(PIPFILE_LOCK, true)?;
+        assert_eq!(packages.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_requirements_no_hashes() {
+        let packages = vec![LockedPackage {
+            name: "six".to_string(),
+            version: "1.14.0".to_string(),
+            hashes: Vec::new(),
+            markers: None,
+        }];
+
+        assert!(render_requirements(&packages).is_err());
+    }
+}
@@ -12,13 +12,24 @@ pub mod binary;
 pub mod bytecode;
 pub mod config;
 pub mod distribution;
+pub mod distribution_mirror;
+pub mod distribution_signature;
 pub mod distutils;
+pub mod dry_run_distribution;
+pub mod download;
+pub mod editable;
 pub mod embedded_resource;
 pub mod filtering;
 pub mod fsscan;
 pub mod libpython;
+pub mod lockfile;
+pub mod package_data;
 pub mod packaging_tool;
 pub mod pyembed;
+pub mod pypy_distribution;
 pub mod resource;
+pub mod sdist;
 pub mod standalone_distribution;
+pub mod wheel;
 pub mod windows_embeddable_distribution;
+pub mod zipapp;
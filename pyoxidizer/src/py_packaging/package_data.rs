@@ -0,0 +1,192 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Selection of package data collected from wheels and source trees.
+
+Non-module files of packages are collected as resources. Glob patterns can
+select which of them are kept, per package. Patterns are matched against the
+path of a file relative to the package directory, e.g. `templates/index.html`.
+Patterns starting with `!` exclude files. When several patterns match a file,
+the last one wins.
+*/
+
+use {
+    super::resource::PythonResource,
+    anyhow::{anyhow, Result},
+    slog::warn,
+    std::collections::BTreeMap,
+};
+
+const MATCH_OPTIONS: glob::MatchOptions = glob::MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// A glob pattern selecting package data files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackageDataPattern {
+    /// Whether matching files are included or excluded.
+    pub include: bool,
+
+    /// Pattern matching paths relative to the package directory.
+    pub pattern: glob::Pattern,
+}
+
+impl PackageDataPattern {
+    /// Parse a pattern, which excludes files if it starts with `!`.
+    pub fn parse(value: &str) -> Result<Self> {
+        let (include, pattern) = if value.starts_with('!') {
+            (false, &value[1..])
+        } else {
+            (true, value)
+        };
+
+        if pattern.is_empty() {
+            return Err(anyhow!("package data pattern {} is empty", value));
+        }
+
+        Ok(Self {
+            include,
+            pattern: glob::Pattern::new(pattern)
+                .map_err(|e| anyhow!("invalid package data pattern {}: {}", value, e))?,
+        })
+    }
+}
+
+/// Selects the package data files to keep, per package.
+///
+/// Files of packages without patterns, and of their sub-packages, are kept.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PackageDataFilter {
+    patterns: BTreeMap<String, Vec<PackageDataPattern>>,
+}
+
+impl PackageDataFilter {
+    /// Add patterns selecting data files of a package.
+    pub fn add_patterns(&mut self, package: &str, patterns: &[String]) -> Result<()> {
+        let entry = self
+            .patterns
+            .entry(package.to_string())
+            .or_insert_with(Vec::new);
+
+        for pattern in patterns {
+            entry.push(PackageDataPattern::parse(pattern)?);
+        }
+
+        Ok(())
+    }
+
+    /// Whether no patterns are defined.
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Whether a data file of a package is kept.
+    ///
+    /// Patterns of the closest package having some apply. Files of
+    /// sub-packages are matched with the path relative to that package.
+    pub fn is_included(&self, package: &str, name: &str) -> bool {
+        let mut package = package;
+        let mut path = name.to_string();
+
+        loop {
+            if let Some(patterns) = self.patterns.get(package) {
+                let mut included = !patterns.iter().any(|p| p.include);
+
+                for p in patterns {
+                    if p.pattern.matches_with(&path, MATCH_OPTIONS) {
+                        included = p.include;
+                    }
+                }
+
+                return included;
+            }
+
+            match package.rfind('.') {
+                Some(pos) => {
+                    path = format!("{}/{}", &package[pos + 1..], path);
+                    package = &package[0..pos];
+                }
+                None => return true,
+            }
+        }
+    }
+
+    /// Remove package data not selected by patterns from resources.
+    ///
+    /// Resources that aren't package data are kept.
+    pub fn filter_resources(
+        &self,
+        logger: &slog::Logger,
+        resources: Vec<PythonResource>,
+    ) -> Vec<PythonResource> {
+        if self.is_empty() {
+            return resources;
+        }
+
+        resources
+            .into_iter()
+            .filter(|resource| match resource {
+                PythonResource::Resource { package, name, .. } => {
+                    if self.is_included(package, name) {
+                        true
+                    } else {
+                        warn!(logger, "excluding package data {}:{}", package, name);
+                        false
+                    }
+                }
+                _ => true,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pattern() -> Result<()> {
+        let pattern = PackageDataPattern::parse("templates/**")?;
+        assert!(pattern.include);
+        assert_eq!(pattern.pattern.as_str(), "templates/**");
+
+        let pattern = PackageDataPattern::parse("!**/*.map")?;
+        assert!(!pattern.include);
+        assert_eq!(pattern.pattern.as_str(), "**/*.map");
+
+        assert!(PackageDataPattern::parse("!").is_err());
+        assert!(PackageDataPattern::parse("a/***").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_included() -> Result<()> {
+        let mut filter = PackageDataFilter::default();
+        filter.add_patterns(
+            "myapp",
+            &["templates/**".to_string(), "!**/*.map".to_string()],
+        )?;
+        filter.add_patterns("other", &["!*.pdf".to_string()])?;
+
+        assert!(filter.is_included("myapp", "templates/index.html"));
+        assert!(filter.is_included("myapp", "templates/js/app.js"));
+        assert!(!filter.is_included("myapp", "templates/js/app.js.map"));
+        assert!(!filter.is_included("myapp", "README.txt"));
+        assert!(!filter.is_included("myapp.sub", "data.txt"));
+        assert!(filter.is_included("myapp.templates", "base.html"));
+        assert!(!filter.is_included("myapp.templates", "app.js.map"));
+
+        assert!(filter.is_included("other", "README.txt"));
+        assert!(!filter.is_included("other", "manual.pdf"));
+        assert!(filter.is_included("other", "docs/manual.pdf"));
+
+        assert!(filter.is_included("unrelated", "manual.pdf"));
+
+        Ok(())
+    }
+}
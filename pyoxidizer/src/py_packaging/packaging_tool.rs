@@ -7,13 +7,26 @@ Interaction with Python packaging tools (pip, setuptools, etc).
 */
 
 use {
-    super::distribution::{download_distribution, PythonDistribution},
+    super::distribution::{download_distribution, PythonDistribution, PythonModuleSuffixes},
     super::distutils::read_built_extensions,
-    super::fsscan::{find_python_resources, PythonFileResource},
-    super::resource::PythonResource,
+    super::download::DownloadOptions,
+    super::editable::{read_editable_finder, EditableMapping},
+    super::fsscan::{
+        find_python_resources, find_python_resources_in, is_package_from_path, read_pth_file,
+        PythonFileResource,
+    },
+    super::lockfile::{render_requirements, LockedPackage},
+    super::resource::{DataLocation, DistributionResource, PythonResource, SourceModule},
+    super::sdist::{is_sdist, SdistBuilder},
     super::standalone_distribution::resolve_python_paths,
+    super::wheel::{verify_installed_wheels, CompatibleTags},
+    crate::app_packaging::resource::{FileContent, FileManifest},
+    crate::licensing::find_package_licenses,
+    crate::progress::Progress,
     crate::python_distributions::GET_PIP_PY_19,
+    crate::timing,
     anyhow::{anyhow, Context, Result},
+    rayon::prelude::*,
     slog::warn,
     std::collections::HashMap,
     std::convert::TryFrom,
@@ -73,9 +86,9 @@ pub fn bootstrap_packaging_tools(
     lib_dir: &Path,
 ) -> Result<()> {
     let get_pip_py_path =
-        download_distribution(&GET_PIP_PY_19.url, &GET_PIP_PY_19.sha256, cache_dir)?;
+        download_distribution(logger, &GET_PIP_PY_19.url, &GET_PIP_PY_19.sha256, cache_dir)?;
 
-    let temp_dir = tempdir::TempDir::new("pyoxidizer-bootstrap-packaging")?;
+    let temp_dir = crate::scratch::scratch_dir("pyoxidizer-bootstrap-packaging")?;
 
     // We need to hack `get-pip.py`'s source code to allow exclusive use of a
     // requirements file for installing `pip`. The `implicit_*` variables control
@@ -168,29 +181,265 @@ pub fn bootstrap_packaging_tools(
     return Ok(());
 }
 
-/// Find resources installed as part of a packaging operation.
-pub fn find_resources(
-    logger: &slog::Logger,
-    dist: &dyn PythonDistribution,
+/// Resolve the distribution name and version from a metadata directory name.
+///
+/// e.g. `foo-1.0.dist-info` or `foo-1.0-py3.7.egg-info`.
+fn parse_distribution_dir_name(dir_name: &str) -> Option<(String, String)> {
+    let stem = if dir_name.ends_with(".dist-info") {
+        &dir_name[0..dir_name.len() - ".dist-info".len()]
+    } else if dir_name.ends_with(".egg-info") {
+        &dir_name[0..dir_name.len() - ".egg-info".len()]
+    } else {
+        return None;
+    };
+
+    let mut parts = stem.split('-');
+
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(version)) if !name.is_empty() && !version.is_empty() => {
+            Some((name.to_string(), version.to_string()))
+        }
+        _ => None,
+    }
+}
+
+/// Find files in the distribution metadata of Python packages in a directory.
+///
+/// `path` is a directory like `site-packages` holding `.dist-info` or
+/// `.egg-info` directories.
+pub fn find_distribution_resources(path: &Path) -> Result<Vec<DistributionResource>> {
+    let mut res = Vec::new();
+
+    if !path.is_dir() {
+        return Ok(res);
+    }
+
+    let mut entries = std::fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let dir_path = entry.path();
+
+        if !dir_path.is_dir() {
+            continue;
+        }
+
+        let (package, version) =
+            match parse_distribution_dir_name(&entry.file_name().to_string_lossy()) {
+                Some(v) => v,
+                None => continue,
+            };
+
+        for f in walkdir::WalkDir::new(&dir_path).sort_by(|a, b| a.file_name().cmp(b.file_name())) {
+            let f = f?;
+
+            if !f.file_type().is_file() {
+                continue;
+            }
+
+            let rel_path = f
+                .path()
+                .strip_prefix(&dir_path)
+                .expect("unable to strip path prefix");
+            let name = rel_path
+                .iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            res.push(DistributionResource {
+                package: package.clone(),
+                version: version.clone(),
+                name,
+                data: DataLocation::Path(f.path().to_path_buf()),
+            });
+        }
+    }
+
+    Ok(res)
+}
+
+/// Find Python modules and resources in a directory.
+///
+/// `.pth` files at the root of the directory are returned, as they
+/// may add directories to `sys.path`. Files are read in parallel.
+fn find_module_resources(
     path: &Path,
-    state_dir: Option<PathBuf>,
+    suffixes: &PythonModuleSuffixes,
+) -> Result<(Vec<PythonResource>, Vec<PathBuf>)> {
+    let mut files = Vec::new();
+    let mut pth_files = Vec::new();
+
+    for r in find_python_resources(&path, suffixes) {
+        match r {
+            PythonFileResource::Source { .. }
+            | PythonFileResource::Resource(..)
+            | PythonFileResource::ExtensionModule { .. } => files.push(r),
+
+            // Python only processes .pth files in site directories.
+            PythonFileResource::PthFile { path: pth_path } => {
+                if pth_path.parent() == Some(path) {
+                    pth_files.push(pth_path);
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    let res = files
+        .par_iter()
+        .map(|r| {
+            PythonResource::try_from(r).with_context(|| match r {
+                PythonFileResource::Source { .. } => "converting source module to PythonResource",
+                PythonFileResource::Resource(..) => "converting resource file to PythonResource",
+                _ => "converting extension module file to PythonResource",
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok((res, pth_files))
+}
+
+/// What `.pth` files of a directory add to the import path.
+#[derive(Debug, Default)]
+struct PthResolution {
+    /// Directories added to `sys.path`.
+    paths: Vec<PathBuf>,
+
+    /// Modules and packages of editable installs using an import hook.
+    editable: Vec<EditableMapping>,
+
+    /// Names of the finder modules of editable installs.
+    finder_modules: Vec<String>,
+}
+
+/// Resolve what `.pth` files add to the import path.
+///
+/// Lines of `.pth` files executing code can't be replicated and are reported,
+/// unless they install the finder of an editable install, whose mappings are
+/// resolved. Paths inside `root` are ignored, as its scan already found their
+/// content.
+fn resolve_pth_files(
+    logger: &slog::Logger,
+    root: &Path,
+    pth_files: &[PathBuf],
+) -> Result<PthResolution> {
+    let mut res = PthResolution::default();
+
+    if pth_files.is_empty() {
+        return Ok(res);
+    }
+
+    let canonical_root = root.canonicalize()?;
+
+    for pth_file in pth_files {
+        let entries = read_pth_file(pth_file)?;
+
+        for line in &entries.executable_lines {
+            match read_editable_finder(root, line)? {
+                Some((module, mappings)) => {
+                    warn!(
+                        logger,
+                        "{} is an editable install of {}; reading it from the working tree",
+                        pth_file.display(),
+                        mappings
+                            .iter()
+                            .map(|m| m.name.clone())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    );
+                    res.finder_modules.push(module);
+                    res.editable.extend(mappings);
+                }
+                None => {
+                    warn!(
+                        logger,
+                        "ignoring line of {} executing code, which can't be replicated: {}",
+                        pth_file.display(),
+                        line
+                    );
+                }
+            }
+        }
+
+        for path in entries.paths {
+            let path = path.canonicalize()?;
+
+            if path.starts_with(&canonical_root) || res.paths.contains(&path) {
+                continue;
+            }
+
+            if path.is_dir() {
+                warn!(
+                    logger,
+                    "{} adds {} to sys.path; searching it for resources",
+                    pth_file.display(),
+                    path.display()
+                );
+                res.paths.push(path);
+            } else {
+                warn!(
+                    logger,
+                    "ignoring {} added to sys.path by {}; only directories are supported",
+                    path.display(),
+                    pth_file.display()
+                );
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+/// Find the resources of a module or package of an editable install.
+///
+/// Resources reference files in the working tree, so they are read when an
+/// executable is built.
+fn find_editable_resources(
+    logger: &slog::Logger,
+    mapping: &EditableMapping,
+    suffixes: &PythonModuleSuffixes,
 ) -> Result<Vec<PythonResource>> {
+    let stem = if mapping.path.is_dir() {
+        mapping.path.file_name()
+    } else {
+        mapping.path.file_stem()
+    };
+
+    let root = match (mapping.path.parent(), stem) {
+        (Some(root), Some(stem)) if stem.to_string_lossy() == mapping.name => root,
+        _ => {
+            warn!(
+                logger,
+                "ignoring editable install of {} from {}; its name doesn't match its path",
+                mapping.name,
+                mapping.path.display()
+            );
+            return Ok(Vec::new());
+        }
+    };
+
     let mut res = Vec::new();
 
-    for r in find_python_resources(&path, &dist.python_module_suffixes()?) {
+    for r in find_python_resources_in(root, &mapping.path, suffixes) {
         match r {
-            PythonFileResource::Source { .. } => {
-                res.push(
-                    PythonResource::try_from(&r)
-                        .context("converting source module to PythonResource")?,
-                );
+            PythonFileResource::Source {
+                full_name, path, ..
+            } => {
+                res.push(PythonResource::ModuleSource {
+                    name: full_name,
+                    is_package: is_package_from_path(&path),
+                    source: DataLocation::Path(path),
+                });
             }
 
-            PythonFileResource::Resource(..) => {
-                res.push(
-                    PythonResource::try_from(&r)
-                        .context("converting resource file to PythonResource")?,
-                );
+            PythonFileResource::Resource(resource) => {
+                res.push(PythonResource::Resource {
+                    package: resource.package,
+                    name: resource.stem,
+                    data: DataLocation::Path(resource.path),
+                });
             }
 
             PythonFileResource::ExtensionModule { .. } => {
@@ -204,6 +453,47 @@ pub fn find_resources(
         }
     }
 
+    Ok(res)
+}
+
+/// Find resources installed as part of a packaging operation.
+///
+/// Directories added to `sys.path` by `.pth` files in `path` are searched
+/// too, as are the working trees of editable installs. Wheels installed in
+/// `path` must be compatible with the distribution.
+pub fn find_resources(
+    logger: &slog::Logger,
+    dist: &dyn PythonDistribution,
+    path: &Path,
+    state_dir: Option<PathBuf>,
+) -> Result<Vec<PythonResource>> {
+    let suffixes = dist.python_module_suffixes()?;
+
+    if let Some(tags) = CompatibleTags::from_distribution(dist)? {
+        verify_installed_wheels(path, &tags)?;
+    }
+
+    let (mut res, pth_files) = find_module_resources(path, &suffixes)?;
+    let pth = resolve_pth_files(logger, path, &pth_files)?;
+
+    res.retain(|r| !r.is_in_packages(&pth.finder_modules));
+
+    for pth_path in &pth.paths {
+        res.extend(find_module_resources(pth_path, &suffixes)?.0);
+    }
+
+    for mapping in &pth.editable {
+        res.extend(find_editable_resources(logger, mapping, &suffixes)?);
+    }
+
+    for license in find_package_licenses(&path)? {
+        res.push(PythonResource::PackageLicense(license));
+    }
+
+    for resource in find_distribution_resources(&path)? {
+        res.push(PythonResource::DistributionResource(resource));
+    }
+
     if let Some(p) = state_dir {
         for ext in read_built_extensions(&p)? {
             res.push(PythonResource::ExtensionModuleStaticallyLinked(ext));
@@ -213,24 +503,300 @@ pub fn find_resources(
     dist.filter_compatible_python_resources(logger, &res)
 }
 
+/// Options controlling where pip obtains packages from.
+#[derive(Clone, Debug, Default)]
+pub struct PipIndexOptions {
+    /// Base URL of the package index to use instead of PyPI.
+    pub index_url: Option<String>,
+
+    /// URLs of package indexes to use in addition to the main index.
+    pub extra_index_urls: Vec<String>,
+
+    /// Hosts to trust even if they don't serve valid HTTPS.
+    pub trusted_hosts: Vec<String>,
+
+    /// Directories holding wheels and source archives to find packages in.
+    pub find_links: Vec<PathBuf>,
+
+    /// Whether to only find packages in `find_links`, without network access.
+    pub offline: bool,
+
+    /// URL of a proxy server to access package indexes through.
+    pub proxy: Option<String>,
+
+    /// Path to a netrc file holding credentials of package indexes.
+    pub netrc: Option<PathBuf>,
+
+    /// Whether to obtain credentials of package indexes from keyring.
+    pub keyring: bool,
+
+    /// Directory to download packages into before installing them.
+    ///
+    /// Used to vendor packages for later installation in offline mode.
+    pub download_dir: Option<PathBuf>,
+
+    /// Whether to build source distributions into wheels before installing them.
+    ///
+    /// Source distributions are built in isolated build environments with
+    /// the distribution's interpreter and the built wheels are verified
+    /// against the distribution's tags.
+    pub build_sdists: bool,
+
+    /// Directory caching wheels built from source distributions.
+    pub sdist_wheel_cache: Option<PathBuf>,
+
+    /// How pip retries downloads and how long it waits for servers.
+    pub download: DownloadOptions,
+}
+
+impl PipIndexOptions {
+    /// Arguments to pass to pip commands finding packages.
+    pub fn pip_args(&self) -> Result<Vec<String>> {
+        let mut args = Vec::new();
+
+        if self.offline {
+            if self.find_links.is_empty() {
+                return Err(anyhow!(
+                    "offline mode requires directories to find packages in"
+                ));
+            }
+
+            args.push("--no-index".to_string());
+        } else {
+            if let Some(url) = &self.index_url {
+                args.push("--index-url".to_string());
+                args.push(url.clone());
+            }
+
+            for url in &self.extra_index_urls {
+                args.push("--extra-index-url".to_string());
+                args.push(url.clone());
+            }
+
+            for host in &self.trusted_hosts {
+                args.push("--trusted-host".to_string());
+                args.push(host.clone());
+            }
+
+            if let Some(proxy) = &self.proxy {
+                args.push("--proxy".to_string());
+                args.push(proxy.clone());
+            }
+        }
+
+        for path in &self.find_links {
+            args.push("--find-links".to_string());
+            args.push(path.display().to_string());
+        }
+
+        Ok(args)
+    }
+
+    /// Environment variables to set for pip commands finding packages.
+    pub fn pip_envs(&self) -> HashMap<String, String> {
+        let mut envs = self.download.pip_envs();
+
+        if let Some(path) = &self.netrc {
+            envs.insert("NETRC".to_string(), path.display().to_string());
+        }
+
+        // Fail rather than hang when credentials are missing.
+        if self.keyring || self.netrc.is_some() {
+            envs.insert("PIP_NO_INPUT".to_string(), "1".to_string());
+        }
+
+        envs
+    }
+}
+
+/// Build the source distributions downloaded to a directory into wheels.
+///
+/// Wheels are built into `wheel_dir`. Returns the arguments to pass to
+/// `pip install` so the built wheels are installed instead of the sdists.
+#[allow(clippy::too_many_arguments)]
+fn build_downloaded_sdists<S: BuildHasher>(
+    logger: &slog::Logger,
+    dist: &dyn PythonDistribution,
+    pip_python_exe: &Path,
+    verbose: bool,
+    download_dir: &Path,
+    wheel_dir: &Path,
+    index_args: &[String],
+    index: &PipIndexOptions,
+    env: &HashMap<String, String, S>,
+    install_args: Vec<String>,
+) -> Result<Vec<String>> {
+    let mut sdists = std::fs::read_dir(download_dir)
+        .with_context(|| format!("reading {}", download_dir.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|p| is_sdist(p))
+        .collect::<Vec<_>>();
+    sdists.sort();
+
+    if sdists.is_empty() {
+        return Ok(install_args);
+    }
+
+    // Extension modules built against a statically linked distribution
+    // are recorded in the build's state directory, so they can't be cached.
+    let cache_dir = if env.contains_key("PYOXIDIZER_DISTUTILS_STATE_DIR") {
+        None
+    } else {
+        index.sdist_wheel_cache.clone()
+    };
+
+    let builder = SdistBuilder {
+        logger,
+        dist,
+        pip_python_exe,
+        cache_dir,
+        index_args,
+        envs: env,
+        verbose,
+    };
+
+    for sdist in &sdists {
+        builder.build_wheel(sdist, wheel_dir)?;
+    }
+
+    let mut args = vec!["--find-links".to_string(), wheel_dir.display().to_string()];
+
+    if install_args.iter().any(|a| a == "--require-hashes") {
+        // Hashes of locked packages were verified when downloading them.
+        // Built wheels have different hashes, so downloaded files are
+        // installed directly.
+        args.extend(vec!["--no-deps".to_string(), "--no-index".to_string()]);
+
+        let mut files = std::fs::read_dir(download_dir)?
+            .chain(std::fs::read_dir(wheel_dir)?)
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|p| !is_sdist(p))
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>();
+        files.sort();
+        args.extend(files);
+    } else {
+        // pip prefers wheels over sdists of the same version.
+        args.extend(install_args);
+    }
+
+    Ok(args)
+}
+
 /// Run `pip install` and return found resources.
+///
+/// Packages only available as source distributions are built into wheels
+/// first if `index.build_sdists` is set.
 pub fn pip_install<S: BuildHasher>(
     logger: &slog::Logger,
     dist: &dyn PythonDistribution,
     verbose: bool,
     install_args: &[String],
+    index: &PipIndexOptions,
     extra_envs: &HashMap<String, String, S>,
 ) -> Result<Vec<PythonResource>> {
-    let temp_dir = tempdir::TempDir::new("pyoxidizer-pip-install")?;
+    let _phase = timing::phase("pip install");
+
+    let temp_dir = crate::scratch::scratch_dir("pyoxidizer-pip-install")?;
 
-    dist.ensure_pip(logger)?;
+    let pip_python_exe = dist.ensure_pip(logger, &temp_dir.path().join("pip"))?;
+
+    let mut index_args = index.pip_args()?;
 
     let mut env = dist.resolve_distutils(logger, temp_dir.path(), &[])?;
 
+    env.extend(index.pip_envs());
+
     for (key, value) in extra_envs.iter() {
         env.insert(key.clone(), value.clone());
     }
 
+    // pip uses keyring for credentials if it can import it.
+    if index.keyring && !index.offline {
+        let keyring_dir = temp_dir.path().join("keyring");
+
+        warn!(
+            logger,
+            "pip installing keyring to {}",
+            keyring_dir.display()
+        );
+        run_logged_command(
+            logger,
+            std::process::Command::new(&pip_python_exe)
+                .args(&["-m", "pip", "--disable-pip-version-check", "install"])
+                .arg("--target")
+                .arg(&keyring_dir)
+                .args(&index_args)
+                .arg("keyring")
+                .envs(&index.pip_envs()),
+        )
+        .context("installing keyring")?;
+
+        let mut paths = vec![keyring_dir];
+        if let Some(path) = env.get("PYTHONPATH") {
+            paths.extend(std::env::split_paths(path));
+        }
+        let python_path = std::env::join_paths(paths)?;
+        env.insert(
+            "PYTHONPATH".to_string(),
+            python_path.to_string_lossy().to_string(),
+        );
+    }
+
+    let mut install_args = install_args.to_vec();
+
+    if index.download_dir.is_some() || index.build_sdists {
+        let download_dir = temp_dir.path().join("download");
+        std::fs::create_dir_all(&download_dir)?;
+
+        warn!(logger, "pip downloading to {}", download_dir.display());
+        run_logged_command(
+            logger,
+            std::process::Command::new(&pip_python_exe)
+                .args(&["-m", "pip", "--disable-pip-version-check", "download"])
+                .arg("--dest")
+                .arg(&download_dir)
+                .args(&index_args)
+                .args(&install_args)
+                .envs(&env),
+        )
+        .context("downloading packages")?;
+
+        if let Some(dest_dir) = &index.download_dir {
+            std::fs::create_dir_all(dest_dir)
+                .with_context(|| format!("creating {}", dest_dir.display()))?;
+
+            for entry in std::fs::read_dir(&download_dir)? {
+                let path = entry?.path();
+                let dest_path = dest_dir.join(path.file_name().unwrap());
+                std::fs::copy(&path, &dest_path)
+                    .with_context(|| format!("copying {}", path.display()))?;
+            }
+        }
+
+        if index.build_sdists {
+            install_args = build_downloaded_sdists(
+                logger,
+                dist,
+                &pip_python_exe,
+                verbose,
+                &download_dir,
+                &temp_dir.path().join("sdist-wheels"),
+                &index_args,
+                index,
+                &env,
+                install_args,
+            )?;
+        }
+
+        index_args.push("--find-links".to_string());
+        index_args.push(download_dir.display().to_string());
+    }
+
     let target_dir = temp_dir.path().join("install");
 
     warn!(logger, "pip installing to {}", target_dir.display());
@@ -251,10 +817,13 @@ pub fn pip_install<S: BuildHasher>(
         format!("{}", target_dir.display()),
     ]);
 
-    pip_args.extend(install_args.iter().cloned());
+    pip_args.extend(index_args);
+    pip_args.extend(install_args);
+
+    let progress = Progress::spinner(logger, "running pip install");
 
     // TODO send stderr to stdout
-    let mut cmd = std::process::Command::new(&dist.python_exe_path())
+    let mut cmd = std::process::Command::new(&pip_python_exe)
         .args(&pip_args)
         .envs(&env)
         .stdout(std::process::Stdio::piped())
@@ -276,6 +845,8 @@ pub fn pip_install<S: BuildHasher>(
         return Err(anyhow!("error running pip"));
     }
 
+    progress.finish();
+
     let state_dir = match env.get("PYOXIDIZER_DISTUTILS_STATE_DIR") {
         Some(p) => Some(PathBuf::from(p)),
         None => None,
@@ -284,6 +855,40 @@ pub fn pip_install<S: BuildHasher>(
     find_resources(logger, dist, &target_dir, state_dir)
 }
 
+/// Install packages pinned by a lockfile and return found resources.
+///
+/// Packages are installed with their hashes verified. Dependencies are not
+/// resolved, as lockfiles pin every package needed.
+pub fn pip_install_locked_packages<S: BuildHasher>(
+    logger: &slog::Logger,
+    dist: &dyn PythonDistribution,
+    verbose: bool,
+    packages: &[LockedPackage],
+    index: &PipIndexOptions,
+    extra_envs: &HashMap<String, String, S>,
+) -> Result<Vec<PythonResource>> {
+    let temp_dir = crate::scratch::scratch_dir("pyoxidizer-lockfile")?;
+
+    let requirements_path = temp_dir.path().join("requirements.txt");
+    std::fs::write(&requirements_path, render_requirements(packages)?)?;
+
+    warn!(logger, "installing {} locked packages", packages.len());
+
+    pip_install(
+        logger,
+        dist,
+        verbose,
+        &[
+            "--no-deps".to_string(),
+            "--require-hashes".to_string(),
+            "-r".to_string(),
+            requirements_path.display().to_string(),
+        ],
+        index,
+        extra_envs,
+    )
+}
+
 /// Discover Python resources from a populated virtualenv directory.
 pub fn read_virtualenv(
     logger: &slog::Logger,
@@ -295,6 +900,120 @@ pub fn read_virtualenv(
     find_resources(logger, dist, &python_paths.site_packages, None)
 }
 
+/// Artifacts found in a conda environment.
+#[derive(Debug, Default)]
+pub struct CondaEnvironmentArtifacts {
+    /// Python resources installed in the environment's `site-packages`.
+    pub resources: Vec<PythonResource>,
+
+    /// Shared libraries and data files of conda packages.
+    ///
+    /// Paths are relative to the environment's root.
+    pub files: FileManifest,
+}
+
+/// Whether a file installed by a conda package should be distributed with an application.
+///
+/// `path` is the file's path relative to the environment root, as recorded in
+/// `conda-meta`. Shared libraries and data files are distributed. Python
+/// modules are handled separately and headers, static libraries, programs
+/// and documentation aren't needed at run-time.
+fn is_conda_runtime_file(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+
+    if path.contains("site-packages/") || path.starts_with("conda-meta/") {
+        false
+    } else if name.ends_with(".so")
+        || name.contains(".so.")
+        || name.ends_with(".dylib")
+        || name.ends_with(".dll")
+    {
+        true
+    } else if let Some(prefix) = ["share/", "Library/share/"]
+        .iter()
+        .find(|prefix| path.starts_with(*prefix))
+    {
+        let rest = &path[prefix.len()..];
+
+        !["doc/", "info/", "man/", "locale/"]
+            .iter()
+            .any(|p| rest.starts_with(p))
+    } else {
+        path.starts_with("etc/") || path.starts_with("Library/etc/")
+    }
+}
+
+/// Discover Python resources and native files from a conda environment.
+///
+/// Python resources are read from the environment's `site-packages`. Package
+/// metadata in `conda-meta` is used to find shared libraries and data files
+/// installed by conda packages outside of `site-packages`. Files of the
+/// `python` package itself are ignored, as the Python distribution provides
+/// them.
+pub fn find_conda_env_artifacts(
+    logger: &slog::Logger,
+    dist: &dyn PythonDistribution,
+    path: &Path,
+) -> Result<CondaEnvironmentArtifacts> {
+    let meta_dir = path.join("conda-meta");
+    if !meta_dir.is_dir() {
+        return Err(anyhow!("{} is not a conda environment", path.display()));
+    }
+
+    let mut res = CondaEnvironmentArtifacts {
+        resources: read_virtualenv(logger, dist, path)?,
+        files: FileManifest::default(),
+    };
+
+    let mut entries = std::fs::read_dir(&meta_dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for meta_path in entries {
+        if meta_path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+
+        let meta: serde_json::Value = serde_json::from_slice(&std::fs::read(&meta_path)?)
+            .with_context(|| format!("parsing {}", meta_path.display()))?;
+
+        if meta.get("name").and_then(|n| n.as_str()) == Some("python") {
+            continue;
+        }
+
+        let files = match meta.get("files").and_then(|f| f.as_array()) {
+            Some(files) => files,
+            None => continue,
+        };
+
+        for file in files.iter().filter_map(|f| f.as_str()) {
+            if !is_conda_runtime_file(file) {
+                continue;
+            }
+
+            let source_path = path.join(file);
+            if !source_path.is_file() {
+                continue;
+            }
+
+            let content = FileContent::try_from(source_path.as_path())
+                .with_context(|| format!("reading {}", source_path.display()))?;
+            res.files.add_file(Path::new(file), &content)?;
+        }
+    }
+
+    warn!(
+        logger,
+        "found {} Python resources and {} files in conda environment {}",
+        res.resources.len(),
+        res.files.entries().count(),
+        path.display()
+    );
+
+    Ok(res)
+}
+
 /// Run `setup.py install` against a path and return found resources.
 pub fn setup_py_install<S: BuildHasher>(
     logger: &slog::Logger,
@@ -311,7 +1030,7 @@ pub fn setup_py_install<S: BuildHasher>(
         ));
     }
 
-    let temp_dir = tempdir::TempDir::new("pyoxidizer-setup-py-install")?;
+    let temp_dir = crate::scratch::scratch_dir("pyoxidizer-setup-py-install")?;
 
     let target_dir_path = temp_dir.path().join("install");
     let target_dir_s = target_dir_path.display().to_string();
@@ -383,12 +1102,400 @@ pub fn setup_py_install<S: BuildHasher>(
     find_resources(logger, dist, &python_paths.site_packages, state_dir)
 }
 
+/// Build a project with its PEP 517 build backend and return found resources.
+///
+/// The project at `project_path` is built into a wheel using the build backend
+/// declared in its `pyproject.toml` (falling back to setuptools). The wheel is
+/// then installed to a temporary directory, which is scanned for resources.
+/// Dependencies of the project are not built or installed.
+pub fn build_pep517_project<S: BuildHasher>(
+    logger: &slog::Logger,
+    dist: &dyn PythonDistribution,
+    project_path: &Path,
+    verbose: bool,
+    extra_envs: &HashMap<String, String, S>,
+) -> Result<Vec<PythonResource>> {
+    if !project_path.is_absolute() {
+        return Err(anyhow!(
+            "project_path must be absolute: got {:?}",
+            project_path.display()
+        ));
+    }
+
+    let temp_dir = crate::scratch::scratch_dir("pyoxidizer-pep517-build")?;
+
+    let pip_python_exe = dist.ensure_pip(logger, &temp_dir.path().join("pip"))?;
+
+    let mut envs = dist.resolve_distutils(logger, temp_dir.path(), &[])?;
+
+    for (key, value) in extra_envs.iter() {
+        envs.insert(key.clone(), value.clone());
+    }
+
+    let wheel_dir = temp_dir.path().join("wheels");
+    let wheel_dir_s = wheel_dir.display().to_string();
+    let project_path_s = project_path.display().to_string();
+
+    let mut args = vec!["-m", "pip", "--disable-pip-version-check"];
+
+    if verbose {
+        args.push("--verbose");
+    }
+
+    args.extend(&[
+        "wheel",
+        "--use-pep517",
+        "--no-deps",
+        "--wheel-dir",
+        &wheel_dir_s,
+        &project_path_s,
+    ]);
+
+    warn!(logger, "building wheel for {}", project_path_s);
+    run_logged_command(
+        logger,
+        std::process::Command::new(&pip_python_exe)
+            .args(&args)
+            .envs(&envs),
+    )
+    .with_context(|| format!("building wheel for {}", project_path_s))?;
+
+    let mut wheels = Vec::new();
+    for entry in std::fs::read_dir(&wheel_dir)? {
+        let path = entry?.path();
+
+        if path.extension().and_then(|e| e.to_str()) == Some("whl") {
+            wheels.push(path);
+        }
+    }
+
+    let wheel = match wheels.as_slice() {
+        [wheel] => wheel.display().to_string(),
+        _ => {
+            return Err(anyhow!(
+                "expected 1 wheel to be built; found {}",
+                wheels.len()
+            ))
+        }
+    };
+
+    let target_dir = temp_dir.path().join("install");
+    let target_dir_s = target_dir.display().to_string();
+
+    warn!(logger, "installing {} to {}", wheel, target_dir_s);
+    run_logged_command(
+        logger,
+        std::process::Command::new(&pip_python_exe).args(&[
+            "-m",
+            "pip",
+            "--disable-pip-version-check",
+            "install",
+            "--no-deps",
+            "--no-index",
+            "--target",
+            target_dir_s.as_str(),
+            wheel.as_str(),
+        ]),
+    )
+    .with_context(|| format!("installing {}", wheel))?;
+
+    let state_dir = match envs.get("PYOXIDIZER_DISTUTILS_STATE_DIR") {
+        Some(p) => Some(PathBuf::from(p)),
+        None => None,
+    };
+
+    find_resources(logger, dist, &target_dir, state_dir)
+}
+
+/// A tool compiling Python modules into extension modules.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModuleCompiler {
+    /// mypyc, which compiles type annotated modules.
+    Mypyc,
+    /// Cython, compiling modules in its pure Python mode.
+    Cython,
+}
+
+impl ModuleCompiler {
+    /// The pip requirement installing the compiler by default.
+    pub fn default_requirement(self) -> &'static str {
+        match self {
+            ModuleCompiler::Mypyc => "mypy",
+            ModuleCompiler::Cython => "Cython",
+        }
+    }
+
+    /// Python arguments invoking the compiler on source files.
+    fn python_args(self) -> Vec<&'static str> {
+        match self {
+            ModuleCompiler::Mypyc => vec!["-m", "mypyc"],
+            ModuleCompiler::Cython => vec!["-m", "Cython.Build.Cythonize", "-3", "-i"],
+        }
+    }
+}
+
+/// Resolve the path of a source module relative to its package root.
+fn source_module_path(module: &SourceModule) -> PathBuf {
+    let mut path = module.name.split('.').collect::<PathBuf>();
+
+    if module.is_package {
+        path.push("__init__.py");
+    } else {
+        path.set_extension("py");
+    }
+
+    path
+}
+
+/// Run a command, logging its output.
+pub fn run_logged_command(
+    logger: &slog::Logger,
+    command: &mut std::process::Command,
+) -> Result<()> {
+    let mut cmd = command.stdout(std::process::Stdio::piped()).spawn()?;
+    {
+        let stdout = cmd
+            .stdout
+            .as_mut()
+            .ok_or_else(|| anyhow!("unable to get stdout"))?;
+        let reader = BufReader::new(stdout);
+
+        for line in reader.lines() {
+            warn!(logger, "{}", line?);
+        }
+    }
+
+    let status = cmd.wait()?;
+    if !status.success() {
+        return Err(anyhow!("command exited with {}", status));
+    }
+
+    Ok(())
+}
+
+/// Compile Python source modules into extension modules.
+///
+/// `modules` holds the source modules of the packages being compiled, which
+/// are available to the compiler for resolving imports. Only modules whose
+/// names are in `compile` are compiled. `requirement` is a pip requirement
+/// installing the compiler.
+///
+/// Returns the extension modules produced, including any runtime library
+/// modules emitted by the compiler.
+pub fn compile_modules<S: BuildHasher>(
+    logger: &slog::Logger,
+    dist: &dyn PythonDistribution,
+    compiler: ModuleCompiler,
+    requirement: &str,
+    modules: &[SourceModule],
+    compile: &[String],
+    extra_envs: &HashMap<String, String, S>,
+) -> Result<Vec<PythonResource>> {
+    let temp_dir = crate::scratch::scratch_dir("pyoxidizer-compile-modules")?;
+
+    let source_dir = temp_dir.path().join("src");
+    let mut compile_paths = Vec::new();
+
+    for module in modules {
+        let path = source_module_path(module);
+        let dest_path = source_dir.join(&path);
+        let parent_dir = dest_path
+            .parent()
+            .ok_or_else(|| anyhow!("unable to determine parent directory"))?;
+        std::fs::create_dir_all(parent_dir)?;
+        std::fs::write(&dest_path, module.source.resolve()?)?;
+
+        if compile.contains(&module.name) {
+            compile_paths.push(path.display().to_string());
+        }
+    }
+
+    if compile_paths.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let pip_python_exe = dist.ensure_pip(logger, &temp_dir.path().join("pip"))?;
+
+    let tools_dir = temp_dir.path().join("tools");
+    let tools_dir_s = tools_dir.display().to_string();
+
+    warn!(logger, "pip installing {} to {}", requirement, tools_dir_s);
+    run_logged_command(
+        logger,
+        std::process::Command::new(&pip_python_exe).args(&[
+            "-m",
+            "pip",
+            "--disable-pip-version-check",
+            "install",
+            "--target",
+            tools_dir_s.as_str(),
+            requirement,
+        ]),
+    )
+    .with_context(|| format!("installing {}", requirement))?;
+
+    let mut envs = dist.resolve_distutils(logger, temp_dir.path(), &[])?;
+
+    for (key, value) in extra_envs.iter() {
+        envs.insert(key.clone(), value.clone());
+    }
+
+    envs.insert("PYTHONPATH".to_string(), tools_dir_s);
+
+    warn!(
+        logger,
+        "compiling {} modules with {:?}",
+        compile_paths.len(),
+        compiler
+    );
+    run_logged_command(
+        logger,
+        std::process::Command::new(&dist.python_exe_path())
+            .current_dir(&source_dir)
+            .args(compiler.python_args())
+            .args(&compile_paths)
+            .envs(&envs),
+    )
+    .with_context(|| format!("compiling modules with {:?}", compiler))?;
+
+    let state_dir = match envs.get("PYOXIDIZER_DISTUTILS_STATE_DIR") {
+        Some(p) => Some(PathBuf::from(p)),
+        None => None,
+    };
+
+    Ok(find_resources(logger, dist, &source_dir, state_dir)?
+        .into_iter()
+        .filter(|r| match r {
+            PythonResource::ExtensionModuleDynamicLibrary(..)
+            | PythonResource::ExtensionModuleStaticallyLinked(..) => true,
+            _ => false,
+        })
+        .collect())
+}
+
 #[cfg(test)]
 mod tests {
     #[cfg(windows)]
     use itertools::Itertools;
     use {super::*, crate::testutil::*, std::ops::Deref};
 
+    #[test]
+    fn test_parse_distribution_dir_name() {
+        assert_eq!(
+            parse_distribution_dir_name("foo_bar-1.0.dist-info"),
+            Some(("foo_bar".to_string(), "1.0".to_string()))
+        );
+        assert_eq!(
+            parse_distribution_dir_name("foo-1.0-py3.7.egg-info"),
+            Some(("foo".to_string(), "1.0".to_string()))
+        );
+        assert_eq!(parse_distribution_dir_name("foo.egg-info"), None);
+        assert_eq!(parse_distribution_dir_name("foo-1.0"), None);
+    }
+
+    #[test]
+    fn test_is_conda_runtime_file() {
+        assert!(is_conda_runtime_file("lib/libopenblas.so.0"));
+        assert!(is_conda_runtime_file("lib/libgfortran.dylib"));
+        assert!(is_conda_runtime_file("Library/bin/mkl_core.dll"));
+        assert!(is_conda_runtime_file("share/proj/proj.db"));
+        assert!(!is_conda_runtime_file("share/man/man1/proj.1"));
+        assert!(!is_conda_runtime_file(
+            "lib/python3.7/site-packages/numpy/core/_multiarray_umath.so"
+        ));
+        assert!(!is_conda_runtime_file("include/cblas.h"));
+        assert!(!is_conda_runtime_file("lib/libopenblas.a"));
+        assert!(!is_conda_runtime_file("bin/f2py"));
+    }
+
+    #[test]
+    fn test_pip_index_options_args() -> Result<()> {
+        let mut options = PipIndexOptions {
+            index_url: Some("https://pypi.example.com/simple".to_string()),
+            trusted_hosts: vec!["pypi.example.com".to_string()],
+            find_links: vec![PathBuf::from("wheels")],
+            ..PipIndexOptions::default()
+        };
+
+        assert_eq!(
+            options.pip_args()?,
+            vec![
+                "--index-url",
+                "https://pypi.example.com/simple",
+                "--trusted-host",
+                "pypi.example.com",
+                "--find-links",
+                "wheels"
+            ]
+        );
+
+        options.offline = true;
+        assert_eq!(
+            options.pip_args()?,
+            vec!["--no-index", "--find-links", "wheels"]
+        );
+
+        options.find_links.clear();
+        assert!(options.pip_args().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_distribution_resources() -> Result<()> {
+        let td = tempdir::TempDir::new("pyoxidizer-test")?;
+        let root = td.path();
+
+        let dist_info = root.join("foo-1.0.dist-info");
+        std::fs::create_dir_all(dist_info.join("licenses"))?;
+        std::fs::write(dist_info.join("METADATA"), b"Name: foo\nVersion: 1.0\n")?;
+        std::fs::write(dist_info.join("licenses").join("LICENSE"), b"MIT")?;
+        std::fs::create_dir(root.join("foo"))?;
+        std::fs::write(root.join("foo").join("__init__.py"), b"")?;
+
+        let resources = find_distribution_resources(root)?;
+
+        assert_eq!(
+            resources,
+            vec![
+                DistributionResource {
+                    package: "foo".to_string(),
+                    version: "1.0".to_string(),
+                    name: "METADATA".to_string(),
+                    data: DataLocation::Path(dist_info.join("METADATA")),
+                },
+                DistributionResource {
+                    package: "foo".to_string(),
+                    version: "1.0".to_string(),
+                    name: "licenses/LICENSE".to_string(),
+                    data: DataLocation::Path(dist_info.join("licenses").join("LICENSE")),
+                },
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_module_path() {
+        assert_eq!(
+            source_module_path(&SourceModule {
+                name: "foo.bar".to_string(),
+                source: DataLocation::Memory(vec![]),
+                is_package: false,
+            }),
+            PathBuf::from("foo").join("bar.py")
+        );
+        assert_eq!(
+            source_module_path(&SourceModule {
+                name: "foo".to_string(),
+                source: DataLocation::Memory(vec![]),
+                is_package: true,
+            }),
+            PathBuf::from("foo").join("__init__.py")
+        );
+    }
+
     #[test]
     fn test_install_black() -> Result<()> {
         let logger = get_logger()?;
@@ -399,6 +1506,7 @@ mod tests {
             distribution.deref().as_ref(),
             false,
             &["black==19.10b0".to_string()],
+            &PipIndexOptions::default(),
             &HashMap::new(),
         )?;
 
@@ -420,6 +1528,7 @@ mod tests {
             distribution.deref().as_ref(),
             false,
             &["cffi==1.14.0".to_string()],
+            &PipIndexOptions::default(),
             &HashMap::new(),
         )?;
 
@@ -436,4 +1545,97 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_find_resources_pth_file() -> Result<()> {
+        let logger = get_logger()?;
+        let td = tempdir::TempDir::new("pyoxidizer-test")?;
+        let site_packages = td.path().join("site-packages");
+        let src = td.path().join("src");
+
+        std::fs::create_dir_all(site_packages.join("foo"))?;
+        std::fs::write(site_packages.join("foo").join("__init__.py"), b"")?;
+        std::fs::create_dir_all(src.join("bar"))?;
+        std::fs::write(src.join("bar").join("__init__.py"), b"")?;
+        std::fs::write(
+            site_packages.join("bar.pth"),
+            b"../src\n./foo\nimport bar_hook\n",
+        )?;
+
+        let distribution = get_default_distribution()?;
+        let resources =
+            find_resources(&logger, distribution.deref().as_ref(), &site_packages, None)?;
+
+        let names = resources
+            .iter()
+            .filter_map(|r| match r {
+                PythonResource::ModuleSource { name, .. } => Some(name.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["foo", "bar"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_resources_editable() -> Result<()> {
+        let logger = get_logger()?;
+        let td = tempdir::TempDir::new("pyoxidizer-test")?;
+        let site_packages = td.path().join("site-packages");
+        let src = td.path().join("src");
+
+        std::fs::create_dir_all(&site_packages)?;
+        std::fs::create_dir_all(src.join("foo"))?;
+        std::fs::write(src.join("foo").join("__init__.py"), b"# foo")?;
+        std::fs::write(src.join("foo").join("data.txt"), b"data")?;
+        std::fs::write(
+            site_packages.join("__editable__.foo-1.0.pth"),
+            b"import __editable___foo_1_0_finder; __editable___foo_1_0_finder.install()\n",
+        )?;
+        std::fs::write(
+            site_packages.join("__editable___foo_1_0_finder.py"),
+            format!(
+                "import sys\nMAPPING = {{'foo': {:?}}}\n",
+                src.join("foo").display().to_string()
+            ),
+        )?;
+
+        let distribution = get_default_distribution()?;
+        let resources =
+            find_resources(&logger, distribution.deref().as_ref(), &site_packages, None)?;
+
+        assert_eq!(resources.len(), 2);
+
+        match &resources[0] {
+            PythonResource::ModuleSource {
+                name,
+                source,
+                is_package,
+            } => {
+                assert_eq!(name, "foo");
+                assert_eq!(
+                    source,
+                    &DataLocation::Path(src.join("foo").join("__init__.py"))
+                );
+                assert!(*is_package);
+            }
+            _ => panic!("expected source module"),
+        }
+
+        match &resources[1] {
+            PythonResource::Resource {
+                package,
+                name,
+                data,
+            } => {
+                assert_eq!(package, "foo");
+                assert_eq!(name, "data.txt");
+                assert_eq!(data, &DataLocation::Path(src.join("foo").join("data.txt")));
+            }
+            _ => panic!("expected resource"),
+        }
+
+        Ok(())
+    }
 }
@@ -8,27 +8,41 @@ Functionality related to the pyembed crate.
 
 use anyhow::Result;
 use itertools::Itertools;
+use python_packed_resources::data::FileEmulation;
 use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
-use super::config::{EmbeddedPythonConfig, RawAllocator, RunMode, TerminfoResolution};
+use super::config::{
+    AuditAction, EmbeddedPythonConfig, RawAllocator, ResourcesIntegrity, RunMode,
+    TerminfoResolution,
+};
 
 /// Obtain the Rust source code to construct a PythonConfig instance.
+///
+/// `name` and `target_triple` describe the binary being built. Update checks
+/// reject manifests of other applications or targets.
 pub fn derive_python_config(
     embedded: &EmbeddedPythonConfig,
+    name: &str,
+    target_triple: &str,
     importlib_bootstrap_path: &PathBuf,
     importlib_bootstrap_external_path: &PathBuf,
     embedded_resources_path: &PathBuf,
+    embedded_resources_digest: &[u8],
 ) -> String {
     format!(
         "pyembed::PythonConfig {{\n    \
          standard_io_encoding: {},\n    \
          standard_io_errors: {},\n    \
+         utf8_mode: {},\n    \
+         windows_console_code_page: {},\n    \
          opt_level: {},\n    \
          use_custom_importlib: true,\n    \
          filesystem_importer: {},\n    \
          sys_paths: [{}].to_vec(),\n    \
+         filesystem_import_paths: [{}].to_vec(),\n    \
+         user_site_packages: {},\n    \
          bytes_warning: {},\n    \
          import_site: {},\n    \
          import_user_site: {},\n    \
@@ -47,6 +61,16 @@ pub fn derive_python_config(
          frozen_importlib_data: include_bytes!(r#\"{}\"#),\n    \
          frozen_importlib_external_data: include_bytes!(r#\"{}\"#),\n    \
          embedded_resources_data: include_bytes!(r#\"{}\"#),\n    \
+         resources_file: {},\n    \
+         resources_integrity: {},\n    \
+         resources_key_env: {},\n    \
+         resources_key_file: {},\n    \
+         pkg_resources_compat: {},\n    \
+         file_emulation: {},\n    \
+         file_extraction_dir: {},\n    \
+         lazy_import: {},\n    \
+         lazy_import_allow: [{}].to_vec(),\n    \
+         lazy_import_deny: [{}].to_vec(),\n    \
          extra_extension_modules: vec![],\n    \
          argvb: false,\n    \
          sys_frozen: {},\n    \
@@ -54,14 +78,36 @@ pub fn derive_python_config(
          raw_allocator: {},\n    \
          terminfo_resolution: {},\n    \
          write_modules_directory_env: {},\n    \
+         import_profile_env: {},\n    \
+         self_test: {},\n    \
+         debug_console: {},\n    \
+         faulthandler: {},\n    \
+         crash_dir: {},\n    \
+         update: {},\n    \
+         splash_image: {},\n    \
+         inherit_environment: {},\n    \
+         remove_environment: [{}].to_vec(),\n    \
+         environment: [{}].to_vec(),\n    \
+         dotenv: {},\n    \
+         install_signal_handlers: {},\n    \
+         forward_signals: [{}].to_vec(),\n    \
+         shutdown_hook: None,\n    \
+         import_allow: {},\n    \
+         import_deny: [{}].to_vec(),\n    \
+         audit_policy: [{}].to_vec(),\n    \
          run: {},\n\
          }}",
         match &embedded.stdio_encoding_name {
-            Some(value) => format_args!("Some(\"{}\")", value).to_string(),
+            Some(value) => format!("Some(r###\"{}\"###.to_string())", value),
             None => "None".to_owned(),
         },
         match &embedded.stdio_encoding_errors {
-            Some(value) => format_args!("Some(\"{}\")", value).to_string(),
+            Some(value) => format!("Some(r###\"{}\"###.to_string())", value),
+            None => "None".to_owned(),
+        },
+        embedded.utf8_mode,
+        match embedded.windows_console_code_page {
+            Some(code_page) => format!("Some({})", code_page),
             None => "None".to_owned(),
         },
         embedded.optimize_level,
@@ -72,6 +118,15 @@ pub fn derive_python_config(
             .map(|p| "\"".to_owned() + p + "\".to_string()")
             .collect::<Vec<String>>()
             .join(", "),
+        &embedded
+            .filesystem_import_paths
+            .iter()
+            .map(|p| format!("r###\"{}\"###.to_string()", p))
+            .join(", "),
+        match &embedded.user_site_packages {
+            Some(value) => format!("Some(r###\"{}\"###.to_string())", value),
+            None => "None".to_owned(),
+        },
         embedded.bytes_warning,
         embedded.site_import,
         embedded.user_site_directory,
@@ -90,6 +145,52 @@ pub fn derive_python_config(
         importlib_bootstrap_path.display(),
         importlib_bootstrap_external_path.display(),
         embedded_resources_path.display(),
+        match &embedded.resources_file {
+            Some(value) => format!("Some(r###\"{}\"###.to_string())", value),
+            None => "None".to_owned(),
+        },
+        match embedded.resources_integrity {
+            ResourcesIntegrity::None => "pyembed::ResourcesIntegrity::None".to_string(),
+            ResourcesIntegrity::Import => "pyembed::ResourcesIntegrity::Import".to_string(),
+            ResourcesIntegrity::Startup => format!(
+                "pyembed::ResourcesIntegrity::Startup(&[{}])",
+                embedded_resources_digest
+                    .iter()
+                    .map(|b| format!("{:#04x}", b))
+                    .join(", ")
+            ),
+        },
+        match &embedded.resources_key_env {
+            Some(value) => format!("Some(r###\"{}\"###.to_string())", value),
+            None => "None".to_owned(),
+        },
+        match &embedded.resources_key_file {
+            Some(value) => format!("Some(r###\"{}\"###.to_string())", value),
+            None => "None".to_owned(),
+        },
+        embedded.pkg_resources_compat,
+        match embedded.file_emulation {
+            FileEmulation::None => "pyembed::FileEmulation::None",
+            FileEmulation::FakePath => "pyembed::FileEmulation::FakePath",
+            FileEmulation::ExtractOnAccess => "pyembed::FileEmulation::ExtractOnAccess",
+        },
+        match &embedded.file_extraction_dir {
+            Some(value) => format!("Some(r###\"{}\"###.to_string())", value),
+            None => "None".to_owned(),
+        },
+        embedded.lazy_import,
+        &embedded
+            .lazy_import_allow
+            .iter()
+            .map(|p| "\"".to_owned() + p + "\".to_string()")
+            .collect::<Vec<String>>()
+            .join(", "),
+        &embedded
+            .lazy_import_deny
+            .iter()
+            .map(|p| "\"".to_owned() + p + "\".to_string()")
+            .collect::<Vec<String>>()
+            .join(", "),
         embedded.sys_frozen,
         embedded.sys_meipass,
         match embedded.raw_allocator {
@@ -108,6 +209,74 @@ pub fn derive_python_config(
             Some(path) => "Some(\"".to_owned() + &path + "\".to_string())",
             _ => "None".to_owned(),
         },
+        match &embedded.import_profile_env {
+            Some(value) => format!("Some(r###\"{}\"###.to_string())", value),
+            None => "None".to_owned(),
+        },
+        embedded.self_test,
+        embedded.debug_console,
+        embedded.faulthandler,
+        match &embedded.crash_dir {
+            Some(value) => format!("Some(r###\"{}\"###.to_string())", value),
+            None => "None".to_owned(),
+        },
+        match &embedded.update {
+            Some(update) => format!(
+                "Some(pyembed::UpdateConfig {{ url: r###\"{}\"###.to_string(), public_key: \"{}\".to_string(), version: r###\"{}\"###.to_string(), name: r###\"{}\"###.to_string(), target_triple: \"{}\".to_string() }})",
+                update.url, update.public_key, update.version, name, target_triple
+            ),
+            None => "None".to_owned(),
+        },
+        match &embedded.splash_image {
+            Some(path) => format!("Some(include_bytes!(r#\"{}\"#))", path.display()),
+            None => "None".to_owned(),
+        },
+        embedded.inherit_environment,
+        &embedded
+            .remove_environment
+            .iter()
+            .map(|p| format!("r###\"{}\"###.to_string()", p))
+            .join(", "),
+        &embedded
+            .environment
+            .iter()
+            .map(|(k, v)| format!("(r###\"{}\"###.to_string(), r###\"{}\"###.to_string())", k, v))
+            .join(", "),
+        embedded.dotenv,
+        embedded.install_signal_handlers,
+        &embedded
+            .forward_signals
+            .iter()
+            .map(|name| format!("\"{}\".to_string()", name))
+            .join(", "),
+        match &embedded.import_allow {
+            Some(modules) => format!(
+                "Some([{}].to_vec())",
+                modules
+                    .iter()
+                    .map(|name| format!("\"{}\".to_string()", name))
+                    .join(", ")
+            ),
+            None => "None".to_owned(),
+        },
+        &embedded
+            .import_deny
+            .iter()
+            .map(|name| format!("\"{}\".to_string()", name))
+            .join(", "),
+        &embedded
+            .audit_policy
+            .iter()
+            .map(|(event, action)| format!(
+                "(r###\"{}\"###.to_string(), pyembed::AuditAction::{})",
+                event,
+                match action {
+                    AuditAction::Allow => "Allow",
+                    AuditAction::Log => "Log",
+                    AuditAction::Block => "Block",
+                }
+            ))
+            .join(", "),
         match embedded.run_mode {
             RunMode::Noop => "pyembed::PythonRunMode::None".to_owned(),
             RunMode::Repl => "pyembed::PythonRunMode::Repl".to_owned(),
@@ -126,6 +295,7 @@ pub fn derive_python_config(
                     + path
                     + "\"###).expect(\"converting filename path to CString\") }"
             }
+            RunMode::PythonCli => "pyembed::PythonRunMode::PythonCli".to_owned(),
         },
     )
 }
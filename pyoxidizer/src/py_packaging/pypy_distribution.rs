@@ -0,0 +1,369 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Functionality for PyPy distributions. */
+
+use {
+    super::binary::PythonBinaryBuilder,
+    super::bytecode::BytecodeCompiler,
+    super::config::EmbeddedPythonConfig,
+    super::distribution::{
+        create_pip_virtualenv, extract_distribution, extract_zip,
+        resolve_python_distribution_from_location, DistributionCapabilities, ExtensionModuleFilter,
+        PythonDistribution, PythonDistributionLocation, PythonImplementation, PythonModuleSuffixes,
+    },
+    super::fsscan::{find_python_resources, is_package_from_path, PythonFileResource},
+    super::libpython::ImportlibBytecode,
+    super::resource::{DataLocation, PythonResource, ResourceData, SourceModule},
+    super::standalone_distribution::ExtensionModule,
+    anyhow::{anyhow, Context, Result},
+    slog::warn,
+    std::collections::{BTreeMap, HashMap},
+    std::io::BufReader,
+    std::path::{Path, PathBuf},
+};
+
+/// Resolve the path to the `pypy3` executable in an extracted distribution.
+#[cfg(windows)]
+fn pypy_exe_path(root: &Path) -> PathBuf {
+    root.join("pypy3.exe")
+}
+
+/// Resolve the path to the `pypy3` executable in an extracted distribution.
+#[cfg(unix)]
+fn pypy_exe_path(root: &Path) -> PathBuf {
+    root.join("bin").join("pypy3")
+}
+
+/// Parse the `X.Y` Python version from a PyPy distribution directory name.
+///
+/// Directories are named like `pypy3.6-v7.3.0-linux64`.
+fn parse_pypy_python_version(dir_name: &str) -> Result<String> {
+    if !dir_name.starts_with("pypy") {
+        return Err(anyhow!(
+            "unexpected PyPy distribution directory name: {}",
+            dir_name
+        ));
+    }
+
+    let version = dir_name[4..].split('-').next().unwrap_or("");
+
+    let components = version.split('.').collect::<Vec<_>>();
+
+    if components.len() != 2 || components.iter().any(|c| c.parse::<u32>().is_err()) {
+        return Err(anyhow!(
+            "unable to determine Python version from PyPy distribution directory name: {}",
+            dir_name
+        ));
+    }
+
+    Ok(version.to_string())
+}
+
+/// A pre-built PyPy distribution.
+///
+/// This type represents the archives published by the PyPy project. PyPy
+/// doesn't provide a static `libpython` and its standard library is split
+/// between `lib-python/3` and `lib_pypy`. So these distributions can't be
+/// embedded in executables. But they can run packaging tools and be the
+/// target of zip applications.
+#[derive(Clone, Debug)]
+pub struct PyPyDistribution {
+    /// Directory holding the distribution's files.
+    pub root: PathBuf,
+
+    /// Path to the `pypy3` executable.
+    pub python_exe: PathBuf,
+
+    /// `X.Y` version of the Python language this distribution implements.
+    pub python_version: String,
+
+    /// Directories containing the standard library.
+    pub stdlib_paths: Vec<PathBuf>,
+
+    /// Python source modules in the standard library.
+    py_modules: BTreeMap<String, PathBuf>,
+
+    /// Non-module files in the standard library, keyed by package.
+    resources: BTreeMap<String, BTreeMap<String, PathBuf>>,
+}
+
+impl PyPyDistribution {
+    pub fn from_location(
+        logger: &slog::Logger,
+        location: &PythonDistributionLocation,
+        distributions_dir: &Path,
+    ) -> Result<Self> {
+        let (archive_path, extract_path) =
+            resolve_python_distribution_from_location(logger, location, distributions_dir)?;
+
+        Self::from_archive(logger, &archive_path, &extract_path)
+    }
+
+    /// Obtain an instance by extracting an archive to a directory.
+    ///
+    /// PyPy publishes `.zip` archives for Windows and `.tar.bz2` archives for
+    /// other platforms. We can't read bzip2, so the latter need to be
+    /// recompressed as `.tar.zst`.
+    pub fn from_archive(logger: &slog::Logger, path: &Path, extract_dir: &Path) -> Result<Self> {
+        let basename = path
+            .file_name()
+            .ok_or_else(|| anyhow!("unable to determine filename"))?
+            .to_string_lossy()
+            .to_string();
+
+        extract_distribution(logger, path, extract_dir, |dest_dir| {
+            if basename.ends_with(".zip") {
+                let zip_data = std::fs::read(path)?;
+                let mut zf = zip::ZipArchive::new(std::io::Cursor::new(zip_data))?;
+                extract_zip(dest_dir, &mut zf)
+            } else if basename.ends_with(".tar.zst") {
+                let fh = std::fs::File::open(path)
+                    .with_context(|| format!("unable to open {}", path.display()))?;
+                let dctx = zstd::stream::Decoder::new(BufReader::new(fh))?;
+                let mut tf = tar::Archive::new(dctx);
+
+                let absolute_path = std::fs::canonicalize(dest_dir)?;
+                tf.unpack(&absolute_path)
+                    .with_context(|| "unable to extract tar archive")
+            } else {
+                Err(anyhow!(
+                    "unsupported PyPy distribution archive format: {}; use a .zip archive or recompress a .tar.bz2 archive as .tar.zst (e.g. `bzcat pypy.tar.bz2 | zstd -o pypy.tar.zst`)",
+                    path.display()
+                ))
+            }
+        })?;
+
+        Self::from_directory(extract_dir)
+    }
+
+    /// Obtain an instance from an extracted archive on the filesystem.
+    ///
+    /// PyPy archives contain a single `pypyX.Y-vA.B.C-<platform>` directory.
+    pub fn from_directory(dist_dir: &Path) -> Result<Self> {
+        let entries = std::fs::read_dir(dist_dir)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let root = match entries.as_slice() {
+            [root] if root.is_dir() => root.clone(),
+            _ => {
+                return Err(anyhow!(
+                    "expected a single directory in PyPy distribution {}",
+                    dist_dir.display()
+                ))
+            }
+        };
+
+        let dir_name = root
+            .file_name()
+            .ok_or_else(|| anyhow!("unable to determine directory name"))?
+            .to_string_lossy()
+            .to_string();
+        let python_version = parse_pypy_python_version(&dir_name)?;
+
+        let python_exe = pypy_exe_path(&root);
+        if !python_exe.exists() {
+            return Err(anyhow!("{} does not exist", python_exe.display()));
+        }
+
+        let stdlib_paths = vec![root.join("lib-python").join("3"), root.join("lib_pypy")];
+        for path in &stdlib_paths {
+            if !path.is_dir() {
+                return Err(anyhow!(
+                    "{} does not exist; is this a PyPy3 distribution?",
+                    path.display()
+                ));
+            }
+        }
+
+        let suffixes = PythonModuleSuffixes::resolve_from_python_exe(&python_exe)?;
+
+        let mut py_modules = BTreeMap::new();
+        let mut resources: BTreeMap<String, BTreeMap<String, PathBuf>> = BTreeMap::new();
+
+        for path in &stdlib_paths {
+            for entry in find_python_resources(path, &suffixes) {
+                match entry {
+                    PythonFileResource::Resource(resource) => {
+                        resources
+                            .entry(resource.package.clone())
+                            .or_insert_with(BTreeMap::new)
+                            .insert(resource.stem.clone(), resource.path);
+                    }
+                    PythonFileResource::Source {
+                        full_name, path, ..
+                    } => {
+                        py_modules.insert(full_name, path);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(PyPyDistribution {
+            root,
+            python_exe,
+            python_version,
+            stdlib_paths,
+            py_modules,
+            resources,
+        })
+    }
+}
+
+impl PythonDistribution for PyPyDistribution {
+    fn clone_box(&self) -> Box<dyn PythonDistribution> {
+        Box::new(self.clone())
+    }
+
+    fn python_exe_path(&self) -> &Path {
+        &self.python_exe
+    }
+
+    fn capabilities(&self) -> DistributionCapabilities {
+        DistributionCapabilities {
+            implementation: PythonImplementation::PyPy,
+            embeddable: false,
+            static_libpython: false,
+            static_extension_modules: false,
+            dynamic_extension_modules: true,
+        }
+    }
+
+    fn python_major_minor_version(&self) -> String {
+        self.python_version.clone()
+    }
+
+    fn python_module_suffixes(&self) -> Result<PythonModuleSuffixes> {
+        PythonModuleSuffixes::resolve_from_python_exe(&self.python_exe)
+    }
+
+    fn create_bytecode_compiler(&self) -> Result<BytecodeCompiler> {
+        BytecodeCompiler::new(&self.python_exe)
+    }
+
+    fn resolve_importlib_bytecode(&self) -> Result<ImportlibBytecode> {
+        Err(anyhow!(
+            "PyPy distributions do not support the PyOxidizer importer"
+        ))
+    }
+
+    fn as_python_executable_builder(
+        &self,
+        _logger: &slog::Logger,
+        _host_triple: &str,
+        _target_triple: &str,
+        _name: &str,
+        _config: &EmbeddedPythonConfig,
+        _extension_module_filter: &ExtensionModuleFilter,
+        _preferred_extension_module_variants: Option<HashMap<String, String>>,
+        _include_sources: bool,
+        _include_resources: bool,
+        _include_test: bool,
+    ) -> Result<Box<dyn PythonBinaryBuilder>> {
+        Err(anyhow!(
+            "PyPy distributions cannot be embedded in executables because PyPy does not provide a static libpython; use to_python_zipapp() to package applications for PyPy"
+        ))
+    }
+
+    fn filter_extension_modules(
+        &self,
+        _logger: &slog::Logger,
+        _filter: &ExtensionModuleFilter,
+        _preferred_variants: Option<HashMap<String, String>>,
+    ) -> Result<Vec<ExtensionModule>> {
+        // PyPy's built-in modules are compiled into libpypy and can't be
+        // selected individually.
+        Ok(Vec::new())
+    }
+
+    fn extension_module_variants(&self, _name: &str) -> Vec<ExtensionModule> {
+        Vec::new()
+    }
+
+    fn source_modules(&self) -> Result<Vec<SourceModule>> {
+        Ok(self
+            .py_modules
+            .iter()
+            .map(|(name, path)| SourceModule {
+                name: name.clone(),
+                source: DataLocation::Path(path.clone()),
+                is_package: is_package_from_path(&path),
+            })
+            .collect())
+    }
+
+    fn resource_datas(&self) -> Result<Vec<ResourceData>> {
+        let mut res = Vec::new();
+
+        for (package, inner) in self.resources.iter() {
+            for (name, path) in inner.iter() {
+                res.push(ResourceData {
+                    package: package.clone(),
+                    name: name.clone(),
+                    data: DataLocation::Path(path.clone()),
+                });
+            }
+        }
+
+        Ok(res)
+    }
+
+    fn ensure_pip(&self, logger: &slog::Logger, dest_dir: &Path) -> Result<PathBuf> {
+        if self.root.join("site-packages").join("pip").exists() {
+            return Ok(self.python_exe.clone());
+        }
+
+        create_pip_virtualenv(logger, &self.python_exe, &dest_dir.join("venv"))
+    }
+
+    fn resolve_distutils(
+        &self,
+        _logger: &slog::Logger,
+        _dest_dir: &Path,
+        _extra_python_paths: &[&Path],
+    ) -> Result<HashMap<String, String>> {
+        // PyPy always builds shared library extension modules. So there is
+        // nothing for a hacked distutils to do.
+        Ok(HashMap::new())
+    }
+
+    fn filter_compatible_python_resources(
+        &self,
+        logger: &slog::Logger,
+        resources: &[PythonResource],
+    ) -> Result<Vec<PythonResource>> {
+        Ok(resources
+            .iter()
+            .filter(|resource| match resource {
+                PythonResource::ExtensionModuleStaticallyLinked(_) => {
+                    warn!(
+                        logger,
+                        "ignoring extension module {} because PyPy can not link extension modules statically",
+                        resource.full_name()
+                    );
+                    false
+                }
+                _ => true,
+            })
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pypy_python_version() -> Result<()> {
+        assert_eq!(parse_pypy_python_version("pypy3.6-v7.3.0-linux64")?, "3.6");
+        assert_eq!(parse_pypy_python_version("pypy3.6-v7.3.0-win32")?, "3.6");
+        assert!(parse_pypy_python_version("python-3.7").is_err());
+        assert!(parse_pypy_python_version("pypy-v7.3.0").is_err());
+
+        Ok(())
+    }
+}
@@ -7,10 +7,12 @@ Defines primitives representing Python resources.
 */
 
 use {
-    super::bytecode::{python_source_encoding, BytecodeCompiler, CompileMode},
+    super::bytecode::{python_source_encoding, BytecodeCompiler, CompileMode, PycInvalidationMode},
     super::fsscan::{is_package_from_path, PythonFileResource},
     crate::app_packaging::resource::{FileContent, FileManifest},
+    crate::licensing::LicensedComponent,
     anyhow::{anyhow, Context, Error, Result},
+    lazy_static::lazy_static,
     std::collections::BTreeSet,
     std::convert::TryFrom,
     std::path::PathBuf,
@@ -96,11 +98,8 @@ pub fn resolve_path_for_module(
     module_path
 }
 
-/// Whether __file__ occurs in Python source code.
-pub fn has_dunder_file(source: &[u8]) -> Result<bool> {
-    // We can't just look for b"__file__ because the source file may be in
-    // encodings like UTF-16. So we need to decode to Unicode first then look for
-    // the code points.
+/// Decode Python source code to Unicode using its declared encoding.
+fn decode_python_source(source: &[u8]) -> String {
     let encoding = python_source_encoding(source);
 
     let encoder = match encoding_rs::Encoding::for_label(&encoding) {
@@ -110,7 +109,67 @@ pub fn has_dunder_file(source: &[u8]) -> Result<bool> {
 
     let (source, ..) = encoder.decode(source);
 
-    Ok(source.contains("__file__"))
+    source.into_owned()
+}
+
+/// Whether __file__ occurs in Python source code.
+pub fn has_dunder_file(source: &[u8]) -> Result<bool> {
+    // We can't just look for b"__file__ because the source file may be in
+    // encodings like UTF-16. So we need to decode to Unicode first then look for
+    // the code points.
+    Ok(decode_python_source(source).contains("__file__"))
+}
+
+lazy_static! {
+    static ref RE_DYNAMIC_IMPORT: regex::Regex = {
+        regex::Regex::new(
+            r#"\b(__import__|import_module|load_entry_point|iter_entry_points|entry_points)\s*\(\s*(?:[rRuU]?['"]([\w.]+)['"])?"#,
+        )
+        .unwrap()
+    };
+}
+
+/// A call in Python source code that may import modules dynamically.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DynamicImport {
+    /// Name of the function called. e.g. `__import__`.
+    pub function: String,
+    /// Name of the module imported, if known from a string literal.
+    pub module: Option<String>,
+}
+
+/// Find calls in Python source code that may import modules dynamically.
+///
+/// Calls to `__import__()`, `importlib.import_module()` and functions
+/// loading entry points are found. The imported module is only known when
+/// `__import__()` or `import_module()` are called with an absolute module
+/// name as a string literal.
+pub fn find_dynamic_imports(source: &[u8]) -> Result<Vec<DynamicImport>> {
+    let source = decode_python_source(source);
+
+    Ok(source
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(|line| {
+            RE_DYNAMIC_IMPORT
+                .captures_iter(line)
+                .map(|caps| {
+                    let function = caps[1].to_string();
+
+                    let module = match (function.as_str(), caps.get(2)) {
+                        ("__import__", Some(m)) | ("import_module", Some(m))
+                            if !m.as_str().starts_with('.') =>
+                        {
+                            Some(m.as_str().to_string())
+                        }
+                        _ => None,
+                    };
+
+                    DynamicImport { function, module }
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect())
 }
 
 /// Represents binary data that can be fetched from somewhere.
@@ -162,6 +221,8 @@ impl SourceModule {
             source: self.source.clone(),
             optimize_level,
             is_package: self.is_package,
+            source_stripped: false,
+            invalidation_mode: PycInvalidationMode::default(),
         }
     }
 
@@ -239,6 +300,10 @@ pub struct BytecodeModule {
     pub source: DataLocation,
     pub optimize_level: BytecodeOptimizationLevel,
     pub is_package: bool,
+    /// Whether the module's source is deliberately not distributed.
+    pub source_stripped: bool,
+    /// How `.pyc` files produced from this module are validated.
+    pub invalidation_mode: PycInvalidationMode,
 }
 
 impl BytecodeModule {
@@ -308,6 +373,44 @@ impl ResourceData {
     }
 }
 
+/// A file in the distribution metadata of an installed Python package.
+///
+/// e.g. the `METADATA` file in a `foo-1.0.dist-info` directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DistributionResource {
+    /// Name of the distribution, as it appears in the metadata directory name.
+    pub package: String,
+    /// Version of the distribution.
+    pub version: String,
+    /// Path of the file relative to the metadata directory.
+    pub name: String,
+    pub data: DataLocation,
+}
+
+impl DistributionResource {
+    pub fn full_name(&self) -> String {
+        format!("{}-{}:{}", self.package, self.version, self.name)
+    }
+
+    pub fn as_python_resource(&self) -> PythonResource {
+        PythonResource::DistributionResource(self.clone())
+    }
+
+    pub fn add_to_file_manifest(&self, manifest: &mut FileManifest, prefix: &str) -> Result<()> {
+        let mut dest_path = PathBuf::from(prefix);
+        dest_path.push(format!("{}-{}.dist-info", self.package, self.version));
+        dest_path.extend(self.name.split('/'));
+
+        manifest.add_file(
+            &dest_path,
+            &FileContent {
+                data: self.data.resolve()?,
+                executable: false,
+            },
+        )
+    }
+}
+
 /// Represents an extension module that can be packaged.
 ///
 /// This is like a light version of `ExtensionModule`.
@@ -406,6 +509,12 @@ pub enum PythonResource {
 
     /// An extension module that was built from source and can be statically linked.
     ExtensionModuleStaticallyLinked(ExtensionModuleData),
+
+    /// Licensing metadata of an installed Python package.
+    PackageLicense(LicensedComponent),
+
+    /// A file in the distribution metadata of an installed Python package.
+    DistributionResource(DistributionResource),
 }
 
 impl TryFrom<&PythonFileResource> for PythonResource {
@@ -532,6 +641,8 @@ impl PythonResource {
             PythonResource::Resource { package, name, .. } => format!("{}.{}", package, name),
             PythonResource::ExtensionModuleDynamicLibrary(em) => em.name.clone(),
             PythonResource::ExtensionModuleStaticallyLinked(em) => em.name.clone(),
+            PythonResource::PackageLicense(l) => l.name.clone(),
+            PythonResource::DistributionResource(r) => r.full_name(),
         }
     }
 
@@ -543,6 +654,8 @@ impl PythonResource {
             PythonResource::Resource { package, .. } => package,
             PythonResource::ExtensionModuleDynamicLibrary(em) => &em.name,
             PythonResource::ExtensionModuleStaticallyLinked(em) => &em.name,
+            PythonResource::PackageLicense(l) => &l.name,
+            PythonResource::DistributionResource(r) => &r.package,
         };
 
         for package in packages {
@@ -575,6 +688,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_find_dynamic_imports() -> Result<()> {
+        assert!(find_dynamic_imports(b"import foo\nfoo.bar()\n")?.is_empty());
+        assert!(find_dynamic_imports(b"# __import__('foo')\n")?.is_empty());
+
+        assert_eq!(
+            find_dynamic_imports(
+                b"m = __import__('foo.bar')\nimportlib.import_module(name)\nimport_module('.baz', __name__)\n"
+            )?,
+            vec![
+                DynamicImport {
+                    function: "__import__".to_string(),
+                    module: Some("foo.bar".to_string()),
+                },
+                DynamicImport {
+                    function: "import_module".to_string(),
+                    module: None,
+                },
+                DynamicImport {
+                    function: "import_module".to_string(),
+                    module: None,
+                },
+            ]
+        );
+
+        assert_eq!(
+            find_dynamic_imports(b"for ep in pkg_resources.iter_entry_points('foo'):\n")?,
+            vec![DynamicImport {
+                function: "iter_entry_points".to_string(),
+                module: None,
+            }]
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_resolve_path_for_module() {
         assert_eq!(
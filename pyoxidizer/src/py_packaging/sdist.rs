@@ -0,0 +1,303 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Building wheels of source distributions.
+
+Packages only available as source distributions (sdists) are built by pip
+while installing them, with whatever environment pip runs in. Instead, they
+are built into wheels ahead of time: each sdist is built by its PEP 517
+build backend in an isolated build environment, using the interpreter of
+the distribution the executable is built with. The built wheels are
+verified against the tags of that distribution, then installed in place of
+the sdists.
+
+Building packages with extension modules is slow, so built wheels are cached
+in a directory keyed by the SHA-256 of the sdist and the ABI of the
+distribution.
+*/
+
+use {
+    super::distribution::{PythonDistribution, PythonModuleSuffixes},
+    super::packaging_tool::run_logged_command,
+    super::wheel::{parse_wheel_filename_tags, CompatibleTags},
+    anyhow::{anyhow, Context, Result},
+    sha2::{Digest, Sha256},
+    slog::warn,
+    std::collections::HashMap,
+    std::hash::BuildHasher,
+    std::io::Read,
+    std::path::{Path, PathBuf},
+};
+
+/// File name suffixes of source distribution archives.
+const SDIST_SUFFIXES: &[&str] = &[".tar.gz", ".tar.bz2", ".tar.xz", ".tgz", ".tar", ".zip"];
+
+/// Whether a file is a source distribution archive.
+pub fn is_sdist(path: &Path) -> bool {
+    let name = match path.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return false,
+    };
+
+    SDIST_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+}
+
+/// Compute the hex encoded SHA-256 of a file.
+pub fn file_sha256(path: &Path) -> Result<String> {
+    let mut fh =
+        std::fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0; 32768];
+
+    loop {
+        let count = fh.read(&mut buffer)?;
+        if count == 0 {
+            break;
+        }
+        hasher.input(&buffer[..count]);
+    }
+
+    Ok(hex::encode(hasher.result()))
+}
+
+/// Obtain the key identifying the ABI of a distribution in the wheel cache.
+///
+/// The most specific extension module suffix identifies the Python version,
+/// ABI, and platform, e.g. `cpython-37m-x86_64-linux-gnu.so`. Returns `None`
+/// if the distribution has no such suffix.
+pub fn wheel_cache_key(suffixes: &PythonModuleSuffixes) -> Option<String> {
+    suffixes
+        .extension
+        .iter()
+        .max_by_key(|s| s.len())
+        .map(|s| s.trim_start_matches('.').to_string())
+        .filter(|s| s.contains('-'))
+}
+
+/// Find the wheel in a directory, if there is exactly one.
+pub fn find_single_wheel(path: &Path) -> Result<Option<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(None);
+    }
+
+    let mut wheels = Vec::new();
+    for entry in std::fs::read_dir(path).with_context(|| format!("reading {}", path.display()))? {
+        let path = entry?.path();
+
+        if path.extension().and_then(|e| e.to_str()) == Some("whl") {
+            wheels.push(path);
+        }
+    }
+
+    if wheels.len() == 1 {
+        Ok(wheels.pop())
+    } else {
+        Ok(None)
+    }
+}
+
+/// Builds wheels of source distributions.
+pub struct SdistBuilder<'a, S: BuildHasher> {
+    pub logger: &'a slog::Logger,
+
+    /// Distribution whose interpreter builds the wheels.
+    pub dist: &'a dyn PythonDistribution,
+
+    /// Python executable running pip, as returned by `ensure_pip()`.
+    pub pip_python_exe: &'a Path,
+
+    /// Directory caching built wheels.
+    ///
+    /// Wheels aren't cached if `None`.
+    pub cache_dir: Option<PathBuf>,
+
+    /// Arguments of pip commands finding build dependencies.
+    pub index_args: &'a [String],
+
+    /// Environment variables of build processes.
+    pub envs: &'a HashMap<String, String, S>,
+
+    pub verbose: bool,
+}
+
+impl<'a, S: BuildHasher> SdistBuilder<'a, S> {
+    /// Build a wheel of a source distribution into a directory.
+    ///
+    /// Returns the path of the wheel.
+    pub fn build_wheel(&self, sdist: &Path, dest_dir: &Path) -> Result<PathBuf> {
+        let sdist_name = sdist.file_name().unwrap().to_string_lossy().to_string();
+        let hash = file_sha256(sdist)?;
+
+        let cache_entry = match (
+            &self.cache_dir,
+            wheel_cache_key(&self.dist.python_module_suffixes()?),
+        ) {
+            (Some(cache_dir), Some(key)) => Some(cache_dir.join(key).join(&hash)),
+            _ => None,
+        };
+
+        if let Some(cache_entry) = &cache_entry {
+            if let Some(wheel) = find_single_wheel(cache_entry)? {
+                warn!(
+                    self.logger,
+                    "using cached wheel {} built from {}",
+                    wheel.display(),
+                    sdist_name
+                );
+                return copy_wheel(&wheel, dest_dir);
+            }
+        }
+
+        let temp_dir = crate::scratch::scratch_dir("pyoxidizer-sdist-build")?;
+        let wheel = self.run_build(sdist, &sdist_name, temp_dir.path())?;
+
+        if let Some(cache_entry) = &cache_entry {
+            // Write to a temporary name first so concurrent builds never
+            // see a partial wheel.
+            let wheel_name = wheel.file_name().unwrap();
+            let partial_path =
+                cache_entry.join(format!("{}.partial", wheel_name.to_string_lossy()));
+
+            std::fs::create_dir_all(cache_entry)
+                .with_context(|| format!("creating {}", cache_entry.display()))?;
+            std::fs::copy(&wheel, &partial_path)
+                .with_context(|| format!("copying {}", wheel.display()))?;
+            std::fs::rename(&partial_path, cache_entry.join(wheel_name))
+                .with_context(|| format!("renaming {}", partial_path.display()))?;
+        }
+
+        copy_wheel(&wheel, dest_dir)
+    }
+
+    /// Run the build backend of a source distribution.
+    ///
+    /// Returns the path of the built wheel, in `out_dir`.
+    fn run_build(&self, sdist: &Path, sdist_name: &str, out_dir: &Path) -> Result<PathBuf> {
+        let mut args = vec![
+            "-m".to_string(),
+            "pip".to_string(),
+            "--disable-pip-version-check".to_string(),
+        ];
+
+        if self.verbose {
+            args.push("--verbose".to_string());
+        }
+
+        args.extend(vec![
+            "wheel".to_string(),
+            "--use-pep517".to_string(),
+            "--no-deps".to_string(),
+            "--wheel-dir".to_string(),
+            out_dir.display().to_string(),
+        ]);
+        args.extend(self.index_args.iter().cloned());
+        args.push(sdist.display().to_string());
+
+        warn!(self.logger, "building wheel of {}", sdist_name);
+        run_logged_command(
+            self.logger,
+            std::process::Command::new(self.pip_python_exe)
+                .args(&args)
+                .envs(self.envs)
+                // Build dependencies are installed in an isolated environment
+                // rather than found in the user's site-packages.
+                .env_remove("PIP_NO_BUILD_ISOLATION")
+                .env("PYTHONNOUSERSITE", "1"),
+        )
+        .with_context(|| format!("building wheel of {}", sdist_name))?;
+
+        let wheel = find_single_wheel(out_dir)?
+            .ok_or_else(|| anyhow!("expected 1 wheel to be built from {}", sdist_name))?;
+        let wheel_name = wheel.file_name().unwrap().to_string_lossy().to_string();
+
+        if let Some(tags) = CompatibleTags::from_distribution(self.dist)? {
+            if !parse_wheel_filename_tags(&wheel_name)?
+                .iter()
+                .any(|t| tags.is_compatible(t))
+            {
+                return Err(anyhow!(
+                    "wheel {} built from {} is incompatible with the Python distribution; acceptable tags are: {}",
+                    wheel_name,
+                    sdist_name,
+                    tags
+                ));
+            }
+        }
+
+        Ok(wheel)
+    }
+}
+
+/// Copy a wheel into a directory, returning its new path.
+fn copy_wheel(wheel: &Path, dest_dir: &Path) -> Result<PathBuf> {
+    let dest_path = dest_dir.join(wheel.file_name().unwrap());
+
+    std::fs::create_dir_all(dest_dir)?;
+    std::fs::copy(wheel, &dest_path).with_context(|| format!("copying {}", wheel.display()))?;
+
+    Ok(dest_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_sdist() {
+        assert!(is_sdist(Path::new("/dl/foo-1.0.tar.gz")));
+        assert!(is_sdist(Path::new("foo-1.0.zip")));
+        assert!(!is_sdist(Path::new("foo-1.0-py3-none-any.whl")));
+        assert!(!is_sdist(Path::new("/")));
+    }
+
+    #[test]
+    fn test_wheel_cache_key() {
+        let suffixes = |extension: &[&str]| PythonModuleSuffixes {
+            source: vec![],
+            bytecode: vec![],
+            debug_bytecode: vec![],
+            optimized_bytecode: vec![],
+            extension: extension.iter().map(|s| s.to_string()).collect(),
+        };
+
+        assert_eq!(
+            wheel_cache_key(&suffixes(&[
+                ".cpython-37m-x86_64-linux-gnu.so",
+                ".abi3.so",
+                ".so"
+            ])),
+            Some("cpython-37m-x86_64-linux-gnu.so".to_string())
+        );
+        assert_eq!(
+            wheel_cache_key(&suffixes(&[".cp38-win_amd64.pyd", ".pyd"])),
+            Some("cp38-win_amd64.pyd".to_string())
+        );
+        assert_eq!(wheel_cache_key(&suffixes(&[".so"])), None);
+    }
+
+    #[test]
+    fn test_find_single_wheel() -> Result<()> {
+        let td = tempdir::TempDir::new("pyoxidizer-test")?;
+        let path = td.path();
+
+        assert_eq!(find_single_wheel(&path.join("missing"))?, None);
+
+        std::fs::write(path.join("foo-1.0-py3-none-any.whl.partial"), b"")?;
+        assert_eq!(find_single_wheel(path)?, None);
+
+        std::fs::write(path.join("foo-1.0-py3-none-any.whl"), b"")?;
+        assert_eq!(
+            find_single_wheel(path)?,
+            Some(path.join("foo-1.0-py3-none-any.whl"))
+        );
+
+        assert_eq!(
+            file_sha256(&path.join("foo-1.0-py3-none-any.whl"))?,
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+
+        Ok(())
+    }
+}
@@ -6,29 +6,35 @@
 
 use {
     super::binary::{
-        EmbeddedPythonBinaryData, EmbeddedResourcesBlobs, PythonBinaryBuilder, PythonLinkingInfo,
+        EmbeddedPythonBinaryData, EmbeddedResourcesBlobs, LibpythonLinkMode, PythonBinaryBuilder,
+        PythonLinkingInfo, WindowsCrtLinkage,
     },
-    super::bytecode::BytecodeCompiler,
-    super::config::{EmbeddedPythonConfig, RawAllocator},
+    super::bytecode::{BytecodeCompiler, SourceTransforms},
+    super::config::{EmbeddedPythonConfig, RawAllocator, RunMode},
     super::distribution::{
-        is_stdlib_test_package, resolve_python_distribution_from_location, DistributionExtractLock,
-        ExtensionModuleFilter, PythonDistribution, PythonDistributionLocation,
-        PythonModuleSuffixes,
+        create_pip_virtualenv, extract_distribution, is_stdlib_test_package,
+        resolve_python_distribution_from_location, DistributionCapabilities,
+        DistributionExtractLock, ExtensionModuleFilter, PythonDistribution,
+        PythonDistributionLocation, PythonImplementation, PythonModuleSuffixes,
     },
     super::distutils::prepare_hacked_distutils,
-    super::embedded_resource::EmbeddedPythonResourcesPrePackaged,
+    super::embedded_resource::{
+        ConflictPolicy, EmbeddedPythonResourcesPrePackaged, ResourceConflict,
+    },
     super::fsscan::{
-        find_python_resources, is_package_from_path, walk_tree_files, PythonFileResource,
+        find_python_resources, find_python_resources_in_paths, is_package_from_path,
+        walk_tree_files, PythonFileResource,
     },
     super::libpython::{derive_importlib, link_libpython, ImportlibBytecode},
     super::resource::{
-        BytecodeModule, BytecodeOptimizationLevel, DataLocation, ExtensionModuleData,
-        PythonResource, ResourceData, SourceModule,
+        BytecodeModule, BytecodeOptimizationLevel, DataLocation, DistributionResource,
+        ExtensionModuleData, PythonResource, ResourceData, SourceModule,
     },
     crate::app_packaging::resource::{FileContent, FileManifest},
-    crate::licensing::NON_GPL_LICENSES,
+    crate::licensing::{distribution_licensed_components, LicensedComponent, NON_GPL_LICENSES},
     anyhow::{anyhow, Context, Result},
     copy_dir::copy_dir,
+    python_packed_resources::data::FileEmulation,
     serde::{Deserialize, Serialize},
     slog::{info, warn},
     std::collections::{BTreeMap, BTreeSet, HashMap},
@@ -37,7 +43,6 @@ use {
     std::io::{BufRead, BufReader, Read},
     std::iter::FromIterator,
     std::path::{Path, PathBuf},
-    tempdir::TempDir,
 };
 
 // This needs to be kept in sync with *compiler.py
@@ -104,6 +109,40 @@ struct PythonBuildExtensionInfo {
     variant: String,
 }
 
+impl PythonBuildExtensionInfo {
+    /// Convert the instance to an `ExtensionModule`.
+    fn to_extension_module(&self, module: &str, python_path: &Path) -> ExtensionModule {
+        ExtensionModule {
+            module: module.to_string(),
+            init_fn: Some(self.init_fn.clone()),
+            builtin_default: self.in_core,
+            disableable: !self.in_core,
+            license_public_domain: self.license_public_domain,
+            license_paths: match self.license_paths {
+                Some(ref refs) => Some(refs.iter().map(|p| python_path.join(p)).collect()),
+                None => None,
+            },
+            licenses: self.licenses.clone(),
+            object_paths: self.objs.iter().map(|p| python_path.join(p)).collect(),
+            required: self.required,
+            static_library: match &self.static_lib {
+                Some(p) => Some(python_path.join(p)),
+                None => None,
+            },
+            shared_library: match &self.shared_lib {
+                Some(p) => Some(python_path.join(p)),
+                None => None,
+            },
+            links: self
+                .links
+                .iter()
+                .map(|link| link.to_library_depends(python_path))
+                .collect(),
+            variant: self.variant.clone(),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct PythonBuildCoreInfo {
     objs: Vec<String>,
@@ -137,16 +176,107 @@ struct PythonJsonMain {
 
 fn parse_python_json(path: &Path) -> Result<PythonJsonMain> {
     if !path.exists() {
-        panic!("PYTHON.json does not exist; are you using an up-to-date Python distribution that conforms with our requirements?");
+        return Err(anyhow!("{} does not exist; are you using an up-to-date Python distribution that conforms with our requirements? See https://github.com/indygreg/python-build-standalone for how to produce one", path.display()));
     }
 
     let buf = std::fs::read(path)?;
 
-    let v: PythonJsonMain = serde_json::from_slice(&buf)?;
+    let v: PythonJsonMain = serde_json::from_slice(&buf).with_context(|| {
+        format!(
+            "unable to parse {}; the distribution may have been produced by an incompatible version of python-build-standalone",
+            path.display()
+        )
+    })?;
 
     Ok(v)
 }
 
+/// Python `major.minor` versions whose ABI we know how to embed.
+pub const SUPPORTED_PYTHON_ABIS: &[&str] = &["3.7"];
+
+/// Verify the metadata of an extracted distribution describes something we can use.
+///
+/// Custom distributions are produced outside our control. So rather than
+/// fail obscurely later in the build, we verify up front that the
+/// distribution is CPython with a supported ABI and that the files
+/// referenced by its metadata actually exist.
+fn validate_python_json(pi: &PythonJsonMain, python_path: &Path) -> Result<()> {
+    if pi.python_flavor != "cpython" {
+        return Err(anyhow!(
+            "Python distribution has unsupported flavor {}; only CPython distributions can be embedded",
+            pi.python_flavor
+        ));
+    }
+
+    let abi = pi
+        .python_version
+        .split('.')
+        .take(2)
+        .collect::<Vec<_>>()
+        .join(".");
+    if !SUPPORTED_PYTHON_ABIS.contains(&abi.as_str()) {
+        return Err(anyhow!(
+            "Python distribution is version {} but only Python {} is supported; rebuild the distribution with a supported Python version",
+            pi.python_version,
+            SUPPORTED_PYTHON_ABIS.join(", ")
+        ));
+    }
+
+    match pi.link_mode.as_ref().map(|s| s.as_str()) {
+        None | Some("static") => {
+            if pi.build_info.core.objs.is_empty() && pi.build_info.core.static_lib.is_none() {
+                return Err(anyhow!("statically linked Python distribution does not define core object files or a static library in PYTHON.json; it cannot be linked into an executable"));
+            }
+        }
+        Some("shared") => {
+            if pi.build_info.core.shared_lib.is_none() {
+                return Err(anyhow!("Python distribution is dynamically linked but PYTHON.json does not define the libpython shared library"));
+            }
+        }
+        Some(v) => {
+            return Err(anyhow!(
+                "Python distribution has unsupported link mode {}; expected static or shared",
+                v
+            ))
+        }
+    }
+
+    let mut required = vec![
+        ("python executable", &pi.python_exe),
+        ("standard library", &pi.python_stdlib),
+        ("include directory", &pi.python_include),
+    ];
+
+    for obj in &pi.build_info.core.objs {
+        required.push(("core object file", obj));
+    }
+    if let Some(lib) = &pi.build_info.core.static_lib {
+        required.push(("libpython static library", lib));
+    }
+    if let Some(lib) = &pi.build_info.core.shared_lib {
+        required.push(("libpython shared library", lib));
+    }
+    for link in &pi.build_info.core.links {
+        if let Some(lib) = &link.path_static {
+            required.push(("core library dependency", lib));
+        }
+    }
+
+    for (description, rel_path) in required {
+        let path = python_path.join(rel_path);
+
+        if !path.exists() {
+            return Err(anyhow!(
+                "{} referenced by PYTHON.json does not exist: {}; the distribution archive is incomplete",
+                description,
+                path.display()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
 fn parse_python_json_from_distribution(dist_dir: &Path) -> Result<PythonJsonMain> {
     let python_json_path = dist_dir.join("python").join("PYTHON.json");
     parse_python_json(&python_json_path)
@@ -278,6 +408,40 @@ pub fn choose_variant<S: BuildHasher>(
     }
 }
 
+/// Select a named variant of an extension module.
+///
+/// `extensions` are all variants of the extension module. The first variant
+/// is selected if `variant` is `None`.
+pub fn select_variant(
+    name: &str,
+    extensions: &[ExtensionModule],
+    variant: Option<&str>,
+) -> Result<ExtensionModule> {
+    if extensions.is_empty() {
+        return Err(anyhow!("extension module {} does not exist", name));
+    }
+
+    match variant {
+        Some(variant) => extensions
+            .iter()
+            .find(|em| em.variant == variant)
+            .cloned()
+            .ok_or_else(|| {
+                anyhow!(
+                    "extension module {} has no variant {}; available variants: {}",
+                    name,
+                    variant,
+                    extensions
+                        .iter()
+                        .map(|em| em.variant.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            }),
+        None => Ok(extensions[0].clone()),
+    }
+}
+
 /// Describes a library dependency.
 ///
 /// If the license fields are Some value, then license metadata was
@@ -373,6 +537,113 @@ pub struct PythonDistributionMinimalInfo {
     pub py_module_count: usize,
 }
 
+/// Select the extension modules of a distribution matching a filter.
+///
+/// `extension_modules` are the variants of each extension module of the
+/// distribution.
+#[allow(clippy::if_same_then_else)]
+pub fn filter_distribution_extension_modules(
+    logger: &slog::Logger,
+    extension_modules: &BTreeMap<String, Vec<ExtensionModule>>,
+    filter: &ExtensionModuleFilter,
+    variants: Option<HashMap<String, String>>,
+) -> Result<Vec<ExtensionModule>> {
+    let mut res = Vec::new();
+
+    for (name, ext_variants) in extension_modules {
+        match filter {
+            ExtensionModuleFilter::Minimal => {
+                let ext_variants = ext_variants
+                    .iter()
+                    .filter_map(|em| {
+                        if em.builtin_default || em.required {
+                            Some(em.clone())
+                        } else {
+                            None
+                        }
+                    })
+                    .collect::<Vec<ExtensionModule>>();
+
+                if !ext_variants.is_empty() {
+                    res.push(choose_variant(&ext_variants, &variants));
+                }
+            }
+
+            ExtensionModuleFilter::All => {
+                res.push(choose_variant(&ext_variants, &variants));
+            }
+
+            ExtensionModuleFilter::NoLibraries => {
+                let ext_variants = ext_variants
+                    .iter()
+                    .filter_map(|em| {
+                        if em.links.is_empty() {
+                            Some(em.clone())
+                        } else {
+                            None
+                        }
+                    })
+                    .collect::<Vec<ExtensionModule>>();
+
+                if !ext_variants.is_empty() {
+                    res.push(choose_variant(&ext_variants, &variants));
+                }
+            }
+
+            ExtensionModuleFilter::NoGPL => {
+                let ext_variants = ext_variants
+                    .iter()
+                    .filter_map(|em| {
+                        if em.links.is_empty() {
+                            Some(em.clone())
+                        // Public domain is always allowed.
+                        } else if em.license_public_domain == Some(true) {
+                            Some(em.clone())
+                        // Use explicit license list if one is defined.
+                        } else if let Some(ref licenses) = em.licenses {
+                            // We filter through an allow list because it is safer. (No new GPL
+                            // licenses can slip through.)
+                            if licenses
+                                .iter()
+                                .all(|license| NON_GPL_LICENSES.contains(&license.as_str()))
+                            {
+                                Some(em.clone())
+                            } else {
+                                None
+                            }
+                        } else {
+                            // In lack of evidence that it isn't GPL, assume GPL.
+                            // TODO consider improving logic here, like allowing known system
+                            // and framework libraries to be used.
+                            warn!(logger, "unable to determine {} is not GPL; ignoring", &name);
+                            None
+                        }
+                    })
+                    .collect::<Vec<ExtensionModule>>();
+
+                if !ext_variants.is_empty() {
+                    res.push(choose_variant(&ext_variants, &variants));
+                }
+            }
+        }
+    }
+
+    // Do a sanity pass to ensure we got all builtin default or required extension modules.
+    let added: BTreeSet<String> = BTreeSet::from_iter(res.iter().map(|em| em.module.clone()));
+
+    for (name, ext_variants) in extension_modules {
+        let required = ext_variants
+            .iter()
+            .any(|em| em.builtin_default || em.required);
+
+        if required && !added.contains(name) {
+            return Err(anyhow!("required extension module {} missing", name));
+        }
+    }
+
+    Ok(res)
+}
+
 /// Describes how libpython is linked in a standalone distribution.
 #[derive(Clone, Debug, PartialEq)]
 pub enum StandaloneDistributionLinkMode {
@@ -380,6 +651,16 @@ pub enum StandaloneDistributionLinkMode {
     Dynamic,
 }
 
+impl StandaloneDistributionLinkMode {
+    fn from_python_json(pi: &PythonJsonMain) -> Result<Self> {
+        match pi.link_mode.as_ref().map(|v| v.as_str()) {
+            None | Some("static") => Ok(StandaloneDistributionLinkMode::Static),
+            Some("shared") => Ok(StandaloneDistributionLinkMode::Dynamic),
+            Some(v) => Err(anyhow!("unhandled link mode: {}", v)),
+        }
+    }
+}
+
 /// Represents a standalone Python distribution.
 ///
 /// This is a Python distributed produced by the `python-build-standalone`
@@ -464,9 +745,184 @@ pub struct StandaloneDistribution {
 
     /// Describes license info for things in this distribution.
     pub license_infos: BTreeMap<String, Vec<LicenseInfo>>,
+}
+
+/// Python resources of a standalone distribution, described by its files.
+///
+/// This is resolved without extracting the distribution or running its
+/// Python interpreter. So it is available in dry runs and for distributions
+/// of other platforms. The content of resources isn't read.
+#[derive(Clone, Debug)]
+pub struct StandaloneDistributionManifest {
+    /// Full version of Python.
+    pub python_version: String,
+
+    /// How libpython is linked.
+    pub link_mode: StandaloneDistributionLinkMode,
+
+    /// Variants of each extension module.
+    pub extension_modules: BTreeMap<String, Vec<ExtensionModule>>,
+
+    /// Source modules of the standard library and whether each is a package.
+    pub py_modules: BTreeMap<String, bool>,
+
+    /// Names of resources in the standard library, keyed by package.
+    pub resources: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl StandaloneDistributionManifest {
+    /// Resolve an instance from a .tar.zst archive, without extracting it.
+    pub fn from_tar_zst_file(path: &Path) -> Result<Self> {
+        let fh = std::fs::File::open(path)
+            .with_context(|| format!("unable to open {}", path.display()))?;
+        let mut tf = tar::Archive::new(zstd::stream::Decoder::new(BufReader::new(fh))?);
+
+        let mut python_json = None;
+        let mut paths = Vec::new();
+
+        for entry in tf
+            .entries()
+            .with_context(|| format!("reading {}", path.display()))?
+        {
+            let mut entry = entry?;
+
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+
+            let entry_path = entry.path()?.to_path_buf();
+            let entry_path = entry_path
+                .strip_prefix(".")
+                .unwrap_or_else(|_| entry_path.as_path())
+                .to_path_buf();
+
+            if entry_path == Path::new("python").join("PYTHON.json") {
+                let mut data = Vec::new();
+                entry.read_to_end(&mut data)?;
+                python_json = Some(data);
+            }
+
+            paths.push(entry_path);
+        }
+
+        let python_json = python_json
+            .ok_or_else(|| anyhow!("{} does not contain python/PYTHON.json", path.display()))?;
+
+        Self::from_files(Path::new(""), &python_json, paths)
+    }
+
+    /// Resolve an instance from an extracted distribution.
+    pub fn from_directory(dist_dir: &Path) -> Result<Self> {
+        let python_json_path = dist_dir.join("python").join("PYTHON.json");
+        let python_json = std::fs::read(&python_json_path)
+            .with_context(|| format!("reading {}", python_json_path.display()))?;
+
+        let paths = walk_tree_files(dist_dir)
+            .map(|entry| entry.path().to_path_buf())
+            .collect();
+
+        Self::from_files(dist_dir, &python_json, paths)
+    }
+
+    /// Resolve an instance from `PYTHON.json` and the files of a distribution.
+    ///
+    /// `paths` are the files of the distribution, under `dist_dir`.
+    fn from_files(dist_dir: &Path, python_json: &[u8], paths: Vec<PathBuf>) -> Result<Self> {
+        let pi: PythonJsonMain = serde_json::from_slice(python_json).context(
+            "unable to parse PYTHON.json; the distribution may have been produced by an incompatible version of python-build-standalone",
+        )?;
 
-    /// Path to copy of hacked dist to use for packaging rules venvs
-    pub venv_base: PathBuf,
+        let python_path = dist_dir.join("python");
+        let stdlib_path = python_path.join(&pi.python_stdlib);
+
+        let paths = paths
+            .into_iter()
+            .filter(|p| p.starts_with(&stdlib_path))
+            .collect();
+
+        // The interpreter isn't run to resolve the suffixes it recognizes. These
+        // are the suffixes of all supported platforms.
+        let suffixes = PythonModuleSuffixes {
+            source: vec![".py".to_string()],
+            bytecode: vec![".pyc".to_string()],
+            debug_bytecode: vec![],
+            optimized_bytecode: vec![],
+            extension: vec![".so".to_string(), ".pyd".to_string()],
+        };
+
+        let mut py_modules = BTreeMap::new();
+        let mut resources = BTreeMap::new();
+
+        for entry in find_python_resources_in_paths(&stdlib_path, paths, &suffixes) {
+            match entry {
+                PythonFileResource::Resource(resource) => {
+                    resources
+                        .entry(resource.package)
+                        .or_insert_with(BTreeSet::new)
+                        .insert(resource.stem);
+                }
+                PythonFileResource::Source {
+                    full_name, path, ..
+                } => {
+                    py_modules.insert(full_name, is_package_from_path(&path));
+                }
+                _ => {}
+            }
+        }
+
+        let extension_modules = pi
+            .build_info
+            .extensions
+            .iter()
+            .map(|(module, variants)| {
+                (
+                    module.clone(),
+                    variants
+                        .iter()
+                        .map(|entry| entry.to_extension_module(module, &python_path))
+                        .collect(),
+                )
+            })
+            .collect();
+
+        Ok(Self {
+            python_version: pi.python_version.clone(),
+            link_mode: StandaloneDistributionLinkMode::from_python_json(&pi)?,
+            extension_modules,
+            py_modules,
+            resources,
+        })
+    }
+}
+
+/// Unpack a tar archive of a standalone distribution to a directory.
+fn unpack_tar<R: Read>(source: R, dest_dir: &Path) -> Result<()> {
+    let mut tf = tar::Archive::new(source);
+
+    let absolute_path = std::fs::canonicalize(dest_dir)?;
+    tf.unpack(&absolute_path)
+        .with_context(|| "unable to extract tar archive")?;
+
+    // Ensure unpacked files are writable. We've had issues where we
+    // consume archives with read-only file permissions. When we later
+    // copy these files, we can run into trouble overwriting a read-only
+    // file.
+    let walk = walkdir::WalkDir::new(&absolute_path);
+    for entry in walk.into_iter() {
+        let entry = entry?;
+
+        let metadata = entry.metadata()?;
+        let mut permissions = metadata.permissions();
+
+        if permissions.readonly() {
+            permissions.set_readonly(false);
+            std::fs::set_permissions(entry.path(), permissions).with_context(|| {
+                format!("unable to mark {} as writable", entry.path().display())
+            })?;
+        }
+    }
+
+    Ok(())
 }
 
 impl StandaloneDistribution {
@@ -498,13 +954,14 @@ impl StandaloneDistribution {
             return Err(anyhow!("unhandled distribution format: {}", path.display()));
         }
 
-        let fh = std::fs::File::open(path)
-            .with_context(|| format!("unable to open {}", path.display()))?;
+        extract_distribution(logger, path, extract_dir, |dest_dir| {
+            let fh = std::fs::File::open(path)
+                .with_context(|| format!("unable to open {}", path.display()))?;
 
-        let reader = BufReader::new(fh);
-        warn!(logger, "reading data from Python distribution...");
+            unpack_tar(zstd::stream::Decoder::new(BufReader::new(fh))?, dest_dir)
+        })?;
 
-        Self::from_tar_zst(reader, &extract_dir)
+        Self::from_directory(extract_dir)
     }
 
     /// Extract and analyze a standalone distribution from a zstd compressed tar stream.
@@ -516,8 +973,6 @@ impl StandaloneDistribution {
 
     /// Extract and analyze a standalone distribution from a tar stream.
     pub fn from_tar<R: Read>(source: R, extract_dir: &Path) -> Result<Self> {
-        let mut tf = tar::Archive::new(source);
-
         {
             let _lock = DistributionExtractLock::new(extract_dir)?;
 
@@ -526,28 +981,7 @@ impl StandaloneDistribution {
             let test_path = extract_dir.join("python").join("PYTHON.json");
             if !test_path.exists() {
                 std::fs::create_dir_all(extract_dir)?;
-                let absolute_path = std::fs::canonicalize(extract_dir)?;
-                tf.unpack(&absolute_path)
-                    .with_context(|| "unable to extract tar archive")?;
-
-                // Ensure unpacked files are writable. We've had issues where we
-                // consume archives with read-only file permissions. When we later
-                // copy these files, we can run into trouble overwriting a read-only
-                // file.
-                let walk = walkdir::WalkDir::new(&absolute_path);
-                for entry in walk.into_iter() {
-                    let entry = entry?;
-
-                    let metadata = entry.metadata()?;
-                    let mut permissions = metadata.permissions();
-
-                    if permissions.readonly() {
-                        permissions.set_readonly(false);
-                        std::fs::set_permissions(entry.path(), permissions).with_context(|| {
-                            format!("unable to mark {} as writable", entry.path().display())
-                        })?;
-                    }
-                }
+                unpack_tar(source, extract_dir)?;
             }
         }
 
@@ -594,6 +1028,8 @@ impl StandaloneDistribution {
         }
 
         let pi = parse_python_json_from_distribution(dist_dir)?;
+        validate_python_json(&pi, &python_path)
+            .with_context(|| format!("validating Python distribution {}", dist_dir.display()))?;
 
         if let Some(ref python_license_path) = pi.license_path {
             let license_path = python_path.join(python_license_path);
@@ -634,17 +1070,12 @@ impl StandaloneDistribution {
             let mut ems: Vec<ExtensionModule> = Vec::new();
 
             for entry in variants.iter() {
-                let object_paths = entry.objs.iter().map(|p| python_path.join(p)).collect();
-                let mut links = Vec::new();
-
                 for link in &entry.links {
                     let depends = link.to_library_depends(&python_path);
 
                     if let Some(p) = &depends.static_path {
                         libraries.insert(depends.name.clone(), p.clone());
                     }
-
-                    links.push(depends);
                 }
 
                 if let Some(ref license_paths) = entry.license_paths {
@@ -670,30 +1101,7 @@ impl StandaloneDistribution {
                     license_infos.insert(module.clone(), licenses);
                 }
 
-                ems.push(ExtensionModule {
-                    module: module.clone(),
-                    init_fn: Some(entry.init_fn.clone()),
-                    builtin_default: entry.in_core,
-                    disableable: !entry.in_core,
-                    license_public_domain: entry.license_public_domain,
-                    license_paths: match entry.license_paths {
-                        Some(ref refs) => Some(refs.iter().map(|p| python_path.join(p)).collect()),
-                        None => None,
-                    },
-                    licenses: entry.licenses.clone(),
-                    object_paths,
-                    required: entry.required,
-                    static_library: match &entry.static_lib {
-                        Some(p) => Some(python_path.join(p)),
-                        None => None,
-                    },
-                    shared_library: match &entry.shared_lib {
-                        Some(p) => Some(python_path.join(p)),
-                        None => None,
-                    },
-                    links,
-                    variant: entry.variant.clone(),
-                });
+                ems.push(entry.to_extension_module(module, &python_path));
             }
 
             extension_modules.insert(module.clone(), ems);
@@ -737,21 +1145,12 @@ impl StandaloneDistribution {
             };
         }
 
-        let venv_base = dist_dir.parent().unwrap().join("hacked_base");
-
-        let (link_mode, libpython_shared_library) = if let Some(ref v) = pi.link_mode {
-            if v == "static" {
-                (StandaloneDistributionLinkMode::Static, None)
-            } else if v == "shared" {
-                (
-                    StandaloneDistributionLinkMode::Dynamic,
-                    Some(python_path.join(pi.build_info.core.shared_lib.unwrap())),
-                )
-            } else {
-                return Err(anyhow!("unhandled link mode: {}", v));
+        let link_mode = StandaloneDistributionLinkMode::from_python_json(&pi)?;
+        let libpython_shared_library = match link_mode {
+            StandaloneDistributionLinkMode::Static => None,
+            StandaloneDistributionLinkMode::Dynamic => {
+                Some(python_path.join(pi.build_info.core.shared_lib.unwrap()))
             }
-        } else {
-            (StandaloneDistributionLinkMode::Static, None)
         };
 
         Ok(Self {
@@ -782,7 +1181,6 @@ impl StandaloneDistribution {
             py_modules,
             resources,
             license_infos,
-            venv_base,
         })
     }
 
@@ -845,10 +1243,20 @@ impl StandaloneDistribution {
     }
 
     /// Duplicate the python distribution, with distutils hacked
-    pub fn create_hacked_base(&self, logger: &slog::Logger) -> PythonPaths {
-        let venv_base = self.venv_base.clone();
+    ///
+    /// The copy is created in `dest_dir`, named after the hash of the
+    /// distribution, as the extracted distribution is shared with other builds.
+    pub fn create_hacked_base(&self, logger: &slog::Logger, dest_dir: &Path) -> PythonPaths {
+        let dist_name = self.base_dir.file_name().unwrap().to_string_lossy();
+        let venv_base = dest_dir.join(format!(
+            "hacked_base.{}",
+            dist_name.trim_start_matches("python.")
+        ));
+
+        let venv_dir_s = venv_base.display().to_string();
 
-        let venv_dir_s = self.venv_base.display().to_string();
+        // Builds of the project could create the copy concurrently.
+        let _lock = DistributionExtractLock::new(&venv_base).unwrap();
 
         if !venv_base.exists() {
             let dist_prefix = self.base_dir.join("python").join("install");
@@ -873,11 +1281,13 @@ impl StandaloneDistribution {
     }
 
     /// Create a venv from the distribution at path.
+    ///
+    /// The hacked base the venv is created from is kept next to it.
     pub fn create_venv(&self, logger: &slog::Logger, path: &Path) -> PythonPaths {
         let venv_dir_s = path.display().to_string();
 
         // This will recreate it, if it was deleted
-        let python_paths = self.create_hacked_base(&logger);
+        let python_paths = self.create_hacked_base(&logger, path.parent().unwrap());
 
         if path.exists() {
             warn!(logger, "re-using {} {}", "venv", venv_dir_s);
@@ -937,6 +1347,18 @@ impl PythonDistribution for StandaloneDistribution {
         &self.python_exe
     }
 
+    fn capabilities(&self) -> DistributionCapabilities {
+        let is_static = self.link_mode == StandaloneDistributionLinkMode::Static;
+
+        DistributionCapabilities {
+            implementation: PythonImplementation::CPython,
+            embeddable: true,
+            static_libpython: is_static,
+            static_extension_modules: is_static,
+            dynamic_extension_modules: !is_static,
+        }
+    }
+
     fn python_major_minor_version(&self) -> String {
         self.version[0..3].to_string()
     }
@@ -1011,110 +1433,29 @@ impl PythonDistribution for StandaloneDistribution {
             importlib_bytecode,
             extension_module_filter: extension_module_filter.clone(),
             extension_module_variants: preferred_extension_module_variants.clone(),
+            windows_crt_linkage: WindowsCrtLinkage::default(),
+            libpython_link_mode: match self.link_mode {
+                StandaloneDistributionLinkMode::Static => LibpythonLinkMode::Static,
+                StandaloneDistributionLinkMode::Dynamic => LibpythonLinkMode::Dynamic,
+            },
+            package_licenses: Vec::new(),
         }))
     }
 
-    #[allow(clippy::if_same_then_else)]
     fn filter_extension_modules(
         &self,
         logger: &slog::Logger,
         filter: &ExtensionModuleFilter,
         variants: Option<HashMap<String, String>>,
     ) -> Result<Vec<ExtensionModule>> {
-        let mut res = Vec::new();
-
-        for (name, ext_variants) in &self.extension_modules {
-            match filter {
-                ExtensionModuleFilter::Minimal => {
-                    let ext_variants = ext_variants
-                        .iter()
-                        .filter_map(|em| {
-                            if em.builtin_default || em.required {
-                                Some(em.clone())
-                            } else {
-                                None
-                            }
-                        })
-                        .collect::<Vec<ExtensionModule>>();
-
-                    if !ext_variants.is_empty() {
-                        res.push(choose_variant(&ext_variants, &variants));
-                    }
-                }
-
-                ExtensionModuleFilter::All => {
-                    res.push(choose_variant(&ext_variants, &variants));
-                }
-
-                ExtensionModuleFilter::NoLibraries => {
-                    let ext_variants = ext_variants
-                        .iter()
-                        .filter_map(|em| {
-                            if em.links.is_empty() {
-                                Some(em.clone())
-                            } else {
-                                None
-                            }
-                        })
-                        .collect::<Vec<ExtensionModule>>();
-
-                    if !ext_variants.is_empty() {
-                        res.push(choose_variant(&ext_variants, &variants));
-                    }
-                }
-
-                ExtensionModuleFilter::NoGPL => {
-                    let ext_variants = ext_variants
-                        .iter()
-                        .filter_map(|em| {
-                            if em.links.is_empty() {
-                                Some(em.clone())
-                            // Public domain is always allowed.
-                            } else if em.license_public_domain == Some(true) {
-                                Some(em.clone())
-                            // Use explicit license list if one is defined.
-                            } else if let Some(ref licenses) = em.licenses {
-                                // We filter through an allow list because it is safer. (No new GPL
-                                // licenses can slip through.)
-                                if licenses
-                                    .iter()
-                                    .all(|license| NON_GPL_LICENSES.contains(&license.as_str()))
-                                {
-                                    Some(em.clone())
-                                } else {
-                                    None
-                                }
-                            } else {
-                                // In lack of evidence that it isn't GPL, assume GPL.
-                                // TODO consider improving logic here, like allowing known system
-                                // and framework libraries to be used.
-                                warn!(logger, "unable to determine {} is not GPL; ignoring", &name);
-                                None
-                            }
-                        })
-                        .collect::<Vec<ExtensionModule>>();
-
-                    if !ext_variants.is_empty() {
-                        res.push(choose_variant(&ext_variants, &variants));
-                    }
-                }
-            }
-        }
-
-        // Do a sanity pass to ensure we got all builtin default or required extension modules.
-        let added: BTreeSet<String> = BTreeSet::from_iter(res.iter().map(|em| em.module.clone()));
-
-        for (name, ext_variants) in &self.extension_modules {
-            let required = ext_variants
-                .iter()
-                .any(|em| em.builtin_default || em.required);
-
-            if required && !added.contains(name) {
-                return Err(anyhow!("required extension module {} missing", name));
-            }
-        }
+        filter_distribution_extension_modules(logger, &self.extension_modules, filter, variants)
+    }
 
-        Ok(res)
+    fn extension_module_variants(&self, name: &str) -> Vec<ExtensionModule> {
+        self.extension_modules
+            .get(name)
+            .cloned()
+            .unwrap_or_else(Vec::new)
     }
 
     fn source_modules(&self) -> Result<Vec<SourceModule>> {
@@ -1148,19 +1489,15 @@ impl PythonDistribution for StandaloneDistribution {
         Ok(res)
     }
 
-    /// Ensure pip is available to run in the distribution.
-    fn ensure_pip(&self, logger: &slog::Logger) -> Result<PathBuf> {
+    fn ensure_pip(&self, logger: &slog::Logger, dest_dir: &Path) -> Result<PathBuf> {
         let dist_prefix = self.base_dir.join("python").join("install");
         let python_paths = resolve_python_paths(&dist_prefix, &self.version);
 
-        let pip_path = python_paths.bin_dir.join(PIP_EXE_BASENAME);
-
-        if !pip_path.exists() {
-            warn!(logger, "{} doesnt exist", pip_path.display().to_string());
-            invoke_python(&python_paths, &logger, &["-m", "ensurepip"]);
+        if python_paths.bin_dir.join(PIP_EXE_BASENAME).exists() {
+            return Ok(self.python_exe.clone());
         }
 
-        Ok(pip_path)
+        create_pip_virtualenv(logger, &self.python_exe, &dest_dir.join("venv"))
     }
 
     fn resolve_distutils(
@@ -1195,7 +1532,7 @@ impl PythonDistribution for StandaloneDistribution {
                 // TODO we should be able to support dynamically linked extension
                 // modules outside of Windows.
                 PythonResource::ExtensionModuleDynamicLibrary { .. } => {
-                    if self.link_mode == StandaloneDistributionLinkMode::Static {
+                    if !self.capabilities().dynamic_extension_modules {
                         warn!(
                             logger,
                             "ignoring extension module {} because not compatible with statically linked distribution",
@@ -1209,14 +1546,15 @@ impl PythonDistribution for StandaloneDistribution {
 
                 // Only look at the raw object files if the distribution produces
                 // them.
-                // TODO have PythonDistribution expose API to determine this.
                 PythonResource::ExtensionModuleStaticallyLinked(_) =>
-                    self.link_mode == StandaloneDistributionLinkMode::Static,
+                    self.capabilities().static_extension_modules,
 
                 PythonResource::ModuleSource { .. } => true,
                 PythonResource::ModuleBytecodeRequest { .. } => true,
                 PythonResource::ModuleBytecode { .. } => true,
                 PythonResource::Resource { .. } => true,
+                PythonResource::PackageLicense(_) => true,
+                PythonResource::DistributionResource(_) => true,
             })
             .cloned()
             .collect())
@@ -1258,6 +1596,15 @@ pub struct StandalonePythonExecutableBuilder {
 
     /// Preferred extension module variants.
     extension_module_variants: Option<HashMap<String, String>>,
+
+    /// How to link against the C runtime when targeting Windows.
+    windows_crt_linkage: WindowsCrtLinkage,
+
+    /// How to link against `libpython`.
+    libpython_link_mode: LibpythonLinkMode,
+
+    /// Licensing metadata of embedded Python packages.
+    package_licenses: Vec<LicensedComponent>,
 }
 
 impl StandalonePythonExecutableBuilder {
@@ -1286,9 +1633,12 @@ impl StandalonePythonExecutableBuilder {
         let libpyembeddedconfig_data: Option<Vec<u8>>;
         let libpyembeddedconfig_filename: Option<PathBuf>;
 
-        match self.distribution.link_mode {
-            StandaloneDistributionLinkMode::Static => {
-                let temp_dir = TempDir::new("pyoxidizer-build-exe")?;
+        match (self.libpython_link_mode, &self.distribution.link_mode) {
+            (LibpythonLinkMode::Static, StandaloneDistributionLinkMode::Static) => {
+                self.windows_crt_linkage
+                    .ensure_compatible(&self.target_triple, self.libpython_link_mode)?;
+
+                let temp_dir = crate::scratch::scratch_dir("pyoxidizer-build-exe")?;
                 let temp_dir_path = temp_dir.path();
 
                 warn!(
@@ -1303,6 +1653,7 @@ impl StandalonePythonExecutableBuilder {
                     &self.host_triple,
                     &self.target_triple,
                     opt_level,
+                    self.windows_crt_linkage,
                 )?;
 
                 libpythonxy_filename =
@@ -1318,16 +1669,28 @@ impl StandalonePythonExecutableBuilder {
                     Some(std::fs::read(&library_info.libpyembeddedconfig_path)?);
             }
 
-            StandaloneDistributionLinkMode::Dynamic => {
+            (LibpythonLinkMode::Dynamic, StandaloneDistributionLinkMode::Dynamic) => {
                 libpythonxy_filename = PathBuf::from("pythonXY.lib");
                 libpythonxy_data = Vec::new();
                 libpython_filename = self.distribution.libpython_shared_library.clone();
                 libpyembeddedconfig_filename = None;
                 libpyembeddedconfig_data = None;
             }
+
+            (wanted, _) => {
+                return Err(anyhow!(
+                    "Python distribution only supports {} libpython linking but {} linking was requested",
+                    match self.distribution.link_mode {
+                        StandaloneDistributionLinkMode::Static => "static",
+                        StandaloneDistributionLinkMode::Dynamic => "dynamic",
+                    },
+                    wanted.as_str()
+                ));
+            }
         }
 
         Ok(PythonLinkingInfo {
+            link_mode: self.libpython_link_mode,
             libpythonxy_filename,
             libpythonxy_data,
             libpython_filename,
@@ -1351,6 +1714,34 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         &self.python_exe
     }
 
+    fn python_version(&self) -> Option<String> {
+        Some(self.distribution.version.clone())
+    }
+
+    fn config(&self) -> &EmbeddedPythonConfig {
+        &self.config
+    }
+
+    fn set_run_mode(&mut self, run_mode: RunMode) {
+        self.config.run_mode = run_mode;
+    }
+
+    fn windows_crt_linkage(&self) -> WindowsCrtLinkage {
+        self.windows_crt_linkage
+    }
+
+    fn set_windows_crt_linkage(&mut self, linkage: WindowsCrtLinkage) {
+        self.windows_crt_linkage = linkage;
+    }
+
+    fn libpython_link_mode(&self) -> LibpythonLinkMode {
+        self.libpython_link_mode
+    }
+
+    fn set_libpython_link_mode(&mut self, mode: LibpythonLinkMode) {
+        self.libpython_link_mode = mode;
+    }
+
     fn source_modules(&self) -> BTreeMap<String, SourceModule> {
         self.resources.get_source_modules()
     }
@@ -1359,6 +1750,10 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         self.resources.get_bytecode_modules()
     }
 
+    fn bytecode_optimize_levels(&self) -> BTreeMap<String, Vec<BytecodeOptimizationLevel>> {
+        self.resources.get_bytecode_optimize_levels()
+    }
+
     fn resources(&self) -> BTreeMap<String, BTreeMap<String, Vec<u8>>> {
         self.resources.get_resources()
     }
@@ -1383,6 +1778,30 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         self.resources.add_resource(resource);
     }
 
+    fn add_distribution_resource(&mut self, resource: &DistributionResource) {
+        self.resources.add_distribution_resource(resource);
+    }
+
+    fn set_file_emulation(&mut self, name: &str, policy: FileEmulation) {
+        self.resources.set_file_emulation(name, policy);
+    }
+
+    fn set_source_transforms(&mut self, package: &str, transforms: SourceTransforms) {
+        self.resources.set_source_transforms(package, transforms);
+    }
+
+    fn resource_conflict_policy(&self) -> ConflictPolicy {
+        self.resources.conflict_policy()
+    }
+
+    fn set_resource_conflict_policy(&mut self, policy: ConflictPolicy) {
+        self.resources.set_conflict_policy(policy);
+    }
+
+    fn take_resource_conflicts(&mut self) -> Vec<ResourceConflict> {
+        self.resources.take_conflicts()
+    }
+
     fn add_extension_module(&mut self, extension_module: &ExtensionModule) {
         self.resources.add_extension_module(extension_module);
     }
@@ -1403,6 +1822,44 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
         self.resources.add_extension_module_data(extension_module);
     }
 
+    fn add_package_license(&mut self, license: &LicensedComponent) {
+        self.package_licenses.retain(|l| l.name != license.name);
+        self.package_licenses.push(license.clone());
+    }
+
+    fn licensed_components(&self) -> Vec<LicensedComponent> {
+        let extension_modules = self.extension_modules().keys().cloned().collect::<Vec<_>>();
+
+        let mut res = distribution_licensed_components(
+            &self.distribution.license_infos,
+            &self.distribution.version,
+            &extension_modules,
+        );
+        res.extend(self.package_licenses.iter().cloned());
+
+        res
+    }
+
+    fn package_licenses(&self) -> Vec<LicensedComponent> {
+        self.package_licenses.clone()
+    }
+
+    fn linked_libraries(&self) -> BTreeSet<String> {
+        let mut res = BTreeSet::new();
+
+        for depends in &self.distribution.links_core {
+            res.insert(depends.name.clone());
+        }
+
+        for em in self.extension_modules().values() {
+            for depends in &em.links {
+                res.insert(depends.name.clone());
+            }
+        }
+
+        res
+    }
+
     fn filter_resources_from_files(
         &mut self,
         logger: &slog::Logger,
@@ -1413,6 +1870,25 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
             .filter_from_files(logger, files, glob_patterns)
     }
 
+    fn import_packages_from_filesystem(&mut self, packages: &[String], sys_paths: &[String]) {
+        self.resources.remove_packages(packages);
+        self.config.filesystem_importer = true;
+
+        let mut paths = sys_paths.to_vec();
+        paths.append(&mut self.config.sys_paths);
+        self.config.sys_paths = paths;
+    }
+
+    fn remove_modules(&mut self, names: &[String]) {
+        self.resources.remove_modules(names);
+    }
+
+    fn restrict_imports(&mut self, allow: Option<&[String]>, deny: &[String]) {
+        self.resources.restrict_imports(allow, deny);
+        self.config.import_allow = allow.map(|modules| modules.to_vec());
+        self.config.import_deny = deny.to_vec();
+    }
+
     fn requires_jemalloc(&self) -> bool {
         self.config.raw_allocator == RawAllocator::Jemalloc
     }
@@ -1424,8 +1900,17 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
     ) -> Result<EmbeddedPythonBinaryData> {
         let linking_info = self.resolve_python_linking_info(logger, opt_level)?;
 
-        let resources =
-            EmbeddedResourcesBlobs::try_from(self.resources.package(logger, &self.python_exe)?)?;
+        let mut resources = self.resources.package(logger, &self.python_exe)?;
+
+        if let Some(key) = self.config.resolve_resources_key()? {
+            warn!(
+                logger,
+                "encrypting embedded Python module source and bytecode"
+            );
+            resources.encrypt(&key);
+        }
+
+        let resources = EmbeddedResourcesBlobs::try_from(resources)?;
         warn!(
             logger,
             "deriving custom importlib modules to support in-memory importing"
@@ -1437,6 +1922,8 @@ impl PythonBinaryBuilder for StandalonePythonExecutableBuilder {
             linking_info,
             importlib,
             resources,
+            python_version: self.python_version(),
+            name: self.exe_name.clone(),
             host: self.host_triple.clone(),
             target: self.target_triple.clone(),
         })
@@ -1518,6 +2005,9 @@ pub mod tests {
             importlib_bytecode,
             extension_module_filter: ExtensionModuleFilter::Minimal,
             extension_module_variants: None,
+            windows_crt_linkage: WindowsCrtLinkage::default(),
+            libpython_link_mode: LibpythonLinkMode::Static,
+            package_licenses: Vec::new(),
         })
     }
 
@@ -1536,4 +2026,68 @@ pub mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_linking_info_link_modes() -> Result<()> {
+        let logger = get_logger()?;
+
+        let mut exe = get_standalone_executable_builder(&logger)?;
+        exe.set_libpython_link_mode(LibpythonLinkMode::Dynamic);
+        let err = exe.resolve_python_linking_info(&logger, "0").unwrap_err();
+        assert!(err.to_string().contains("dynamic linking was requested"));
+
+        let mut exe = get_standalone_executable_builder(&logger)?;
+        exe.target_triple = "x86_64-pc-windows-msvc".to_string();
+        exe.set_windows_crt_linkage(WindowsCrtLinkage::Static);
+        let err = exe.resolve_python_linking_info(&logger, "0").unwrap_err();
+        assert!(err.to_string().contains("/MT"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_python_json() -> Result<()> {
+        let distribution = get_default_distribution()?;
+        let python_path = distribution.base_dir.join("python");
+
+        let mut pi = parse_python_json_from_distribution(&distribution.base_dir)?;
+        validate_python_json(&pi, &python_path)?;
+
+        pi.python_version = "2.7.17".to_string();
+        let err = validate_python_json(&pi, &python_path).unwrap_err();
+        assert!(err.to_string().contains("only Python 3.7 is supported"));
+
+        let mut pi = parse_python_json_from_distribution(&distribution.base_dir)?;
+        pi.python_stdlib = "does-not-exist".to_string();
+        let err = validate_python_json(&pi, &python_path).unwrap_err();
+        assert!(err.to_string().contains("standard library"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ensure_pip() -> Result<()> {
+        let logger = get_logger()?;
+        let distribution = get_default_distribution()?;
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+
+        let python_exe = distribution.ensure_pip(&logger, temp_dir.path())?;
+
+        let status = std::process::Command::new(&python_exe)
+            .args(&["-m", "pip", "--version"])
+            .status()?;
+        assert!(status.success());
+
+        // pip isn't installed in the shared distribution.
+        if python_exe != distribution.python_exe {
+            let python_paths = resolve_python_paths(
+                &distribution.base_dir.join("python").join("install"),
+                &distribution.version,
+            );
+            assert!(!python_paths.bin_dir.join(PIP_EXE_BASENAME).exists());
+            assert!(python_exe.starts_with(temp_dir.path()));
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,411 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Compatibility of wheels with Python distributions.
+
+Wheels are tagged with the Python implementations and versions, ABIs, and
+platforms they support (PEP 425). Installed wheels record their tags in the
+`WHEEL` file of their `.dist-info` directory. A wheel built for another
+Python version or platform installs fine but its extension modules crash or
+fail to load on the target machine, so the tags of installed wheels are
+verified against those of the distribution.
+
+Tags supported by a CPython distribution are derived from its version and
+the suffix of its extension modules, e.g. `.cpython-37m-x86_64-linux-gnu.so`.
+*/
+
+use {
+    super::distribution::{PythonDistribution, PythonImplementation, PythonModuleSuffixes},
+    anyhow::{anyhow, Context, Result},
+    std::fmt::{Display, Formatter},
+    std::path::Path,
+};
+
+/// A PEP 425 compatibility tag.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WheelTag {
+    /// Python tag, e.g. `cp37` or `py3`.
+    pub python: String,
+
+    /// ABI tag, e.g. `cp37m`, `abi3`, or `none`.
+    pub abi: String,
+
+    /// Platform tag, e.g. `manylinux2014_x86_64` or `any`.
+    pub platform: String,
+}
+
+impl Display for WheelTag {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}-{}", self.python, self.abi, self.platform)
+    }
+}
+
+/// Parse a tag, expanding compressed tag sets such as `py2.py3-none-any`.
+pub fn parse_wheel_tag(value: &str) -> Result<Vec<WheelTag>> {
+    let parts = value.trim().split('-').collect::<Vec<_>>();
+    if parts.len() != 3 || parts.iter().any(|p| p.is_empty()) {
+        return Err(anyhow!("{} is not a valid wheel tag", value));
+    }
+
+    let mut tags = Vec::new();
+
+    for python in parts[0].split('.') {
+        for abi in parts[1].split('.') {
+            for platform in parts[2].split('.') {
+                tags.push(WheelTag {
+                    python: python.to_string(),
+                    abi: abi.to_string(),
+                    platform: platform.to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(tags)
+}
+
+/// Parse the tags of a wheel from its file name.
+///
+/// e.g. `foo-1.0-cp37-cp37m-manylinux1_x86_64.whl`.
+pub fn parse_wheel_filename_tags(filename: &str) -> Result<Vec<WheelTag>> {
+    if !filename.ends_with(".whl") {
+        return Err(anyhow!("{} is not a wheel", filename));
+    }
+
+    let parts = filename[..filename.len() - 4]
+        .split('-')
+        .collect::<Vec<_>>();
+    if parts.len() < 5 {
+        return Err(anyhow!("{} is not a valid wheel file name", filename));
+    }
+
+    parse_wheel_tag(&parts[parts.len() - 3..].join("-"))
+}
+
+/// Parse the tags recorded in the content of a `WHEEL` metadata file.
+pub fn parse_wheel_metadata_tags(data: &str) -> Result<Vec<WheelTag>> {
+    let mut tags = Vec::new();
+
+    for line in data.lines() {
+        if line.starts_with("Tag:") {
+            tags.extend(parse_wheel_tag(&line[4..])?);
+        }
+    }
+
+    Ok(tags)
+}
+
+/// Tags of wheels compatible with a Python distribution.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompatibleTags {
+    /// Acceptable Python tags.
+    pub pythons: Vec<String>,
+
+    /// Acceptable ABI tags.
+    pub abis: Vec<String>,
+
+    /// Glob patterns of acceptable platform tags.
+    pub platforms: Vec<String>,
+}
+
+/// Obtain the platform tags of the platform an extension module suffix is for.
+fn platforms_from_extension_suffix(suffix: &str) -> Option<Vec<String>> {
+    if suffix.ends_with(".pyd") {
+        let platform = if suffix.contains("win_amd64") {
+            "win_amd64"
+        } else if suffix.contains("win_arm64") {
+            "win_arm64"
+        } else {
+            "win32"
+        };
+
+        return Some(vec![platform.to_string(), "any".to_string()]);
+    }
+
+    // e.g. .cpython-37m-x86_64-linux-gnu.so or .cpython-38-darwin.so
+    let parts = suffix
+        .trim_end_matches(".so")
+        .split('-')
+        .collect::<Vec<_>>();
+
+    if parts.last() == Some(&"darwin") {
+        let arch = match std::env::consts::ARCH {
+            "aarch64" => "arm64",
+            arch => arch,
+        };
+
+        return Some(vec![
+            format!("macosx_*_{}", arch),
+            "macosx_*_universal2".to_string(),
+            if arch == "x86_64" {
+                "macosx_*_intel".to_string()
+            } else {
+                "macosx_*_universal".to_string()
+            },
+            "any".to_string(),
+        ]);
+    }
+
+    if parts.len() < 4 || parts[parts.len() - 2] != "linux" {
+        return None;
+    }
+
+    let arch = match parts[parts.len() - 3] {
+        "i386" | "i486" | "i586" => "i686",
+        arch => arch,
+    };
+
+    let manylinux = if parts[parts.len() - 1].starts_with("musl") {
+        "musllinux"
+    } else {
+        "manylinux"
+    };
+
+    Some(vec![
+        format!("{}*_{}", manylinux, arch),
+        format!("linux_{}", arch),
+        "any".to_string(),
+    ])
+}
+
+impl CompatibleTags {
+    /// Resolve the tags compatible with a CPython version.
+    ///
+    /// `major_minor` is the `X.Y` version of Python. The platform is derived
+    /// from extension module suffixes. Returns `None` if the platform isn't
+    /// recognized.
+    pub fn from_cpython(major_minor: &str, suffixes: &PythonModuleSuffixes) -> Option<Self> {
+        let mut parts = major_minor.split('.');
+        let major = parts.next()?;
+        let minor = parts.next()?.parse::<u32>().ok()?;
+
+        let platforms = suffixes
+            .extension
+            .iter()
+            .filter_map(|s| platforms_from_extension_suffix(s))
+            .next()?;
+
+        // Python 3.7 and older have an `m` ABI flag, as they use pymalloc.
+        let abi = if major == "3" && minor <= 7 {
+            format!("cp{}{}m", major, minor)
+        } else {
+            format!("cp{}{}", major, minor)
+        };
+
+        let mut pythons = Vec::new();
+        for m in (0..=minor).rev() {
+            pythons.push(format!("cp{}{}", major, m));
+        }
+        for m in (0..=minor).rev() {
+            pythons.push(format!("py{}{}", major, m));
+        }
+        pythons.push(format!("py{}", major));
+
+        Some(Self {
+            pythons,
+            abis: vec![abi, "abi3".to_string(), "none".to_string()],
+            platforms,
+        })
+    }
+
+    /// Resolve the tags compatible with a Python distribution.
+    ///
+    /// Returns `None` if they can't be determined, in which case wheels
+    /// can't be verified.
+    pub fn from_distribution(dist: &dyn PythonDistribution) -> Result<Option<Self>> {
+        match dist.capabilities().implementation {
+            PythonImplementation::CPython => Ok(Self::from_cpython(
+                &dist.python_major_minor_version(),
+                &dist.python_module_suffixes()?,
+            )),
+            PythonImplementation::PyPy => Ok(None),
+        }
+    }
+
+    /// Whether a wheel with a tag is compatible.
+    pub fn is_compatible(&self, tag: &WheelTag) -> bool {
+        self.pythons.contains(&tag.python)
+            && self.abis.contains(&tag.abi)
+            && self.platforms.iter().any(|p| match glob::Pattern::new(p) {
+                Ok(pattern) => pattern.matches(&tag.platform),
+                Err(_) => false,
+            })
+    }
+}
+
+impl Display for CompatibleTags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Python tags {}; ABI tags {}; platform tags {}",
+            self.pythons.join(", "),
+            self.abis.join(", "),
+            self.platforms.join(", ")
+        )
+    }
+}
+
+/// Verify wheels installed in a directory are compatible.
+///
+/// `path` is a directory like `site-packages` holding `.dist-info`
+/// directories. Packages not installed from wheels have no recorded tags and
+/// are ignored.
+pub fn verify_installed_wheels(path: &Path, tags: &CompatibleTags) -> Result<()> {
+    let mut entries = std::fs::read_dir(path)
+        .with_context(|| format!("reading {}", path.display()))?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    let mut incompatible = Vec::new();
+
+    for entry in entries {
+        let wheel_path = entry.join("WHEEL");
+
+        if !entry.to_string_lossy().ends_with(".dist-info") || !wheel_path.is_file() {
+            continue;
+        }
+
+        let data = std::fs::read_to_string(&wheel_path)
+            .with_context(|| format!("reading {}", wheel_path.display()))?;
+        let wheel_tags = parse_wheel_metadata_tags(&data)
+            .with_context(|| format!("parsing {}", wheel_path.display()))?;
+
+        if !wheel_tags.is_empty() && !wheel_tags.iter().any(|t| tags.is_compatible(t)) {
+            incompatible.push(format!(
+                "{} is built for {}",
+                entry.file_name().unwrap().to_string_lossy(),
+                wheel_tags
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+    }
+
+    if incompatible.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "wheels incompatible with the Python distribution are installed:\n{}\nacceptable tags are: {}",
+            incompatible.join("\n"),
+            tags
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suffixes(extension: &[&str]) -> PythonModuleSuffixes {
+        PythonModuleSuffixes {
+            source: vec![],
+            bytecode: vec![],
+            debug_bytecode: vec![],
+            optimized_bytecode: vec![],
+            extension: extension.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse_wheel_tag() -> Result<()> {
+        assert_eq!(
+            parse_wheel_tag("py2.py3-none-any")?,
+            vec![
+                WheelTag {
+                    python: "py2".to_string(),
+                    abi: "none".to_string(),
+                    platform: "any".to_string(),
+                },
+                WheelTag {
+                    python: "py3".to_string(),
+                    abi: "none".to_string(),
+                    platform: "any".to_string(),
+                },
+            ]
+        );
+        assert!(parse_wheel_tag("py3-none").is_err());
+
+        assert_eq!(
+            parse_wheel_filename_tags("foo_bar-1.0-1-cp38-abi3-macosx_10_9_x86_64.whl")?,
+            parse_wheel_tag("cp38-abi3-macosx_10_9_x86_64")?
+        );
+        assert!(parse_wheel_filename_tags("foo-1.0.tar.gz").is_err());
+
+        assert_eq!(
+            parse_wheel_metadata_tags(
+                "Wheel-Version: 1.0\nRoot-Is-Purelib: false\nTag: cp37-cp37m-manylinux1_x86_64\n"
+            )?
+            .iter()
+            .map(|t| t.to_string())
+            .collect::<Vec<_>>(),
+            vec!["cp37-cp37m-manylinux1_x86_64"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_compatible() -> Result<()> {
+        let tags = CompatibleTags::from_cpython(
+            "3.7",
+            &suffixes(&[".cpython-37m-x86_64-linux-gnu.so", ".abi3.so", ".so"]),
+        )
+        .unwrap();
+
+        let compatible = |tag: &str| tags.is_compatible(&parse_wheel_tag(tag).unwrap()[0]);
+
+        assert!(compatible("py3-none-any"));
+        assert!(compatible("cp37-cp37m-manylinux1_x86_64"));
+        assert!(compatible("cp37-cp37m-manylinux_2_17_x86_64"));
+        assert!(compatible("cp36-abi3-manylinux2014_x86_64"));
+        assert!(compatible("cp37-cp37m-linux_x86_64"));
+        assert!(!compatible("cp38-cp38-manylinux1_x86_64"));
+        assert!(!compatible("cp37-cp37m-win_amd64"));
+        assert!(!compatible("cp37-cp37m-manylinux1_i686"));
+        assert!(!compatible("cp37-cp37m-musllinux_1_1_x86_64"));
+
+        let tags = CompatibleTags::from_cpython("3.8", &suffixes(&[".cp38-win_amd64.pyd", ".pyd"]))
+            .unwrap();
+        assert!(tags.is_compatible(&parse_wheel_tag("cp38-cp38-win_amd64")?[0]));
+        assert!(!tags.is_compatible(&parse_wheel_tag("cp38-cp38-win32")?[0]));
+
+        assert!(CompatibleTags::from_cpython("3.8", &suffixes(&[".so"])).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_installed_wheels() -> Result<()> {
+        let td = tempdir::TempDir::new("pyoxidizer-test")?;
+        let root = td.path();
+
+        std::fs::create_dir(root.join("foo-1.0.dist-info"))?;
+        std::fs::write(
+            root.join("foo-1.0.dist-info").join("WHEEL"),
+            "Tag: py3-none-any\n",
+        )?;
+
+        let tags =
+            CompatibleTags::from_cpython("3.7", &suffixes(&[".cpython-37m-x86_64-linux-gnu.so"]))
+                .unwrap();
+        verify_installed_wheels(root, &tags)?;
+
+        std::fs::create_dir(root.join("bar-1.0.dist-info"))?;
+        std::fs::write(
+            root.join("bar-1.0.dist-info").join("WHEEL"),
+            "Tag: cp38-cp38-manylinux2014_x86_64\n",
+        )?;
+
+        let err = verify_installed_wheels(root, &tags).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("bar-1.0.dist-info is built for cp38-cp38-manylinux2014_x86_64"));
+
+        Ok(())
+    }
+}
@@ -5,31 +5,40 @@
 /*! Functionality for Windows embeddable distributions. */
 
 use {
-    super::binary::{EmbeddedPythonBinaryData, PythonBinaryBuilder, PythonLinkingInfo},
-    super::bytecode::BytecodeCompiler,
-    super::config::EmbeddedPythonConfig,
+    super::binary::{
+        EmbeddedPythonBinaryData, LibpythonLinkMode, PythonBinaryBuilder, PythonLinkingInfo,
+        WindowsCrtLinkage,
+    },
+    super::bytecode::{BytecodeCompiler, SourceTransforms},
+    super::config::{EmbeddedPythonConfig, RunMode},
     super::distribution::{
-        extract_zip, resolve_python_distribution_from_location, DistributionExtractLock,
-        ExtensionModuleFilter, PythonDistribution, PythonDistributionLocation,
-        PythonModuleSuffixes, IMPORTLIB_BOOTSTRAP_EXTERNAL_PY_37, IMPORTLIB_BOOTSTRAP_PY_37,
+        extract_distribution, extract_zip, resolve_python_distribution_from_location,
+        DistributionCapabilities, ExtensionModuleFilter, PythonDistribution,
+        PythonDistributionLocation, PythonImplementation, PythonModuleSuffixes,
+        IMPORTLIB_BOOTSTRAP_EXTERNAL_PY_37, IMPORTLIB_BOOTSTRAP_PY_37,
+    },
+    super::embedded_resource::{
+        ConflictPolicy, EmbeddedPythonResourcesPrePackaged, ResourceConflict,
     },
-    super::embedded_resource::EmbeddedPythonResourcesPrePackaged,
     super::libpython::{derive_importlib, ImportlibBytecode},
     super::packaging_tool::bootstrap_packaging_tools,
     super::resource::{
-        BytecodeModule, ExtensionModuleData, PythonResource, ResourceData, SourceModule,
+        BytecodeModule, BytecodeOptimizationLevel, DistributionResource, ExtensionModuleData,
+        PythonResource, ResourceData, SourceModule,
     },
     super::standalone_distribution::ExtensionModule,
     crate::analyze::find_pe_dependencies_path,
     crate::app_packaging::resource::FileManifest,
-    anyhow::{anyhow, Result},
+    crate::licensing::LicensedComponent,
+    anyhow::{anyhow, Context, Result},
+    copy_dir::copy_dir,
+    python_packed_resources::data::FileEmulation,
     slog::warn,
-    std::collections::{BTreeMap, HashMap},
+    std::collections::{BTreeMap, BTreeSet, HashMap},
     std::convert::TryInto,
     std::fmt::{Debug, Formatter},
     std::iter::FromIterator,
     std::path::{Path, PathBuf},
-    tempdir::TempDir,
 };
 
 /// Represents a Python extension module on Windows that is standalone.
@@ -116,20 +125,21 @@ impl WindowsEmbeddableDistribution {
         let (archive_path, extract_path) =
             resolve_python_distribution_from_location(logger, location, distributions_dir)?;
 
-        Self::from_zip_file(&archive_path, &extract_path)
+        Self::from_zip_file(logger, &archive_path, &extract_path)
     }
 
     /// Obtain an instance by extracting a zip file to a directory.
-    pub fn from_zip_file(path: &Path, extract_dir: &Path) -> Result<Self> {
-        let zip_data = std::fs::read(path)?;
-        let cursor = std::io::Cursor::new(zip_data);
+    ///
+    /// The zip file is only extracted if ``extract_dir`` doesn't exist.
+    pub fn from_zip_file(logger: &slog::Logger, path: &Path, extract_dir: &Path) -> Result<Self> {
+        extract_distribution(logger, path, extract_dir, |dest_dir| {
+            let zip_data = std::fs::read(path)?;
+            let cursor = std::io::Cursor::new(zip_data);
 
-        let mut zf = zip::ZipArchive::new(cursor)?;
+            let mut zf = zip::ZipArchive::new(cursor)?;
 
-        {
-            let _lock = DistributionExtractLock::new(extract_dir)?;
-            extract_zip(extract_dir, &mut zf)?;
-        }
+            extract_zip(dest_dir, &mut zf)
+        })?;
 
         Self::from_directory(extract_dir)
     }
@@ -248,6 +258,16 @@ impl PythonDistribution for WindowsEmbeddableDistribution {
         &self.python_exe
     }
 
+    fn capabilities(&self) -> DistributionCapabilities {
+        DistributionCapabilities {
+            implementation: PythonImplementation::CPython,
+            embeddable: true,
+            static_libpython: false,
+            static_extension_modules: false,
+            dynamic_extension_modules: true,
+        }
+    }
+
     fn python_major_minor_version(&self) -> String {
         unimplemented!()
     }
@@ -283,6 +303,9 @@ impl PythonDistribution for WindowsEmbeddableDistribution {
         _include_resources: bool,
         _include_test: bool,
     ) -> Result<Box<dyn PythonBinaryBuilder>> {
+        let license_text = std::fs::read_to_string(&self.license_path)
+            .with_context(|| format!("reading {}", self.license_path.display()))?;
+
         Ok(Box::new(WindowsEmbeddedablePythonExecutableBuilder {
             host_triple: host_triple.to_string(),
             target_triple: target_triple.to_string(),
@@ -293,6 +316,16 @@ impl PythonDistribution for WindowsEmbeddableDistribution {
             resources: EmbeddedPythonResourcesPrePackaged::default(),
             config: config.clone(),
             importlib_bytecode: self.resolve_importlib_bytecode()?,
+            windows_crt_linkage: WindowsCrtLinkage::default(),
+            libpython_link_mode: LibpythonLinkMode::Dynamic,
+            python_license: LicensedComponent {
+                name: "Python".to_string(),
+                version: Some(self.python_major_minor_version()),
+                licenses: vec!["Python-2.0".to_string()],
+                license_texts: vec![license_text],
+                sha256: None,
+            },
+            package_licenses: Vec::new(),
         }))
     }
 
@@ -305,6 +338,10 @@ impl PythonDistribution for WindowsEmbeddableDistribution {
         unimplemented!();
     }
 
+    fn extension_module_variants(&self, _name: &str) -> Vec<ExtensionModule> {
+        Vec::new()
+    }
+
     fn source_modules(&self) -> Result<Vec<SourceModule>> {
         // Windows embeddable distributions don't have source modules.
         Ok(Vec::new())
@@ -316,7 +353,7 @@ impl PythonDistribution for WindowsEmbeddableDistribution {
         Ok(Vec::new())
     }
 
-    fn ensure_pip(&self, logger: &slog::Logger) -> Result<PathBuf> {
+    fn ensure_pip(&self, logger: &slog::Logger, dest_dir: &Path) -> Result<PathBuf> {
         // Windows embeddable distributions don't contain pip or ensurepip. So we
         // download a deterministic version of get-pip.py and run it to install pip.
 
@@ -328,23 +365,36 @@ impl PythonDistribution for WindowsEmbeddableDistribution {
             .parent()
             .ok_or(anyhow!("could not resolve parent directory"))?;
 
-        let pip_exe_path = dist_dir.join("pip.exe");
+        // The distribution is shared with other builds. So pip is installed in a
+        // copy of it.
+        let copy_path = dest_dir.join("python");
+        warn!(
+            logger,
+            "copying {} to {} to install pip",
+            dist_dir.display(),
+            copy_path.display()
+        );
+        copy_dir(dist_dir, &copy_path)
+            .with_context(|| format!("copying {}", dist_dir.display()))?;
 
-        if !pip_exe_path.exists() {
-            warn!(logger, "pip not present; installing");
-            bootstrap_packaging_tools(
-                logger,
-                &self.python_exe,
-                dist_parent_dir,
-                // Install executables and packages in the distribution itself because
-                // the default locations of `Scripts` and `Lib/site-packages` aren't picked
-                // up by the distribution by default.
-                dist_dir,
-                dist_dir,
-            )?;
-        }
+        let python_exe = copy_path.join(
+            self.python_exe
+                .file_name()
+                .ok_or(anyhow!("could not resolve executable name"))?,
+        );
+
+        bootstrap_packaging_tools(
+            logger,
+            &python_exe,
+            dist_parent_dir,
+            // Install executables and packages in the copy itself because
+            // the default locations of `Scripts` and `Lib/site-packages` aren't picked
+            // up by the distribution by default.
+            &copy_path,
+            &copy_path,
+        )?;
 
-        Ok(pip_exe_path)
+        Ok(python_exe)
     }
 
     fn resolve_distutils(
@@ -466,6 +516,18 @@ pub struct WindowsEmbeddedablePythonExecutableBuilder {
 
     /// Compiled bytecode for importlib bootstrap modules.
     importlib_bytecode: ImportlibBytecode,
+
+    /// How to link against the C runtime.
+    windows_crt_linkage: WindowsCrtLinkage,
+
+    /// How to link against `libpython`. Only dynamic linking is supported.
+    libpython_link_mode: LibpythonLinkMode,
+
+    /// Licensing metadata of Python.
+    python_license: LicensedComponent,
+
+    /// Licensing metadata of embedded Python packages.
+    package_licenses: Vec<LicensedComponent>,
 }
 
 impl WindowsEmbeddedablePythonExecutableBuilder {
@@ -480,7 +542,7 @@ impl WindowsEmbeddedablePythonExecutableBuilder {
     pub fn resolve_pythonxy_lib(&self, logger: &slog::Logger, opt_level: &str) -> Result<Vec<u8>> {
         warn!(logger, "compiling fake pythonXY.lib");
 
-        let temp_dir = TempDir::new("pyoxidizer-build-libpython")?;
+        let temp_dir = crate::scratch::scratch_dir("pyoxidizer-build-libpython")?;
 
         let empty_source = temp_dir.path().join("empty.c");
         std::fs::File::create(&empty_source)?;
@@ -490,6 +552,7 @@ impl WindowsEmbeddedablePythonExecutableBuilder {
             .host(&self.host_triple)
             .target(&self.target_triple)
             .opt_level_str(opt_level)
+            .static_crt(self.windows_crt_linkage == WindowsCrtLinkage::Static)
             .file(&empty_source)
             .cargo_metadata(false)
             .compile("pythonXY");
@@ -505,6 +568,13 @@ impl WindowsEmbeddedablePythonExecutableBuilder {
         logger: &slog::Logger,
         opt_level: &str,
     ) -> Result<PythonLinkingInfo> {
+        if self.libpython_link_mode != LibpythonLinkMode::Dynamic {
+            return Err(anyhow!(
+                "Windows embeddable distributions only provide a Python DLL and can't link libpython {}",
+                self.libpython_link_mode.as_str()
+            ));
+        }
+
         let libpython_dir = self
             .python_dll
             .parent()
@@ -516,6 +586,7 @@ impl WindowsEmbeddedablePythonExecutableBuilder {
         ];
 
         Ok(PythonLinkingInfo {
+            link_mode: LibpythonLinkMode::Dynamic,
             libpythonxy_filename: PathBuf::from("pythonXY.lib"),
             libpythonxy_data: self.resolve_pythonxy_lib(logger, opt_level)?,
             libpython_filename: Some(self.python_dll.clone()),
@@ -539,6 +610,34 @@ impl PythonBinaryBuilder for WindowsEmbeddedablePythonExecutableBuilder {
         &self.python_exe
     }
 
+    fn python_version(&self) -> Option<String> {
+        None
+    }
+
+    fn config(&self) -> &EmbeddedPythonConfig {
+        &self.config
+    }
+
+    fn set_run_mode(&mut self, run_mode: RunMode) {
+        self.config.run_mode = run_mode;
+    }
+
+    fn windows_crt_linkage(&self) -> WindowsCrtLinkage {
+        self.windows_crt_linkage
+    }
+
+    fn set_windows_crt_linkage(&mut self, linkage: WindowsCrtLinkage) {
+        self.windows_crt_linkage = linkage;
+    }
+
+    fn libpython_link_mode(&self) -> LibpythonLinkMode {
+        self.libpython_link_mode
+    }
+
+    fn set_libpython_link_mode(&mut self, mode: LibpythonLinkMode) {
+        self.libpython_link_mode = mode;
+    }
+
     fn source_modules(&self) -> BTreeMap<String, SourceModule> {
         self.resources.get_source_modules()
     }
@@ -547,6 +646,10 @@ impl PythonBinaryBuilder for WindowsEmbeddedablePythonExecutableBuilder {
         self.resources.get_bytecode_modules()
     }
 
+    fn bytecode_optimize_levels(&self) -> BTreeMap<String, Vec<BytecodeOptimizationLevel>> {
+        self.resources.get_bytecode_optimize_levels()
+    }
+
     fn resources(&self) -> BTreeMap<String, BTreeMap<String, Vec<u8>>> {
         self.resources.get_resources()
     }
@@ -571,6 +674,30 @@ impl PythonBinaryBuilder for WindowsEmbeddedablePythonExecutableBuilder {
         self.resources.add_resource(resource)
     }
 
+    fn add_distribution_resource(&mut self, resource: &DistributionResource) {
+        self.resources.add_distribution_resource(resource)
+    }
+
+    fn set_file_emulation(&mut self, name: &str, policy: FileEmulation) {
+        self.resources.set_file_emulation(name, policy)
+    }
+
+    fn set_source_transforms(&mut self, package: &str, transforms: SourceTransforms) {
+        self.resources.set_source_transforms(package, transforms)
+    }
+
+    fn resource_conflict_policy(&self) -> ConflictPolicy {
+        self.resources.conflict_policy()
+    }
+
+    fn set_resource_conflict_policy(&mut self, policy: ConflictPolicy) {
+        self.resources.set_conflict_policy(policy)
+    }
+
+    fn take_resource_conflicts(&mut self) -> Vec<ResourceConflict> {
+        self.resources.take_conflicts()
+    }
+
     fn add_extension_module(&mut self, _extension_module: &ExtensionModule) {
         unimplemented!()
     }
@@ -579,6 +706,32 @@ impl PythonBinaryBuilder for WindowsEmbeddedablePythonExecutableBuilder {
         unimplemented!()
     }
 
+    fn add_package_license(&mut self, license: &LicensedComponent) {
+        self.package_licenses.retain(|l| l.name != license.name);
+        self.package_licenses.push(license.clone());
+    }
+
+    fn licensed_components(&self) -> Vec<LicensedComponent> {
+        let mut res = vec![self.python_license.clone()];
+        res.extend(self.package_licenses.iter().cloned());
+
+        res
+    }
+
+    fn package_licenses(&self) -> Vec<LicensedComponent> {
+        self.package_licenses.clone()
+    }
+
+    fn linked_libraries(&self) -> BTreeSet<String> {
+        let mut res = BTreeSet::new();
+
+        if let Some(stem) = self.python_dll.file_stem() {
+            res.insert(stem.to_string_lossy().to_string());
+        }
+
+        res
+    }
+
     fn filter_resources_from_files(
         &mut self,
         logger: &slog::Logger,
@@ -589,6 +742,25 @@ impl PythonBinaryBuilder for WindowsEmbeddedablePythonExecutableBuilder {
             .filter_from_files(logger, files, glob_patterns)
     }
 
+    fn import_packages_from_filesystem(&mut self, packages: &[String], sys_paths: &[String]) {
+        self.resources.remove_packages(packages);
+        self.config.filesystem_importer = true;
+
+        let mut paths = sys_paths.to_vec();
+        paths.append(&mut self.config.sys_paths);
+        self.config.sys_paths = paths;
+    }
+
+    fn remove_modules(&mut self, names: &[String]) {
+        self.resources.remove_modules(names);
+    }
+
+    fn restrict_imports(&mut self, allow: Option<&[String]>, deny: &[String]) {
+        self.resources.restrict_imports(allow, deny);
+        self.config.import_allow = allow.map(|modules| modules.to_vec());
+        self.config.import_deny = deny.to_vec();
+    }
+
     fn requires_jemalloc(&self) -> bool {
         // jemalloc not supported on Windows.
         false
@@ -599,10 +771,17 @@ impl PythonBinaryBuilder for WindowsEmbeddedablePythonExecutableBuilder {
         logger: &slog::Logger,
         opt_level: &str,
     ) -> Result<EmbeddedPythonBinaryData> {
-        let resources = self
-            .resources
-            .package(logger, &self.python_exe)?
-            .try_into()?;
+        let mut resources = self.resources.package(logger, &self.python_exe)?;
+
+        if let Some(key) = self.config.resolve_resources_key()? {
+            warn!(
+                logger,
+                "encrypting embedded Python module source and bytecode"
+            );
+            resources.encrypt(&key);
+        }
+
+        let resources = resources.try_into()?;
 
         let linking_info = self.as_python_linking_info(logger, opt_level)?;
 
@@ -611,6 +790,8 @@ impl PythonBinaryBuilder for WindowsEmbeddedablePythonExecutableBuilder {
             linking_info,
             importlib: self.importlib_bytecode.clone(),
             resources,
+            python_version: self.python_version(),
+            name: self.exe_name.clone(),
             host: self.host_triple.clone(),
             target: self.target_triple.clone(),
         })
@@ -624,7 +805,7 @@ impl PythonBinaryBuilder for WindowsEmbeddedablePythonExecutableBuilder {
 #[cfg(test)]
 mod tests {
     #[cfg(windows)]
-    use crate::py_packaging::packaging_tool::pip_install;
+    use crate::py_packaging::packaging_tool::{pip_install, PipIndexOptions};
     use {
         super::*, crate::python_distributions::CPYTHON_WINDOWS_EMBEDDABLE_BY_TRIPLE,
         crate::testutil::*,
@@ -721,10 +902,16 @@ mod tests {
         let logger = get_logger()?;
         let dist = get_windows_embeddable_distribution()?;
 
-        let pip_path = dist.ensure_pip(&logger)?;
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+
+        let python_exe = dist.ensure_pip(&logger, temp_dir.path())?;
 
-        assert_eq!(pip_path, dist.python_exe.parent().unwrap().join("pip.exe"));
-        assert!(pip_path.exists());
+        assert_eq!(
+            python_exe,
+            temp_dir.path().join("python").join("python.exe")
+        );
+        assert!(python_exe.parent().unwrap().join("pip.exe").exists());
+        assert!(!dist.python_exe.parent().unwrap().join("pip.exe").exists());
 
         Ok(())
     }
@@ -735,13 +922,12 @@ mod tests {
         let logger = get_logger()?;
         let dist = get_windows_embeddable_distribution()?;
 
-        let pip_path = dist.ensure_pip(&logger)?;
-
         let resources = pip_install(
             &logger,
             &dist,
             false,
             &["black==19.10b0".to_string()],
+            &PipIndexOptions::default(),
             &HashMap::new(),
         )?;
 
@@ -0,0 +1,352 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Producing Python zip applications.
+
+A zip application is a zip file containing Python modules and resources
+that the Python interpreter can execute directly. See PEP 441 and the
+`zipapp` module in the Python standard library for more.
+*/
+
+use {
+    super::bytecode::{BytecodeCompiler, CompileMode},
+    super::resource::{
+        packages_from_module_name, resolve_path_for_module, BytecodeModule, ResourceData,
+        SourceModule,
+    },
+    crate::app_packaging::resource::{FileContent, FileManifest},
+    crate::build_info::{is_valid_module_name, python_str},
+    anyhow::{anyhow, Context, Result},
+    slog::info,
+    std::collections::BTreeMap,
+    std::io::{Seek, Write},
+    std::path::{Path, PathBuf},
+};
+
+/// Parse an entry point string into a module name and optional callable.
+///
+/// `entry_point` is either a module name (`pkg.mod`) or a `module:callable`
+/// string (`pkg.mod:main`). Both components must be valid dotted Python names.
+pub fn parse_entry_point(entry_point: &str) -> Result<(String, Option<String>)> {
+    let (module, function) = if let Some(idx) = entry_point.find(':') {
+        (&entry_point[0..idx], Some(&entry_point[idx + 1..]))
+    } else {
+        (entry_point, None)
+    };
+
+    if !is_valid_module_name(module) {
+        return Err(anyhow!("invalid module in entry point: {}", entry_point));
+    }
+
+    if let Some(function) = function {
+        if !is_valid_module_name(function) {
+            return Err(anyhow!("invalid callable in entry point: {}", entry_point));
+        }
+    }
+
+    Ok((module.to_string(), function.map(|f| f.to_string())))
+}
+
+/// Derive the source code of a `__main__.py` for an entry point.
+///
+/// If the entry point names a module, the module is executed as `__main__`.
+/// If it names a callable, the callable is invoked and its return value is
+/// the exit status of the process, like console script entry points. This
+/// mirrors the `zipapp` module.
+pub fn main_py_source(entry_point: &str) -> Result<String> {
+    let (module, function) = parse_entry_point(entry_point)?;
+
+    Ok(match function {
+        Some(function) => format!(
+            "# -*- coding: utf-8 -*-\nimport sys\nimport {}\nsys.exit({}.{}())\n",
+            module, module, function
+        ),
+        None => format!(
+            "# -*- coding: utf-8 -*-\nimport runpy\nrunpy.run_module({}, run_name='__main__', alter_sys=True)\n",
+            python_str(&module)
+        ),
+    })
+}
+
+/// Builds Python zip applications from Python resources.
+#[derive(Clone, Debug)]
+pub struct ZipAppBuilder {
+    /// Name of the application. Used to derive the output filename.
+    pub name: String,
+
+    /// Python executable used to compile bytecode.
+    pub python_exe: PathBuf,
+
+    /// Interpreter to reference in a `#!` line at the beginning of the archive.
+    pub interpreter: Option<String>,
+
+    /// Entry point to run when the archive is executed.
+    pub main: Option<String>,
+
+    source_modules: BTreeMap<String, SourceModule>,
+    bytecode_modules: BTreeMap<String, BytecodeModule>,
+    resources: BTreeMap<String, ResourceData>,
+}
+
+impl ZipAppBuilder {
+    pub fn new(
+        name: &str,
+        python_exe: &Path,
+        interpreter: Option<String>,
+        main: Option<String>,
+    ) -> Self {
+        ZipAppBuilder {
+            name: name.to_string(),
+            python_exe: python_exe.to_path_buf(),
+            interpreter,
+            main,
+            source_modules: BTreeMap::new(),
+            bytecode_modules: BTreeMap::new(),
+            resources: BTreeMap::new(),
+        }
+    }
+
+    /// The filename the zip application should be written to.
+    pub fn filename(&self) -> String {
+        format!("{}.pyz", self.name)
+    }
+
+    pub fn source_modules(&self) -> &BTreeMap<String, SourceModule> {
+        &self.source_modules
+    }
+
+    pub fn bytecode_modules(&self) -> &BTreeMap<String, BytecodeModule> {
+        &self.bytecode_modules
+    }
+
+    pub fn resources(&self) -> &BTreeMap<String, ResourceData> {
+        &self.resources
+    }
+
+    pub fn add_source_module(&mut self, module: &SourceModule) {
+        self.source_modules
+            .insert(module.name.clone(), module.clone());
+    }
+
+    pub fn add_bytecode_module(&mut self, module: &BytecodeModule) {
+        self.bytecode_modules
+            .insert(module.name.clone(), module.clone());
+    }
+
+    pub fn add_resource(&mut self, resource: &ResourceData) {
+        self.resources
+            .insert(resource.full_name(), resource.clone());
+    }
+
+    /// Resolve the files that will be written into the zip archive.
+    ///
+    /// Bytecode is compiled to `.pyc` files next to the source, as `zipimport`
+    /// doesn't look in `__pycache__` directories.
+    pub fn file_manifest(&self, logger: &slog::Logger) -> Result<FileManifest> {
+        let mut manifest = FileManifest::default();
+
+        for module in self.source_modules.values() {
+            module.add_to_file_manifest(&mut manifest, "")?;
+        }
+
+        if !self.bytecode_modules.is_empty() {
+            let mut compiler = BytecodeCompiler::new(&self.python_exe)?;
+
+            for module in self.bytecode_modules.values() {
+                info!(logger, "compiling bytecode for {}", module.name);
+                let data = module
+                    .compile(&mut compiler, CompileMode::from(module.invalidation_mode))
+                    .with_context(|| format!("compiling bytecode for {}", module.name))?;
+
+                let path = resolve_path_for_module("", &module.name, module.is_package, None)
+                    .with_extension("pyc");
+                manifest.add_file(
+                    &path,
+                    &FileContent {
+                        data,
+                        executable: false,
+                    },
+                )?;
+
+                for package in packages_from_module_name(&module.name) {
+                    let package_path = resolve_path_for_module("", &package, true, None);
+
+                    if !manifest.has_path(&package_path)
+                        && !manifest.has_path(&package_path.with_extension("pyc"))
+                    {
+                        manifest.add_file(
+                            &package_path,
+                            &FileContent {
+                                data: vec![],
+                                executable: false,
+                            },
+                        )?;
+                    }
+                }
+            }
+        }
+
+        for resource in self.resources.values() {
+            resource.add_to_file_manifest(&mut manifest, "")?;
+        }
+
+        if let Some(main) = &self.main {
+            manifest.add_file(
+                Path::new("__main__.py"),
+                &FileContent {
+                    data: main_py_source(main)?.into_bytes(),
+                    executable: false,
+                },
+            )?;
+        }
+
+        Ok(manifest)
+    }
+
+    /// Write a zip application to a writer.
+    pub fn write_zipapp<W: Write + Seek>(
+        &self,
+        logger: &slog::Logger,
+        mut writer: W,
+    ) -> Result<()> {
+        let manifest = self.file_manifest(logger)?;
+
+        if !manifest.has_path(Path::new("__main__.py"))
+            && !manifest.has_path(Path::new("__main__.pyc"))
+        {
+            return Err(anyhow!(
+                "zip application has no __main__ module; define an entry point via main"
+            ));
+        }
+
+        if let Some(interpreter) = &self.interpreter {
+            writer.write_all(format!("#!{}\n", interpreter).as_bytes())?;
+        }
+
+        let mut zf = zip::ZipWriter::new(writer);
+        // Use a fixed modification time so archives are reproducible.
+        let options = zip::write::FileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated)
+            .last_modified_time(zip_datetime());
+
+        for (path, content) in manifest.entries() {
+            // Zip archives always use / as the path separator.
+            let name = path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            zf.start_file(name, options)?;
+            zf.write_all(&content.data)?;
+        }
+
+        zf.finish()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::py_packaging::resource::DataLocation,
+        crate::testutil::*,
+        std::io::{Cursor, Read},
+    };
+
+    #[test]
+    fn test_parse_entry_point() -> Result<()> {
+        assert_eq!(parse_entry_point("foo")?, ("foo".to_string(), None));
+        assert_eq!(
+            parse_entry_point("foo.bar:main")?,
+            ("foo.bar".to_string(), Some("main".to_string()))
+        );
+        assert!(parse_entry_point("foo-bar").is_err());
+        assert!(parse_entry_point(":main").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_main_py_source() -> Result<()> {
+        assert_eq!(
+            main_py_source("foo.bar:main")?,
+            "# -*- coding: utf-8 -*-\nimport sys\nimport foo.bar\nsys.exit(foo.bar.main())\n"
+        );
+        assert!(main_py_source("foo")
+            .unwrap()
+            .contains("runpy.run_module(\"foo\", "));
+        assert!(main_py_source("").is_err());
+        assert!(main_py_source("foo:").is_err());
+        assert!(main_py_source("foo;bar").is_err());
+        assert!(main_py_source("1foo").is_err());
+        assert!(main_py_source("foo')").is_err());
+        assert!(main_py_source("foo\\").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_zipapp() -> Result<()> {
+        let logger = get_logger()?;
+
+        let mut builder = ZipAppBuilder::new(
+            "app",
+            Path::new("python3"),
+            Some("/usr/bin/env python3".to_string()),
+            Some("foo.bar:main".to_string()),
+        );
+        builder.add_source_module(&SourceModule {
+            name: "foo.bar".to_string(),
+            source: DataLocation::Memory(b"def main(): pass\n".to_vec()),
+            is_package: false,
+        });
+        builder.add_resource(&ResourceData {
+            package: "foo".to_string(),
+            name: "data.txt".to_string(),
+            data: DataLocation::Memory(b"data".to_vec()),
+        });
+
+        let mut buffer = Cursor::new(Vec::new());
+        builder.write_zipapp(&logger, &mut buffer)?;
+
+        let data = buffer.into_inner();
+        assert!(data.starts_with(b"#!/usr/bin/env python3\n"));
+
+        let mut za = zip::ZipArchive::new(Cursor::new(data))?;
+        let mut names = (0..za.len())
+            .map(|i| za.by_index(i).unwrap().name().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "__main__.py",
+                "foo/__init__.py",
+                "foo/bar.py",
+                "foo/data.txt"
+            ]
+        );
+
+        let mut source = String::new();
+        za.by_name("foo/bar.py")?.read_to_string(&mut source)?;
+        assert_eq!(source, "def main(): pass\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_zipapp_no_main() -> Result<()> {
+        let logger = get_logger()?;
+        let builder = ZipAppBuilder::new("app", Path::new("python3"), None, None);
+
+        let mut buffer = Cursor::new(Vec::new());
+        assert!(builder.write_zipapp(&logger, &mut buffer).is_err());
+
+        Ok(())
+    }
+}
@@ -4,111 +4,139 @@
 
 //! Defines known Python distributions.
 
-use lazy_static::lazy_static;
-use std::collections::BTreeMap;
+use {
+    crate::py_packaging::distribution::DistributionFlavor,
+    anyhow::{anyhow, Result},
+    lazy_static::lazy_static,
+    std::collections::BTreeMap,
+};
 
 /// Describes a Python distribution available at a URL.
+#[derive(Clone, Debug, PartialEq)]
 pub struct HostedDistribution {
     pub url: String,
     pub sha256: String,
 }
 
+/// An entry in the index of known Python distributions.
+#[derive(Clone, Debug)]
+pub struct PythonDistributionRecord {
+    /// Full version of the Python distribution. e.g. `3.7.6`.
+    pub python_version: String,
+
+    /// Rust target triple the distribution runs on.
+    pub target_triple: String,
+
+    /// The flavor of the distribution.
+    ///
+    /// Never `DistributionFlavor::Standalone`, as records always describe
+    /// a specific link mode.
+    pub flavor: DistributionFlavor,
+
+    /// Where the distribution can be obtained from.
+    pub location: HostedDistribution,
+}
+
+/// Obtain distributions of a flavor in the index, keyed by target triple.
+fn distributions_by_triple(flavor: DistributionFlavor) -> BTreeMap<String, HostedDistribution> {
+    PYTHON_DISTRIBUTIONS
+        .iter()
+        .filter(|record| record.flavor == flavor)
+        .map(|record| (record.target_triple.clone(), record.location.clone()))
+        .collect()
+}
+
 lazy_static! {
-    pub static ref CPYTHON_STANDALONE_STATIC_BY_TRIPLE: BTreeMap<String, HostedDistribution> = {
-        let mut res: BTreeMap<String, HostedDistribution> = BTreeMap::new();
-
-        res.insert(
-            "x86_64-unknown-linux-gnu".to_string(),
-            HostedDistribution {
-                url: String::from("https://github.com/indygreg/python-build-standalone/releases/download/20200216/cpython-3.7.6-linux64-20200216T2303.tar.zst"),
-                sha256: String::from(
-                    "58067eecbd1600ea765f7fe7b43562bbc8058db4c84ddbfcaddcd2ee18193907",
-                ),
+    /// Index of known Python distributions.
+    pub static ref PYTHON_DISTRIBUTIONS: Vec<PythonDistributionRecord> = vec![
+        PythonDistributionRecord {
+            python_version: "3.7.6".to_string(),
+            target_triple: "x86_64-unknown-linux-gnu".to_string(),
+            flavor: DistributionFlavor::StandaloneStatic,
+            location: HostedDistribution {
+                url: "https://github.com/indygreg/python-build-standalone/releases/download/20200216/cpython-3.7.6-linux64-20200216T2303.tar.zst".to_string(),
+                sha256: "58067eecbd1600ea765f7fe7b43562bbc8058db4c84ddbfcaddcd2ee18193907".to_string(),
             },
-        );
-
-        res.insert(
-            "x86_64-unknown-linux-musl".to_string(),
-            HostedDistribution {
-                url: String::from("https://github.com/indygreg/python-build-standalone/releases/download/20200217/cpython-3.7.6-linux64-musl-20200218T0557.tar.zst"),
-                sha256: String::from(
-                    "d5e5d8681b0af13bc3e718a35d6237b3629908a669050cb8c8ab919a731c5718",
-                ),
+        },
+        PythonDistributionRecord {
+            python_version: "3.7.6".to_string(),
+            target_triple: "x86_64-unknown-linux-musl".to_string(),
+            flavor: DistributionFlavor::StandaloneStatic,
+            location: HostedDistribution {
+                url: "https://github.com/indygreg/python-build-standalone/releases/download/20200217/cpython-3.7.6-linux64-musl-20200218T0557.tar.zst".to_string(),
+                sha256: "d5e5d8681b0af13bc3e718a35d6237b3629908a669050cb8c8ab919a731c5718".to_string(),
             },
-        );
-
-        res.insert(
-            "i686-pc-windows-msvc".to_string(),
-            HostedDistribution {
-                url: String::from("https://github.com/indygreg/python-build-standalone/releases/download/20200216/cpython-3.7.6-windows-x86-static-20200216T2309.tar.zst"),
-                sha256: String::from("29fcca67a022bfac3f29a8a32cb070eef8cecbf052dd9a4eff6feae441ca8fb6"),
+        },
+        PythonDistributionRecord {
+            python_version: "3.7.6".to_string(),
+            target_triple: "i686-pc-windows-msvc".to_string(),
+            flavor: DistributionFlavor::StandaloneStatic,
+            location: HostedDistribution {
+                url: "https://github.com/indygreg/python-build-standalone/releases/download/20200216/cpython-3.7.6-windows-x86-static-20200216T2309.tar.zst".to_string(),
+                sha256: "29fcca67a022bfac3f29a8a32cb070eef8cecbf052dd9a4eff6feae441ca8fb6".to_string(),
             },
-        );
-
-        res.insert(
-            "x86_64-pc-windows-msvc".to_string(),
-            HostedDistribution {
-                url: String::from("https://github.com/indygreg/python-build-standalone/releases/download/20200216/cpython-3.7.6-windows-amd64-static-20200216T2300.tar.zst"),
-                sha256: String::from("a9348f50d7289fde92e0ad36073febf9b86448737286cecead08881d009b5829"),
+        },
+        PythonDistributionRecord {
+            python_version: "3.7.6".to_string(),
+            target_triple: "x86_64-pc-windows-msvc".to_string(),
+            flavor: DistributionFlavor::StandaloneStatic,
+            location: HostedDistribution {
+                url: "https://github.com/indygreg/python-build-standalone/releases/download/20200216/cpython-3.7.6-windows-amd64-static-20200216T2300.tar.zst".to_string(),
+                sha256: "a9348f50d7289fde92e0ad36073febf9b86448737286cecead08881d009b5829".to_string(),
             },
-        );
-
-        res.insert(
-            "x86_64-apple-darwin".to_string(),
-            HostedDistribution {
-                url: String::from("https://github.com/indygreg/python-build-standalone/releases/download/20200216/cpython-3.7.6-macos-20200216T2344.tar.zst"),
-                sha256: String::from("0487f70c2b857ddcf8d005ba6de9b97b13800aeaeb505c42da7659d83c79d233"),
+        },
+        PythonDistributionRecord {
+            python_version: "3.7.6".to_string(),
+            target_triple: "x86_64-apple-darwin".to_string(),
+            flavor: DistributionFlavor::StandaloneStatic,
+            location: HostedDistribution {
+                url: "https://github.com/indygreg/python-build-standalone/releases/download/20200216/cpython-3.7.6-macos-20200216T2344.tar.zst".to_string(),
+                sha256: "0487f70c2b857ddcf8d005ba6de9b97b13800aeaeb505c42da7659d83c79d233".to_string(),
             },
-        );
-
-        res
-    };
-    pub static ref CPYTHON_STANDALONE_DYNAMIC_BY_TRIPLE: BTreeMap<String, HostedDistribution> = {
-        let mut res: BTreeMap<String, HostedDistribution> = BTreeMap::new();
-
-        res.insert(
-            "i686-pc-windows-msvc".to_string(),
-            HostedDistribution {
-                url: String::from("https://github.com/indygreg/python-build-standalone/releases/download/20200216/cpython-3.7.6-windows-x86-shared-pgo-20200217T0110.tar.zst"),
-                sha256: String::from("a77b2245f0109fa80cd46adeb40815a1e8892002fffa64293a9702f50d547bc2"),
+        },
+        PythonDistributionRecord {
+            python_version: "3.7.6".to_string(),
+            target_triple: "i686-pc-windows-msvc".to_string(),
+            flavor: DistributionFlavor::StandaloneDynamic,
+            location: HostedDistribution {
+                url: "https://github.com/indygreg/python-build-standalone/releases/download/20200216/cpython-3.7.6-windows-x86-shared-pgo-20200217T0110.tar.zst".to_string(),
+                sha256: "a77b2245f0109fa80cd46adeb40815a1e8892002fffa64293a9702f50d547bc2".to_string(),
             },
-        );
-
-        res.insert(
-            "x86_64-pc-windows-msvc".to_string(),
-            HostedDistribution {
-                url: String::from("https://github.com/indygreg/python-build-standalone/releases/download/20200216/cpython-3.7.6-windows-amd64-shared-pgo-20200217T0022.tar.zst"),
-                sha256: String::from("35ccece4950147a9344e4843bc6148882b12b79806707726b15e846eb6cfed4e"),
+        },
+        PythonDistributionRecord {
+            python_version: "3.7.6".to_string(),
+            target_triple: "x86_64-pc-windows-msvc".to_string(),
+            flavor: DistributionFlavor::StandaloneDynamic,
+            location: HostedDistribution {
+                url: "https://github.com/indygreg/python-build-standalone/releases/download/20200216/cpython-3.7.6-windows-amd64-shared-pgo-20200217T0022.tar.zst".to_string(),
+                sha256: "35ccece4950147a9344e4843bc6148882b12b79806707726b15e846eb6cfed4e".to_string(),
             },
-        );
-
-        res
-    };
-    pub static ref CPYTHON_WINDOWS_EMBEDDABLE_BY_TRIPLE: BTreeMap<String, HostedDistribution> = {
-        let mut res: BTreeMap<String, HostedDistribution> = BTreeMap::new();
-
-        res.insert(
-            "i686-pc-windows-msvc".to_string(),
-            HostedDistribution {
-                url: "https://www.python.org/ftp/python/3.7.6/python-3.7.6-embed-win32.zip"
-                    .to_string(),
-                sha256: "e2257b87e2e1a131e5d2adf843887fdab5021f8d4d6d68d49691aa965650c3ab"
-                    .to_string(),
+        },
+        PythonDistributionRecord {
+            python_version: "3.7.6".to_string(),
+            target_triple: "i686-pc-windows-msvc".to_string(),
+            flavor: DistributionFlavor::WindowsEmbeddable,
+            location: HostedDistribution {
+                url: "https://www.python.org/ftp/python/3.7.6/python-3.7.6-embed-win32.zip".to_string(),
+                sha256: "e2257b87e2e1a131e5d2adf843887fdab5021f8d4d6d68d49691aa965650c3ab".to_string(),
             },
-        );
-
-        res.insert(
-            "x86_64-pc-windows-msvc".to_string(),
-            HostedDistribution {
-                url: "https://www.python.org/ftp/python/3.7.6/python-3.7.6-embed-amd64.zip"
-                    .to_string(),
-                sha256: "114638061d636285600cbc3d4def64b45c43da9b225cb9eeead30fe7fe7d60d4"
-                    .to_string(),
+        },
+        PythonDistributionRecord {
+            python_version: "3.7.6".to_string(),
+            target_triple: "x86_64-pc-windows-msvc".to_string(),
+            flavor: DistributionFlavor::WindowsEmbeddable,
+            location: HostedDistribution {
+                url: "https://www.python.org/ftp/python/3.7.6/python-3.7.6-embed-amd64.zip".to_string(),
+                sha256: "114638061d636285600cbc3d4def64b45c43da9b225cb9eeead30fe7fe7d60d4".to_string(),
             },
-        );
-
-        res
-    };
+        },
+    ];
+    pub static ref CPYTHON_STANDALONE_STATIC_BY_TRIPLE: BTreeMap<String, HostedDistribution> =
+        distributions_by_triple(DistributionFlavor::StandaloneStatic);
+    pub static ref CPYTHON_STANDALONE_DYNAMIC_BY_TRIPLE: BTreeMap<String, HostedDistribution> =
+        distributions_by_triple(DistributionFlavor::StandaloneDynamic);
+    pub static ref CPYTHON_WINDOWS_EMBEDDABLE_BY_TRIPLE: BTreeMap<String, HostedDistribution> =
+        distributions_by_triple(DistributionFlavor::WindowsEmbeddable);
     /// Location of source code for get-pip.py, version 19.3.1.
     pub static ref GET_PIP_PY_19: HostedDistribution = {
         HostedDistribution {
@@ -117,3 +145,144 @@ lazy_static! {
         }
     };
 }
+
+/// Parse a version string into numeric components.
+fn version_components(version: &str) -> Vec<u32> {
+    version
+        .split('.')
+        .map(|part| part.parse::<u32>().unwrap_or(0))
+        .collect()
+}
+
+/// Whether `version` satisfies a version constraint.
+///
+/// The constraint is a version prefix. e.g. `3.7` matches `3.7.6` but not
+/// `3.8.1` or `3.70.0`.
+fn version_matches(version: &str, constraint: &str) -> bool {
+    let version = version.split('.').collect::<Vec<_>>();
+    let constraint = constraint.split('.').collect::<Vec<_>>();
+
+    constraint.len() <= version.len() && constraint.iter().zip(version.iter()).all(|(a, b)| a == b)
+}
+
+/// Find the distribution in the index best matching constraints.
+///
+/// `flavor` of `DistributionFlavor::Standalone` matches statically and
+/// dynamically linked standalone distributions, preferring statically linked
+/// ones. `python_version` is a version prefix, like `3.7`. If multiple
+/// distributions match, the one with the newest Python version wins.
+pub fn find_python_distribution(
+    flavor: &DistributionFlavor,
+    python_version: Option<&str>,
+    target: &str,
+) -> Result<&'static PythonDistributionRecord> {
+    // Lower ranks are preferred.
+    let flavor_rank = |record: &PythonDistributionRecord| -> Option<u8> {
+        match (flavor, &record.flavor) {
+            (DistributionFlavor::Standalone, DistributionFlavor::StandaloneStatic) => Some(0),
+            (DistributionFlavor::Standalone, DistributionFlavor::StandaloneDynamic) => Some(1),
+            (wanted, actual) if wanted == actual => Some(0),
+            _ => None,
+        }
+    };
+
+    let candidates = PYTHON_DISTRIBUTIONS
+        .iter()
+        .filter(|record| record.target_triple == target && flavor_rank(record).is_some())
+        .collect::<Vec<_>>();
+
+    if candidates.is_empty() {
+        return Err(anyhow!(
+            "could not find default Python distribution for {}",
+            target
+        ));
+    }
+
+    candidates
+        .iter()
+        .filter(|record| match python_version {
+            Some(constraint) => version_matches(&record.python_version, constraint),
+            None => true,
+        })
+        .max_by(|a, b| {
+            version_components(&a.python_version)
+                .cmp(&version_components(&b.python_version))
+                .then_with(|| flavor_rank(b).cmp(&flavor_rank(a)))
+        })
+        .cloned()
+        .ok_or_else(|| {
+            let mut available = candidates
+                .iter()
+                .map(|record| record.python_version.clone())
+                .collect::<Vec<_>>();
+            available.sort();
+            available.dedup();
+
+            anyhow!(
+                "could not find Python distribution matching version {} for {}; available versions: {}",
+                python_version.unwrap_or(""),
+                target,
+                available.join(", ")
+            )
+        })
+}
+
+/// Find the distribution in the index whose archive has a SHA-256.
+pub fn find_python_distribution_by_sha256(
+    sha256: &str,
+) -> Option<&'static PythonDistributionRecord> {
+    PYTHON_DISTRIBUTIONS
+        .iter()
+        .find(|record| record.location.sha256.eq_ignore_ascii_case(sha256))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_matches() {
+        assert!(version_matches("3.7.6", "3"));
+        assert!(version_matches("3.7.6", "3.7"));
+        assert!(version_matches("3.7.6", "3.7.6"));
+        assert!(!version_matches("3.7.6", "3.8"));
+        assert!(!version_matches("3.7.6", "3.7.60"));
+        assert!(!version_matches("3.7", "3.7.6"));
+    }
+
+    #[test]
+    fn test_find_python_distribution() -> Result<()> {
+        let record = find_python_distribution(
+            &DistributionFlavor::Standalone,
+            None,
+            "x86_64-pc-windows-msvc",
+        )?;
+        assert_eq!(record.flavor, DistributionFlavor::StandaloneStatic);
+
+        let record = find_python_distribution(
+            &DistributionFlavor::StandaloneDynamic,
+            Some("3.7"),
+            "x86_64-pc-windows-msvc",
+        )?;
+        assert_eq!(record.flavor, DistributionFlavor::StandaloneDynamic);
+        assert_eq!(
+            &record.location,
+            CPYTHON_STANDALONE_DYNAMIC_BY_TRIPLE
+                .get("x86_64-pc-windows-msvc")
+                .unwrap()
+        );
+
+        assert!(find_python_distribution(
+            &DistributionFlavor::Standalone,
+            Some("2.7"),
+            "x86_64-unknown-linux-gnu"
+        )
+        .is_err());
+        assert!(
+            find_python_distribution(&DistributionFlavor::Standalone, None, "unknown-triple")
+                .is_err()
+        );
+
+        Ok(())
+    }
+}
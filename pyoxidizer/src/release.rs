@@ -0,0 +1,644 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Publishing built targets as releases.
+
+A release target names the targets whose deliverables make up a release of
+an application. `pyoxidizer release` builds them in release mode and
+gathers their deliverables in a directory, along with a `SHA256SUMS` file
+and a Markdown release notes fragment listing the artifacts and the
+versions of the application and of the Python it embeds.
+
+The release can then be uploaded to a GitHub or GitLab release, using an
+API token read from an environment variable.
+*/
+
+use {
+    crate::project::BuildOutput,
+    crate::py_packaging::sdist::file_sha256,
+    crate::size_budget::format_size,
+    anyhow::{anyhow, Context, Result},
+    reqwest::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, USER_AGENT},
+    slog::warn,
+    std::collections::BTreeMap,
+    std::path::{Path, PathBuf},
+};
+
+/// Filename of the checksums written next to released artifacts.
+pub const CHECKSUMS_FILENAME: &str = "SHA256SUMS";
+
+/// Filename of the release notes fragment written next to released artifacts.
+pub const RELEASE_NOTES_FILENAME: &str = "RELEASE_NOTES.md";
+
+/// A service hosting releases.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReleaseProvider {
+    GitHub,
+    GitLab,
+}
+
+impl ReleaseProvider {
+    /// Parse a provider from its name in configuration files.
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "github" => Ok(ReleaseProvider::GitHub),
+            "gitlab" => Ok(ReleaseProvider::GitLab),
+            _ => Err(anyhow!(
+                "unknown release provider {}; expected github or gitlab",
+                value
+            )),
+        }
+    }
+
+    /// URL of the API of the public instance of the service.
+    pub fn default_api_url(self) -> &'static str {
+        match self {
+            ReleaseProvider::GitHub => "https://api.github.com",
+            ReleaseProvider::GitLab => "https://gitlab.com/api/v4",
+        }
+    }
+
+    /// Environment variable holding the API token by default.
+    pub fn default_token_env(self) -> &'static str {
+        match self {
+            ReleaseProvider::GitHub => "GITHUB_TOKEN",
+            ReleaseProvider::GitLab => "GITLAB_TOKEN",
+        }
+    }
+}
+
+/// Represents a registered release target in the Starlark environment.
+#[derive(Clone, Debug)]
+pub struct ReleaseTarget {
+    /// Targets whose deliverables are released.
+    pub targets: Vec<String>,
+
+    /// Version of the released application.
+    pub version: String,
+
+    /// Name of the tag of the release.
+    pub tag: String,
+
+    /// Service to upload the release to, if any.
+    pub provider: Option<ReleaseProvider>,
+
+    /// Repository the release belongs to, e.g. `owner/name`.
+    pub repository: Option<String>,
+
+    /// Environment variable holding the API token, if not the provider's default.
+    pub token_env: Option<String>,
+
+    /// URL of the API, if not the provider's public instance.
+    pub api_url: Option<String>,
+}
+
+/// A released file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReleaseArtifact {
+    pub path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl ReleaseArtifact {
+    /// Name of the file.
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+}
+
+/// A release written to a directory.
+#[derive(Clone, Debug)]
+pub struct PreparedRelease {
+    /// Title of the release.
+    pub title: String,
+
+    /// Deliverables of the released targets and the build manifest.
+    pub artifacts: Vec<ReleaseArtifact>,
+
+    /// Path to the checksums of `artifacts`.
+    pub checksums_path: PathBuf,
+
+    /// Markdown release notes fragment.
+    pub notes: String,
+
+    /// Path the release notes were written to.
+    pub notes_path: PathBuf,
+}
+
+impl PreparedRelease {
+    /// Files to upload to a release, including the checksums.
+    pub fn upload_paths(&self) -> Vec<PathBuf> {
+        self.artifacts
+            .iter()
+            .map(|a| a.path.clone())
+            .chain(std::iter::once(self.checksums_path.clone()))
+            .collect()
+    }
+}
+
+/// Render checksums of artifacts in the format of `sha256sum`.
+pub fn render_checksums(artifacts: &[ReleaseArtifact]) -> String {
+    artifacts
+        .iter()
+        .map(|a| format!("{}  {}\n", a.sha256, a.name()))
+        .collect()
+}
+
+/// Render the release notes fragment of a release.
+pub fn render_release_notes(
+    title: &str,
+    output: &BuildOutput,
+    artifacts: &[ReleaseArtifact],
+) -> String {
+    let mut python_versions = output
+        .manifest
+        .targets
+        .iter()
+        .filter_map(|t| t.python_version.clone())
+        .collect::<Vec<_>>();
+    python_versions.sort();
+    python_versions.dedup();
+
+    let mut lines = vec![
+        format!("## {}", title),
+        "".to_string(),
+        "| Artifact | Size | SHA-256 |".to_string(),
+        "| --- | --- | --- |".to_string(),
+    ];
+
+    for artifact in artifacts {
+        lines.push(format!(
+            "| `{}` | {} | `{}` |",
+            artifact.name(),
+            format_size(artifact.size),
+            artifact.sha256
+        ));
+    }
+
+    lines.push("".to_string());
+
+    let mut built_by = format!(
+        "Built by PyOxidizer {} for {}",
+        output.manifest.pyoxidizer_version, output.manifest.target_triple
+    );
+    if !python_versions.is_empty() {
+        built_by.push_str(&format!(
+            ", embedding Python {}",
+            python_versions.join(", ")
+        ));
+    }
+    built_by.push('.');
+    lines.push(built_by);
+
+    format!("{}\n", lines.join("\n"))
+}
+
+/// Write a release of built targets to a directory.
+///
+/// `name` is the name of the release target. The deliverables of the built
+/// targets are copied to `dest_dir` as by `BuildOutput::copy_to_artifact_dir()`,
+/// then checksums and release notes are written next to them.
+pub fn prepare_release(
+    name: &str,
+    release: &ReleaseTarget,
+    output: &BuildOutput,
+    dest_dir: &Path,
+) -> Result<PreparedRelease> {
+    let mut artifacts = Vec::new();
+
+    for path in output.copy_to_artifact_dir(dest_dir)? {
+        artifacts.push(ReleaseArtifact {
+            size: std::fs::metadata(&path)?.len(),
+            sha256: file_sha256(&path)?,
+            path,
+        });
+    }
+
+    for filename in &[CHECKSUMS_FILENAME, RELEASE_NOTES_FILENAME] {
+        if artifacts.iter().any(|a| a.name() == *filename) {
+            return Err(anyhow!(
+                "a released target produces {}, which conflicts with the release metadata",
+                filename
+            ));
+        }
+    }
+
+    let title = format!("{} {}", name, release.version);
+
+    let checksums_path = dest_dir.join(CHECKSUMS_FILENAME);
+    std::fs::write(&checksums_path, render_checksums(&artifacts))
+        .with_context(|| format!("writing {}", checksums_path.display()))?;
+
+    let notes = render_release_notes(&title, output, &artifacts);
+    let notes_path = dest_dir.join(RELEASE_NOTES_FILENAME);
+    std::fs::write(&notes_path, &notes)
+        .with_context(|| format!("writing {}", notes_path.display()))?;
+
+    Ok(PreparedRelease {
+        title,
+        artifacts,
+        checksums_path,
+        notes,
+        notes_path,
+    })
+}
+
+/// Read the body of an API response as JSON, failing on error statuses.
+fn json_response(mut response: reqwest::Response, action: &str) -> Result<serde_json::Value> {
+    let status = response.status();
+    let text = response.text()?;
+
+    if !status.is_success() {
+        return Err(anyhow!("{} failed with HTTP {}: {}", action, status, text));
+    }
+
+    serde_json::from_str(&text).with_context(|| format!("parsing response of {}", action))
+}
+
+/// Read the name of a file to upload, along with its content.
+fn read_upload(path: &Path) -> Result<(String, Vec<u8>)> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("{} has no file name", path.display()))?
+        .to_string_lossy()
+        .to_string();
+    let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+
+    Ok((name, data))
+}
+
+/// Obtain the IDs of GitHub release assets, keyed by name.
+fn github_asset_ids(assets: &serde_json::Value) -> Result<BTreeMap<String, u64>> {
+    assets
+        .as_array()
+        .ok_or_else(|| anyhow!("GitHub release assets are not a list"))?
+        .iter()
+        .map(
+            |asset| match (asset["name"].as_str(), asset["id"].as_u64()) {
+                (Some(name), Some(id)) => Ok((name.to_string(), id)),
+                _ => Err(anyhow!("GitHub release asset has no name or id")),
+            },
+        )
+        .collect()
+}
+
+/// Upload files to the GitHub release of a tag, creating the release if needed.
+///
+/// Assets of the release with the name of an uploaded file are replaced, so
+/// a release can be uploaded again. Returns the URL of the release.
+fn upload_github(
+    logger: &slog::Logger,
+    client: &reqwest::Client,
+    api_url: &str,
+    repository: &str,
+    token: &str,
+    tag: &str,
+    release: &PreparedRelease,
+) -> Result<String> {
+    let auth = format!("token {}", token);
+    let releases_url = format!("{}/repos/{}/releases", api_url, repository);
+
+    let response = client
+        .get(&format!("{}/tags/{}", releases_url, tag))
+        .header(AUTHORIZATION, auth.as_str())
+        .header(ACCEPT, "application/vnd.github.v3+json")
+        .header(USER_AGENT, "pyoxidizer")
+        .send()?;
+
+    let github_release = if response.status() == reqwest::StatusCode::NOT_FOUND {
+        warn!(logger, "creating GitHub release {} in {}", tag, repository);
+        json_response(
+            client
+                .post(&releases_url)
+                .header(AUTHORIZATION, auth.as_str())
+                .header(ACCEPT, "application/vnd.github.v3+json")
+                .header(USER_AGENT, "pyoxidizer")
+                .json(&serde_json::json!({
+                    "tag_name": tag,
+                    "name": release.title,
+                    "body": release.notes,
+                }))
+                .send()?,
+            "creating GitHub release",
+        )?
+    } else {
+        warn!(logger, "using existing GitHub release {}", tag);
+        json_response(response, "looking up GitHub release")?
+    };
+
+    // The URL is a template, e.g. https://uploads.github.com/repos/o/r/releases/1/assets{?name,label}.
+    let upload_url = github_release["upload_url"]
+        .as_str()
+        .ok_or_else(|| anyhow!("GitHub release has no upload_url"))?;
+    let upload_url = upload_url.split('{').next().unwrap_or(upload_url);
+    let release_id = github_release["id"]
+        .as_u64()
+        .ok_or_else(|| anyhow!("GitHub release has no id"))?;
+
+    // GitHub refuses to upload an asset with the name of an existing one.
+    let mut existing_assets = BTreeMap::new();
+    for page in 1.. {
+        let assets = github_asset_ids(&json_response(
+            client
+                .get(&format!(
+                    "{}/{}/assets?per_page=100&page={}",
+                    releases_url, release_id, page
+                ))
+                .header(AUTHORIZATION, auth.as_str())
+                .header(ACCEPT, "application/vnd.github.v3+json")
+                .header(USER_AGENT, "pyoxidizer")
+                .send()?,
+            "listing GitHub release assets",
+        )?)?;
+
+        if assets.is_empty() {
+            break;
+        }
+        existing_assets.extend(assets);
+    }
+
+    for path in release.upload_paths() {
+        let (name, data) = read_upload(&path)?;
+
+        if let Some(id) = existing_assets.get(&name) {
+            warn!(logger, "replacing existing asset {}", name);
+            let response = client
+                .delete(&format!("{}/assets/{}", releases_url, id))
+                .header(AUTHORIZATION, auth.as_str())
+                .header(ACCEPT, "application/vnd.github.v3+json")
+                .header(USER_AGENT, "pyoxidizer")
+                .send()?;
+
+            if !response.status().is_success() {
+                return Err(anyhow!(
+                    "deleting GitHub release asset {} failed with HTTP {}",
+                    name,
+                    response.status()
+                ));
+            }
+        }
+
+        warn!(logger, "uploading {}", name);
+        let url = reqwest::Url::parse_with_params(upload_url, &[("name", &name)])?;
+        json_response(
+            client
+                .post(url)
+                .header(AUTHORIZATION, auth.as_str())
+                .header(USER_AGENT, "pyoxidizer")
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .body(data)
+                .send()?,
+            &format!("uploading {}", name),
+        )?;
+    }
+
+    Ok(github_release["html_url"]
+        .as_str()
+        .unwrap_or(upload_url)
+        .to_string())
+}
+
+/// Upload files to GitLab and create a release of a tag linking to them.
+///
+/// Returns the URL of the release.
+fn upload_gitlab(
+    logger: &slog::Logger,
+    client: &reqwest::Client,
+    api_url: &str,
+    repository: &str,
+    token: &str,
+    tag: &str,
+    release: &PreparedRelease,
+) -> Result<String> {
+    let project_url = format!("{}/projects/{}", api_url, repository.replace('/', "%2F"));
+    // Uploads are served from the web interface of the project.
+    let web_url = format!(
+        "{}/{}",
+        api_url.trim_end_matches('/').trim_end_matches("/api/v4"),
+        repository
+    );
+
+    let mut links = Vec::new();
+    for path in release.upload_paths() {
+        let (name, data) = read_upload(&path)?;
+
+        warn!(logger, "uploading {}", name);
+        let form = reqwest::multipart::Form::new().part(
+            "file",
+            reqwest::multipart::Part::bytes(data).file_name(name.clone()),
+        );
+        let upload = json_response(
+            client
+                .post(&format!("{}/uploads", project_url))
+                .header("PRIVATE-TOKEN", token)
+                .multipart(form)
+                .send()?,
+            &format!("uploading {}", name),
+        )?;
+
+        let url = upload["url"]
+            .as_str()
+            .ok_or_else(|| anyhow!("GitLab upload of {} has no url", name))?;
+        links.push(serde_json::json!({
+            "name": name,
+            "url": format!("{}{}", web_url, url),
+        }));
+    }
+
+    warn!(logger, "creating GitLab release {} in {}", tag, repository);
+    json_response(
+        client
+            .post(&format!("{}/releases", project_url))
+            .header("PRIVATE-TOKEN", token)
+            .json(&serde_json::json!({
+                "tag_name": tag,
+                "name": release.title,
+                "description": release.notes,
+                "assets": {
+                    "links": links,
+                },
+            }))
+            .send()?,
+        "creating GitLab release",
+    )?;
+
+    Ok(format!("{}/-/releases/{}", web_url, tag))
+}
+
+/// Upload a prepared release to the provider of a release target.
+///
+/// Returns the URL of the release.
+pub fn upload_release(
+    logger: &slog::Logger,
+    release: &ReleaseTarget,
+    prepared: &PreparedRelease,
+) -> Result<String> {
+    let provider = release
+        .provider
+        .ok_or_else(|| anyhow!("release target does not define a provider to upload to"))?;
+    let repository = release
+        .repository
+        .as_ref()
+        .ok_or_else(|| anyhow!("release target does not define a repository"))?;
+    let api_url = release
+        .api_url
+        .clone()
+        .unwrap_or_else(|| provider.default_api_url().to_string());
+    let token_env = release
+        .token_env
+        .clone()
+        .unwrap_or_else(|| provider.default_token_env().to_string());
+    let token = std::env::var(&token_env)
+        .map_err(|_| anyhow!("{} must be set to the API token to upload with", token_env))?;
+
+    let client = crate::py_packaging::distribution::get_http_client()?;
+    let api_url = api_url.trim_end_matches('/');
+
+    match provider {
+        ReleaseProvider::GitHub => upload_github(
+            logger,
+            &client,
+            api_url,
+            repository,
+            &token,
+            &release.tag,
+            prepared,
+        ),
+        ReleaseProvider::GitLab => upload_gitlab(
+            logger,
+            &client,
+            api_url,
+            repository,
+            &token,
+            &release.tag,
+            prepared,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::starlark::build_manifest::{artifacts_from_paths, BuildManifest, TargetManifest},
+        crate::timing::TimingReport,
+    };
+
+    #[test]
+    fn test_parse_provider() {
+        assert_eq!(
+            ReleaseProvider::parse("github").unwrap(),
+            ReleaseProvider::GitHub
+        );
+        assert_eq!(
+            ReleaseProvider::parse("gitlab").unwrap(),
+            ReleaseProvider::GitLab
+        );
+        assert!(ReleaseProvider::parse("GitHub").is_err());
+    }
+
+    #[test]
+    fn test_github_asset_ids() -> Result<()> {
+        let assets = serde_json::json!([
+            {"id": 1, "name": "app"},
+            {"id": 2, "name": "SHA256SUMS"},
+        ]);
+        let ids = github_asset_ids(&assets)?;
+        assert_eq!(ids.get("app"), Some(&1));
+        assert_eq!(ids.get("SHA256SUMS"), Some(&2));
+
+        assert!(github_asset_ids(&serde_json::json!({})).is_err());
+        assert!(github_asset_ids(&serde_json::json!([{"name": "app"}])).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepare_release() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+        let output_path = temp_dir.path().join("exe");
+        let dest_dir = temp_dir.path().join("release");
+
+        std::fs::create_dir_all(output_path.join("lib"))?;
+        std::fs::write(output_path.join("app"), b"foo")?;
+        std::fs::write(output_path.join("lib").join("data"), b"")?;
+
+        let manifest_path = temp_dir.path().join("build-manifest.json");
+        std::fs::write(&manifest_path, b"{}")?;
+
+        let output = BuildOutput {
+            manifest: BuildManifest {
+                pyoxidizer_version: "0.7.0".to_string(),
+                host_triple: "x86_64-unknown-linux-gnu".to_string(),
+                target_triple: "x86_64-unknown-linux-gnu".to_string(),
+                release: true,
+                targets: vec![TargetManifest {
+                    name: "exe".to_string(),
+                    target_type: "PythonExecutable".to_string(),
+                    output_path: output_path.clone(),
+                    python_version: Some("3.7.7".to_string()),
+                    resource_counts: None,
+                    artifacts: artifacts_from_paths(&output_path, &[output_path.clone()])?,
+                    sbom_path: None,
+                }],
+            },
+            manifest_path,
+            timings: TimingReport::default(),
+            timings_path: temp_dir.path().join("build-timings.json"),
+        };
+
+        let release = ReleaseTarget {
+            targets: vec!["exe".to_string()],
+            version: "1.0".to_string(),
+            tag: "v1.0".to_string(),
+            provider: None,
+            repository: None,
+            token_env: None,
+            api_url: None,
+        };
+
+        let prepared = prepare_release("myapp", &release, &output, &dest_dir)?;
+
+        assert_eq!(prepared.title, "myapp 1.0");
+        assert_eq!(
+            prepared
+                .artifacts
+                .iter()
+                .map(|a| a.name())
+                .collect::<Vec<_>>(),
+            vec!["app", "build-manifest.json"]
+        );
+        assert_eq!(
+            std::fs::read_to_string(dest_dir.join(CHECKSUMS_FILENAME))?,
+            "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae  app\n\
+             44136fa355b3678a1146ad16f7e8649e94fb4fc21fe77e8310c060f61caaff8a  build-manifest.json\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(&prepared.notes_path)?,
+            prepared.notes
+        );
+        assert!(prepared.notes.starts_with("## myapp 1.0\n"));
+        assert!(prepared.notes.contains(
+            "| `app` | 3 B | `2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae` |"
+        ));
+        assert!(prepared.notes.ends_with(
+            "Built by PyOxidizer 0.7.0 for x86_64-unknown-linux-gnu, embedding Python 3.7.7.\n"
+        ));
+        assert_eq!(
+            prepared.upload_paths(),
+            vec![
+                dest_dir.join("app"),
+                dest_dir.join("build-manifest.json"),
+                dest_dir.join(CHECKSUMS_FILENAME),
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Support for reproducible builds.
+
+Building a project twice from the same inputs should produce identical
+artifacts. Timestamps written into artifacts are derived from the
+`SOURCE_DATE_EPOCH` environment variable (see
+<https://reproducible-builds.org/specs/source-date-epoch/>) and fall back
+to fixed values when it isn't set.
+*/
+
+use {
+    crate::project::Project,
+    crate::starlark::build_manifest::BuildManifest,
+    anyhow::Result,
+    std::collections::BTreeMap,
+    std::time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Obtain the value of `SOURCE_DATE_EPOCH`, if set and valid.
+pub fn source_date_epoch() -> Option<u64> {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+}
+
+/// Obtain the time to record as the time of a build, in seconds since the UNIX epoch.
+///
+/// `SOURCE_DATE_EPOCH` is honored so artifacts can be reproducible.
+pub fn build_time() -> u64 {
+    if let Some(value) = source_date_epoch() {
+        return value;
+    }
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Convert seconds since the UNIX epoch to UTC calendar components.
+///
+/// Returns a tuple of (year, month, day, hour, minute, second).
+pub fn utc_datetime(secs: u64) -> (i64, u32, u32, u32, u32, u32) {
+    let days = (secs / 86400) as i64;
+    let remainder = (secs % 86400) as u32;
+
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days.
+    // Days are never negative, which simplifies the algorithm.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    (
+        year,
+        month,
+        day,
+        remainder / 3600,
+        (remainder % 3600) / 60,
+        remainder % 60,
+    )
+}
+
+/// Format seconds since the UNIX epoch as an ISO 8601 UTC timestamp.
+pub fn format_timestamp(secs: u64) -> String {
+    let (year, month, day, hour, minute, second) = utc_datetime(secs);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+/// Obtain the modification time to record for entries in zip archives.
+///
+/// Zip archives can't represent times before 1980, so earlier values are
+/// clamped to 1980-01-01.
+pub fn zip_datetime() -> zip::DateTime {
+    if let Some(secs) = source_date_epoch() {
+        let (year, month, day, hour, minute, second) = utc_datetime(secs);
+
+        if (1980..=2107).contains(&year) {
+            if let Ok(dt) = zip::DateTime::from_date_and_time(
+                year as u16,
+                month as u8,
+                day as u8,
+                hour as u8,
+                minute as u8,
+                second as u8,
+            ) {
+                return dt;
+            }
+        }
+    }
+
+    zip::DateTime::default()
+}
+
+/// A file whose content differed between two builds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArtifactDifference {
+    /// Name of the target producing the file.
+    pub target: String,
+
+    /// Path of the file relative to the target's output directory.
+    pub path: String,
+
+    /// SHA-256 of the file in the first build, if it was produced.
+    pub first_sha256: Option<String>,
+
+    /// SHA-256 of the file in the second build, if it was produced.
+    pub second_sha256: Option<String>,
+}
+
+/// Find files whose content differs between two build manifests.
+pub fn compare_build_manifests(
+    first: &BuildManifest,
+    second: &BuildManifest,
+) -> Vec<ArtifactDifference> {
+    let mut artifacts: BTreeMap<(String, String), (Option<String>, Option<String>)> =
+        BTreeMap::new();
+
+    for target in &first.targets {
+        for artifact in &target.artifacts {
+            artifacts
+                .entry((target.name.clone(), artifact.path.clone()))
+                .or_insert((None, None))
+                .0 = Some(artifact.sha256.clone());
+        }
+    }
+
+    for target in &second.targets {
+        for artifact in &target.artifacts {
+            artifacts
+                .entry((target.name.clone(), artifact.path.clone()))
+                .or_insert((None, None))
+                .1 = Some(artifact.sha256.clone());
+        }
+    }
+
+    artifacts
+        .into_iter()
+        .filter(|(_, (first, second))| first != second)
+        .map(
+            |((target, path), (first_sha256, second_sha256))| ArtifactDifference {
+                target,
+                path,
+                first_sha256,
+                second_sha256,
+            },
+        )
+        .collect()
+}
+
+/// Build targets of a project twice and compare the produced files.
+///
+/// Returns the files that differ between builds. An empty result means the
+/// build is reproducible.
+pub fn verify_reproducible(
+    project: &Project,
+    targets: Option<Vec<String>>,
+) -> Result<Vec<ArtifactDifference>> {
+    let first = project.build_targets(targets.clone())?;
+    let second = project.build_targets(targets)?;
+
+    Ok(compare_build_manifests(&first.manifest, &second.manifest))
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::starlark::build_manifest::{BuildArtifact, TargetManifest},
+        std::path::PathBuf,
+    };
+
+    fn manifest(artifacts: Vec<(&str, &str)>) -> BuildManifest {
+        BuildManifest {
+            pyoxidizer_version: "0".to_string(),
+            host_triple: "host".to_string(),
+            target_triple: "target".to_string(),
+            release: false,
+            targets: vec![TargetManifest {
+                name: "exe".to_string(),
+                target_type: "PythonExecutable".to_string(),
+                output_path: PathBuf::from("build"),
+                python_version: None,
+                resource_counts: None,
+                artifacts: artifacts
+                    .into_iter()
+                    .map(|(path, sha256)| BuildArtifact {
+                        path: path.to_string(),
+                        size: 0,
+                        sha256: sha256.to_string(),
+                    })
+                    .collect(),
+                sbom_path: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_utc_datetime() {
+        assert_eq!(utc_datetime(0), (1970, 1, 1, 0, 0, 0));
+        assert_eq!(utc_datetime(951_782_400), (2000, 2, 29, 0, 0, 0));
+        assert_eq!(utc_datetime(1_589_500_800 + 3661), (2020, 5, 15, 1, 1, 1));
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(
+            format_timestamp(1_589_500_800 + 3661),
+            "2020-05-15T01:01:01Z"
+        );
+    }
+
+    #[test]
+    fn test_compare_build_manifests() {
+        let first = manifest(vec![("app", "aa"), ("lib/a", "bb"), ("lib/b", "cc")]);
+        let second = manifest(vec![("app", "aa"), ("lib/a", "dd"), ("lib/c", "ee")]);
+
+        assert!(compare_build_manifests(&first, &first).is_empty());
+        assert_eq!(
+            compare_build_manifests(&first, &second),
+            vec![
+                ArtifactDifference {
+                    target: "exe".to_string(),
+                    path: "lib/a".to_string(),
+                    first_sha256: Some("bb".to_string()),
+                    second_sha256: Some("dd".to_string()),
+                },
+                ArtifactDifference {
+                    target: "exe".to_string(),
+                    path: "lib/b".to_string(),
+                    first_sha256: Some("cc".to_string()),
+                    second_sha256: None,
+                },
+                ArtifactDifference {
+                    target: "exe".to_string(),
+                    path: "lib/c".to_string(),
+                    first_sha256: None,
+                    second_sha256: Some("ee".to_string()),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,278 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Resolve the Rust toolchain used to build executables.
+
+By default, PyOxidizer downloads a pinned Rust toolchain from
+<https://static.rust-lang.org/> and installs it next to the Python
+distributions it downloads, so Rust doesn't need to be installed to build
+executables. Setting the `PYOXIDIZER_SYSTEM_RUST` environment variable (the
+`--system-rust` argument does this) uses `cargo` and `rustc` from `PATH`
+instead.
+*/
+
+use {
+    crate::environment::MINIMUM_RUST_VERSION,
+    crate::py_packaging::distribution::get_http_client,
+    anyhow::{anyhow, Context, Result},
+    sha2::{Digest, Sha256},
+    slog::warn,
+    std::io::Read,
+    std::path::{Path, PathBuf},
+};
+
+/// Version of the Rust toolchain PyOxidizer downloads.
+pub const RUST_TOOLCHAIN_VERSION: &str = "1.43.1";
+
+/// Base URL Rust toolchain archives are downloaded from.
+const RUST_DIST_URL: &str = "https://static.rust-lang.org/dist";
+
+/// Environment variable requesting use of the Rust toolchain in `PATH`.
+pub const SYSTEM_RUST_ENV: &str = "PYOXIDIZER_SYSTEM_RUST";
+
+/// Whether the Rust toolchain in `PATH` should be used.
+pub fn use_system_rust() -> bool {
+    std::env::var_os(SYSTEM_RUST_ENV).is_some()
+}
+
+/// A Rust toolchain able to build executables.
+#[derive(Clone, Debug)]
+pub struct RustToolchain {
+    /// Path to the `cargo` executable.
+    pub cargo_path: PathBuf,
+
+    /// Path to the `rustc` executable.
+    pub rustc_path: PathBuf,
+}
+
+impl RustToolchain {
+    /// The toolchain whose executables are found in `PATH`.
+    pub fn system() -> Self {
+        Self {
+            cargo_path: PathBuf::from("cargo"),
+            rustc_path: PathBuf::from("rustc"),
+        }
+    }
+
+    /// A toolchain installed in a directory.
+    pub fn from_install_dir(path: &Path) -> Self {
+        let bin = path.join("bin");
+
+        Self {
+            cargo_path: bin.join(format!("cargo{}", std::env::consts::EXE_SUFFIX)),
+            rustc_path: bin.join(format!("rustc{}", std::env::consts::EXE_SUFFIX)),
+        }
+    }
+
+    /// Obtain the version of `rustc`.
+    pub fn version(&self) -> Result<semver::Version> {
+        let meta =
+            rustc_version::VersionMeta::for_command(std::process::Command::new(&self.rustc_path))
+                .with_context(|| format!("running {}", self.rustc_path.display()))?;
+
+        Ok(meta.semver)
+    }
+
+    /// Ensure the toolchain can build for a target triple.
+    pub fn verify(&self, target: &str) -> Result<semver::Version> {
+        let version = self.version()?;
+        if version.lt(&MINIMUM_RUST_VERSION) {
+            return Err(anyhow!(
+                "PyOxidizer requires Rust {}; version {} found",
+                *MINIMUM_RUST_VERSION,
+                version
+            ));
+        }
+
+        let output = std::process::Command::new(&self.rustc_path)
+            .args(&["--print", "sysroot"])
+            .output()
+            .with_context(|| format!("running {}", self.rustc_path.display()))?;
+        let sysroot = PathBuf::from(String::from_utf8_lossy(&output.stdout).trim());
+
+        if !sysroot.join("lib").join("rustlib").join(target).is_dir() {
+            return Err(anyhow!(
+                "Rust standard library for {} is not installed; run `rustup target add {}`",
+                target,
+                target
+            ));
+        }
+
+        Ok(version)
+    }
+
+    /// Environment variables to set when invoking `cargo`.
+    ///
+    /// Ensures `cargo` invokes this toolchain's `rustc`.
+    pub fn cargo_envs(&self) -> Vec<(&'static str, String)> {
+        vec![("RUSTC", self.rustc_path.display().to_string())]
+    }
+}
+
+/// URL of a Rust installer archive, e.g. `rust-1.43.1-x86_64-unknown-linux-gnu`.
+pub fn rust_archive_url(archive: &str) -> String {
+    format!("{}/{}.tar.gz", RUST_DIST_URL, archive)
+}
+
+/// Download a file from static.rust-lang.org, verifying its published SHA-256.
+fn download_verified(logger: &slog::Logger, url: &str) -> Result<Vec<u8>> {
+    let client = get_http_client()?;
+
+    let mut expected = String::new();
+    client
+        .get(&format!("{}.sha256", url))
+        .send()?
+        .error_for_status()?
+        .read_to_string(&mut expected)?;
+    let expected = expected
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow!("empty SHA-256 for {}", url))?;
+    let expected = hex::decode(expected)?;
+
+    warn!(logger, "downloading {}", url);
+    let mut data = Vec::new();
+    client
+        .get(url)
+        .send()?
+        .error_for_status()?
+        .read_to_end(&mut data)?;
+
+    let mut hasher = Sha256::new();
+    hasher.input(&data);
+
+    if hasher.result().to_vec() != expected {
+        return Err(anyhow!("sha256 of {} does not validate", url));
+    }
+
+    Ok(data)
+}
+
+/// Install the components of a Rust installer archive into a directory.
+///
+/// Installer archives contain a directory per component, each holding files
+/// relative to the install prefix. This does what the archive's `install.sh`
+/// does, which can't run on Windows.
+fn install_archive(data: &[u8], install_dir: &Path) -> Result<()> {
+    let temp_dir = crate::scratch::scratch_dir("pyoxidizer-rust")?;
+
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(data));
+    archive.unpack(temp_dir.path())?;
+
+    let root = std::fs::read_dir(temp_dir.path())?
+        .next()
+        .ok_or_else(|| anyhow!("Rust archive is empty"))??
+        .path();
+
+    let components = std::fs::read_to_string(root.join("components"))
+        .context("reading components of Rust archive")?;
+
+    for component in components.lines().filter(|l| !l.is_empty()) {
+        let component_dir = root.join(component);
+
+        for entry in walkdir::WalkDir::new(&component_dir) {
+            let entry = entry?;
+            let rel_path = entry.path().strip_prefix(&component_dir)?;
+
+            if entry.file_type().is_dir() || rel_path == Path::new("manifest.in") {
+                continue;
+            }
+
+            let dest_path = install_dir.join(rel_path);
+            std::fs::create_dir_all(dest_path.parent().unwrap())?;
+            std::fs::copy(entry.path(), &dest_path)
+                .with_context(|| format!("installing {}", dest_path.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Ensure the pinned Rust toolchain is installed in a directory.
+///
+/// The toolchain for `host` and the standard library for `target` are
+/// downloaded and installed into a child directory of `toolchains_dir` if
+/// they aren't already there. If `download` is false, an error is raised
+/// instead of downloading anything.
+pub fn install_rust_toolchain(
+    logger: &slog::Logger,
+    toolchains_dir: &Path,
+    host: &str,
+    target: &str,
+    download: bool,
+) -> Result<RustToolchain> {
+    let install_dir = toolchains_dir.join(format!("rust-{}-{}", RUST_TOOLCHAIN_VERSION, host));
+
+    let mut archives = Vec::new();
+
+    // A marker is written once installation completes so interrupted
+    // installs are redone.
+    if !install_dir.join(".installed").exists() {
+        archives.push(format!("rust-{}-{}", RUST_TOOLCHAIN_VERSION, host));
+    }
+
+    if target != host
+        && !install_dir
+            .join("lib")
+            .join("rustlib")
+            .join(target)
+            .is_dir()
+    {
+        archives.push(format!("rust-std-{}-{}", RUST_TOOLCHAIN_VERSION, target));
+    }
+
+    if !archives.is_empty() && !download {
+        return Err(anyhow!(
+            "Rust {} for {} not found in {}; run `pyoxidizer vendor` first",
+            RUST_TOOLCHAIN_VERSION,
+            target,
+            toolchains_dir.display()
+        ));
+    }
+
+    for archive in &archives {
+        let url = rust_archive_url(archive);
+        let data = download_verified(logger, &url)?;
+
+        warn!(
+            logger,
+            "installing {} to {}",
+            archive,
+            install_dir.display()
+        );
+        install_archive(&data, &install_dir)?;
+
+        if archive.starts_with("rust-std-") {
+            continue;
+        }
+
+        std::fs::write(install_dir.join(".installed"), RUST_TOOLCHAIN_VERSION)?;
+    }
+
+    Ok(RustToolchain::from_install_dir(&install_dir))
+}
+
+/// Resolve the Rust toolchain to build executables with.
+///
+/// If `toolchains_dir` is defined, the pinned toolchain is installed there.
+/// Otherwise the toolchain in `PATH` is used. The toolchain is verified to be
+/// able to build for `target`.
+pub fn resolve_rust_toolchain(
+    logger: &slog::Logger,
+    toolchains_dir: Option<&Path>,
+    host: &str,
+    target: &str,
+    download: bool,
+) -> Result<RustToolchain> {
+    let toolchain = match toolchains_dir {
+        Some(path) => install_rust_toolchain(logger, path, host, target, download)?,
+        None => RustToolchain::system(),
+    };
+
+    let version = toolchain.verify(target)?;
+    warn!(logger, "building with Rust {}", version);
+
+    Ok(toolchain)
+}
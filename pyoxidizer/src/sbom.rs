@@ -0,0 +1,345 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Software bill of materials (SBOM) generation.
+
+An SBOM describes the components that went into a built artifact so supply
+chain tooling can reason about them. We support emitting SPDX (tag-value)
+and CycloneDX (JSON) documents.
+*/
+
+use {
+    crate::environment::PYOXIDIZER_VERSION,
+    crate::py_packaging::binary::PythonBinaryBuilder,
+    crate::reproducible::{build_time, format_timestamp},
+    anyhow::Result,
+    serde::Serialize,
+    sha2::{Digest, Sha256},
+    std::convert::TryFrom,
+};
+
+/// Formats of SBOM documents.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SbomFormat {
+    /// SPDX 2.2 in tag-value format.
+    Spdx,
+    /// CycloneDX 1.2 in JSON format.
+    CycloneDx,
+}
+
+impl SbomFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SbomFormat::Spdx => "spdx",
+            SbomFormat::CycloneDx => "cyclonedx",
+        }
+    }
+
+    /// Filename of the SBOM describing an artifact named `name`.
+    pub fn filename(&self, name: &str) -> String {
+        match self {
+            SbomFormat::Spdx => format!("{}.spdx", name),
+            SbomFormat::CycloneDx => format!("{}.cdx.json", name),
+        }
+    }
+}
+
+impl TryFrom<&str> for SbomFormat {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "spdx" => Ok(SbomFormat::Spdx),
+            "cyclonedx" => Ok(SbomFormat::CycloneDx),
+            t => Err(format!(
+                "{} is not a valid SBOM format; expected spdx or cyclonedx",
+                t
+            )),
+        }
+    }
+}
+
+/// The kind of a component in an SBOM.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum SbomComponentKind {
+    PythonInterpreter,
+    PythonPackage,
+    NativeLibrary,
+}
+
+/// A component described by an SBOM.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SbomComponent {
+    pub kind: SbomComponentKind,
+    pub name: String,
+    pub version: Option<String>,
+    pub licenses: Vec<String>,
+    /// Hex encoded SHA-256 identifying the component content.
+    pub sha256: Option<String>,
+}
+
+impl SbomComponent {
+    /// Package URL of this component, if it has one.
+    fn purl(&self) -> Option<String> {
+        match (self.kind, &self.version) {
+            (SbomComponentKind::PythonPackage, Some(version)) => Some(format!(
+                "pkg:pypi/{}@{}",
+                self.name.to_lowercase().replace('_', "-"),
+                version
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Resolve the components embedded in a Python binary.
+pub fn sbom_components(exe: &dyn PythonBinaryBuilder) -> Vec<SbomComponent> {
+    let mut res = Vec::new();
+
+    let python_licenses = exe
+        .licensed_components()
+        .into_iter()
+        .find(|c| c.name == "Python")
+        .map(|c| c.licenses)
+        .unwrap_or_else(Vec::new);
+
+    res.push(SbomComponent {
+        kind: SbomComponentKind::PythonInterpreter,
+        name: "python".to_string(),
+        version: exe.python_version(),
+        licenses: python_licenses,
+        sha256: None,
+    });
+
+    for package in exe.package_licenses() {
+        res.push(SbomComponent {
+            kind: SbomComponentKind::PythonPackage,
+            name: package.name,
+            version: package.version,
+            licenses: package.licenses,
+            sha256: package.sha256,
+        });
+    }
+
+    for library in exe.linked_libraries() {
+        res.push(SbomComponent {
+            kind: SbomComponentKind::NativeLibrary,
+            name: library,
+            version: None,
+            licenses: Vec::new(),
+            sha256: None,
+        });
+    }
+
+    res
+}
+
+/// Derive a stable identifier for a document describing `components`.
+fn document_id(name: &str, components: &[SbomComponent]) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.input(name.as_bytes());
+    hasher.input(&serde_json::to_vec(components)?);
+
+    Ok(
+        uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_URL, &hasher.result())
+            .to_hyphenated()
+            .to_string(),
+    )
+}
+
+/// Render licenses as an SPDX license expression, if possible.
+fn spdx_license_expression(licenses: &[String]) -> String {
+    // Free-form license names from package metadata aren't valid SPDX
+    // identifiers.
+    if licenses.is_empty() || licenses.iter().any(|l| l.contains(char::is_whitespace)) {
+        "NOASSERTION".to_string()
+    } else {
+        licenses.join(" AND ")
+    }
+}
+
+/// Render an SPDX tag-value document describing an artifact named `name`.
+pub fn render_spdx(name: &str, components: &[SbomComponent]) -> Result<String> {
+    let mut lines = vec![
+        "SPDXVersion: SPDX-2.2".to_string(),
+        "DataLicense: CC0-1.0".to_string(),
+        "SPDXID: SPDXRef-DOCUMENT".to_string(),
+        format!("DocumentName: {}", name),
+        format!(
+            "DocumentNamespace: https://pyoxidizer.readthedocs.io/spdx/{}-{}",
+            name,
+            document_id(name, components)?
+        ),
+        format!("Creator: Tool: pyoxidizer-{}", PYOXIDIZER_VERSION),
+        format!("Created: {}", format_timestamp(build_time())),
+        "".to_string(),
+        format!("PackageName: {}", name),
+        "SPDXID: SPDXRef-Package-0".to_string(),
+        "PackageDownloadLocation: NOASSERTION".to_string(),
+        "FilesAnalyzed: false".to_string(),
+        "PackageLicenseConcluded: NOASSERTION".to_string(),
+        "PackageLicenseDeclared: NOASSERTION".to_string(),
+        "PackageCopyrightText: NOASSERTION".to_string(),
+        "Relationship: SPDXRef-DOCUMENT DESCRIBES SPDXRef-Package-0".to_string(),
+    ];
+
+    for (i, component) in components.iter().enumerate() {
+        let id = format!("SPDXRef-Package-{}", i + 1);
+
+        lines.push("".to_string());
+        lines.push(format!("PackageName: {}", component.name));
+        lines.push(format!("SPDXID: {}", id));
+        if let Some(version) = &component.version {
+            lines.push(format!("PackageVersion: {}", version));
+        }
+        lines.push("PackageDownloadLocation: NOASSERTION".to_string());
+        lines.push("FilesAnalyzed: false".to_string());
+        if let Some(sha256) = &component.sha256 {
+            lines.push(format!("PackageChecksum: SHA256: {}", sha256));
+        }
+        lines.push("PackageLicenseConcluded: NOASSERTION".to_string());
+        let expression = spdx_license_expression(&component.licenses);
+        if expression == "NOASSERTION" && !component.licenses.is_empty() {
+            lines.push(format!(
+                "PackageLicenseComments: <text>{}</text>",
+                component.licenses.join(", ")
+            ));
+        }
+        lines.push(format!("PackageLicenseDeclared: {}", expression));
+        lines.push("PackageCopyrightText: NOASSERTION".to_string());
+        if let Some(purl) = component.purl() {
+            lines.push(format!("ExternalRef: PACKAGE-MANAGER purl {}", purl));
+        }
+        lines.push(format!("Relationship: SPDXRef-Package-0 CONTAINS {}", id));
+    }
+
+    lines.push("".to_string());
+
+    Ok(lines.join("\n"))
+}
+
+/// Render a CycloneDX JSON document describing an artifact named `name`.
+pub fn render_cyclonedx(name: &str, components: &[SbomComponent]) -> Result<String> {
+    let serial_number = format!("urn:uuid:{}", document_id(name, components)?);
+
+    let components = components
+        .iter()
+        .map(|component| {
+            let mut value = serde_json::json!({
+                "type": match component.kind {
+                    SbomComponentKind::PythonInterpreter => "application",
+                    SbomComponentKind::PythonPackage => "library",
+                    SbomComponentKind::NativeLibrary => "library",
+                },
+                "name": component.name,
+                "version": component.version.clone().unwrap_or_else(|| "".to_string()),
+                "licenses": component
+                    .licenses
+                    .iter()
+                    .map(|l| serde_json::json!({"license": {"name": l}}))
+                    .collect::<Vec<_>>(),
+            });
+
+            if let Some(sha256) = &component.sha256 {
+                value["hashes"] = serde_json::json!([{"alg": "SHA-256", "content": sha256}]);
+            }
+            if let Some(purl) = component.purl() {
+                value["purl"] = serde_json::json!(purl);
+            }
+
+            value
+        })
+        .collect::<Vec<_>>();
+
+    let document = serde_json::json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.2",
+        "serialNumber": serial_number,
+        "version": 1,
+        "metadata": {
+            "timestamp": format_timestamp(build_time()),
+            "tools": [{"vendor": "PyOxidizer", "name": "pyoxidizer", "version": PYOXIDIZER_VERSION}],
+            "component": {"type": "application", "name": name},
+        },
+        "components": components,
+    });
+
+    Ok(serde_json::to_string_pretty(&document)?)
+}
+
+/// Render an SBOM document in the given format.
+pub fn render_sbom(
+    format: SbomFormat,
+    name: &str,
+    exe: &dyn PythonBinaryBuilder,
+) -> Result<String> {
+    let components = sbom_components(exe);
+
+    match format {
+        SbomFormat::Spdx => render_spdx(name, &components),
+        SbomFormat::CycloneDx => render_cyclonedx(name, &components),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn components() -> Vec<SbomComponent> {
+        vec![
+            SbomComponent {
+                kind: SbomComponentKind::PythonInterpreter,
+                name: "python".to_string(),
+                version: Some("3.7.6".to_string()),
+                licenses: vec!["Python-2.0".to_string()],
+                sha256: None,
+            },
+            SbomComponent {
+                kind: SbomComponentKind::PythonPackage,
+                name: "Foo_Bar".to_string(),
+                version: Some("1.0".to_string()),
+                licenses: vec!["MIT License".to_string()],
+                sha256: Some("00".repeat(32)),
+            },
+            SbomComponent {
+                kind: SbomComponentKind::NativeLibrary,
+                name: "ssl".to_string(),
+                version: None,
+                licenses: vec![],
+                sha256: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_render_spdx() -> Result<()> {
+        let doc = render_spdx("myapp", &components())?;
+
+        assert!(doc.starts_with("SPDXVersion: SPDX-2.2\n"));
+        assert!(doc.contains("PackageName: Foo_Bar\nSPDXID: SPDXRef-Package-2\n"));
+        assert!(doc.contains(&format!("PackageChecksum: SHA256: {}\n", "00".repeat(32))));
+        assert!(doc.contains("PackageLicenseDeclared: Python-2.0\n"));
+        assert!(doc.contains("PackageLicenseComments: <text>MIT License</text>\n"));
+        assert!(doc.contains("ExternalRef: PACKAGE-MANAGER purl pkg:pypi/foo-bar@1.0\n"));
+        assert!(doc.contains("Relationship: SPDXRef-Package-0 CONTAINS SPDXRef-Package-3\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_render_cyclonedx() -> Result<()> {
+        let doc: serde_json::Value =
+            serde_json::from_str(&render_cyclonedx("myapp", &components())?)?;
+
+        assert_eq!(doc["bomFormat"], "CycloneDX");
+        assert_eq!(doc["metadata"]["component"]["name"], "myapp");
+        assert_eq!(doc["components"].as_array().unwrap().len(), 3);
+        assert_eq!(doc["components"][1]["purl"], "pkg:pypi/foo-bar@1.0");
+        assert_eq!(doc["components"][1]["hashes"][0]["alg"], "SHA-256");
+        assert_eq!(doc["components"][2]["name"], "ssl");
+
+        Ok(())
+    }
+}
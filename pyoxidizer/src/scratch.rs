@@ -0,0 +1,285 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Scratch directories holding temporary build state.
+
+Building an executable creates temporary directories holding generated Rust
+projects, extracted distributions, and compiled objects, often totalling
+gigabytes. They are created in a scratch directory, the system's temporary
+directory unless configured otherwise, and removed when no longer needed.
+
+Directories of interrupted builds are left behind. A size limit can be
+defined for the scratch directory, in which case the oldest leftovers are
+removed when builds start. `pyoxidizer clean` removes all of them.
+*/
+
+use {
+    crate::size_budget::{format_size, parse_size},
+    anyhow::{anyhow, Context, Result},
+    slog::warn,
+    std::path::{Path, PathBuf},
+    std::time::{Duration, SystemTime},
+};
+
+/// Environment variable defining the directory scratch directories are created in.
+///
+/// The `--scratch-path` argument of `pyoxidizer` sets it.
+pub const SCRATCH_PATH_ENV: &str = "PYOXIDIZER_SCRATCH_PATH";
+
+/// Environment variable limiting the size of leftover scratch directories.
+///
+/// The `--scratch-max-size` argument of `pyoxidizer` sets it.
+pub const SCRATCH_MAX_SIZE_ENV: &str = "PYOXIDIZER_SCRATCH_MAX_SIZE";
+
+/// Prefix of the names of scratch directories.
+///
+/// Only entries with this prefix are considered ours, as the scratch
+/// directory may be shared with other programs.
+pub const SCRATCH_PREFIX: &str = "pyoxidizer";
+
+/// Scratch directories modified more recently are assumed to be in use.
+const SCRATCH_MIN_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Obtain the directory scratch directories are created in.
+pub fn scratch_path() -> PathBuf {
+    match std::env::var_os(SCRATCH_PATH_ENV) {
+        Some(value) if !value.is_empty() => PathBuf::from(value),
+        _ => std::env::temp_dir(),
+    }
+}
+
+/// Obtain the size limit of leftover scratch directories, in bytes.
+pub fn scratch_max_size() -> Result<Option<u64>> {
+    match std::env::var(SCRATCH_MAX_SIZE_ENV) {
+        Ok(value) if !value.is_empty() => {
+            Ok(Some(parse_size(&value).map_err(|e| {
+                anyhow!("invalid {}: {}", SCRATCH_MAX_SIZE_ENV, e)
+            })?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Create a scratch directory, which is removed when dropped.
+pub fn scratch_dir(prefix: &str) -> Result<tempdir::TempDir> {
+    let path = scratch_path();
+    std::fs::create_dir_all(&path).context(format!("creating {}", path.display()))?;
+
+    let prefix = if prefix.starts_with(SCRATCH_PREFIX) {
+        prefix.to_string()
+    } else {
+        format!("{}-{}", SCRATCH_PREFIX, prefix)
+    };
+
+    tempdir::TempDir::new_in(&path, &prefix)
+        .context(format!("creating scratch directory in {}", path.display()))
+}
+
+/// A scratch directory left in the scratch path.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScratchEntry {
+    /// Path to the entry.
+    pub path: PathBuf,
+
+    /// Total size of files in the entry, in bytes.
+    pub size: u64,
+
+    /// Most recent modification time of the entry and files in it.
+    pub modified: SystemTime,
+}
+
+impl ScratchEntry {
+    /// Describe a file or directory.
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let mut size = 0;
+        let mut modified = std::fs::symlink_metadata(path)
+            .context(format!("reading {}", path.display()))?
+            .modified()?;
+
+        // Files can disappear while a build is using the directory.
+        for f in walkdir::WalkDir::new(path).into_iter().flatten() {
+            if let Ok(metadata) = f.metadata() {
+                if metadata.is_file() {
+                    size += metadata.len();
+                }
+                if let Ok(t) = metadata.modified() {
+                    if t > modified {
+                        modified = t;
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            size,
+            modified,
+        })
+    }
+}
+
+/// Find scratch directories left in a directory.
+pub fn find_scratch_entries(path: &Path) -> Result<Vec<ScratchEntry>> {
+    let mut entries = Vec::new();
+
+    if !path.is_dir() {
+        return Ok(entries);
+    }
+
+    for entry in std::fs::read_dir(path).context(format!("reading {}", path.display()))? {
+        let entry = entry?;
+
+        if !entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(SCRATCH_PREFIX)
+        {
+            continue;
+        }
+
+        entries.push(ScratchEntry::from_path(&entry.path())?);
+    }
+
+    entries.sort_by(|a, b| a.modified.cmp(&b.modified).then(a.path.cmp(&b.path)));
+
+    Ok(entries)
+}
+
+/// Select the entries to remove so the remaining ones fit in `max_size`.
+///
+/// The oldest entries are selected first. Entries modified after `cutoff`
+/// may belong to running builds and are never selected.
+pub fn entries_exceeding_size(
+    entries: &[ScratchEntry],
+    max_size: u64,
+    cutoff: SystemTime,
+) -> Vec<ScratchEntry> {
+    let mut total: u64 = entries.iter().map(|e| e.size).sum();
+    let mut remove = Vec::new();
+
+    for entry in entries {
+        if total <= max_size {
+            break;
+        }
+
+        if entry.modified < cutoff {
+            total -= entry.size;
+            remove.push(entry.clone());
+        }
+    }
+
+    remove
+}
+
+/// Remove scratch directories.
+///
+/// Returns the number of bytes freed.
+pub fn remove_scratch_entries(logger: &slog::Logger, entries: &[ScratchEntry]) -> Result<u64> {
+    let mut freed = 0;
+
+    for entry in entries {
+        warn!(
+            logger,
+            "removing {} ({})",
+            entry.path.display(),
+            format_size(entry.size)
+        );
+
+        if entry.path.is_dir() {
+            std::fs::remove_dir_all(&entry.path)
+        } else {
+            std::fs::remove_file(&entry.path)
+        }
+        .context(format!("removing {}", entry.path.display()))?;
+
+        freed += entry.size;
+    }
+
+    Ok(freed)
+}
+
+/// Remove the oldest leftover scratch directories exceeding the size limit.
+pub fn enforce_scratch_max_size(logger: &slog::Logger) -> Result<()> {
+    let max_size = match scratch_max_size()? {
+        Some(size) => size,
+        None => return Ok(()),
+    };
+
+    let cutoff = SystemTime::now()
+        .checked_sub(SCRATCH_MIN_AGE)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let entries = find_scratch_entries(&scratch_path())?;
+
+    remove_scratch_entries(logger, &entries_exceeding_size(&entries, max_size, cutoff))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, size: u64, age: u64) -> ScratchEntry {
+        ScratchEntry {
+            path: PathBuf::from(name),
+            size,
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(10_000 - age),
+        }
+    }
+
+    #[test]
+    fn test_entries_exceeding_size() {
+        let entries = vec![
+            entry("a", 500, 5000),
+            entry("b", 300, 4000),
+            entry("c", 200, 3000),
+            entry("d", 400, 10),
+        ];
+        let cutoff = SystemTime::UNIX_EPOCH + Duration::from_secs(9_000);
+
+        assert!(entries_exceeding_size(&entries, 2000, cutoff).is_empty());
+        assert_eq!(
+            entries_exceeding_size(&entries, 900, cutoff),
+            vec![entries[0].clone()]
+        );
+        assert_eq!(
+            entries_exceeding_size(&entries, 0, cutoff),
+            entries[0..3].to_vec()
+        );
+    }
+
+    #[test]
+    fn test_find_scratch_entries() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+        let path = temp_dir.path();
+
+        std::fs::create_dir_all(path.join("pyoxidizer-build-exe.1234/project"))?;
+        std::fs::write(
+            path.join("pyoxidizer-build-exe.1234/project/main.rs"),
+            b"1234",
+        )?;
+        std::fs::write(path.join("pyoxidizer-lockfile.5678"), b"12")?;
+        std::fs::create_dir(path.join("other"))?;
+
+        let entries = find_scratch_entries(path)?;
+        let mut names = entries
+            .iter()
+            .map(|e| e.path.file_name().unwrap().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["pyoxidizer-build-exe.1234", "pyoxidizer-lockfile.5678"]
+        );
+        assert_eq!(entries.iter().map(|e| e.size).sum::<u64>(), 6);
+
+        let logger = crate::testutil::get_logger()?;
+        remove_scratch_entries(&logger, &entries)?;
+        assert!(find_scratch_entries(path)?.is_empty());
+        assert!(path.join("other").is_dir());
+
+        Ok(())
+    }
+}
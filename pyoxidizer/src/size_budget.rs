@@ -0,0 +1,247 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Binary size budgets.
+
+A budget limits the size of a built executable and, optionally, the size of
+resources embedded for individual Python packages. Builds exceeding a budget
+fail with a report of the largest packages, so dependency bloat is noticed
+when it is introduced.
+*/
+
+use {
+    crate::py_packaging::binary::PythonBinaryBuilder,
+    crate::py_packaging::resource::DataLocation,
+    anyhow::{anyhow, Result},
+    std::collections::BTreeMap,
+};
+
+/// Number of packages listed in size reports.
+const REPORT_PACKAGE_COUNT: usize = 10;
+
+/// Size units accepted by `parse_size()` and their number of bytes.
+const SIZE_UNITS: &[(&str, u64)] = &[
+    ("B", 1),
+    ("KB", 1_000),
+    ("MB", 1_000_000),
+    ("GB", 1_000_000_000),
+    ("KIB", 1 << 10),
+    ("MIB", 1 << 20),
+    ("GIB", 1 << 30),
+];
+
+/// Parse a human readable size like `60 MB` or `1.5 GiB` into bytes.
+///
+/// Units are case insensitive. `KB`, `MB`, and `GB` are powers of 1000 and
+/// `KiB`, `MiB`, and `GiB` powers of 1024. A number without unit is bytes.
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let pos = value
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(value.len());
+    let (number, unit) = (&value[0..pos], value[pos..].trim().to_uppercase());
+
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("{} is not a valid size", value))?;
+
+    let multiplier = if unit.is_empty() {
+        1
+    } else {
+        SIZE_UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .map(|(_, multiplier)| *multiplier)
+            .ok_or_else(|| format!("{} is not a valid size unit", unit))?
+    };
+
+    Ok((number * multiplier as f64) as u64)
+}
+
+/// Format a size in bytes for humans.
+pub fn format_size(size: u64) -> String {
+    if size >= 1_000_000 {
+        format!("{:.1} MB", size as f64 / 1_000_000.0)
+    } else if size >= 1_000 {
+        format!("{:.1} KB", size as f64 / 1_000.0)
+    } else {
+        format!("{} B", size)
+    }
+}
+
+/// Size limits of a built executable.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SizeBudget {
+    /// Maximum size of the executable and its resources file, in bytes.
+    pub total: Option<u64>,
+
+    /// Maximum size of resources embedded for packages, in bytes.
+    ///
+    /// Limits apply to the package and its sub-packages.
+    pub packages: BTreeMap<String, u64>,
+}
+
+impl SizeBudget {
+    /// Whether the budget has no limits.
+    pub fn is_empty(&self) -> bool {
+        self.total.is_none() && self.packages.is_empty()
+    }
+}
+
+fn data_location_size(location: &DataLocation) -> Result<u64> {
+    Ok(match location {
+        DataLocation::Path(path) => std::fs::metadata(path)?.len(),
+        DataLocation::Memory(data) => data.len() as u64,
+    })
+}
+
+/// Obtain the names and sizes of resources embedded in a binary.
+///
+/// Sizes of bytecode modules are approximated by the size of their source,
+/// as bytecode is only compiled when the binary is built. Resource data is
+/// attributed to its package. Extension modules of the Python distribution
+/// are not included.
+pub fn resource_sizes(exe: &dyn PythonBinaryBuilder) -> Result<Vec<(String, u64)>> {
+    let mut sizes = Vec::new();
+
+    for (name, module) in exe.source_modules() {
+        sizes.push((name, data_location_size(&module.source)?));
+    }
+
+    for (name, module) in exe.bytecode_modules() {
+        sizes.push((name, data_location_size(&module.source)?));
+    }
+
+    for (package, resources) in exe.resources() {
+        for data in resources.values() {
+            sizes.push((package.clone(), data.len() as u64));
+        }
+    }
+
+    for (name, em) in exe.extension_module_datas() {
+        let size = match &em.extension_data {
+            Some(data) => data.len() as u64,
+            None => em.object_file_data.iter().map(|d| d.len() as u64).sum(),
+        };
+        sizes.push((name, size));
+    }
+
+    Ok(sizes)
+}
+
+/// Obtain the size of a package and its sub-packages.
+fn package_size(sizes: &[(String, u64)], package: &str) -> u64 {
+    let prefix = format!("{}.", package);
+
+    sizes
+        .iter()
+        .filter(|(name, _)| name == package || name.starts_with(&prefix))
+        .map(|(_, size)| size)
+        .sum()
+}
+
+/// Describe the largest top-level packages, for inclusion in error messages.
+fn size_report(sizes: &[(String, u64)]) -> String {
+    let mut packages = BTreeMap::new();
+    for (name, size) in sizes {
+        let package = name.split('.').next().unwrap_or(name);
+        *packages.entry(package).or_insert(0) += *size;
+    }
+
+    let mut packages: Vec<(&str, u64)> = packages.into_iter().collect();
+    packages.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+    let mut lines = vec!["largest packages:".to_string()];
+    for (package, size) in packages.into_iter().take(REPORT_PACKAGE_COUNT) {
+        lines.push(format!("  {:>10}  {}", format_size(size), package));
+    }
+
+    lines.join("\n")
+}
+
+/// Ensure a built executable fits in a size budget.
+///
+/// `total` is the size of the executable and its resources file. `sizes` is
+/// the size of each embedded resource, as returned by `resource_sizes()`.
+pub fn check_size_budget(budget: &SizeBudget, total: u64, sizes: &[(String, u64)]) -> Result<()> {
+    let mut problems = Vec::new();
+
+    if let Some(limit) = budget.total {
+        if total > limit {
+            problems.push(format!(
+                "executable is {}, exceeding its budget of {}",
+                format_size(total),
+                format_size(limit)
+            ));
+        }
+    }
+
+    for (package, limit) in &budget.packages {
+        let size = package_size(sizes, package);
+
+        if size > *limit {
+            problems.push(format!(
+                "package {} is {}, exceeding its budget of {}",
+                package,
+                format_size(size),
+                format_size(*limit)
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "size budget exceeded:\n{}\n{}",
+            problems.join("\n"),
+            size_report(sizes)
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("60 MB"), Ok(60_000_000));
+        assert_eq!(parse_size("60mb"), Ok(60_000_000));
+        assert_eq!(parse_size("1.5 GiB"), Ok(1_610_612_736));
+        assert_eq!(parse_size("512 KiB"), Ok(524_288));
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("60 parsecs").is_err());
+    }
+
+    #[test]
+    fn test_check_size_budget() {
+        let sizes = vec![
+            ("numpy".to_string(), 5_000_000),
+            ("numpy.linalg".to_string(), 15_000_000),
+            ("numpyro".to_string(), 1_000_000),
+            ("json".to_string(), 50_000),
+        ];
+
+        let mut budget = SizeBudget {
+            total: Some(60_000_000),
+            packages: BTreeMap::new(),
+        };
+        assert!(check_size_budget(&budget, 50_000_000, &sizes).is_ok());
+
+        budget.packages.insert("numpy".to_string(), 10_000_000);
+        budget
+            .packages
+            .insert("numpy.linalg".to_string(), 20_000_000);
+        let err = check_size_budget(&budget, 70_000_000, &sizes)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("executable is 70.0 MB, exceeding its budget of 60.0 MB"));
+        assert!(err.contains("package numpy is 20.0 MB, exceeding its budget of 10.0 MB"));
+        assert!(!err.contains("package numpy.linalg"));
+        assert!(err.contains("20.0 MB  numpy\n"));
+    }
+}
@@ -0,0 +1,297 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Machine-readable manifests describing built targets.
+
+After targets are built, a JSON document describing each built target and
+the files it produced is written to the build directory. This allows
+consumers like CI pipelines to locate artifacts without knowledge of
+PyOxidizer's directory layout.
+*/
+
+use {
+    super::env::EnvironmentContext,
+    super::python_executable::PythonExecutable,
+    super::python_zipapp::PythonZipApp,
+    crate::environment::PYOXIDIZER_VERSION,
+    crate::py_packaging::binary::PythonBinaryBuilder,
+    crate::py_packaging::zipapp::ZipAppBuilder,
+    anyhow::{Context, Result},
+    serde::Serialize,
+    sha2::{Digest, Sha256},
+    std::collections::BTreeSet,
+    std::io::Read,
+    std::ops::Deref,
+    std::path::{Path, PathBuf},
+};
+
+/// Filename of written build manifests.
+pub const BUILD_MANIFEST_FILENAME: &str = "build-manifest.json";
+
+/// Describes a file produced by a target.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BuildArtifact {
+    /// Path of the file, relative to the target's output directory.
+    pub path: String,
+
+    /// Size of the file in bytes.
+    pub size: u64,
+
+    /// Hex encoded SHA-256 of the file content.
+    pub sha256: String,
+}
+
+/// Counts of Python resources embedded in a target.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ResourceCounts {
+    pub source_modules: usize,
+    pub bytecode_modules: usize,
+    pub resources: usize,
+    pub extension_modules: usize,
+}
+
+impl ResourceCounts {
+    pub fn from_binary_builder(exe: &dyn PythonBinaryBuilder) -> Self {
+        ResourceCounts {
+            source_modules: exe.source_modules().len(),
+            bytecode_modules: exe.bytecode_modules().len(),
+            resources: exe.resources().values().map(|r| r.len()).sum(),
+            extension_modules: exe.extension_modules().len() + exe.extension_module_datas().len(),
+        }
+    }
+
+    pub fn from_zipapp(zipapp: &ZipAppBuilder) -> Self {
+        ResourceCounts {
+            source_modules: zipapp.source_modules().len(),
+            bytecode_modules: zipapp.bytecode_modules().len(),
+            resources: zipapp.resources().len(),
+            extension_modules: 0,
+        }
+    }
+}
+
+/// Describes a built target.
+#[derive(Clone, Debug, Serialize)]
+pub struct TargetManifest {
+    /// Name of the target.
+    pub name: String,
+
+    /// Starlark type of the value the target resolved to.
+    pub target_type: String,
+
+    /// Directory the target's files were written to.
+    pub output_path: PathBuf,
+
+    /// Version of Python embedded in the target, if known.
+    pub python_version: Option<String>,
+
+    /// Python resources in the target, if the target holds Python resources.
+    pub resource_counts: Option<ResourceCounts>,
+
+    /// Files written by the target.
+    pub artifacts: Vec<BuildArtifact>,
+
+    /// Path to the software bill of materials describing the target, if written.
+    pub sbom_path: Option<PathBuf>,
+}
+
+/// Describes all targets built by an invocation.
+#[derive(Clone, Debug, Serialize)]
+pub struct BuildManifest {
+    pub pyoxidizer_version: String,
+    pub host_triple: String,
+    pub target_triple: String,
+    pub release: bool,
+    pub targets: Vec<TargetManifest>,
+}
+
+/// Hash a file without reading it into memory.
+///
+/// Returns the size of the file and its hex encoded SHA-256.
+fn hash_file(path: &Path) -> Result<(u64, String)> {
+    let mut fh =
+        std::fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    let mut buffer = [0; 32768];
+
+    loop {
+        let count = fh
+            .read(&mut buffer)
+            .with_context(|| format!("reading {}", path.display()))?;
+        if count == 0 {
+            break;
+        }
+        hasher.input(&buffer[..count]);
+        size += count as u64;
+    }
+
+    Ok((size, hex::encode(hasher.result())))
+}
+
+/// Resolve files written by a target as `BuildArtifact` instances.
+///
+/// `paths` are the files and directories the target wrote. Every file
+/// beneath a directory is an artifact. Artifact paths are relative to
+/// `output_path`, or are the filename of paths outside it.
+pub fn artifacts_from_paths(output_path: &Path, paths: &[PathBuf]) -> Result<Vec<BuildArtifact>> {
+    let mut res = Vec::new();
+    let mut seen = BTreeSet::new();
+
+    for path in paths {
+        for entry in walkdir::WalkDir::new(path) {
+            let entry = entry?;
+
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let rel_path = match entry.path().strip_prefix(output_path) {
+                Ok(p) if p.components().next().is_some() => p.to_path_buf(),
+                _ => PathBuf::from(entry.file_name()),
+            };
+
+            // Normalize to / so manifests are identical across platforms.
+            let rel_path = rel_path
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("/");
+
+            if !seen.insert(rel_path.clone()) {
+                continue;
+            }
+
+            let (size, sha256) = hash_file(entry.path())?;
+
+            res.push(BuildArtifact {
+                path: rel_path,
+                size,
+                sha256,
+            });
+        }
+    }
+
+    res.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(res)
+}
+
+impl BuildManifest {
+    /// Construct an instance from targets that have been built.
+    pub fn from_context(context: &EnvironmentContext) -> Result<Self> {
+        let mut targets = Vec::new();
+
+        for name in &context.targets_order {
+            let target = &context.targets[name];
+
+            let (built, value) = match (&target.built_target, &target.resolved_value) {
+                (Some(built), Some(value)) => (built, value),
+                _ => continue,
+            };
+
+            let (python_version, resource_counts, sbom_path) = match value.get_type() {
+                "PythonExecutable" => value.downcast_apply(|x: &PythonExecutable| {
+                    (
+                        x.exe.python_version(),
+                        Some(ResourceCounts::from_binary_builder(x.exe.deref())),
+                        x.build_options
+                            .sbom
+                            .map(|format| built.output_path.join(format.filename(&x.exe.name()))),
+                    )
+                }),
+                "PythonZipApp" => value.downcast_apply(|x: &PythonZipApp| {
+                    (None, Some(ResourceCounts::from_zipapp(&x.zipapp)), None)
+                }),
+                _ => (None, None, None),
+            };
+
+            targets.push(TargetManifest {
+                name: name.clone(),
+                target_type: value.get_type().to_string(),
+                output_path: built.output_path.clone(),
+                python_version,
+                resource_counts,
+                artifacts: artifacts_from_paths(&built.output_path, &built.artifacts)?,
+                sbom_path,
+            });
+        }
+
+        Ok(BuildManifest {
+            pyoxidizer_version: PYOXIDIZER_VERSION.to_string(),
+            host_triple: context.build_host_triple.clone(),
+            target_triple: context.build_target_triple.clone(),
+            release: context.build_release,
+            targets,
+        })
+    }
+
+    /// Write the manifest as JSON to the build directory for the context.
+    ///
+    /// Returns the path the manifest was written to.
+    pub fn write(&self, context: &EnvironmentContext) -> Result<PathBuf> {
+        let path = context
+            .build_path
+            .join(&self.target_triple)
+            .join(if self.release { "release" } else { "debug" })
+            .join(BUILD_MANIFEST_FILENAME);
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context("creating build manifest directory")?;
+        }
+
+        let data = serde_json::to_vec_pretty(self)?;
+        std::fs::write(&path, data).with_context(|| format!("writing {}", path.display()))?;
+
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_artifacts_from_paths() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+        let path = temp_dir.path();
+
+        std::fs::create_dir(path.join("lib"))?;
+        std::fs::write(path.join("app"), b"foo")?;
+        std::fs::write(path.join("lib").join("data"), b"")?;
+        // Written by another target sharing the output directory.
+        std::fs::write(path.join("other"), b"bar")?;
+
+        assert_eq!(
+            artifacts_from_paths(path, &[path.join("lib"), path.join("app")])?,
+            vec![
+                BuildArtifact {
+                    path: "app".to_string(),
+                    size: 3,
+                    sha256: "2c26b46b68ffc68ff99b453c1d30413413422d706483bfa0f98a5e886266e7ae"
+                        .to_string(),
+                },
+                BuildArtifact {
+                    path: "lib/data".to_string(),
+                    size: 0,
+                    sha256: "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+                        .to_string(),
+                },
+            ]
+        );
+
+        // A file output path is described by its filename.
+        assert_eq!(
+            artifacts_from_paths(&path.join("app"), &[path.join("app")])?
+                .iter()
+                .map(|a| a.path.as_str())
+                .collect::<Vec<_>>(),
+            vec!["app"]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,535 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Executables built during configuration file evaluation.
+
+`PythonExecutable.build()` builds an executable immediately and returns a
+`BuiltExecutable`. Its methods post-process the built file, so signing,
+stripping, and staging executables can be expressed in configuration files.
+Executables can also be run to generate files installed next to them, such
+as shell completions and man pages. A `BuiltExecutable` returned by a target
+function is the built target.
+*/
+
+use {
+    super::env::EnvironmentContext,
+    super::file_resource::FileManifest,
+    super::target::{BuildContext, BuildTarget, ResolvedTarget, RunMode},
+    super::util::{
+        optional_dict_arg, optional_list_arg, optional_str_arg, required_list_arg,
+        required_str_arg, required_type_arg,
+    },
+    crate::app_packaging::resource::FileContent,
+    crate::project_building::strip_executable,
+    anyhow::{anyhow, Context, Result},
+    slog::warn,
+    starlark::environment::Environment,
+    starlark::values::{default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult},
+    starlark::{
+        any, immutable, not_supported, starlark_fun, starlark_module, starlark_signature,
+        starlark_signature_extraction, starlark_signatures,
+    },
+    std::any::Any,
+    std::cmp::Ordering,
+    std::collections::{BTreeMap, HashMap},
+    std::path::{Path, PathBuf},
+};
+
+/// Placeholder for the executable path in signing commands.
+const SIGNER_PATH_PLACEHOLDER: &str = "{path}";
+
+/// Resolve the command line signing an executable.
+///
+/// Occurrences of `{path}` in arguments are replaced by the path to the
+/// executable. The path is appended if no argument references it.
+pub fn signer_command(signer: &[String], path: &Path) -> Result<Vec<String>> {
+    if signer.is_empty() {
+        return Err(anyhow!("signing command must not be empty"));
+    }
+
+    let path = path.display().to_string();
+    let mut command: Vec<String> = signer
+        .iter()
+        .map(|arg| arg.replace(SIGNER_PATH_PLACEHOLDER, &path))
+        .collect();
+
+    if !signer
+        .iter()
+        .any(|arg| arg.contains(SIGNER_PATH_PLACEHOLDER))
+    {
+        command.push(path);
+    }
+
+    Ok(command)
+}
+
+/// An executable that has been built.
+#[derive(Clone, Debug)]
+pub struct BuiltExecutable {
+    /// Path to the executable.
+    pub path: PathBuf,
+
+    /// Rust target triple the executable was built for.
+    pub target_triple: String,
+
+    /// Whether the executable was only pretended to be built.
+    ///
+    /// In dry run mode, the executable doesn't exist and methods don't
+    /// touch the filesystem.
+    pub dry_run: bool,
+}
+
+impl TypedValue for BuiltExecutable {
+    immutable!();
+    any!();
+    not_supported!(binop, container, function, get_hash, to_int);
+
+    fn to_str(&self) -> String {
+        format!("BuiltExecutable<path={}>", self.path.display())
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "BuiltExecutable"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+
+    fn get_attr(&self, attribute: &str) -> ValueResult {
+        let v = match attribute {
+            "path" => Value::new(self.path.display().to_string()),
+            "size" => Value::new(if self.dry_run {
+                0
+            } else {
+                std::fs::metadata(&self.path)
+                    .map_err(|e| self.runtime_error("size", e.into()))?
+                    .len() as i64
+            }),
+            "target_triple" => Value::new(self.target_triple.clone()),
+            attr => {
+                return Err(ValueError::OperationNotSupported {
+                    op: format!(".{}", attr),
+                    left: "BuiltExecutable".to_string(),
+                    right: None,
+                })
+            }
+        };
+
+        Ok(v)
+    }
+
+    fn has_attr(&self, attribute: &str) -> Result<bool, ValueError> {
+        Ok(match attribute {
+            "path" => true,
+            "size" => true,
+            "target_triple" => true,
+            _ => false,
+        })
+    }
+}
+
+impl BuildTarget for BuiltExecutable {
+    fn build(&mut self, context: &BuildContext) -> Result<ResolvedTarget> {
+        if self.dry_run {
+            return Err(anyhow!("executable was not built in dry run mode"));
+        }
+
+        Ok(ResolvedTarget {
+            run_mode: RunMode::Path {
+                path: self.path.clone(),
+            },
+            output_path: match self.path.parent() {
+                Some(parent) => parent.to_path_buf(),
+                None => context.output_path.clone(),
+            },
+            artifacts: vec![self.path.clone()],
+        })
+    }
+}
+
+impl BuiltExecutable {
+    fn runtime_error(&self, label: &str, e: anyhow::Error) -> ValueError {
+        RuntimeError {
+            code: "PYOXIDIZER_BUILD",
+            message: e.to_string(),
+            label: format!("{}()", label),
+        }
+        .into()
+    }
+
+    fn logger(env: &Environment) -> slog::Logger {
+        let context = env.get("CONTEXT").expect("CONTEXT not set");
+        context.downcast_apply(|x: &EnvironmentContext| x.logger.clone())
+    }
+
+    fn sign(&self, logger: &slog::Logger, command: &[String]) -> Result<()> {
+        warn!(
+            logger,
+            "signing {} with {}",
+            self.path.display(),
+            command[0]
+        );
+        let status = std::process::Command::new(&command[0])
+            .args(&command[1..])
+            .status()
+            .context(format!("running {}", command[0]))?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("signing {} failed", self.path.display()))
+        }
+    }
+
+    /// Run the executable, returning what it writes to stdout.
+    ///
+    /// The executable runs from its directory, so it finds files installed
+    /// next to it.
+    fn capture_output(
+        &self,
+        logger: &slog::Logger,
+        args: &[String],
+        envs: &BTreeMap<String, String>,
+    ) -> Result<Vec<u8>> {
+        warn!(logger, "running {} {}", self.path.display(), args.join(" "));
+
+        let mut command = std::process::Command::new(&self.path);
+        command
+            .args(args)
+            .envs(envs)
+            .stdin(std::process::Stdio::null());
+        if let Some(parent) = self.path.parent() {
+            command.current_dir(parent);
+        }
+
+        let output = command
+            .output()
+            .context(format!("running {}", self.path.display()))?;
+
+        if output.status.success() {
+            Ok(output.stdout)
+        } else {
+            Err(anyhow!(
+                "running {} failed with {}: {}",
+                self.path.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+
+    fn copy_to(&self, logger: &slog::Logger, dest: &Path) -> Result<PathBuf> {
+        let dest = if dest.is_dir() {
+            dest.join(
+                self.path
+                    .file_name()
+                    .ok_or_else(|| anyhow!("executable has no filename"))?,
+            )
+        } else {
+            dest.to_path_buf()
+        };
+
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent).context(format!("creating {}", parent.display()))?;
+        }
+
+        warn!(
+            logger,
+            "copying {} to {}",
+            self.path.display(),
+            dest.display()
+        );
+        std::fs::copy(&self.path, &dest).context(format!("copying to {}", dest.display()))?;
+
+        Ok(dest)
+    }
+}
+
+// Starlark functions.
+impl BuiltExecutable {
+    /// BuiltExecutable.sign(signer)
+    pub fn starlark_sign(&self, env: &Environment, signer: &Value) -> ValueResult {
+        required_list_arg("signer", "string", &signer)?;
+        let signer: Vec<String> = signer.into_iter()?.map(|x| x.to_string()).collect();
+        let command =
+            signer_command(&signer, &self.path).map_err(|e| self.runtime_error("sign", e))?;
+        let logger = Self::logger(env);
+
+        if self.dry_run {
+            warn!(logger, "dry run: not signing {}", self.path.display());
+        } else {
+            self.sign(&logger, &command)
+                .map_err(|e| self.runtime_error("sign", e))?;
+        }
+
+        Ok(Value::new(None))
+    }
+
+    /// BuiltExecutable.strip()
+    pub fn starlark_strip(&self, env: &Environment) -> ValueResult {
+        let logger = Self::logger(env);
+
+        if self.dry_run {
+            warn!(logger, "dry run: not stripping {}", self.path.display());
+        } else {
+            strip_executable(&logger, &self.path, &self.target_triple)
+                .map_err(|e| self.runtime_error("strip", e))?;
+        }
+
+        Ok(Value::new(None))
+    }
+
+    /// BuiltExecutable.capture_output(manifest, path, args=None, module=None, environment=None)
+    #[allow(clippy::too_many_arguments)]
+    pub fn starlark_capture_output(
+        &self,
+        env: &Environment,
+        manifest: &Value,
+        path: &Value,
+        args: &Value,
+        module: &Value,
+        environment: &Value,
+    ) -> ValueResult {
+        required_type_arg("manifest", "FileManifest", &manifest)?;
+        let path = required_str_arg("path", &path)?;
+        optional_list_arg("args", "string", &args)?;
+        let module = optional_str_arg("module", &module)?;
+        optional_dict_arg("environment", "string", "string", &environment)?;
+
+        let mut command_args = Vec::new();
+        if let Some(module) = module {
+            command_args.push("-m".to_string());
+            command_args.push(module);
+        }
+        if args.get_type() == "list" {
+            command_args.extend(args.into_iter()?.map(|x| x.to_string()));
+        }
+
+        let mut envs = BTreeMap::new();
+        if environment.get_type() == "dict" {
+            for key in environment.into_iter()? {
+                let value = environment.at(key.clone())?.to_string();
+                envs.insert(key.to_string(), value);
+            }
+        }
+
+        let context = env.get("CONTEXT").expect("CONTEXT not set");
+        let (logger, host_triple) = context.downcast_apply(|x: &EnvironmentContext| {
+            (x.logger.clone(), x.build_host_triple.clone())
+        });
+
+        if self.dry_run {
+            warn!(
+                logger,
+                "dry run: not running {} to generate {}",
+                self.path.display(),
+                path
+            );
+            return Ok(Value::new(None));
+        }
+
+        if self.target_triple != host_triple {
+            return Err(self.runtime_error(
+                "capture_output",
+                anyhow!(
+                    "executable built for {} can't run on {}",
+                    self.target_triple,
+                    host_triple
+                ),
+            ));
+        }
+
+        let data = self
+            .capture_output(&logger, &command_args, &envs)
+            .map_err(|e| self.runtime_error("capture_output", e))?;
+
+        let content = FileContent {
+            data,
+            executable: false,
+        };
+
+        manifest.downcast_apply_mut(|m: &mut FileManifest| {
+            m.manifest
+                .add_file(Path::new(&path), &content)
+                .map_err(|e| self.runtime_error("capture_output", e))
+        })?;
+
+        Ok(Value::new(None))
+    }
+
+    /// BuiltExecutable.copy_to(path)
+    pub fn starlark_copy_to(&self, env: &Environment, path: &Value) -> ValueResult {
+        let path = required_str_arg("path", &path)?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not set");
+        let (logger, cwd) =
+            context.downcast_apply(|x: &EnvironmentContext| (x.logger.clone(), x.cwd.clone()));
+        let dest = cwd.join(path);
+
+        let path = if self.dry_run {
+            warn!(
+                logger,
+                "dry run: not copying {} to {}",
+                self.path.display(),
+                dest.display()
+            );
+            dest
+        } else {
+            self.copy_to(&logger, &dest)
+                .map_err(|e| self.runtime_error("copy_to", e))?
+        };
+
+        Ok(Value::new(BuiltExecutable {
+            path,
+            target_triple: self.target_triple.clone(),
+            dry_run: self.dry_run,
+        }))
+    }
+}
+
+starlark_module! { built_executable_env =>
+    #[allow(clippy::ptr_arg)]
+    BuiltExecutable.sign(env env, this, signer) {
+        this.downcast_apply(|exe: &BuiltExecutable| exe.starlark_sign(&env, &signer))
+    }
+
+    #[allow(clippy::ptr_arg)]
+    BuiltExecutable.strip(env env, this) {
+        this.downcast_apply(|exe: &BuiltExecutable| exe.starlark_strip(&env))
+    }
+
+    #[allow(clippy::ptr_arg)]
+    BuiltExecutable.capture_output(
+        env env,
+        this,
+        manifest,
+        path,
+        args=None,
+        module=None,
+        environment=None
+    ) {
+        this.downcast_apply(|exe: &BuiltExecutable| {
+            exe.starlark_capture_output(&env, &manifest, &path, &args, &module, &environment)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    BuiltExecutable.copy_to(env env, this, path) {
+        this.downcast_apply(|exe: &BuiltExecutable| exe.starlark_copy_to(&env, &path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::super::testutil::*, super::*};
+
+    #[test]
+    fn test_signer_command() -> Result<()> {
+        let path = PathBuf::from("/build/myapp");
+
+        assert_eq!(
+            signer_command(
+                &["codesign".to_string(), "-s".to_string(), "Me".to_string()],
+                &path
+            )?,
+            vec!["codesign", "-s", "Me", "/build/myapp"]
+        );
+        assert_eq!(
+            signer_command(
+                &[
+                    "signtool".to_string(),
+                    "--in={path}".to_string(),
+                    "--force".to_string()
+                ],
+                &path
+            )?,
+            vec!["signtool", "--in=/build/myapp", "--force"]
+        );
+        assert!(signer_command(&[], &path).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_dry_run() {
+        let mut env = starlark_env();
+        env.get("CONTEXT")
+            .unwrap()
+            .downcast_apply_mut(|x: &mut EnvironmentContext| x.dry_run = true);
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+
+        let built = starlark_eval_in_env(&mut env, "exe.build('exe')").unwrap();
+        assert_eq!(built.get_type(), "BuiltExecutable");
+        assert!(built
+            .get_attr("path")
+            .unwrap()
+            .to_string()
+            .contains("testapp"));
+        assert_eq!(built.get_attr("size").unwrap().to_int().unwrap(), 0);
+
+        starlark_eval_in_env(&mut env, "built = exe.build('exe')").unwrap();
+        starlark_eval_in_env(&mut env, "built.strip()").unwrap();
+        starlark_eval_in_env(&mut env, "built.sign(['codesign', '-s', 'Me'])").unwrap();
+        let copied = starlark_eval_in_env(&mut env, "built.copy_to('dist/app')").unwrap();
+        assert_eq!(copied.get_type(), "BuiltExecutable");
+        assert!(copied
+            .get_attr("path")
+            .unwrap()
+            .to_string()
+            .ends_with("app"));
+
+        let err = starlark_eval_in_env(&mut env, "built.sign([])").unwrap_err();
+        assert_eq!(err.message, "signing command must not be empty");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_capture_output() {
+        let mut env = starlark_env();
+        env.set(
+            "sh",
+            Value::new(BuiltExecutable {
+                path: PathBuf::from("/bin/sh"),
+                target_triple: crate::project_building::HOST.to_string(),
+                dry_run: false,
+            }),
+        )
+        .unwrap();
+        starlark_eval_in_env(&mut env, "m = FileManifest()").unwrap();
+
+        starlark_eval_in_env(
+            &mut env,
+            "sh.capture_output(m, 'share/bash-completion/completions/myapp', args=['-c', 'echo complete $SHELL_NAME'], environment={'SHELL_NAME': 'bash'})",
+        )
+        .unwrap();
+
+        let m = env.get("m").unwrap();
+        m.downcast_apply(|m: &FileManifest| {
+            let content = m
+                .manifest
+                .get(Path::new("share/bash-completion/completions/myapp"))
+                .unwrap();
+            assert_eq!(content.data, b"complete bash\n".to_vec());
+            assert!(!content.executable);
+        });
+
+        let err = starlark_eval_in_env(
+            &mut env,
+            "sh.capture_output(m, 'out', args=['-c', 'exit 3'])",
+        )
+        .unwrap_err();
+        assert!(err.message.contains("failed with exit"));
+    }
+}
@@ -0,0 +1,503 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Versioning of the configuration file API.
+
+Configuration files declare the API version they are written against by
+calling `set_config_version()`. Files not declaring a version are assumed
+to target version 1, the API of PyOxidizer 0.6.
+
+Names removed from the API keep working in configuration files targeting
+an older version, with a warning. `migrate_source()` rewrites configuration
+files to use current names, which is what `pyoxidizer migrate-config` does.
+*/
+
+use {
+    super::env::EnvironmentContext,
+    super::util::required_bool_arg,
+    slog::warn,
+    starlark::environment::Environment,
+    starlark::values::{RuntimeError, Value, ValueError},
+};
+
+/// Version of the configuration file API implemented by this PyOxidizer.
+pub const CONFIG_VERSION: i64 = 2;
+
+/// Version assumed for configuration files not declaring one.
+pub const DEFAULT_CONFIG_VERSION: i64 = 1;
+
+/// A change to the configuration file API.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ApiChange {
+    /// A function or method was renamed.
+    Function {
+        old: &'static str,
+        new: &'static str,
+    },
+
+    /// A keyword argument of a function or method was renamed.
+    ///
+    /// `negated` arguments are booleans whose meaning was inverted.
+    Argument {
+        function: &'static str,
+        old: &'static str,
+        new: &'static str,
+        negated: bool,
+    },
+}
+
+/// Changes to the configuration file API and the version introducing them.
+pub const API_CHANGES: &[(i64, ApiChange)] = &[
+    (
+        2,
+        ApiChange::Function {
+            old: "filter_from_files",
+            new: "filter_resources_from_files",
+        },
+    ),
+    (
+        2,
+        ApiChange::Argument {
+            function: "filter_from_files",
+            old: "glob_patterns",
+            new: "glob_files",
+            negated: false,
+        },
+    ),
+    (
+        2,
+        ApiChange::Argument {
+            function: "PythonInterpreterConfig",
+            old: "dont_write_bytecode",
+            new: "write_bytecode",
+            negated: true,
+        },
+    ),
+    (
+        2,
+        ApiChange::Argument {
+            function: "PythonInterpreterConfig",
+            old: "no_site",
+            new: "site_import",
+            negated: true,
+        },
+    ),
+    (
+        2,
+        ApiChange::Argument {
+            function: "PythonInterpreterConfig",
+            old: "no_user_site_directory",
+            new: "user_site_directory",
+            negated: true,
+        },
+    ),
+];
+
+/// Ensure a name removed from the configuration file API may be used.
+///
+/// `old` and `new` describe the removed name and its replacement. Configs
+/// targeting a version predating the removal get a warning. Others get an
+/// error.
+pub fn check_removed_name(
+    env: &Environment,
+    label: &str,
+    old: &str,
+    new: &str,
+) -> Result<(), ValueError> {
+    let context = env.get("CONTEXT").expect("CONTEXT not defined");
+    let (logger, version) =
+        context.downcast_apply(|x: &EnvironmentContext| (x.logger.clone(), x.config_version));
+
+    if version >= CONFIG_VERSION {
+        Err(RuntimeError {
+            code: "PYOXIDIZER_BUILD",
+            message: format!(
+                "{} was replaced by {} in config version {}; run `pyoxidizer migrate-config` to update the config",
+                old, new, CONFIG_VERSION
+            ),
+            label: label.to_string(),
+        }
+        .into())
+    } else {
+        warn!(
+            logger,
+            "{} is deprecated; use {} instead or run `pyoxidizer migrate-config`", old, new
+        );
+
+        Ok(())
+    }
+}
+
+/// Resolve a boolean argument replacing a removed argument with the inverse meaning.
+///
+/// Returns `value` unless `old_value`, the value of the removed argument
+/// `old`, is defined, in which case its negation is returned.
+pub fn negated_removed_bool_arg(
+    env: &Environment,
+    label: &str,
+    old: &str,
+    old_value: &Value,
+    new: &str,
+    value: bool,
+) -> Result<bool, ValueError> {
+    if old_value.get_type() == "NoneType" {
+        return Ok(value);
+    }
+
+    let old_value = required_bool_arg(old, &old_value)?;
+    check_removed_name(
+        env,
+        label,
+        &format!("the {} argument", old),
+        &format!("{} with the inverse value", new),
+    )?;
+
+    Ok(!old_value)
+}
+
+/// Whether a byte can be part of an identifier.
+fn is_ident_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'_'
+}
+
+/// Find the end of a string literal starting at `start`.
+fn string_end(source: &[u8], start: usize) -> usize {
+    let quote = source[start];
+    let triple =
+        source.len() >= start + 3 && source[start + 1] == quote && source[start + 2] == quote;
+    let mut i = if triple { start + 3 } else { start + 1 };
+
+    while i < source.len() {
+        if source[i] == b'\\' {
+            i += 2;
+        } else if source[i] == quote {
+            if !triple {
+                return i + 1;
+            } else if source.len() >= i + 3 && source[i + 1] == quote && source[i + 2] == quote {
+                return i + 3;
+            }
+            i += 1;
+        } else if source[i] == b'\n' && !triple {
+            return i;
+        } else {
+            i += 1;
+        }
+    }
+
+    source.len()
+}
+
+/// Find the end of an argument value starting at `start`.
+///
+/// The value ends at a `,` or closing bracket that isn't nested in the value,
+/// or at the end of the line if `in_comment`.
+fn value_end(source: &[u8], start: usize, in_comment: bool) -> usize {
+    let mut depth = 0;
+    let mut i = start;
+
+    while i < source.len() {
+        match source[i] {
+            b'"' | b'\'' => {
+                i = string_end(source, i);
+                continue;
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' if depth == 0 => return i,
+            b')' | b']' | b'}' => depth -= 1,
+            b',' if depth == 0 => return i,
+            b'\n' if in_comment => return i,
+            _ => {}
+        }
+        i += 1;
+    }
+
+    source.len()
+}
+
+/// Negate the source of a boolean expression.
+fn negate(value: &str) -> String {
+    match value {
+        "True" | "true" => "False".to_string(),
+        "False" | "false" => "True".to_string(),
+        _ if value.bytes().all(is_ident_byte) => format!("not {}", value),
+        _ => format!("not ({})", value),
+    }
+}
+
+/// Rewrite configuration file source to the current API version.
+///
+/// Removed functions, methods, and arguments are renamed and a
+/// `set_config_version()` call declaring the current version is added or
+/// updated. Commented out code is rewritten as well. Returns the new source
+/// and a description of each change.
+pub fn migrate_source(source: &str) -> (String, Vec<String>) {
+    let bytes = source.as_bytes();
+    let line = |offset: usize| bytes[0..offset].iter().filter(|c| **c == b'\n').count() + 1;
+
+    // (start, end, replacement, description)
+    let mut edits: Vec<(usize, usize, String, String)> = Vec::new();
+
+    // Names of called functions enclosing the current position. `None` for
+    // other brackets.
+    let mut calls: Vec<Option<&str>> = Vec::new();
+    // State to restore at the end of a comment.
+    let mut comment_calls: Option<Vec<Option<&str>>> = None;
+    let mut last_ident: Option<&str> = None;
+    let mut version_call = None;
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let in_comment = comment_calls.is_some();
+        let c = bytes[i];
+
+        if c == b'#' && !in_comment {
+            comment_calls = Some(calls.clone());
+            last_ident = None;
+            i += 1;
+        } else if c == b'\n' {
+            if let Some(saved) = comment_calls.take() {
+                calls = saved;
+                last_ident = None;
+            }
+            i += 1;
+        } else if (c == b'"' || c == b'\'') && !in_comment {
+            i = string_end(bytes, i);
+            last_ident = None;
+        } else if c == b'(' || c == b'[' || c == b'{' {
+            calls.push(if c == b'(' { last_ident } else { None });
+            last_ident = None;
+            i += 1;
+        } else if c == b')' || c == b']' || c == b'}' {
+            calls.pop();
+            last_ident = None;
+            i += 1;
+        } else if c.is_ascii_whitespace() {
+            i += 1;
+        } else if is_ident_byte(c) && !c.is_ascii_digit() {
+            let start = i;
+            while i < bytes.len() && is_ident_byte(bytes[i]) {
+                i += 1;
+            }
+            let ident = &source[start..i];
+
+            let mut next = i;
+            while next < bytes.len() && (bytes[next] == b' ' || bytes[next] == b'\t') {
+                next += 1;
+            }
+            let next_byte = bytes.get(next).cloned();
+
+            if next_byte == Some(b'(') {
+                if ident == "set_config_version" && !in_comment && version_call.is_none() {
+                    let value_start = next + 1;
+                    version_call = Some((value_start, value_end(bytes, value_start, false)));
+                }
+
+                for (_, change) in API_CHANGES {
+                    if let ApiChange::Function { old, new } = change {
+                        if ident == *old {
+                            edits.push((
+                                start,
+                                i,
+                                new.to_string(),
+                                format!("line {}: renamed {}() to {}()", line(start), old, new),
+                            ));
+                        }
+                    }
+                }
+            } else if next_byte == Some(b'=') && bytes.get(next + 1) != Some(&b'=') {
+                let function = calls.last().cloned().unwrap_or(None);
+
+                for (_, change) in API_CHANGES {
+                    if let ApiChange::Argument {
+                        function: f,
+                        old,
+                        new,
+                        negated,
+                    } = change
+                    {
+                        if function != Some(*f) || ident != *old {
+                            continue;
+                        }
+
+                        let description = format!(
+                            "line {}: renamed {} argument of {}() to {}",
+                            line(start),
+                            old,
+                            f,
+                            new
+                        );
+
+                        if *negated {
+                            let mut value_start = next + 1;
+                            while value_start < bytes.len()
+                                && (bytes[value_start] == b' ' || bytes[value_start] == b'\t')
+                            {
+                                value_start += 1;
+                            }
+                            let value = source
+                                [value_start..value_end(bytes, value_start, in_comment)]
+                                .trim_end();
+
+                            edits.push((
+                                start,
+                                value_start + value.len(),
+                                format!("{}{}{}", new, &source[i..value_start], negate(value)),
+                                format!("{} (inverting its value)", description),
+                            ));
+                            i = value_start + value.len();
+                        } else {
+                            edits.push((start, i, new.to_string(), description));
+                        }
+                    }
+                }
+            }
+
+            last_ident = Some(ident);
+        } else if c.is_ascii_digit() {
+            while i < bytes.len() && is_ident_byte(bytes[i]) {
+                i += 1;
+            }
+            last_ident = None;
+        } else {
+            i += 1;
+            last_ident = None;
+        }
+    }
+
+    match version_call {
+        Some((start, end)) => {
+            if source[start..end].trim() != CONFIG_VERSION.to_string() {
+                edits.push((
+                    start,
+                    end,
+                    CONFIG_VERSION.to_string(),
+                    format!(
+                        "line {}: set config version to {}",
+                        line(start),
+                        CONFIG_VERSION
+                    ),
+                ));
+            }
+        }
+        None => {
+            // Declare the version after the comment at the top of the file.
+            let mut offset = 0;
+            for l in source.split('\n') {
+                if !l.trim_start().starts_with('#') || offset + l.len() >= source.len() {
+                    break;
+                }
+                offset += l.len() + 1;
+            }
+
+            let call = format!("set_config_version({})", CONFIG_VERSION);
+            edits.push((
+                offset,
+                offset,
+                if offset == 0 {
+                    format!("{}\n\n", call)
+                } else {
+                    format!("\n{}\n", call)
+                },
+                format!(
+                    "line {}: declared config version {}",
+                    line(offset),
+                    CONFIG_VERSION
+                ),
+            ));
+        }
+    }
+
+    edits.sort_by_key(|(start, _, _, _)| *start);
+
+    let mut res = String::new();
+    let mut descriptions = Vec::new();
+    let mut offset = 0;
+    for (start, end, replacement, description) in edits {
+        res.push_str(&source[offset..start]);
+        res.push_str(&replacement);
+        descriptions.push(description);
+        offset = end;
+    }
+    res.push_str(&source[offset..]);
+
+    (res, descriptions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_source() {
+        let source = concat!(
+            "# My application.\n",
+            "\n",
+            "def make_exe(dist):\n",
+            "    config = PythonInterpreterConfig(\n",
+            "        dont_write_bytecode=True,\n",
+            "    #    no_site=True,\n",
+            "        no_user_site_directory = BUILD_RELEASE,\n",
+            "        run_eval=\"no_site=True\",\n",
+            "    )\n",
+            "    exe = dist.to_python_executable(name=\"app\", config=config)\n",
+            "    exe.filter_from_files(files=[\"f\"], glob_patterns=[\"*.txt\"])\n",
+            "    return exe\n",
+        );
+
+        let (migrated, changes) = migrate_source(source);
+
+        assert_eq!(
+            migrated,
+            concat!(
+                "# My application.\n",
+                "\n",
+                "set_config_version(2)\n",
+                "\n",
+                "def make_exe(dist):\n",
+                "    config = PythonInterpreterConfig(\n",
+                "        write_bytecode=False,\n",
+                "    #    site_import=False,\n",
+                "        user_site_directory = not BUILD_RELEASE,\n",
+                "        run_eval=\"no_site=True\",\n",
+                "    )\n",
+                "    exe = dist.to_python_executable(name=\"app\", config=config)\n",
+                "    exe.filter_resources_from_files(files=[\"f\"], glob_files=[\"*.txt\"])\n",
+                "    return exe\n",
+            )
+        );
+        assert_eq!(changes.len(), 6);
+        assert_eq!(changes[0], "line 2: declared config version 2");
+        assert_eq!(
+            changes[1],
+            "line 5: renamed dont_write_bytecode argument of PythonInterpreterConfig() to write_bytecode (inverting its value)"
+        );
+        assert_eq!(
+            changes[4],
+            "line 11: renamed filter_from_files() to filter_resources_from_files()"
+        );
+    }
+
+    #[test]
+    fn test_migrate_source_version() {
+        let (migrated, changes) = migrate_source("set_config_version(1)\nresolve_targets()\n");
+        assert_eq!(migrated, "set_config_version(2)\nresolve_targets()\n");
+        assert_eq!(changes, vec!["line 1: set config version to 2".to_string()]);
+
+        let (migrated, changes) = migrate_source("set_config_version(2)\n");
+        assert_eq!(migrated, "set_config_version(2)\n");
+        assert!(changes.is_empty());
+
+        let (migrated, _) = migrate_source("resolve_targets()\n");
+        assert_eq!(migrated, "set_config_version(2)\n\nresolve_targets()\n");
+    }
+
+    #[test]
+    fn test_negate() {
+        assert_eq!(negate("True"), "False");
+        assert_eq!(negate("false"), "True");
+        assert_eq!(negate("BUILD_RELEASE"), "not BUILD_RELEASE");
+        assert_eq!(negate("a or b"), "not (a or b)");
+    }
+}
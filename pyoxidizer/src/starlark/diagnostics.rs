@@ -0,0 +1,274 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Helpers for reporting Starlark evaluation errors.
+
+Raw Starlark errors tell you what went wrong but not how to fix it. This
+module derives hints for common mistakes, like misspelled function names
+and arguments of the wrong type.
+*/
+
+use {
+    codemap::CodeMap,
+    codemap_diagnostic::{Diagnostic, Level},
+    starlark::environment::Environment,
+    starlark::values::INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+};
+
+/// Extract the name of a variable from a line of an environment's `Debug` representation.
+fn variable_name(line: &str) -> Option<&str> {
+    if !line.starts_with('"') {
+        return None;
+    }
+
+    let end = line.find("\": Value[")?;
+    let name = &line[1..end];
+
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+/// Obtain the names of variables defined in an environment and its parents.
+///
+/// starlark doesn't provide a way to enumerate the variables of an
+/// environment. But its pretty-printed `Debug` representation lists each
+/// variable on its own line, keyed by name, so names are taken from there.
+/// Methods of types are stored separately and aren't included.
+pub fn defined_names(env: &Environment) -> Vec<String> {
+    let debug = format!("{:#?}", env);
+
+    let mut names = Vec::new();
+    let mut entry_indent = None;
+
+    for line in debug.lines() {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+
+        match entry_indent {
+            None => {
+                if trimmed == "variables: {" {
+                    entry_indent = Some(indent + 4);
+                }
+            }
+            Some(expected) if indent < expected => {
+                entry_indent = None;
+            }
+            Some(expected) if indent == expected => {
+                if let Some(name) = variable_name(trimmed) {
+                    names.push(name.to_string());
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    names.sort();
+    names.dedup();
+
+    names
+}
+
+/// Compute the Levenshtein edit distance between 2 strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b_chars.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+
+        for (j, cb) in b_chars.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(row[j + 1])
+            };
+            previous = current;
+        }
+    }
+
+    row[b_chars.len()]
+}
+
+/// Find the candidate most similar to `name`, if any is similar enough.
+pub fn closest_name<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let max_distance = std::cmp::max(1, name.len() / 3);
+
+    candidates
+        .iter()
+        .filter(|c| !c.is_empty() && **c != name)
+        .map(|c| {
+            // Case differences are a common typo. So treat them as cheap.
+            let distance = if c.to_lowercase() == name.to_lowercase() {
+                0
+            } else {
+                edit_distance(name, c)
+            };
+
+            (distance, *c)
+        })
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, c)| c)
+}
+
+/// Extract the name of an unresolved variable from an error message.
+fn unknown_variable(message: &str) -> Option<&str> {
+    let start = message.find("ariable '")? + "ariable '".len();
+    let end = message[start..].find('\'')? + start;
+
+    Some(&message[start..end])
+}
+
+/// Derive a hint for fixing an evaluation error, if possible.
+///
+/// `names` are the variables defined when the error occurred. They are
+/// candidates for misspelled names.
+pub fn suggestion(diagnostic: &Diagnostic, names: &[String]) -> Option<String> {
+    if let Some(name) = unknown_variable(&diagnostic.message) {
+        let candidates = names.iter().map(|n| n.as_str()).collect::<Vec<_>>();
+
+        return if let Some(candidate) = closest_name(name, &candidates) {
+            Some(format!("did you mean `{}`?", candidate))
+        } else {
+            Some(format!(
+                "`{}` is not defined; functions must be defined before they are called",
+                name
+            ))
+        };
+    }
+
+    if diagnostic.code.as_ref().map(|c| c.as_str()) == Some(INCORRECT_PARAMETER_TYPE_ERROR_CODE)
+        || diagnostic.message.contains("function expects")
+    {
+        return Some(
+            "see the configuration file API documentation for the types this function accepts"
+                .to_string(),
+        );
+    }
+
+    None
+}
+
+/// Obtain a `file:line:column` string for the primary location of a diagnostic.
+pub fn location(map: &CodeMap, diagnostic: &Diagnostic) -> Option<String> {
+    let span = diagnostic.spans.first()?;
+    let loc = map.look_up_span(span.span);
+
+    Some(format!(
+        "{}:{}:{}",
+        loc.file.name(),
+        loc.begin.line + 1,
+        loc.begin.column + 1
+    ))
+}
+
+/// Render a diagnostic, its source snippet, and any hint into a string.
+///
+/// `names` are the variables defined when the error occurred.
+pub fn render(map: &CodeMap, diagnostic: &Diagnostic, names: &[String]) -> String {
+    let mut diagnostics = vec![diagnostic.clone()];
+
+    if let Some(hint) = suggestion(diagnostic, names) {
+        diagnostics.push(Diagnostic {
+            level: Level::Help,
+            message: hint,
+            code: None,
+            spans: vec![],
+        });
+    }
+
+    let mut msg = Vec::new();
+    {
+        let mut emitter = codemap_diagnostic::Emitter::vec(&mut msg, Some(map));
+        emitter.emit(&diagnostics);
+    }
+
+    String::from_utf8_lossy(&msg).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::super::testutil::*, super::*};
+
+    #[test]
+    fn test_closest_name() {
+        let candidates = &["PythonInterpreterConfig", "default_python_distribution"];
+
+        assert_eq!(
+            closest_name("default_python_distrbution", candidates),
+            Some("default_python_distribution")
+        );
+        assert_eq!(
+            closest_name("pythoninterpreterconfig", candidates),
+            Some("PythonInterpreterConfig")
+        );
+        assert_eq!(closest_name("foo", candidates), None);
+    }
+
+    #[test]
+    fn test_defined_names() {
+        let mut env = starlark_env();
+        let names = defined_names(&env);
+
+        for name in &[
+            "BUILD_TARGET_TRIPLE",
+            "CWD",
+            "InstallLayout",
+            "OciImageBuilder",
+            "default_python_distribution",
+            "len",
+            "register_resource_hook",
+            "register_test_target",
+            "set_output_layout",
+        ] {
+            assert!(names.contains(&name.to_string()), "{} is defined", name);
+        }
+
+        // Methods of types aren't global variables.
+        assert!(!names.contains(&"isalnum".to_string()));
+
+        for name in &names {
+            assert!(env.get(name).is_ok(), "{} is defined", name);
+        }
+
+        starlark_eval_in_env(&mut env, "def make_exe():\n    pass\n").unwrap();
+        assert!(defined_names(&env).contains(&"make_exe".to_string()));
+    }
+
+    #[test]
+    fn test_suggestion_unknown_variable() {
+        let env = starlark_env();
+        let diagnostic = starlark_nok("default_python_distrbution()");
+
+        assert_eq!(
+            suggestion(&diagnostic, &defined_names(&env)),
+            Some("did you mean `default_python_distribution`?".to_string())
+        );
+    }
+
+    #[test]
+    fn test_suggestion_config_function() {
+        let mut env = starlark_env();
+        starlark_eval_in_env(&mut env, "def make_exe():\n    pass\n").unwrap();
+        let diagnostic = starlark_eval_in_env(&mut env, "mak_exe()").unwrap_err();
+
+        assert_eq!(
+            suggestion(&diagnostic, &defined_names(&env)),
+            Some("did you mean `make_exe`?".to_string())
+        );
+    }
+
+    #[test]
+    fn test_suggestion_wrong_type() {
+        let diagnostic = starlark_nok("register_target(1, None)");
+
+        assert!(suggestion(&diagnostic, &[]).is_some());
+    }
+}
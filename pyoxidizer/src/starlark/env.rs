@@ -3,16 +3,44 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
+    super::built_executable::BuiltExecutable,
+    super::config_version::{CONFIG_VERSION, DEFAULT_CONFIG_VERSION},
     super::file_resource::FileManifest,
-    super::python_embedded_resources::PythonEmbeddedData,
+    super::jupyter_kernel::JupyterKernel,
+    super::linux_package::{FlatpakPackage, SnapcraftPackage},
+    super::nsis_installer::NsisInstallerBuilder,
+    super::oci_image::OciImageBuilder,
+    super::python_embedded_resources::{PythonCargoArtifacts, PythonEmbeddedData},
     super::python_executable::PythonExecutable,
-    super::target::{BuildContext, BuildTarget, ResolvedTarget},
-    super::util::{optional_list_arg, required_bool_arg, required_str_arg, required_type_arg},
+    super::python_zipapp::PythonZipApp,
+    super::target::{BuildContext, BuildTarget, DetachedBuild, ResolvedTarget},
+    super::util::{
+        optional_list_arg, optional_str_arg, required_bool_arg, required_list_arg,
+        required_str_arg, required_type_arg,
+    },
+    crate::licensing::LicensePolicy,
+    crate::output_layout::{OutputLayout, OUTPUT_LAYOUT_ENV},
+    crate::py_packaging::config::RunMode,
+    crate::py_packaging::distribution_mirror::{
+        distribution_cache_path_from_env, DistributionMirrors, MirrorRule,
+    },
+    crate::py_packaging::distribution_signature::{
+        use_locked_mode, DistributionVerifier, SigningKey,
+    },
+    crate::py_packaging::download::DownloadOptions,
+    crate::py_packaging::packaging_tool::PipIndexOptions,
+    crate::release::{ReleaseProvider, ReleaseTarget},
+    crate::rust_toolchain::use_system_rust,
+    crate::scratch::{SCRATCH_MAX_SIZE_ENV, SCRATCH_PATH_ENV},
+    crate::size_budget::parse_size,
     anyhow::{anyhow, Context, Result},
     path_dedot::ParseDot,
-    slog::warn,
+    slog::{info, warn},
     starlark::environment::{Environment, EnvironmentError},
-    starlark::values::{default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult},
+    starlark::values::{
+        default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult,
+        INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+    },
     starlark::{
         any, immutable, not_supported, starlark_fun, starlark_module, starlark_signature,
         starlark_signature_extraction, starlark_signatures,
@@ -41,6 +69,125 @@ pub struct Target {
     pub built_target: Option<ResolvedTarget>,
 }
 
+/// Represents a registered test target in the Starlark environment.
+///
+/// A test target runs code exercising the executable built by another target.
+#[derive(Debug, Clone)]
+pub struct TestTarget {
+    /// Target resolving to the `PythonExecutable` being tested.
+    pub executable_target: String,
+
+    /// Names of modules to import.
+    pub imports: Vec<String>,
+
+    /// Arguments to pass to `pytest`, if pytest should run.
+    pub pytest_args: Option<Vec<String>>,
+
+    /// Whether the executable is moved before running, to verify it doesn't
+    /// depend on the path it was built at.
+    pub relocate: bool,
+}
+
+impl TestTarget {
+    /// Python source code running this test.
+    ///
+    /// The code exits with a non-zero exit code if any test fails.
+    pub fn python_code(&self) -> Result<String> {
+        let mut code = vec![
+            "import importlib".to_string(),
+            "import sys".to_string(),
+            "import traceback".to_string(),
+            "failures = 0".to_string(),
+            format!("for name in {}:", serde_json::to_string(&self.imports)?),
+            "    try:".to_string(),
+            "        importlib.import_module(name)".to_string(),
+            "        print('import %s: ok' % name)".to_string(),
+            "    except Exception:".to_string(),
+            "        failures += 1".to_string(),
+            "        print('import %s: FAILED' % name)".to_string(),
+            "        traceback.print_exc()".to_string(),
+        ];
+
+        if let Some(args) = &self.pytest_args {
+            code.push("import pytest".to_string());
+            code.push(format!(
+                "if pytest.main({}) != 0:",
+                serde_json::to_string(args)?
+            ));
+            code.push("    failures += 1".to_string());
+        }
+
+        code.push("sys.exit(1 if failures else 0)".to_string());
+
+        Ok(code.join("\n"))
+    }
+}
+
+/// Run an executable after moving the directory it was built in.
+///
+/// `output_path` is renamed to a sibling directory for the duration of the
+/// run, so paths recorded at build time no longer exist. On Unix, the
+/// executable is run through a symlink in another directory, as it would be
+/// from `PATH`.
+fn run_relocated(
+    logger: &slog::Logger,
+    output_path: &Path,
+    exe: &Path,
+) -> Result<std::process::ExitStatus> {
+    let relative = exe.strip_prefix(output_path)?.to_path_buf();
+
+    let mut moved_path = output_path.as_os_str().to_os_string();
+    moved_path.push(".relocated");
+    let moved_path = PathBuf::from(moved_path);
+
+    let mut link_path = output_path.as_os_str().to_os_string();
+    link_path.push(".link");
+    let link_path = PathBuf::from(link_path);
+
+    for path in &[&moved_path, &link_path] {
+        if path.exists() {
+            std::fs::remove_dir_all(path)?;
+        }
+    }
+
+    warn!(
+        logger,
+        "moving {} to {}",
+        output_path.display(),
+        moved_path.display()
+    );
+    std::fs::rename(output_path, &moved_path)
+        .with_context(|| format!("moving {}", output_path.display()))?;
+
+    let result = (|| -> Result<std::process::ExitStatus> {
+        let moved_exe = moved_path.join(&relative);
+
+        #[cfg(unix)]
+        let run_path = {
+            std::fs::create_dir_all(&link_path)?;
+            let run_path = link_path.join(relative.file_name().unwrap_or_default());
+            std::os::unix::fs::symlink(&moved_exe, &run_path)
+                .with_context(|| format!("linking {}", run_path.display()))?;
+            run_path
+        };
+        #[cfg(not(unix))]
+        let run_path = moved_exe;
+
+        std::process::Command::new(&run_path)
+            .current_dir(&moved_path)
+            .status()
+            .with_context(|| format!("running {}", run_path.display()))
+    })();
+
+    if link_path.exists() {
+        std::fs::remove_dir_all(&link_path)?;
+    }
+    std::fs::rename(&moved_path, output_path)
+        .with_context(|| format!("moving {} back", moved_path.display()))?;
+
+    result
+}
+
 /// Holds state for evaluating a Starlark config file.
 #[derive(Debug, Clone)]
 pub struct EnvironmentContext {
@@ -57,6 +204,9 @@ pub struct EnvironmentContext {
     /// Path to the configuration file.
     pub config_path: PathBuf,
 
+    /// Version of the configuration file API the config is written against.
+    pub config_version: i64,
+
     /// Host triple we are building from.
     pub build_host_triple: String,
 
@@ -72,9 +222,21 @@ pub struct EnvironmentContext {
     /// Base directory to use for build state.
     pub build_path: PathBuf,
 
+    /// Template of the output directories of targets.
+    pub output_layout: OutputLayout,
+
     /// Path where Python distributions are written.
     pub python_distributions_path: PathBuf,
 
+    /// Directory caching Python distributions across projects, if defined.
+    pub distribution_cache_path: Option<PathBuf>,
+
+    /// Rules rewriting the URLs Python distributions are downloaded from.
+    pub distribution_mirrors: DistributionMirrors,
+
+    /// Verifies signatures of Python distribution archives.
+    pub distribution_verifier: DistributionVerifier,
+
     /// Registered build targets.
     ///
     /// A target consists of a name and a Starlark callable.
@@ -96,6 +258,34 @@ pub struct EnvironmentContext {
     ///
     /// This will change the default target to resolve.
     pub build_script_mode: bool,
+
+    /// Whether we are operating in dry run mode.
+    ///
+    /// Target functions are evaluated but nothing is compiled or linked.
+    pub dry_run: bool,
+
+    /// Restrictions on the licenses of components in built artifacts.
+    pub license_policy: LicensePolicy,
+
+    /// Starlark callables invoked for each resource added to an executable.
+    pub resource_hooks: Vec<Value>,
+
+    /// Registered test targets.
+    pub test_targets: BTreeMap<String, TestTarget>,
+
+    /// Registered release targets.
+    pub release_targets: BTreeMap<String, ReleaseTarget>,
+
+    /// Directory holding artifacts downloaded by `pyoxidizer vendor`.
+    pub vendor_path: Option<PathBuf>,
+
+    /// Whether builds must not access the network.
+    ///
+    /// Everything a build needs is obtained from `vendor_path`.
+    pub offline: bool,
+
+    /// Whether artifacts needed by builds are being downloaded to `vendor_path`.
+    pub vendoring: bool,
 }
 
 impl EnvironmentContext {
@@ -122,24 +312,43 @@ impl EnvironmentContext {
         };
 
         let build_path = parent.join("build");
+        let distribution_cache_path = distribution_cache_path_from_env();
 
         Ok(EnvironmentContext {
             logger: logger.clone(),
             verbose,
             cwd: parent,
             config_path: config_path.to_path_buf(),
+            config_version: DEFAULT_CONFIG_VERSION,
             build_host_triple: build_host_triple.to_string(),
             build_target_triple: build_target_triple.to_string(),
             build_release,
             build_opt_level: build_opt_level.to_string(),
             build_path: build_path.clone(),
-            python_distributions_path: build_path.join("python_distributions"),
+            output_layout: OutputLayout::from_env()?.unwrap_or_default(),
+            python_distributions_path: distribution_cache_path
+                .clone()
+                .unwrap_or_else(|| build_path.join("python_distributions")),
+            distribution_cache_path,
+            distribution_mirrors: DistributionMirrors::from_env()?,
+            distribution_verifier: DistributionVerifier {
+                keys: Vec::new(),
+                locked: use_locked_mode(),
+            },
             targets: BTreeMap::new(),
             targets_order: Vec::new(),
             default_target: None,
             default_build_script_target: None,
             resolve_targets,
             build_script_mode,
+            dry_run: false,
+            license_policy: LicensePolicy::default(),
+            resource_hooks: Vec::new(),
+            test_targets: BTreeMap::new(),
+            release_targets: BTreeMap::new(),
+            vendor_path: None,
+            offline: false,
+            vendoring: false,
         })
     }
 
@@ -152,11 +361,110 @@ impl EnvironmentContext {
         .parse_dot()?;
 
         self.build_path = path.clone();
+
+        // Vendored and cached distributions take precedence over the build directory.
+        if self.vendor_path.is_none() && self.distribution_cache_path.is_none() {
+            self.python_distributions_path = path.join("python_distributions");
+        }
+
+        Ok(())
+    }
+
+    /// Set the directory caching Python distributions across projects.
+    pub fn set_distribution_cache_path(&mut self, path: &Path) -> Result<()> {
+        let path = if path.is_relative() {
+            self.cwd.join(path)
+        } else {
+            path.to_path_buf()
+        }
+        .parse_dot()?;
+
+        // Vendored distributions take precedence over the cache.
+        if self.vendor_path.is_none() {
+            self.python_distributions_path = path.clone();
+        }
+        self.distribution_cache_path = Some(path);
+
+        Ok(())
+    }
+
+    /// Set the directory holding vendored build artifacts.
+    ///
+    /// Python distributions are resolved from this directory.
+    pub fn set_vendor_path(&mut self, path: &Path) -> Result<()> {
+        let path = if path.is_relative() {
+            self.cwd.join(path)
+        } else {
+            path.to_path_buf()
+        }
+        .parse_dot()?;
+
         self.python_distributions_path = path.join("python_distributions");
+        self.vendor_path = Some(path);
 
         Ok(())
     }
 
+    /// Directory holding vendored pip artifacts, if vendoring is configured.
+    pub fn pip_vendor_path(&self) -> Option<PathBuf> {
+        self.vendor_path.as_ref().map(|p| p.join("pip"))
+    }
+
+    /// Cargo home directory holding vendored crates, if vendoring is configured.
+    pub fn cargo_vendor_path(&self) -> Option<PathBuf> {
+        self.vendor_path.as_ref().map(|p| p.join("cargo"))
+    }
+
+    /// Directory the pinned Rust toolchain is installed to.
+    ///
+    /// `None` if the Rust toolchain in `PATH` should be used, as is always
+    /// the case in build script mode, where Cargo is already running.
+    pub fn rust_toolchains_path(&self) -> Option<PathBuf> {
+        if self.build_script_mode || use_system_rust() {
+            None
+        } else if let Some(path) = &self.vendor_path {
+            Some(path.join("rust_toolchains"))
+        } else {
+            Some(self.build_path.join("rust_toolchains"))
+        }
+    }
+
+    /// Whether Python distributions and packages are known from metadata only.
+    ///
+    /// Dry runs don't obtain distributions or run packaging tools, unless
+    /// artifacts are being vendored.
+    pub fn metadata_only(&self) -> bool {
+        self.dry_run && !self.vendoring
+    }
+
+    /// Options for finding packages with pip honoring offline and vendoring modes.
+    pub fn pip_index_options(&self) -> Result<PipIndexOptions> {
+        let mut options = PipIndexOptions {
+            build_sdists: true,
+            sdist_wheel_cache: Some(self.build_path.join("sdist_wheels")),
+            download: DownloadOptions::from_env()?,
+            ..PipIndexOptions::default()
+        };
+
+        if let Some(path) = self.pip_vendor_path() {
+            if self.vendoring {
+                options.download_dir = Some(path);
+            } else if self.offline {
+                if !path.is_dir() {
+                    return Err(anyhow!(
+                        "vendored pip artifacts not found at {}; run `pyoxidizer vendor` first",
+                        path.display()
+                    ));
+                }
+
+                options.offline = true;
+                options.find_links.push(path);
+            }
+        }
+
+        Ok(options)
+    }
+
     /// Register a named target.
     pub fn register_target(
         &mut self,
@@ -205,8 +513,40 @@ impl EnvironmentContext {
         }
     }
 
+    /// Directory files of a target are written to.
+    pub fn target_output_path(&self, target: &str) -> PathBuf {
+        self.output_layout.target_path(
+            &self.cwd,
+            &self.build_path,
+            &self.build_target_triple,
+            self.build_release,
+            target,
+        )
+    }
+
+    /// Obtain the context for building a target.
+    ///
+    /// The target's output directory is created.
+    pub fn build_context(&self, target: &str) -> Result<BuildContext> {
+        let output_path = self.target_output_path(target);
+
+        std::fs::create_dir_all(&output_path).context("creating output path")?;
+
+        Ok(BuildContext {
+            logger: self.logger.clone(),
+            host_triple: self.build_host_triple.clone(),
+            target_triple: self.build_target_triple.clone(),
+            release: self.build_release,
+            opt_level: self.build_opt_level.clone(),
+            output_path,
+            license_policy: self.license_policy.clone(),
+        })
+    }
+
     /// Build a resolved target.
     pub fn build_resolved_target(&mut self, target: &str) -> Result<ResolvedTarget> {
+        let _phase = crate::timing::phase(&format!("build target {}", target));
+
         let resolved_value = if let Some(t) = self.targets.get(target) {
             if let Some(t) = &t.built_target {
                 return Ok(t.clone());
@@ -224,26 +564,7 @@ impl EnvironmentContext {
         let mut raw_value = resolved_value.0.borrow_mut();
         let raw_any = raw_value.as_any_mut();
 
-        let output_path = self
-            .build_path
-            .join(&self.build_target_triple)
-            .join(if self.build_release {
-                "release"
-            } else {
-                "debug"
-            })
-            .join(target);
-
-        std::fs::create_dir_all(&output_path).context("creating output path")?;
-
-        let context = BuildContext {
-            logger: self.logger.clone(),
-            host_triple: self.build_host_triple.clone(),
-            target_triple: self.build_target_triple.clone(),
-            release: self.build_release,
-            opt_level: self.build_opt_level.clone(),
-            output_path,
-        };
+        let context = self.build_context(target)?;
 
         let resolved_target: ResolvedTarget = if raw_any.is::<FileManifest>() {
             raw_any
@@ -260,6 +581,46 @@ impl EnvironmentContext {
                 .downcast_mut::<PythonEmbeddedData>()
                 .unwrap()
                 .build(&context)
+        } else if raw_any.is::<PythonCargoArtifacts>() {
+            raw_any
+                .downcast_mut::<PythonCargoArtifacts>()
+                .unwrap()
+                .build(&context)
+        } else if raw_any.is::<PythonZipApp>() {
+            raw_any
+                .downcast_mut::<PythonZipApp>()
+                .unwrap()
+                .build(&context)
+        } else if raw_any.is::<BuiltExecutable>() {
+            raw_any
+                .downcast_mut::<BuiltExecutable>()
+                .unwrap()
+                .build(&context)
+        } else if raw_any.is::<JupyterKernel>() {
+            raw_any
+                .downcast_mut::<JupyterKernel>()
+                .unwrap()
+                .build(&context)
+        } else if raw_any.is::<OciImageBuilder>() {
+            raw_any
+                .downcast_mut::<OciImageBuilder>()
+                .unwrap()
+                .build(&context)
+        } else if raw_any.is::<SnapcraftPackage>() {
+            raw_any
+                .downcast_mut::<SnapcraftPackage>()
+                .unwrap()
+                .build(&context)
+        } else if raw_any.is::<FlatpakPackage>() {
+            raw_any
+                .downcast_mut::<FlatpakPackage>()
+                .unwrap()
+                .build(&context)
+        } else if raw_any.is::<NsisInstallerBuilder>() {
+            raw_any
+                .downcast_mut::<NsisInstallerBuilder>()
+                .unwrap()
+                .build(&context)
         } else {
             Err(anyhow!("could not determine type of target"))
         }?;
@@ -269,6 +630,49 @@ impl EnvironmentContext {
         Ok(resolved_target)
     }
 
+    /// Obtain a build of a resolved target that can run on another thread.
+    ///
+    /// Starlark values can't be sent to other threads. So this is only
+    /// possible for values not referring to other Starlark values, like
+    /// `PythonExecutable`, which are cloned. `None` is returned for other
+    /// values, which must be built with `build_resolved_target()`.
+    ///
+    /// Use `set_built_target()` to record the result of the build.
+    pub fn detach_resolved_target_build(&self, target: &str) -> Result<Option<DetachedBuild>> {
+        let resolved_value = match self.targets.get(target) {
+            Some(t) => match &t.resolved_value {
+                Some(v) => v.clone(),
+                None => return Err(anyhow!("target {} is not resolved", target)),
+            },
+            None => return Err(anyhow!("target {} is not registered", target)),
+        };
+
+        let raw_value = resolved_value.0.borrow();
+        let phase_name = format!("build target {}", target);
+
+        Ok(raw_value
+            .as_any()
+            .downcast_ref::<PythonExecutable>()
+            .map(|exe| {
+                let mut exe = exe.clone();
+
+                Box::new(move |context: &BuildContext| {
+                    let _phase = crate::timing::phase(&phase_name);
+                    exe.build(context)
+                }) as DetachedBuild
+            }))
+    }
+
+    /// Record the result of building a target.
+    pub fn set_built_target(&mut self, target: &str, built: ResolvedTarget) -> Result<()> {
+        self.targets
+            .get_mut(target)
+            .ok_or_else(|| anyhow!("target {} is not registered", target))?
+            .built_target = Some(built);
+
+        Ok(())
+    }
+
     /// Build a target, defined optionally.
     ///
     /// This will build the default target if `target` is `None`.
@@ -291,6 +695,95 @@ impl EnvironmentContext {
         resolved_target.run()
     }
 
+    /// Build and run a test target.
+    ///
+    /// The executable target being tested must be resolved. A copy of its
+    /// executable running the test code is built in the test target's output
+    /// directory and executed. An error is returned if any test fails.
+    pub fn run_test_target(&self, target: &str) -> Result<()> {
+        let test = self
+            .test_targets
+            .get(target)
+            .ok_or_else(|| anyhow!("test target {} is not registered", target))?;
+
+        let resolved_value = self
+            .targets
+            .get(&test.executable_target)
+            .and_then(|t| t.resolved_value.clone())
+            .ok_or_else(|| anyhow!("target {} is not resolved", test.executable_target))?;
+
+        if resolved_value.get_type() != "PythonExecutable" {
+            return Err(anyhow!(
+                "target {} does not resolve to a PythonExecutable",
+                test.executable_target
+            ));
+        }
+
+        let mut exe = resolved_value.downcast_apply(|exe: &PythonExecutable| exe.clone());
+        exe.exe.set_run_mode(RunMode::Eval {
+            code: test.python_code()?,
+        });
+
+        let context = self.build_context(target)?;
+        let path = match exe.build(&context)?.run_mode {
+            super::target::RunMode::Path { path } => path,
+            super::target::RunMode::None => {
+                return Err(anyhow!("test target {} is not runnable", target));
+            }
+        };
+
+        warn!(&self.logger, "running test target {}", target);
+        let status = if test.relocate {
+            run_relocated(&self.logger, &context.output_path, &path)?
+        } else {
+            std::process::Command::new(&path)
+                .current_dir(&context.output_path)
+                .status()
+                .context(format!("running {}", path.display()))?
+        };
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(anyhow!("test target {} failed", target))
+        }
+    }
+
+    /// Build a copy of an executable target for development.
+    ///
+    /// The target must be resolved to a `PythonExecutable`. The copy imports
+    /// `packages` from `sys_paths` on the filesystem instead of embedding them,
+    /// so changes to their source take effect without rebuilding. Returns the
+    /// path to the built executable.
+    pub fn build_devserver_target(
+        &self,
+        target: &str,
+        packages: &[String],
+        sys_paths: &[String],
+    ) -> Result<PathBuf> {
+        let resolved_value = self
+            .targets
+            .get(target)
+            .and_then(|t| t.resolved_value.clone())
+            .ok_or_else(|| anyhow!("target {} is not resolved", target))?;
+
+        if resolved_value.get_type() != "PythonExecutable" {
+            return Err(anyhow!(
+                "target {} does not resolve to a PythonExecutable",
+                target
+            ));
+        }
+
+        let mut exe = resolved_value.downcast_apply(|exe: &PythonExecutable| exe.clone());
+        exe.exe.import_packages_from_filesystem(packages, sys_paths);
+
+        let context = self.build_context(&format!("{}-devserver", target))?;
+        match exe.build(&context)?.run_mode {
+            super::target::RunMode::Path { path } => Ok(path),
+            super::target::RunMode::None => Err(anyhow!("target {} is not runnable", target)),
+        }
+    }
+
     pub fn run_target(&mut self, target: Option<&str>) -> Result<()> {
         let target = if let Some(t) = target {
             t.to_string()
@@ -373,6 +866,142 @@ fn starlark_register_target(
     Ok(Value::new(None))
 }
 
+/// register_test_target(target, executable_target, imports=None, pytest_args=None, relocate=False)
+fn starlark_register_test_target(
+    env: &Environment,
+    target: &Value,
+    executable_target: &Value,
+    imports: &Value,
+    pytest_args: &Value,
+    relocate: &Value,
+) -> ValueResult {
+    let target = required_str_arg("target", &target)?;
+    let executable_target = required_str_arg("executable_target", &executable_target)?;
+    optional_list_arg("imports", "string", &imports)?;
+    optional_list_arg("pytest_args", "string", &pytest_args)?;
+    let relocate = required_bool_arg("relocate", &relocate)?;
+
+    let imports = match imports.get_type() {
+        "list" => imports.into_iter()?.map(|x| x.to_string()).collect(),
+        _ => Vec::new(),
+    };
+    let pytest_args = match pytest_args.get_type() {
+        "list" => Some(pytest_args.into_iter()?.map(|x| x.to_string()).collect()),
+        _ => None,
+    };
+
+    if imports.is_empty() && pytest_args.is_none() {
+        return Err(RuntimeError {
+            code: "PYOXIDIZER_BUILD",
+            message: "test target must define imports or pytest_args".to_string(),
+            label: "register_test_target()".to_string(),
+        }
+        .into());
+    }
+
+    let mut context = env.get("CONTEXT").expect("CONTEXT not set");
+
+    context.downcast_apply_mut(|x: &mut EnvironmentContext| {
+        x.test_targets.insert(
+            target.clone(),
+            TestTarget {
+                executable_target: executable_target.clone(),
+                imports: imports.clone(),
+                pytest_args: pytest_args.clone(),
+                relocate,
+            },
+        )
+    });
+
+    Ok(Value::new(None))
+}
+
+/// register_release_target(target, targets, version, tag=None, provider=None, repository=None, token_env=None, api_url=None)
+#[allow(clippy::too_many_arguments)]
+fn starlark_register_release_target(
+    env: &Environment,
+    target: &Value,
+    targets: &Value,
+    version: &Value,
+    tag: &Value,
+    provider: &Value,
+    repository: &Value,
+    token_env: &Value,
+    api_url: &Value,
+) -> ValueResult {
+    let target = required_str_arg("target", &target)?;
+    required_list_arg("targets", "string", &targets)?;
+    let version = required_str_arg("version", &version)?;
+    let tag = optional_str_arg("tag", &tag)?;
+    let provider = optional_str_arg("provider", &provider)?;
+    let repository = optional_str_arg("repository", &repository)?;
+    let token_env = optional_str_arg("token_env", &token_env)?;
+    let api_url = optional_str_arg("api_url", &api_url)?;
+
+    let targets = targets
+        .into_iter()?
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+    if targets.is_empty() {
+        return Err(RuntimeError {
+            code: "PYOXIDIZER_BUILD",
+            message: "release target must define targets to release".to_string(),
+            label: "register_release_target()".to_string(),
+        }
+        .into());
+    }
+
+    let provider = match provider {
+        Some(provider) => Some(ReleaseProvider::parse(&provider).map_err(|e| RuntimeError {
+            code: "PYOXIDIZER_BUILD",
+            message: e.to_string(),
+            label: "register_release_target()".to_string(),
+        })?),
+        None => None,
+    };
+
+    if provider.is_some() != repository.is_some() {
+        return Err(RuntimeError {
+            code: "PYOXIDIZER_BUILD",
+            message: "provider and repository must be defined together".to_string(),
+            label: "register_release_target()".to_string(),
+        }
+        .into());
+    }
+
+    let tag = tag.unwrap_or_else(|| format!("v{}", version));
+
+    let mut context = env.get("CONTEXT").expect("CONTEXT not set");
+
+    context.downcast_apply_mut(|x: &mut EnvironmentContext| {
+        x.release_targets.insert(
+            target.clone(),
+            ReleaseTarget {
+                targets: targets.clone(),
+                version: version.clone(),
+                tag: tag.clone(),
+                provider,
+                repository: repository.clone(),
+                token_env: token_env.clone(),
+                api_url: api_url.clone(),
+            },
+        )
+    });
+
+    Ok(Value::new(None))
+}
+
+/// register_resource_hook(callable)
+fn starlark_register_resource_hook(env: &Environment, callable: &Value) -> ValueResult {
+    required_type_arg("callable", "function", &callable)?;
+
+    let mut context = env.get("CONTEXT").expect("CONTEXT not set");
+    context
+        .downcast_apply_mut(|x: &mut EnvironmentContext| x.resource_hooks.push(callable.clone()));
+
+    Ok(Value::new(None))
+}
+
 /// resolve_target(target)
 ///
 /// This will return a Value returned from the called function.
@@ -458,10 +1087,11 @@ fn starlark_resolve_target(
 fn starlark_resolve_targets(env: &Environment, call_stack: &Vec<(String, String)>) -> ValueResult {
     let context = env.get("CONTEXT").expect("CONTEXT not set");
 
-    let targets =
-        context.downcast_apply(|context: &EnvironmentContext| context.targets_to_resolve());
+    let (logger, targets) = context.downcast_apply(|context: &EnvironmentContext| {
+        (context.logger.clone(), context.targets_to_resolve())
+    });
 
-    println!("resolving {} targets", targets.len());
+    warn!(logger, "resolving {} targets", targets.len());
     for target in targets {
         let resolve = env.get("resolve_target").unwrap();
 
@@ -497,29 +1127,315 @@ fn starlark_set_build_path(env: &Environment, path: &Value) -> ValueResult {
     Ok(Value::new(None))
 }
 
-starlark_module! { global_module =>
-    #[allow(clippy::ptr_arg)]
-    register_target(
-        env env,
-        target,
-        callable,
-        depends=None,
-        default=false,
-        default_build_script=false
-    ) {
-        starlark_register_target(
-            &env,
-            &target,
-            &callable,
-            &depends,
-            &default,
-            &default_build_script,
-        )
-    }
+/// set_output_layout(layout)
+fn starlark_set_output_layout(env: &Environment, layout: &Value) -> ValueResult {
+    let layout = required_str_arg("layout", &layout)?;
 
-    #[allow(clippy::ptr_arg)]
-    resolve_target(env env, call_stack cs, target) {
-        starlark_resolve_target(&env, &cs, &target)
+    let layout = OutputLayout::parse(&layout).or_else(|e| {
+        Err(RuntimeError {
+            code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            message: e.to_string(),
+            label: "set_output_layout()".to_string(),
+        }
+        .into())
+    })?;
+
+    let mut context = env.get("CONTEXT").expect("CONTEXT not set");
+    let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
+
+    // The layout given on the command line wins.
+    if std::env::var_os(OUTPUT_LAYOUT_ENV).is_some() {
+        info!(
+            logger,
+            "ignoring set_output_layout() as {} is defined", OUTPUT_LAYOUT_ENV
+        );
+    } else {
+        context.downcast_apply_mut(|x: &mut EnvironmentContext| x.output_layout = layout.clone());
+    }
+
+    Ok(Value::new(None))
+}
+
+/// register_distribution_mirror(prefix, url)
+fn starlark_register_distribution_mirror(
+    env: &Environment,
+    prefix: &Value,
+    url: &Value,
+) -> ValueResult {
+    let prefix = required_str_arg("prefix", &prefix)?;
+    let url = required_str_arg("url", &url)?;
+
+    if prefix.is_empty() || url.is_empty() {
+        return Err(RuntimeError {
+            code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            message: "prefix and url must not be empty".to_string(),
+            label: "register_distribution_mirror()".to_string(),
+        }
+        .into());
+    }
+
+    let mut context = env.get("CONTEXT").expect("CONTEXT not set");
+    context.downcast_apply_mut(|x: &mut EnvironmentContext| {
+        x.distribution_mirrors.add_project_rule(MirrorRule {
+            prefix: prefix.clone(),
+            replacement: url.clone(),
+        })
+    });
+
+    Ok(Value::new(None))
+}
+
+/// set_config_version(version)
+fn starlark_set_config_version(env: &Environment, version: &Value) -> ValueResult {
+    required_type_arg("version", "int", &version)?;
+    let version = version.to_int()?;
+
+    if version < DEFAULT_CONFIG_VERSION || version > CONFIG_VERSION {
+        return Err(RuntimeError {
+            code: "PYOXIDIZER_BUILD",
+            message: format!(
+                "config version {} is not supported; this version of PyOxidizer supports config versions {} to {}",
+                version, DEFAULT_CONFIG_VERSION, CONFIG_VERSION
+            ),
+            label: "set_config_version()".to_string(),
+        }
+        .into());
+    }
+
+    let mut context = env.get("CONTEXT").expect("CONTEXT not set");
+    context.downcast_apply_mut(|x: &mut EnvironmentContext| x.config_version = version);
+
+    Ok(Value::new(None))
+}
+
+/// set_distribution_cache_path(path)
+fn starlark_set_distribution_cache_path(env: &Environment, path: &Value) -> ValueResult {
+    let path = required_str_arg("path", &path)?;
+    let mut context = env.get("CONTEXT").expect("CONTEXT not set");
+
+    context
+        .downcast_apply_mut(|x: &mut EnvironmentContext| {
+            x.set_distribution_cache_path(&PathBuf::from(&path))
+        })
+        .or_else(|e| {
+            Err(RuntimeError {
+                code: "PYOXIDIZER_BUILD",
+                message: e.to_string(),
+                label: "set_distribution_cache_path()".to_string(),
+            }
+            .into())
+        })?;
+
+    Ok(Value::new(None))
+}
+
+/// set_distribution_signing_keys(gpg=None, minisign=None)
+fn starlark_set_distribution_signing_keys(
+    env: &Environment,
+    gpg: &Value,
+    minisign: &Value,
+) -> ValueResult {
+    optional_list_arg("gpg", "string", &gpg)?;
+    optional_list_arg("minisign", "string", &minisign)?;
+
+    let mut context = env.get("CONTEXT").expect("CONTEXT not set");
+    let cwd = context.downcast_apply(|x: &EnvironmentContext| x.cwd.clone());
+
+    let mut keys = Vec::new();
+
+    if gpg.get_type() == "list" {
+        for path in gpg.into_iter()? {
+            keys.push(SigningKey::Gpg {
+                path: cwd.join(path.to_string()),
+            });
+        }
+    }
+
+    if minisign.get_type() == "list" {
+        for public_key in minisign.into_iter()? {
+            keys.push(SigningKey::Minisign {
+                public_key: public_key.to_string(),
+            });
+        }
+    }
+
+    context.downcast_apply_mut(|x: &mut EnvironmentContext| {
+        x.distribution_verifier.keys = keys.clone();
+    });
+
+    Ok(Value::new(None))
+}
+
+/// set_scratch_path(path, max_size=None)
+fn starlark_set_scratch_path(env: &Environment, path: &Value, max_size: &Value) -> ValueResult {
+    let path = required_str_arg("path", &path)?;
+    let max_size = optional_str_arg("max_size", &max_size)?;
+
+    if let Some(size) = &max_size {
+        parse_size(size).or_else(|e| {
+            Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: e,
+                label: "set_scratch_path()".to_string(),
+            }
+            .into())
+        })?;
+    }
+
+    let context = env.get("CONTEXT").expect("CONTEXT not set");
+    let (logger, cwd) =
+        context.downcast_apply(|x: &EnvironmentContext| (x.logger.clone(), x.cwd.clone()));
+
+    // Scratch directories are created throughout the process, so the
+    // settings are process wide. Settings from the environment win.
+    if std::env::var_os(SCRATCH_PATH_ENV).is_some() {
+        info!(
+            logger,
+            "ignoring set_scratch_path() as {} is defined", SCRATCH_PATH_ENV
+        );
+    } else {
+        std::env::set_var(SCRATCH_PATH_ENV, cwd.join(path));
+    }
+
+    if let Some(size) = max_size {
+        if std::env::var_os(SCRATCH_MAX_SIZE_ENV).is_none() {
+            std::env::set_var(SCRATCH_MAX_SIZE_ENV, size);
+        }
+    }
+
+    Ok(Value::new(None))
+}
+
+/// set_license_policy(deny=None, warn=None)
+fn starlark_set_license_policy(env: &Environment, deny: &Value, warn: &Value) -> ValueResult {
+    optional_list_arg("deny", "string", &deny)?;
+    optional_list_arg("warn", "string", &warn)?;
+
+    let to_strings = |value: &Value| -> Result<Vec<String>, ValueError> {
+        if value.get_type() == "list" {
+            Ok(value.into_iter()?.map(|x| x.to_string()).collect())
+        } else {
+            Ok(Vec::new())
+        }
+    };
+
+    let policy =
+        LicensePolicy::new(to_strings(deny)?, to_strings(warn)?).map_err(|e| RuntimeError {
+            code: "PYOXIDIZER_BUILD",
+            message: e.to_string(),
+            label: "set_license_policy()".to_string(),
+        })?;
+
+    let mut context = env.get("CONTEXT").expect("CONTEXT not set");
+    context.downcast_apply_mut(|x: &mut EnvironmentContext| x.license_policy = policy.clone());
+
+    Ok(Value::new(None))
+}
+
+/// set_offline(vendor_path="vendor")
+fn starlark_set_offline(env: &Environment, vendor_path: &Value) -> ValueResult {
+    let vendor_path = required_str_arg("vendor_path", &vendor_path)?;
+    let mut context = env.get("CONTEXT").expect("CONTEXT not set");
+
+    context
+        .downcast_apply_mut(|x: &mut EnvironmentContext| -> Result<()> {
+            // `pyoxidizer vendor` defines its own destination and needs the network.
+            if !x.vendoring {
+                x.set_vendor_path(&PathBuf::from(&vendor_path))?;
+                x.offline = true;
+            }
+
+            Ok(())
+        })
+        .or_else(|e| {
+            Err(RuntimeError {
+                code: "PYOXIDIZER_BUILD",
+                message: e.to_string(),
+                label: "set_offline()".to_string(),
+            }
+            .into())
+        })?;
+
+    Ok(Value::new(None))
+}
+
+starlark_module! { global_module =>
+    #[allow(clippy::ptr_arg)]
+    register_target(
+        env env,
+        target,
+        callable,
+        depends=None,
+        default=false,
+        default_build_script=false
+    ) {
+        starlark_register_target(
+            &env,
+            &target,
+            &callable,
+            &depends,
+            &default,
+            &default_build_script,
+        )
+    }
+
+    #[allow(clippy::ptr_arg)]
+    register_test_target(
+        env env,
+        target,
+        executable_target,
+        imports=None,
+        pytest_args=None,
+        relocate=false
+    ) {
+        starlark_register_test_target(
+            &env,
+            &target,
+            &executable_target,
+            &imports,
+            &pytest_args,
+            &relocate,
+        )
+    }
+
+    #[allow(clippy::ptr_arg)]
+    register_release_target(
+        env env,
+        target,
+        targets,
+        version,
+        tag=None,
+        provider=None,
+        repository=None,
+        token_env=None,
+        api_url=None
+    ) {
+        starlark_register_release_target(
+            &env,
+            &target,
+            &targets,
+            &version,
+            &tag,
+            &provider,
+            &repository,
+            &token_env,
+            &api_url,
+        )
+    }
+
+    #[allow(clippy::ptr_arg)]
+    register_resource_hook(env env, callable) {
+        starlark_register_resource_hook(&env, &callable)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    register_distribution_mirror(env env, prefix, url) {
+        starlark_register_distribution_mirror(&env, &prefix, &url)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    resolve_target(env env, call_stack cs, target) {
+        starlark_resolve_target(&env, &cs, &target)
     }
 
     #[allow(clippy::ptr_arg)]
@@ -531,16 +1447,59 @@ starlark_module! { global_module =>
     set_build_path(env env, path) {
         starlark_set_build_path(&env, &path)
     }
+
+    #[allow(clippy::ptr_arg)]
+    set_config_version(env env, version) {
+        starlark_set_config_version(&env, &version)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    set_distribution_cache_path(env env, path) {
+        starlark_set_distribution_cache_path(&env, &path)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    set_distribution_signing_keys(env env, gpg=None, minisign=None) {
+        starlark_set_distribution_signing_keys(&env, &gpg, &minisign)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    set_license_policy(env env, deny=None, warn=None) {
+        starlark_set_license_policy(&env, &deny, &warn)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    set_output_layout(env env, layout) {
+        starlark_set_output_layout(&env, &layout)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    set_scratch_path(env env, path, max_size=None) {
+        starlark_set_scratch_path(&env, &path, &max_size)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    set_offline(env env, vendor_path="vendor") {
+        starlark_set_offline(&env, &vendor_path)
+    }
 }
 
 /// Obtain a Starlark environment for evaluating PyOxidizer configurations.
 pub fn global_environment(context: &EnvironmentContext) -> Result<Environment, EnvironmentError> {
     let env = starlark::stdlib::global_environment();
     let env = global_module(env);
+    let env = super::built_executable::built_executable_env(env);
     let env = super::file_resource::file_resource_env(env);
+    let env = super::install_layout::install_layout_env(env);
+    let env = super::linux_package::linux_package_env(env);
+    let env = super::nsis_installer::nsis_installer_env(env);
+    let env = super::oci_image::oci_image_env(env);
     let env = super::python_distribution::python_distribution_module(env);
     let env = super::python_executable::python_executable_env(env);
     let env = super::python_interpreter_config::embedded_python_config_module(env);
+    let env = super::python_resource::python_resource_env(env);
+    let env = super::python_zipapp::python_zipapp_env(env);
+    let env = super::stdlib::stdlib_env(env);
 
     env.set("CONTEXT", Value::new(context.clone()))?;
 
@@ -553,6 +1512,7 @@ pub fn global_environment(context: &EnvironmentContext) -> Result<Environment, E
         "BUILD_TARGET_TRIPLE",
         Value::from(context.build_target_triple.clone()),
     )?;
+    env.set("BUILD_RELEASE", Value::new(context.build_release))?;
 
     Ok(env)
 }
@@ -575,6 +1535,119 @@ pub mod tests {
         assert_eq!(target.to_str(), crate::project_building::HOST);
     }
 
+    #[test]
+    fn test_build_release() {
+        let release = starlark_ok("BUILD_RELEASE");
+        assert_eq!(release.get_type(), "bool");
+    }
+
+    #[test]
+    fn test_set_offline() {
+        let mut env = starlark_env();
+        starlark_eval_in_env(&mut env, "set_offline()").unwrap();
+
+        let context = env.get("CONTEXT").unwrap();
+
+        context.downcast_apply(|x: &EnvironmentContext| {
+            let vendor_path = x.cwd.join("vendor");
+
+            assert!(x.offline);
+            assert_eq!(x.vendor_path, Some(vendor_path.clone()));
+            assert_eq!(
+                x.python_distributions_path,
+                vendor_path.join("python_distributions")
+            );
+            assert_eq!(x.cargo_vendor_path(), Some(vendor_path.join("cargo")));
+        });
+    }
+
+    #[test]
+    fn test_distribution_mirror() {
+        let mut env = starlark_env();
+        starlark_eval_in_env(
+            &mut env,
+            "register_distribution_mirror('https://github.com/', 'https://mirror.example.com/')",
+        )
+        .unwrap();
+        starlark_eval_in_env(&mut env, "set_distribution_cache_path('dists')").unwrap();
+        starlark_eval_in_env(&mut env, "set_build_path('other')").unwrap();
+
+        let context = env.get("CONTEXT").unwrap();
+
+        context.downcast_apply(|x: &EnvironmentContext| {
+            assert_eq!(
+                x.distribution_mirrors
+                    .rewrite_url("https://github.com/indygreg/python.tar.zst"),
+                Some("https://mirror.example.com/indygreg/python.tar.zst".to_string())
+            );
+            assert_eq!(x.distribution_cache_path, Some(x.cwd.join("dists")));
+            assert_eq!(x.python_distributions_path, x.cwd.join("dists"));
+        });
+
+        starlark_nok("register_distribution_mirror('', 'https://mirror/')");
+    }
+
+    #[test]
+    fn test_set_distribution_signing_keys() {
+        let mut env = starlark_env();
+        starlark_eval_in_env(
+            &mut env,
+            "set_distribution_signing_keys(gpg=['keys/release.asc'], minisign=['RWQkey'])",
+        )
+        .unwrap();
+
+        let context = env.get("CONTEXT").unwrap();
+
+        context.downcast_apply(|x: &EnvironmentContext| {
+            assert_eq!(
+                x.distribution_verifier.keys,
+                vec![
+                    SigningKey::Gpg {
+                        path: x.cwd.join("keys/release.asc")
+                    },
+                    SigningKey::Minisign {
+                        public_key: "RWQkey".to_string()
+                    },
+                ]
+            );
+        });
+
+        starlark_nok("set_distribution_signing_keys(gpg='keys/release.asc')");
+    }
+
+    #[test]
+    fn test_set_scratch_path_invalid() {
+        let err = starlark_nok("set_scratch_path('scratch', max_size='10 parsecs')");
+        assert_eq!(err.message, "PARSECS is not a valid size unit");
+
+        starlark_nok("set_scratch_path(None)");
+    }
+
+    #[test]
+    fn test_set_output_layout() {
+        let mut env = starlark_env();
+        starlark_eval_in_env(
+            &mut env,
+            "set_output_layout('dist/{target_triple}/{target}')",
+        )
+        .unwrap();
+
+        let context = env.get("CONTEXT").unwrap();
+
+        context.downcast_apply(|x: &EnvironmentContext| {
+            assert_eq!(
+                x.target_output_path("exe"),
+                x.cwd.join("dist").join(&x.build_target_triple).join("exe")
+            );
+        });
+
+        let err = starlark_nok("set_output_layout('dist/{target_triple}')");
+        assert_eq!(
+            err.message,
+            "output layout dist/{target_triple} must contain {target}"
+        );
+    }
+
     #[test]
     fn test_register_target() {
         let mut env = starlark_env();
@@ -618,4 +1691,161 @@ pub mod tests {
             );
         });
     }
+
+    #[test]
+    fn test_register_resource_hook() {
+        let mut env = starlark_env();
+        starlark_eval_in_env(&mut env, "def hook(resource): return resource").unwrap();
+        starlark_eval_in_env(&mut env, "register_resource_hook(hook)").unwrap();
+
+        let context = env.get("CONTEXT").unwrap();
+
+        context.downcast_apply(|x: &EnvironmentContext| {
+            assert_eq!(x.resource_hooks.len(), 1);
+            assert_eq!(
+                x.resource_hooks[0].to_string(),
+                "hook(resource)".to_string()
+            );
+        });
+
+        assert!(starlark_eval_in_env(&mut env, "register_resource_hook('hook')").is_err());
+    }
+
+    #[test]
+    fn test_register_test_target() {
+        let mut env = starlark_env();
+        starlark_eval_in_env(
+            &mut env,
+            "register_test_target('test', 'exe', imports=['foo', 'bar.baz'])",
+        )
+        .unwrap();
+
+        let context = env.get("CONTEXT").unwrap();
+
+        context.downcast_apply(|x: &EnvironmentContext| {
+            assert_eq!(x.test_targets.len(), 1);
+            let test = x.test_targets.get("test").unwrap();
+            assert_eq!(test.executable_target, "exe");
+            assert_eq!(test.imports, vec!["foo".to_string(), "bar.baz".to_string()]);
+            assert_eq!(test.pytest_args, None);
+            assert!(!test.relocate);
+            assert!(test
+                .python_code()
+                .unwrap()
+                .contains("for name in [\"foo\",\"bar.baz\"]:"));
+            assert!(!test.python_code().unwrap().contains("pytest"));
+        });
+
+        starlark_eval_in_env(
+            &mut env,
+            "register_test_target('pytest', 'exe', pytest_args=['-x', 'tests'], relocate=True)",
+        )
+        .unwrap();
+
+        let context = env.get("CONTEXT").unwrap();
+
+        context.downcast_apply(|x: &EnvironmentContext| {
+            let test = x.test_targets.get("pytest").unwrap();
+            assert!(test.imports.is_empty());
+            assert!(test.relocate);
+            assert!(test
+                .python_code()
+                .unwrap()
+                .contains("if pytest.main([\"-x\",\"tests\"]) != 0:"));
+        });
+
+        assert!(starlark_eval_in_env(&mut env, "register_test_target('test', 'exe')").is_err());
+    }
+
+    #[test]
+    fn test_register_release_target() {
+        let mut env = starlark_env();
+        starlark_eval_in_env(
+            &mut env,
+            "register_release_target('release', ['exe', 'installer'], '1.2.0')",
+        )
+        .unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "register_release_target('github', ['exe'], '1.2.0', tag='1.2.0', provider='github', repository='owner/app', token_env='RELEASE_TOKEN')",
+        )
+        .unwrap();
+
+        let context = env.get("CONTEXT").unwrap();
+
+        context.downcast_apply(|x: &EnvironmentContext| {
+            assert_eq!(x.release_targets.len(), 2);
+
+            let release = x.release_targets.get("release").unwrap();
+            assert_eq!(
+                release.targets,
+                vec!["exe".to_string(), "installer".to_string()]
+            );
+            assert_eq!(release.version, "1.2.0");
+            assert_eq!(release.tag, "v1.2.0");
+            assert_eq!(release.provider, None);
+
+            let release = x.release_targets.get("github").unwrap();
+            assert_eq!(release.tag, "1.2.0");
+            assert_eq!(release.provider, Some(ReleaseProvider::GitHub));
+            assert_eq!(release.repository, Some("owner/app".to_string()));
+            assert_eq!(release.token_env, Some("RELEASE_TOKEN".to_string()));
+            assert_eq!(release.api_url, None);
+        });
+
+        assert!(starlark_eval_in_env(&mut env, "register_release_target('r', [], '1.0')").is_err());
+        assert!(starlark_eval_in_env(
+            &mut env,
+            "register_release_target('r', ['exe'], '1.0', provider='github')"
+        )
+        .is_err());
+        assert_eq!(
+            starlark_nok(
+                "register_release_target('r', ['exe'], '1.0', provider='gitea', repository='a/b')"
+            )
+            .message,
+            "unknown release provider gitea; expected github or gitlab"
+        );
+    }
+
+    #[test]
+    fn test_set_config_version() {
+        let mut env = starlark_env();
+
+        let context = env.get("CONTEXT").unwrap();
+        context.downcast_apply(|x: &EnvironmentContext| {
+            assert_eq!(x.config_version, DEFAULT_CONFIG_VERSION);
+        });
+
+        starlark_eval_in_env(&mut env, "set_config_version(2)").unwrap();
+
+        let context = env.get("CONTEXT").unwrap();
+        context.downcast_apply(|x: &EnvironmentContext| {
+            assert_eq!(x.config_version, 2);
+        });
+
+        assert!(starlark_eval_in_env(&mut env, "set_config_version(0)").is_err());
+        assert!(starlark_eval_in_env(&mut env, "set_config_version(99)").is_err());
+        assert!(starlark_eval_in_env(&mut env, "set_config_version('2')").is_err());
+    }
+
+    #[test]
+    fn test_set_license_policy() {
+        let mut env = starlark_env();
+        starlark_eval_in_env(
+            &mut env,
+            "set_license_policy(deny=['GPL-3.0'], warn=['LGPL-*'])",
+        )
+        .unwrap();
+
+        let context = env.get("CONTEXT").unwrap();
+
+        context.downcast_apply(|x: &EnvironmentContext| {
+            assert_eq!(x.license_policy.deny, vec!["GPL-3.0".to_string()]);
+            assert_eq!(x.license_policy.warn, vec!["LGPL-*".to_string()]);
+        });
+
+        assert!(starlark_eval_in_env(&mut env, "set_license_policy(deny=['['])").is_err());
+        assert!(starlark_eval_in_env(&mut env, "set_license_policy(deny='GPL-3.0')").is_err());
+    }
 }
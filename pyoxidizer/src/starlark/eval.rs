@@ -3,12 +3,15 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
+    super::diagnostics::{defined_names, location, render},
     super::env::{global_environment, EnvironmentContext},
     anyhow::{anyhow, Result},
     codemap::CodeMap,
     codemap_diagnostic::{Diagnostic, Level},
     starlark::environment::Environment,
-    std::path::Path,
+    starlark::values::Value,
+    std::collections::HashMap,
+    std::path::{Path, PathBuf},
     std::sync::{Arc, Mutex},
 };
 
@@ -19,6 +22,83 @@ pub struct EvalResult {
     pub context: EnvironmentContext,
 }
 
+impl EvalResult {
+    /// Enable or disable dry run mode for subsequent target resolution.
+    pub fn set_dry_run(&mut self, dry_run: bool) -> Result<()> {
+        let mut env_context = self
+            .env
+            .get("CONTEXT")
+            .or_else(|e| Err(anyhow!("{:?}", e)))?;
+        env_context.downcast_apply_mut(|x: &mut EnvironmentContext| x.dry_run = dry_run);
+        self.context.dry_run = dry_run;
+
+        Ok(())
+    }
+
+    /// Download artifacts needed by builds to a vendor directory during subsequent target resolution.
+    ///
+    /// If `path` is not defined, the directory configured by `set_offline()` is
+    /// used. Returns the resolved vendor directory.
+    pub fn set_vendoring(&mut self, path: Option<&Path>) -> Result<PathBuf> {
+        let mut env_context = self
+            .env
+            .get("CONTEXT")
+            .or_else(|e| Err(anyhow!("{:?}", e)))?;
+
+        let vendor_path =
+            env_context.downcast_apply_mut(|x: &mut EnvironmentContext| -> Result<PathBuf> {
+                match path {
+                    Some(path) => x.set_vendor_path(path)?,
+                    None if x.vendor_path.is_some() => {}
+                    None => {
+                        return Err(anyhow!(
+                            "no vendor directory specified and config does not call set_offline()"
+                        ))
+                    }
+                }
+
+                x.offline = false;
+                x.vendoring = true;
+
+                Ok(x.vendor_path.clone().unwrap())
+            })?;
+
+        self.context = env_context.downcast_apply(|x: &EnvironmentContext| x.clone());
+
+        Ok(vendor_path)
+    }
+
+    /// Resolve a target after evaluation.
+    ///
+    /// `context` is updated to reflect the resolved state.
+    pub fn resolve_target(&mut self, target: &str) -> Result<Value> {
+        let resolve = self
+            .env
+            .get("resolve_target")
+            .or_else(|e| Err(anyhow!("{:?}", e)))?;
+
+        let call_stack: Vec<(String, String)> = Vec::new();
+        let value = resolve
+            .call(
+                &call_stack,
+                self.env.clone(),
+                vec![Value::new(target.to_string())],
+                HashMap::new(),
+                None,
+                None,
+            )
+            .or_else(|e| Err(anyhow!("error resolving target {}: {:?}", target, e)))?;
+
+        let env_context = self
+            .env
+            .get("CONTEXT")
+            .or_else(|e| Err(anyhow!("{:?}", e)))?;
+        self.context = env_context.downcast_apply(|x: &EnvironmentContext| x.clone());
+
+        Ok(value)
+    }
+}
+
 /// Evaluate a Starlark configuration file, returning a low-level result.
 pub fn evaluate_file(
     logger: &slog::Logger,
@@ -28,6 +108,56 @@ pub fn evaluate_file(
     verbose: bool,
     resolve_targets: Option<Vec<String>>,
     build_script_mode: bool,
+) -> Result<EvalResult, Diagnostic> {
+    evaluate(
+        logger,
+        config_path,
+        None,
+        build_target_triple,
+        release,
+        verbose,
+        resolve_targets,
+        build_script_mode,
+    )
+}
+
+/// Evaluate Starlark configuration source code, returning a low-level result.
+///
+/// `config_path` doesn't need to exist. It is used to derive the working and
+/// build directories as if the source were read from that path.
+#[allow(clippy::too_many_arguments)]
+pub fn evaluate_source(
+    logger: &slog::Logger,
+    config_path: &Path,
+    source: &str,
+    build_target_triple: &str,
+    release: bool,
+    verbose: bool,
+    resolve_targets: Option<Vec<String>>,
+    build_script_mode: bool,
+) -> Result<EvalResult, Diagnostic> {
+    evaluate(
+        logger,
+        config_path,
+        Some(source),
+        build_target_triple,
+        release,
+        verbose,
+        resolve_targets,
+        build_script_mode,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn evaluate(
+    logger: &slog::Logger,
+    config_path: &Path,
+    source: Option<&str>,
+    build_target_triple: &str,
+    release: bool,
+    verbose: bool,
+    resolve_targets: Option<Vec<String>>,
+    build_script_mode: bool,
 ) -> Result<EvalResult, Diagnostic> {
     let context = EnvironmentContext::new(
         logger,
@@ -60,19 +190,26 @@ pub fn evaluate_file(
     })?;
 
     let map = Arc::new(Mutex::new(CodeMap::new()));
-    starlark::eval::simple::eval_file(&map, &config_path.display().to_string(), false, &mut env)
-        .or_else(|e| {
-            let mut msg = Vec::new();
-            let raw_map = map.lock().unwrap();
-            {
-                let mut emitter = codemap_diagnostic::Emitter::vec(&mut msg, Some(&raw_map));
-                emitter.emit(&[e.clone()]);
-            }
+    let path = config_path.display().to_string();
+    if let Some(source) = source {
+        starlark::eval::simple::eval(&map, &path, source, false, &mut env)
+    } else {
+        starlark::eval::simple::eval_file(&map, &path, false, &mut env)
+    }
+    .or_else(|e| {
+        let raw_map = map.lock().unwrap();
 
-            slog::error!(logger, "{}", String::from_utf8_lossy(&msg));
+        slog::error!(logger, "{}", render(&raw_map, &e, &defined_names(&env)));
 
-            Err(e)
-        })?;
+        // Prefix the message with the error location so it is available to
+        // consumers that only see the message.
+        let mut e = e;
+        if let Some(location) = location(&raw_map, &e) {
+            e.message = format!("{}: {}", location, e.message);
+        }
+
+        Err(e)
+    })?;
 
     // The EnvironmentContext is cloned as part of evaluation, which is a bit wonky.
     // TODO avoid this clone.
@@ -101,6 +238,8 @@ pub fn eval_starlark_config_file(
     resolve_targets: Option<Vec<String>>,
     build_script_mode: bool,
 ) -> Result<EvalResult> {
+    let _phase = crate::timing::phase("evaluate configuration");
+
     crate::starlark::eval::evaluate_file(
         logger,
         path,
@@ -112,3 +251,28 @@ pub fn eval_starlark_config_file(
     )
     .or_else(|d| Err(anyhow!(d.message)))
 }
+
+/// Evaluate Starlark configuration source code and return its result.
+#[allow(clippy::too_many_arguments)]
+pub fn eval_starlark_config_source(
+    logger: &slog::Logger,
+    path: &Path,
+    source: &str,
+    build_target_triple: &str,
+    release: bool,
+    verbose: bool,
+    resolve_targets: Option<Vec<String>>,
+    build_script_mode: bool,
+) -> Result<EvalResult> {
+    crate::starlark::eval::evaluate_source(
+        logger,
+        path,
+        source,
+        build_target_triple,
+        release,
+        verbose,
+        resolve_targets,
+        build_script_mode,
+    )
+    .or_else(|d| Err(anyhow!(d.message)))
+}
@@ -7,21 +7,29 @@ use {
     super::python_executable::PythonExecutable,
     super::python_resource::PythonExtensionModuleFlavor,
     super::python_resource::{
-        PythonBytecodeModule, PythonExtensionModule, PythonResourceData, PythonSourceModule,
+        PythonBytecodeModule, PythonExtensionModule, PythonPackageDistributionResource,
+        PythonPackageLicense, PythonResourceData, PythonSourceModule,
     },
     super::target::{BuildContext, BuildTarget, ResolvedTarget, RunMode},
     super::util::{
-        optional_list_arg, optional_str_arg, required_bool_arg, required_list_arg,
-        required_str_arg, required_type_arg,
+        optional_dict_arg, optional_list_arg, optional_str_arg, required_bool_arg,
+        required_list_arg, required_str_arg, required_type_arg,
     },
     crate::app_packaging::glob::evaluate_glob,
+    crate::app_packaging::linux::{
+        icon_install_path, is_valid_unit_name, DesktopEntry, SystemdService, DESKTOP_ENTRY_DIR,
+        SYSTEMD_UNIT_DIR,
+    },
     crate::app_packaging::resource::{
         FileContent as RawFileContent, FileManifest as RawFileManifest,
     },
-    crate::project_building::build_python_executable,
+    crate::licensing::{render_third_party_notices, THIRD_PARTY_NOTICES_STEM},
+    crate::project_building::{build_python_executable, exe_filename, RustBuildOptions},
     crate::py_packaging::binary::PythonBinaryBuilder,
+    crate::py_packaging::embedded_resource::merge_packed_resources,
     crate::py_packaging::resource::BytecodeModule,
     crate::py_packaging::standalone_distribution::ExtensionModule,
+    crate::sbom::render_sbom,
     anyhow::Result,
     itertools::Itertools,
     slog::warn,
@@ -36,7 +44,7 @@ use {
     },
     std::any::Any,
     std::cmp::Ordering,
-    std::collections::{HashMap, HashSet},
+    std::collections::{BTreeMap, HashMap, HashSet},
     std::convert::TryFrom,
     std::ops::Deref,
     std::path::Path,
@@ -80,13 +88,19 @@ pub struct FileManifest {
 
 impl FileManifest {
     // TODO implement.
-    fn add_bytecode_module(&self, _prefix: &str, _module: &BytecodeModule) {
-        println!("support for adding bytecode modules not yet implemented");
+    fn add_bytecode_module(&self, logger: &slog::Logger, _prefix: &str, _module: &BytecodeModule) {
+        warn!(
+            logger,
+            "support for adding bytecode modules not yet implemented"
+        );
     }
 
     // TODO implement.
-    fn add_extension_module(&self, _prefix: &str, _em: &ExtensionModule) {
-        println!("support for adding extension modules not yet implemented");
+    fn add_extension_module(&self, logger: &slog::Logger, _prefix: &str, _em: &ExtensionModule) {
+        warn!(
+            logger,
+            "support for adding extension modules not yet implemented"
+        );
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -95,21 +109,68 @@ impl FileManifest {
         logger: &slog::Logger,
         prefix: &str,
         exe: &dyn PythonBinaryBuilder,
+        build_options: &RustBuildOptions,
         target: &str,
         release: bool,
         opt_level: &str,
     ) -> Result<()> {
-        let (filename, data) =
-            build_python_executable(logger, &exe.name(), exe, target, opt_level, release)?;
+        let resources_path = exe.config().resources_file_relative_path()?;
+
+        let built = build_python_executable(
+            logger,
+            &exe.name(),
+            exe,
+            target,
+            opt_level,
+            release,
+            build_options,
+        )?;
 
         let content = RawFileContent {
-            data,
+            data: built.data,
             executable: true,
         };
 
-        let path = Path::new(&prefix).join(filename);
+        let path = Path::new(&prefix).join(&built.filename);
         self.manifest.add_file(&path, &content)?;
 
+        for (path, content) in built.debug_files.entries() {
+            self.manifest
+                .add_file(&Path::new(&prefix).join(path), content)?;
+        }
+
+        // Executables installed to the same directory can share a resources
+        // file, which then holds the resources of all of them.
+        if let (Some(path), Some(data)) = (resources_path, built.resources_data) {
+            let path = Path::new(&prefix).join(path);
+
+            let data = match self.manifest.get(&path) {
+                Some(existing) => {
+                    warn!(logger, "merging resources into {}", path.display());
+                    merge_packed_resources(&existing.data, &data)?
+                }
+                None => data,
+            };
+
+            self.manifest.add_file(
+                &path,
+                &RawFileContent {
+                    data,
+                    executable: false,
+                },
+            )?;
+        }
+
+        if let Some(format) = build_options.sbom {
+            let content = RawFileContent {
+                data: render_sbom(format, &exe.name(), exe)?.into_bytes(),
+                executable: false,
+            };
+
+            let path = Path::new(&prefix).join(format.filename(&exe.name()));
+            self.manifest.add_file(&path, &content)?;
+        }
+
         // Add any additional files that the exe builder requires.
         let extra_files = exe.extra_install_files(logger, prefix)?;
         for (path, _) in extra_files.entries() {
@@ -120,6 +181,35 @@ impl FileManifest {
 
         Ok(())
     }
+
+    /// Add files describing licenses of third party components in an executable.
+    fn add_third_party_notices(
+        &mut self,
+        prefix: &str,
+        exe: &dyn PythonBinaryBuilder,
+    ) -> Result<()> {
+        let components = exe.licensed_components();
+
+        let text = RawFileContent {
+            data: render_third_party_notices(&components).into_bytes(),
+            executable: false,
+        };
+        let json = RawFileContent {
+            data: serde_json::to_vec_pretty(&components)?,
+            executable: false,
+        };
+
+        self.manifest.add_file(
+            &Path::new(prefix).join(format!("{}.txt", THIRD_PARTY_NOTICES_STEM)),
+            &text,
+        )?;
+        self.manifest.add_file(
+            &Path::new(prefix).join(format!("{}.json", THIRD_PARTY_NOTICES_STEM)),
+            &json,
+        )?;
+
+        Ok(())
+    }
 }
 
 impl BuildTarget for FileManifest {
@@ -150,6 +240,11 @@ impl BuildTarget for FileManifest {
         Ok(ResolvedTarget {
             run_mode,
             output_path: context.output_path.clone(),
+            artifacts: self
+                .manifest
+                .entries()
+                .map(|(path, _)| context.output_path.join(path))
+                .collect(),
         })
     }
 }
@@ -237,7 +332,7 @@ impl FileManifest {
             "PythonBytecodeModule" => {
                 let m = resource.downcast_apply(|m: &PythonBytecodeModule| m.module.clone());
                 warn!(logger, "adding bytecode module {} to {}", m.name, prefix);
-                self.add_bytecode_module(&prefix, &m);
+                self.add_bytecode_module(&logger, &prefix, &m);
 
                 Ok(())
             }
@@ -268,7 +363,7 @@ impl FileManifest {
                             logger,
                             "adding distribution module {} to {}", m.module, prefix
                         );
-                        self.add_extension_module(&prefix, &m);
+                        self.add_extension_module(&logger, &prefix, &m);
                         Ok(())
                     }
                     PythonExtensionModuleFlavor::StaticallyLinked(m) => {
@@ -303,19 +398,66 @@ impl FileManifest {
                     }
                 }
             }
+            "PythonPackageDistributionResource" => {
+                let r = resource
+                    .downcast_apply(|r: &PythonPackageDistributionResource| r.resource.clone());
+                warn!(
+                    logger,
+                    "adding distribution resource {} to {}",
+                    r.full_name(),
+                    prefix
+                );
+                r.add_to_file_manifest(&mut self.manifest, &prefix)
+                    .map_err(|e| {
+                        RuntimeError {
+                            code: "PYOXIDIZER_BUILD",
+                            message: e.to_string(),
+                            label: "add_python_resource".to_string(),
+                        }
+                        .into()
+                    })
+            }
+            "PythonPackageLicense" => {
+                // License metadata isn't installed as-is. Use
+                // add_third_party_notices() to materialize it.
+                let name =
+                    resource.downcast_apply(|l: &PythonPackageLicense| l.license.name.clone());
+                warn!(logger, "ignoring license metadata of {}", name);
+                Ok(())
+            }
             "PythonExecutable" => {
                 let context = env.get("CONTEXT").expect("CONTEXT not defined");
-                let (target, release, opt_level) =
+                let (target, release, opt_level, dry_run, license_policy) =
                     context.downcast_apply(|x: &EnvironmentContext| {
                         (
                             x.build_target_triple.clone(),
                             x.build_release,
                             x.build_opt_level.clone(),
+                            x.dry_run,
+                            x.license_policy.clone(),
                         )
                     });
 
                 let raw_exe = resource.0.borrow();
                 let exe = raw_exe.as_any().downcast_ref::<PythonExecutable>().unwrap();
+
+                license_policy
+                    .check(&logger, &exe.exe.licensed_components())
+                    .map_err(|e| RuntimeError {
+                        code: "PYOXIDIZER_BUILD",
+                        message: e.to_string(),
+                        label: "add_python_resource".to_string(),
+                    })?;
+
+                if dry_run {
+                    warn!(
+                        logger,
+                        "dry run: not building Python executable {}",
+                        exe.exe.name()
+                    );
+                    return Ok(Value::new(None));
+                }
+
                 warn!(
                     logger,
                     "adding Python executable {} to {}",
@@ -326,6 +468,7 @@ impl FileManifest {
                     &logger,
                     &prefix,
                     exe.exe.deref(),
+                    &exe.build_options,
                     &target,
                     release,
                     &opt_level,
@@ -367,6 +510,269 @@ impl FileManifest {
         Ok(Value::new(None))
     }
 
+    /// FileManifest.add_third_party_notices(prefix, exe)
+    pub fn starlark_add_third_party_notices(
+        &mut self,
+        env: &Environment,
+        prefix: &Value,
+        exe: &Value,
+    ) -> ValueResult {
+        let prefix = required_str_arg("prefix", &prefix)?;
+        required_type_arg("exe", "PythonExecutable", &exe)?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not set");
+        let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
+
+        warn!(logger, "adding third party notices to {}", prefix);
+
+        let raw_exe = exe.0.borrow();
+        let exe = raw_exe.as_any().downcast_ref::<PythonExecutable>().unwrap();
+
+        self.add_third_party_notices(&prefix, exe.exe.deref())
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "add_third_party_notices()".to_string(),
+                }
+                .into())
+            })?;
+
+        Ok(Value::new(None))
+    }
+
+    /// Resolve the name and installed path of an executable on Linux.
+    ///
+    /// `install_path` is the absolute directory the executable is installed
+    /// to on target machines.
+    fn linux_exe_path(
+        env: &Environment,
+        exe: &Value,
+        install_path: &str,
+        label: &str,
+    ) -> Result<(String, String), ValueError> {
+        required_type_arg("exe", "PythonExecutable", &exe)?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let target = context.downcast_apply(|x: &EnvironmentContext| x.build_target_triple.clone());
+
+        if !target.contains("-linux-") {
+            return Err(RuntimeError {
+                code: "PYOXIDIZER_BUILD",
+                message: format!("{} requires a Linux target triple; got {}", label, target),
+                label: label.to_string(),
+            }
+            .into());
+        }
+
+        if !install_path.starts_with('/') {
+            return Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: format!("install_path must be absolute; got {}", install_path),
+                label: label.to_string(),
+            }
+            .into());
+        }
+
+        let name = exe.downcast_apply(|exe: &PythonExecutable| exe.exe.name());
+        let path = format!(
+            "{}/{}",
+            install_path.trim_end_matches('/'),
+            exe_filename(&name, &target)
+        );
+
+        Ok((name, path))
+    }
+
+    /// Convert an optional list of strings to a vector.
+    fn string_list(value: &Value) -> Result<Vec<String>, ValueError> {
+        match value.get_type() {
+            "list" => Ok(value.into_iter()?.map(|x| x.to_string()).collect()),
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    fn add_text_file(&mut self, path: &Path, text: String, label: &str) -> ValueResult {
+        self.manifest
+            .add_file(
+                path,
+                &RawFileContent {
+                    data: text.into_bytes(),
+                    executable: false,
+                },
+            )
+            .map_err(|e| RuntimeError {
+                code: "PYOXIDIZER_BUILD",
+                message: e.to_string(),
+                label: label.to_string(),
+            })?;
+
+        Ok(Value::new(None))
+    }
+
+    /// FileManifest.add_systemd_service(exe, install_path, name=None, description=None, args=None, user=None, environment=None, working_dir=None, restart="on-failure", wanted_by="multi-user.target", prefix="")
+    #[allow(clippy::too_many_arguments)]
+    pub fn starlark_add_systemd_service(
+        &mut self,
+        env: &Environment,
+        exe: &Value,
+        install_path: &Value,
+        name: &Value,
+        description: &Value,
+        args: &Value,
+        user: &Value,
+        environment: &Value,
+        working_dir: &Value,
+        restart: &Value,
+        wanted_by: &Value,
+        prefix: &Value,
+    ) -> ValueResult {
+        let install_path = required_str_arg("install_path", &install_path)?;
+        let name = optional_str_arg("name", &name)?;
+        let description = optional_str_arg("description", &description)?;
+        optional_list_arg("args", "string", &args)?;
+        let user = optional_str_arg("user", &user)?;
+        optional_dict_arg("environment", "string", "string", &environment)?;
+        let working_dir = optional_str_arg("working_dir", &working_dir)?;
+        let restart = required_str_arg("restart", &restart)?;
+        let wanted_by = required_str_arg("wanted_by", &wanted_by)?;
+        let prefix = required_str_arg("prefix", &prefix)?;
+
+        let (exe_name, exe_path) =
+            Self::linux_exe_path(env, exe, &install_path, "add_systemd_service()")?;
+        let name = name.unwrap_or_else(|| exe_name.clone());
+
+        if !is_valid_unit_name(&name) {
+            return Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: format!("{} is not a valid systemd unit name", name),
+                label: "add_systemd_service()".to_string(),
+            }
+            .into());
+        }
+
+        let mut exec_start = vec![exe_path];
+        exec_start.extend(Self::string_list(&args)?);
+
+        let mut env_vars = BTreeMap::new();
+        if environment.get_type() == "dict" {
+            for key in environment.into_iter()? {
+                let value = environment.at(key.clone())?.to_string();
+                env_vars.insert(key.to_string(), value);
+            }
+        }
+
+        let service = SystemdService {
+            description: description.unwrap_or_else(|| exe_name.clone()),
+            name,
+            exec_start,
+            user,
+            environment: env_vars,
+            working_directory: working_dir,
+            restart,
+            wanted_by,
+        };
+
+        let context = env.get("CONTEXT").expect("CONTEXT not set");
+        let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
+
+        let path = Path::new(&prefix)
+            .join(SYSTEMD_UNIT_DIR)
+            .join(service.filename());
+        warn!(logger, "adding systemd service {}", path.display());
+
+        self.add_text_file(&path, service.render(), "add_systemd_service()")
+    }
+
+    /// FileManifest.add_desktop_entry(exe, install_path, name=None, comment=None, args=None, icon=None, categories=None, terminal=False, prefix="")
+    #[allow(clippy::too_many_arguments)]
+    pub fn starlark_add_desktop_entry(
+        &mut self,
+        env: &Environment,
+        exe: &Value,
+        install_path: &Value,
+        name: &Value,
+        comment: &Value,
+        args: &Value,
+        icon: &Value,
+        categories: &Value,
+        terminal: &Value,
+        prefix: &Value,
+    ) -> ValueResult {
+        let install_path = required_str_arg("install_path", &install_path)?;
+        let name = optional_str_arg("name", &name)?;
+        let comment = optional_str_arg("comment", &comment)?;
+        optional_list_arg("args", "string", &args)?;
+        let icon = optional_str_arg("icon", &icon)?;
+        optional_list_arg("categories", "string", &categories)?;
+        let terminal = required_bool_arg("terminal", &terminal)?;
+        let prefix = required_str_arg("prefix", &prefix)?;
+
+        let (exe_name, exe_path) =
+            Self::linux_exe_path(env, exe, &install_path, "add_desktop_entry()")?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not set");
+        let (logger, cwd) =
+            context.downcast_apply(|x: &EnvironmentContext| (x.logger.clone(), x.cwd.clone()));
+
+        // Icons are installed to the icon theme under the name of the
+        // executable, which desktop entries refer to.
+        let icon = if let Some(icon) = icon {
+            let icon_path = cwd.join(&icon);
+            let data = std::fs::read(&icon_path).map_err(|e| RuntimeError {
+                code: "PYOXIDIZER_BUILD",
+                message: format!("reading {}: {}", icon_path.display(), e),
+                label: "add_desktop_entry()".to_string(),
+            })?;
+
+            let path = icon_install_path(&exe_name, &icon, &data).map_err(|e| RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: e.to_string(),
+                label: "add_desktop_entry()".to_string(),
+            })?;
+            let path = Path::new(&prefix).join(path);
+            warn!(logger, "adding icon {}", path.display());
+
+            self.manifest
+                .add_file(
+                    &path,
+                    &RawFileContent {
+                        data,
+                        executable: false,
+                    },
+                )
+                .map_err(|e| RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "add_desktop_entry()".to_string(),
+                })?;
+
+            Some(exe_name.clone())
+        } else {
+            None
+        };
+
+        let mut exec = vec![exe_path];
+        exec.extend(Self::string_list(&args)?);
+
+        let entry = DesktopEntry {
+            id: exe_name.clone(),
+            name: name.unwrap_or(exe_name),
+            comment,
+            exec,
+            icon,
+            categories: Self::string_list(&categories)?,
+            terminal,
+        };
+
+        let path = Path::new(&prefix)
+            .join(DESKTOP_ENTRY_DIR)
+            .join(entry.filename());
+        warn!(logger, "adding desktop entry {}", path.display());
+
+        self.add_text_file(&path, entry.render(), "add_desktop_entry()")
+    }
+
     /// FileManifest.install(path, replace=true)
     pub fn install(&self, env: &Environment, path: &Value, replace: &Value) -> ValueResult {
         let path = required_str_arg("path", &path)?;
@@ -521,6 +927,77 @@ starlark_module! { file_resource_env =>
         })
     }
 
+    #[allow(clippy::ptr_arg)]
+    FileManifest.add_third_party_notices(env env, this, prefix, exe) {
+        this.downcast_apply_mut(|manifest: &mut FileManifest| {
+            manifest.starlark_add_third_party_notices(&env, &prefix, &exe)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    FileManifest.add_systemd_service(
+        env env,
+        this,
+        exe,
+        install_path,
+        name=None,
+        description=None,
+        args=None,
+        user=None,
+        environment=None,
+        working_dir=None,
+        restart="on-failure",
+        wanted_by="multi-user.target",
+        prefix=""
+    ) {
+        this.downcast_apply_mut(|manifest: &mut FileManifest| {
+            manifest.starlark_add_systemd_service(
+                &env,
+                &exe,
+                &install_path,
+                &name,
+                &description,
+                &args,
+                &user,
+                &environment,
+                &working_dir,
+                &restart,
+                &wanted_by,
+                &prefix,
+            )
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    FileManifest.add_desktop_entry(
+        env env,
+        this,
+        exe,
+        install_path,
+        name=None,
+        comment=None,
+        args=None,
+        icon=None,
+        categories=None,
+        terminal=false,
+        prefix=""
+    ) {
+        this.downcast_apply_mut(|manifest: &mut FileManifest| {
+            manifest.starlark_add_desktop_entry(
+                &env,
+                &exe,
+                &install_path,
+                &name,
+                &comment,
+                &args,
+                &icon,
+                &categories,
+                &terminal,
+                &prefix,
+            )
+        })
+    }
+
     #[allow(clippy::ptr_arg)]
     FileManifest.install(env env, this, path, replace=true) {
         this.downcast_apply(|manifest: &FileManifest| {
@@ -656,6 +1133,98 @@ mod tests {
         starlark_eval_in_env(&mut env, "m.add_python_resource('bin', exe)").unwrap();
     }
 
+    #[test]
+    fn test_add_third_party_notices() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+        starlark_eval_in_env(&mut env, "m = FileManifest()").unwrap();
+
+        starlark_eval_in_env(&mut env, "m.add_third_party_notices('doc', exe)").unwrap();
+
+        let m = env.get("m").unwrap();
+        m.downcast_apply(|m: &FileManifest| {
+            let mut entries = m.manifest.entries();
+
+            let (p, _) = entries.next().unwrap();
+            assert_eq!(p, &PathBuf::from("doc/THIRD-PARTY-NOTICES.json"));
+
+            let (p, c) = entries.next().unwrap();
+            assert_eq!(p, &PathBuf::from("doc/THIRD-PARTY-NOTICES.txt"));
+            assert!(String::from_utf8_lossy(&c.data).contains("Python 3."));
+
+            assert!(entries.next().is_none());
+        });
+
+        assert!(starlark_eval_in_env(&mut env, "m.add_third_party_notices('doc', 'exe')").is_err());
+    }
+
+    #[test]
+    fn test_add_systemd_service_and_desktop_entry() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+        starlark_eval_in_env(&mut env, "m = FileManifest()").unwrap();
+
+        if !crate::project_building::HOST.contains("-linux-") {
+            assert!(
+                starlark_eval_in_env(&mut env, "m.add_systemd_service(exe, '/opt/testapp')")
+                    .is_err()
+            );
+            return;
+        }
+
+        let td = tempdir::TempDir::new("pyoxidizer-test").unwrap();
+        let icon_path = td.path().join("icon.svg");
+        std::fs::write(&icon_path, b"<svg/>").unwrap();
+        env.set("icon", Value::from(icon_path.display().to_string()))
+            .unwrap();
+
+        starlark_eval_in_env(
+            &mut env,
+            "m.add_systemd_service(exe, '/opt/testapp', args=['--serve'], user='testapp')",
+        )
+        .unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "m.add_desktop_entry(exe, '/opt/testapp', name='Test App', icon=icon, categories=['Utility'], prefix='usr')",
+        )
+        .unwrap();
+
+        let m = env.get("m").unwrap();
+        m.downcast_apply(|m: &FileManifest| {
+            let service = m
+                .manifest
+                .get(Path::new("lib/systemd/system/testapp.service"))
+                .unwrap();
+            let service = String::from_utf8_lossy(&service.data);
+            assert!(service.contains("ExecStart=/opt/testapp/testapp --serve\n"));
+            assert!(service.contains("User=testapp\n"));
+
+            let entry = m
+                .manifest
+                .get(Path::new("usr/share/applications/testapp.desktop"))
+                .unwrap();
+            let entry = String::from_utf8_lossy(&entry.data);
+            assert!(entry.contains("Name=Test App\n"));
+            assert!(entry.contains("Exec=/opt/testapp/testapp\n"));
+            assert!(entry.contains("Icon=testapp\n"));
+
+            assert!(m.manifest.has_path(Path::new(
+                "usr/share/icons/hicolor/scalable/apps/testapp.svg"
+            )));
+        });
+
+        let err = starlark_eval_in_env(&mut env, "m.add_systemd_service(exe, 'opt/testapp')")
+            .unwrap_err();
+        assert_eq!(
+            err.message,
+            "install_path must be absolute; got opt/testapp"
+        );
+    }
+
     #[test]
     fn test_install() {
         let mut env = starlark_env();
@@ -0,0 +1,243 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Layouts of installed applications.
+
+An `InstallLayout` resolves the `bin`, `lib`, `share`, and configuration
+directories of an application for the target platform. Installer targets
+accept a layout to install the files of a `FileManifest` to these
+directories instead of copying them to a single directory.
+*/
+
+use {
+    super::env::EnvironmentContext,
+    super::file_resource::FileManifest,
+    super::util::{optional_str_arg, optional_type_arg, required_str_arg, required_type_arg},
+    crate::app_packaging::install_layout::{InstallLayout as RawInstallLayout, InstallScope},
+    crate::app_packaging::resource::FileManifest as RawFileManifest,
+    starlark::environment::Environment,
+    starlark::values::{
+        default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult,
+        INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+    },
+    starlark::{
+        any, immutable, not_supported, starlark_fun, starlark_module, starlark_signature,
+        starlark_signature_extraction, starlark_signatures,
+    },
+    std::any::Any,
+    std::cmp::Ordering,
+    std::path::Path,
+};
+
+/// Directories an application is installed to.
+#[derive(Clone, Debug)]
+pub struct InstallLayout {
+    pub layout: RawInstallLayout,
+}
+
+impl TypedValue for InstallLayout {
+    immutable!();
+    any!();
+    not_supported!(binop, container, function, get_hash, to_int);
+
+    fn to_str(&self) -> String {
+        format!(
+            "InstallLayout<name={}, scope={}>",
+            self.layout.name,
+            self.layout.scope.as_str()
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "InstallLayout"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+
+    fn get_attr(&self, attribute: &str) -> ValueResult {
+        let path = |p: &Path| Value::new(p.display().to_string());
+
+        let v = match attribute {
+            "name" => Value::new(self.layout.name.clone()),
+            "scope" => Value::new(self.layout.scope.as_str().to_string()),
+            "bin_dir" => path(&self.layout.bin_dir),
+            "lib_dir" => path(&self.layout.lib_dir),
+            "share_dir" => path(&self.layout.share_dir),
+            "config_dir" => path(&self.layout.config_dir),
+            attr => {
+                return Err(ValueError::OperationNotSupported {
+                    op: format!(".{}", attr),
+                    left: "InstallLayout".to_string(),
+                    right: None,
+                })
+            }
+        };
+
+        Ok(v)
+    }
+
+    fn has_attr(&self, attribute: &str) -> Result<bool, ValueError> {
+        Ok(match attribute {
+            "name" => true,
+            "scope" => true,
+            "bin_dir" => true,
+            "lib_dir" => true,
+            "share_dir" => true,
+            "config_dir" => true,
+            _ => false,
+        })
+    }
+}
+
+/// Obtain the layout of an optional `layout` argument.
+pub fn optional_layout_arg(value: &Value) -> Result<Option<RawInstallLayout>, ValueError> {
+    optional_type_arg("layout", "InstallLayout", &value)?;
+
+    Ok(match value.get_type() {
+        "InstallLayout" => Some(value.downcast_apply(|x: &InstallLayout| x.layout.clone())),
+        _ => None,
+    })
+}
+
+/// Install application files with an optional layout.
+///
+/// Files are returned as-is without a layout.
+pub fn install_files(
+    layout: &Option<RawInstallLayout>,
+    files: RawFileManifest,
+    label: &str,
+) -> Result<RawFileManifest, ValueError> {
+    match layout {
+        Some(layout) => layout.install(&files).map_err(|e| {
+            RuntimeError {
+                code: "PYOXIDIZER_BUILD",
+                message: e.to_string(),
+                label: label.to_string(),
+            }
+            .into()
+        }),
+        None => Ok(files),
+    }
+}
+
+// Starlark functions.
+impl InstallLayout {
+    /// InstallLayout(name, scope="system", prefix=None)
+    fn new_from_args(
+        env: &Environment,
+        name: &Value,
+        scope: &Value,
+        prefix: &Value,
+    ) -> ValueResult {
+        let name = required_str_arg("name", &name)?;
+        let scope = required_str_arg("scope", &scope)?;
+        let prefix = optional_str_arg("prefix", &prefix)?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let target = context.downcast_apply(|x: &EnvironmentContext| x.build_target_triple.clone());
+
+        let layout = InstallScope::parse(&scope)
+            .and_then(|scope| {
+                RawInstallLayout::for_target(
+                    &name,
+                    &target,
+                    scope,
+                    prefix.as_ref().map(|p| p.as_str()),
+                )
+            })
+            .map_err(|e| RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: e.to_string(),
+                label: "InstallLayout()".to_string(),
+            })?;
+
+        Ok(Value::new(InstallLayout { layout }))
+    }
+
+    /// InstallLayout.install(files)
+    pub fn install(&self, files: &Value) -> ValueResult {
+        required_type_arg("files", "FileManifest", &files)?;
+
+        let files = files.downcast_apply(|m: &FileManifest| m.manifest.clone());
+        let manifest = install_files(&Some(self.layout.clone()), files, "install()")?;
+
+        Ok(Value::new(FileManifest { manifest }))
+    }
+}
+
+starlark_module! { install_layout_env =>
+    #[allow(non_snake_case, clippy::ptr_arg)]
+    InstallLayout(env env, name, scope="system", prefix=None) {
+        InstallLayout::new_from_args(&env, &name, &scope, &prefix)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    InstallLayout.install(this, files) {
+        this.downcast_apply(|x: &InstallLayout| x.install(&files))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::super::testutil::*, super::*, crate::app_packaging::resource::FileContent};
+
+    #[test]
+    fn test_install_layout() {
+        let mut manifest = RawFileManifest::default();
+        manifest
+            .add_file(
+                Path::new("myapp"),
+                &FileContent {
+                    data: b"exe".to_vec(),
+                    executable: true,
+                },
+            )
+            .unwrap();
+
+        let mut env = starlark_env();
+        env.get("CONTEXT")
+            .unwrap()
+            .downcast_apply_mut(|x: &mut EnvironmentContext| {
+                x.build_target_triple = "x86_64-unknown-linux-gnu".to_string();
+            });
+        env.set("m", Value::new(FileManifest { manifest })).unwrap();
+
+        let layout = starlark_eval_in_env(&mut env, "InstallLayout('myapp')").unwrap();
+        assert_eq!(layout.get_type(), "InstallLayout");
+        assert_eq!(
+            starlark_eval_in_env(&mut env, "InstallLayout('myapp').lib_dir")
+                .unwrap()
+                .to_string(),
+            "usr/lib/myapp"
+        );
+        assert_eq!(
+            starlark_eval_in_env(&mut env, "InstallLayout('myapp', scope='user').config_dir")
+                .unwrap()
+                .to_string(),
+            ".config/myapp"
+        );
+
+        let installed =
+            starlark_eval_in_env(&mut env, "InstallLayout('myapp', prefix='').install(m)").unwrap();
+        installed.downcast_apply(|x: &FileManifest| {
+            assert!(x.manifest.has_path(Path::new("bin/myapp")));
+            assert!(x.manifest.has_path(Path::new("lib/myapp/myapp")));
+        });
+
+        assert!(
+            starlark_eval_in_env(&mut env, "InstallLayout('myapp', scope='everyone')").is_err()
+        );
+    }
+}
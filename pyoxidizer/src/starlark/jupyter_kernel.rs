@@ -0,0 +1,197 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Jupyter kernels backed by Python executables.
+
+A kernelspec is a directory holding a `kernel.json` describing how Jupyter
+launches the kernel. Building a `JupyterKernel` builds its executable into
+such a directory, which can be installed with `jupyter kernelspec install`.
+*/
+
+use {
+    super::python_executable::PythonExecutable,
+    super::target::{BuildContext, BuildTarget, ResolvedTarget, RunMode},
+    crate::py_packaging::config::RunMode as PythonRunMode,
+    anyhow::{anyhow, Context, Result},
+    serde::Serialize,
+    slog::warn,
+    starlark::values::{default_compare, TypedValue, ValueError},
+    starlark::{any, immutable, not_supported},
+    std::any::Any,
+    std::cmp::Ordering,
+};
+
+/// Filename of the kernel definition in a kernelspec directory.
+pub const KERNEL_JSON_FILENAME: &str = "kernel.json";
+
+/// Whether a string is a valid Jupyter kernel name.
+pub fn is_valid_kernel_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+}
+
+/// The content of a `kernel.json` file.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct KernelSpec {
+    /// Command line launching the kernel.
+    pub argv: Vec<String>,
+
+    /// Name of the kernel shown in user interfaces.
+    pub display_name: String,
+
+    /// Language of the kernel.
+    pub language: String,
+}
+
+impl KernelSpec {
+    /// Describe a kernel launching an executable in the kernelspec directory.
+    ///
+    /// Executables behaving like `python` are told to run `ipykernel_launcher`.
+    /// Others are expected to start a kernel themselves, e.g. by running the
+    /// `ipykernel_launcher` module, and receive the connection file arguments.
+    pub fn new(exe_name: &str, display_name: &str, python_cli: bool) -> Self {
+        let mut argv = vec![format!("{{resource_dir}}/{}", exe_name)];
+
+        if python_cli {
+            argv.push("-m".to_string());
+            argv.push("ipykernel_launcher".to_string());
+        }
+
+        argv.push("-f".to_string());
+        argv.push("{connection_file}".to_string());
+
+        Self {
+            argv,
+            display_name: display_name.to_string(),
+            language: "python".to_string(),
+        }
+    }
+}
+
+/// A Python executable packaged as a Jupyter kernel.
+pub struct JupyterKernel {
+    /// Name of the kernel and its kernelspec directory.
+    pub name: String,
+
+    /// Name of the kernel shown in user interfaces.
+    pub display_name: String,
+
+    /// The executable running the kernel.
+    pub exe: PythonExecutable,
+}
+
+impl TypedValue for JupyterKernel {
+    immutable!();
+    any!();
+    not_supported!(binop, container, function, get_hash, to_int);
+
+    fn to_str(&self) -> String {
+        format!("JupyterKernel<name={}>", self.name)
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "JupyterKernel"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+impl BuildTarget for JupyterKernel {
+    fn build(&mut self, context: &BuildContext) -> Result<ResolvedTarget> {
+        let kernel_path = context.output_path.join(&self.name);
+        std::fs::create_dir_all(&kernel_path)
+            .context(format!("creating {}", kernel_path.display()))?;
+
+        let exe_context = BuildContext {
+            logger: context.logger.clone(),
+            host_triple: context.host_triple.clone(),
+            target_triple: context.target_triple.clone(),
+            release: context.release,
+            opt_level: context.opt_level.clone(),
+            output_path: kernel_path.clone(),
+            license_policy: context.license_policy.clone(),
+        };
+
+        let exe_path = match self.exe.build(&exe_context)?.run_mode {
+            RunMode::Path { path } => path,
+            RunMode::None => return Err(anyhow!("executable was not built")),
+        };
+        let exe_name = exe_path
+            .file_name()
+            .ok_or_else(|| anyhow!("executable has no filename"))?
+            .to_string_lossy();
+
+        let python_cli = match self.exe.exe.config().run_mode {
+            PythonRunMode::PythonCli => true,
+            _ => false,
+        };
+
+        let spec = KernelSpec::new(&exe_name, &self.display_name, python_cli);
+        let spec_path = kernel_path.join(KERNEL_JSON_FILENAME);
+        std::fs::write(&spec_path, serde_json::to_vec_pretty(&spec)?)
+            .context(format!("writing {}", spec_path.display()))?;
+
+        warn!(
+            &context.logger,
+            "wrote Jupyter kernel to {}; install it with `jupyter kernelspec install {}`",
+            kernel_path.display(),
+            kernel_path.display()
+        );
+
+        Ok(ResolvedTarget {
+            run_mode: RunMode::None,
+            output_path: kernel_path.clone(),
+            artifacts: vec![kernel_path],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kernel_spec() {
+        let spec = KernelSpec::new("kernel", "My Kernel", true);
+        assert_eq!(
+            spec.argv,
+            vec![
+                "{resource_dir}/kernel",
+                "-m",
+                "ipykernel_launcher",
+                "-f",
+                "{connection_file}"
+            ]
+        );
+
+        let spec = KernelSpec::new("kernel.exe", "My Kernel", false);
+        assert_eq!(
+            spec.argv,
+            vec!["{resource_dir}/kernel.exe", "-f", "{connection_file}"]
+        );
+        assert_eq!(spec.display_name, "My Kernel");
+        assert_eq!(spec.language, "python");
+    }
+
+    #[test]
+    fn test_is_valid_kernel_name() {
+        assert!(is_valid_kernel_name("myteam-py3.8_stack"));
+        assert!(!is_valid_kernel_name(""));
+        assert!(!is_valid_kernel_name("my kernel"));
+        assert!(!is_valid_kernel_name("kernels/mine"));
+    }
+}
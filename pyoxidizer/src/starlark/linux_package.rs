@@ -0,0 +1,540 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Projects packaging installed files for Linux app stores.
+
+Building a `SnapcraftPackage` or `FlatpakPackage` writes a directory holding
+the files of a `FileManifest` and a snapcraft or `flatpak-builder` manifest
+wrapping them, ready to be built by those tools.
+*/
+
+use {
+    super::env::EnvironmentContext,
+    super::file_resource::FileManifest,
+    super::install_layout::{install_files, optional_layout_arg},
+    super::target::{BuildContext, BuildTarget, ResolvedTarget, RunMode},
+    super::util::{optional_list_arg, optional_str_arg, required_str_arg, required_type_arg},
+    crate::app_packaging::flatpak::{FlatpakManifest, FLATPAK_SOURCE_DIR},
+    crate::app_packaging::install_layout::InstallLayout,
+    crate::app_packaging::resource::FileManifest as RawFileManifest,
+    crate::app_packaging::snapcraft::{Snapcraft, SNAPCRAFT_YAML_FILENAME, SNAP_SOURCE_DIR},
+    anyhow::{Context, Result},
+    slog::warn,
+    starlark::environment::Environment,
+    starlark::values::{
+        default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult,
+        INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+    },
+    starlark::{
+        any, immutable, not_supported, starlark_fun, starlark_module, starlark_signature,
+        starlark_signature_extraction, starlark_signatures,
+    },
+    std::any::Any,
+    std::cmp::Ordering,
+    std::path::Path,
+};
+
+/// Obtain the path of the only executable in a manifest.
+///
+/// With a layout, the path is the one the executable is installed to.
+fn single_executable(
+    files: &RawFileManifest,
+    layout: &Option<InstallLayout>,
+    label: &str,
+) -> Result<String, ValueError> {
+    let exes = files
+        .entries()
+        .filter(|(_, c)| c.executable)
+        .map(|(p, _)| p)
+        .collect::<Vec<_>>();
+
+    if exes.len() == 1 {
+        let exe = match layout {
+            Some(layout) => layout.installed_exe_path(exes[0]),
+            None => exes[0].clone(),
+        };
+
+        Ok(exe
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+            .join("/"))
+    } else {
+        Err(RuntimeError {
+            code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            message: format!(
+                "command is required when files hold {} executables",
+                exes.len()
+            ),
+            label: label.to_string(),
+        }
+        .into())
+    }
+}
+
+/// Write a manifest and the files it packages to a project directory.
+///
+/// Files are written to `source_dir` in the project, which is replaced.
+fn write_project(
+    logger: &slog::Logger,
+    project_path: &Path,
+    files: &RawFileManifest,
+    source_dir: &str,
+    manifest_filename: &str,
+    manifest: &str,
+) -> Result<()> {
+    warn!(
+        logger,
+        "writing {} project to {}",
+        manifest_filename,
+        project_path.display()
+    );
+
+    files.replace_path(&project_path.join(source_dir))?;
+
+    let manifest_path = project_path.join(manifest_filename);
+    std::fs::write(&manifest_path, manifest)
+        .with_context(|| format!("writing {}", manifest_path.display()))?;
+
+    Ok(())
+}
+
+fn check_linux_target(target_triple: &str, label: &str) -> Result<(), ValueError> {
+    if target_triple.contains("-linux-") {
+        Ok(())
+    } else {
+        Err(RuntimeError {
+            code: "PYOXIDIZER_BUILD",
+            message: format!(
+                "{} requires a Linux target triple; got {}",
+                label, target_triple
+            ),
+            label: label.to_string(),
+        }
+        .into())
+    }
+}
+
+fn string_list(value: &Value) -> Result<Vec<String>, ValueError> {
+    match value.get_type() {
+        "list" => Ok(value.into_iter()?.map(|x| x.to_string()).collect()),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// A snapcraft project packaging installed files as a snap.
+pub struct SnapcraftPackage {
+    pub snap: Snapcraft,
+
+    /// Files of the snap.
+    pub files: RawFileManifest,
+}
+
+impl TypedValue for SnapcraftPackage {
+    immutable!();
+    any!();
+    not_supported!(binop, container, function, get_hash, to_int);
+
+    fn to_str(&self) -> String {
+        format!("SnapcraftPackage<name={}>", self.snap.name)
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "SnapcraftPackage"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+impl BuildTarget for SnapcraftPackage {
+    fn build(&mut self, context: &BuildContext) -> Result<ResolvedTarget> {
+        let project_path = context.output_path.join(&self.snap.name);
+
+        write_project(
+            &context.logger,
+            &project_path,
+            &self.files,
+            SNAP_SOURCE_DIR,
+            SNAPCRAFT_YAML_FILENAME,
+            &self.snap.render(),
+        )?;
+
+        warn!(
+            &context.logger,
+            "build the snap by running `snapcraft` in {}",
+            project_path.display()
+        );
+
+        Ok(ResolvedTarget {
+            run_mode: RunMode::None,
+            output_path: project_path.clone(),
+            artifacts: vec![project_path],
+        })
+    }
+}
+
+/// A `flatpak-builder` project packaging installed files.
+pub struct FlatpakPackage {
+    pub manifest: FlatpakManifest,
+
+    /// Files installed to `/app`.
+    pub files: RawFileManifest,
+}
+
+impl TypedValue for FlatpakPackage {
+    immutable!();
+    any!();
+    not_supported!(binop, container, function, get_hash, to_int);
+
+    fn to_str(&self) -> String {
+        format!("FlatpakPackage<app_id={}>", self.manifest.app_id)
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "FlatpakPackage"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+impl BuildTarget for FlatpakPackage {
+    fn build(&mut self, context: &BuildContext) -> Result<ResolvedTarget> {
+        let project_path = context.output_path.join(&self.manifest.app_id);
+        let filename = self.manifest.filename();
+
+        write_project(
+            &context.logger,
+            &project_path,
+            &self.files,
+            FLATPAK_SOURCE_DIR,
+            &filename,
+            &self.manifest.render()?,
+        )?;
+
+        warn!(
+            &context.logger,
+            "build the application by running `flatpak-builder build {}` in {}",
+            filename,
+            project_path.display()
+        );
+
+        Ok(ResolvedTarget {
+            run_mode: RunMode::None,
+            output_path: project_path.clone(),
+            artifacts: vec![project_path],
+        })
+    }
+}
+
+// Starlark functions.
+impl SnapcraftPackage {
+    /// SnapcraftPackage(name, files, version, summary, description=None, command=None, plugs=None, confinement="strict", grade="stable", base="core20", layout=None)
+    #[allow(clippy::too_many_arguments)]
+    fn new_from_args(
+        env: &Environment,
+        name: &Value,
+        files: &Value,
+        version: &Value,
+        summary: &Value,
+        description: &Value,
+        command: &Value,
+        plugs: &Value,
+        confinement: &Value,
+        grade: &Value,
+        base: &Value,
+        layout: &Value,
+    ) -> ValueResult {
+        let name = required_str_arg("name", &name)?;
+        required_type_arg("files", "FileManifest", &files)?;
+        let version = required_str_arg("version", &version)?;
+        let summary = required_str_arg("summary", &summary)?;
+        let description = optional_str_arg("description", &description)?;
+        let command = optional_str_arg("command", &command)?;
+        optional_list_arg("plugs", "string", &plugs)?;
+        let confinement = required_str_arg("confinement", &confinement)?;
+        let grade = required_str_arg("grade", &grade)?;
+        let base = required_str_arg("base", &base)?;
+        let layout = optional_layout_arg(&layout)?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let target = context.downcast_apply(|x: &EnvironmentContext| x.build_target_triple.clone());
+        check_linux_target(&target, "SnapcraftPackage()")?;
+
+        let files = files.downcast_apply(|m: &FileManifest| m.manifest.clone());
+        let command = match command {
+            Some(command) => command,
+            None => single_executable(&files, &layout, "SnapcraftPackage()")?,
+        };
+        let files = install_files(&layout, files, "SnapcraftPackage()")?;
+
+        let snap = Snapcraft {
+            name,
+            version,
+            description: description.unwrap_or_else(|| summary.clone()),
+            summary,
+            command,
+            plugs: string_list(&plugs)?,
+            confinement,
+            grade,
+            base,
+        };
+
+        snap.validate().map_err(|e| RuntimeError {
+            code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            message: e.to_string(),
+            label: "SnapcraftPackage()".to_string(),
+        })?;
+
+        Ok(Value::new(SnapcraftPackage { snap, files }))
+    }
+}
+
+impl FlatpakPackage {
+    /// FlatpakPackage(app_id, files, command=None, finish_args=None, runtime="org.freedesktop.Platform", runtime_version="20.08", sdk="org.freedesktop.Sdk", layout=None)
+    #[allow(clippy::too_many_arguments)]
+    fn new_from_args(
+        env: &Environment,
+        app_id: &Value,
+        files: &Value,
+        command: &Value,
+        finish_args: &Value,
+        runtime: &Value,
+        runtime_version: &Value,
+        sdk: &Value,
+        layout: &Value,
+    ) -> ValueResult {
+        let app_id = required_str_arg("app_id", &app_id)?;
+        required_type_arg("files", "FileManifest", &files)?;
+        let command = optional_str_arg("command", &command)?;
+        optional_list_arg("finish_args", "string", &finish_args)?;
+        let runtime = required_str_arg("runtime", &runtime)?;
+        let runtime_version = required_str_arg("runtime_version", &runtime_version)?;
+        let sdk = required_str_arg("sdk", &sdk)?;
+        let layout = optional_layout_arg(&layout)?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let target = context.downcast_apply(|x: &EnvironmentContext| x.build_target_triple.clone());
+        check_linux_target(&target, "FlatpakPackage()")?;
+
+        let files = files.downcast_apply(|m: &FileManifest| m.manifest.clone());
+        // Files are installed to /app in the sandbox.
+        let command = match command {
+            Some(command) => command,
+            None => format!(
+                "/app/{}",
+                single_executable(&files, &layout, "FlatpakPackage()")?
+            ),
+        };
+        let files = install_files(&layout, files, "FlatpakPackage()")?;
+
+        let manifest = FlatpakManifest::new(
+            &app_id,
+            &runtime,
+            &runtime_version,
+            &sdk,
+            &command,
+            string_list(&finish_args)?,
+        )
+        .map_err(|e| RuntimeError {
+            code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            message: e.to_string(),
+            label: "FlatpakPackage()".to_string(),
+        })?;
+
+        Ok(Value::new(FlatpakPackage { manifest, files }))
+    }
+}
+
+starlark_module! { linux_package_env =>
+    #[allow(non_snake_case, clippy::ptr_arg)]
+    SnapcraftPackage(
+        env env,
+        name,
+        files,
+        version,
+        summary,
+        description=None,
+        command=None,
+        plugs=None,
+        confinement="strict",
+        grade="stable",
+        base="core20",
+        layout=None
+    ) {
+        SnapcraftPackage::new_from_args(
+            &env,
+            &name,
+            &files,
+            &version,
+            &summary,
+            &description,
+            &command,
+            &plugs,
+            &confinement,
+            &grade,
+            &base,
+            &layout,
+        )
+    }
+
+    #[allow(non_snake_case, clippy::ptr_arg)]
+    FlatpakPackage(
+        env env,
+        app_id,
+        files,
+        command=None,
+        finish_args=None,
+        runtime="org.freedesktop.Platform",
+        runtime_version="20.08",
+        sdk="org.freedesktop.Sdk",
+        layout=None
+    ) {
+        FlatpakPackage::new_from_args(
+            &env,
+            &app_id,
+            &files,
+            &command,
+            &finish_args,
+            &runtime,
+            &runtime_version,
+            &sdk,
+            &layout,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::super::testutil::*, super::*, crate::app_packaging::resource::FileContent,
+        std::path::PathBuf,
+    };
+
+    fn env_with_files() -> Environment {
+        let mut manifest = RawFileManifest::default();
+        manifest
+            .add_file(
+                &PathBuf::from("bin/myapp"),
+                &FileContent {
+                    data: b"exe".to_vec(),
+                    executable: true,
+                },
+            )
+            .unwrap();
+
+        let mut env = starlark_env();
+        env.set("m", Value::new(FileManifest { manifest })).unwrap();
+
+        env
+    }
+
+    #[test]
+    fn test_snapcraft_package() -> Result<()> {
+        let mut env = env_with_files();
+
+        if !crate::project_building::HOST.contains("-linux-") {
+            assert!(starlark_eval_in_env(
+                &mut env,
+                "SnapcraftPackage('myapp', m, '1.0', 'My app')"
+            )
+            .is_err());
+            return Ok(());
+        }
+
+        let snap = starlark_eval_in_env(
+            &mut env,
+            "SnapcraftPackage('myapp', m, '1.0', 'My app', plugs=['network'])",
+        )
+        .unwrap();
+        assert_eq!(snap.get_type(), "SnapcraftPackage");
+
+        let td = tempdir::TempDir::new("pyoxidizer-test")?;
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+
+        snap.downcast_apply_mut(|x: &mut SnapcraftPackage| -> Result<()> {
+            assert_eq!(x.snap.command, "bin/myapp");
+            assert_eq!(x.snap.description, "My app");
+            assert_eq!(x.snap.plugs, vec!["network"]);
+
+            let resolved = x.build(&BuildContext {
+                logger: logger.clone(),
+                host_triple: crate::project_building::HOST.to_string(),
+                target_triple: crate::project_building::HOST.to_string(),
+                release: false,
+                opt_level: "0".to_string(),
+                output_path: td.path().to_path_buf(),
+                license_policy: Default::default(),
+            })?;
+
+            assert_eq!(resolved.output_path, td.path().join("myapp"));
+            assert!(resolved.output_path.join("snapcraft.yaml").is_file());
+            assert!(resolved.output_path.join("app/bin/myapp").is_file());
+
+            Ok(())
+        })?;
+
+        let err = starlark_eval_in_env(&mut env, "SnapcraftPackage('My App', m, '1.0', 'My app')")
+            .unwrap_err();
+        assert!(err.message.starts_with("invalid snap name My App"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_flatpak_package() {
+        let mut env = env_with_files();
+
+        if !crate::project_building::HOST.contains("-linux-") {
+            return;
+        }
+
+        let package = starlark_eval_in_env(
+            &mut env,
+            "FlatpakPackage('org.example.MyApp', m, finish_args=['--share=network'])",
+        )
+        .unwrap();
+        assert_eq!(package.get_type(), "FlatpakPackage");
+
+        package.downcast_apply(|x: &FlatpakPackage| {
+            assert_eq!(x.manifest.command, "/app/bin/myapp");
+            assert_eq!(x.manifest.finish_args, vec!["--share=network"]);
+            assert_eq!(x.manifest.runtime, "org.freedesktop.Platform");
+        });
+
+        let package = starlark_eval_in_env(
+            &mut env,
+            "FlatpakPackage('org.example.MyApp', m, layout=InstallLayout('org.example.MyApp', prefix=''))",
+        )
+        .unwrap();
+        package.downcast_apply(|x: &FlatpakPackage| {
+            assert_eq!(x.manifest.command, "/app/lib/org.example.MyApp/bin/myapp");
+            assert!(x
+                .files
+                .has_path(Path::new("lib/org.example.MyApp/bin/myapp")));
+        });
+
+        assert!(starlark_eval_in_env(&mut env, "FlatpakPackage('myapp', m)").is_err());
+    }
+}
@@ -8,14 +8,25 @@ The `starlark` module and related sub-modules define the
 define Oxidized Python binaries.
 */
 
+pub mod build_manifest;
+pub mod built_executable;
+pub mod config_version;
+pub mod diagnostics;
 pub mod env;
 pub mod eval;
 pub mod file_resource;
+pub mod install_layout;
+pub mod jupyter_kernel;
+pub mod linux_package;
+pub mod nsis_installer;
+pub mod oci_image;
 pub mod python_distribution;
 pub mod python_embedded_resources;
 pub mod python_executable;
 pub mod python_interpreter_config;
 pub mod python_resource;
+pub mod python_zipapp;
+pub mod stdlib;
 pub mod target;
 #[cfg(test)]
 mod testutil;
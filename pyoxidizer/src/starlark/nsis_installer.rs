@@ -0,0 +1,394 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Windows installers built with NSIS.
+
+Building an `NsisInstallerBuilder` writes the files of a `FileManifest`, an
+include file installing them, and an installer script to a directory, then
+runs `makensis` on the generated script or on a user-provided script.
+*/
+
+use {
+    super::env::EnvironmentContext,
+    super::file_resource::FileManifest,
+    super::install_layout::{install_files, optional_layout_arg},
+    super::target::{BuildContext, BuildTarget, ResolvedTarget, RunMode},
+    super::util::{optional_dict_arg, optional_str_arg, required_str_arg, required_type_arg},
+    crate::app_packaging::install_layout::InstallScope,
+    crate::app_packaging::nsis::{
+        is_valid_define_name, makensis_args, program_files_variable, NsisInstaller,
+        NSIS_INCLUDE_FILENAME, NSIS_SCRIPT_FILENAME,
+    },
+    crate::app_packaging::resource::FileManifest as RawFileManifest,
+    crate::py_packaging::packaging_tool::run_logged_command,
+    anyhow::{Context, Result},
+    slog::warn,
+    starlark::environment::Environment,
+    starlark::values::{
+        default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult,
+        INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+    },
+    starlark::{
+        any, immutable, not_supported, starlark_fun, starlark_module, starlark_signature,
+        starlark_signature_extraction, starlark_signatures,
+    },
+    std::any::Any,
+    std::cmp::Ordering,
+    std::collections::BTreeMap,
+    std::path::{Path, PathBuf},
+};
+
+/// Directory of the project holding the installed files.
+const NSIS_FILES_DIR: &str = "files";
+
+/// Builds a Windows installer with `makensis`.
+pub struct NsisInstallerBuilder {
+    pub name: String,
+
+    pub installer: NsisInstaller,
+
+    /// Files to install.
+    pub files: RawFileManifest,
+
+    /// Variable holding the program files directory of the target.
+    pub program_files: String,
+
+    /// Installer script run instead of the generated script.
+    pub script: Option<PathBuf>,
+
+    /// Path or name of the `makensis` executable.
+    pub makensis: String,
+}
+
+impl TypedValue for NsisInstallerBuilder {
+    immutable!();
+    any!();
+    not_supported!(binop, container, function, get_hash, to_int);
+
+    fn to_str(&self) -> String {
+        format!("NsisInstallerBuilder<name={}>", self.name)
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "NsisInstallerBuilder"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+impl NsisInstallerBuilder {
+    /// Filename of the built installer.
+    pub fn installer_filename(&self) -> String {
+        format!("{}-{}-setup.exe", self.name, self.installer.product_version)
+    }
+
+    /// Write the installed files, include file, and generated script.
+    ///
+    /// `project_path` is replaced. Returns the path of the script to build.
+    pub fn write_project(&self, logger: &slog::Logger, project_path: &Path) -> Result<PathBuf> {
+        warn!(logger, "writing NSIS project to {}", project_path.display());
+
+        if project_path.exists() {
+            std::fs::remove_dir_all(project_path)?;
+        }
+        std::fs::create_dir_all(project_path)?;
+
+        let files_path = project_path.join(NSIS_FILES_DIR);
+        self.files.write_to_path(&files_path)?;
+
+        let include_path = project_path.join(NSIS_INCLUDE_FILENAME);
+        std::fs::write(
+            &include_path,
+            self.installer.render_include(&self.files, &files_path),
+        )
+        .with_context(|| format!("writing {}", include_path.display()))?;
+
+        let script_path = project_path.join(NSIS_SCRIPT_FILENAME);
+        std::fs::write(
+            &script_path,
+            self.installer.render_script(&self.program_files),
+        )
+        .with_context(|| format!("writing {}", script_path.display()))?;
+
+        Ok(match &self.script {
+            Some(script) => script.clone(),
+            None => script_path,
+        })
+    }
+}
+
+impl BuildTarget for NsisInstallerBuilder {
+    fn build(&mut self, context: &BuildContext) -> Result<ResolvedTarget> {
+        let project_path = context.output_path.join(&self.name);
+        let script_path = self.write_project(&context.logger, &project_path)?;
+
+        let installer_path = context.output_path.join(self.installer_filename());
+
+        warn!(
+            &context.logger,
+            "building {} from {}",
+            installer_path.display(),
+            script_path.display()
+        );
+        run_logged_command(
+            &context.logger,
+            std::process::Command::new(&self.makensis).args(makensis_args(
+                &script_path,
+                &project_path,
+                &installer_path,
+            )),
+        )
+        .with_context(|| format!("running {}", self.makensis))?;
+
+        Ok(ResolvedTarget {
+            run_mode: RunMode::None,
+            output_path: installer_path.clone(),
+            artifacts: vec![installer_path],
+        })
+    }
+}
+
+// Starlark functions.
+impl NsisInstallerBuilder {
+    /// NsisInstallerBuilder(name, files, version, product_name=None, publisher=None, exe=None, script=None, defines=None, makensis="makensis", layout=None)
+    #[allow(clippy::too_many_arguments)]
+    fn new_from_args(
+        env: &Environment,
+        name: &Value,
+        files: &Value,
+        version: &Value,
+        product_name: &Value,
+        publisher: &Value,
+        exe: &Value,
+        script: &Value,
+        defines: &Value,
+        makensis: &Value,
+        layout: &Value,
+    ) -> ValueResult {
+        let name = required_str_arg("name", &name)?;
+        required_type_arg("files", "FileManifest", &files)?;
+        let version = required_str_arg("version", &version)?;
+        let product_name = optional_str_arg("product_name", &product_name)?;
+        let publisher = optional_str_arg("publisher", &publisher)?;
+        let exe = optional_str_arg("exe", &exe)?;
+        let script = optional_str_arg("script", &script)?;
+        optional_dict_arg("defines", "string", "string", &defines)?;
+        let makensis = required_str_arg("makensis", &makensis)?;
+        let layout = optional_layout_arg(&layout)?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let (target, cwd) = context.downcast_apply(|x: &EnvironmentContext| {
+            (x.build_target_triple.clone(), x.cwd.clone())
+        });
+
+        let program_files = program_files_variable(&target).map_err(|e| RuntimeError {
+            code: "PYOXIDIZER_BUILD",
+            message: e.to_string(),
+            label: "NsisInstallerBuilder()".to_string(),
+        })?;
+
+        let mut define_values = BTreeMap::new();
+        if defines.get_type() == "dict" {
+            for key in defines.into_iter()? {
+                let value = defines.at(key.clone())?.to_string();
+                let key = key.to_string();
+
+                if !is_valid_define_name(&key) {
+                    return Err(RuntimeError {
+                        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                        message: format!(
+                            "invalid define name {}; names are letters, digits, and underscores",
+                            key
+                        ),
+                        label: "NsisInstallerBuilder()".to_string(),
+                    }
+                    .into());
+                }
+
+                define_values.insert(key, value);
+            }
+        }
+
+        let files = files.downcast_apply(|m: &FileManifest| m.manifest.clone());
+
+        // Shortcuts launch the only executable of the files by default.
+        let exe = match exe {
+            Some(exe) => {
+                if !files.has_path(Path::new(&exe)) {
+                    return Err(RuntimeError {
+                        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                        message: format!("{} is not in files", exe),
+                        label: "NsisInstallerBuilder()".to_string(),
+                    }
+                    .into());
+                }
+
+                Some(PathBuf::from(exe))
+            }
+            None => {
+                let exes = files
+                    .entries()
+                    .map(|(p, _)| p)
+                    .filter(|p| p.extension().map(|e| e == "exe").unwrap_or(false))
+                    .collect::<Vec<_>>();
+
+                if exes.len() == 1 {
+                    Some(exes[0].clone())
+                } else {
+                    None
+                }
+            }
+        };
+        let exe = match &layout {
+            Some(layout) => exe.map(|exe| layout.installed_exe_path(&exe)),
+            None => exe,
+        };
+        let files = install_files(&layout, files, "NsisInstallerBuilder()")?;
+
+        let installer = NsisInstaller {
+            product_name: product_name.unwrap_or_else(|| name.clone()),
+            product_version: version,
+            publisher,
+            exe,
+            defines: define_values,
+            per_user: layout
+                .as_ref()
+                .map(|l| l.scope == InstallScope::User)
+                .unwrap_or(false),
+        };
+
+        Ok(Value::new(NsisInstallerBuilder {
+            name,
+            installer,
+            files,
+            program_files: program_files.to_string(),
+            script: script.map(|p| cwd.join(p)),
+            makensis,
+        }))
+    }
+}
+
+starlark_module! { nsis_installer_env =>
+    #[allow(non_snake_case, clippy::ptr_arg)]
+    NsisInstallerBuilder(
+        env env,
+        name,
+        files,
+        version,
+        product_name=None,
+        publisher=None,
+        exe=None,
+        script=None,
+        defines=None,
+        makensis="makensis",
+        layout=None
+    ) {
+        NsisInstallerBuilder::new_from_args(
+            &env,
+            &name,
+            &files,
+            &version,
+            &product_name,
+            &publisher,
+            &exe,
+            &script,
+            &defines,
+            &makensis,
+            &layout,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::super::testutil::*, super::*, crate::app_packaging::resource::FileContent};
+
+    #[test]
+    fn test_nsis_installer_builder() -> Result<()> {
+        let mut manifest = RawFileManifest::default();
+        manifest.add_file(
+            Path::new("myapp.exe"),
+            &FileContent {
+                data: b"exe".to_vec(),
+                executable: true,
+            },
+        )?;
+
+        let mut env = starlark_env();
+        env.set("m", Value::new(FileManifest { manifest })).unwrap();
+
+        if !crate::project_building::HOST.contains("pc-windows") {
+            assert!(
+                starlark_eval_in_env(&mut env, "NsisInstallerBuilder('myapp', m, '1.0')").is_err()
+            );
+
+            env.get("CONTEXT")
+                .unwrap()
+                .downcast_apply_mut(|x: &mut EnvironmentContext| {
+                    x.build_target_triple = "x86_64-pc-windows-msvc".to_string();
+                });
+        }
+
+        let builder = starlark_eval_in_env(
+            &mut env,
+            "NsisInstallerBuilder('myapp', m, '1.0', product_name='My App', defines={'LEGACY_KEY': 'Software\\\\Old'})",
+        )
+        .unwrap();
+        assert_eq!(builder.get_type(), "NsisInstallerBuilder");
+
+        let td = tempdir::TempDir::new("pyoxidizer-test")?;
+        let logger = slog::Logger::root(slog::Discard, slog::o!());
+
+        builder.downcast_apply(|x: &NsisInstallerBuilder| -> Result<()> {
+            assert_eq!(x.installer.product_name, "My App");
+            assert_eq!(x.installer.exe, Some(PathBuf::from("myapp.exe")));
+            assert_eq!(x.installer_filename(), "myapp-1.0-setup.exe");
+
+            let project_path = td.path().join("myapp");
+            let script = x.write_project(&logger, &project_path)?;
+
+            assert_eq!(script, project_path.join("installer.nsi"));
+            assert!(project_path.join("files/myapp.exe").is_file());
+
+            let include = std::fs::read_to_string(project_path.join("pyoxidizer.nsh"))?;
+            assert!(include.contains("!define LEGACY_KEY \"Software\\Old\""));
+
+            Ok(())
+        })?;
+
+        assert!(starlark_eval_in_env(
+            &mut env,
+            "NsisInstallerBuilder('myapp', m, '1.0', defines={'MY-KEY': 'value'})"
+        )
+        .is_err());
+        assert!(starlark_eval_in_env(
+            &mut env,
+            "NsisInstallerBuilder('myapp', m, '1.0', exe='missing.exe')"
+        )
+        .is_err());
+
+        let builder = starlark_eval_in_env(
+            &mut env,
+            "NsisInstallerBuilder('myapp', m, '1.0', layout=InstallLayout('myapp', scope='user'))",
+        )
+        .unwrap();
+        builder.downcast_apply(|x: &NsisInstallerBuilder| {
+            assert!(x.installer.per_user);
+            assert_eq!(x.installer.exe, Some(PathBuf::from("myapp.exe")));
+        });
+
+        Ok(())
+    }
+}
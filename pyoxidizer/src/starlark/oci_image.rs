@@ -0,0 +1,414 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Container images holding installed files.
+
+Building an `OciImageBuilder` adds the files of a `FileManifest`, typically
+holding a built executable, as a layer on top of a base image and writes the
+image as an OCI image layout and as a tar archive of that layout. The image
+is optionally pushed to a registry.
+*/
+
+use {
+    super::env::EnvironmentContext,
+    super::file_resource::FileManifest,
+    super::install_layout::{install_files, optional_layout_arg},
+    super::target::{BuildContext, BuildTarget, ResolvedTarget, RunMode},
+    super::util::{
+        optional_dict_arg, optional_list_arg, optional_str_arg, required_str_arg, required_type_arg,
+    },
+    crate::app_packaging::resource::FileManifest as RawFileManifest,
+    crate::oci::{
+        is_image_layout, platform_for_triple, resolve_base_image, BaseImage, Image, ImageReference,
+        ImageSettings, Layer, RegistryClient,
+    },
+    crate::reproducible::{build_time, format_timestamp},
+    anyhow::{Context, Result},
+    slog::warn,
+    starlark::environment::Environment,
+    starlark::values::{
+        default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult,
+        INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+    },
+    starlark::{
+        any, immutable, not_supported, starlark_fun, starlark_module, starlark_signature,
+        starlark_signature_extraction, starlark_signatures,
+    },
+    std::any::Any,
+    std::cmp::Ordering,
+    std::collections::BTreeMap,
+    std::path::PathBuf,
+};
+
+/// Name of the directory in the build path caching pulled image blobs.
+pub const OCI_BLOB_CACHE_DIR: &str = "oci_blobs";
+
+/// A container image holding the files of a manifest.
+pub struct OciImageBuilder {
+    /// Name of the image layout directory and archive.
+    pub name: String,
+
+    /// Files added to the image, relative to its root directory.
+    pub files: RawFileManifest,
+
+    /// Path of an image layout or reference of an image in a registry.
+    ///
+    /// Files are added to an empty image if `None`.
+    pub base: Option<String>,
+
+    /// Tag of the image in the image layout.
+    pub tag: String,
+
+    /// Registry reference the image is pushed to.
+    pub push_to: Option<ImageReference>,
+
+    pub settings: ImageSettings,
+
+    /// Directory relative base image paths are resolved from.
+    pub cwd: PathBuf,
+
+    /// Directory caching blobs of pulled base images.
+    pub blob_cache_path: PathBuf,
+}
+
+impl TypedValue for OciImageBuilder {
+    immutable!();
+    any!();
+    not_supported!(binop, container, function, get_hash, to_int);
+
+    fn to_str(&self) -> String {
+        format!("OciImageBuilder<name={}>", self.name)
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "OciImageBuilder"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+impl BuildTarget for OciImageBuilder {
+    fn build(&mut self, context: &BuildContext) -> Result<ResolvedTarget> {
+        let platform = platform_for_triple(&context.target_triple)?;
+
+        let base = match &self.base {
+            Some(base) => resolve_base_image(
+                &context.logger,
+                base,
+                &self.cwd,
+                &platform,
+                &self.blob_cache_path,
+            )
+            .with_context(|| format!("resolving base image {}", base))?,
+            None => {
+                if !context.target_triple.contains("-musl") {
+                    warn!(
+                        &context.logger,
+                        "building image without base image for {}; executables may require shared libraries missing from the image",
+                        context.target_triple
+                    );
+                }
+
+                BaseImage::scratch(&platform)
+            }
+        };
+
+        let time = build_time();
+        let layer = Layer::from_manifest(&self.files, time)?;
+        let image = Image::assemble(base, layer, &self.settings, &format_timestamp(time))?;
+
+        std::fs::create_dir_all(&context.output_path)
+            .with_context(|| format!("creating {}", context.output_path.display()))?;
+
+        let layout_path = context.output_path.join(&self.name);
+        warn!(
+            &context.logger,
+            "writing image layout to {}",
+            layout_path.display()
+        );
+        image.write_layout(&layout_path, &self.tag)?;
+
+        let archive_path = context.output_path.join(format!("{}.tar", self.name));
+        warn!(
+            &context.logger,
+            "writing image archive to {}",
+            archive_path.display()
+        );
+        image.write_archive(&archive_path, &self.tag, time)?;
+
+        if let Some(reference) = &self.push_to {
+            warn!(&context.logger, "pushing image to {}", reference);
+            RegistryClient::new(&context.logger, reference)?
+                .push(&image)
+                .with_context(|| format!("pushing image to {}", reference))?;
+        }
+
+        Ok(ResolvedTarget {
+            run_mode: RunMode::None,
+            output_path: context.output_path.clone(),
+            artifacts: vec![layout_path, archive_path],
+        })
+    }
+}
+
+/// Convert a dict of strings to a map.
+fn string_dict(value: &Value) -> Result<BTreeMap<String, String>, ValueError> {
+    let mut result = BTreeMap::new();
+
+    if value.get_type() == "dict" {
+        for key in value.into_iter()? {
+            let v = value.at(key.clone())?.to_string();
+            result.insert(key.to_string(), v);
+        }
+    }
+
+    Ok(result)
+}
+
+// Starlark functions.
+impl OciImageBuilder {
+    /// OciImageBuilder(name, files, base=None, tag="latest", entrypoint=None, env=None, labels=None, working_dir=None, push_to=None, layout=None)
+    #[allow(clippy::too_many_arguments)]
+    fn new_from_args(
+        env: &Environment,
+        name: &Value,
+        files: &Value,
+        base: &Value,
+        tag: &Value,
+        entrypoint: &Value,
+        environment: &Value,
+        labels: &Value,
+        working_dir: &Value,
+        push_to: &Value,
+        layout: &Value,
+    ) -> ValueResult {
+        let name = required_str_arg("name", &name)?;
+        required_type_arg("files", "FileManifest", &files)?;
+        let base = optional_str_arg("base", &base)?;
+        let tag = required_str_arg("tag", &tag)?;
+        optional_list_arg("entrypoint", "string", &entrypoint)?;
+        optional_dict_arg("env", "string", "string", &environment)?;
+        optional_dict_arg("labels", "string", "string", &labels)?;
+        let working_dir = optional_str_arg("working_dir", &working_dir)?;
+        let push_to = optional_str_arg("push_to", &push_to)?;
+        let layout = optional_layout_arg(&layout)?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let (cwd, build_path) =
+            context.downcast_apply(|x: &EnvironmentContext| (x.cwd.clone(), x.build_path.clone()));
+
+        let files = files.downcast_apply(|m: &FileManifest| m.manifest.clone());
+
+        let entrypoint = match entrypoint.get_type() {
+            "list" => entrypoint
+                .into_iter()?
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>(),
+            // Run the executable if the image holds exactly one.
+            _ => {
+                let exes = files
+                    .entries()
+                    .filter(|(_, c)| c.executable)
+                    .map(|(p, _)| p)
+                    .collect::<Vec<_>>();
+
+                if exes.len() == 1 {
+                    let exe = match &layout {
+                        Some(layout) => layout.installed_exe_path(exes[0]),
+                        None => exes[0].clone(),
+                    };
+
+                    vec![format!("/{}", exe.display())]
+                } else {
+                    return Err(RuntimeError {
+                        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                        message: format!(
+                            "entrypoint is required when files hold {} executables",
+                            exes.len()
+                        ),
+                        label: "OciImageBuilder()".to_string(),
+                    }
+                    .into());
+                }
+            }
+        };
+
+        let files = install_files(&layout, files, "OciImageBuilder()")?;
+
+        // Base images are resolved when building but references are validated
+        // right away.
+        if let Some(base) = &base {
+            if !is_image_layout(&cwd.join(base)) {
+                ImageReference::parse(base).map_err(|e| RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message: e.to_string(),
+                    label: "OciImageBuilder()".to_string(),
+                })?;
+            }
+        }
+
+        let push_to = match push_to {
+            Some(value) => Some(ImageReference::parse(&value).map_err(|e| RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: e.to_string(),
+                label: "OciImageBuilder()".to_string(),
+            })?),
+            None => None,
+        };
+
+        Ok(Value::new(OciImageBuilder {
+            name,
+            files,
+            base,
+            tag,
+            push_to,
+            settings: ImageSettings {
+                entrypoint,
+                env: string_dict(&environment)?,
+                labels: string_dict(&labels)?,
+                working_dir,
+            },
+            cwd,
+            blob_cache_path: build_path.join(OCI_BLOB_CACHE_DIR),
+        }))
+    }
+}
+
+starlark_module! { oci_image_env =>
+    #[allow(non_snake_case, clippy::ptr_arg)]
+    OciImageBuilder(
+        env call_env,
+        name,
+        files,
+        base=None,
+        tag="latest",
+        entrypoint=None,
+        env=None,
+        labels=None,
+        working_dir=None,
+        push_to=None,
+        layout=None
+    ) {
+        OciImageBuilder::new_from_args(
+            &call_env,
+            &name,
+            &files,
+            &base,
+            &tag,
+            &entrypoint,
+            &env,
+            &labels,
+            &working_dir,
+            &push_to,
+            &layout,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::super::testutil::*, super::*, crate::app_packaging::resource::FileContent,
+        std::path::Path,
+    };
+
+    #[test]
+    fn test_oci_image_builder() {
+        let mut manifest = RawFileManifest::default();
+        manifest
+            .add_file(
+                Path::new("app/myapp"),
+                &FileContent {
+                    data: vec![],
+                    executable: true,
+                },
+            )
+            .unwrap();
+
+        let mut flat = RawFileManifest::default();
+        flat.add_file(
+            Path::new("myapp"),
+            &FileContent {
+                data: vec![],
+                executable: true,
+            },
+        )
+        .unwrap();
+
+        let mut env = starlark_env();
+        env.set("m", Value::new(FileManifest { manifest })).unwrap();
+        env.set(
+            "empty",
+            Value::new(FileManifest {
+                manifest: RawFileManifest::default(),
+            }),
+        )
+        .unwrap();
+
+        let image = starlark_eval_in_env(
+            &mut env,
+            "OciImageBuilder('myapp', m, base='debian:buster-slim', env={'LANG': 'C.UTF-8'}, push_to='ghcr.io/org/myapp:1.0')",
+        )
+        .unwrap();
+        assert_eq!(image.get_type(), "OciImageBuilder");
+
+        image.downcast_apply(|x: &OciImageBuilder| {
+            assert_eq!(x.base, Some("debian:buster-slim".to_string()));
+            assert_eq!(x.tag, "latest");
+            assert_eq!(x.settings.entrypoint, vec!["/app/myapp"]);
+            assert_eq!(x.settings.env["LANG"], "C.UTF-8");
+            assert_eq!(
+                x.push_to.as_ref().map(|r| r.to_string()),
+                Some("ghcr.io/org/myapp:1.0".to_string())
+            );
+        });
+
+        let image = starlark_eval_in_env(
+            &mut env,
+            "OciImageBuilder('myapp', empty, entrypoint=['/bin/sh'])",
+        )
+        .unwrap();
+        image.downcast_apply(|x: &OciImageBuilder| {
+            assert_eq!(x.settings.entrypoint, vec!["/bin/sh"]);
+        });
+
+        let err = starlark_eval_in_env(&mut env, "OciImageBuilder('myapp', empty)").unwrap_err();
+        assert!(err
+            .message
+            .contains("entrypoint is required when files hold 0 executables"));
+
+        assert!(
+            starlark_eval_in_env(&mut env, "OciImageBuilder('myapp', m, push_to='Bad')").is_err()
+        );
+
+        env.get("CONTEXT")
+            .unwrap()
+            .downcast_apply_mut(|x: &mut EnvironmentContext| {
+                x.build_target_triple = "x86_64-unknown-linux-gnu".to_string();
+            });
+        env.set("flat", Value::new(FileManifest { manifest: flat }))
+            .unwrap();
+
+        let image = starlark_eval_in_env(
+            &mut env,
+            "OciImageBuilder('myapp', flat, layout=InstallLayout('myapp'))",
+        )
+        .unwrap();
+        image.downcast_apply(|x: &OciImageBuilder| {
+            assert_eq!(x.settings.entrypoint, vec!["/usr/bin/myapp"]);
+            assert!(x.files.has_path(Path::new("usr/lib/myapp/myapp")));
+        });
+    }
+}
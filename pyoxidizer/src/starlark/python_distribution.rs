@@ -4,26 +4,42 @@
 
 use {
     super::env::EnvironmentContext,
-    super::python_executable::PythonExecutable,
+    super::file_resource::FileManifest,
+    super::python_executable::{apply_resource_hooks_to_embedded, PythonExecutable},
     super::python_resource::{
-        PythonExtensionModule, PythonExtensionModuleFlavor, PythonResourceData, PythonSourceModule,
+        PythonBytecodeModule, PythonExtensionModule, PythonExtensionModuleFlavor,
+        PythonResourceData, PythonSourceModule,
     },
+    super::python_zipapp::PythonZipApp,
     super::util::{
         optional_dict_arg, optional_list_arg, optional_str_arg, optional_type_arg,
-        required_bool_arg, required_list_arg, required_str_arg,
+        required_bool_arg, required_list_arg, required_str_arg, required_type_arg,
     },
+    crate::project_building::RustBuildOptions,
+    crate::py_packaging::binary::{LibpythonLinkMode, WindowsCrtLinkage},
     crate::py_packaging::bytecode::{BytecodeCompiler, CompileMode},
-    crate::py_packaging::config::EmbeddedPythonConfig,
+    crate::py_packaging::config::{EmbeddedPythonConfig, RunMode},
     crate::py_packaging::distribution::{
-        default_distribution_location, is_stdlib_test_package, resolve_distribution,
-        DistributionFlavor, ExtensionModuleFilter, PythonDistribution as PythonDistributionTrait,
+        default_distribution_location, is_distribution_archive_cached, is_stdlib_test_package,
+        resolve_distribution, resolve_python_distribution_archive, DistributionFlavor,
+        ExtensionModuleFilter, PythonDistribution as PythonDistributionTrait,
         PythonDistributionLocation,
     },
+    crate::py_packaging::distribution_mirror::DistributionMirrors,
+    crate::py_packaging::distribution_signature::DistributionVerifier,
+    crate::py_packaging::dry_run_distribution::DryRunDistribution,
+    crate::py_packaging::fsscan::find_top_level_packages,
+    crate::py_packaging::lockfile::{read_pipfile_lock, read_poetry_lock, LockedPackage},
+    crate::py_packaging::package_data::PackageDataFilter,
     crate::py_packaging::packaging_tool::{
-        find_resources, pip_install as raw_pip_install, read_virtualenv as raw_read_virtualenv,
-        setup_py_install as raw_setup_py_install,
+        build_pep517_project as raw_build_pep517_project, compile_modules as raw_compile_modules,
+        find_conda_env_artifacts, find_resources, pip_install as raw_pip_install,
+        pip_install_locked_packages, read_virtualenv as raw_read_virtualenv,
+        setup_py_install as raw_setup_py_install, ModuleCompiler,
     },
     crate::py_packaging::resource::BytecodeOptimizationLevel,
+    crate::py_packaging::standalone_distribution::select_variant,
+    crate::py_packaging::zipapp::ZipAppBuilder,
     anyhow::{anyhow, Result},
     itertools::Itertools,
     slog::warn,
@@ -45,12 +61,49 @@ use {
     std::sync::Arc,
 };
 
+/// Resolve a `package_data` argument to the patterns selecting package data.
+fn package_data_arg(value: &Value, label: &str) -> Result<PackageDataFilter, ValueError> {
+    optional_dict_arg("package_data", "string", "list", value)?;
+
+    let mut filter = PackageDataFilter::default();
+
+    if value.get_type() == "dict" {
+        for package in value.into_iter()? {
+            let patterns = value.at(package.clone())?;
+            required_list_arg("package_data", "string", &patterns)?;
+            let patterns: Vec<String> = patterns.into_iter()?.map(|x| x.to_string()).collect();
+
+            filter
+                .add_patterns(&package.to_string(), &patterns)
+                .or_else(|e| {
+                    Err(RuntimeError {
+                        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                        message: e.to_string(),
+                        label: label.to_string(),
+                    }
+                    .into())
+                })?;
+        }
+    }
+
+    Ok(filter)
+}
+
 pub struct PythonDistribution {
     flavor: DistributionFlavor,
     pub source: PythonDistributionLocation,
 
     dest_dir: PathBuf,
 
+    /// Whether the distribution must be resolved without network access.
+    offline: bool,
+
+    /// Rules rewriting the URL the distribution is downloaded from.
+    mirrors: DistributionMirrors,
+
+    /// Verifies the signature of the distribution archive.
+    verifier: DistributionVerifier,
+
     pub distribution: Option<Arc<Box<dyn PythonDistributionTrait>>>,
 
     compiler: Option<BytecodeCompiler>,
@@ -61,22 +114,63 @@ impl PythonDistribution {
         flavor: DistributionFlavor,
         location: PythonDistributionLocation,
         dest_dir: &Path,
+        offline: bool,
+        mirrors: DistributionMirrors,
+        verifier: DistributionVerifier,
     ) -> PythonDistribution {
         PythonDistribution {
             flavor,
             source: location,
             dest_dir: dest_dir.to_path_buf(),
+            offline,
+            mirrors,
+            verifier,
             distribution: None,
             compiler: None,
         }
     }
 
-    pub fn ensure_distribution_resolved(&mut self, logger: &slog::Logger) -> Result<()> {
+    /// Resolve the distribution this instance refers to.
+    ///
+    /// With `metadata_only`, the distribution isn't obtained and is instead
+    /// described by what the index of known distributions and any copy
+    /// already on disk say about it.
+    pub fn ensure_distribution_resolved(
+        &mut self,
+        logger: &slog::Logger,
+        metadata_only: bool,
+    ) -> Result<()> {
         if self.distribution.is_some() {
             return Ok(());
         }
 
-        let dist = resolve_distribution(logger, &self.flavor, &self.source, &self.dest_dir)?;
+        let source = self.mirrors.rewrite_location(&self.source);
+
+        if metadata_only {
+            let dist = DryRunDistribution::from_location(&self.flavor, &source, &self.dest_dir)?;
+            self.distribution = Some(Arc::new(Box::new(dist) as Box<dyn PythonDistributionTrait>));
+
+            return Ok(());
+        }
+
+        if source != self.source {
+            warn!(logger, "using mirrored Python distribution {:?}", source);
+        }
+
+        if self.offline && !is_distribution_archive_cached(&source, &self.dest_dir) {
+            return Err(anyhow!(
+                "Python distribution {:?} not found in {}; run `pyoxidizer vendor` first",
+                source,
+                self.dest_dir.display()
+            ));
+        }
+
+        // Verify the archive before it is extracted.
+        let archive_path = resolve_python_distribution_archive(logger, &source, &self.dest_dir)?;
+        self.verifier
+            .verify(logger, &source, &archive_path, self.offline)?;
+
+        let dist = resolve_distribution(logger, &self.flavor, &source, &self.dest_dir)?;
         //warn!(logger, "distribution info: {:#?}", dist.as_minimal_info());
 
         self.distribution = Some(Arc::new(dist));
@@ -97,7 +191,7 @@ impl PythonDistribution {
         optimize: BytecodeOptimizationLevel,
         output_mode: CompileMode,
     ) -> Result<Vec<u8>> {
-        self.ensure_distribution_resolved(logger)?;
+        self.ensure_distribution_resolved(logger, false)?;
 
         if let Some(dist) = &self.distribution {
             if self.compiler.is_none() {
@@ -145,14 +239,16 @@ impl TypedValue for PythonDistribution {
 
 // Starlark functions.
 impl PythonDistribution {
-    /// default_python_distribution(flavor, build_target=None)
+    /// default_python_distribution(flavor, build_target=None, version=None)
     fn default_python_distribution(
         env: &Environment,
         flavor: &Value,
         build_target: &Value,
+        version: &Value,
     ) -> ValueResult {
         let flavor = required_str_arg("flavor", flavor)?;
         let build_target = optional_str_arg("build_target", build_target)?;
+        let python_version = optional_str_arg("version", version)?;
 
         let build_target = match build_target {
             Some(t) => t,
@@ -174,7 +270,12 @@ impl PythonDistribution {
             }
         };
 
-        let location = default_distribution_location(&flavor, &build_target).or_else(|e| {
+        let location = default_distribution_location(
+            &flavor,
+            &build_target,
+            python_version.as_ref().map(|v| v.as_str()),
+        )
+        .or_else(|e| {
             Err(RuntimeError {
                 code: "PYOXIDIZER_BUILD",
                 message: e.to_string(),
@@ -184,11 +285,18 @@ impl PythonDistribution {
         })?;
 
         let context = env.get("CONTEXT").expect("CONTEXT not defined");
-        let dest_dir =
-            context.downcast_apply(|x: &EnvironmentContext| x.python_distributions_path.clone());
+        let (dest_dir, offline, mirrors, verifier) =
+            context.downcast_apply(|x: &EnvironmentContext| {
+                (
+                    x.python_distributions_path.clone(),
+                    x.offline,
+                    x.distribution_mirrors.clone(),
+                    x.distribution_verifier.clone(),
+                )
+            });
 
         Ok(Value::new(PythonDistribution::from_location(
-            flavor, location, &dest_dir,
+            flavor, location, &dest_dir, offline, mirrors, verifier,
         )))
     }
 
@@ -229,6 +337,7 @@ impl PythonDistribution {
         let flavor = match flavor.as_ref() {
             "standalone" => DistributionFlavor::Standalone,
             "windows_embeddable" => DistributionFlavor::WindowsEmbeddable,
+            "pypy" => DistributionFlavor::PyPy,
             v => {
                 return Err(RuntimeError {
                     code: "PYOXIDIZER_BUILD",
@@ -240,13 +349,77 @@ impl PythonDistribution {
         };
 
         let context = env.get("CONTEXT").expect("CONTEXT not defined");
-        let dest_dir =
-            context.downcast_apply(|x: &EnvironmentContext| x.python_distributions_path.clone());
+        let (dest_dir, offline, mirrors, verifier) =
+            context.downcast_apply(|x: &EnvironmentContext| {
+                (
+                    x.python_distributions_path.clone(),
+                    x.offline,
+                    x.distribution_mirrors.clone(),
+                    x.distribution_verifier.clone(),
+                )
+            });
 
         Ok(Value::new(PythonDistribution::from_location(
             flavor,
             distribution,
             &dest_dir,
+            offline,
+            mirrors,
+            verifier,
+        )))
+    }
+
+    /// CustomPythonDistribution(path_or_url, sha256)
+    fn custom_python_distribution(
+        env: &Environment,
+        path_or_url: &Value,
+        sha256: &Value,
+    ) -> ValueResult {
+        let path_or_url = required_str_arg("path_or_url", path_or_url)?;
+        let sha256 = required_str_arg("sha256", sha256)?;
+
+        if sha256.len() != 64 || !sha256.chars().all(|c: char| c.is_ascii_hexdigit()) {
+            return Err(RuntimeError {
+                code: "PYOXIDIZER_BUILD",
+                message: format!(
+                    "sha256 must be a 64 character hex digest; got {}; compute it with `sha256sum` or `shasum -a 256`",
+                    sha256
+                ),
+                label: "CustomPythonDistribution()".to_string(),
+            }
+            .into());
+        }
+
+        let distribution = if path_or_url.contains("://") {
+            PythonDistributionLocation::Url {
+                url: path_or_url,
+                sha256,
+            }
+        } else {
+            PythonDistributionLocation::Local {
+                local_path: path_or_url,
+                sha256,
+            }
+        };
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let (dest_dir, offline, mirrors, verifier) =
+            context.downcast_apply(|x: &EnvironmentContext| {
+                (
+                    x.python_distributions_path.clone(),
+                    x.offline,
+                    x.distribution_mirrors.clone(),
+                    x.distribution_verifier.clone(),
+                )
+            });
+
+        Ok(Value::new(PythonDistribution::from_location(
+            DistributionFlavor::Standalone,
+            distribution,
+            &dest_dir,
+            offline,
+            mirrors,
+            verifier,
         )))
     }
 
@@ -258,6 +431,8 @@ impl PythonDistribution {
     ///     include_sources=true,
     ///     include_resources=true,
     ///     include_test=false,
+    ///     libpython_link_mode=None,
+    ///     windows_crt_linkage="dynamic",
     /// )
     #[allow(clippy::ptr_arg, clippy::too_many_arguments)]
     fn as_python_executable_starlark(
@@ -271,6 +446,8 @@ impl PythonDistribution {
         include_sources: &Value,
         include_resources: &Value,
         include_test: &Value,
+        libpython_link_mode: &Value,
+        windows_crt_linkage: &Value,
     ) -> ValueResult {
         let name = required_str_arg("name", &name)?;
         optional_type_arg("config", "PythonInterpreterConfig", &config)?;
@@ -285,12 +462,46 @@ impl PythonDistribution {
         let include_sources = required_bool_arg("include_sources", &include_sources)?;
         let include_resources = required_bool_arg("include_resources", &include_resources)?;
         let include_test = required_bool_arg("include_test", &include_test)?;
+        let libpython_link_mode = optional_str_arg("libpython_link_mode", &libpython_link_mode)?;
+        let windows_crt_linkage = required_str_arg("windows_crt_linkage", &windows_crt_linkage)?;
+
+        let libpython_link_mode = match libpython_link_mode {
+            Some(v) => Some(LibpythonLinkMode::try_from(v.as_str()).or_else(|e| {
+                Err(RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message: e,
+                    label: "invalid libpython_link_mode value".to_string(),
+                }
+                .into())
+            })?),
+            None => None,
+        };
+
+        let windows_crt_linkage = WindowsCrtLinkage::try_from(windows_crt_linkage.as_str())
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message: e,
+                    label: "invalid windows_crt_linkage value".to_string(),
+                }
+                .into())
+            })?;
 
         let context = env.get("CONTEXT").expect("CONTEXT not defined");
         let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
-        let (host_triple, target_triple) = context.downcast_apply(|x: &EnvironmentContext| {
-            (x.build_host_triple.clone(), x.build_target_triple.clone())
-        });
+        let (host_triple, target_triple, offline_cargo_home, rust_toolchains_path) = context
+            .downcast_apply(|x: &EnvironmentContext| {
+                (
+                    x.build_host_triple.clone(),
+                    x.build_target_triple.clone(),
+                    if x.offline {
+                        x.cargo_vendor_path()
+                    } else {
+                        None
+                    },
+                    x.rust_toolchains_path(),
+                )
+            });
 
         let extension_module_filter =
             ExtensionModuleFilter::try_from(extension_module_filter.as_str()).or_else(|e| {
@@ -320,137 +531,212 @@ impl PythonDistribution {
                 _ => panic!("type should have been validated above"),
             };
 
-        self.ensure_distribution_resolved(&logger).or_else(|e| {
-            Err(RuntimeError {
+        let metadata_only = context.downcast_apply(|x: &EnvironmentContext| x.metadata_only());
+        self.ensure_distribution_resolved(&logger, metadata_only)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "resolve_distribution()".to_string(),
+                }
+                .into())
+            })?;
+        let dist = self.distribution.as_ref().unwrap().clone();
+
+        let capabilities = dist.capabilities();
+        if !capabilities.embeddable {
+            return Err(RuntimeError {
                 code: "PYOXIDIZER_BUILD",
-                message: e.to_string(),
-                label: "resolve_distribution()".to_string(),
+                message: format!(
+                    "{} distributions cannot be embedded in executables; use to_python_zipapp() instead",
+                    capabilities.implementation.as_str()
+                ),
+                label: "to_python_executable()".to_string(),
             }
-            .into())
-        })?;
-        let dist = self.distribution.as_ref().unwrap().clone();
+            .into());
+        }
+
+        // libpython linkage is constrained by the distribution. Fail early
+        // with a helpful message if it can't provide what was asked for.
+        if let Some(wanted) = libpython_link_mode {
+            let available = if capabilities.static_libpython {
+                LibpythonLinkMode::Static
+            } else {
+                LibpythonLinkMode::Dynamic
+            };
+
+            if available != wanted {
+                return Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: format!(
+                        "distribution links libpython {} but {} linking was requested; use default_python_distribution(flavor=\"standalone_{}\") to obtain a compatible distribution",
+                        available.as_str(),
+                        wanted.as_str(),
+                        wanted.as_str()
+                    ),
+                    label: "to_python_executable()".to_string(),
+                }
+                .into());
+            }
+        }
 
         let config = if config.get_type() == "NoneType" {
             let v = env
                 .get("PythonInterpreterConfig")
                 .expect("PythonInterpreterConfig not defined");
-            v.call(call_stack, env, Vec::new(), HashMap::new(), None, None)?
-                .downcast_apply(|c: &EmbeddedPythonConfig| c.clone())
+            v.call(
+                call_stack,
+                env.clone(),
+                Vec::new(),
+                HashMap::new(),
+                None,
+                None,
+            )?
+            .downcast_apply(|c: &EmbeddedPythonConfig| c.clone())
         } else {
             config.downcast_apply(|c: &EmbeddedPythonConfig| c.clone())
         };
 
-        Ok(Value::new(PythonExecutable {
-            exe: dist
-                .as_python_executable_builder(
-                    &logger,
-                    &host_triple,
-                    &target_triple,
-                    &name,
-                    &config,
-                    &extension_module_filter,
-                    preferred_extension_module_variants,
-                    include_sources,
-                    include_resources,
-                    include_test,
-                )
-                .or_else(|e| {
-                    Err(RuntimeError {
-                        code: "PYOXIDIZER_BUILD",
-                        message: e.to_string(),
-                        label: "to_python_executable()".to_string(),
-                    }
-                    .into())
-                })?,
-        }))
-    }
-
-    /// PythonDistribution.extension_modules(filter="all", preferred_variants=None)
-    pub fn extension_modules(
-        &mut self,
-        env: &Environment,
-        filter: &Value,
-        preferred_variants: &Value,
-    ) -> ValueResult {
-        let filter = required_str_arg("filter", &filter)?;
-        optional_dict_arg(
-            "preferred_variants",
-            "string",
-            "string",
-            &preferred_variants,
-        )?;
-
-        let filter = ExtensionModuleFilter::try_from(filter.as_str()).or_else(|e| {
-            Err(RuntimeError {
-                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
-                message: e,
-                label: "invalid policy value".to_string(),
-            }
-            .into())
-        })?;
+        let mut exe = dist
+            .as_python_executable_builder(
+                &logger,
+                &host_triple,
+                &target_triple,
+                &name,
+                &config,
+                &extension_module_filter,
+                preferred_extension_module_variants,
+                include_sources,
+                include_resources,
+                include_test,
+            )
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "to_python_executable()".to_string(),
+                }
+                .into())
+            })?;
 
-        let preferred_variants = match preferred_variants.get_type() {
-            "NoneType" => None,
-            "dict" => {
-                let mut m = HashMap::new();
+        exe.set_windows_crt_linkage(windows_crt_linkage);
+        if let Some(mode) = libpython_link_mode {
+            exe.set_libpython_link_mode(mode);
+        }
 
-                for k in preferred_variants.into_iter()? {
-                    let v = preferred_variants.at(k.clone())?.to_string();
-                    m.insert(k.to_string(), v);
+        windows_crt_linkage
+            .ensure_compatible(&target_triple, exe.libpython_link_mode())
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "to_python_executable()".to_string(),
                 }
+                .into())
+            })?;
 
-                Some(m)
-            }
-            _ => panic!("type should have been validated above"),
-        };
+        let exe = Value::new(PythonExecutable {
+            exe,
+            build_options: RustBuildOptions {
+                offline_cargo_home,
+                rust_toolchains_path,
+                ..RustBuildOptions::default()
+            },
+            bytecode_only_packages: Vec::new(),
+            bytecode_only_optimize_level: None,
+            first_party: None,
+            update_signing_key: None,
+            build_info: None,
+            resource_conflicts: Vec::new(),
+            import_allow: None,
+            import_deny: Vec::new(),
+        });
 
-        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        // Resources of the distribution go through resource hooks like
+        // resources added later.
+        let hooks_registered =
+            context.downcast_apply(|x: &EnvironmentContext| !x.resource_hooks.is_empty());
+        if hooks_registered {
+            apply_resource_hooks_to_embedded(&env, call_stack, &exe)?;
+        }
 
-        let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
+        Ok(exe)
+    }
 
-        self.ensure_distribution_resolved(&logger).or_else(|e| {
-            Err(RuntimeError {
-                code: "PYOXIDIZER_BUILD",
-                message: e.to_string(),
-                label: "resolve_distribution()".to_string(),
-            }
-            .into())
-        })?;
+    /// PythonDistribution.to_python_executable_repl(
+    ///     name="python",
+    ///     config=None,
+    ///     extension_module_filter="all",
+    ///     preferred_extension_module_variants=None,
+    ///     include_sources=true,
+    ///     include_resources=true,
+    ///     include_test=false,
+    ///     libpython_link_mode=None,
+    ///     windows_crt_linkage="dynamic",
+    /// )
+    #[allow(clippy::ptr_arg, clippy::too_many_arguments)]
+    fn as_python_executable_repl_starlark(
+        &mut self,
+        env: Environment,
+        call_stack: &Vec<(String, String)>,
+        name: &Value,
+        config: &Value,
+        extension_module_filter: &Value,
+        preferred_extension_module_variants: &Value,
+        include_sources: &Value,
+        include_resources: &Value,
+        include_test: &Value,
+        libpython_link_mode: &Value,
+        windows_crt_linkage: &Value,
+    ) -> ValueResult {
+        optional_type_arg("config", "PythonInterpreterConfig", &config)?;
 
-        Ok(Value::from(
-            self.distribution
-                .as_ref()
-                .unwrap()
-                .filter_extension_modules(&logger, &filter, preferred_variants)
-                .or_else(|e| {
-                    Err(RuntimeError {
-                        code: "PYOXIDIZER_BUILD",
-                        message: e.to_string(),
-                        label: "extension_modules()".to_string(),
-                    }
-                    .into())
-                })?
-                .iter()
-                .map(|em| {
-                    Value::new(PythonExtensionModule {
-                        em: PythonExtensionModuleFlavor::Distribution(em.clone()),
-                    })
-                })
-                .collect_vec(),
-        ))
+        let mut config = if config.get_type() == "NoneType" {
+            let v = env
+                .get("PythonInterpreterConfig")
+                .expect("PythonInterpreterConfig not defined");
+            v.call(
+                call_stack,
+                env.clone(),
+                Vec::new(),
+                HashMap::new(),
+                None,
+                None,
+            )?
+            .downcast_apply(|c: &EmbeddedPythonConfig| c.clone())
+        } else {
+            config.downcast_apply(|c: &EmbeddedPythonConfig| c.clone())
+        };
+
+        // The executable interprets its arguments like `python` does.
+        config.run_mode = RunMode::PythonCli;
+
+        self.as_python_executable_starlark(
+            env,
+            call_stack,
+            name,
+            &Value::new(config),
+            extension_module_filter,
+            preferred_extension_module_variants,
+            include_sources,
+            include_resources,
+            include_test,
+            libpython_link_mode,
+            windows_crt_linkage,
+        )
     }
 
-    /// PythonDistribution.pip_install(args, extra_envs=None)
-    pub fn pip_install(
+    /// PythonDistribution.build_pep517_project(path, extra_envs=None, package_data=None)
+    pub fn build_pep517_project(
         &mut self,
         env: &Environment,
-        args: &Value,
+        path: &Value,
         extra_envs: &Value,
+        package_data: &Value,
     ) -> ValueResult {
-        required_list_arg("args", "string", &args)?;
+        let path = required_str_arg("path", &path)?;
         optional_dict_arg("extra_envs", "string", "string", &extra_envs)?;
-
-        let args: Vec<String> = args.into_iter()?.map(|x| x.to_string()).collect();
+        let package_data = package_data_arg(&package_data, "build_pep517_project()")?;
 
         let extra_envs = match extra_envs.get_type() {
             "dict" => extra_envs
@@ -466,67 +752,556 @@ impl PythonDistribution {
         };
 
         let context = env.get("CONTEXT").expect("CONTEXT not defined");
-        let (logger, verbose) =
-            context.downcast_apply(|x: &EnvironmentContext| (x.logger.clone(), x.verbose));
+        let (logger, verbose, cwd) = context
+            .downcast_apply(|x: &EnvironmentContext| (x.logger.clone(), x.verbose, x.cwd.clone()));
+
+        let path = cwd.join(path);
+
+        let metadata_only = context.downcast_apply(|x: &EnvironmentContext| x.metadata_only());
+        self.ensure_distribution_resolved(&logger, metadata_only)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "resolve_distribution()".to_string(),
+                }
+                .into())
+            })?;
+
+        if metadata_only {
+            warn!(
+                logger,
+                "dry run: not building PEP 517 project {}",
+                path.display()
+            );
+            return Ok(Value::from(Vec::<Value>::new()));
+        }
 
-        self.ensure_distribution_resolved(&logger).or_else(|e| {
-            Err(RuntimeError {
-                code: "PYOXIDIZER_BUILD",
-                message: e.to_string(),
-                label: "resolve_distribution()".to_string(),
-            }
-            .into())
-        })?;
         let dist = self.distribution.as_ref().unwrap();
 
         let resources =
-            raw_pip_install(&logger, dist.deref().as_ref(), verbose, &args, &extra_envs).or_else(
-                |e| {
-                    Err(RuntimeError {
-                        code: "PIP_INSTALL_ERROR",
-                        message: format!("error running pip install: {}", e),
-                        label: "pip_install()".to_string(),
-                    }
-                    .into())
-                },
-            )?;
+            raw_build_pep517_project(&logger, dist.deref().as_ref(), &path, verbose, &extra_envs)
+                .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PEP517_BUILD_ERROR",
+                    message: e.to_string(),
+                    label: "build_pep517_project()".to_string(),
+                }
+                .into())
+            })?;
+        let resources = package_data.filter_resources(&logger, resources);
+
+        warn!(
+            logger,
+            "collected {} resources from PEP 517 build",
+            resources.len()
+        );
 
         Ok(Value::from(
             resources.iter().map(Value::from).collect::<Vec<Value>>(),
         ))
     }
 
-    /// PythonDistribution.read_package_root(path, packages)
-    pub fn read_package_root(
+    /// PythonDistribution.compile_modules_with_<compiler>(resources, include, requirement=None, extra_envs=None)
+    #[allow(clippy::too_many_arguments)]
+    pub fn compile_modules(
         &mut self,
         env: &Environment,
-        path: &Value,
-        packages: &Value,
+        compiler: ModuleCompiler,
+        resources: &Value,
+        include: &Value,
+        requirement: &Value,
+        extra_envs: &Value,
+        label: &str,
     ) -> ValueResult {
-        let path = required_str_arg("path", &path)?;
-        required_list_arg("packages", "string", &packages)?;
+        required_type_arg("resources", "list", &resources)?;
+        required_list_arg("include", "string", &include)?;
+        let requirement = optional_str_arg("requirement", &requirement)?
+            .unwrap_or_else(|| compiler.default_requirement().to_string());
+        optional_dict_arg("extra_envs", "string", "string", &extra_envs)?;
 
-        let packages = packages
+        let patterns = include
             .into_iter()?
-            .map(|x| x.to_string())
-            .collect::<Vec<String>>();
+            .map(|x| {
+                glob::Pattern::new(&x.to_string()).or_else(|e| {
+                    Err(RuntimeError {
+                        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                        message: format!("invalid include pattern {}: {}", x.to_string(), e),
+                        label: label.to_string(),
+                    }
+                    .into())
+                })
+            })
+            .collect::<Result<Vec<_>, ValueError>>()?;
+
+        let extra_envs = match extra_envs.get_type() {
+            "dict" => extra_envs
+                .into_iter()?
+                .map(|key| {
+                    let k = key.to_string();
+                    let v = extra_envs.at(key).unwrap().to_string();
+                    (k, v)
+                })
+                .collect(),
+            "NoneType" => HashMap::new(),
+            _ => panic!("should have validated type above"),
+        };
+
+        let modules = resources
+            .into_iter()?
+            .filter(|resource| resource.get_type() == "PythonSourceModule")
+            .map(|resource| resource.downcast_apply(|m: &PythonSourceModule| m.module.clone()))
+            .collect::<Vec<_>>();
+
+        let compile = modules
+            .iter()
+            .filter(|m| patterns.iter().any(|p| p.matches(&m.name)))
+            .map(|m| m.name.clone())
+            .collect::<Vec<_>>();
 
         let context = env.get("CONTEXT").expect("CONTEXT not defined");
         let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
 
-        self.ensure_distribution_resolved(&logger).or_else(|e| {
+        let metadata_only = context.downcast_apply(|x: &EnvironmentContext| x.metadata_only());
+        self.ensure_distribution_resolved(&logger, metadata_only)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "resolve_distribution()".to_string(),
+                }
+                .into())
+            })?;
+
+        if metadata_only {
+            warn!(
+                logger,
+                "dry run: not compiling modules with {}",
+                compiler.default_requirement()
+            );
+            return Ok(Value::from(resources.into_iter()?.collect::<Vec<Value>>()));
+        }
+
+        let dist = self.distribution.as_ref().unwrap();
+
+        let extension_modules = raw_compile_modules(
+            &logger,
+            dist.deref().as_ref(),
+            compiler,
+            &requirement,
+            &modules,
+            &compile,
+            &extra_envs,
+        )
+        .or_else(|e| {
             Err(RuntimeError {
-                code: "PYOXIDIZER_BUILD",
-                message: e.to_string(),
-                label: "resolve_distribution()".to_string(),
+                code: "COMPILE_MODULES_ERROR",
+                message: format!("error compiling modules: {:?}", e),
+                label: label.to_string(),
+            }
+            .into())
+        })?;
+
+        let mut res = Vec::new();
+
+        for resource in resources.into_iter()? {
+            let name = match resource.get_type() {
+                "PythonSourceModule" => {
+                    Some(resource.downcast_apply(|m: &PythonSourceModule| m.module.name.clone()))
+                }
+                "PythonBytecodeModule" => {
+                    Some(resource.downcast_apply(|m: &PythonBytecodeModule| m.module.name.clone()))
+                }
+                _ => None,
+            };
+
+            // Compiled modules are replaced by their extension module.
+            match name {
+                Some(name) if compile.contains(&name) => {}
+                _ => res.push(resource),
+            }
+        }
+
+        res.extend(extension_modules.iter().map(Value::from));
+
+        Ok(Value::from(res))
+    }
+
+    /// PythonDistribution.extension_module(name, variant=None)
+    pub fn extension_module(
+        &mut self,
+        env: &Environment,
+        name: &Value,
+        variant: &Value,
+    ) -> ValueResult {
+        let name = required_str_arg("name", &name)?;
+        let variant = optional_str_arg("variant", &variant)?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
+
+        let metadata_only = context.downcast_apply(|x: &EnvironmentContext| x.metadata_only());
+        self.ensure_distribution_resolved(&logger, metadata_only)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "resolve_distribution()".to_string(),
+                }
+                .into())
+            })?;
+
+        let variants = self
+            .distribution
+            .as_ref()
+            .unwrap()
+            .extension_module_variants(&name);
+
+        // Variants aren't known without the distribution.
+        let variant = if metadata_only { None } else { variant };
+
+        let em = select_variant(&name, &variants, variant.as_ref().map(|v| v.as_str())).or_else(
+            |e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "extension_module()".to_string(),
+                }
+                .into())
+            },
+        )?;
+
+        Ok(Value::new(PythonExtensionModule {
+            em: PythonExtensionModuleFlavor::Distribution(em),
+        }))
+    }
+
+    /// PythonDistribution.extension_modules(filter="all", preferred_variants=None)
+    pub fn extension_modules(
+        &mut self,
+        env: &Environment,
+        filter: &Value,
+        preferred_variants: &Value,
+    ) -> ValueResult {
+        let filter = required_str_arg("filter", &filter)?;
+        optional_dict_arg(
+            "preferred_variants",
+            "string",
+            "string",
+            &preferred_variants,
+        )?;
+
+        let filter = ExtensionModuleFilter::try_from(filter.as_str()).or_else(|e| {
+            Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: e,
+                label: "invalid policy value".to_string(),
             }
             .into())
         })?;
 
+        let preferred_variants = match preferred_variants.get_type() {
+            "NoneType" => None,
+            "dict" => {
+                let mut m = HashMap::new();
+
+                for k in preferred_variants.into_iter()? {
+                    let v = preferred_variants.at(k.clone())?.to_string();
+                    m.insert(k.to_string(), v);
+                }
+
+                Some(m)
+            }
+            _ => panic!("type should have been validated above"),
+        };
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+
+        let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
+
+        let metadata_only = context.downcast_apply(|x: &EnvironmentContext| x.metadata_only());
+        self.ensure_distribution_resolved(&logger, metadata_only)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "resolve_distribution()".to_string(),
+                }
+                .into())
+            })?;
+
+        Ok(Value::from(
+            self.distribution
+                .as_ref()
+                .unwrap()
+                .filter_extension_modules(&logger, &filter, preferred_variants)
+                .or_else(|e| {
+                    Err(RuntimeError {
+                        code: "PYOXIDIZER_BUILD",
+                        message: e.to_string(),
+                        label: "extension_modules()".to_string(),
+                    }
+                    .into())
+                })?
+                .iter()
+                .map(|em| {
+                    Value::new(PythonExtensionModule {
+                        em: PythonExtensionModuleFlavor::Distribution(em.clone()),
+                    })
+                })
+                .collect_vec(),
+        ))
+    }
+
+    /// PythonDistribution.find_resources_in_conda_env(path)
+    pub fn find_resources_in_conda_env(&mut self, env: &Environment, path: &Value) -> ValueResult {
+        let path = required_str_arg("path", &path)?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let (logger, cwd) =
+            context.downcast_apply(|x: &EnvironmentContext| (x.logger.clone(), x.cwd.clone()));
+
+        let metadata_only = context.downcast_apply(|x: &EnvironmentContext| x.metadata_only());
+        self.ensure_distribution_resolved(&logger, metadata_only)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "resolve_distribution()".to_string(),
+                }
+                .into())
+            })?;
+
+        if metadata_only {
+            warn!(logger, "dry run: not reading conda environment {}", path);
+            return Ok(Value::from((
+                Value::from(Vec::<Value>::new()),
+                Value::new(FileManifest {
+                    manifest: Default::default(),
+                }),
+            )));
+        }
+
         let dist = self.distribution.as_ref().unwrap();
 
-        let resources = find_resources(&logger, dist.deref().as_ref(), Path::new(&path), None)
+        let artifacts = find_conda_env_artifacts(&logger, dist.deref().as_ref(), &cwd.join(path))
+            .or_else(|e| {
+            Err(RuntimeError {
+                code: "CONDA_ENV_ERROR",
+                message: format!("could not find resources: {}", e),
+                label: "find_resources_in_conda_env()".to_string(),
+            }
+            .into())
+        })?;
+
+        let resources = Value::from(
+            artifacts
+                .resources
+                .iter()
+                .map(Value::from)
+                .collect::<Vec<Value>>(),
+        );
+        let manifest = Value::new(FileManifest {
+            manifest: artifacts.files,
+        });
+
+        Ok(Value::from((resources, manifest)))
+    }
+
+    /// PythonDistribution.pip_install(args, extra_envs=None, index_url=None, extra_index_urls=None, trusted_hosts=None, find_links=None, offline=false, proxy=None, netrc=None, keyring=false, package_data=None, build_sdists=true)
+    #[allow(clippy::too_many_arguments)]
+    pub fn pip_install(
+        &mut self,
+        env: &Environment,
+        args: &Value,
+        extra_envs: &Value,
+        index_url: &Value,
+        extra_index_urls: &Value,
+        trusted_hosts: &Value,
+        find_links: &Value,
+        offline: &Value,
+        proxy: &Value,
+        netrc: &Value,
+        keyring: &Value,
+        package_data: &Value,
+        build_sdists: &Value,
+    ) -> ValueResult {
+        required_list_arg("args", "string", &args)?;
+        optional_dict_arg("extra_envs", "string", "string", &extra_envs)?;
+        let index_url = optional_str_arg("index_url", &index_url)?;
+        optional_list_arg("extra_index_urls", "string", &extra_index_urls)?;
+        optional_list_arg("trusted_hosts", "string", &trusted_hosts)?;
+        optional_list_arg("find_links", "string", &find_links)?;
+        let offline = required_bool_arg("offline", &offline)?;
+        let proxy = optional_str_arg("proxy", &proxy)?;
+        let netrc = optional_str_arg("netrc", &netrc)?;
+        let keyring = required_bool_arg("keyring", &keyring)?;
+        let package_data = package_data_arg(&package_data, "pip_install()")?;
+        let build_sdists = required_bool_arg("build_sdists", &build_sdists)?;
+
+        let args: Vec<String> = args.into_iter()?.map(|x| x.to_string()).collect();
+
+        let string_list = |value: &Value| -> Result<Vec<String>, ValueError> {
+            match value.get_type() {
+                "list" => Ok(value.into_iter()?.map(|x| x.to_string()).collect()),
+                _ => Ok(Vec::new()),
+            }
+        };
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let (logger, verbose, cwd) = context
+            .downcast_apply(|x: &EnvironmentContext| (x.logger.clone(), x.verbose, x.cwd.clone()));
+
+        let mut index = context
+            .downcast_apply(|x: &EnvironmentContext| x.pip_index_options())
             .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "pip_install()".to_string(),
+                }
+                .into())
+            })?;
+
+        index.index_url = index_url;
+        index.extra_index_urls = string_list(extra_index_urls)?;
+        index.trusted_hosts = string_list(trusted_hosts)?;
+        index
+            .find_links
+            .extend(string_list(find_links)?.iter().map(|p| cwd.join(p)));
+        index.offline = index.offline || offline;
+        index.proxy = proxy;
+        index.netrc = netrc.map(|p| cwd.join(p));
+        index.keyring = keyring;
+        index.build_sdists = index.build_sdists && build_sdists;
+
+        let extra_envs = match extra_envs.get_type() {
+            "dict" => extra_envs
+                .into_iter()?
+                .map(|key| {
+                    let k = key.to_string();
+                    let v = extra_envs.at(key).unwrap().to_string();
+                    (k, v)
+                })
+                .collect(),
+            "NoneType" => HashMap::new(),
+            _ => panic!("should have validated type above"),
+        };
+
+        let metadata_only = context.downcast_apply(|x: &EnvironmentContext| x.metadata_only());
+        self.ensure_distribution_resolved(&logger, metadata_only)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "resolve_distribution()".to_string(),
+                }
+                .into())
+            })?;
+
+        if metadata_only {
+            warn!(
+                logger,
+                "dry run: not running pip install {}",
+                args.join(" ")
+            );
+            return Ok(Value::from(Vec::<Value>::new()));
+        }
+
+        let dist = self.distribution.as_ref().unwrap();
+
+        let resources = raw_pip_install(
+            &logger,
+            dist.deref().as_ref(),
+            verbose,
+            &args,
+            &index,
+            &extra_envs,
+        )
+        .or_else(|e| {
+            Err(RuntimeError {
+                code: "PIP_INSTALL_ERROR",
+                message: format!("error running pip install: {}", e),
+                label: "pip_install()".to_string(),
+            }
+            .into())
+        })?;
+        let resources = package_data.filter_resources(&logger, resources);
+
+        Ok(Value::from(
+            resources.iter().map(Value::from).collect::<Vec<Value>>(),
+        ))
+    }
+
+    /// PythonDistribution.read_package_root(path, packages=None, package_data=None)
+    pub fn read_package_root(
+        &mut self,
+        env: &Environment,
+        path: &Value,
+        packages: &Value,
+        package_data: &Value,
+    ) -> ValueResult {
+        let path = required_str_arg("path", &path)?;
+        optional_list_arg("packages", "string", &packages)?;
+        let package_data = package_data_arg(&package_data, "read_package_root()")?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let (logger, cwd) =
+            context.downcast_apply(|x: &EnvironmentContext| (x.logger.clone(), x.cwd.clone()));
+        let path = cwd.join(path);
+
+        let metadata_only = context.downcast_apply(|x: &EnvironmentContext| x.metadata_only());
+        self.ensure_distribution_resolved(&logger, metadata_only)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "resolve_distribution()".to_string(),
+                }
+                .into())
+            })?;
+
+        if metadata_only {
+            warn!(
+                logger,
+                "dry run: not reading package root {}",
+                path.display()
+            );
+            return Ok(Value::from(Vec::<Value>::new()));
+        }
+
+        let dist = self.distribution.as_ref().unwrap();
+
+        let packages = match packages.get_type() {
+            "list" => packages
+                .into_iter()?
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>(),
+            "NoneType" => {
+                let suffixes = dist.python_module_suffixes().or_else(|e| {
+                    Err(RuntimeError {
+                        code: "PACKAGE_ROOT_ERROR",
+                        message: e.to_string(),
+                        label: "read_package_root()".to_string(),
+                    }
+                    .into())
+                })?;
+                let packages = find_top_level_packages(&path, &suffixes);
+
+                warn!(
+                    logger,
+                    "found packages in {}: {}",
+                    path.display(),
+                    packages.join(", ")
+                );
+
+                packages
+            }
+            _ => panic!("should have validated type above"),
+        };
+
+        let resources =
+            find_resources(&logger, dist.deref().as_ref(), &path, None).or_else(|e| {
                 Err(RuntimeError {
                     code: "PACKAGE_ROOT_ERROR",
                     message: format!("could not find resources: {}", e),
@@ -534,6 +1309,7 @@ impl PythonDistribution {
                 }
                 .into())
             })?;
+        let resources = package_data.filter_resources(&logger, resources);
 
         Ok(Value::from(
             resources
@@ -544,21 +1320,35 @@ impl PythonDistribution {
         ))
     }
 
-    /// PythonDistribution.read_virtualenv(path)
-    pub fn read_virtualenv(&mut self, env: &Environment, path: &Value) -> ValueResult {
+    /// PythonDistribution.read_virtualenv(path, package_data=None)
+    pub fn read_virtualenv(
+        &mut self,
+        env: &Environment,
+        path: &Value,
+        package_data: &Value,
+    ) -> ValueResult {
         let path = required_str_arg("path", &path)?;
+        let package_data = package_data_arg(&package_data, "read_virtualenv()")?;
 
         let context = env.get("CONTEXT").expect("CONTEXT not defined");
         let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
 
-        self.ensure_distribution_resolved(&logger).or_else(|e| {
-            Err(RuntimeError {
-                code: "PYOXIDIZER_BUILD",
-                message: e.to_string(),
-                label: "resolve_distribution()".to_string(),
-            }
-            .into())
-        })?;
+        let metadata_only = context.downcast_apply(|x: &EnvironmentContext| x.metadata_only());
+        self.ensure_distribution_resolved(&logger, metadata_only)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "resolve_distribution()".to_string(),
+                }
+                .into())
+            })?;
+
+        if metadata_only {
+            warn!(logger, "dry run: not reading virtualenv {}", path);
+            return Ok(Value::from(Vec::<Value>::new()));
+        }
+
         let dist = self.distribution.as_ref().unwrap();
 
         let resources = raw_read_virtualenv(&logger, dist.deref().as_ref(), &Path::new(&path))
@@ -570,29 +1360,163 @@ impl PythonDistribution {
                 }
                 .into())
             })?;
+        let resources = package_data.filter_resources(&logger, resources);
 
         Ok(Value::from(
             resources.iter().map(Value::from).collect::<Vec<Value>>(),
         ))
     }
 
-    /// PythonDistribution.resources_data(include_test=false)
-    pub fn resources_data(&mut self, env: &Environment, include_test: &Value) -> ValueResult {
-        let include_test = required_bool_arg("include_test", &include_test)?;
+    /// Install packages pinned by a lockfile read with `read`.
+    fn resources_from_lockfile(
+        &mut self,
+        env: &Environment,
+        path: &Value,
+        dev: &Value,
+        extra_envs: &Value,
+        read: fn(&Path, bool) -> Result<Vec<LockedPackage>>,
+        label: &str,
+    ) -> ValueResult {
+        let path = required_str_arg("path", &path)?;
+        let dev = required_bool_arg("dev", &dev)?;
+        optional_dict_arg("extra_envs", "string", "string", &extra_envs)?;
 
-        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let extra_envs = match extra_envs.get_type() {
+            "dict" => extra_envs
+                .into_iter()?
+                .map(|key| {
+                    let k = key.to_string();
+                    let v = extra_envs.at(key).unwrap().to_string();
+                    (k, v)
+                })
+                .collect(),
+            "NoneType" => HashMap::new(),
+            _ => panic!("should have validated type above"),
+        };
 
-        let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let (logger, verbose, cwd) = context
+            .downcast_apply(|x: &EnvironmentContext| (x.logger.clone(), x.verbose, x.cwd.clone()));
 
-        self.ensure_distribution_resolved(&logger).or_else(|e| {
+        let packages = read(&cwd.join(path), dev).or_else(|e| {
             Err(RuntimeError {
-                code: "PYOXIDIZER_BUILD",
+                code: "LOCKFILE_ERROR",
                 message: e.to_string(),
-                label: "resolve_distribution()".to_string(),
+                label: label.to_string(),
             }
             .into())
         })?;
 
+        let index = context
+            .downcast_apply(|x: &EnvironmentContext| x.pip_index_options())
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: label.to_string(),
+                }
+                .into())
+            })?;
+
+        let metadata_only = context.downcast_apply(|x: &EnvironmentContext| x.metadata_only());
+        self.ensure_distribution_resolved(&logger, metadata_only)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "resolve_distribution()".to_string(),
+                }
+                .into())
+            })?;
+
+        if metadata_only {
+            warn!(
+                logger,
+                "dry run: not installing {} locked packages",
+                packages.len()
+            );
+            return Ok(Value::from(Vec::<Value>::new()));
+        }
+
+        let dist = self.distribution.as_ref().unwrap();
+
+        let resources = pip_install_locked_packages(
+            &logger,
+            dist.deref().as_ref(),
+            verbose,
+            &packages,
+            &index,
+            &extra_envs,
+        )
+        .or_else(|e| {
+            Err(RuntimeError {
+                code: "PIP_INSTALL_ERROR",
+                message: format!("error installing locked packages: {}", e),
+                label: label.to_string(),
+            }
+            .into())
+        })?;
+
+        Ok(Value::from(
+            resources.iter().map(Value::from).collect::<Vec<Value>>(),
+        ))
+    }
+
+    /// PythonDistribution.resources_from_pipfile_lock(path, dev=false, extra_envs=None)
+    pub fn resources_from_pipfile_lock(
+        &mut self,
+        env: &Environment,
+        path: &Value,
+        dev: &Value,
+        extra_envs: &Value,
+    ) -> ValueResult {
+        self.resources_from_lockfile(
+            env,
+            path,
+            dev,
+            extra_envs,
+            read_pipfile_lock,
+            "resources_from_pipfile_lock()",
+        )
+    }
+
+    /// PythonDistribution.resources_from_poetry_lock(path, dev=false, extra_envs=None)
+    pub fn resources_from_poetry_lock(
+        &mut self,
+        env: &Environment,
+        path: &Value,
+        dev: &Value,
+        extra_envs: &Value,
+    ) -> ValueResult {
+        self.resources_from_lockfile(
+            env,
+            path,
+            dev,
+            extra_envs,
+            read_poetry_lock,
+            "resources_from_poetry_lock()",
+        )
+    }
+
+    /// PythonDistribution.resources_data(include_test=false)
+    pub fn resources_data(&mut self, env: &Environment, include_test: &Value) -> ValueResult {
+        let include_test = required_bool_arg("include_test", &include_test)?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+
+        let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
+
+        let metadata_only = context.downcast_apply(|x: &EnvironmentContext| x.metadata_only());
+        self.ensure_distribution_resolved(&logger, metadata_only)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "resolve_distribution()".to_string(),
+                }
+                .into())
+            })?;
+
         let resources = self
             .distribution
             .as_ref()
@@ -621,17 +1545,19 @@ impl PythonDistribution {
         ))
     }
 
-    /// PythonDistribution.setup_py_install(package_path, extra_envs=None, extra_global_arguments=None)
+    /// PythonDistribution.setup_py_install(package_path, extra_envs=None, extra_global_arguments=None, package_data=None)
     pub fn setup_py_install(
         &mut self,
         env: &Environment,
         package_path: &Value,
         extra_envs: &Value,
         extra_global_arguments: &Value,
+        package_data: &Value,
     ) -> ValueResult {
         let package_path = required_str_arg("package_path", &package_path)?;
         optional_dict_arg("extra_envs", "string", "string", &extra_envs)?;
         optional_list_arg("extra_global_arguments", "string", &extra_global_arguments)?;
+        let package_data = package_data_arg(&package_data, "setup_py_install()")?;
 
         let extra_envs = match extra_envs.get_type() {
             "dict" => extra_envs
@@ -667,14 +1593,26 @@ impl PythonDistribution {
             PathBuf::from(cwd).join(package_path)
         };
 
-        self.ensure_distribution_resolved(&logger).or_else(|e| {
-            Err(RuntimeError {
-                code: "PYOXIDIZER_BUILD",
-                message: e.to_string(),
-                label: "resolve_distribution()".to_string(),
-            }
-            .into())
-        })?;
+        let metadata_only = context.downcast_apply(|x: &EnvironmentContext| x.metadata_only());
+        self.ensure_distribution_resolved(&logger, metadata_only)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "resolve_distribution()".to_string(),
+                }
+                .into())
+            })?;
+
+        if metadata_only {
+            warn!(
+                logger,
+                "dry run: not running setup.py install in {}",
+                package_path.display()
+            );
+            return Ok(Value::from(Vec::<Value>::new()));
+        }
+
         let dist = self.distribution.as_ref().unwrap();
 
         let resources = raw_setup_py_install(
@@ -693,6 +1631,7 @@ impl PythonDistribution {
             }
             .into())
         })?;
+        let resources = package_data.filter_resources(&logger, resources);
 
         warn!(
             logger,
@@ -711,14 +1650,16 @@ impl PythonDistribution {
 
         let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
 
-        self.ensure_distribution_resolved(&logger).or_else(|e| {
-            Err(RuntimeError {
-                code: "PYOXIDIZER_BUILD",
-                message: e.to_string(),
-                label: "resolve_distribution()".to_string(),
-            }
-            .into())
-        })?;
+        let metadata_only = context.downcast_apply(|x: &EnvironmentContext| x.metadata_only());
+        self.ensure_distribution_resolved(&logger, metadata_only)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "resolve_distribution()".to_string(),
+                }
+                .into())
+            })?;
 
         let modules = self
             .distribution
@@ -745,6 +1686,39 @@ impl PythonDistribution {
                 .collect_vec(),
         ))
     }
+
+    /// PythonDistribution.to_python_zipapp(name, interpreter="/usr/bin/env python3", main=None)
+    pub fn to_python_zipapp(
+        &mut self,
+        env: &Environment,
+        name: &Value,
+        interpreter: &Value,
+        main: &Value,
+    ) -> ValueResult {
+        let name = required_str_arg("name", &name)?;
+        let interpreter = optional_str_arg("interpreter", &interpreter)?;
+        let main = optional_str_arg("main", &main)?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
+
+        let metadata_only = context.downcast_apply(|x: &EnvironmentContext| x.metadata_only());
+        self.ensure_distribution_resolved(&logger, metadata_only)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "resolve_distribution()".to_string(),
+                }
+                .into())
+            })?;
+
+        let python_exe = self.distribution.as_ref().unwrap().python_exe_path();
+
+        Ok(Value::new(PythonZipApp {
+            zipapp: ZipAppBuilder::new(&name, python_exe, interpreter, main),
+        }))
+    }
 }
 
 starlark_module! { python_distribution_module =>
@@ -753,6 +1727,75 @@ starlark_module! { python_distribution_module =>
         PythonDistribution::from_args(&env, &sha256, &local_path, &url, &flavor)
     }
 
+    #[allow(non_snake_case, clippy::ptr_arg)]
+    CustomPythonDistribution(env env, path_or_url, sha256) {
+        PythonDistribution::custom_python_distribution(&env, &path_or_url, &sha256)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonDistribution.build_pep517_project(
+        env env,
+        this,
+        path,
+        extra_envs=None,
+        package_data=None
+    ) {
+        this.downcast_apply_mut(|dist: &mut PythonDistribution| {
+            dist.build_pep517_project(&env, &path, &extra_envs, &package_data)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonDistribution.compile_modules_with_cython(
+        env env,
+        this,
+        resources,
+        include,
+        requirement=None,
+        extra_envs=None
+    ) {
+        this.downcast_apply_mut(|dist: &mut PythonDistribution| {
+            dist.compile_modules(
+                &env,
+                ModuleCompiler::Cython,
+                &resources,
+                &include,
+                &requirement,
+                &extra_envs,
+                "compile_modules_with_cython()",
+            )
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonDistribution.compile_modules_with_mypyc(
+        env env,
+        this,
+        resources,
+        include,
+        requirement=None,
+        extra_envs=None
+    ) {
+        this.downcast_apply_mut(|dist: &mut PythonDistribution| {
+            dist.compile_modules(
+                &env,
+                ModuleCompiler::Mypyc,
+                &resources,
+                &include,
+                &requirement,
+                &extra_envs,
+                "compile_modules_with_mypyc()",
+            )
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonDistribution.extension_module(env env, this, name, variant=None) {
+        this.downcast_apply_mut(|dist: &mut PythonDistribution| {
+            dist.extension_module(&env, &name, &variant)
+        })
+    }
+
     #[allow(clippy::ptr_arg)]
     PythonDistribution.extension_modules(env env, this, filter="all", preferred_variants=None) {
         this.downcast_apply_mut(|dist: &mut PythonDistribution| {
@@ -775,9 +1818,45 @@ starlark_module! { python_distribution_module =>
     }
 
     #[allow(clippy::ptr_arg)]
-    PythonDistribution.pip_install(env env, this, args, extra_envs=None) {
+    PythonDistribution.find_resources_in_conda_env(env env, this, path) {
         this.downcast_apply_mut(|dist: &mut PythonDistribution| {
-            dist.pip_install(&env, &args, &extra_envs)
+            dist.find_resources_in_conda_env(&env, &path)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonDistribution.pip_install(
+        env env,
+        this,
+        args,
+        extra_envs=None,
+        index_url=None,
+        extra_index_urls=None,
+        trusted_hosts=None,
+        find_links=None,
+        offline=false,
+        proxy=None,
+        netrc=None,
+        keyring=false,
+        package_data=None,
+        build_sdists=true
+    ) {
+        this.downcast_apply_mut(|dist: &mut PythonDistribution| {
+            dist.pip_install(
+                &env,
+                &args,
+                &extra_envs,
+                &index_url,
+                &extra_index_urls,
+                &trusted_hosts,
+                &find_links,
+                &offline,
+                &proxy,
+                &netrc,
+                &keyring,
+                &package_data,
+                &build_sdists,
+            )
         })
     }
 
@@ -786,10 +1865,11 @@ starlark_module! { python_distribution_module =>
         env env,
         this,
         path,
-        packages
+        packages=None,
+        package_data=None
     ) {
         this.downcast_apply_mut(|dist: &mut PythonDistribution| {
-            dist.read_package_root(&env, &path, &packages)
+            dist.read_package_root(&env, &path, &packages, &package_data)
         })
     }
 
@@ -797,10 +1877,37 @@ starlark_module! { python_distribution_module =>
     PythonDistribution.read_virtualenv(
         env env,
         this,
-        path
+        path,
+        package_data=None
+    ) {
+        this.downcast_apply_mut(|dist: &mut PythonDistribution| {
+            dist.read_virtualenv(&env, &path, &package_data)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonDistribution.resources_from_pipfile_lock(
+        env env,
+        this,
+        path,
+        dev=false,
+        extra_envs=None
+    ) {
+        this.downcast_apply_mut(|dist: &mut PythonDistribution| {
+            dist.resources_from_pipfile_lock(&env, &path, &dev, &extra_envs)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonDistribution.resources_from_poetry_lock(
+        env env,
+        this,
+        path,
+        dev=false,
+        extra_envs=None
     ) {
         this.downcast_apply_mut(|dist: &mut PythonDistribution| {
-            dist.read_virtualenv(&env, &path)
+            dist.resources_from_poetry_lock(&env, &path, &dev, &extra_envs)
         })
     }
 
@@ -810,10 +1917,17 @@ starlark_module! { python_distribution_module =>
         this,
         package_path,
         extra_envs=None,
-        extra_global_arguments=None
+        extra_global_arguments=None,
+        package_data=None
     ) {
         this.downcast_apply_mut(|dist: &mut PythonDistribution| {
-            dist.setup_py_install(&env, &package_path, &extra_envs, &extra_global_arguments)
+            dist.setup_py_install(
+                &env,
+                &package_path,
+                &extra_envs,
+                &extra_global_arguments,
+                &package_data,
+            )
         })
     }
 
@@ -828,7 +1942,9 @@ starlark_module! { python_distribution_module =>
         preferred_extension_module_variants=None,
         include_sources=true,
         include_resources=false,
-        include_test=false
+        include_test=false,
+        libpython_link_mode=None,
+        windows_crt_linkage="dynamic"
     ) {
         this.downcast_apply_mut(|dist: &mut PythonDistribution| {
             dist.as_python_executable_starlark(
@@ -841,13 +1957,70 @@ starlark_module! { python_distribution_module =>
                 &include_sources,
                 &include_resources,
                 &include_test,
+                &libpython_link_mode,
+                &windows_crt_linkage,
+            )
+        })
+    }
+
+    #[allow(non_snake_case, clippy::ptr_arg)]
+    PythonDistribution.to_python_executable_repl(
+        env env,
+        call_stack call_stack,
+        this,
+        name="python",
+        config=None,
+        extension_module_filter="all",
+        preferred_extension_module_variants=None,
+        include_sources=true,
+        include_resources=true,
+        include_test=false,
+        libpython_link_mode=None,
+        windows_crt_linkage="dynamic"
+    ) {
+        this.downcast_apply_mut(|dist: &mut PythonDistribution| {
+            dist.as_python_executable_repl_starlark(
+                env.clone(),
+                call_stack,
+                &name,
+                &config,
+                &extension_module_filter,
+                &preferred_extension_module_variants,
+                &include_sources,
+                &include_resources,
+                &include_test,
+                &libpython_link_mode,
+                &windows_crt_linkage,
             )
         })
     }
 
     #[allow(clippy::ptr_arg)]
-    default_python_distribution(env env, flavor="standalone", build_target=None) {
-        PythonDistribution::default_python_distribution(&env, &flavor, &build_target)
+    PythonDistribution.to_python_zipapp(
+        env env,
+        this,
+        name,
+        interpreter="/usr/bin/env python3",
+        main=None
+    ) {
+        this.downcast_apply_mut(|dist: &mut PythonDistribution| {
+            dist.to_python_zipapp(&env, &name, &interpreter, &main)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    default_python_distribution(
+        env env,
+        flavor="standalone",
+        build_target=None,
+        version=None
+    ) {
+        PythonDistribution::default_python_distribution(
+            &env,
+            &flavor,
+            &build_target,
+            &version,
+        )
     }
 }
 
@@ -884,6 +2057,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_default_python_distribution_version() {
+        let dist = starlark_ok("default_python_distribution(version='3.7')");
+        assert_eq!(dist.get_type(), "PythonDistribution");
+
+        let err = starlark_nok("default_python_distribution(version='3.10')");
+        assert!(err.message.contains("available versions"));
+
+        let err = starlark_nok("default_python_distribution(version=3)");
+        assert_eq!(
+            err.message,
+            "function expects an optional string for version; got type int"
+        );
+    }
+
     #[test]
     #[cfg(windows)]
     fn test_default_python_distribution_windows() {
@@ -920,6 +2108,55 @@ mod tests {
         dist.downcast_apply(|x: &PythonDistribution| assert_eq!(x.source, wanted));
     }
 
+    #[test]
+    fn test_python_distribution_pypy() {
+        let dist = starlark_ok("PythonDistribution('sha256', url='some_url', flavor='pypy')");
+
+        dist.downcast_apply(|x: &PythonDistribution| {
+            assert_eq!(x.flavor, DistributionFlavor::PyPy);
+        });
+    }
+
+    #[test]
+    fn test_to_python_executable_linkage_args() {
+        let err = starlark_nok(
+            "default_python_distribution().to_python_executable('testapp', windows_crt_linkage='foo')",
+        );
+        assert_eq!(
+            err.message,
+            "foo is not a valid Windows CRT linkage; expected static or dynamic"
+        );
+
+        let err = starlark_nok(
+            "default_python_distribution().to_python_executable('testapp', libpython_link_mode='foo')",
+        );
+        assert!(err
+            .message
+            .starts_with("foo is not a valid libpython link mode"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_to_python_executable_libpython_link_mode_mismatch() {
+        let err = starlark_nok(
+            "default_python_distribution(flavor='standalone_static').to_python_executable('testapp', libpython_link_mode='dynamic')",
+        );
+        assert!(err
+            .message
+            .starts_with("distribution links libpython static"));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_to_python_executable_libpython_link_mode() {
+        let exe = starlark_ok(
+            "default_python_distribution(flavor='standalone_static').to_python_executable('testapp', libpython_link_mode='static')",
+        );
+        exe.downcast_apply(|x: &PythonExecutable| {
+            assert_eq!(x.exe.libpython_link_mode(), LibpythonLinkMode::Static);
+        });
+    }
+
     #[test]
     fn test_python_distribution_no_args() {
         let err = starlark_nok("PythonDistribution()");
@@ -973,12 +2210,103 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_custom_python_distribution() {
+        let sha256 = "a".repeat(64);
+
+        let dist = starlark_ok(&format!(
+            "CustomPythonDistribution('https://example.com/python.tar.zst', '{}')",
+            sha256
+        ));
+        dist.downcast_apply(|x: &PythonDistribution| {
+            assert_eq!(
+                x.source,
+                PythonDistributionLocation::Url {
+                    url: "https://example.com/python.tar.zst".to_string(),
+                    sha256: sha256.clone(),
+                }
+            );
+            assert_eq!(x.flavor, DistributionFlavor::Standalone);
+        });
+
+        let dist = starlark_ok(&format!(
+            "CustomPythonDistribution('dist/python.tar.zst', '{}')",
+            sha256
+        ));
+        dist.downcast_apply(|x: &PythonDistribution| {
+            assert_eq!(
+                x.source,
+                PythonDistributionLocation::Local {
+                    local_path: "dist/python.tar.zst".to_string(),
+                    sha256: sha256.clone(),
+                }
+            );
+        });
+
+        let err = starlark_nok("CustomPythonDistribution('dist/python.tar.zst', 'abc')");
+        assert!(err
+            .message
+            .starts_with("sha256 must be a 64 character hex digest"));
+    }
+
     #[test]
     fn test_source_modules() {
         let mods = starlark_ok("default_python_distribution().source_modules()");
         assert_eq!(mods.get_type(), "list");
     }
 
+    #[test]
+    fn test_extension_module_attrs() {
+        let mut env = starlark_env();
+        starlark_eval_in_env(
+            &mut env,
+            "ems = {em.name: em for em in default_python_distribution().extension_modules()}",
+        )
+        .unwrap();
+
+        let em = starlark_eval_in_env(&mut env, "ems['_sqlite3']").unwrap();
+        assert_eq!(em.get_type(), "PythonExtensionModule");
+        assert_eq!(em.get_attr("init_fn").unwrap().to_str(), "PyInit__sqlite3");
+        assert!(em.get_attr("is_stdlib").unwrap().to_bool());
+        assert!(!em.get_attr("is_builtin_default").unwrap().to_bool());
+        assert_eq!(em.get_attr("variant").unwrap().to_str(), "default");
+
+        let libraries = starlark_eval_in_env(&mut env, "ems['_sqlite3'].required_libraries")
+            .unwrap()
+            .into_iter()
+            .unwrap()
+            .map(|v| v.to_str())
+            .collect::<Vec<_>>();
+        assert!(libraries.contains(&"sqlite3".to_string()));
+
+        let em = starlark_eval_in_env(&mut env, "ems['_io']").unwrap();
+        assert!(em.get_attr("is_builtin_default").unwrap().to_bool());
+    }
+
+    #[test]
+    fn test_extension_module() {
+        let em = starlark_ok("default_python_distribution().extension_module('_sqlite3')");
+        assert_eq!(em.get_type(), "PythonExtensionModule");
+        assert_eq!(em.get_attr("name").unwrap().to_str(), "_sqlite3");
+
+        let em = starlark_ok(
+            "default_python_distribution().extension_module('_sqlite3', variant='default')",
+        );
+        assert_eq!(em.get_attr("variant").unwrap().to_str(), "default");
+
+        let err = starlark_nok(
+            "default_python_distribution().extension_module('_sqlite3', variant='missing')",
+        );
+        assert!(err.message.contains(
+            "extension module _sqlite3 has no variant missing; available variants: default"
+        ));
+
+        let err = starlark_nok("default_python_distribution().extension_module('missing')");
+        assert!(err
+            .message
+            .contains("extension module missing does not exist"));
+    }
+
     #[test]
     fn test_resources_data() {
         let data_default = starlark_ok("default_python_distribution().resources_data()");
@@ -1007,6 +2335,44 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_compile_modules_no_match() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+
+        let root = temp_dir.path();
+        std::fs::write(root.join("foo.py"), "# foo")?;
+
+        let mut env = starlark_env();
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            &format!(
+                "resources = dist.read_package_root(\"{}\", packages=['foo'])",
+                root.display()
+            ),
+        )
+        .unwrap();
+
+        let resources = starlark_eval_in_env(
+            &mut env,
+            "dist.compile_modules_with_mypyc(resources, include=['bar.*'])",
+        )
+        .unwrap();
+        assert_eq!(resources.length().unwrap(), 1);
+        assert_eq!(
+            resources.into_iter().unwrap().next().unwrap().get_type(),
+            "PythonSourceModule"
+        );
+
+        assert!(starlark_eval_in_env(
+            &mut env,
+            "dist.compile_modules_with_cython(resources, include='foo')"
+        )
+        .is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_read_package_root_simple() -> Result<()> {
         let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
@@ -1054,4 +2420,77 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_read_package_root_detect_packages() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+
+        let root = temp_dir.path();
+        let templates_path = root.join("acme").join("app").join("templates");
+        std::fs::create_dir_all(&templates_path)?;
+        std::fs::write(root.join("acme").join("app").join("__init__.py"), "# app")?;
+        std::fs::write(templates_path.join("index.html"), "<html/>")?;
+        std::fs::write(root.join("setup.py"), "# setup")?;
+
+        let resources = starlark_ok(&format!(
+            "default_python_distribution().read_package_root(\"{}\")",
+            root.display()
+        ));
+
+        assert_eq!(resources.length().unwrap(), 2);
+
+        let mut it = resources.into_iter().unwrap();
+
+        let v = it.next().unwrap();
+        assert_eq!(v.get_type(), "PythonSourceModule");
+        v.downcast_apply(|x: &PythonSourceModule| {
+            assert_eq!(x.module.name, "acme.app");
+            assert!(x.module.is_package);
+        });
+
+        let v = it.next().unwrap();
+        assert_eq!(v.get_type(), "PythonResourceData");
+        v.downcast_apply(|x: &PythonResourceData| {
+            assert_eq!(x.data.package, "acme.app");
+            assert_eq!(x.data.name, "templates/index.html");
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_package_root_package_data() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+
+        let root = temp_dir.path();
+        let templates_path = root.join("myapp").join("templates");
+        std::fs::create_dir_all(&templates_path)?;
+        std::fs::write(root.join("myapp").join("__init__.py"), "# myapp")?;
+        std::fs::write(root.join("myapp").join("notes.txt"), "notes")?;
+        std::fs::write(templates_path.join("app.js"), "app")?;
+        std::fs::write(templates_path.join("app.js.map"), "map")?;
+
+        let resources = starlark_ok(&format!(
+            "default_python_distribution().read_package_root(\"{}\", package_data={{'myapp': ['templates/**', '!**/*.map']}})",
+            root.display()
+        ));
+
+        let names = resources
+            .into_iter()
+            .unwrap()
+            .filter(|v| v.get_type() == "PythonResourceData")
+            .map(|v| v.downcast_apply(|x: &PythonResourceData| x.data.name.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["templates/app.js"]);
+
+        let err = starlark_nok(&format!(
+            "default_python_distribution().read_package_root(\"{}\", package_data={{'myapp': ['a/***']}})",
+            root.display()
+        ));
+        assert!(err
+            .message
+            .starts_with("invalid package data pattern a/***"));
+
+        Ok(())
+    }
 }
@@ -4,6 +4,7 @@
 
 use {
     super::target::{BuildContext, BuildTarget, ResolvedTarget, RunMode},
+    crate::project_building::write_cargo_artifacts,
     crate::py_packaging::binary::PythonBinaryBuilder,
     anyhow::Result,
     slog::warn,
@@ -13,6 +14,7 @@ use {
     std::any::Any,
     std::cmp::Ordering,
     std::collections::HashMap,
+    std::ops::Deref,
 };
 
 pub struct PythonEmbeddedData {
@@ -57,15 +59,99 @@ impl BuildTarget for PythonEmbeddedData {
             context.output_path.display()
         );
 
+        context
+            .license_policy
+            .check(&context.logger, &self.exe.licensed_components())?;
+
         let embedded = self
             .exe
             .as_embedded_python_binary_data(&context.logger, &context.opt_level)?;
 
-        embedded.write_files(&context.output_path)?;
+        let paths = embedded.write_files(&context.output_path)?;
+
+        Ok(ResolvedTarget {
+            run_mode: RunMode::None,
+            output_path: context.output_path.clone(),
+            artifacts: paths.files(),
+        })
+    }
+}
+
+/// Embedded Python artifacts for consumption by an existing Rust crate.
+pub struct PythonCargoArtifacts {
+    pub exe: Box<dyn PythonBinaryBuilder>,
+}
+
+impl TypedValue for PythonCargoArtifacts {
+    immutable!();
+    any!();
+    not_supported!(binop);
+    not_supported!(container);
+    not_supported!(function);
+    not_supported!(get_hash);
+    not_supported!(to_int);
+
+    fn to_str(&self) -> String {
+        "PythonCargoArtifacts".to_string()
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "PythonCargoArtifacts"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+impl BuildTarget for PythonCargoArtifacts {
+    fn build(&mut self, context: &BuildContext) -> Result<ResolvedTarget> {
+        warn!(
+            &context.logger,
+            "writing Python artifacts for Cargo to {}",
+            context.output_path.display()
+        );
+
+        context
+            .license_policy
+            .check(&context.logger, &self.exe.licensed_components())?;
+
+        let artifacts = write_cargo_artifacts(
+            &context.logger,
+            self.exe.deref(),
+            &context.opt_level,
+            &context.output_path,
+        )?;
+
+        warn!(
+            &context.logger,
+            "include!(\"{}\") from your build script and call pyoxidizer_build()",
+            artifacts.build_rs_path.display()
+        );
+        warn!(
+            &context.logger,
+            "enable pyembed features: {}",
+            artifacts.pyembed_features.join(", ")
+        );
+        for (key, value) in &artifacts.cargo_env {
+            warn!(
+                &context.logger,
+                "set environment variable {}={}", key, value
+            );
+        }
 
         Ok(ResolvedTarget {
             run_mode: RunMode::None,
             output_path: context.output_path.clone(),
+            artifacts: artifacts.files,
         })
     }
 }
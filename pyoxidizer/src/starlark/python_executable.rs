@@ -3,17 +3,40 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
+    super::built_executable::BuiltExecutable,
+    super::config_version::check_removed_name,
     super::env::EnvironmentContext,
-    super::python_embedded_resources::PythonEmbeddedData,
+    super::jupyter_kernel::{is_valid_kernel_name, JupyterKernel},
+    super::python_embedded_resources::{PythonCargoArtifacts, PythonEmbeddedData},
     super::python_resource::{
-        PythonExtensionModule, PythonExtensionModuleFlavor, PythonResourceData, PythonSourceModule,
+        PythonExtensionModule, PythonExtensionModuleFlavor, PythonPackageDistributionResource,
+        PythonPackageLicense, PythonResourceData, PythonSourceModule,
     },
     super::target::{BuildContext, BuildTarget, ResolvedTarget, RunMode},
-    super::util::{optional_list_arg, required_bool_arg, required_type_arg},
-    crate::project_building::build_python_executable,
+    super::util::{
+        optional_bool_arg, optional_dict_arg, optional_list_arg, optional_str_arg,
+        optional_type_arg, required_bool_arg, required_list_arg, required_str_arg,
+        required_type_arg,
+    },
+    crate::analyze::{find_manylinux_policy, MANYLINUX_POLICIES},
+    crate::build_info::{git_commit, is_valid_module_name, BuildInfo},
+    crate::project_building::{
+        build_python_executable, exe_filename, RustBuildOptions, RUST_OPT_LEVELS,
+        WINDOWS_SUBSYSTEMS,
+    },
     crate::py_packaging::binary::PythonBinaryBuilder,
-    crate::py_packaging::resource::{BytecodeModule, BytecodeOptimizationLevel},
-    anyhow::{Context, Result},
+    crate::py_packaging::bytecode::{PycInvalidationMode, SourceTransforms},
+    crate::py_packaging::embedded_resource::{ConflictPolicy, ResourceConflict},
+    crate::py_packaging::filtering::INTERPRETER_MODULES,
+    crate::py_packaging::resource::{
+        BytecodeModule, BytecodeOptimizationLevel, DataLocation, ResourceData, SourceModule,
+    },
+    crate::reproducible::build_time,
+    crate::sbom::{render_sbom, SbomFormat},
+    crate::size_budget::parse_size,
+    crate::update::{write_update_manifest, UpdateManifest},
+    anyhow::{anyhow, Context, Result},
+    python_packed_resources::data::FileEmulation,
     slog::{info, warn},
     starlark::environment::Environment,
     starlark::values::{
@@ -26,15 +49,518 @@ use {
     },
     std::any::Any,
     std::cmp::Ordering,
-    std::collections::HashMap,
+    std::collections::{BTreeMap, BTreeSet, HashMap},
+    std::convert::TryFrom,
     std::io::Write,
     std::ops::Deref,
     std::path::{Path, PathBuf},
 };
 
+/// Resolve a size argument, either a number of bytes or a string like `60 MB`.
+fn size_arg(arg_name: &str, value: &Value) -> Result<u64, ValueError> {
+    let message = match value.get_type() {
+        "int" => match value.to_int()? {
+            size if size >= 0 => return Ok(size as u64),
+            size => format!("{} must not be negative; got {}", arg_name, size),
+        },
+        "string" => match parse_size(&value.to_string()) {
+            Ok(size) => return Ok(size),
+            Err(e) => e,
+        },
+        t => format!("{} must be an int or string; got {}", arg_name, t),
+    };
+
+    Err(RuntimeError {
+        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+        message,
+        label: format!("invalid {} value", arg_name),
+    }
+    .into())
+}
+
+/// Resolve an `optimize_level` argument to bytecode optimization levels.
+///
+/// The argument can be a single optimization level or a list of them.
+fn optimize_levels_arg(value: &Value) -> Result<Vec<BytecodeOptimizationLevel>, ValueError> {
+    let values = match value.get_type() {
+        "int" => vec![value.clone()],
+        "list" => value.into_iter()?.collect(),
+        t => {
+            return Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: format!("optimize_level must be an int or list of ints; got {}", t),
+                label: "invalid optimize_level value".to_string(),
+            }
+            .into());
+        }
+    };
+
+    let mut levels = Vec::new();
+
+    for value in values {
+        required_type_arg("optimize_level", "int", &value)?;
+
+        let level = match value.to_int().unwrap() {
+            0 => BytecodeOptimizationLevel::Zero,
+            1 => BytecodeOptimizationLevel::One,
+            2 => BytecodeOptimizationLevel::Two,
+            i => {
+                return Err(RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message: format!("optimize_level must be 0, 1, or 2: got {}", i),
+                    label: "invalid optimize_level value".to_string(),
+                }
+                .into());
+            }
+        };
+
+        if !levels.contains(&level) {
+            levels.push(level);
+        }
+    }
+
+    if levels.is_empty() {
+        return Err(RuntimeError {
+            code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            message: "optimize_level must not be empty".to_string(),
+            label: "invalid optimize_level value".to_string(),
+        }
+        .into());
+    }
+
+    Ok(levels)
+}
+
+/// Resolve an argument holding a list of Python module names.
+fn module_names_arg(arg_name: &str, value: &Value, label: &str) -> Result<Vec<String>, ValueError> {
+    required_list_arg(arg_name, "string", value)?;
+
+    let names: Vec<String> = value.into_iter()?.map(|x| x.to_string()).collect();
+
+    if let Some(name) = names.iter().find(|name| !is_valid_module_name(name)) {
+        return Err(RuntimeError {
+            code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            message: format!("{} is not a valid Python module name", name),
+            label: label.to_string(),
+        }
+        .into());
+    }
+
+    Ok(names)
+}
+
+/// Resolve a `file_emulation` argument to a `__file__` policy.
+///
+/// `None` means the interpreter's default policy applies.
+fn file_emulation_arg(value: &Value) -> Result<Option<FileEmulation>, ValueError> {
+    match optional_str_arg("file_emulation", value)? {
+        Some(policy) => Ok(Some(FileEmulation::try_from(policy.as_str()).map_err(
+            |e| RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: e,
+                label: "invalid file_emulation value".to_string(),
+            },
+        )?)),
+        None => Ok(None),
+    }
+}
+
+/// Describes which resources are first-party and how they are packaged.
+#[derive(Clone, Debug)]
+pub struct FirstPartyPolicy {
+    /// Packages whose resources are first-party.
+    pub packages: Vec<String>,
+
+    /// Directories first-party resources originate from.
+    pub paths: Vec<PathBuf>,
+
+    /// Whether first-party packages are imported from `paths` at run-time.
+    ///
+    /// If false, first-party modules are embedded as bytecode only.
+    pub filesystem: bool,
+
+    /// Optimization level to compile embedded first-party modules at.
+    pub optimize_level: BytecodeOptimizationLevel,
+}
+
+impl FirstPartyPolicy {
+    /// Whether a resource is first-party.
+    ///
+    /// `name` is the name of the module or package the resource belongs to and
+    /// `location` is where its data comes from.
+    pub fn is_first_party(&self, name: &str, location: &DataLocation) -> bool {
+        if self
+            .packages
+            .iter()
+            .any(|package| name == package || name.starts_with(&format!("{}.", package)))
+        {
+            return true;
+        }
+
+        match location {
+            DataLocation::Path(path) => self.paths.iter().any(|p| path.starts_with(p)),
+            DataLocation::Memory(_) => false,
+        }
+    }
+
+    /// `sys.path` entries first-party packages are imported from.
+    fn sys_paths(&self) -> Vec<String> {
+        // Backslashes would be interpreted as escapes by the generated Rust code.
+        self.paths
+            .iter()
+            .map(|p| p.display().to_string().replace('\\', "/"))
+            .collect()
+    }
+}
+
 /// Represents a builder for a Python executable.
 pub struct PythonExecutable {
     pub exe: Box<dyn PythonBinaryBuilder>,
+
+    /// Options for compiling the Rust project producing the executable.
+    pub build_options: RustBuildOptions,
+
+    /// Packages whose modules must only be embedded as bytecode.
+    pub bytecode_only_packages: Vec<String>,
+
+    /// Optimization level to compile modules of bytecode-only packages at.
+    pub bytecode_only_optimize_level: Option<BytecodeOptimizationLevel>,
+
+    /// How first-party resources are packaged, if they are distinguished.
+    pub first_party: Option<FirstPartyPolicy>,
+
+    /// Key to sign update manifests of built executables with.
+    pub update_signing_key: Option<PathBuf>,
+
+    /// Build information module to embed, if any.
+    pub build_info: Option<BuildInfo>,
+
+    /// Conflicts between resources added from different sources.
+    pub resource_conflicts: Vec<ResourceConflict>,
+
+    /// Modules Python code may import, if imports are restricted.
+    pub import_allow: Option<Vec<String>>,
+
+    /// Modules Python code may not import.
+    pub import_deny: Vec<String>,
+}
+
+impl Clone for PythonExecutable {
+    fn clone(&self) -> Self {
+        PythonExecutable {
+            exe: self.exe.clone_box(),
+            build_options: self.build_options.clone(),
+            bytecode_only_packages: self.bytecode_only_packages.clone(),
+            bytecode_only_optimize_level: self.bytecode_only_optimize_level,
+            first_party: self.first_party.clone(),
+            update_signing_key: self.update_signing_key.clone(),
+            build_info: self.build_info.clone(),
+            resource_conflicts: self.resource_conflicts.clone(),
+            import_allow: self.import_allow.clone(),
+            import_deny: self.import_deny.clone(),
+        }
+    }
+}
+
+impl PythonExecutable {
+    /// Whether a module or resource package is in a bytecode-only package.
+    fn is_bytecode_only(&self, name: &str) -> bool {
+        self.bytecode_only_packages
+            .iter()
+            .any(|package| name == package || name.starts_with(&format!("{}.", package)))
+    }
+
+    /// Obtain the first-party policy applying to a resource, if it is first-party.
+    fn first_party_policy(&self, name: &str, location: &DataLocation) -> Option<&FirstPartyPolicy> {
+        self.first_party
+            .as_ref()
+            .filter(|policy| policy.is_first_party(name, location))
+    }
+
+    /// Obtain the modules Python code may import, if imports are restricted.
+    ///
+    /// Modules the interpreter needs to start are always allowed.
+    fn resolve_import_allow(&self, exe: &dyn PythonBinaryBuilder) -> Option<Vec<String>> {
+        self.import_allow.as_ref().map(|modules| {
+            let mut modules = modules.clone();
+            modules.extend(INTERPRETER_MODULES.iter().map(|m| m.to_string()));
+            modules.extend(
+                exe.extension_modules()
+                    .values()
+                    .filter(|m| m.required)
+                    .map(|m| m.module.clone()),
+            );
+            modules
+        })
+    }
+
+    /// Obtain the binary builder with the first-party policy, import restrictions, and build information applied.
+    fn resolve_exe(&self) -> Box<dyn PythonBinaryBuilder> {
+        let mut exe = self.exe.clone_box();
+
+        if self.import_allow.is_some() || !self.import_deny.is_empty() {
+            let allow = self.resolve_import_allow(exe.as_ref());
+            exe.restrict_imports(
+                allow.as_ref().map(|modules| modules.as_slice()),
+                &self.import_deny,
+            );
+        }
+
+        if let Some(policy) = &self.first_party {
+            if policy.filesystem {
+                exe.import_packages_from_filesystem(&policy.packages, &policy.sys_paths());
+            }
+        }
+
+        if let Some(build_info) = &self.build_info {
+            build_info.add_to_binary(exe.as_mut(), build_time());
+        }
+
+        exe
+    }
+
+    /// Log resource conflicts that occurred while adding resources.
+    fn report_resource_conflicts(&mut self, logger: &slog::Logger) {
+        for conflict in self.exe.take_resource_conflicts() {
+            warn!(
+                logger,
+                "{}; {}",
+                conflict,
+                if conflict.replaced {
+                    "the new one replaces it"
+                } else {
+                    "ignoring the new one"
+                }
+            );
+            self.resource_conflicts.push(conflict);
+        }
+    }
+
+    /// Verify no resources conflict if conflicts are errors.
+    pub fn verify_resource_conflicts(&self) -> Result<()> {
+        if self.exe.resource_conflict_policy() == ConflictPolicy::Error
+            && !self.resource_conflicts.is_empty()
+        {
+            Err(anyhow!(
+                "resources added from different sources conflict:\n{}",
+                self.resource_conflicts
+                    .iter()
+                    .map(|c| c.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Verify no Python source is embedded for bytecode-only packages.
+    pub fn verify_bytecode_only(&self) -> Result<()> {
+        let mut violations = Vec::new();
+
+        for name in self.exe.source_modules().keys() {
+            if self.is_bytecode_only(name) {
+                violations.push(format!("{} (source module)", name));
+            }
+        }
+
+        for (name, module) in self.exe.bytecode_modules() {
+            if self.is_bytecode_only(&name) && !module.source_stripped {
+                violations.push(format!("{} (bytecode with source)", name));
+            }
+        }
+
+        for (package, resources) in self.exe.resources() {
+            if self.is_bytecode_only(&package) {
+                for name in resources.keys() {
+                    if name.ends_with(".py") {
+                        violations.push(format!("{}:{} (resource)", package, name));
+                    }
+                }
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Python source embedded for bytecode-only packages: {}",
+                violations.join(", ")
+            ))
+        }
+    }
+}
+
+/// Pass a resource through the registered resource hooks.
+///
+/// Each hook is called with a resource and returns the resource to add in its
+/// place, a list of resources, or `None` to exclude it. Hooks are applied in
+/// the order they were registered.
+#[allow(clippy::ptr_arg)]
+fn apply_resource_hooks(
+    env: &Environment,
+    call_stack: &Vec<(String, String)>,
+    resource: &Value,
+) -> Result<Vec<Value>, ValueError> {
+    let context = env.get("CONTEXT").expect("CONTEXT not set");
+    let hooks = context.downcast_apply(|x: &EnvironmentContext| x.resource_hooks.clone());
+
+    let mut resources = vec![resource.clone()];
+
+    for hook in hooks {
+        let mut hooked = Vec::new();
+
+        for resource in resources {
+            let res = hook.call(
+                call_stack,
+                env.clone(),
+                vec![resource],
+                HashMap::new(),
+                None,
+                None,
+            )?;
+
+            match res.get_type() {
+                "NoneType" => {}
+                "list" => hooked.extend(res.into_iter()?),
+                _ => hooked.push(res),
+            }
+        }
+
+        resources = hooked;
+    }
+
+    Ok(resources)
+}
+
+/// Add resources to an executable after passing them through resource hooks.
+///
+/// Every method adding resources to a `PythonExecutable` goes through here.
+/// Hooks run before borrowing the executable so they can't observe it
+/// mid-update.
+#[allow(clippy::ptr_arg, clippy::too_many_arguments)]
+fn add_hooked_python_resources(
+    env: &Environment,
+    call_stack: &Vec<(String, String)>,
+    exe: &Value,
+    resources: Vec<Value>,
+    add_source_module: &Value,
+    add_bytecode_module: &Value,
+    optimize_level: &Value,
+    file_emulation: &Value,
+) -> ValueResult {
+    let mut hooked = Vec::new();
+    for resource in resources {
+        hooked.extend(apply_resource_hooks(env, call_stack, &resource)?);
+    }
+    let resources = Value::from(hooked);
+
+    exe.downcast_apply_mut(|exe: &mut PythonExecutable| {
+        exe.starlark_add_python_resources(
+            env,
+            &resources,
+            add_source_module,
+            add_bytecode_module,
+            optimize_level,
+            file_emulation,
+        )
+    })
+}
+
+/// Pass the resources an executable was created with through resource hooks.
+///
+/// Embedded modules, resource data, and extension modules are removed and
+/// added again as the hooks direct.
+#[allow(clippy::ptr_arg)]
+pub fn apply_resource_hooks_to_embedded(
+    env: &Environment,
+    call_stack: &Vec<(String, String)>,
+    exe: &Value,
+) -> ValueResult {
+    let (sources, bytecodes, optimize_levels, resources, extension_modules) =
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            (
+                exe.exe.source_modules(),
+                exe.exe.bytecode_modules(),
+                exe.exe.bytecode_optimize_levels(),
+                exe.exe.resources(),
+                exe.exe.extension_modules(),
+            )
+        });
+
+    let names = sources
+        .keys()
+        .chain(bytecodes.keys())
+        .chain(resources.keys())
+        .chain(extension_modules.keys())
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    exe.downcast_apply_mut(|exe: &mut PythonExecutable| exe.exe.remove_modules(&names));
+
+    for name in &names {
+        let module = match (sources.get(name), bytecodes.get(name)) {
+            (Some(module), _) => module.clone(),
+            (None, Some(module)) => SourceModule {
+                name: module.name.clone(),
+                source: module.source.clone(),
+                is_package: module.is_package,
+            },
+            (None, None) => continue,
+        };
+
+        let optimize_level =
+            Value::from(optimize_levels.get(name).map_or_else(Vec::new, |levels| {
+                levels
+                    .iter()
+                    .map(|level| Value::new(i64::from(i32::from(*level))))
+                    .collect()
+            }));
+
+        add_hooked_python_resources(
+            env,
+            call_stack,
+            exe,
+            vec![Value::new(PythonSourceModule { module })],
+            &Value::new(sources.contains_key(name)),
+            &Value::new(bytecodes.contains_key(name)),
+            &optimize_level,
+            &Value::new(None),
+        )?;
+    }
+
+    let resources = resources
+        .into_iter()
+        .flat_map(|(package, resources)| {
+            resources.into_iter().map(move |(name, data)| {
+                Value::new(PythonResourceData {
+                    data: ResourceData {
+                        package: package.clone(),
+                        name,
+                        data: DataLocation::Memory(data),
+                    },
+                })
+            })
+        })
+        .chain(extension_modules.into_iter().map(|(_, em)| {
+            Value::new(PythonExtensionModule {
+                em: PythonExtensionModuleFlavor::Distribution(em),
+            })
+        }))
+        .collect();
+
+    add_hooked_python_resources(
+        env,
+        call_stack,
+        exe,
+        resources,
+        &Value::new(true),
+        &Value::new(true),
+        &Value::new(0),
+        &Value::new(None),
+    )
 }
 
 impl TypedValue for PythonExecutable {
@@ -65,18 +591,27 @@ impl TypedValue for PythonExecutable {
 
 impl BuildTarget for PythonExecutable {
     fn build(&mut self, context: &BuildContext) -> Result<ResolvedTarget> {
+        context
+            .license_policy
+            .check(&context.logger, &self.exe.licensed_components())?;
+        self.verify_bytecode_only()?;
+        self.verify_resource_conflicts()?;
+        let exe = self.resolve_exe();
+        let resources_path = exe.config().resources_file_relative_path()?;
+
         // Build an executable by writing out a temporary Rust project
         // and building it.
-        let (exe_name, exe_data) = build_python_executable(
+        let built = build_python_executable(
             &context.logger,
-            &self.exe.name(),
-            self.exe.deref(),
+            &exe.name(),
+            exe.deref(),
             &context.target_triple,
             &context.opt_level,
             context.release,
+            &self.build_options,
         )?;
 
-        let dest_path = context.output_path.join(exe_name);
+        let dest_path = context.output_path.join(&built.filename);
         warn!(
             &context.logger,
             "writing executable to {}",
@@ -84,93 +619,232 @@ impl BuildTarget for PythonExecutable {
         );
         let mut fh = std::fs::File::create(&dest_path)
             .context(format!("creating {}", dest_path.display()))?;
-        fh.write_all(&exe_data)
+        fh.write_all(&built.data)
             .context(format!("writing {}", dest_path.display()))?;
 
         crate::app_packaging::resource::set_executable(&mut fh)
             .context("making binary executable")?;
 
+        let mut artifacts = vec![dest_path.clone()];
+
+        built
+            .debug_files
+            .write_to_path(&context.output_path)
+            .context("writing debug info")?;
+        artifacts.extend(
+            built
+                .debug_files
+                .entries()
+                .map(|(path, _)| context.output_path.join(path)),
+        );
+
+        if let (Some(path), Some(data)) = (resources_path, built.resources_data) {
+            let resources_path = context.output_path.join(path);
+            warn!(
+                &context.logger,
+                "writing resources to {}",
+                resources_path.display()
+            );
+            if let Some(parent) = resources_path.parent() {
+                std::fs::create_dir_all(parent)
+                    .context(format!("creating {}", parent.display()))?;
+            }
+            std::fs::write(&resources_path, &data)
+                .context(format!("writing {}", resources_path.display()))?;
+            artifacts.push(resources_path);
+        }
+
+        // Shared libraries are loaded from the directory of the executable.
+        let extra_files = exe.extra_install_files(&context.logger, "")?;
+        extra_files
+            .write_to_path(&context.output_path)
+            .context("writing shared libraries")?;
+        artifacts.extend(
+            extra_files
+                .entries()
+                .map(|(path, _)| context.output_path.join(path)),
+        );
+
+        if let Some(format) = self.build_options.sbom {
+            let sbom_path = context.output_path.join(format.filename(&self.exe.name()));
+            warn!(&context.logger, "writing SBOM to {}", sbom_path.display());
+            let data = render_sbom(format, &self.exe.name(), self.exe.deref())?;
+            std::fs::write(&sbom_path, data).context(format!("writing {}", sbom_path.display()))?;
+            artifacts.push(sbom_path);
+        }
+
+        if let (Some(update), Some(key_path)) = (&exe.config().update, &self.update_signing_key) {
+            let manifest_path = write_update_manifest(
+                &context.output_path,
+                &artifacts,
+                &exe.name(),
+                &update.version,
+                &context.target_triple,
+                &update.public_key,
+                key_path,
+            )?;
+            warn!(
+                &context.logger,
+                "wrote signed update manifest to {}",
+                manifest_path.display()
+            );
+            artifacts.push(manifest_path);
+            artifacts.push(
+                context
+                    .output_path
+                    .join(UpdateManifest::signature_filename(&exe.name())),
+            );
+        }
+
         Ok(ResolvedTarget {
             run_mode: RunMode::Path { path: dest_path },
             output_path: context.output_path.clone(),
+            artifacts,
         })
     }
 }
 
 // Starlark functions.
 impl PythonExecutable {
-    /// PythonExecutable.add_module_source(module)
-    pub fn starlark_add_module_source(&mut self, env: &Environment, module: &Value) -> ValueResult {
+    /// PythonExecutable.add_module_source(module, file_emulation=None)
+    pub fn starlark_add_module_source(
+        &mut self,
+        env: &Environment,
+        module: &Value,
+        file_emulation: &Value,
+    ) -> ValueResult {
         required_type_arg("module", "PythonSourceModule", &module)?;
+        let file_emulation = file_emulation_arg(&file_emulation)?;
 
         let context = env.get("CONTEXT").expect("CONTEXT not set");
         let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
 
         let m = module.downcast_apply(|m: &PythonSourceModule| m.module.clone());
+
+        if self.is_bytecode_only(&m.name) {
+            info!(
+                &logger,
+                "not embedding source module {} from bytecode-only package", m.name
+            );
+            return Ok(Value::new(None));
+        }
+
+        if self.first_party_policy(&m.name, &m.source).is_some() {
+            info!(
+                &logger,
+                "not embedding source module {} from first-party package", m.name
+            );
+            return Ok(Value::new(None));
+        }
+
         info!(&logger, "adding embedded source module {}", m.name);
         self.exe.add_source_module(&m);
+        self.report_resource_conflicts(&logger);
+        if let Some(policy) = file_emulation {
+            self.exe.set_file_emulation(&m.name, policy);
+        }
 
         Ok(Value::new(None))
     }
 
-    /// PythonExecutable.add_module_bytecode(module, optimize_level=0)
+    /// PythonExecutable.add_module_bytecode(module, optimize_level=0, file_emulation=None)
     pub fn starlark_add_module_bytecode(
         &mut self,
         env: &Environment,
         module: &Value,
         optimize_level: &Value,
+        file_emulation: &Value,
     ) -> ValueResult {
         required_type_arg("module", "PythonSourceModule", &module)?;
-        required_type_arg("optimize_level", "int", &optimize_level)?;
+        let optimize_levels = optimize_levels_arg(&optimize_level)?;
+        let file_emulation = file_emulation_arg(&file_emulation)?;
 
         let context = env.get("CONTEXT").expect("CONTEXT not set");
         let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
 
-        let optimize_level = optimize_level.to_int().unwrap();
+        let m = module.downcast_apply(|m: &PythonSourceModule| m.module.clone());
+        let first_party = self.first_party_policy(&m.name, &m.source).cloned();
+
+        if first_party
+            .as_ref()
+            .map_or(false, |policy| policy.filesystem)
+        {
+            info!(
+                &logger,
+                "not embedding bytecode module {} imported from filesystem", m.name
+            );
+            return Ok(Value::new(None));
+        }
 
-        let optimize_level = match optimize_level {
-            0 => BytecodeOptimizationLevel::Zero,
-            1 => BytecodeOptimizationLevel::One,
-            2 => BytecodeOptimizationLevel::Two,
-            i => {
-                return Err(RuntimeError {
-                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
-                    message: format!("optimize_level must be 0, 1, or 2: got {}", i),
-                    label: "invalid optimize_level value".to_string(),
-                }
-                .into());
-            }
+        let source_stripped = self.is_bytecode_only(&m.name) || first_party.is_some();
+        let optimize_levels = match (first_party, self.bytecode_only_optimize_level) {
+            (Some(policy), _) => vec![policy.optimize_level],
+            (None, Some(level)) if source_stripped => vec![level],
+            _ => optimize_levels,
         };
 
-        let m = module.downcast_apply(|m: &PythonSourceModule| m.module.clone());
         info!(&logger, "adding embedded bytecode module {}", m.name);
-        self.exe.add_bytecode_module(&BytecodeModule {
-            name: m.name.clone(),
-            source: m.source.clone(),
-            optimize_level,
-            is_package: m.is_package,
-        });
+        for optimize_level in optimize_levels {
+            self.exe.add_bytecode_module(&BytecodeModule {
+                name: m.name.clone(),
+                source: m.source.clone(),
+                optimize_level,
+                is_package: m.is_package,
+                source_stripped,
+                invalidation_mode: PycInvalidationMode::default(),
+            });
+        }
+        self.report_resource_conflicts(&logger);
+        if let Some(policy) = file_emulation {
+            self.exe.set_file_emulation(&m.name, policy);
+        }
 
         Ok(Value::new(None))
     }
 
-    /// PythonExecutable.add_resource_data(resource)
+    /// PythonExecutable.add_resource_data(resource, file_emulation=None)
     pub fn starlark_add_resource_data(
         &mut self,
         env: &Environment,
         resource: &Value,
+        file_emulation: &Value,
     ) -> ValueResult {
         required_type_arg("resource", "PythonResourceData", &resource)?;
+        let file_emulation = file_emulation_arg(&file_emulation)?;
 
         let context = env.get("CONTEXT").expect("CONTEXT not set");
         let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
 
         let r = resource.downcast_apply(|r: &PythonResourceData| r.data.clone());
+
+        if r.name.ends_with(".py") && self.is_bytecode_only(&r.package) {
+            info!(
+                &logger,
+                "not embedding resource data {}:{} from bytecode-only package", r.package, r.name
+            );
+            return Ok(Value::new(None));
+        }
+
+        if self
+            .first_party_policy(&r.package, &r.data)
+            .map_or(false, |policy| policy.filesystem)
+        {
+            info!(
+                &logger,
+                "not embedding resource data {}:{} imported from filesystem", r.package, r.name
+            );
+            return Ok(Value::new(None));
+        }
+
         info!(
             &logger,
             "adding embedded resource data {}:{}", r.package, r.name
         );
         self.exe.add_resource(&r);
+        self.report_resource_conflicts(&logger);
+        if let Some(policy) = file_emulation {
+            self.exe.set_file_emulation(&r.package, policy);
+        }
 
         Ok(Value::new(None))
     }
@@ -200,11 +874,12 @@ impl PythonExecutable {
                 self.exe.add_extension_module_data(&m);
             }
         }
+        self.report_resource_conflicts(&logger);
 
         Ok(Value::new(None))
     }
 
-    /// PythonExecutable.add_python_resource(resource, add_source_module=true, add_bytecode_module=true, optimize_level=0)
+    /// PythonExecutable.add_python_resource(resource, add_source_module=true, add_bytecode_module=true, optimize_level=0, file_emulation=None)
     pub fn starlark_add_python_resource(
         &mut self,
         env: &Environment,
@@ -212,27 +887,55 @@ impl PythonExecutable {
         add_source_module: &Value,
         add_bytecode_module: &Value,
         optimize_level: &Value,
+        file_emulation: &Value,
     ) -> ValueResult {
         let add_source_module = required_bool_arg("add_source_module", &add_source_module)?;
         let add_bytecode_module = required_bool_arg("add_bytecode_module", &add_bytecode_module)?;
-        required_type_arg("optimize_level", "int", &optimize_level)?;
+        optimize_levels_arg(&optimize_level)?;
+        file_emulation_arg(&file_emulation)?;
 
         match resource.get_type() {
             "PythonSourceModule" => {
                 if add_source_module {
-                    self.starlark_add_module_source(env, resource)?;
+                    self.starlark_add_module_source(env, resource, file_emulation)?;
                 }
                 if add_bytecode_module {
-                    self.starlark_add_module_bytecode(env, resource, optimize_level)?;
+                    self.starlark_add_module_bytecode(
+                        env,
+                        resource,
+                        optimize_level,
+                        file_emulation,
+                    )?;
                 }
 
                 Ok(Value::new(None))
             }
             "PythonBytecodeModule" => {
-                self.starlark_add_module_bytecode(env, resource, optimize_level)
+                self.starlark_add_module_bytecode(env, resource, optimize_level, file_emulation)
             }
-            "PythonResourceData" => self.starlark_add_resource_data(env, resource),
+            "PythonResourceData" => self.starlark_add_resource_data(env, resource, file_emulation),
             "PythonExtensionModule" => self.starlark_add_extension_module(env, resource),
+            "PythonPackageLicense" => {
+                let license = resource.downcast_apply(|l: &PythonPackageLicense| l.license.clone());
+                self.exe.add_package_license(&license);
+
+                Ok(Value::new(None))
+            }
+            "PythonPackageDistributionResource" => {
+                let context = env.get("CONTEXT").expect("CONTEXT not set");
+                let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
+
+                let r = resource
+                    .downcast_apply(|r: &PythonPackageDistributionResource| r.resource.clone());
+                info!(
+                    &logger,
+                    "adding embedded distribution resource {}",
+                    r.full_name()
+                );
+                self.exe.add_distribution_resource(&r);
+
+                Ok(Value::new(None))
+            }
             _ => Err(RuntimeError {
                 code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
                 message: "resource argument must be a Python resource type".to_string(),
@@ -242,7 +945,7 @@ impl PythonExecutable {
         }
     }
 
-    /// PythonExecutable.add_python_resources(resources, add_source_module=true, add_bytecode_module=true, optimize_level=0)
+    /// PythonExecutable.add_python_resources(resources, add_source_module=true, add_bytecode_module=true, optimize_level=0, file_emulation=None)
     pub fn starlark_add_python_resources(
         &mut self,
         env: &Environment,
@@ -250,10 +953,12 @@ impl PythonExecutable {
         add_source_module: &Value,
         add_bytecode_module: &Value,
         optimize_level: &Value,
+        file_emulation: &Value,
     ) -> ValueResult {
         required_bool_arg("add_source_module", &add_source_module)?;
         required_bool_arg("add_bytecode_module", &add_bytecode_module)?;
-        required_type_arg("optimize_level", "int", &optimize_level)?;
+        optimize_levels_arg(&optimize_level)?;
+        file_emulation_arg(&file_emulation)?;
 
         for resource in resources.into_iter()? {
             self.starlark_add_python_resource(
@@ -262,196 +967,1660 @@ impl PythonExecutable {
                 add_source_module,
                 add_bytecode_module,
                 optimize_level,
+                file_emulation,
             )?;
         }
 
         Ok(Value::new(None))
     }
 
-    /// PythonExecutable.to_embedded_data()
-    pub fn starlark_to_embedded_data(&self) -> ValueResult {
-        Ok(Value::new(PythonEmbeddedData {
-            exe: self.exe.clone_box(),
-        }))
-    }
-
-    /// PythonExecutable.filter_resources_from_files(files=None, glob_files=None)
-    pub fn starlark_filter_resources_from_files(
+    /// PythonExecutable.build_options(opt_level=None, lto=None, strip=None, split_debug_info=None, rustflags=None, cargo_features=None, sbom=None, windows_subsystem=None)
+    #[allow(clippy::too_many_arguments)]
+    pub fn starlark_build_options(
         &mut self,
-        env: &Environment,
-        files: &Value,
-        glob_files: &Value,
+        opt_level: &Value,
+        lto: &Value,
+        strip: &Value,
+        split_debug_info: &Value,
+        rustflags: &Value,
+        cargo_features: &Value,
+        sbom: &Value,
+        windows_subsystem: &Value,
     ) -> ValueResult {
-        optional_list_arg("files", "string", &files)?;
-        optional_list_arg("glob_files", "string", &glob_files)?;
+        let opt_level = optional_str_arg("opt_level", &opt_level)?;
+        let sbom = optional_str_arg("sbom", &sbom)?;
+        let windows_subsystem = optional_str_arg("windows_subsystem", &windows_subsystem)?;
+        let lto = optional_bool_arg("lto", &lto)?;
+        let strip = optional_bool_arg("strip", &strip)?;
+        let split_debug_info = optional_bool_arg("split_debug_info", &split_debug_info)?;
+        optional_list_arg("rustflags", "string", &rustflags)?;
+        optional_list_arg("cargo_features", "string", &cargo_features)?;
+
+        if let Some(level) = opt_level {
+            if !RUST_OPT_LEVELS.contains(&level.as_str()) {
+                return Err(RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message: format!(
+                        "opt_level must be one of {}; got {}",
+                        RUST_OPT_LEVELS.join(", "),
+                        level
+                    ),
+                    label: "invalid opt_level value".to_string(),
+                }
+                .into());
+            }
 
-        let files = match files.get_type() {
-            "list" => files
-                .into_iter()?
-                .map(|x| PathBuf::from(x.to_string()))
-                .collect(),
-            "NoneType" => Vec::new(),
-            _ => panic!("type should have been validated above"),
-        };
+            self.build_options.opt_level = Some(level);
+        }
 
-        let glob_files = match glob_files.get_type() {
-            "list" => glob_files.into_iter()?.map(|x| x.to_string()).collect(),
-            "NoneType" => Vec::new(),
-            _ => panic!("type should have been validated above"),
-        };
+        if lto.is_some() {
+            self.build_options.lto = lto;
+        }
 
-        let files_refs = files.iter().map(|x| x.as_ref()).collect::<Vec<&Path>>();
-        let glob_files_refs = glob_files.iter().map(|x| x.as_ref()).collect::<Vec<&str>>();
+        if let Some(strip) = strip {
+            self.build_options.strip = strip;
+        }
 
-        let context = env.get("CONTEXT").expect("CONTEXT not defined");
-        let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
+        if let Some(split_debug_info) = split_debug_info {
+            self.build_options.split_debug_info = split_debug_info;
+        }
 
-        self.exe
-            .filter_resources_from_files(&logger, &files_refs, &glob_files_refs)
-            .or_else(|e| {
-                Err(RuntimeError {
-                    code: "RUNTIME_ERROR",
-                    message: e.to_string(),
-                    label: "filter_from_files()".to_string(),
-                }
-                .into())
-            })?;
+        if rustflags.get_type() == "list" {
+            self.build_options.rustflags = rustflags.into_iter()?.map(|x| x.to_string()).collect();
+        }
 
-        Ok(Value::new(None))
-    }
-}
+        if cargo_features.get_type() == "list" {
+            self.build_options.cargo_features =
+                cargo_features.into_iter()?.map(|x| x.to_string()).collect();
+        }
 
-starlark_module! { python_executable_env =>
-    #[allow(non_snake_case, clippy::ptr_arg)]
-    PythonExecutable.add_module_source(env env, this, module) {
-        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
-            exe.starlark_add_module_source(&env, &module)
-        })
-    }
+        if let Some(sbom) = sbom {
+            self.build_options.sbom =
+                Some(
+                    SbomFormat::try_from(sbom.as_str()).map_err(|e| RuntimeError {
+                        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                        message: e,
+                        label: "invalid sbom value".to_string(),
+                    })?,
+                );
+        }
+
+        if let Some(subsystem) = windows_subsystem {
+            if !WINDOWS_SUBSYSTEMS.contains(&subsystem.as_str()) {
+                return Err(RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message: format!(
+                        "windows_subsystem must be one of {}; got {}",
+                        WINDOWS_SUBSYSTEMS.join(", "),
+                        subsystem
+                    ),
+                    label: "invalid windows_subsystem value".to_string(),
+                }
+                .into());
+            }
+
+            self.build_options.windows_subsystem = Some(subsystem);
+        }
+
+        Ok(Value::new(None))
+    }
+
+    /// PythonExecutable.set_rust_sources(main_rs_template=None, sources_path=None)
+    pub fn starlark_set_rust_sources(
+        &mut self,
+        env: &Environment,
+        main_rs_template: &Value,
+        sources_path: &Value,
+    ) -> ValueResult {
+        let main_rs_template = optional_str_arg("main_rs_template", &main_rs_template)?;
+        let sources_path = optional_str_arg("sources_path", &sources_path)?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not set");
+        let cwd = context.downcast_apply(|x: &EnvironmentContext| x.cwd.clone());
+
+        if let Some(path) = main_rs_template {
+            let path = cwd.join(path);
+
+            if !path.is_file() {
+                return Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: format!("main.rs template {} does not exist", path.display()),
+                    label: "set_rust_sources()".to_string(),
+                }
+                .into());
+            }
+
+            self.build_options.main_rs_template = Some(path);
+        }
+
+        if let Some(path) = sources_path {
+            let path = cwd.join(path);
+
+            if !path.is_dir() {
+                return Err(RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: format!("Rust sources directory {} does not exist", path.display()),
+                    label: "set_rust_sources()".to_string(),
+                }
+                .into());
+            }
+
+            self.build_options.rust_sources_path = Some(path);
+        }
+
+        Ok(Value::new(None))
+    }
+
+    /// PythonExecutable.set_update_signing_key(path)
+    pub fn starlark_set_update_signing_key(
+        &mut self,
+        env: &Environment,
+        path: &Value,
+    ) -> ValueResult {
+        let path = required_str_arg("path", &path)?;
+
+        if self.exe.config().update.is_none() {
+            return Err(RuntimeError {
+                code: "PYOXIDIZER_BUILD",
+                message: "executable has no update channel; define update_url, update_public_key, and update_version in PythonInterpreterConfig()".to_string(),
+                label: "set_update_signing_key()".to_string(),
+            }
+            .into());
+        }
+
+        let context = env.get("CONTEXT").expect("CONTEXT not set");
+        let cwd = context.downcast_apply(|x: &EnvironmentContext| x.cwd.clone());
+
+        self.update_signing_key = Some(cwd.join(path));
+
+        Ok(Value::new(None))
+    }
+
+    /// PythonExecutable.set_resource_conflict_policy(policy)
+    pub fn starlark_set_resource_conflict_policy(&mut self, policy: &Value) -> ValueResult {
+        let policy = required_str_arg("policy", &policy)?;
+
+        let policy = ConflictPolicy::try_from(policy.as_str()).map_err(|e| RuntimeError {
+            code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            message: e,
+            label: "set_resource_conflict_policy()".to_string(),
+        })?;
+
+        self.exe.set_resource_conflict_policy(policy);
+
+        Ok(Value::new(None))
+    }
+
+    /// PythonExecutable.set_build_info(module_name="__pyoxidizer__", git_commit=True, variables=None)
+    pub fn starlark_set_build_info(
+        &mut self,
+        env: &Environment,
+        module_name: &Value,
+        git_commit_arg: &Value,
+        variables: &Value,
+    ) -> ValueResult {
+        let module_name = required_str_arg("module_name", &module_name)?;
+        let record_git_commit = required_bool_arg("git_commit", &git_commit_arg)?;
+        optional_dict_arg("variables", "string", "string", &variables)?;
+
+        if !is_valid_module_name(&module_name) {
+            return Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: format!("{} is not a valid Python module name", module_name),
+                label: "set_build_info()".to_string(),
+            }
+            .into());
+        }
+
+        let variables = match variables.get_type() {
+            "dict" => {
+                let mut res = BTreeMap::new();
+
+                for k in variables.into_iter()? {
+                    let v = variables.at(k.clone())?.to_string();
+                    res.insert(k.to_string(), v);
+                }
+
+                res
+            }
+            _ => BTreeMap::new(),
+        };
+
+        let context = env.get("CONTEXT").expect("CONTEXT not set");
+        let (cwd, target_triple) = context.downcast_apply(|x: &EnvironmentContext| {
+            (x.cwd.clone(), x.build_target_triple.clone())
+        });
+
+        self.build_info = Some(BuildInfo {
+            module_name,
+            git_commit: if record_git_commit {
+                git_commit(&cwd)
+            } else {
+                None
+            },
+            target_triple,
+            variables,
+        });
+
+        Ok(Value::new(None))
+    }
+
+    /// PythonExecutable.set_linux_compat_level(level, fail=True)
+    pub fn starlark_set_linux_compat_level(&mut self, level: &Value, fail: &Value) -> ValueResult {
+        let level = required_str_arg("level", &level)?;
+        let fail = required_bool_arg("fail", &fail)?;
+
+        if find_manylinux_policy(&level).is_none() {
+            return Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: format!(
+                    "level must be one of {}; got {}",
+                    MANYLINUX_POLICIES
+                        .iter()
+                        .map(|p| p.name)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    level
+                ),
+                label: "invalid level value".to_string(),
+            }
+            .into());
+        }
+
+        self.build_options.linux_compat_level = Some(level);
+        self.build_options.linux_compat_fail = fail;
+
+        Ok(Value::new(None))
+    }
+
+    /// PythonExecutable.set_size_budget(size=None, packages=None)
+    pub fn starlark_set_size_budget(&mut self, size: &Value, packages: &Value) -> ValueResult {
+        if size.get_type() != "NoneType" {
+            self.build_options.size_budget.total = Some(size_arg("size", &size)?);
+        }
+
+        match packages.get_type() {
+            "NoneType" => {}
+            "dict" => {
+                for k in packages.into_iter()? {
+                    let v = size_arg("packages", &packages.at(k.clone())?)?;
+                    self.build_options
+                        .size_budget
+                        .packages
+                        .insert(k.to_string(), v);
+                }
+            }
+            t => {
+                return Err(RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message: format!("packages must be a dict; got {}", t),
+                    label: "invalid packages value".to_string(),
+                }
+                .into())
+            }
+        }
+
+        Ok(Value::new(None))
+    }
+
+    /// PythonExecutable.set_bytecode_only_packages(packages, optimize_level=None)
+    pub fn starlark_set_bytecode_only_packages(
+        &mut self,
+        packages: &Value,
+        optimize_level: &Value,
+    ) -> ValueResult {
+        required_list_arg("packages", "string", &packages)?;
+        optional_type_arg("optimize_level", "int", &optimize_level)?;
+
+        self.bytecode_only_packages = packages.into_iter()?.map(|x| x.to_string()).collect();
+
+        self.bytecode_only_optimize_level = match optimize_level.get_type() {
+            "int" => Some(match optimize_level.to_int().unwrap() {
+                0 => BytecodeOptimizationLevel::Zero,
+                1 => BytecodeOptimizationLevel::One,
+                2 => BytecodeOptimizationLevel::Two,
+                i => {
+                    return Err(RuntimeError {
+                        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                        message: format!("optimize_level must be 0, 1, or 2: got {}", i),
+                        label: "invalid optimize_level value".to_string(),
+                    }
+                    .into());
+                }
+            }),
+            _ => None,
+        };
+
+        Ok(Value::new(None))
+    }
+
+    /// PythonExecutable.add_import_allowlist(modules)
+    pub fn starlark_add_import_allowlist(&mut self, modules: &Value) -> ValueResult {
+        let modules = module_names_arg("modules", &modules, "add_import_allowlist()")?;
+
+        self.import_allow
+            .get_or_insert_with(Vec::new)
+            .extend(modules);
+
+        Ok(Value::new(None))
+    }
+
+    /// PythonExecutable.deny_import(modules)
+    pub fn starlark_deny_import(&mut self, modules: &Value) -> ValueResult {
+        let modules = module_names_arg("modules", &modules, "deny_import()")?;
+
+        self.import_deny.extend(modules);
+
+        Ok(Value::new(None))
+    }
+
+    /// PythonExecutable.set_source_transforms(packages, strip_comments=false, strip_docstrings=false, minify=false, intern_strings=false)
+    pub fn starlark_set_source_transforms(
+        &mut self,
+        packages: &Value,
+        strip_comments: &Value,
+        strip_docstrings: &Value,
+        minify: &Value,
+        intern_strings: &Value,
+    ) -> ValueResult {
+        required_list_arg("packages", "string", &packages)?;
+
+        let transforms = SourceTransforms {
+            strip_comments: required_bool_arg("strip_comments", &strip_comments)?,
+            strip_docstrings: required_bool_arg("strip_docstrings", &strip_docstrings)?,
+            minify: required_bool_arg("minify", &minify)?,
+            intern_strings: required_bool_arg("intern_strings", &intern_strings)?,
+        };
+
+        for package in packages.into_iter()? {
+            self.exe
+                .set_source_transforms(&package.to_string(), transforms);
+        }
+
+        Ok(Value::new(None))
+    }
+
+    /// PythonExecutable.set_first_party(packages=None, paths=None, filesystem=None, optimize_level=1)
+    pub fn starlark_set_first_party(
+        &mut self,
+        env: &Environment,
+        packages: &Value,
+        paths: &Value,
+        filesystem: &Value,
+        optimize_level: &Value,
+    ) -> ValueResult {
+        optional_list_arg("packages", "string", &packages)?;
+        optional_list_arg("paths", "string", &paths)?;
+        let filesystem = optional_bool_arg("filesystem", &filesystem)?;
+        required_type_arg("optimize_level", "int", &optimize_level)?;
+
+        let optimize_level = match optimize_level.to_int().unwrap() {
+            0 => BytecodeOptimizationLevel::Zero,
+            1 => BytecodeOptimizationLevel::One,
+            2 => BytecodeOptimizationLevel::Two,
+            i => {
+                return Err(RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message: format!("optimize_level must be 0, 1, or 2: got {}", i),
+                    label: "invalid optimize_level value".to_string(),
+                }
+                .into());
+            }
+        };
+
+        let context = env.get("CONTEXT").expect("CONTEXT not set");
+        let (cwd, build_release) =
+            context.downcast_apply(|x: &EnvironmentContext| (x.cwd.clone(), x.build_release));
+
+        let packages = match packages.get_type() {
+            "list" => packages.into_iter()?.map(|x| x.to_string()).collect(),
+            _ => Vec::new(),
+        };
+        let paths: Vec<PathBuf> = match paths.get_type() {
+            "list" => paths
+                .into_iter()?
+                .map(|x| cwd.join(x.to_string()))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        // First-party code is read from the filesystem during development.
+        let filesystem = filesystem.unwrap_or(!build_release);
+
+        if filesystem && paths.is_empty() {
+            return Err(RuntimeError {
+                code: "PYOXIDIZER_BUILD",
+                message: "paths must be defined to import first-party packages from the filesystem"
+                    .to_string(),
+                label: "set_first_party()".to_string(),
+            }
+            .into());
+        }
+
+        self.first_party = Some(FirstPartyPolicy {
+            packages,
+            paths,
+            filesystem,
+            optimize_level,
+        });
+
+        Ok(Value::new(None))
+    }
+
+    /// PythonExecutable.is_first_party(resource)
+    pub fn starlark_is_first_party(&self, resource: &Value) -> ValueResult {
+        let (name, location) = match resource.get_type() {
+            "PythonSourceModule" => resource.downcast_apply(|m: &PythonSourceModule| {
+                (m.module.name.clone(), m.module.source.clone())
+            }),
+            "PythonResourceData" => resource.downcast_apply(|r: &PythonResourceData| {
+                (r.data.package.clone(), r.data.data.clone())
+            }),
+            _ => {
+                return Err(RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message: "resource must be a PythonSourceModule or PythonResourceData"
+                        .to_string(),
+                    label: "is_first_party()".to_string(),
+                }
+                .into());
+            }
+        };
+
+        Ok(Value::new(
+            self.first_party_policy(&name, &location).is_some(),
+        ))
+    }
+
+    /// Verify the bytecode-only and conflict policies, reporting failure as a Starlark error.
+    fn starlark_verify(&self, label: &str) -> Result<(), ValueError> {
+        self.verify_bytecode_only()
+            .and_then(|_| self.verify_resource_conflicts())
+            .map_err(|e| {
+                RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: label.to_string(),
+                }
+                .into()
+            })
+    }
+
+    /// PythonExecutable.to_embedded_data()
+    pub fn starlark_to_embedded_data(&self) -> ValueResult {
+        self.starlark_verify("to_embedded_data()")?;
+
+        Ok(Value::new(PythonEmbeddedData {
+            exe: self.resolve_exe(),
+        }))
+    }
+
+    /// PythonExecutable.to_cargo_artifacts()
+    pub fn starlark_to_cargo_artifacts(&self) -> ValueResult {
+        self.starlark_verify("to_cargo_artifacts()")?;
+
+        Ok(Value::new(PythonCargoArtifacts {
+            exe: self.resolve_exe(),
+        }))
+    }
+
+    /// PythonExecutable.to_jupyter_kernel(name, display_name=None)
+    pub fn starlark_to_jupyter_kernel(&self, name: &Value, display_name: &Value) -> ValueResult {
+        let name = required_str_arg("name", &name)?;
+        let display_name = optional_str_arg("display_name", &display_name)?;
+
+        if !is_valid_kernel_name(&name) {
+            return Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: format!(
+                    "kernel name {} must only contain ASCII letters, numbers, '.', '_', and '-'",
+                    name
+                ),
+                label: "to_jupyter_kernel()".to_string(),
+            }
+            .into());
+        }
+
+        self.starlark_verify("to_jupyter_kernel()")?;
+
+        Ok(Value::new(JupyterKernel {
+            display_name: display_name.unwrap_or_else(|| name.clone()),
+            name,
+            exe: self.clone(),
+        }))
+    }
+
+    /// PythonExecutable.build(target)
+    pub fn starlark_build(&mut self, env: &Environment, target: &Value) -> ValueResult {
+        let target = required_str_arg("target", &target)?;
+
+        let context = env.get("CONTEXT").expect("CONTEXT not set");
+        let (logger, dry_run, target_triple, output_path) =
+            context.downcast_apply(|x: &EnvironmentContext| {
+                (
+                    x.logger.clone(),
+                    x.dry_run,
+                    x.build_target_triple.clone(),
+                    x.target_output_path(&target),
+                )
+            });
+
+        let path = if dry_run {
+            warn!(
+                logger,
+                "dry run: not building Python executable {}",
+                self.exe.name()
+            );
+            output_path.join(exe_filename(&self.exe.name(), &target_triple))
+        } else {
+            let resolved = context
+                .downcast_apply(|x: &EnvironmentContext| x.build_context(&target))
+                .and_then(|build_context| self.build(&build_context))
+                .map_err(|e| RuntimeError {
+                    code: "PYOXIDIZER_BUILD",
+                    message: e.to_string(),
+                    label: "build()".to_string(),
+                })?;
+
+            match resolved.run_mode {
+                RunMode::Path { path } => path,
+                RunMode::None => {
+                    return Err(RuntimeError {
+                        code: "PYOXIDIZER_BUILD",
+                        message: "executable was not built".to_string(),
+                        label: "build()".to_string(),
+                    }
+                    .into())
+                }
+            }
+        };
+
+        Ok(Value::new(BuiltExecutable {
+            path,
+            target_triple,
+            dry_run,
+        }))
+    }
+
+    /// PythonExecutable.filter_resources_from_files(files=None, glob_files=None)
+    pub fn starlark_filter_resources_from_files(
+        &mut self,
+        env: &Environment,
+        files: &Value,
+        glob_files: &Value,
+    ) -> ValueResult {
+        optional_list_arg("files", "string", &files)?;
+        optional_list_arg("glob_files", "string", &glob_files)?;
+
+        let files = match files.get_type() {
+            "list" => files
+                .into_iter()?
+                .map(|x| PathBuf::from(x.to_string()))
+                .collect(),
+            "NoneType" => Vec::new(),
+            _ => panic!("type should have been validated above"),
+        };
+
+        let glob_files = match glob_files.get_type() {
+            "list" => glob_files.into_iter()?.map(|x| x.to_string()).collect(),
+            "NoneType" => Vec::new(),
+            _ => panic!("type should have been validated above"),
+        };
+
+        let files_refs = files.iter().map(|x| x.as_ref()).collect::<Vec<&Path>>();
+        let glob_files_refs = glob_files.iter().map(|x| x.as_ref()).collect::<Vec<&str>>();
+
+        let context = env.get("CONTEXT").expect("CONTEXT not defined");
+        let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
+
+        self.exe
+            .filter_resources_from_files(&logger, &files_refs, &glob_files_refs)
+            .or_else(|e| {
+                Err(RuntimeError {
+                    code: "RUNTIME_ERROR",
+                    message: e.to_string(),
+                    label: "filter_resources_from_files()".to_string(),
+                }
+                .into())
+            })?;
+
+        Ok(Value::new(None))
+    }
+
+    /// PythonExecutable.filter_from_files(files=None, glob_patterns=None)
+    ///
+    /// The name of `filter_resources_from_files()` in config version 1.
+    pub fn starlark_filter_from_files(
+        &mut self,
+        env: &Environment,
+        files: &Value,
+        glob_patterns: &Value,
+    ) -> ValueResult {
+        check_removed_name(
+            env,
+            "filter_from_files()",
+            "PythonExecutable.filter_from_files()",
+            "PythonExecutable.filter_resources_from_files()",
+        )?;
+
+        self.starlark_filter_resources_from_files(env, files, glob_patterns)
+    }
+}
+
+starlark_module! { python_executable_env =>
+    #[allow(non_snake_case, clippy::ptr_arg)]
+    PythonExecutable.add_module_source(
+        env env,
+        call_stack call_stack,
+        this,
+        module,
+        file_emulation=None
+    ) {
+        required_type_arg("module", "PythonSourceModule", &module)?;
+
+        add_hooked_python_resources(
+            &env,
+            call_stack,
+            &this,
+            vec![module],
+            &Value::new(true),
+            &Value::new(false),
+            &Value::new(0),
+            &file_emulation,
+        )
+    }
+
+    // TODO consider unifying with add_module_source() so there only needs to be
+    // a single function call.
+    #[allow(non_snake_case, clippy::ptr_arg)]
+    PythonExecutable.add_module_bytecode(
+        env env,
+        call_stack call_stack,
+        this,
+        module,
+        optimize_level=0,
+        file_emulation=None
+    ) {
+        required_type_arg("module", "PythonSourceModule", &module)?;
+
+        add_hooked_python_resources(
+            &env,
+            call_stack,
+            &this,
+            vec![module],
+            &Value::new(false),
+            &Value::new(true),
+            &optimize_level,
+            &file_emulation,
+        )
+    }
+
+    #[allow(non_snake_case, clippy::ptr_arg)]
+    PythonExecutable.add_resource_data(
+        env env,
+        call_stack call_stack,
+        this,
+        resource,
+        file_emulation=None
+    ) {
+        required_type_arg("resource", "PythonResourceData", &resource)?;
+
+        add_hooked_python_resources(
+            &env,
+            call_stack,
+            &this,
+            vec![resource],
+            &Value::new(true),
+            &Value::new(true),
+            &Value::new(0),
+            &file_emulation,
+        )
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.add_extension_module(env env, call_stack call_stack, this, module) {
+        required_type_arg("module", "PythonExtensionModule", &module)?;
+
+        add_hooked_python_resources(
+            &env,
+            call_stack,
+            &this,
+            vec![module],
+            &Value::new(true),
+            &Value::new(true),
+            &Value::new(0),
+            &Value::new(None),
+        )
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.add_python_resource(
+        env env,
+        call_stack call_stack,
+        this,
+        resource,
+        add_source_module=true,
+        add_bytecode_module=true,
+        optimize_level=0,
+        file_emulation=None
+        )
+    {
+        add_hooked_python_resources(
+            &env,
+            call_stack,
+            &this,
+            vec![resource],
+            &add_source_module,
+            &add_bytecode_module,
+            &optimize_level,
+            &file_emulation,
+        )
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.add_python_resources(
+        env env,
+        call_stack call_stack,
+        this,
+        resources,
+        add_source_module=true,
+        add_bytecode_module=true,
+        optimize_level=0,
+        file_emulation=None
+    ) {
+        add_hooked_python_resources(
+            &env,
+            call_stack,
+            &this,
+            resources.into_iter()?.collect(),
+            &add_source_module,
+            &add_bytecode_module,
+            &optimize_level,
+            &file_emulation,
+        )
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.filter_resources_from_files(
+        env env,
+        this,
+        files=None,
+        glob_files=None)
+    {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_filter_resources_from_files(&env, &files, &glob_files)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.filter_from_files(env env, this, files=None, glob_patterns=None) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_filter_from_files(&env, &files, &glob_patterns)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.build(env env, this, target) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_build(&env, &target)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.build_options(
+        this,
+        opt_level=None,
+        lto=None,
+        strip=None,
+        split_debug_info=None,
+        rustflags=None,
+        cargo_features=None,
+        sbom=None,
+        windows_subsystem=None
+    ) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_build_options(
+                &opt_level,
+                &lto,
+                &strip,
+                &split_debug_info,
+                &rustflags,
+                &cargo_features,
+                &sbom,
+                &windows_subsystem,
+            )
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.set_rust_sources(env env, this, main_rs_template=None, sources_path=None) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_set_rust_sources(&env, &main_rs_template, &sources_path)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.set_update_signing_key(env env, this, path) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_set_update_signing_key(&env, &path)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.set_resource_conflict_policy(this, policy) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_set_resource_conflict_policy(&policy)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.set_build_info(
+        env env,
+        this,
+        module_name="__pyoxidizer__",
+        git_commit=true,
+        variables=None
+    ) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_set_build_info(&env, &module_name, &git_commit, &variables)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.set_linux_compat_level(this, level, fail=true) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_set_linux_compat_level(&level, &fail)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.set_size_budget(this, size=None, packages=None) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_set_size_budget(&size, &packages)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.set_bytecode_only_packages(this, packages, optimize_level=None) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_set_bytecode_only_packages(&packages, &optimize_level)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.add_import_allowlist(this, modules) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_add_import_allowlist(&modules)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.deny_import(this, modules) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_deny_import(&modules)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.set_source_transforms(
+        this,
+        packages,
+        strip_comments=false,
+        strip_docstrings=false,
+        minify=false,
+        intern_strings=false
+    ) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_set_source_transforms(
+                &packages,
+                &strip_comments,
+                &strip_docstrings,
+                &minify,
+                &intern_strings,
+            )
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.set_first_party(
+        env env,
+        this,
+        packages=None,
+        paths=None,
+        filesystem=None,
+        optimize_level=1
+    ) {
+        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            exe.starlark_set_first_party(&env, &packages, &paths, &filesystem, &optimize_level)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.is_first_party(this, resource) {
+        this.downcast_apply(|exe: &PythonExecutable| {
+            exe.starlark_is_first_party(&resource)
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.to_embedded_data(this) {
+        this.downcast_apply(|exe: &PythonExecutable| {
+            exe.starlark_to_embedded_data()
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.to_cargo_artifacts(this) {
+        this.downcast_apply(|exe: &PythonExecutable| {
+            exe.starlark_to_cargo_artifacts()
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonExecutable.to_jupyter_kernel(this, name, display_name=None) {
+        this.downcast_apply(|exe: &PythonExecutable| {
+            exe.starlark_to_jupyter_kernel(&name, &display_name)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testutil::*;
+    use super::*;
+    use crate::py_packaging::resource::ResourceData;
+
+    #[test]
+    fn test_default_values() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+
+        let exe = starlark_eval_in_env(&mut env, "dist.to_python_executable('testapp')").unwrap();
+
+        assert_eq!(exe.get_type(), "PythonExecutable");
+
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            assert!(!exe.exe.extension_modules().is_empty());
+            assert!(!exe.exe.source_modules().is_empty());
+            assert!(!exe.exe.bytecode_modules().is_empty());
+            assert!(exe.exe.resources().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_no_sources() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+
+        let exe = starlark_eval_in_env(
+            &mut env,
+            "dist.to_python_executable('testapp', include_sources=False)",
+        )
+        .unwrap();
+
+        assert_eq!(exe.get_type(), "PythonExecutable");
+
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            assert!(exe.exe.source_modules().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_python_executable_repl() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+
+        let exe = starlark_eval_in_env(&mut env, "dist.to_python_executable_repl()").unwrap();
+
+        assert_eq!(exe.get_type(), "PythonExecutable");
+
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            assert_eq!(exe.exe.name(), "python");
+            assert!(!exe.exe.source_modules().is_empty());
+            assert!(!exe.exe.resources().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_build_options() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+
+        starlark_eval_in_env(
+            &mut env,
+            "exe.build_options(opt_level='z', lto=True, strip=True, split_debug_info=True, rustflags=['-C', 'panic=abort'])",
+        )
+        .unwrap();
+        starlark_eval_in_env(&mut env, "exe.build_options(cargo_features=['foo'])").unwrap();
+        starlark_eval_in_env(&mut env, "exe.build_options(sbom='cyclonedx')").unwrap();
+        starlark_eval_in_env(&mut env, "exe.build_options(windows_subsystem='windows')").unwrap();
+
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            assert_eq!(
+                exe.build_options,
+                RustBuildOptions {
+                    opt_level: Some("z".to_string()),
+                    lto: Some(true),
+                    strip: true,
+                    split_debug_info: true,
+                    rustflags: vec!["-C".to_string(), "panic=abort".to_string()],
+                    cargo_features: vec!["foo".to_string()],
+                    main_rs_template: None,
+                    rust_sources_path: None,
+                    sbom: Some(SbomFormat::CycloneDx),
+                    offline_cargo_home: None,
+                    rust_toolchains_path: exe.build_options.rust_toolchains_path.clone(),
+                    windows_subsystem: Some("windows".to_string()),
+                    linux_compat_level: None,
+                    linux_compat_fail: false,
+                    size_budget: Default::default(),
+                }
+            );
+        });
+
+        assert!(starlark_eval_in_env(&mut env, "exe.build_options(opt_level='fast')").is_err());
+        assert!(starlark_eval_in_env(&mut env, "exe.build_options(sbom='swid')").is_err());
+        assert!(
+            starlark_eval_in_env(&mut env, "exe.build_options(windows_subsystem='gui')").is_err()
+        );
+    }
+
+    #[test]
+    fn test_set_rust_sources() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+
+        let template = temp_dir.path().join("main.rs.hbs");
+        std::fs::write(&template, "{{include_default_python_config}}")?;
+        let sources = temp_dir.path().join("src");
+        std::fs::create_dir(&sources)?;
+
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+
+        starlark_eval_in_env(
+            &mut env,
+            &format!(
+                "exe.set_rust_sources(main_rs_template='{}', sources_path='{}')",
+                template.display(),
+                sources.display()
+            ),
+        )
+        .unwrap();
+
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            assert_eq!(exe.build_options.main_rs_template, Some(template.clone()));
+            assert_eq!(exe.build_options.rust_sources_path, Some(sources.clone()));
+        });
+
+        assert!(starlark_eval_in_env(
+            &mut env,
+            &format!(
+                "exe.set_rust_sources(sources_path='{}')",
+                temp_dir.path().join("missing").display()
+            ),
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_update_signing_key() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+
+        // The executable must define an update channel.
+        assert!(
+            starlark_eval_in_env(&mut env, "exe.set_update_signing_key('update.key')").is_err()
+        );
+
+        starlark_eval_in_env(
+            &mut env,
+            &format!(
+                "config = PythonInterpreterConfig(update_url='https://example.com/testapp.update.json', update_public_key='{}', update_version='1.0')",
+                "ab".repeat(32)
+            ),
+        )
+        .unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe = dist.to_python_executable('testapp', config=config)",
+        )
+        .unwrap();
+        starlark_eval_in_env(&mut env, "exe.set_update_signing_key('update.key')").unwrap();
+
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            assert!(exe
+                .update_signing_key
+                .as_ref()
+                .unwrap()
+                .ends_with("update.key"));
+        });
+    }
+
+    #[test]
+    fn test_set_build_info() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe.set_build_info(git_commit=False, variables={'channel': 'stable'})",
+        )
+        .unwrap();
+
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            let info = exe.build_info.as_ref().unwrap();
+            assert_eq!(info.module_name, "__pyoxidizer__");
+            assert_eq!(info.git_commit, None);
+            assert_eq!(info.variables.get("channel"), Some(&"stable".to_string()));
+
+            let resolved = exe.resolve_exe();
+            assert!(resolved.source_modules().contains_key("__pyoxidizer__"));
+            assert!(resolved.bytecode_modules().contains_key("__pyoxidizer__"));
+            assert!(!exe.exe.source_modules().contains_key("__pyoxidizer__"));
+        });
 
-    // TODO consider unifying with add_module_source() so there only needs to be
-    // a single function call.
-    #[allow(non_snake_case, clippy::ptr_arg)]
-    PythonExecutable.add_module_bytecode(env env, this, module, optimize_level=0) {
-        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
-            exe.starlark_add_module_bytecode(&env, &module, &optimize_level)
-        })
+        let err =
+            starlark_eval_in_env(&mut env, "exe.set_build_info(module_name='1info')").unwrap_err();
+        assert_eq!(err.message, "1info is not a valid Python module name");
+        assert!(starlark_eval_in_env(&mut env, "exe.set_build_info(variables={'a': 1})").is_err());
     }
 
-    #[allow(non_snake_case, clippy::ptr_arg)]
-    PythonExecutable.add_resource_data(env env, this, resource) {
-        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
-            exe.starlark_add_resource_data(&env, &resource)
-        })
+    #[test]
+    fn test_set_resource_conflict_policy() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+        starlark_eval_in_env(&mut env, "exe.set_resource_conflict_policy('error')").unwrap();
+
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply_mut(|exe: &mut PythonExecutable| {
+            assert_eq!(exe.exe.resource_conflict_policy(), ConflictPolicy::Error);
+            assert!(exe.verify_resource_conflicts().is_ok());
+
+            exe.exe.add_resource(&ResourceData {
+                package: "foo".to_string(),
+                name: "data.txt".to_string(),
+                data: DataLocation::Memory(vec![42]),
+            });
+            exe.exe.add_resource(&ResourceData {
+                package: "foo".to_string(),
+                name: "data.txt".to_string(),
+                data: DataLocation::Memory(vec![43]),
+            });
+            exe.report_resource_conflicts(&crate::testutil::get_logger().unwrap());
+            assert_eq!(exe.resource_conflicts.last().unwrap().name, "foo:data.txt");
+            assert!(exe.verify_resource_conflicts().is_err());
+        });
+
+        let err = starlark_eval_in_env(&mut env, "exe.to_embedded_data()").unwrap_err();
+        assert!(err
+            .message
+            .contains("resource foo:data.txt from 1 bytes in memory conflicts with"));
+
+        let err = starlark_eval_in_env(&mut env, "exe.set_resource_conflict_policy('newest')")
+            .unwrap_err();
+        assert!(err
+            .message
+            .starts_with("newest is not a valid resource conflict policy"));
     }
 
-    #[allow(clippy::ptr_arg)]
-    PythonExecutable.add_extension_module(env env, this, module) {
-        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
-            exe.starlark_add_extension_module(&env, &module)
-        })
+    #[test]
+    fn test_import_lists() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+        starlark_eval_in_env(&mut env, "exe.deny_import(['ctypes', '_ctypes'])").unwrap();
+
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            assert_eq!(exe.import_deny, vec!["ctypes", "_ctypes"]);
+            assert_eq!(exe.import_allow, None);
+            assert!(exe.exe.source_modules().contains_key("ctypes.util"));
+
+            let resolved = exe.resolve_exe();
+            assert!(!resolved.source_modules().contains_key("ctypes.util"));
+            assert!(!resolved.extension_modules().contains_key("_ctypes"));
+            assert!(resolved.source_modules().contains_key("json"));
+            assert_eq!(resolved.config().import_deny, vec!["ctypes", "_ctypes"]);
+        });
+
+        starlark_eval_in_env(&mut env, "exe.add_import_allowlist(['json'])").unwrap();
+        starlark_eval_in_env(&mut env, "exe.add_import_allowlist(['myapp.cli'])").unwrap();
+
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            assert_eq!(
+                exe.import_allow,
+                Some(vec!["json".to_string(), "myapp.cli".to_string()])
+            );
+
+            let resolved = exe.resolve_exe();
+            let source_modules = resolved.source_modules();
+            assert!(source_modules.contains_key("json.decoder"));
+            assert!(source_modules.contains_key("encodings.utf_8"));
+            assert!(!source_modules.contains_key("socket"));
+            assert!(resolved
+                .config()
+                .import_allow
+                .as_ref()
+                .unwrap()
+                .contains(&"encodings".to_string()));
+        });
+
+        let err = starlark_eval_in_env(&mut env, "exe.deny_import(['my-module'])").unwrap_err();
+        assert_eq!(err.message, "my-module is not a valid Python module name");
+        assert!(starlark_eval_in_env(&mut env, "exe.add_import_allowlist('json')").is_err());
     }
 
-    #[allow(clippy::ptr_arg)]
-    PythonExecutable.add_python_resource(
-        env env,
-        this,
-        resource,
-        add_source_module=true,
-        add_bytecode_module=true,
-        optimize_level=0
+    #[test]
+    fn test_set_linux_compat_level() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+
+        assert!(starlark_eval_in_env(&mut env, "exe.set_linux_compat_level('centos7')").is_err());
+
+        starlark_eval_in_env(
+            &mut env,
+            "exe.set_linux_compat_level('manylinux2010', fail=False)",
         )
-    {
-        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
-            exe.starlark_add_python_resource(
-                &env,
-                &resource,
-                &add_source_module,
-                &add_bytecode_module,
-                &optimize_level,
-            )
-        })
+        .unwrap();
+
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            assert_eq!(
+                exe.build_options.linux_compat_level,
+                Some("manylinux2010".to_string())
+            );
+            assert!(!exe.build_options.linux_compat_fail);
+        });
     }
 
-    #[allow(clippy::ptr_arg)]
-    PythonExecutable.add_python_resources(
-        env env,
-        this,
-        resources,
-        add_source_module=true,
-        add_bytecode_module=true,
-        optimize_level=0
-    ) {
-        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
-            exe.starlark_add_python_resources(
-                &env,
-                &resources,
-                &add_source_module,
-                &add_bytecode_module,
-                &optimize_level,
-            )
-        })
+    #[test]
+    fn test_set_size_budget() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+
+        starlark_eval_in_env(&mut env, "exe.set_size_budget('60 MB')").unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe.set_size_budget(packages={'numpy': '20 MiB', 'json': 100000})",
+        )
+        .unwrap();
+
+        assert!(starlark_eval_in_env(&mut env, "exe.set_size_budget('60 parsecs')").is_err());
+        assert!(starlark_eval_in_env(&mut env, "exe.set_size_budget(-1)").is_err());
+        assert!(starlark_eval_in_env(&mut env, "exe.set_size_budget(packages=['numpy'])").is_err());
+
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            let budget = &exe.build_options.size_budget;
+            assert_eq!(budget.total, Some(60_000_000));
+            assert_eq!(budget.packages.get("numpy"), Some(&20_971_520));
+            assert_eq!(budget.packages.get("json"), Some(&100_000));
+        });
     }
 
-    #[allow(clippy::ptr_arg)]
-    PythonExecutable.filter_resources_from_files(
-        env env,
-        this,
-        files=None,
-        glob_files=None)
-    {
-        this.downcast_apply_mut(|exe: &mut PythonExecutable| {
-            exe.starlark_filter_resources_from_files(&env, &files, &glob_files)
-        })
+    #[test]
+    fn test_to_cargo_artifacts() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+
+        let artifacts = starlark_eval_in_env(&mut env, "exe.to_cargo_artifacts()").unwrap();
+        assert_eq!(artifacts.get_type(), "PythonCargoArtifacts");
     }
 
-    #[allow(clippy::ptr_arg)]
-    PythonExecutable.to_embedded_data(this) {
-        this.downcast_apply(|exe: &PythonExecutable| {
-            exe.starlark_to_embedded_data()
-        })
+    #[test]
+    fn test_to_jupyter_kernel() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+
+        let kernel = starlark_eval_in_env(
+            &mut env,
+            "exe.to_jupyter_kernel('testapp', display_name='Test App')",
+        )
+        .unwrap();
+        assert_eq!(kernel.get_type(), "JupyterKernel");
+        kernel.downcast_apply(|kernel: &JupyterKernel| {
+            assert_eq!(kernel.name, "testapp");
+            assert_eq!(kernel.display_name, "Test App");
+        });
+
+        let kernel = starlark_eval_in_env(&mut env, "exe.to_jupyter_kernel('testapp')").unwrap();
+        kernel.downcast_apply(|kernel: &JupyterKernel| {
+            assert_eq!(kernel.display_name, "testapp");
+        });
+
+        assert!(starlark_eval_in_env(&mut env, "exe.to_jupyter_kernel('test app')").is_err());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::super::testutil::*;
-    use super::*;
+    #[test]
+    fn test_set_bytecode_only_packages() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe.set_bytecode_only_packages(['json'], optimize_level=2)",
+        )
+        .unwrap();
+
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            assert!(exe.is_bytecode_only("json"));
+            assert!(exe.is_bytecode_only("json.decoder"));
+            assert!(!exe.is_bytecode_only("jsonx"));
+            assert_eq!(
+                exe.bytecode_only_optimize_level,
+                Some(BytecodeOptimizationLevel::Two)
+            );
+        });
+
+        // The standard library's json sources are already embedded.
+        assert!(starlark_eval_in_env(&mut env, "exe.to_embedded_data()").is_err());
+
+        starlark_eval_in_env(
+            &mut env,
+            "exe = dist.to_python_executable('testapp', include_sources=False)",
+        )
+        .unwrap();
+        starlark_eval_in_env(&mut env, "exe.set_bytecode_only_packages(['json'])").unwrap();
+        starlark_eval_in_env(&mut env, "exe.to_embedded_data()").unwrap();
+
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            let modules = exe.exe.bytecode_modules();
+            assert!(modules.get("json").unwrap().source_stripped);
+        });
+
+        assert!(starlark_eval_in_env(
+            &mut env,
+            "exe.set_bytecode_only_packages(['json'], optimize_level=3)"
+        )
+        .is_err());
+    }
 
     #[test]
-    fn test_default_values() {
+    fn test_set_first_party() {
         let mut env = starlark_env();
 
         starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe = dist.to_python_executable('testapp', include_sources=False)",
+        )
+        .unwrap();
 
-        let exe = starlark_eval_in_env(&mut env, "dist.to_python_executable('testapp')").unwrap();
+        // Development builds import first-party code from the filesystem.
+        assert!(starlark_eval_in_env(&mut env, "exe.set_first_party(packages=['myapp'])").is_err());
 
-        assert_eq!(exe.get_type(), "PythonExecutable");
+        starlark_eval_in_env(
+            &mut env,
+            "exe.set_first_party(packages=['myapp'], filesystem=False, optimize_level=2)",
+        )
+        .unwrap();
+        starlark_eval_in_env(&mut env, "m = PythonSourceModule('myapp.cli', 'print(1)')").unwrap();
+
+        let first_party = starlark_eval_in_env(&mut env, "exe.is_first_party(m)").unwrap();
+        assert!(first_party.to_bool());
+
+        starlark_eval_in_env(&mut env, "exe.add_python_resource(m)").unwrap();
 
+        let exe = env.get("exe").unwrap();
         exe.downcast_apply(|exe: &PythonExecutable| {
-            assert!(!exe.exe.extension_modules().is_empty());
-            assert!(!exe.exe.source_modules().is_empty());
-            assert!(!exe.exe.bytecode_modules().is_empty());
-            assert!(exe.exe.resources().is_empty());
+            assert!(!exe.exe.source_modules().contains_key("myapp.cli"));
+            let modules = exe.exe.bytecode_modules();
+            let module = modules.get("myapp.cli").unwrap();
+            assert!(module.source_stripped);
+            assert_eq!(module.optimize_level, BytecodeOptimizationLevel::Two);
+        });
+
+        starlark_eval_in_env(
+            &mut env,
+            "exe.set_first_party(packages=['myapp'], paths=['src'], filesystem=True)",
+        )
+        .unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe.add_python_resource(PythonSourceModule('myapp.other', ''))",
+        )
+        .unwrap();
+
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            assert!(!exe.exe.bytecode_modules().contains_key("myapp.other"));
         });
     }
 
     #[test]
-    fn test_no_sources() {
+    fn test_set_source_transforms() {
         let mut env = starlark_env();
 
         starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe.set_source_transforms(['json'], strip_docstrings=True, minify=True)",
+        )
+        .unwrap();
+        starlark_eval_in_env(&mut env, "exe.to_embedded_data()").unwrap();
 
-        let exe = starlark_eval_in_env(
+        assert!(starlark_eval_in_env(&mut env, "exe.set_source_transforms('json')").is_err());
+        assert!(
+            starlark_eval_in_env(&mut env, "exe.set_source_transforms(['json'], minify=1)")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_resource_hooks() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(
             &mut env,
-            "dist.to_python_executable('testapp', include_sources=False)",
+            "exe = dist.to_python_executable('testapp', include_sources=False)",
+        )
+        .unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "def hook(resource):\n    if resource.name == 'vetoed':\n        return None\n    if resource.name == 'old':\n        return [PythonSourceModule('new', resource.source), PythonResourceData('new', 'data.txt', 'injected')]\n    return resource\n",
+        )
+        .unwrap();
+        starlark_eval_in_env(&mut env, "register_resource_hook(hook)").unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe.add_python_resources([PythonSourceModule('old', 'x = 1'), PythonSourceModule('vetoed', '')], add_bytecode_module=False)",
+        )
+        .unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe.add_python_resource(PythonSourceModule('kept', ''), add_bytecode_module=False)",
         )
         .unwrap();
 
-        assert_eq!(exe.get_type(), "PythonExecutable");
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            let modules = exe.exe.source_modules();
+            assert!(modules.contains_key("new"));
+            assert!(modules.contains_key("kept"));
+            assert!(!modules.contains_key("old"));
+            assert!(!modules.contains_key("vetoed"));
+
+            let resources = exe.exe.resources();
+            assert_eq!(
+                resources.get("new").unwrap().get("data.txt"),
+                Some(&b"injected".to_vec())
+            );
+        });
+    }
+
+    #[test]
+    fn test_resource_hooks_add_methods() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe = dist.to_python_executable('testapp', include_sources=False)",
+        )
+        .unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "def hook(resource):\n    if resource.name == 'vetoed':\n        return None\n    if resource.name == 'old':\n        return PythonSourceModule('new', resource.source)\n    return resource\n",
+        )
+        .unwrap();
+        starlark_eval_in_env(&mut env, "register_resource_hook(hook)").unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe.add_module_source(PythonSourceModule('old', 'x = 1'))",
+        )
+        .unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe.add_module_bytecode(PythonSourceModule('vetoed', ''))",
+        )
+        .unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe.add_resource_data(PythonResourceData('pkg', 'vetoed', 'data'))",
+        )
+        .unwrap();
 
+        let exe = env.get("exe").unwrap();
         exe.downcast_apply(|exe: &PythonExecutable| {
-            assert!(exe.exe.source_modules().is_empty());
+            let modules = exe.exe.source_modules();
+            assert!(modules.contains_key("new"));
+            assert!(!modules.contains_key("old"));
+            assert!(!exe.exe.bytecode_modules().contains_key("vetoed"));
+            assert!(!exe.exe.resources().contains_key("pkg"));
+        });
+    }
+
+    #[test]
+    fn test_resource_hooks_distribution() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(
+            &mut env,
+            "def hook(resource):\n    if resource.name == 'json' or resource.name.startswith('json.'):\n        return None\n    return resource\n",
+        )
+        .unwrap();
+        starlark_eval_in_env(&mut env, "register_resource_hook(hook)").unwrap();
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            let sources = exe.exe.source_modules();
+            let bytecodes = exe.exe.bytecode_modules();
+            assert!(!sources.contains_key("json"));
+            assert!(!bytecodes.contains_key("json"));
+            assert!(!bytecodes.contains_key("json.decoder"));
+            assert!(sources.contains_key("os"));
+            assert!(bytecodes.contains_key("os"));
+        });
+    }
+
+    #[test]
+    fn test_optimize_levels() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe = dist.to_python_executable('testapp', include_sources=False)",
+        )
+        .unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe.add_python_resources(dist.source_modules(), add_source_module=False, optimize_level=[0, 1, 2])",
+        )
+        .unwrap();
+
+        let exe = env.get("exe").unwrap();
+        exe.downcast_apply(|exe: &PythonExecutable| {
+            assert_eq!(
+                exe.exe.bytecode_optimize_levels().get("json"),
+                Some(&vec![
+                    BytecodeOptimizationLevel::Zero,
+                    BytecodeOptimizationLevel::One,
+                    BytecodeOptimizationLevel::Two
+                ])
+            );
         });
+
+        assert_eq!(
+            optimize_levels_arg(&starlark_eval_in_env(&mut env, "[2, 0, 2]").unwrap()).unwrap(),
+            vec![
+                BytecodeOptimizationLevel::Two,
+                BytecodeOptimizationLevel::Zero
+            ]
+        );
+
+        for value in &["[]", "[0, 3]", "['0']", "'1'"] {
+            assert!(starlark_eval_in_env(
+                &mut env,
+                &format!(
+                    "exe.add_python_resources(dist.source_modules(), optimize_level={})",
+                    value
+                )
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn test_file_emulation() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "exe = dist.to_python_executable('testapp')").unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "exe.add_python_resources(dist.source_modules(), file_emulation='extract-on-access')",
+        )
+        .unwrap();
+
+        assert_eq!(
+            file_emulation_arg(&starlark_eval_in_env(&mut env, "'fake-path'").unwrap()).unwrap(),
+            Some(FileEmulation::FakePath)
+        );
+        assert_eq!(
+            file_emulation_arg(&starlark_eval_in_env(&mut env, "None").unwrap()).unwrap(),
+            None
+        );
+
+        assert!(starlark_eval_in_env(
+            &mut env,
+            "exe.add_python_resources(dist.source_modules(), file_emulation='bogus')"
+        )
+        .is_err());
     }
 }
@@ -4,10 +4,17 @@
 
 use crate::py_packaging::config::RunMode;
 use {
-    super::util::{optional_list_arg, optional_str_arg, required_bool_arg, required_type_arg},
+    super::config_version::negated_removed_bool_arg,
+    super::env::EnvironmentContext,
+    super::util::{
+        optional_dict_arg, optional_list_arg, optional_str_arg, optional_type_arg,
+        required_bool_arg, required_str_arg, required_type_arg,
+    },
     crate::py_packaging::config::{
-        default_raw_allocator, EmbeddedPythonConfig, RawAllocator, TerminfoResolution,
+        default_raw_allocator, AuditAction, EmbeddedPythonConfig, RawAllocator, ResourcesIntegrity,
+        TerminfoResolution, UpdateChannel,
     },
+    python_packed_resources::data::FileEmulation,
     starlark::environment::Environment,
     starlark::values::{
         default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult,
@@ -20,6 +27,7 @@ use {
     std::any::Any,
     std::cmp::Ordering,
     std::collections::HashMap,
+    std::convert::TryFrom,
 };
 
 impl TypedValue for EmbeddedPythonConfig {
@@ -52,77 +60,220 @@ impl TypedValue for EmbeddedPythonConfig {
     }
 }
 
+/// Parse a `stdio_encoding` value into an encoding and an error handler.
+///
+/// Values are of the form `encoding:errors`. Either part can be omitted to
+/// keep Python's default, e.g. `utf-8` or `:replace`.
+pub fn parse_stdio_encoding(value: &str) -> Result<(Option<String>, Option<String>), String> {
+    let (encoding, errors) = match value.find(':') {
+        Some(pos) => (&value[0..pos], &value[pos + 1..]),
+        None => (value, ""),
+    };
+
+    if (encoding.is_empty() && errors.is_empty()) || errors.contains(':') {
+        return Err(format!(
+            "stdio_encoding must be of the form encoding:errors; got {}",
+            value
+        ));
+    }
+
+    let part = |s: &str| {
+        if s.is_empty() {
+            None
+        } else {
+            Some(s.to_string())
+        }
+    };
+
+    Ok((part(encoding), part(errors)))
+}
+
+/// Whether a value names a signal, e.g. `SIGTERM`.
+///
+/// Whether the signal exists is only known on the machine running the
+/// application.
+pub fn is_signal_name(value: &str) -> bool {
+    value.len() > 3
+        && value.starts_with("SIG")
+        && value
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
 // Starlark functions.
 impl EmbeddedPythonConfig {
     /// PythonInterpreterConfig(...)
     #[allow(clippy::too_many_arguments)]
     pub fn starlark_new(
         env: &Environment,
+        audit_policy: &Value,
         bytes_warning: &Value,
+        crash_dir: &Value,
+        debug_console: &Value,
+        dont_write_bytecode: &Value,
+        dotenv: &Value,
+        environment: &Value,
         ignore_environment: &Value,
+        import_profile_env: &Value,
+        inherit_environment: &Value,
+        install_signal_handlers: &Value,
         inspect: &Value,
         interactive: &Value,
         isolated: &Value,
+        lazy_import: &Value,
+        lazy_import_allow: &Value,
+        lazy_import_deny: &Value,
         legacy_windows_fs_encoding: &Value,
         legacy_windows_stdio: &Value,
+        no_site: &Value,
+        no_user_site_directory: &Value,
         optimize_level: &Value,
         parser_debug: &Value,
+        pkg_resources_compat: &Value,
         stdio_encoding: &Value,
         unbuffered_stdio: &Value,
+        faulthandler: &Value,
+        file_emulation: &Value,
+        file_extraction_dir: &Value,
+        filesystem_import_paths: &Value,
         filesystem_importer: &Value,
+        forward_signals: &Value,
         quiet: &Value,
         run_eval: &Value,
         run_file: &Value,
         run_module: &Value,
         run_noop: &Value,
+        run_python_cli: &Value,
         run_repl: &Value,
+        self_test: &Value,
         site_import: &Value,
+        splash_image: &Value,
+        strict_isolation: &Value,
         sys_frozen: &Value,
         sys_meipass: &Value,
         sys_paths: &Value,
         raw_allocator: &Value,
+        remove_environment: &Value,
+        resources_file: &Value,
+        resources_integrity: &Value,
+        resources_key_env: &Value,
+        resources_key_file: &Value,
         terminfo_resolution: &Value,
         terminfo_dirs: &Value,
+        update_public_key: &Value,
+        update_url: &Value,
+        update_version: &Value,
         use_hash_seed: &Value,
         user_site_directory: &Value,
+        user_site_packages: &Value,
+        utf8_mode: &Value,
         verbose: &Value,
+        windows_console_code_page: &Value,
         write_bytecode: &Value,
         write_modules_directory_env: &Value,
     ) -> ValueResult {
+        optional_dict_arg("audit_policy", "string", "string", &audit_policy)?;
         required_type_arg("bytes_warning", "int", &bytes_warning)?;
+        let crash_dir = optional_str_arg("crash_dir", &crash_dir)?;
+        let debug_console = required_bool_arg("debug_console", &debug_console)?;
+        let dotenv = required_bool_arg("dotenv", &dotenv)?;
+        optional_dict_arg("environment", "string", "string", &environment)?;
+        let faulthandler = required_bool_arg("faulthandler", &faulthandler)?;
         let ignore_environment = required_bool_arg("ignore_environment", &ignore_environment)?;
+        let import_profile_env = optional_str_arg("import_profile_env", &import_profile_env)?;
+        let inherit_environment = required_bool_arg("inherit_environment", &inherit_environment)?;
+        let install_signal_handlers =
+            required_bool_arg("install_signal_handlers", &install_signal_handlers)?;
         let inspect = required_bool_arg("inspect", &inspect)?;
         let interactive = required_bool_arg("interactive", &interactive)?;
         let isolated = required_bool_arg("isolated", &isolated)?;
+        let strict_isolation = required_bool_arg("strict_isolation", &strict_isolation)?;
+        let lazy_import = required_bool_arg("lazy_import", &lazy_import)?;
+        optional_list_arg("lazy_import_allow", "string", &lazy_import_allow)?;
+        optional_list_arg("lazy_import_deny", "string", &lazy_import_deny)?;
+        optional_list_arg("remove_environment", "string", &remove_environment)?;
         let legacy_windows_fs_encoding =
             required_bool_arg("legacy_windows_fs_encoding", &legacy_windows_fs_encoding)?;
         let legacy_windows_stdio =
             required_bool_arg("legacy_windows_stdio", &legacy_windows_stdio)?;
         required_type_arg("optimize_level", "int", &optimize_level)?;
         let parser_debug = required_bool_arg("parser_debug", &parser_debug)?;
+        let pkg_resources_compat =
+            required_bool_arg("pkg_resources_compat", &pkg_resources_compat)?;
         let stdio_encoding = optional_str_arg("stdio_encoding", &stdio_encoding)?;
         let unbuffered_stdio = required_bool_arg("unbuffered_stdio", &unbuffered_stdio)?;
+        let file_emulation = required_str_arg("file_emulation", &file_emulation)?;
+        let file_extraction_dir = optional_str_arg("file_extraction_dir", &file_extraction_dir)?;
         let filesystem_importer = required_bool_arg("filesystem_importer", &filesystem_importer)?;
+        optional_list_arg("forward_signals", "string", &forward_signals)?;
+        let self_test = required_bool_arg("self_test", &self_test)?;
         let quiet = required_bool_arg("quiet", &quiet)?;
         let run_eval = optional_str_arg("run_eval", &run_eval)?;
         let run_file = optional_str_arg("run_file", &run_file)?;
         let run_module = optional_str_arg("run_module", &run_module)?;
         let run_noop = required_bool_arg("run_noop", &run_noop)?;
+        let run_python_cli = required_bool_arg("run_python_cli", &run_python_cli)?;
         let run_repl = required_bool_arg("run_repl", &run_repl)?;
         let sys_frozen = required_bool_arg("sys_frozen", &sys_frozen)?;
         let sys_meipass = required_bool_arg("sys_meipass", &sys_meipass)?;
         optional_list_arg("sys_paths", "string", &sys_paths)?;
+        optional_list_arg(
+            "filesystem_import_paths",
+            "string",
+            &filesystem_import_paths,
+        )?;
         let raw_allocator = optional_str_arg("raw_allocator", &raw_allocator)?;
+        let resources_file = optional_str_arg("resources_file", &resources_file)?;
+        let resources_integrity = optional_str_arg("resources_integrity", &resources_integrity)?;
+        let resources_key_env = optional_str_arg("resources_key_env", &resources_key_env)?;
+        let resources_key_file = optional_str_arg("resources_key_file", &resources_key_file)?;
         let site_import = required_bool_arg("site_importer", &site_import)?;
+        let splash_image = optional_str_arg("splash_image", &splash_image)?;
         let terminfo_resolution = optional_str_arg("terminfo_resolution", &terminfo_resolution)?;
         let terminfo_dirs = optional_str_arg("terminfo_dirs", &terminfo_dirs)?;
+        let update_public_key = optional_str_arg("update_public_key", &update_public_key)?;
+        let update_url = optional_str_arg("update_url", &update_url)?;
+        let update_version = optional_str_arg("update_version", &update_version)?;
         let use_hash_seed = required_bool_arg("use_hash_seed", &use_hash_seed)?;
         let user_site_directory = required_bool_arg("user_site_directory", &user_site_directory)?;
+        let user_site_packages = optional_str_arg("user_site_packages", &user_site_packages)?;
+        let utf8_mode = required_bool_arg("utf8_mode", &utf8_mode)?;
         required_type_arg("verbose", "int", &verbose)?;
+        optional_type_arg(
+            "windows_console_code_page",
+            "int",
+            &windows_console_code_page,
+        )?;
         let write_bytecode = required_bool_arg("write_bytecode", &write_bytecode)?;
         let write_modules_directory_env =
             optional_str_arg("write_modules_directory_env", &write_modules_directory_env)?;
 
+        // Arguments of config version 1 inverting current arguments.
+        let write_bytecode = negated_removed_bool_arg(
+            env,
+            "PythonInterpreterConfig()",
+            "dont_write_bytecode",
+            &dont_write_bytecode,
+            "write_bytecode",
+            write_bytecode,
+        )?;
+        let site_import = negated_removed_bool_arg(
+            env,
+            "PythonInterpreterConfig()",
+            "no_site",
+            &no_site,
+            "site_import",
+            site_import,
+        )?;
+        let user_site_directory = negated_removed_bool_arg(
+            env,
+            "PythonInterpreterConfig()",
+            "no_user_site_directory",
+            &no_user_site_directory,
+            "user_site_directory",
+            user_site_directory,
+        )?;
+
         let build_target = env.get("BUILD_TARGET_TRIPLE").unwrap().to_str();
 
         let mut run_count = 0;
@@ -138,6 +289,9 @@ impl EmbeddedPythonConfig {
         if run_noop {
             run_count += 1;
         }
+        if run_python_cli {
+            run_count += 1;
+        }
         if run_repl {
             run_count += 1;
         }
@@ -159,15 +313,34 @@ impl EmbeddedPythonConfig {
             RunMode::Module { module }
         } else if run_noop {
             RunMode::Noop
+        } else if run_python_cli {
+            RunMode::PythonCli
         } else {
             RunMode::Repl
         };
 
-        let (stdio_encoding_name, stdio_encoding_errors) = if let Some(ref v) = stdio_encoding {
-            let values: Vec<&str> = v.split(':').collect();
-            (Some(values[0].to_string()), Some(values[1].to_string()))
-        } else {
-            (None, None)
+        let (stdio_encoding_name, stdio_encoding_errors) = match stdio_encoding {
+            Some(value) => parse_stdio_encoding(&value).map_err(|e| RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: e,
+                label: "invalid value for stdio_encoding".to_string(),
+            })?,
+            None => (None, None),
+        };
+
+        let windows_console_code_page = match windows_console_code_page.get_type() {
+            "int" => match windows_console_code_page.to_int()? {
+                code_page if code_page > 0 && code_page <= 65535 => Some(code_page as u32),
+                code_page => {
+                    return Err(RuntimeError {
+                        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                        message: format!("{} is not a valid Windows code page", code_page),
+                        label: "invalid value for windows_console_code_page".to_string(),
+                    }
+                    .into());
+                }
+            },
+            _ => None,
         };
 
         let raw_allocator = match raw_allocator {
@@ -187,6 +360,43 @@ impl EmbeddedPythonConfig {
             None => default_raw_allocator(&build_target),
         };
 
+        let resources_integrity = match resources_integrity {
+            Some(x) => match x.as_ref() {
+                "none" => ResourcesIntegrity::None,
+                "import" => ResourcesIntegrity::Import,
+                "startup" => ResourcesIntegrity::Startup,
+                _ => {
+                    return Err(RuntimeError {
+                        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                        message: "resources_integrity must be 'none', 'import', or 'startup'"
+                            .to_string(),
+                        label: "invalid value for resources_integrity".to_string(),
+                    }
+                    .into());
+                }
+            },
+            None => ResourcesIntegrity::None,
+        };
+
+        // The digest of resources in a file can't be known when building an
+        // executable, as executables sharing the file add to it.
+        if resources_file.is_some() && resources_integrity == ResourcesIntegrity::Startup {
+            return Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: "resources_integrity='startup' cannot be used with resources_file"
+                    .to_string(),
+                label: "invalid value for resources_integrity".to_string(),
+            }
+            .into());
+        }
+
+        let file_emulation =
+            FileEmulation::try_from(file_emulation.as_str()).map_err(|e| RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: e,
+                label: "invalid value for file_emulation".to_string(),
+            })?;
+
         let terminfo_resolution = match terminfo_resolution {
             Some(x) => match x.as_ref() {
                 "dynamic" => TerminfoResolution::Dynamic,
@@ -213,6 +423,75 @@ impl EmbeddedPythonConfig {
             None => TerminfoResolution::None,
         };
 
+        let splash_image = match splash_image {
+            Some(path) => {
+                let context = env.get("CONTEXT").expect("CONTEXT not set");
+                let path = context.downcast_apply(|x: &EnvironmentContext| x.cwd.join(&path));
+
+                // Only the header is needed to tell BMP images apart.
+                let mut header = [0u8; 2];
+                let is_bmp = std::fs::File::open(&path)
+                    .and_then(|mut f| std::io::Read::read_exact(&mut f, &mut header))
+                    .map(|_| &header == b"BM")
+                    .unwrap_or(false);
+
+                if !is_bmp {
+                    return Err(RuntimeError {
+                        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                        message: format!("{} is not a BMP image", path.display()),
+                        label: "invalid value for splash_image".to_string(),
+                    }
+                    .into());
+                }
+
+                Some(path)
+            }
+            None => None,
+        };
+
+        let update = match (update_url, update_public_key, update_version) {
+            (Some(url), Some(public_key), Some(version)) => {
+                if public_key.len() != 64 || !public_key.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err(RuntimeError {
+                        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                        message: "update_public_key must be 64 hexadecimal characters".to_string(),
+                        label: "invalid value for update_public_key".to_string(),
+                    }
+                    .into());
+                }
+
+                if version
+                    .split('.')
+                    .any(|part| part.is_empty() || !part.chars().all(|c| c.is_ascii_digit()))
+                {
+                    return Err(RuntimeError {
+                        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                        message: "update_version must consist of dot-delimited integers"
+                            .to_string(),
+                        label: "invalid value for update_version".to_string(),
+                    }
+                    .into());
+                }
+
+                Some(UpdateChannel {
+                    url,
+                    public_key,
+                    version,
+                })
+            }
+            (None, None, None) => None,
+            _ => {
+                return Err(RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message:
+                        "update_url, update_public_key, and update_version must be set together"
+                            .to_string(),
+                    label: "incomplete update channel".to_string(),
+                }
+                .into());
+            }
+        };
+
         let sys_paths = match sys_paths.get_type() {
             "list" => sys_paths
                 .into_iter()
@@ -222,9 +501,142 @@ impl EmbeddedPythonConfig {
             _ => Vec::new(),
         };
 
-        let filesystem_importer = filesystem_importer || !sys_paths.is_empty();
+        let filesystem_import_paths = match filesystem_import_paths.get_type() {
+            "list" => filesystem_import_paths
+                .into_iter()
+                .unwrap()
+                .map(|x| x.to_string())
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let filesystem_importer = filesystem_importer
+            || !sys_paths.is_empty()
+            || !filesystem_import_paths.is_empty()
+            || user_site_packages.is_some();
+
+        let lazy_import_allow = match lazy_import_allow.get_type() {
+            "list" => lazy_import_allow
+                .into_iter()
+                .unwrap()
+                .map(|x| x.to_string())
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let lazy_import_deny = match lazy_import_deny.get_type() {
+            "list" => lazy_import_deny
+                .into_iter()
+                .unwrap()
+                .map(|x| x.to_string())
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let forward_signals: Vec<String> = match forward_signals.get_type() {
+            "list" => forward_signals
+                .into_iter()
+                .unwrap()
+                .map(|x| x.to_string())
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        if let Some(name) = forward_signals.iter().find(|name| !is_signal_name(name)) {
+            return Err(RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: format!("{} is not a signal name", name),
+                label: "invalid value for forward_signals".to_string(),
+            }
+            .into());
+        }
+
+        let remove_environment = match remove_environment.get_type() {
+            "list" => remove_environment
+                .into_iter()
+                .unwrap()
+                .map(|x| x.to_string())
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        // Strict isolation guarantees the host's Python installation and
+        // environment can't influence the application. Settings which would
+        // let them are rejected rather than silently overridden.
+        let (isolated, remove_environment) = if strict_isolation {
+            let conflict = if !ignore_environment {
+                Some("ignore_environment=False")
+            } else if user_site_directory {
+                Some("user_site_directory=True")
+            } else {
+                None
+            };
 
-        Ok(Value::new(EmbeddedPythonConfig {
+            if let Some(setting) = conflict {
+                return Err(RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message: format!("{} is incompatible with strict_isolation", setting),
+                    label: "strict_isolation".to_string(),
+                }
+                .into());
+            }
+
+            if let Some(path) = sys_paths.iter().find(|p| !p.starts_with("$ORIGIN")) {
+                return Err(RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message: format!(
+                        "sys_paths entry {} is outside the application; strict_isolation requires paths relative to $ORIGIN",
+                        path
+                    ),
+                    label: "strict_isolation".to_string(),
+                }
+                .into());
+            }
+
+            let mut remove_environment = remove_environment;
+            if !remove_environment.iter().any(|p| p == "PYTHON*") {
+                remove_environment.push("PYTHON*".to_string());
+            }
+
+            (true, remove_environment)
+        } else {
+            (isolated, remove_environment)
+        };
+
+        let environment = match environment.get_type() {
+            "dict" => {
+                let mut res = Vec::new();
+
+                for k in environment.into_iter()? {
+                    let v = environment.at(k.clone())?.to_string();
+                    res.push((k.to_string(), v));
+                }
+
+                res
+            }
+            _ => Vec::new(),
+        };
+
+        let audit_policy = match audit_policy.get_type() {
+            "dict" => {
+                let mut res = Vec::new();
+
+                for k in audit_policy.into_iter()? {
+                    let v = audit_policy.at(k.clone())?.to_string();
+                    let action = AuditAction::try_from(v.as_str()).map_err(|e| RuntimeError {
+                        code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                        message: e,
+                        label: "invalid value for audit_policy".to_string(),
+                    })?;
+                    res.push((k.to_string(), action));
+                }
+
+                res
+            }
+            _ => Vec::new(),
+        };
+
+        let config = EmbeddedPythonConfig {
             bytes_warning: bytes_warning.to_int().unwrap() as i32,
             ignore_environment,
             inspect,
@@ -234,16 +646,30 @@ impl EmbeddedPythonConfig {
             legacy_windows_stdio,
             optimize_level: optimize_level.to_int().unwrap(),
             parser_debug,
+            pkg_resources_compat,
+            file_emulation,
+            file_extraction_dir,
+            lazy_import,
+            lazy_import_allow,
+            lazy_import_deny,
             quiet,
             stdio_encoding_name,
             stdio_encoding_errors,
+            utf8_mode,
+            windows_console_code_page,
             unbuffered_stdio,
             filesystem_importer,
             site_import,
             sys_frozen,
             sys_meipass,
             sys_paths,
+            filesystem_import_paths,
+            user_site_packages,
             raw_allocator,
+            resources_file,
+            resources_integrity,
+            resources_key_env,
+            resources_key_file,
             run_mode,
             terminfo_resolution,
             use_hash_seed,
@@ -251,7 +677,33 @@ impl EmbeddedPythonConfig {
             verbose: verbose.to_int().unwrap() as i32,
             write_bytecode,
             write_modules_directory_env,
-        }))
+            import_profile_env,
+            self_test,
+            debug_console,
+            faulthandler,
+            crash_dir,
+            update,
+            splash_image,
+            inherit_environment,
+            remove_environment,
+            environment,
+            dotenv,
+            install_signal_handlers,
+            forward_signals,
+            import_allow: None,
+            import_deny: Vec::new(),
+            audit_policy,
+        };
+
+        config
+            .resources_file_relative_path()
+            .map_err(|e| RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: e.to_string(),
+                label: "invalid value for resources_file".to_string(),
+            })?;
+
+        Ok(Value::new(config))
     }
 }
 
@@ -259,67 +711,137 @@ starlark_module! { embedded_python_config_module =>
     #[allow(non_snake_case, clippy::ptr_arg)]
     PythonInterpreterConfig(
         env env,
+        audit_policy=None,
         bytes_warning=0,
+        crash_dir=None,
+        debug_console=false,
+        dont_write_bytecode=None,
+        dotenv=false,
+        environment=None,
         ignore_environment=true,
+        import_profile_env=None,
+        inherit_environment=true,
+        install_signal_handlers=true,
         inspect=false,
         interactive=false,
         isolated=false,
+        lazy_import=false,
+        lazy_import_allow=None,
+        lazy_import_deny=None,
         legacy_windows_fs_encoding=false,
         legacy_windows_stdio=false,
+        no_site=None,
+        no_user_site_directory=None,
         optimize_level=0,
         parser_debug=false,
+        pkg_resources_compat=false,
         stdio_encoding=None,
         unbuffered_stdio=false,
+        faulthandler=false,
+        file_emulation="none",
+        file_extraction_dir=None,
+        filesystem_import_paths=None,
         filesystem_importer=false,
+        forward_signals=None,
         quiet=false,
         run_eval=None,
         run_file=None,
         run_module=None,
         run_noop=false,
+        run_python_cli=false,
         run_repl=false,
+        self_test=false,
         site_import=false,
+        splash_image=None,
+        strict_isolation=false,
         sys_frozen=false,
         sys_meipass=false,
         sys_paths=None,
         raw_allocator=None,
+        remove_environment=None,
+        resources_file=None,
+        resources_integrity=None,
+        resources_key_env=None,
+        resources_key_file=None,
         terminfo_resolution="dynamic",
         terminfo_dirs=None,
+        update_public_key=None,
+        update_url=None,
+        update_version=None,
         use_hash_seed=false,
         user_site_directory=false,
+        user_site_packages=None,
+        utf8_mode=false,
         verbose=0,
+        windows_console_code_page=None,
         write_bytecode=false,
         write_modules_directory_env=None
     ) {
         EmbeddedPythonConfig::starlark_new(
             &env,
+            &audit_policy,
             &bytes_warning,
+            &crash_dir,
+            &debug_console,
+            &dont_write_bytecode,
+            &dotenv,
+            &environment,
             &ignore_environment,
+            &import_profile_env,
+            &inherit_environment,
+            &install_signal_handlers,
             &inspect,
             &interactive,
             &isolated,
+            &lazy_import,
+            &lazy_import_allow,
+            &lazy_import_deny,
             &legacy_windows_fs_encoding,
             &legacy_windows_stdio,
+            &no_site,
+            &no_user_site_directory,
             &optimize_level,
             &parser_debug,
+            &pkg_resources_compat,
             &stdio_encoding,
             &unbuffered_stdio,
+            &faulthandler,
+            &file_emulation,
+            &file_extraction_dir,
+            &filesystem_import_paths,
             &filesystem_importer,
+            &forward_signals,
             &quiet,
             &run_eval,
             &run_file,
             &run_module,
             &run_noop,
+            &run_python_cli,
             &run_repl,
+            &self_test,
             &site_import,
+            &splash_image,
+            &strict_isolation,
             &sys_frozen,
             &sys_meipass,
             &sys_paths,
             &raw_allocator,
+            &remove_environment,
+            &resources_file,
+            &resources_integrity,
+            &resources_key_env,
+            &resources_key_file,
             &terminfo_resolution,
             &terminfo_dirs,
+            &update_public_key,
+            &update_url,
+            &update_version,
             &use_hash_seed,
             &user_site_directory,
+            &user_site_packages,
+            &utf8_mode,
             &verbose,
+            &windows_console_code_page,
             &write_bytecode,
             &write_modules_directory_env
         )
@@ -345,28 +867,250 @@ mod tests {
             legacy_windows_stdio: false,
             optimize_level: 0,
             parser_debug: false,
+            pkg_resources_compat: false,
+            file_emulation: FileEmulation::None,
+            file_extraction_dir: None,
+            lazy_import: false,
+            lazy_import_allow: Vec::new(),
+            lazy_import_deny: Vec::new(),
             quiet: false,
             use_hash_seed: false,
             verbose: 0,
             stdio_encoding_name: None,
             stdio_encoding_errors: None,
+            utf8_mode: false,
+            windows_console_code_page: None,
             unbuffered_stdio: false,
             filesystem_importer: false,
             site_import: false,
             sys_frozen: false,
             sys_meipass: false,
             sys_paths: Vec::new(),
+            filesystem_import_paths: Vec::new(),
+            user_site_packages: None,
             raw_allocator: default_raw_allocator(crate::project_building::HOST),
+            resources_file: None,
+            resources_integrity: ResourcesIntegrity::None,
+            resources_key_env: None,
+            resources_key_file: None,
             run_mode: RunMode::Repl,
             terminfo_resolution: TerminfoResolution::Dynamic,
             user_site_directory: false,
             write_bytecode: false,
             write_modules_directory_env: None,
+            import_profile_env: None,
+            self_test: false,
+            debug_console: false,
+            faulthandler: false,
+            crash_dir: None,
+            update: None,
+            splash_image: None,
+            inherit_environment: true,
+            remove_environment: Vec::new(),
+            environment: Vec::new(),
+            dotenv: false,
+            install_signal_handlers: true,
+            forward_signals: Vec::new(),
+            import_allow: None,
+            import_deny: Vec::new(),
+            audit_policy: Vec::new(),
         };
 
         c.downcast_apply(|x: &EmbeddedPythonConfig| assert_eq!(x, &wanted));
     }
 
+    #[test]
+    fn test_self_test() {
+        let c = starlark_ok("PythonInterpreterConfig(self_test=True)");
+        c.downcast_apply(|x: &EmbeddedPythonConfig| assert!(x.self_test));
+
+        starlark_nok("PythonInterpreterConfig(self_test='yes')");
+    }
+
+    #[test]
+    fn test_debug_console() {
+        let c = starlark_ok("PythonInterpreterConfig(debug_console=True)");
+        c.downcast_apply(|x: &EmbeddedPythonConfig| assert!(x.debug_console));
+
+        starlark_nok("PythonInterpreterConfig(debug_console='yes')");
+    }
+
+    #[test]
+    fn test_faulthandler() {
+        let c =
+            starlark_ok("PythonInterpreterConfig(faulthandler=True, crash_dir='$ORIGIN/crashes')");
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert!(x.faulthandler);
+            assert_eq!(x.crash_dir, Some("$ORIGIN/crashes".to_string()));
+        });
+
+        starlark_nok("PythonInterpreterConfig(faulthandler='yes')");
+        starlark_nok("PythonInterpreterConfig(crash_dir=True)");
+    }
+
+    #[test]
+    fn test_strict_isolation() {
+        let c = starlark_ok(
+            "PythonInterpreterConfig(strict_isolation=True, sys_paths=['$ORIGIN/lib'], remove_environment=['FOO'])",
+        );
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert!(x.isolated);
+            assert!(x.ignore_environment);
+            assert!(!x.user_site_directory);
+            assert_eq!(
+                x.remove_environment,
+                vec!["FOO".to_string(), "PYTHON*".to_string()]
+            );
+        });
+
+        starlark_nok("PythonInterpreterConfig(strict_isolation=True, ignore_environment=False)");
+        starlark_nok("PythonInterpreterConfig(strict_isolation=True, user_site_directory=True)");
+        starlark_nok(
+            "PythonInterpreterConfig(strict_isolation=True, sys_paths=['/usr/lib/python3'])",
+        );
+    }
+
+    #[test]
+    fn test_filesystem_import_paths() {
+        let c = starlark_ok(
+            "PythonInterpreterConfig(filesystem_import_paths=['$ORIGIN/plugins', '${HOME}/.myapp/plugins'])",
+        );
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(
+                x.filesystem_import_paths,
+                vec!["$ORIGIN/plugins", "${HOME}/.myapp/plugins"]
+            );
+            // Plugin directories are loaded by the filesystem importer.
+            assert!(x.filesystem_importer);
+        });
+
+        starlark_nok("PythonInterpreterConfig(filesystem_import_paths='$ORIGIN/plugins')");
+    }
+
+    #[test]
+    fn test_user_site_packages() {
+        let c = starlark_ok(
+            "PythonInterpreterConfig(user_site_packages='$USER_DATA/myapp/site-packages')",
+        );
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(
+                x.user_site_packages,
+                Some("$USER_DATA/myapp/site-packages".to_string())
+            );
+            assert!(x.filesystem_importer);
+        });
+    }
+
+    #[test]
+    fn test_environment() {
+        let c = starlark_ok(
+            "PythonInterpreterConfig(environment={'FOO': 'bar', 'PATH': '$ORIGIN/bin'}, dotenv=True, inherit_environment=False, remove_environment=['PYTHON*'])",
+        );
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(
+                x.environment,
+                vec![
+                    ("FOO".to_string(), "bar".to_string()),
+                    ("PATH".to_string(), "$ORIGIN/bin".to_string())
+                ]
+            );
+            assert!(x.dotenv);
+            assert!(!x.inherit_environment);
+            assert_eq!(x.remove_environment, vec!["PYTHON*".to_string()]);
+        });
+
+        starlark_nok("PythonInterpreterConfig(environment={'FOO': 1})");
+        starlark_nok("PythonInterpreterConfig(remove_environment='PYTHONPATH')");
+    }
+
+    #[test]
+    fn test_audit_policy() {
+        let c = starlark_ok("PythonInterpreterConfig(audit_policy={'open': 'log', 'subprocess.Popen': 'block', '*': 'allow'})");
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(
+                x.audit_policy,
+                vec![
+                    ("open".to_string(), AuditAction::Log),
+                    ("subprocess.Popen".to_string(), AuditAction::Block),
+                    ("*".to_string(), AuditAction::Allow),
+                ]
+            );
+        });
+
+        let err = starlark_nok("PythonInterpreterConfig(audit_policy={'open': 'deny'})");
+        assert_eq!(
+            err.message,
+            "deny is not a valid audit action; expected allow, log, or block"
+        );
+        starlark_nok("PythonInterpreterConfig(audit_policy={'open': True})");
+    }
+
+    #[test]
+    fn test_signals() {
+        let c = starlark_ok(
+            "PythonInterpreterConfig(install_signal_handlers=False, forward_signals=['SIGTERM', 'SIGINT'])",
+        );
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert!(!x.install_signal_handlers);
+            assert_eq!(x.forward_signals, vec!["SIGTERM", "SIGINT"]);
+        });
+
+        let err = starlark_nok("PythonInterpreterConfig(forward_signals=['TERM'])");
+        assert_eq!(err.message, "TERM is not a signal name");
+        starlark_nok("PythonInterpreterConfig(forward_signals='SIGTERM')");
+        starlark_nok("PythonInterpreterConfig(install_signal_handlers='yes')");
+    }
+
+    #[test]
+    fn test_splash_image() {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test").unwrap();
+        let image = temp_dir.path().join("splash.bmp");
+        std::fs::write(&image, b"BM").unwrap();
+        let not_image = temp_dir.path().join("splash.png");
+        std::fs::write(&not_image, b"\x89PNG").unwrap();
+
+        let c = starlark_ok(&format!(
+            "PythonInterpreterConfig(splash_image='{}')",
+            image.display()
+        ));
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(x.splash_image, Some(image.clone()));
+        });
+
+        starlark_nok(&format!(
+            "PythonInterpreterConfig(splash_image='{}')",
+            not_image.display()
+        ));
+        starlark_nok("PythonInterpreterConfig(splash_image='missing.bmp')");
+    }
+
+    #[test]
+    fn test_update() {
+        let public_key = "ab".repeat(32);
+
+        let c = starlark_ok(&format!(
+            "PythonInterpreterConfig(update_url='https://example.com/app.update.json', update_public_key='{}', update_version='1.0')",
+            public_key
+        ));
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(
+                x.update,
+                Some(UpdateChannel {
+                    url: "https://example.com/app.update.json".to_string(),
+                    public_key: public_key.clone(),
+                    version: "1.0".to_string(),
+                })
+            );
+        });
+
+        starlark_nok("PythonInterpreterConfig(update_url='https://example.com/app.update.json')");
+        starlark_nok("PythonInterpreterConfig(update_url='https://example.com/app.update.json', update_public_key='abcd', update_version='1.0')");
+        starlark_nok(&format!(
+            "PythonInterpreterConfig(update_url='https://example.com/app.update.json', update_public_key='{}', update_version='1.0-beta')",
+            public_key
+        ));
+    }
+
     #[test]
     fn test_bytes_warning() {
         let c = starlark_ok("PythonInterpreterConfig(bytes_warning=2)");
@@ -379,6 +1123,24 @@ mod tests {
         c.downcast_apply(|x: &EmbeddedPythonConfig| assert_eq!(x.optimize_level, 1));
     }
 
+    #[test]
+    fn test_import_profile_env() {
+        let c =
+            starlark_ok("PythonInterpreterConfig(import_profile_env='PYOXIDIZER_IMPORT_PROFILE')");
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(
+                x.import_profile_env,
+                Some("PYOXIDIZER_IMPORT_PROFILE".to_string())
+            )
+        });
+    }
+
+    #[test]
+    fn test_pkg_resources_compat() {
+        let c = starlark_ok("PythonInterpreterConfig(pkg_resources_compat=True)");
+        c.downcast_apply(|x: &EmbeddedPythonConfig| assert!(x.pkg_resources_compat));
+    }
+
     #[test]
     fn test_sys_paths() {
         let c = starlark_ok("PythonInterpreterConfig(sys_paths=['foo', 'bar'])");
@@ -395,7 +1157,35 @@ mod tests {
         c.downcast_apply(|x: &EmbeddedPythonConfig| {
             assert_eq!(x.stdio_encoding_name, Some("foo".to_string()));
             assert_eq!(x.stdio_encoding_errors, Some("strict".to_string()));
-        })
+        });
+
+        let c = starlark_ok("PythonInterpreterConfig(stdio_encoding=':replace')");
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(x.stdio_encoding_name, None);
+            assert_eq!(x.stdio_encoding_errors, Some("replace".to_string()));
+        });
+
+        assert_eq!(
+            parse_stdio_encoding("utf-8"),
+            Ok((Some("utf-8".to_string()), None))
+        );
+        assert!(parse_stdio_encoding(":").is_err());
+        assert!(parse_stdio_encoding("utf-8:strict:replace").is_err());
+        starlark_nok("PythonInterpreterConfig(stdio_encoding='')");
+    }
+
+    #[test]
+    fn test_utf8_mode() {
+        let c =
+            starlark_ok("PythonInterpreterConfig(utf8_mode=True, windows_console_code_page=65001)");
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert!(x.utf8_mode);
+            assert_eq!(x.windows_console_code_page, Some(65001));
+        });
+
+        let err = starlark_nok("PythonInterpreterConfig(windows_console_code_page=-1)");
+        assert_eq!(err.message, "-1 is not a valid Windows code page");
+        starlark_nok("PythonInterpreterConfig(windows_console_code_page='utf-8')");
     }
 
     #[test]
@@ -461,6 +1251,16 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_run_python_cli() {
+        let c = starlark_ok("PythonInterpreterConfig(run_python_cli=True)");
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(x.run_mode, RunMode::PythonCli);
+        });
+
+        starlark_nok("PythonInterpreterConfig(run_python_cli=True, run_repl=True)");
+    }
+
     #[test]
     fn test_run_repl() {
         let c = starlark_ok("PythonInterpreterConfig(run_repl=True)");
@@ -486,4 +1286,100 @@ mod tests {
             );
         });
     }
+
+    #[test]
+    fn test_resources_integrity() {
+        let c = starlark_ok("PythonInterpreterConfig(resources_integrity='import')");
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(x.resources_integrity, ResourcesIntegrity::Import)
+        });
+
+        let c = starlark_ok("PythonInterpreterConfig(resources_integrity='startup')");
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(x.resources_integrity, ResourcesIntegrity::Startup)
+        });
+
+        let err = starlark_nok("PythonInterpreterConfig(resources_integrity='bogus')");
+        assert!(err.message.starts_with("resources_integrity must be"));
+    }
+
+    #[test]
+    fn test_file_emulation() {
+        let c = starlark_ok(
+            "PythonInterpreterConfig(file_emulation='extract-on-access', file_extraction_dir='$ORIGIN/files')",
+        );
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(x.file_emulation, FileEmulation::ExtractOnAccess);
+            assert_eq!(x.file_extraction_dir, Some("$ORIGIN/files".to_string()));
+        });
+
+        let c = starlark_ok("PythonInterpreterConfig(file_emulation='fake-path')");
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(x.file_emulation, FileEmulation::FakePath)
+        });
+
+        let err = starlark_nok("PythonInterpreterConfig(file_emulation='bogus')");
+        assert!(err
+            .message
+            .starts_with("bogus is not a valid file emulation policy"));
+    }
+
+    #[test]
+    fn test_lazy_import() {
+        let c = starlark_ok(
+            "PythonInterpreterConfig(lazy_import=True, lazy_import_allow=['numpy'], lazy_import_deny=['numpy.core'])",
+        );
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert!(x.lazy_import);
+            assert_eq!(x.lazy_import_allow, vec!["numpy".to_string()]);
+            assert_eq!(x.lazy_import_deny, vec!["numpy.core".to_string()]);
+        });
+
+        starlark_nok("PythonInterpreterConfig(lazy_import_allow=[True])");
+    }
+
+    #[test]
+    fn test_resources_key() {
+        let c = starlark_ok(
+            "PythonInterpreterConfig(resources_key_env='APP_KEY', resources_key_file='$ORIGIN/key')",
+        );
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(x.resources_key_env, Some("APP_KEY".to_string()));
+            assert_eq!(x.resources_key_file, Some("$ORIGIN/key".to_string()));
+        });
+    }
+
+    #[test]
+    fn test_resources_file() {
+        let c = starlark_ok("PythonInterpreterConfig(resources_file='$ORIGIN/app.pyres')");
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert_eq!(x.resources_file, Some("$ORIGIN/app.pyres".to_string()));
+        });
+
+        let err = starlark_nok("PythonInterpreterConfig(resources_file='/opt/app.pyres')");
+        assert!(err.message.contains("must be a path under $ORIGIN"));
+
+        let err = starlark_nok(
+            "PythonInterpreterConfig(resources_file='$ORIGIN/app.pyres', resources_integrity='startup')",
+        );
+        assert!(err.message.contains("cannot be used with resources_file"));
+    }
+
+    #[test]
+    fn test_config_version_1_arguments() {
+        let c = starlark_ok(
+            "PythonInterpreterConfig(dont_write_bytecode=False, no_site=False, no_user_site_directory=True)",
+        );
+        c.downcast_apply(|x: &EmbeddedPythonConfig| {
+            assert!(x.write_bytecode);
+            assert!(x.site_import);
+            assert!(!x.user_site_directory);
+        });
+
+        let mut env = starlark_env();
+        starlark_eval_in_env(&mut env, "set_config_version(2)").unwrap();
+        let err =
+            starlark_eval_in_env(&mut env, "PythonInterpreterConfig(no_site=True)").unwrap_err();
+        assert!(err.message.contains("pyoxidizer migrate-config"));
+    }
 }
@@ -3,19 +3,42 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
+    super::util::{required_bool_arg, required_str_arg},
+    crate::licensing::LicensedComponent,
+    crate::py_packaging::bytecode::PycInvalidationMode,
     crate::py_packaging::resource::{
-        BytecodeModule, BytecodeOptimizationLevel, ExtensionModuleData, PythonResource,
-        ResourceData, SourceModule,
+        BytecodeModule, BytecodeOptimizationLevel, DataLocation, DistributionResource,
+        ExtensionModuleData, PythonResource, ResourceData, SourceModule,
     },
     crate::py_packaging::standalone_distribution::ExtensionModule,
     starlark::environment::Environment,
-    starlark::values::{default_compare, TypedValue, Value, ValueError, ValueResult},
-    starlark::{any, immutable, not_supported},
+    starlark::values::{default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult},
+    starlark::{
+        any, immutable, not_supported, starlark_fun, starlark_module, starlark_signature,
+        starlark_signature_extraction, starlark_signatures,
+    },
     std::any::Any,
     std::cmp::Ordering,
     std::collections::HashMap,
 };
 
+/// Resolve Python source code to a string for exposing to Starlark.
+fn source_value(source: &DataLocation, label: &str) -> ValueResult {
+    let data = source.resolve().map_err(|e| RuntimeError {
+        code: "PYOXIDIZER_BUILD",
+        message: e.to_string(),
+        label: label.to_string(),
+    })?;
+
+    let source = String::from_utf8(data).map_err(|_| RuntimeError {
+        code: "PYOXIDIZER_BUILD",
+        message: "source is not valid UTF-8".to_string(),
+        label: label.to_string(),
+    })?;
+
+    Ok(Value::new(source))
+}
+
 #[derive(Debug, Clone)]
 pub struct PythonSourceModule {
     pub module: SourceModule,
@@ -51,8 +74,7 @@ impl TypedValue for PythonSourceModule {
     fn get_attr(&self, attribute: &str) -> ValueResult {
         let v = match attribute {
             "name" => Value::new(self.module.name.clone()),
-            // TODO expose source
-            // "source" => Value::new(self.module.source),
+            "source" => source_value(&self.module.source, "PythonSourceModule.source")?,
             "is_package" => Value::new(self.module.is_package),
             attr => {
                 return Err(ValueError::OperationNotSupported {
@@ -69,8 +91,7 @@ impl TypedValue for PythonSourceModule {
     fn has_attr(&self, attribute: &str) -> Result<bool, ValueError> {
         Ok(match attribute {
             "name" => true,
-            // TODO expose source
-            // "source" => true,
+            "source" => true,
             "is_package" => true,
             _ => false,
         })
@@ -115,14 +136,15 @@ impl TypedValue for PythonBytecodeModule {
     fn get_attr(&self, attribute: &str) -> ValueResult {
         let v = match attribute {
             "name" => Value::new(self.module.name.clone()),
-            // TODO expose source
-            // "source" => Value::new(self.module.source),
+            "source" => source_value(&self.module.source, "PythonBytecodeModule.source")?,
             "optimize_level" => Value::new(match self.module.optimize_level {
                 BytecodeOptimizationLevel::Zero => 0,
                 BytecodeOptimizationLevel::One => 1,
                 BytecodeOptimizationLevel::Two => 2,
             }),
             "is_package" => Value::new(self.module.is_package),
+            "source_stripped" => Value::new(self.module.source_stripped),
+            "invalidation_mode" => Value::new(self.module.invalidation_mode.as_str().to_string()),
             attr => {
                 return Err(ValueError::OperationNotSupported {
                     op: format!(".{}", attr),
@@ -138,10 +160,11 @@ impl TypedValue for PythonBytecodeModule {
     fn has_attr(&self, attribute: &str) -> Result<bool, ValueError> {
         Ok(match attribute {
             "name" => true,
-            // TODO expose source
-            // "source" => true,
+            "source" => true,
             "optimize_level" => true,
             "is_package" => true,
+            "source_stripped" => true,
+            "invalidation_mode" => true,
             _ => false,
         })
     }
@@ -230,6 +253,17 @@ impl PythonExtensionModuleFlavor {
             PythonExtensionModuleFlavor::DynamicLibrary(m) => m.name.clone(),
         }
     }
+
+    /// Names of libraries that need to be linked to use this extension module.
+    pub fn required_libraries(&self) -> Vec<String> {
+        match self {
+            PythonExtensionModuleFlavor::Distribution(m) => {
+                m.links.iter().map(|l| l.name.clone()).collect()
+            }
+            PythonExtensionModuleFlavor::StaticallyLinked(m) => m.libraries.clone(),
+            PythonExtensionModuleFlavor::DynamicLibrary(m) => m.libraries.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -264,13 +298,131 @@ impl TypedValue for PythonExtensionModule {
         default_compare(self, other)
     }
 
+    fn get_attr(&self, attribute: &str) -> ValueResult {
+        let optional_str = |value: &Option<String>| match value {
+            Some(value) => Value::new(value.clone()),
+            None => Value::new(None),
+        };
+
+        let v = match (attribute, &self.em) {
+            ("name", _) => Value::new(self.em.name()),
+            ("init_fn", PythonExtensionModuleFlavor::Distribution(m)) => optional_str(&m.init_fn),
+            ("init_fn", PythonExtensionModuleFlavor::StaticallyLinked(m))
+            | ("init_fn", PythonExtensionModuleFlavor::DynamicLibrary(m)) => {
+                optional_str(&m.init_fn)
+            }
+            ("required_libraries", _) => Value::from(
+                self.em
+                    .required_libraries()
+                    .into_iter()
+                    .map(Value::new)
+                    .collect::<Vec<Value>>(),
+            ),
+            ("is_stdlib", PythonExtensionModuleFlavor::Distribution(_)) => Value::new(true),
+            ("is_stdlib", _) => Value::new(false),
+            ("is_builtin_default", PythonExtensionModuleFlavor::Distribution(m)) => {
+                Value::new(m.builtin_default)
+            }
+            ("is_builtin_default", _) => Value::new(false),
+            ("variant", PythonExtensionModuleFlavor::Distribution(m)) => {
+                Value::new(m.variant.clone())
+            }
+            ("variant", _) => Value::new(None),
+            ("licenses", PythonExtensionModuleFlavor::Distribution(m)) => match &m.licenses {
+                Some(licenses) => Value::from(
+                    licenses
+                        .iter()
+                        .map(|l| Value::new(l.clone()))
+                        .collect::<Vec<Value>>(),
+                ),
+                None => Value::new(None),
+            },
+            ("licenses", _) => Value::new(None),
+            ("license_public_domain", PythonExtensionModuleFlavor::Distribution(m)) => {
+                match m.license_public_domain {
+                    Some(value) => Value::new(value),
+                    None => Value::new(None),
+                }
+            }
+            ("license_public_domain", _) => Value::new(None),
+            (attr, _) => {
+                return Err(ValueError::OperationNotSupported {
+                    op: format!(".{}", attr),
+                    left: "PythonExtensionModule".to_string(),
+                    right: None,
+                })
+            }
+        };
+
+        Ok(v)
+    }
+
+    fn has_attr(&self, attribute: &str) -> Result<bool, ValueError> {
+        Ok(match attribute {
+            "name" => true,
+            "init_fn" => true,
+            "required_libraries" => true,
+            "is_stdlib" => true,
+            "is_builtin_default" => true,
+            "variant" => true,
+            "licenses" => true,
+            "license_public_domain" => true,
+            _ => false,
+        })
+    }
+}
+
+/// Licensing metadata of an installed Python package.
+#[derive(Debug, Clone)]
+pub struct PythonPackageLicense {
+    pub license: LicensedComponent,
+}
+
+impl TypedValue for PythonPackageLicense {
+    immutable!();
+    any!();
+    not_supported!(
+        binop, dir_attr, function, get_hash, indexable, iterable, sequence, set_attr, to_int
+    );
+
+    fn to_str(&self) -> String {
+        format!("PythonPackageLicense<name={}>", self.license.name)
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "PythonPackageLicense"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+
     fn get_attr(&self, attribute: &str) -> ValueResult {
         let v = match attribute {
-            "name" => Value::new(self.em.name()),
+            "name" => Value::new(self.license.name.clone()),
+            "version" => match &self.license.version {
+                Some(version) => Value::new(version.clone()),
+                None => Value::new(None),
+            },
+            "licenses" => Value::from(
+                self.license
+                    .licenses
+                    .iter()
+                    .map(|l| Value::new(l.clone()))
+                    .collect::<Vec<Value>>(),
+            ),
             attr => {
                 return Err(ValueError::OperationNotSupported {
                     op: format!(".{}", attr),
-                    left: "PythonExtensionModule".to_string(),
+                    left: "PythonPackageLicense".to_string(),
                     right: None,
                 })
             }
@@ -281,6 +433,71 @@ impl TypedValue for PythonExtensionModule {
 
     fn has_attr(&self, attribute: &str) -> Result<bool, ValueError> {
         Ok(match attribute {
+            "name" => true,
+            "version" => true,
+            "licenses" => true,
+            _ => false,
+        })
+    }
+}
+
+/// A file in the distribution metadata of an installed Python package.
+#[derive(Debug, Clone)]
+pub struct PythonPackageDistributionResource {
+    pub resource: DistributionResource,
+}
+
+impl TypedValue for PythonPackageDistributionResource {
+    immutable!();
+    any!();
+    not_supported!(
+        binop, dir_attr, function, get_hash, indexable, iterable, sequence, set_attr, to_int
+    );
+
+    fn to_str(&self) -> String {
+        format!(
+            "PythonPackageDistributionResource<package={}, version={}, name={}>",
+            self.resource.package, self.resource.version, self.resource.name
+        )
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "PythonPackageDistributionResource"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+
+    fn get_attr(&self, attribute: &str) -> ValueResult {
+        let v = match attribute {
+            "package" => Value::new(self.resource.package.clone()),
+            "version" => Value::new(self.resource.version.clone()),
+            "name" => Value::new(self.resource.name.clone()),
+            attr => {
+                return Err(ValueError::OperationNotSupported {
+                    op: format!(".{}", attr),
+                    left: "PythonPackageDistributionResource".to_string(),
+                    right: None,
+                })
+            }
+        };
+
+        Ok(v)
+    }
+
+    fn has_attr(&self, attribute: &str) -> Result<bool, ValueError> {
+        Ok(match attribute {
+            "package" => true,
+            "version" => true,
             "name" => true,
             _ => false,
         })
@@ -313,6 +530,8 @@ impl<'a> From<&'a PythonResource> for Value {
                     source: source.clone(),
                     optimize_level: BytecodeOptimizationLevel::from(*optimize_level),
                     is_package: *is_package,
+                    source_stripped: false,
+                    invalidation_mode: PycInvalidationMode::default(),
                 },
             }),
 
@@ -343,6 +562,58 @@ impl<'a> From<&'a PythonResource> for Value {
                     em: PythonExtensionModuleFlavor::StaticallyLinked(em.clone()),
                 })
             }
+
+            PythonResource::PackageLicense(license) => Value::new(PythonPackageLicense {
+                license: license.clone(),
+            }),
+
+            PythonResource::DistributionResource(resource) => {
+                Value::new(PythonPackageDistributionResource {
+                    resource: resource.clone(),
+                })
+            }
         }
     }
 }
+
+/// PythonSourceModule(name, source, is_package=false)
+fn starlark_source_module(name: &Value, source: &Value, is_package: &Value) -> ValueResult {
+    let name = required_str_arg("name", &name)?;
+    let source = required_str_arg("source", &source)?;
+    let is_package = required_bool_arg("is_package", &is_package)?;
+
+    Ok(Value::new(PythonSourceModule {
+        module: SourceModule {
+            name,
+            source: DataLocation::Memory(source.into_bytes()),
+            is_package,
+        },
+    }))
+}
+
+/// PythonResourceData(package, name, data)
+fn starlark_resource_data(package: &Value, name: &Value, data: &Value) -> ValueResult {
+    let package = required_str_arg("package", &package)?;
+    let name = required_str_arg("name", &name)?;
+    let data = required_str_arg("data", &data)?;
+
+    Ok(Value::new(PythonResourceData {
+        data: ResourceData {
+            package,
+            name,
+            data: DataLocation::Memory(data.into_bytes()),
+        },
+    }))
+}
+
+starlark_module! { python_resource_env =>
+    #[allow(non_snake_case, clippy::ptr_arg)]
+    PythonSourceModule(name, source, is_package=false) {
+        starlark_source_module(&name, &source, &is_package)
+    }
+
+    #[allow(non_snake_case, clippy::ptr_arg)]
+    PythonResourceData(package, name, data) {
+        starlark_resource_data(&package, &name, &data)
+    }
+}
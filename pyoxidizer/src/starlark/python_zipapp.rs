@@ -0,0 +1,343 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use {
+    super::env::EnvironmentContext,
+    super::python_resource::{PythonBytecodeModule, PythonResourceData, PythonSourceModule},
+    super::target::{BuildContext, BuildTarget, ResolvedTarget, RunMode},
+    super::util::{required_bool_arg, required_str_arg, required_type_arg},
+    crate::py_packaging::bytecode::PycInvalidationMode,
+    crate::py_packaging::resource::BytecodeOptimizationLevel,
+    crate::py_packaging::zipapp::ZipAppBuilder,
+    anyhow::{Context, Result},
+    slog::{info, warn},
+    starlark::environment::Environment,
+    starlark::values::{
+        default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult,
+        INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+    },
+    starlark::{
+        any, immutable, not_supported, starlark_fun, starlark_module, starlark_signature,
+        starlark_signature_extraction, starlark_signatures,
+    },
+    std::any::Any,
+    std::cmp::Ordering,
+    std::collections::HashMap,
+    std::convert::TryFrom,
+};
+
+/// Represents a builder for a Python zip application.
+pub struct PythonZipApp {
+    pub zipapp: ZipAppBuilder,
+}
+
+impl TypedValue for PythonZipApp {
+    immutable!();
+    any!();
+    not_supported!(binop, container, function, get_hash, to_int);
+
+    fn to_str(&self) -> String {
+        format!("PythonZipApp<name={}>", self.zipapp.name)
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        "PythonZipApp"
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+impl BuildTarget for PythonZipApp {
+    fn build(&mut self, context: &BuildContext) -> Result<ResolvedTarget> {
+        let dest_path = context.output_path.join(self.zipapp.filename());
+        warn!(
+            &context.logger,
+            "writing zip application to {}",
+            dest_path.display()
+        );
+
+        let mut fh = std::fs::File::create(&dest_path)
+            .context(format!("creating {}", dest_path.display()))?;
+        self.zipapp
+            .write_zipapp(&context.logger, &mut fh)
+            .context(format!("writing {}", dest_path.display()))?;
+
+        // Without an interpreter line, the archive can only be run by passing it
+        // to a Python interpreter.
+        let run_mode = if self.zipapp.interpreter.is_some() {
+            crate::app_packaging::resource::set_executable(&mut fh)
+                .context("making zip application executable")?;
+
+            RunMode::Path {
+                path: dest_path.clone(),
+            }
+        } else {
+            RunMode::None
+        };
+
+        Ok(ResolvedTarget {
+            run_mode,
+            output_path: context.output_path.clone(),
+            artifacts: vec![dest_path],
+        })
+    }
+}
+
+// Starlark functions.
+impl PythonZipApp {
+    /// PythonZipApp.add_python_resource(resource, add_source_module=true, add_bytecode_module=true, optimize_level=0, pyc_invalidation_mode="unchecked-hash")
+    pub fn starlark_add_python_resource(
+        &mut self,
+        env: &Environment,
+        resource: &Value,
+        add_source_module: &Value,
+        add_bytecode_module: &Value,
+        optimize_level: &Value,
+        pyc_invalidation_mode: &Value,
+    ) -> ValueResult {
+        let add_source_module = required_bool_arg("add_source_module", &add_source_module)?;
+        let add_bytecode_module = required_bool_arg("add_bytecode_module", &add_bytecode_module)?;
+        required_type_arg("optimize_level", "int", &optimize_level)?;
+        let pyc_invalidation_mode =
+            required_str_arg("pyc_invalidation_mode", &pyc_invalidation_mode)?;
+
+        let invalidation_mode = PycInvalidationMode::try_from(pyc_invalidation_mode.as_str())
+            .map_err(|e| RuntimeError {
+                code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                message: e.to_string(),
+                label: "invalid pyc_invalidation_mode value".to_string(),
+            })?;
+
+        let optimize_level = match optimize_level.to_int().unwrap() {
+            0 => BytecodeOptimizationLevel::Zero,
+            1 => BytecodeOptimizationLevel::One,
+            2 => BytecodeOptimizationLevel::Two,
+            i => {
+                return Err(RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message: format!("optimize_level must be 0, 1, or 2: got {}", i),
+                    label: "invalid optimize_level value".to_string(),
+                }
+                .into());
+            }
+        };
+
+        let context = env.get("CONTEXT").expect("CONTEXT not set");
+        let logger = context.downcast_apply(|x: &EnvironmentContext| x.logger.clone());
+
+        match resource.get_type() {
+            "PythonSourceModule" => {
+                let m = resource.downcast_apply(|m: &PythonSourceModule| m.module.clone());
+
+                if add_source_module {
+                    info!(&logger, "adding zip application source module {}", m.name);
+                    self.zipapp.add_source_module(&m);
+                }
+                if add_bytecode_module {
+                    info!(&logger, "adding zip application bytecode module {}", m.name);
+                    let mut bytecode = m.as_bytecode_module(optimize_level);
+                    bytecode.invalidation_mode = invalidation_mode;
+                    self.zipapp.add_bytecode_module(&bytecode);
+                }
+            }
+            "PythonBytecodeModule" => {
+                let mut m = resource.downcast_apply(|m: &PythonBytecodeModule| m.module.clone());
+                m.invalidation_mode = invalidation_mode;
+                info!(&logger, "adding zip application bytecode module {}", m.name);
+                self.zipapp.add_bytecode_module(&m);
+            }
+            "PythonResourceData" => {
+                let r = resource.downcast_apply(|r: &PythonResourceData| r.data.clone());
+                info!(
+                    &logger,
+                    "adding zip application resource data {}",
+                    r.full_name()
+                );
+                self.zipapp.add_resource(&r);
+            }
+            "PythonPackageLicense" | "PythonPackageDistributionResource" => {
+                // Zip applications don't carry package metadata.
+            }
+            "PythonExtensionModule" => {
+                // zipimport cannot load extension modules. So we let the interpreter
+                // running the zip application provide them.
+                warn!(
+                    &logger,
+                    "ignoring extension module {}: zip applications cannot contain extension modules",
+                    resource.get_attr("name")?.to_string()
+                );
+            }
+            t => {
+                return Err(RuntimeError {
+                    code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+                    message: format!("resource should be a Python resource type; got {}", t),
+                    label: ".add_python_resource()".to_string(),
+                }
+                .into());
+            }
+        }
+
+        Ok(Value::new(None))
+    }
+
+    /// PythonZipApp.add_python_resources(resources, add_source_module=true, add_bytecode_module=true, optimize_level=0, pyc_invalidation_mode="unchecked-hash")
+    pub fn starlark_add_python_resources(
+        &mut self,
+        env: &Environment,
+        resources: &Value,
+        add_source_module: &Value,
+        add_bytecode_module: &Value,
+        optimize_level: &Value,
+        pyc_invalidation_mode: &Value,
+    ) -> ValueResult {
+        for resource in resources.into_iter()? {
+            self.starlark_add_python_resource(
+                env,
+                &resource,
+                add_source_module,
+                add_bytecode_module,
+                optimize_level,
+                pyc_invalidation_mode,
+            )?;
+        }
+
+        Ok(Value::new(None))
+    }
+}
+
+starlark_module! { python_zipapp_env =>
+    #[allow(clippy::ptr_arg)]
+    PythonZipApp.add_python_resource(
+        env env,
+        this,
+        resource,
+        add_source_module=true,
+        add_bytecode_module=true,
+        optimize_level=0,
+        pyc_invalidation_mode="unchecked-hash"
+    ) {
+        this.downcast_apply_mut(|app: &mut PythonZipApp| {
+            app.starlark_add_python_resource(
+                &env,
+                &resource,
+                &add_source_module,
+                &add_bytecode_module,
+                &optimize_level,
+                &pyc_invalidation_mode,
+            )
+        })
+    }
+
+    #[allow(clippy::ptr_arg)]
+    PythonZipApp.add_python_resources(
+        env env,
+        this,
+        resources,
+        add_source_module=true,
+        add_bytecode_module=true,
+        optimize_level=0,
+        pyc_invalidation_mode="unchecked-hash"
+    ) {
+        this.downcast_apply_mut(|app: &mut PythonZipApp| {
+            app.starlark_add_python_resources(
+                &env,
+                &resources,
+                &add_source_module,
+                &add_bytecode_module,
+                &optimize_level,
+                &pyc_invalidation_mode,
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::testutil::*;
+    use super::*;
+
+    #[test]
+    fn test_to_python_zipapp() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+
+        let app = starlark_eval_in_env(
+            &mut env,
+            "dist.to_python_zipapp('myapp', main='myapp:main')",
+        )
+        .unwrap();
+        assert_eq!(app.get_type(), "PythonZipApp");
+
+        app.downcast_apply(|app: &PythonZipApp| {
+            assert_eq!(app.zipapp.filename(), "myapp.pyz");
+            assert_eq!(
+                app.zipapp.interpreter,
+                Some("/usr/bin/env python3".to_string())
+            );
+            assert_eq!(app.zipapp.main, Some("myapp:main".to_string()));
+            assert!(app.zipapp.source_modules().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_add_python_resources() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "app = dist.to_python_zipapp('myapp')").unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "app.add_python_resources([m for m in dist.source_modules() if m.name == 'json'])",
+        )
+        .unwrap();
+
+        let app = env.get("app").unwrap();
+        app.downcast_apply(|app: &PythonZipApp| {
+            assert!(app.zipapp.source_modules().contains_key("json"));
+            assert!(app.zipapp.bytecode_modules().contains_key("json"));
+        });
+    }
+
+    #[test]
+    fn test_pyc_invalidation_mode() {
+        let mut env = starlark_env();
+
+        starlark_eval_in_env(&mut env, "dist = default_python_distribution()").unwrap();
+        starlark_eval_in_env(&mut env, "app = dist.to_python_zipapp('myapp')").unwrap();
+        starlark_eval_in_env(
+            &mut env,
+            "app.add_python_resources([m for m in dist.source_modules() if m.name == 'json'], pyc_invalidation_mode='checked-hash')",
+        )
+        .unwrap();
+
+        let app = env.get("app").unwrap();
+        app.downcast_apply(|app: &PythonZipApp| {
+            assert_eq!(
+                app.zipapp
+                    .bytecode_modules()
+                    .get("json")
+                    .unwrap()
+                    .invalidation_mode,
+                PycInvalidationMode::CheckedHash
+            );
+        });
+
+        assert!(starlark_eval_in_env(
+            &mut env,
+            "app.add_python_resources([m for m in dist.source_modules() if m.name == 'json'], pyc_invalidation_mode='mtime')",
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,436 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Utility modules for configuration files.
+
+The `path`, `re`, and `hashlib` modules let configuration files compute
+metadata, like a version defined in a file or a cache key derived from the
+content of files, without running external programs.
+*/
+
+use {
+    super::env::EnvironmentContext,
+    super::util::required_str_arg,
+    crate::py_packaging::sdist::file_sha256,
+    path_dedot::ParseDot,
+    regex::Regex,
+    sha2::{Digest, Sha256},
+    starlark::environment::Environment,
+    starlark::values::{
+        default_compare, RuntimeError, TypedValue, Value, ValueError, ValueResult,
+        INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+    },
+    starlark::{
+        any, immutable, not_supported, starlark_fun, starlark_module, starlark_signature,
+        starlark_signature_extraction, starlark_signatures,
+    },
+    std::any::Any,
+    std::cmp::Ordering,
+    std::path::{Path, PathBuf},
+};
+
+/// Default maximum size of files read by `path.read_text()`.
+const DEFAULT_MAX_READ_SIZE: i64 = 1_048_576;
+
+/// A module of utility functions.
+///
+/// Functions are methods of the type named after the module.
+#[derive(Clone, Debug)]
+pub struct UtilityModule {
+    name: &'static str,
+}
+
+impl TypedValue for UtilityModule {
+    immutable!();
+    any!();
+    not_supported!(binop, container, function, get_hash, to_int);
+
+    fn to_str(&self) -> String {
+        format!("<module {}>", self.name)
+    }
+
+    fn to_repr(&self) -> String {
+        self.to_str()
+    }
+
+    fn get_type(&self) -> &'static str {
+        self.name
+    }
+
+    fn to_bool(&self) -> bool {
+        true
+    }
+
+    fn compare(&self, other: &dyn TypedValue, _recursion: u32) -> Result<Ordering, ValueError> {
+        default_compare(self, other)
+    }
+}
+
+fn build_error(message: String, label: &str) -> ValueError {
+    RuntimeError {
+        code: "PYOXIDIZER_BUILD",
+        message,
+        label: label.to_string(),
+    }
+    .into()
+}
+
+/// Resolve a path relative to the directory of the configuration file.
+fn resolve_path(env: &Environment, path: &str, label: &str) -> Result<PathBuf, ValueError> {
+    let context = env.get("CONTEXT").expect("CONTEXT not defined");
+    let cwd = context.downcast_apply(|x: &EnvironmentContext| x.cwd.clone());
+
+    cwd.join(path)
+        .parse_dot()
+        .map_err(|e| build_error(e.to_string(), label))
+}
+
+fn path_value(path: &Path) -> Value {
+    Value::new(path.display().to_string())
+}
+
+/// path.join(*parts)
+fn path_join(parts: &Value) -> ValueResult {
+    let mut path = PathBuf::new();
+
+    for part in parts.into_iter()? {
+        path.push(required_str_arg("parts", &part)?);
+    }
+
+    Ok(path_value(&path))
+}
+
+/// path.dirname(path)
+fn path_dirname(path: &Value) -> ValueResult {
+    let path = required_str_arg("path", &path)?;
+
+    Ok(match Path::new(&path).parent() {
+        Some(parent) => path_value(parent),
+        None => Value::new(String::new()),
+    })
+}
+
+/// path.basename(path)
+fn path_basename(path: &Value) -> ValueResult {
+    let path = required_str_arg("path", &path)?;
+
+    Ok(match Path::new(&path).file_name() {
+        Some(name) => Value::new(name.to_string_lossy().to_string()),
+        None => Value::new(String::new()),
+    })
+}
+
+/// path.abspath(path)
+fn path_abspath(env: &Environment, path: &Value) -> ValueResult {
+    let path = required_str_arg("path", &path)?;
+
+    Ok(path_value(&resolve_path(env, &path, "abspath()")?))
+}
+
+/// path.exists(path)
+fn path_exists(env: &Environment, path: &Value) -> ValueResult {
+    let path = required_str_arg("path", &path)?;
+
+    Ok(Value::new(resolve_path(env, &path, "exists()")?.exists()))
+}
+
+/// path.read_text(path, max_size=1048576)
+fn path_read_text(env: &Environment, path: &Value, max_size: &Value) -> ValueResult {
+    let path = required_str_arg("path", &path)?;
+    if max_size.get_type() != "int" {
+        return Err(RuntimeError {
+            code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            message: format!(
+                "function expects an int for max_size; got type {}",
+                max_size.get_type()
+            ),
+            label: format!("expected type int; got {}", max_size.get_type()),
+        }
+        .into());
+    }
+    let max_size = max_size.to_int()?;
+
+    let path = resolve_path(env, &path, "read_text()")?;
+
+    let size = std::fs::metadata(&path)
+        .map_err(|e| build_error(format!("{}: {}", path.display(), e), "read_text()"))?
+        .len();
+    if size > max_size as u64 {
+        return Err(build_error(
+            format!(
+                "{} is {} bytes, larger than max_size of {} bytes",
+                path.display(),
+                size,
+                max_size
+            ),
+            "read_text()",
+        ));
+    }
+
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| build_error(format!("{}: {}", path.display(), e), "read_text()"))?;
+
+    Ok(Value::new(text))
+}
+
+fn compile_regex(pattern: &str, label: &str) -> Result<Regex, ValueError> {
+    Regex::new(pattern).map_err(|e| {
+        RuntimeError {
+            code: INCORRECT_PARAMETER_TYPE_ERROR_CODE,
+            message: e.to_string(),
+            label: label.to_string(),
+        }
+        .into()
+    })
+}
+
+/// Find a match of a regular expression.
+///
+/// The match is returned as a list of the matched string followed by the
+/// strings matched by each group, or None for groups that didn't match.
+fn regex_captures(regex: &Regex, string: &str) -> Value {
+    match regex.captures(string) {
+        Some(captures) => Value::from(
+            captures
+                .iter()
+                .map(|m| match m {
+                    Some(m) => Value::new(m.as_str().to_string()),
+                    None => Value::new(None),
+                })
+                .collect::<Vec<Value>>(),
+        ),
+        None => Value::new(None),
+    }
+}
+
+/// re.fullmatch(pattern, string)
+fn re_fullmatch(pattern: &Value, string: &Value) -> ValueResult {
+    let pattern = required_str_arg("pattern", &pattern)?;
+    let string = required_str_arg("string", &string)?;
+
+    let regex = compile_regex(&format!("^(?:{})$", pattern), "fullmatch()")?;
+
+    Ok(regex_captures(&regex, &string))
+}
+
+/// re.search(pattern, string)
+fn re_search(pattern: &Value, string: &Value) -> ValueResult {
+    let pattern = required_str_arg("pattern", &pattern)?;
+    let string = required_str_arg("string", &string)?;
+
+    let regex = compile_regex(&pattern, "search()")?;
+
+    Ok(regex_captures(&regex, &string))
+}
+
+/// re.sub(pattern, repl, string)
+fn re_sub(pattern: &Value, repl: &Value, string: &Value) -> ValueResult {
+    let pattern = required_str_arg("pattern", &pattern)?;
+    let repl = required_str_arg("repl", &repl)?;
+    let string = required_str_arg("string", &string)?;
+
+    let regex = compile_regex(&pattern, "sub()")?;
+
+    Ok(Value::new(
+        regex.replace_all(&string, repl.as_str()).to_string(),
+    ))
+}
+
+/// hashlib.sha256(data)
+fn hashlib_sha256(data: &Value) -> ValueResult {
+    let data = required_str_arg("data", &data)?;
+
+    let mut hasher = Sha256::new();
+    hasher.input(data.as_bytes());
+
+    Ok(Value::new(hex::encode(hasher.result())))
+}
+
+/// hashlib.file_sha256(path)
+fn hashlib_file_sha256(env: &Environment, path: &Value) -> ValueResult {
+    let path = required_str_arg("path", &path)?;
+    let path = resolve_path(env, &path, "file_sha256()")?;
+
+    let digest = file_sha256(&path).map_err(|e| build_error(e.to_string(), "file_sha256()"))?;
+
+    Ok(Value::new(digest))
+}
+
+starlark_module! { stdlib_module =>
+    #[allow(clippy::ptr_arg)]
+    path.join(this, *args) {
+        path_join(&args)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    path.dirname(this, path) {
+        path_dirname(&path)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    path.basename(this, path) {
+        path_basename(&path)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    path.abspath(env env, this, path) {
+        path_abspath(&env, &path)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    path.exists(env env, this, path) {
+        path_exists(&env, &path)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    path.read_text(env env, this, path, max_size=DEFAULT_MAX_READ_SIZE) {
+        path_read_text(&env, &path, &max_size)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    re.fullmatch(this, pattern, string) {
+        re_fullmatch(&pattern, &string)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    re.search(this, pattern, string) {
+        re_search(&pattern, &string)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    re.sub(this, pattern, repl, string) {
+        re_sub(&pattern, &repl, &string)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    hashlib.sha256(this, data) {
+        hashlib_sha256(&data)
+    }
+
+    #[allow(clippy::ptr_arg)]
+    hashlib.file_sha256(env env, this, path) {
+        hashlib_file_sha256(&env, &path)
+    }
+}
+
+/// Register the utility modules in an environment.
+pub fn stdlib_env(env: Environment) -> Environment {
+    let env = stdlib_module(env);
+
+    for name in &["hashlib", "path", "re"] {
+        env.set(name, Value::new(UtilityModule { name: *name }))
+            .expect("unable to define utility module");
+    }
+
+    env
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::super::testutil::*, super::*};
+
+    #[test]
+    fn test_path() {
+        assert_eq!(
+            starlark_ok("path.join('a', 'b', 'c.txt')").to_str(),
+            PathBuf::from("a")
+                .join("b")
+                .join("c.txt")
+                .display()
+                .to_string()
+        );
+        assert_eq!(starlark_ok("path.dirname('a/b/c.txt')").to_str(), "a/b");
+        assert_eq!(starlark_ok("path.dirname('c.txt')").to_str(), "");
+        assert_eq!(starlark_ok("path.basename('a/b/c.txt')").to_str(), "c.txt");
+
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(
+            starlark_ok("path.abspath('a/../b')").to_str(),
+            cwd.join("b").display().to_string()
+        );
+        assert!(starlark_ok("path.exists('Cargo.toml')").to_bool());
+        assert!(!starlark_ok("path.exists('does-not-exist')").to_bool());
+
+        let err = starlark_nok("path.join('a', 1)");
+        assert!(err.message.contains("function expects a string for parts"));
+    }
+
+    #[test]
+    fn test_read_text() -> Result<(), ValueError> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test").unwrap();
+        let version_path = temp_dir.path().join("VERSION");
+        std::fs::write(&version_path, "1.2.3\n").unwrap();
+
+        let mut env = starlark_env();
+        env.set("VERSION_PATH", path_value(&version_path)).unwrap();
+
+        let text = starlark_eval_in_env(&mut env, "path.read_text(VERSION_PATH)").unwrap();
+        assert_eq!(text.to_str(), "1.2.3\n");
+
+        let err =
+            starlark_eval_in_env(&mut env, "path.read_text(VERSION_PATH, max_size=3)").unwrap_err();
+        assert!(err.message.contains("larger than max_size of 3 bytes"));
+
+        let version = starlark_eval_in_env(
+            &mut env,
+            "re.search('(\\\\d+)\\\\.(\\\\d+)', path.read_text(VERSION_PATH))",
+        )
+        .unwrap();
+        assert_eq!(version.get_type(), "list");
+        assert_eq!(version.at(Value::new(2))?.to_str(), "2");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_re() -> Result<(), ValueError> {
+        let m = starlark_ok("re.search('v(\\\\d+)(-rc)?', 'release v12 final')");
+        assert_eq!(m.length()?, 3);
+        assert_eq!(m.at(Value::new(0))?.to_str(), "v12");
+        assert_eq!(m.at(Value::new(1))?.to_str(), "12");
+        assert_eq!(m.at(Value::new(2))?.get_type(), "NoneType");
+
+        assert_eq!(
+            starlark_ok("re.fullmatch('\\\\d+', 'v12')").get_type(),
+            "NoneType"
+        );
+        assert_eq!(
+            starlark_ok("re.fullmatch('v\\\\d+', 'v12')[0]").to_str(),
+            "v12"
+        );
+        assert_eq!(starlark_ok("re.search('\\\\d+', 'v12')[0]").to_str(), "12");
+        assert_eq!(
+            starlark_ok("re.sub('[^A-Za-z0-9]+', '-', 'My App 2')").to_str(),
+            "My-App-2"
+        );
+
+        let err = starlark_nok("re.search('(', 'a')");
+        assert!(err.message.contains("regex parse error"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_hashlib() {
+        assert_eq!(
+            starlark_ok("hashlib.sha256('')").to_str(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test").unwrap();
+        let path = temp_dir.path().join("data");
+        std::fs::write(&path, b"").unwrap();
+
+        let mut env = starlark_env();
+        env.set("DATA_PATH", path_value(&path)).unwrap();
+
+        assert_eq!(
+            starlark_eval_in_env(&mut env, "hashlib.file_sha256(DATA_PATH)")
+                .unwrap()
+                .to_str(),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
@@ -3,6 +3,7 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
+    crate::licensing::LicensePolicy,
     anyhow::{anyhow, Result},
     std::path::PathBuf,
 };
@@ -24,6 +25,12 @@ pub struct ResolvedTarget {
 
     /// Where build artifacts are stored on the filesystem.
     pub output_path: PathBuf,
+
+    /// Files written by the target.
+    ///
+    /// A directory stands for every file beneath it. Other targets may write
+    /// to the same `output_path`, so only these files belong to the target.
+    pub artifacts: Vec<PathBuf>,
 }
 
 impl ResolvedTarget {
@@ -31,9 +38,17 @@ impl ResolvedTarget {
         match &self.run_mode {
             RunMode::None => Ok(()),
             RunMode::Path { path } => {
-                let status = std::process::Command::new(&path)
-                    .current_dir(&path.parent().unwrap())
-                    .status()?;
+                // WebAssembly modules are run by a runtime granted access to
+                // the directory holding them.
+                let mut command = if path.extension() == Some(std::ffi::OsStr::new("wasm")) {
+                    let mut command = std::process::Command::new("wasmtime");
+                    command.arg("--dir=.").arg(path.file_name().unwrap());
+                    command
+                } else {
+                    std::process::Command::new(&path)
+                };
+
+                let status = command.current_dir(&path.parent().unwrap()).status()?;
 
                 if status.success() {
                     Ok(())
@@ -68,8 +83,16 @@ pub struct BuildContext {
 
     /// Where generated files should be written.
     pub output_path: PathBuf,
+
+    /// Restrictions on the licenses of components in built artifacts.
+    pub license_policy: LicensePolicy,
 }
 
+/// A build of a target that can run on another thread.
+///
+/// Obtained with `EnvironmentContext::detach_resolved_target_build()`.
+pub type DetachedBuild = Box<dyn FnOnce(&BuildContext) -> Result<ResolvedTarget> + Send>;
+
 /// Trait that indicates a type can be resolved as a target.
 pub trait BuildTarget {
     /// Build the target, resolving it
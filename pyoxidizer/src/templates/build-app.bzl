@@ -0,0 +1,48 @@
+# This file was generated by `pyoxidizer build-app`. It builds a standalone
+# executable for an application installed via `pip install`. See the
+# pyoxidizer crate's documentation for extensive documentation on this file
+# format.
+
+set_config_version(2)
+
+def make_dist():
+    return default_python_distribution()
+
+def make_exe(dist):
+    python_config = PythonInterpreterConfig(
+        {{#if run_module}}
+        run_module={{{ run_module }}},
+        {{else}}
+        run_eval={{{ code }}},
+        {{/if}}
+    )
+
+    exe = dist.to_python_executable(
+        name={{{ program_name }}},
+        config=python_config,
+        extension_module_filter='all',
+        include_sources=True,
+        include_resources=False,
+        include_test=False,
+    )
+
+    {{#each pip_install_simple}}
+    exe.add_python_resources(dist.pip_install([{{{ this }}}]))
+    {{/each}}
+
+    return exe
+
+def make_install(exe):
+    files = FileManifest()
+    files.add_python_resource(".", exe)
+
+    return files
+
+register_target("dist", make_dist)
+register_target("exe", make_exe, depends=["dist"])
+register_target("install", make_install, depends=["exe"], default=True)
+
+resolve_targets()
+
+PYOXIDIZER_VERSION = "{{{ pyoxidizer_version }}}"
+PYOXIDIZER_COMMIT = "{{{ pyoxidizer_commit }}}"
@@ -0,0 +1,71 @@
+# This file defines how PyOxidizer builds a data science batch job using
+# packages like NumPy and pandas. See the pyoxidizer crate's documentation
+# for extensive documentation on this file format.
+
+# Version of the configuration file API this file is written against.
+# `pyoxidizer migrate-config` updates the file to newer versions.
+set_config_version(2)
+
+def make_dist():
+    return default_python_distribution()
+
+def make_exe(dist):
+    python_config = PythonInterpreterConfig(
+        {{#if code}}
+        run_eval="{{{ code }}}",
+        {{else}}
+        # Run the `{{module_name}}` package, whose `__main__.py` runs the job.
+        run_module="{{module_name}}",
+        {{/if}}
+
+        # Scientific packages ship extension modules and shared libraries
+        # that must be loaded from files. So they are installed to a `lib`
+        # directory next to the executable instead of being embedded. See
+        # `make_install()`.
+        sys_paths=["$ORIGIN/lib"],
+
+        # Write log messages as soon as they are emitted, so they show up in
+        # the logs of job schedulers.
+        unbuffered_stdio=True,
+
+        # Print the Python traceback of every thread if the job crashes.
+        faulthandler=True,
+    )
+
+    exe = dist.to_python_executable(
+        name="{{program_name}}",
+        config=python_config,
+        extension_module_filter="all",
+        include_sources=True,
+        include_resources=False,
+        include_test=False,
+    )
+
+    # Embed the job package from the directory of this file.
+    exe.add_python_resources(dist.read_package_root(
+        path=CWD,
+        packages=["{{module_name}}"],
+    ))
+
+    return exe
+
+def make_install(dist, exe):
+    files = FileManifest()
+    files.add_python_resource(".", exe)
+
+    files.add_python_resources("lib", dist.pip_install(["numpy", "pandas"]))
+
+    {{#each pip_install_simple}}
+    files.add_python_resources("lib", dist.pip_install(["{{{ this }}}"]))
+    {{/each}}
+
+    return files
+
+register_target("dist", make_dist)
+register_target("exe", make_exe, depends=["dist"])
+register_target("install", make_install, depends=["dist", "exe"], default=True)
+
+resolve_targets()
+
+PYOXIDIZER_VERSION = "{{{ pyoxidizer_version }}}"
+PYOXIDIZER_COMMIT = "{{{ pyoxidizer_commit }}}"
@@ -0,0 +1,68 @@
+# This file defines how PyOxidizer builds a command line tool. See the
+# pyoxidizer crate's documentation for extensive documentation on this file
+# format.
+
+# Version of the configuration file API this file is written against.
+# `pyoxidizer migrate-config` updates the file to newer versions.
+set_config_version(2)
+
+def make_dist():
+    return default_python_distribution()
+
+def make_exe(dist):
+    python_config = PythonInterpreterConfig(
+        {{#if code}}
+        run_eval="{{{ code }}}",
+        {{else}}
+        # Run the `{{module_name}}` package, whose `__main__.py` implements
+        # the command.
+        run_module="{{module_name}}",
+        {{/if}}
+
+        # Only execute modules when they are used, so each command only pays
+        # the startup cost of the code it runs.
+        lazy_import=True,
+
+        # Don't let PYTHON* environment variables or the user's
+        # site-packages change the behavior of the tool.
+        isolated=True,
+    )
+
+    exe = dist.to_python_executable(
+        name="{{program_name}}",
+        config=python_config,
+        extension_module_filter="all",
+        # Bytecode is all that's needed to run. Omitting sources makes the
+        # executable smaller.
+        include_sources=False,
+        include_resources=False,
+        include_test=False,
+    )
+
+    # Install the application and its dependencies from the directory of
+    # this file.
+    exe.add_python_resources(dist.pip_install([CWD]))
+
+    {{#each pip_install_simple}}
+    exe.add_python_resources(dist.pip_install(["{{{ this }}}"]))
+    {{/each}}
+
+    # Fail the build if the executable grows unexpectedly.
+    #exe.set_size_budget(size="40 MB")
+
+    return exe
+
+def make_install(exe):
+    files = FileManifest()
+    files.add_python_resource(".", exe)
+
+    return files
+
+register_target("dist", make_dist)
+register_target("exe", make_exe, depends=["dist"])
+register_target("install", make_install, depends=["exe"], default=True)
+
+resolve_targets()
+
+PYOXIDIZER_VERSION = "{{{ pyoxidizer_version }}}"
+PYOXIDIZER_COMMIT = "{{{ pyoxidizer_commit }}}"
@@ -0,0 +1,68 @@
+# This file defines how PyOxidizer builds a web service, such as a Flask
+# application. See the pyoxidizer crate's documentation for extensive
+# documentation on this file format.
+
+# Version of the configuration file API this file is written against.
+# `pyoxidizer migrate-config` updates the file to newer versions.
+set_config_version(2)
+
+def make_dist():
+    return default_python_distribution()
+
+def make_exe(dist):
+    python_config = PythonInterpreterConfig(
+        {{#if code}}
+        run_eval="{{{ code }}}",
+        {{else}}
+        # Run the `{{module_name}}` package, whose `__main__.py` starts the
+        # web server. e.g. `waitress.serve(app)`.
+        run_module="{{module_name}}",
+        {{/if}}
+
+        # Flask and Jinja2 find templates and static files relative to the
+        # `__file__` of packages. Write packages to the filesystem when they
+        # are imported so these files can be read.
+        file_emulation="extract-on-access",
+
+        # Write log messages as soon as they are emitted.
+        unbuffered_stdio=True,
+
+        # Print the Python traceback of every thread if the service crashes.
+        faulthandler=True,
+    )
+
+    exe = dist.to_python_executable(
+        name="{{program_name}}",
+        config=python_config,
+        # Web services commonly need `ssl`, `sqlite3`, and other extension
+        # modules linking against external libraries.
+        extension_module_filter="all",
+        include_sources=True,
+        include_resources=False,
+        include_test=False,
+    )
+
+    # Install the application, its dependencies, and the templates and
+    # static files of their packages, from the directory of this file.
+    exe.add_python_resources(dist.pip_install([CWD]))
+
+    {{#each pip_install_simple}}
+    exe.add_python_resources(dist.pip_install(["{{{ this }}}"]))
+    {{/each}}
+
+    return exe
+
+def make_install(exe):
+    files = FileManifest()
+    files.add_python_resource(".", exe)
+
+    return files
+
+register_target("dist", make_dist)
+register_target("exe", make_exe, depends=["dist"])
+register_target("install", make_install, depends=["exe"], default=True)
+
+resolve_targets()
+
+PYOXIDIZER_VERSION = "{{{ pyoxidizer_version }}}"
+PYOXIDIZER_COMMIT = "{{{ pyoxidizer_commit }}}"
@@ -2,6 +2,10 @@
 # performed. See the pyoxidizer crate's documentation for extensive
 # documentation on this file format.
 
+# Version of the configuration file API this file is written against.
+# `pyoxidizer migrate-config` updates the file to newer versions.
+set_config_version(2)
+
 # Obtain the default PythonDistribution for our build target. We link
 # this distribution into our produced executable and extract the Python
 # standard library from it.
@@ -16,15 +20,15 @@ def make_exe(dist):
     # embedded Python interpreter.
     python_config = PythonInterpreterConfig(
     #     bytes_warning=0,
-    #     dont_write_bytecode=True,
+    #     write_bytecode=False,
     #     ignore_environment=True,
     #     inspect=False,
     #     interactive=False,
     #     isolated=False,
     #     legacy_windows_fs_encoding=False,
     #     legacy_windows_stdio=False,
-    #     no_site=True,
-    #     no_user_site_directory=True,
+    #     site_import=False,
+    #     user_site_directory=False,
     #     optimize_level=0,
     #     parser_debug=False,
     #     stdio_encoding=None,
@@ -119,7 +123,7 @@ def make_exe(dist):
 
     # Filter all resources collected so far through a filter of names
     # in a file.
-    #exe.filter_from_files(files=["/path/to/filter-file"]))
+    #exe.filter_resources_from_files(files=["/path/to/filter-file"]))
 
     # Return our `PythonExecutable` instance so it can be built and
     # referenced by other consumers of this target.
@@ -0,0 +1,345 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Timing of build phases.
+
+Phases like evaluating configuration files, installing packages, and running
+`cargo` record their wall time when they complete. Builds collect the
+recorded phases into a `TimingReport`, which summarizes where time was spent
+and can be written as a trace viewable in `chrome://tracing`.
+*/
+
+use {
+    anyhow::{Context, Result},
+    lazy_static::lazy_static,
+    serde::Serialize,
+    std::collections::BTreeMap,
+    std::path::Path,
+    std::sync::atomic::{AtomicU64, Ordering},
+    std::sync::Mutex,
+    std::time::{Duration, Instant},
+};
+
+/// Filename of the trace written next to the build manifest.
+pub const TIMINGS_FILENAME: &str = "build-timings.json";
+
+lazy_static! {
+    /// When timing started. Span times are relative to it.
+    static ref ORIGIN: Instant = Instant::now();
+
+    /// Phases completed since the last report.
+    static ref SPANS: Mutex<Vec<Span>> = Mutex::new(Vec::new());
+}
+
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// Identifies threads in traces.
+    static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+/// A completed phase.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub name: String,
+
+    /// Thread the phase ran on.
+    pub thread: u64,
+
+    /// When the phase started, relative to when timing started.
+    pub start: Duration,
+
+    pub duration: Duration,
+}
+
+impl Span {
+    fn end(&self) -> Duration {
+        self.start + self.duration
+    }
+
+    /// Whether another span ran within this one on the same thread.
+    fn contains(&self, other: &Span) -> bool {
+        self.thread == other.thread
+            && self.start <= other.start
+            && other.end() <= self.end()
+            && self != other
+    }
+}
+
+/// Records the wall time of a phase when dropped.
+pub struct Phase {
+    name: String,
+    start: Instant,
+}
+
+impl Drop for Phase {
+    fn drop(&mut self) {
+        let span = Span {
+            name: self.name.clone(),
+            thread: THREAD_ID.with(|id| *id),
+            start: self.start.duration_since(*ORIGIN),
+            duration: self.start.elapsed(),
+        };
+
+        SPANS.lock().unwrap().push(span);
+    }
+}
+
+/// Start timing a phase.
+///
+/// The phase ends when the returned value is dropped, including when the
+/// phase fails.
+pub fn phase(name: &str) -> Phase {
+    lazy_static::initialize(&ORIGIN);
+
+    Phase {
+        name: name.to_string(),
+        start: Instant::now(),
+    }
+}
+
+/// Event in the Trace Event Format read by `chrome://tracing`.
+#[derive(Serialize)]
+struct TraceEvent<'a> {
+    name: &'a str,
+    cat: &'static str,
+    ph: &'static str,
+    /// Start in microseconds.
+    ts: u64,
+    /// Duration in microseconds.
+    dur: u64,
+    pid: u32,
+    tid: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Trace<'a> {
+    trace_events: Vec<TraceEvent<'a>>,
+    display_time_unit: &'static str,
+}
+
+/// Time spent in a phase, summed over all its runs.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PhaseTotal {
+    pub name: String,
+
+    /// Number of times the phase ran.
+    pub count: usize,
+
+    /// Wall time of the phase.
+    pub total: Duration,
+
+    /// Wall time of the phase not spent in phases nested in it.
+    pub own: Duration,
+}
+
+/// Phases recorded during a build.
+#[derive(Clone, Debug, Default)]
+pub struct TimingReport {
+    pub spans: Vec<Span>,
+}
+
+impl TimingReport {
+    /// Obtain the phases completed since the last report.
+    pub fn take() -> Self {
+        let mut spans = std::mem::replace(&mut *SPANS.lock().unwrap(), Vec::new());
+        spans.sort_by_key(|span| (span.start, span.thread));
+
+        TimingReport { spans }
+    }
+
+    /// Wall time from the start of the first phase to the end of the last.
+    pub fn wall_time(&self) -> Duration {
+        match (
+            self.spans.iter().map(|s| s.start).min(),
+            self.spans.iter().map(|s| s.end()).max(),
+        ) {
+            (Some(start), Some(end)) => end - start,
+            _ => Duration::default(),
+        }
+    }
+
+    /// Obtain the time spent in each phase, longest own time first.
+    ///
+    /// Phases running within other phases, e.g. installing packages while
+    /// evaluating configuration files, are subtracted from the own time of
+    /// the enclosing phase.
+    pub fn phase_totals(&self) -> Vec<PhaseTotal> {
+        let mut totals: BTreeMap<&str, PhaseTotal> = BTreeMap::new();
+
+        for span in &self.spans {
+            // Spans directly nested in this one.
+            let children = self
+                .spans
+                .iter()
+                .filter(|child| {
+                    span.contains(child)
+                        && !self
+                            .spans
+                            .iter()
+                            .any(|other| span.contains(other) && other.contains(child))
+                })
+                .map(|child| child.duration)
+                .fold(Duration::default(), |acc, d| acc + d);
+
+            let entry = totals.entry(&span.name).or_insert_with(|| PhaseTotal {
+                name: span.name.clone(),
+                ..PhaseTotal::default()
+            });
+            entry.count += 1;
+            entry.total += span.duration;
+            entry.own += span.duration.checked_sub(children).unwrap_or_default();
+        }
+
+        let mut totals = totals.into_iter().map(|(_, v)| v).collect::<Vec<_>>();
+        totals.sort_by(|a, b| b.own.cmp(&a.own).then_with(|| a.name.cmp(&b.name)));
+
+        totals
+    }
+
+    /// Render a human readable summary of where time was spent.
+    pub fn summary(&self) -> String {
+        let wall_time = self.wall_time();
+        let totals = self.phase_totals();
+
+        let width = totals.iter().map(|t| t.name.len()).max().unwrap_or(0);
+
+        let mut lines = vec![format!(
+            "build timings ({} total):",
+            format_seconds(wall_time)
+        )];
+
+        for total in totals {
+            let percent = if wall_time.as_millis() == 0 {
+                0.0
+            } else {
+                total.own.as_millis() as f64 * 100.0 / wall_time.as_millis() as f64
+            };
+
+            let mut line = format!(
+                "  {:width$}  {:>9}  {:>5.1}%",
+                total.name,
+                format_seconds(total.own),
+                percent,
+                width = width
+            );
+            if total.count > 1 {
+                line.push_str(&format!("  ({} runs)", total.count));
+            }
+
+            lines.push(line);
+        }
+
+        lines.join("\n")
+    }
+
+    /// Serialize phases in the Trace Event Format read by `chrome://tracing`.
+    pub fn to_chrome_trace(&self) -> Result<Vec<u8>> {
+        let trace = Trace {
+            trace_events: self
+                .spans
+                .iter()
+                .map(|span| TraceEvent {
+                    name: &span.name,
+                    cat: "pyoxidizer",
+                    ph: "X",
+                    ts: span.start.as_micros() as u64,
+                    dur: span.duration.as_micros() as u64,
+                    pid: std::process::id(),
+                    tid: span.thread,
+                })
+                .collect(),
+            display_time_unit: "ms",
+        };
+
+        Ok(serde_json::to_vec_pretty(&trace)?)
+    }
+
+    /// Write the trace of phases to a file.
+    pub fn write_chrome_trace(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, self.to_chrome_trace()?)
+            .with_context(|| format!("writing {}", path.display()))
+    }
+}
+
+/// Format a duration in seconds with millisecond precision.
+fn format_seconds(duration: Duration) -> String {
+    format!("{}.{:03}s", duration.as_secs(), duration.subsec_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(name: &str, thread: u64, start: u64, duration: u64) -> Span {
+        Span {
+            name: name.to_string(),
+            thread,
+            start: Duration::from_secs(start),
+            duration: Duration::from_secs(duration),
+        }
+    }
+
+    #[test]
+    fn test_phase_totals() {
+        let report = TimingReport {
+            spans: vec![
+                span("evaluate configuration", 1, 0, 10),
+                span("pip install", 1, 1, 6),
+                span("resolve Python distribution", 1, 7, 2),
+                span("download Python distribution", 1, 7, 1),
+                span("pip install", 2, 2, 3),
+                span("cargo build", 1, 10, 20),
+            ],
+        };
+
+        assert_eq!(report.wall_time(), Duration::from_secs(30));
+
+        let totals = report.phase_totals();
+        assert_eq!(
+            totals
+                .iter()
+                .map(|t| (t.name.as_str(), t.count, t.own.as_secs()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("cargo build", 1, 20),
+                ("pip install", 2, 9),
+                ("evaluate configuration", 1, 2),
+                ("download Python distribution", 1, 1),
+                ("resolve Python distribution", 1, 1),
+            ]
+        );
+
+        let summary = report.summary();
+        assert!(summary.starts_with("build timings (30.000s total):\n"));
+        assert!(summary.contains("pip install"));
+        assert!(summary.contains("(2 runs)"));
+    }
+
+    #[test]
+    fn test_chrome_trace() -> Result<()> {
+        let report = TimingReport {
+            spans: vec![Span {
+                name: "pip install".to_string(),
+                thread: 2,
+                start: Duration::from_millis(1500),
+                duration: Duration::from_micros(2500),
+            }],
+        };
+
+        let trace: serde_json::Value = serde_json::from_slice(&report.to_chrome_trace()?)?;
+        let event = &trace["traceEvents"][0];
+
+        assert_eq!(event["name"], "pip install");
+        assert_eq!(event["ph"], "X");
+        assert_eq!(event["ts"], 1_500_000);
+        assert_eq!(event["dur"], 2500);
+        assert_eq!(event["tid"], 2);
+        assert_eq!(trace["displayTimeUnit"], "ms");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*!
+Signed update manifests.
+
+Executables can embed an update channel: the URL of an update manifest and
+the Ed25519 public key it is signed with. The `oxidized_update` module of
+`pyembed` downloads the manifest at run-time and verifies its signature.
+
+When building an executable with an update signing key, a manifest
+describing the built artifacts is written next to them along with its
+signature. Publishing both at the channel URL makes the update available.
+*/
+
+use {
+    crate::starlark::build_manifest::{artifacts_from_paths, BuildArtifact},
+    anyhow::{anyhow, Context, Result},
+    ed25519_dalek::{Keypair, PublicKey, SecretKey, Signer},
+    rand::RngCore,
+    serde::Serialize,
+    std::io::Write,
+    std::path::{Path, PathBuf},
+};
+
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+
+/// Describes a version of an application available as an update.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct UpdateManifest {
+    /// Name of the application.
+    pub name: String,
+
+    /// Version of the application.
+    pub version: String,
+
+    /// Rust target triple the artifacts were built for.
+    pub target_triple: String,
+
+    /// Files making up the application, relative to the manifest.
+    pub artifacts: Vec<BuildArtifact>,
+}
+
+impl UpdateManifest {
+    /// Filename of the manifest of an application.
+    pub fn filename(name: &str) -> String {
+        format!("{}.update.json", name)
+    }
+
+    /// Filename of the signature of the manifest of an application.
+    pub fn signature_filename(name: &str) -> String {
+        format!("{}.sig", Self::filename(name))
+    }
+}
+
+/// Read an Ed25519 signing key from a file.
+///
+/// The file contains the 32 byte secret key as 64 hexadecimal characters.
+pub fn read_signing_key(path: &Path) -> Result<Keypair> {
+    let data = std::fs::read_to_string(path)
+        .with_context(|| format!("reading update signing key {}", path.display()))?;
+    let secret = hex::decode(data.trim())
+        .with_context(|| format!("parsing update signing key {}", path.display()))?;
+    let secret = SecretKey::from_bytes(&secret)
+        .map_err(|e| anyhow!("invalid update signing key {}: {}", path.display(), e))?;
+    let public = PublicKey::from(&secret);
+
+    Ok(Keypair { secret, public })
+}
+
+/// Generate an Ed25519 signing key, writing it to a file.
+///
+/// Returns the hex encoded public key to embed in executables.
+pub fn generate_signing_key(path: &Path) -> Result<String> {
+    if path.exists() {
+        return Err(anyhow!("{} already exists", path.display()));
+    }
+
+    let mut secret = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut secret);
+
+    // The key is only readable by its owner.
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+
+    let mut fh = options
+        .open(path)
+        .with_context(|| format!("creating {}", path.display()))?;
+    fh.write_all(format!("{}\n", hex::encode(&secret)).as_bytes())
+        .with_context(|| format!("writing {}", path.display()))?;
+
+    Ok(hex::encode(read_signing_key(path)?.public.as_bytes()))
+}
+
+/// Write a signed update manifest describing files of an application.
+///
+/// `artifacts` are the files and directories making up the application in
+/// `dest_dir`, which the manifest and its signature are written to.
+/// `public_key` is the key embedded in the application. It must belong to
+/// the signing key, or the application would reject the manifest.
+pub fn write_update_manifest(
+    dest_dir: &Path,
+    artifacts: &[PathBuf],
+    name: &str,
+    version: &str,
+    target_triple: &str,
+    public_key: &str,
+    signing_key_path: &Path,
+) -> Result<PathBuf> {
+    let keypair = read_signing_key(signing_key_path)?;
+
+    if hex::encode(keypair.public.as_bytes()) != public_key.to_lowercase() {
+        return Err(anyhow!(
+            "update signing key {} does not match the update public key {}",
+            signing_key_path.display(),
+            public_key
+        ));
+    }
+
+    let manifest = UpdateManifest {
+        name: name.to_string(),
+        version: version.to_string(),
+        target_triple: target_triple.to_string(),
+        artifacts: artifacts_from_paths(dest_dir, artifacts)?,
+    };
+
+    let data = serde_json::to_vec_pretty(&manifest)?;
+    let signature = keypair.sign(&data);
+
+    let manifest_path = dest_dir.join(UpdateManifest::filename(name));
+    std::fs::write(&manifest_path, &data)
+        .with_context(|| format!("writing {}", manifest_path.display()))?;
+
+    let signature_path = dest_dir.join(UpdateManifest::signature_filename(name));
+    std::fs::write(
+        &signature_path,
+        format!("{}\n", hex::encode(&signature.to_bytes()[..])),
+    )
+    .with_context(|| format!("writing {}", signature_path.display()))?;
+
+    Ok(manifest_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, ed25519_dalek::Verifier};
+
+    #[test]
+    fn test_write_update_manifest() -> Result<()> {
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+        let key_path = temp_dir.path().join("update.key");
+        let public_key = generate_signing_key(&key_path)?;
+
+        let dest_dir = temp_dir.path().join("dist");
+        std::fs::create_dir(&dest_dir)?;
+        std::fs::write(dest_dir.join("myapp"), b"binary")?;
+        // Not part of the application.
+        std::fs::write(dest_dir.join("other"), b"other")?;
+        let artifacts = vec![dest_dir.join("myapp")];
+
+        let manifest_path = write_update_manifest(
+            &dest_dir,
+            &artifacts,
+            "myapp",
+            "1.0.0",
+            "x",
+            &public_key,
+            &key_path,
+        )?;
+
+        let data = std::fs::read(&manifest_path)?;
+        let manifest: serde_json::Value = serde_json::from_slice(&data)?;
+        assert_eq!(manifest["version"], "1.0.0");
+        assert_eq!(manifest["artifacts"].as_array().unwrap().len(), 1);
+        assert_eq!(manifest["artifacts"][0]["path"], "myapp");
+
+        let signature = std::fs::read_to_string(dest_dir.join("myapp.update.json.sig"))?;
+        let signature = ed25519_dalek::Signature::from_bytes(&hex::decode(signature.trim())?)
+            .map_err(|e| anyhow!("{}", e))?;
+        let public_key =
+            PublicKey::from_bytes(&hex::decode(&public_key)?).map_err(|e| anyhow!("{}", e))?;
+        assert!(public_key.verify(&data, &signature).is_ok());
+
+        let other_key_path = temp_dir.path().join("other.key");
+        generate_signing_key(&other_key_path)?;
+        assert!(write_update_manifest(
+            &dest_dir,
+            &artifacts,
+            "myapp",
+            "1.0.0",
+            "x",
+            &hex::encode(public_key.as_bytes()),
+            &other_key_path
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_generate_signing_key_permissions() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempdir::TempDir::new("pyoxidizer-test")?;
+        let key_path = temp_dir.path().join("update.key");
+        generate_signing_key(&key_path)?;
+
+        let mode = std::fs::metadata(&key_path)?.permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert!(generate_signing_key(&key_path).is_err());
+
+        Ok(())
+    }
+}
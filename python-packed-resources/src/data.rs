@@ -2,11 +2,23 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use {std::borrow::Cow, std::collections::HashMap, std::convert::TryFrom, std::sync::Arc};
+use {
+    sha2::{Digest, Sha256},
+    std::borrow::Cow,
+    std::collections::HashMap,
+    std::convert::TryFrom,
+    std::sync::Arc,
+};
 
 /// Header value for version 1 of resources payload.
 pub const HEADER_V1: &[u8] = b"pyembed\x01";
 
+/// Header value for version 2 of resources payload.
+pub const HEADER_V2: &[u8] = b"pyembed\x02";
+
+/// Length in bytes of an entry in the name index of version 2 payloads.
+pub const NAME_INDEX_ENTRY_LENGTH: usize = 10;
+
 /// Defines interior padding mechanism between entries in blob sections.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum BlobInteriorPadding {
@@ -88,6 +100,10 @@ pub enum ResourceField {
     InMemoryPackageDistribution,
     InMemorySharedLibrary,
     SharedLibraryDependencyNames,
+    ContentDigest,
+    IsEncrypted,
+    FileEmulation,
+    BlobOffsets,
 }
 
 impl Into<u8> for ResourceField {
@@ -108,6 +124,10 @@ impl Into<u8> for ResourceField {
             ResourceField::InMemoryPackageDistribution => 12,
             ResourceField::InMemorySharedLibrary => 13,
             ResourceField::SharedLibraryDependencyNames => 14,
+            ResourceField::ContentDigest => 15,
+            ResourceField::IsEncrypted => 16,
+            ResourceField::FileEmulation => 17,
+            ResourceField::BlobOffsets => 18,
         }
     }
 }
@@ -132,11 +152,90 @@ impl TryFrom<u8> for ResourceField {
             0x0c => Ok(ResourceField::InMemoryPackageDistribution),
             0x0d => Ok(ResourceField::InMemorySharedLibrary),
             0x0e => Ok(ResourceField::SharedLibraryDependencyNames),
+            0x0f => Ok(ResourceField::ContentDigest),
+            0x10 => Ok(ResourceField::IsEncrypted),
+            0x11 => Ok(ResourceField::FileEmulation),
+            0x12 => Ok(ResourceField::BlobOffsets),
             _ => Err("invalid field type"),
         }
     }
 }
 
+/// Defines how `__file__` is set on Python modules imported from memory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileEmulation {
+    /// Do not set `__file__`.
+    None,
+
+    /// Set `__file__` to a path next to the executable.
+    ///
+    /// The file does not exist. This satisfies code that only inspects
+    /// `__file__`.
+    FakePath,
+
+    /// Set `__file__` to a path in an extraction directory.
+    ///
+    /// The module's source and its package's resources are written to the
+    /// extraction directory when the module is imported.
+    ExtractOnAccess,
+}
+
+impl FileEmulation {
+    /// String representation of the policy.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileEmulation::None => "none",
+            FileEmulation::FakePath => "fake-path",
+            FileEmulation::ExtractOnAccess => "extract-on-access",
+        }
+    }
+}
+
+impl Default for FileEmulation {
+    fn default() -> Self {
+        FileEmulation::None
+    }
+}
+
+impl Into<u8> for FileEmulation {
+    fn into(self) -> u8 {
+        match self {
+            FileEmulation::None => 0x00,
+            FileEmulation::FakePath => 0x01,
+            FileEmulation::ExtractOnAccess => 0x02,
+        }
+    }
+}
+
+impl TryFrom<u8> for FileEmulation {
+    type Error = &'static str;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x00 => Ok(FileEmulation::None),
+            0x01 => Ok(FileEmulation::FakePath),
+            0x02 => Ok(FileEmulation::ExtractOnAccess),
+            _ => Err("invalid file emulation value"),
+        }
+    }
+}
+
+impl TryFrom<&str> for FileEmulation {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "none" => Ok(FileEmulation::None),
+            "fake-path" => Ok(FileEmulation::FakePath),
+            "extract-on-access" => Ok(FileEmulation::ExtractOnAccess),
+            _ => Err(format!(
+                "{} is not a valid file emulation policy; use none, fake-path, or extract-on-access",
+                value
+            )),
+        }
+    }
+}
+
 /// Represents an embedded resource and all its metadata.
 #[derive(Clone, Debug, PartialEq)]
 pub struct Resource<'a, X: 'a>
@@ -183,6 +282,21 @@ where
 
     /// Sequence of names of shared libraries this resource depends on.
     pub shared_library_dependency_names: Option<Vec<Cow<'a, str>>>,
+
+    /// SHA-256 digest of the resource's in-memory data.
+    ///
+    /// See `Resource::compute_content_digest()` for what is digested.
+    pub content_digest: Option<Cow<'a, [X]>>,
+
+    /// Whether Python module source and bytecode are encrypted.
+    ///
+    /// See the `encryption` module for how data is encrypted.
+    pub is_encrypted: bool,
+
+    /// How `__file__` is set when the Python module is imported.
+    ///
+    /// If not defined, the importer's default policy is used.
+    pub file_emulation: Option<FileEmulation>,
 }
 
 impl<'a, X> Default for Resource<'a, X>
@@ -203,6 +317,111 @@ where
             in_memory_package_distribution: None,
             in_memory_shared_library: None,
             shared_library_dependency_names: None,
+            content_digest: None,
+            is_encrypted: false,
+            file_emulation: None,
+        }
+    }
+}
+
+impl<'a> Resource<'a, u8> {
+    /// Compute the SHA-256 digest of the resource's in-memory data.
+    ///
+    /// Every in-memory data field is digested in resource field order. Each
+    /// field is preceded by its resource field type and its length as a
+    /// little endian `u64`. Entries of resources and distribution metadata
+    /// are digested in name order, each with their name.
+    pub fn compute_content_digest(&self) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+
+        let mut digest_data = |field: ResourceField, data: &[u8]| {
+            let field: u8 = field.into();
+            hasher.input([field]);
+            hasher.input((data.len() as u64).to_le_bytes());
+            hasher.input(data);
+        };
+
+        let fields = [
+            (ResourceField::InMemorySource, &self.in_memory_source),
+            (ResourceField::InMemoryBytecode, &self.in_memory_bytecode),
+            (
+                ResourceField::InMemoryBytecodeOpt1,
+                &self.in_memory_bytecode_opt1,
+            ),
+            (
+                ResourceField::InMemoryBytecodeOpt2,
+                &self.in_memory_bytecode_opt2,
+            ),
+            (
+                ResourceField::InMemoryExtensionModuleSharedLibrary,
+                &self.in_memory_extension_module_shared_library,
+            ),
+        ];
+
+        for (field, data) in fields.iter() {
+            if let Some(data) = data {
+                digest_data(*field, data);
+            }
+        }
+
+        if let Some(resources) = &self.in_memory_resources {
+            let mut names = resources.keys().collect::<Vec<_>>();
+            names.sort();
+
+            for name in names {
+                digest_data(ResourceField::InMemoryResourcesData, name.as_bytes());
+                digest_data(ResourceField::InMemoryResourcesData, &resources[name]);
+            }
+        }
+
+        if let Some(metadata) = &self.in_memory_package_distribution {
+            let mut names = metadata.keys().collect::<Vec<_>>();
+            names.sort();
+
+            for name in names {
+                digest_data(ResourceField::InMemoryPackageDistribution, name.as_bytes());
+                digest_data(ResourceField::InMemoryPackageDistribution, &metadata[name]);
+            }
+        }
+
+        if let Some(data) = &self.in_memory_shared_library {
+            digest_data(ResourceField::InMemorySharedLibrary, data);
+        }
+
+        hasher.result().to_vec()
+    }
+
+    /// Whether the resource's recorded content digest matches its data.
+    ///
+    /// Returns false if the resource has no recorded digest.
+    pub fn verify_content_digest(&self) -> bool {
+        match &self.content_digest {
+            Some(digest) => digest.as_ref() == self.compute_content_digest().as_slice(),
+            None => false,
         }
     }
+
+    /// Encrypt Python module source and bytecode with a key.
+    ///
+    /// Does nothing if the resource is already encrypted.
+    pub fn encrypt(&mut self, key: &[u8]) {
+        if self.is_encrypted {
+            return;
+        }
+
+        for data in [
+            &mut self.in_memory_source,
+            &mut self.in_memory_bytecode,
+            &mut self.in_memory_bytecode_opt1,
+            &mut self.in_memory_bytecode_opt2,
+        ]
+        .iter_mut()
+        {
+            if let Some(value) = data.take() {
+                **data = Some(Cow::Owned(crate::encryption::encrypt(key, &value)));
+            }
+        }
+
+        self.is_encrypted = true;
+    }
 }
@@ -0,0 +1,157 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+/*! Encryption of resource data.
+
+Resource data can be encrypted with a 256-bit key so Python source and
+bytecode aren't stored in the clear.
+
+Data is encrypted with AES-256-GCM-SIV (RFC 8452). A fixed nonce is used,
+which makes encryption deterministic: encrypting the same data with the same
+key always yields the same output. GCM-SIV is resistant to nonce reuse, so
+this only reveals whether two resources hold the same data. Encrypted data
+consists of the ciphertext followed by the 16 byte authentication tag.
+
+Encryption only raises the bar for extracting code: whoever can run the
+program can obtain the key.
+*/
+
+use aes_gcm_siv::{
+    aead::{Aead, NewAead},
+    Aes256GcmSiv, Key, Nonce,
+};
+
+/// Length in bytes of encryption keys.
+pub const KEY_LENGTH: usize = 32;
+
+/// Length in bytes of the authentication tag following encrypted data.
+pub const TAG_LENGTH: usize = 16;
+
+/// Nonce of every encryption.
+const NONCE: [u8; 12] = [0; 12];
+
+fn cipher(key: &[u8]) -> Aes256GcmSiv {
+    Aes256GcmSiv::new(Key::from_slice(key))
+}
+
+/// Parse an encryption key.
+///
+/// Keys are 64 hexadecimal characters, optionally surrounded by whitespace,
+/// or 32 raw bytes.
+pub fn parse_key(data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let text = std::str::from_utf8(data).map(|s| s.trim());
+
+    if let Ok(text) = text {
+        if text.len() == KEY_LENGTH * 2 && text.is_ascii() {
+            let mut key = Vec::with_capacity(KEY_LENGTH);
+
+            for i in 0..KEY_LENGTH {
+                key.push(
+                    u8::from_str_radix(&text[i * 2..i * 2 + 2], 16)
+                        .map_err(|_| "invalid hex in encryption key")?,
+                );
+            }
+
+            return Ok(key);
+        }
+    }
+
+    if data.len() == KEY_LENGTH {
+        Ok(data.to_vec())
+    } else {
+        Err("encryption key must be 32 bytes or 64 hex characters")
+    }
+}
+
+/// Encrypt data with a key.
+///
+/// The key must be `KEY_LENGTH` bytes, as returned by `parse_key()`.
+pub fn encrypt(key: &[u8], data: &[u8]) -> Vec<u8> {
+    cipher(key)
+        .encrypt(Nonce::from_slice(&NONCE), data)
+        .expect("encryption only fails on data larger than 64 GiB")
+}
+
+/// Decrypt data produced by `encrypt()`.
+///
+/// Errors if the data was not encrypted with this key or was modified.
+pub fn decrypt(key: &[u8], data: &[u8]) -> Result<Vec<u8>, &'static str> {
+    if key.len() != KEY_LENGTH {
+        return Err("encryption key must be 32 bytes");
+    }
+
+    if data.len() < TAG_LENGTH {
+        return Err("encrypted data too short");
+    }
+
+    // The tag is verified in constant time to not leak timing information.
+    cipher(key)
+        .decrypt(Nonce::from_slice(&NONCE), data)
+        .map_err(|_| "failed to decrypt data: wrong key or data modified")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    #[test]
+    fn test_aes_gcm_siv() {
+        // RFC 8452 appendix C.2, AEAD_AES_256_GCM_SIV with an 8 byte plaintext.
+        let mut key = [0u8; 32];
+        key[0] = 1;
+
+        let mut nonce = [0u8; 12];
+        nonce[0] = 3;
+
+        assert_eq!(
+            cipher(&key)
+                .encrypt(Nonce::from_slice(&nonce), &[1u8, 0, 0, 0, 0, 0, 0, 0][..])
+                .unwrap(),
+            vec![
+                0xc2, 0xef, 0x32, 0x8e, 0x5c, 0x71, 0xc8, 0x3b, 0x84, 0x31, 0x22, 0x13, 0x0f, 0x73,
+                0x64, 0xb7, 0x61, 0xe0, 0xb9, 0x74, 0x27, 0xe3, 0xdf, 0x28
+            ]
+        );
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for len in &[0, 1, 31, 32, 33, 100] {
+            let data = vec![42u8; *len];
+            let encrypted = encrypt(&KEY, &data);
+
+            assert_eq!(encrypted.len(), len + TAG_LENGTH);
+            if *len > 0 {
+                assert_ne!(&encrypted[0..*len], data.as_slice());
+            }
+            assert_eq!(encrypt(&KEY, &data), encrypted);
+            assert_eq!(decrypt(&KEY, &encrypted).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_decrypt_errors() {
+        let mut encrypted = encrypt(&KEY, b"print('hello')");
+
+        assert!(decrypt(&[8; 32], &encrypted).is_err());
+        assert!(decrypt(&KEY[0..16], &encrypted).is_err());
+        assert!(decrypt(&KEY, &encrypted[0..10]).is_err());
+
+        encrypted[0] ^= 1;
+        assert!(decrypt(&KEY, &encrypted).is_err());
+    }
+
+    #[test]
+    fn test_parse_key() {
+        assert_eq!(parse_key(&[1; 32]).unwrap(), vec![1; 32]);
+        assert_eq!(
+            parse_key(format!("{}\n", "0a".repeat(32)).as_bytes()).unwrap(),
+            vec![10; 32]
+        );
+        assert!(parse_key(b"0a0a").is_err());
+        assert!(parse_key("zz".repeat(32).as_bytes()).is_err());
+    }
+}
@@ -21,6 +21,7 @@ the module `import` mechanism.
 */
 
 pub mod data;
+pub mod encryption;
 #[allow(unused)]
 pub mod parser;
 pub mod specifications;
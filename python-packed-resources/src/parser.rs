@@ -7,9 +7,13 @@ Management of Python resources.
 */
 
 use {
-    super::data::{BlobInteriorPadding, BlobSectionField, Resource, ResourceField, HEADER_V1},
+    super::data::{
+        BlobInteriorPadding, BlobSectionField, FileEmulation, Resource, ResourceField, HEADER_V1,
+        HEADER_V2, NAME_INDEX_ENTRY_LENGTH,
+    },
     byteorder::{LittleEndian, ReadBytesExt},
     std::borrow::Cow,
+    std::cmp::Ordering,
     std::collections::{HashMap, HashSet},
     std::convert::TryFrom,
     std::io::{Cursor, Read},
@@ -27,262 +31,358 @@ struct BlobSection {
 /// Holds state used to read an individual blob section.
 #[derive(Clone, Copy)]
 struct BlobSectionReadState {
+    /// Offset of the start of the blob section.
+    start: usize,
     offset: usize,
     interior_padding: BlobInteriorPadding,
 }
 
 pub type PythonPackageResources<'a> = Arc<Box<HashMap<&'a str, &'a [u8]>>>;
 
-pub struct ResourceParserIterator<'a> {
-    done: bool,
+/// Reads blob data of resource fields from blob sections.
+trait BlobReader<'a> {
+    /// Position the blob section of a resource field at an offset within it.
+    fn seek(&mut self, field: u8, offset: usize) -> Result<(), &'static str>;
+
+    /// Read the next blob of a resource field.
+    fn read(&mut self, field: ResourceField, length: usize) -> Result<&'a [u8], &'static str>;
+}
+
+/// Read the blob at the current offset of a blob section and advance past it.
+fn read_blob<'a>(
     data: &'a [u8],
-    reader: Cursor<&'a [u8]>,
-    blob_sections: [Option<BlobSectionReadState>; 256],
-    claimed_resources_count: usize,
-    read_resources_count: usize,
+    state: &mut BlobSectionReadState,
+    length: usize,
+) -> Result<&'a [u8], &'static str> {
+    let blob = data
+        .get(state.offset..state.offset + length)
+        .ok_or("blob data out of bounds")?;
+
+    state.offset += match &state.interior_padding {
+        BlobInteriorPadding::None => length,
+        BlobInteriorPadding::Null => length + 1,
+    };
+
+    Ok(blob)
 }
 
-impl<'a> ResourceParserIterator<'a> {
-    /// Resolve a slice to an individual blob's data.
-    ///
-    /// This accepts a reference to the original blobs payload, an array of
-    /// current blob section offsets, the resource field being accessed, and the
-    /// length of the blob and returns a slice to that blob.
-    fn resolve_blob_data(&mut self, resource_field: ResourceField, length: usize) -> &'a [u8] {
-        let mut state = self.blob_sections[resource_field as usize]
+/// Blob sections read in order, as resources are read in order.
+#[derive(Clone)]
+struct BlobSections<'a> {
+    data: &'a [u8],
+    sections: [Option<BlobSectionReadState>; 256],
+}
+
+impl<'a> BlobReader<'a> for BlobSections<'a> {
+    fn seek(&mut self, field: u8, offset: usize) -> Result<(), &'static str> {
+        let state = self.sections[field as usize]
             .as_mut()
-            .unwrap();
+            .ok_or("blob offset for unknown blob section")?;
+        state.offset = state.start + offset;
 
-        let blob = &self.data[state.offset..state.offset + length];
+        Ok(())
+    }
 
-        let increment = match &state.interior_padding {
-            BlobInteriorPadding::None => length,
-            BlobInteriorPadding::Null => length + 1,
-        };
+    fn read(&mut self, field: ResourceField, length: usize) -> Result<&'a [u8], &'static str> {
+        let state = self.sections[field as usize]
+            .as_mut()
+            .ok_or("blob data for unknown blob section")?;
+
+        read_blob(self.data, state, length)
+    }
+}
+
+/// Blob sections of a single resource, located by the resource's blob offsets.
+struct ResourceBlobs<'a, 's> {
+    sections: &'s BlobSections<'a>,
+    /// Read state of the blob sections the resource has data in.
+    states: Vec<(u8, BlobSectionReadState)>,
+}
+
+impl<'a, 's> BlobReader<'a> for ResourceBlobs<'a, 's> {
+    fn seek(&mut self, field: u8, offset: usize) -> Result<(), &'static str> {
+        let mut state =
+            self.sections.sections[field as usize].ok_or("blob offset for unknown blob section")?;
+        state.offset = state.start + offset;
 
-        state.offset += increment;
+        self.states.retain(|(f, _)| *f != field);
+        self.states.push((field, state));
 
-        blob
+        Ok(())
     }
 
-    fn parse_next(&mut self) -> Result<Option<Resource<'a, u8>>, &'static str> {
-        let mut current_resource = Resource::default();
-        let mut current_resource_name = None;
+    fn read(&mut self, field: ResourceField, length: usize) -> Result<&'a [u8], &'static str> {
+        let field = field as u8;
+        let state = self
+            .states
+            .iter_mut()
+            .find(|(f, _)| *f == field)
+            .map(|(_, state)| state)
+            .ok_or("blob data without blob offset")?;
 
-        loop {
-            let field_type = self
-                .reader
-                .read_u8()
-                .or_else(|_| Err("failed reading field type"))?;
+        read_blob(self.sections.data, state, length)
+    }
+}
 
-            let field_type = ResourceField::try_from(field_type)?;
+/// Parse a resource entry of the resources index.
+///
+/// Returns `None` if the end of the index is reached instead.
+fn parse_entry<'a, B: BlobReader<'a>>(
+    reader: &mut Cursor<&'a [u8]>,
+    blobs: &mut B,
+) -> Result<Option<Resource<'a, u8>>, &'static str> {
+    let mut current_resource = Resource::default();
+    let mut current_resource_name = None;
 
-            match field_type {
-                ResourceField::EndOfIndex => {
-                    self.done = true;
+    loop {
+        let field_type = reader.read_u8().map_err(|_| "failed reading field type")?;
 
-                    if self.read_resources_count != self.claimed_resources_count {
-                        return Err("mismatch between advertised index count and actual");
-                    }
+        let field_type = ResourceField::try_from(field_type)?;
 
-                    return Ok(None);
-                }
-                ResourceField::StartOfEntry => {
-                    self.read_resources_count += 1;
-                    current_resource = Resource::default();
-                    current_resource_name = None;
-                }
+        match field_type {
+            ResourceField::EndOfIndex => {
+                return Ok(None);
+            }
+            ResourceField::StartOfEntry => {
+                current_resource = Resource::default();
+                current_resource_name = None;
+            }
 
-                ResourceField::EndOfEntry => {
-                    let res = if let Some(name) = current_resource_name {
-                        Ok(Some(current_resource))
-                    } else {
-                        Err("resource name field is required")
-                    };
+            ResourceField::EndOfEntry => {
+                let res = if let Some(name) = current_resource_name {
+                    Ok(Some(current_resource))
+                } else {
+                    Err("resource name field is required")
+                };
 
-                    current_resource = Resource::default();
-                    current_resource_name = None;
+                current_resource = Resource::default();
+                current_resource_name = None;
 
-                    return res;
-                }
-                ResourceField::ModuleName => {
-                    let l = self
-                        .reader
-                        .read_u16::<LittleEndian>()
-                        .or_else(|_| Err("failed reading resource name length"))?
-                        as usize;
+                return res;
+            }
+            ResourceField::ModuleName => {
+                let l = reader
+                    .read_u16::<LittleEndian>()
+                    .map_err(|_| "failed reading resource name length")?
+                    as usize;
 
-                    let name = unsafe {
-                        std::str::from_utf8_unchecked(self.resolve_blob_data(field_type, l))
-                    };
+                let name = unsafe { std::str::from_utf8_unchecked(blobs.read(field_type, l)?) };
 
-                    current_resource_name = Some(name);
-                    current_resource.name = Cow::Borrowed(name);
-                }
-                ResourceField::IsPackage => {
-                    current_resource.is_package = true;
-                }
-                ResourceField::IsNamespacePackage => {
-                    current_resource.is_namespace_package = true;
-                }
-                ResourceField::InMemorySource => {
-                    let l = self
-                        .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading source length"))?
-                        as usize;
+                current_resource_name = Some(name);
+                current_resource.name = Cow::Borrowed(name);
+            }
+            ResourceField::IsPackage => {
+                current_resource.is_package = true;
+            }
+            ResourceField::IsNamespacePackage => {
+                current_resource.is_namespace_package = true;
+            }
+            ResourceField::IsEncrypted => {
+                current_resource.is_encrypted = true;
+            }
+            ResourceField::BlobOffsets => {
+                let count = reader
+                    .read_u8()
+                    .map_err(|_| "failed reading blob offsets count")?;
 
-                    current_resource.in_memory_source =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
-                }
-                ResourceField::InMemoryBytecode => {
-                    let l = self
-                        .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading bytecode length"))?
+                for _ in 0..count {
+                    let field = reader
+                        .read_u8()
+                        .map_err(|_| "failed reading blob offset field type")?;
+                    let offset = reader
+                        .read_u64::<LittleEndian>()
+                        .map_err(|_| "failed reading blob offset")?
                         as usize;
 
-                    current_resource.in_memory_bytecode =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                    blobs.seek(field, offset)?;
                 }
-                ResourceField::InMemoryBytecodeOpt1 => {
-                    let l = self
-                        .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading bytecode length"))?
-                        as usize;
+            }
+            ResourceField::FileEmulation => {
+                let value = reader
+                    .read_u8()
+                    .map_err(|_| "failed reading file emulation value")?;
 
-                    current_resource.in_memory_bytecode_opt1 =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
-                }
-                ResourceField::InMemoryBytecodeOpt2 => {
-                    let l = self
-                        .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading bytecode length"))?
-                        as usize;
+                current_resource.file_emulation = Some(FileEmulation::try_from(value)?);
+            }
+            ResourceField::InMemorySource => {
+                let l = reader
+                    .read_u32::<LittleEndian>()
+                    .map_err(|_| "failed reading source length")? as usize;
 
-                    current_resource.in_memory_bytecode_opt2 =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
-                }
-                ResourceField::InMemoryExtensionModuleSharedLibrary => {
-                    let l = self
-                        .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading extension module length"))?
+                current_resource.in_memory_source = Some(Cow::Borrowed(blobs.read(field_type, l)?));
+            }
+            ResourceField::InMemoryBytecode => {
+                let l = reader
+                    .read_u32::<LittleEndian>()
+                    .map_err(|_| "failed reading bytecode length")?
+                    as usize;
+
+                current_resource.in_memory_bytecode =
+                    Some(Cow::Borrowed(blobs.read(field_type, l)?));
+            }
+            ResourceField::InMemoryBytecodeOpt1 => {
+                let l = reader
+                    .read_u32::<LittleEndian>()
+                    .map_err(|_| "failed reading bytecode length")?
+                    as usize;
+
+                current_resource.in_memory_bytecode_opt1 =
+                    Some(Cow::Borrowed(blobs.read(field_type, l)?));
+            }
+            ResourceField::InMemoryBytecodeOpt2 => {
+                let l = reader
+                    .read_u32::<LittleEndian>()
+                    .map_err(|_| "failed reading bytecode length")?
+                    as usize;
+
+                current_resource.in_memory_bytecode_opt2 =
+                    Some(Cow::Borrowed(blobs.read(field_type, l)?));
+            }
+            ResourceField::InMemoryExtensionModuleSharedLibrary => {
+                let l = reader
+                    .read_u32::<LittleEndian>()
+                    .map_err(|_| "failed reading extension module length")?
+                    as usize;
+
+                current_resource.in_memory_extension_module_shared_library =
+                    Some(Cow::Borrowed(blobs.read(field_type, l)?));
+            }
+
+            ResourceField::InMemoryResourcesData => {
+                let resource_count = reader
+                    .read_u32::<LittleEndian>()
+                    .map_err(|_| "failed reading resources length")?
+                    as usize;
+
+                let mut resources = Box::new(HashMap::with_capacity(resource_count));
+
+                for _ in 0..resource_count {
+                    let resource_name_length = reader
+                        .read_u16::<LittleEndian>()
+                        .map_err(|_| "failed reading resource name")?
                         as usize;
 
-                    current_resource.in_memory_extension_module_shared_library =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
-                }
+                    let resource_name = unsafe {
+                        std::str::from_utf8_unchecked(blobs.read(field_type, resource_name_length)?)
+                    };
 
-                ResourceField::InMemoryResourcesData => {
-                    let resource_count = self
-                        .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading resources length"))?
+                    let resource_length = reader
+                        .read_u64::<LittleEndian>()
+                        .map_err(|_| "failed reading resource length")?
                         as usize;
 
-                    let mut resources = Box::new(HashMap::with_capacity(resource_count));
+                    let resource_data = blobs.read(field_type, resource_length)?;
 
-                    for _ in 0..resource_count {
-                        let resource_name_length = self
-                            .reader
-                            .read_u16::<LittleEndian>()
-                            .or_else(|_| Err("failed reading resource name"))?
-                            as usize;
+                    resources.insert(Cow::Borrowed(resource_name), Cow::Borrowed(resource_data));
+                }
 
-                        let resource_name = unsafe {
-                            std::str::from_utf8_unchecked(
-                                self.resolve_blob_data(field_type, resource_name_length),
-                            )
-                        };
+                current_resource.in_memory_resources = Some(Arc::new(resources));
+            }
 
-                        let resource_length = self
-                            .reader
-                            .read_u64::<LittleEndian>()
-                            .or_else(|_| Err("failed reading resource length"))?
-                            as usize;
+            ResourceField::InMemoryPackageDistribution => {
+                let resource_count = reader
+                    .read_u32::<LittleEndian>()
+                    .map_err(|_| "failed reading package distribution length")?
+                    as usize;
 
-                        let resource_data = self.resolve_blob_data(field_type, resource_length);
+                let mut resources = HashMap::with_capacity(resource_count);
 
-                        resources
-                            .insert(Cow::Borrowed(resource_name), Cow::Borrowed(resource_data));
-                    }
+                for _ in 0..resource_count {
+                    let name_length = reader
+                        .read_u16::<LittleEndian>()
+                        .map_err(|_| "failed reading distribution metadata name")?
+                        as usize;
 
-                    current_resource.in_memory_resources = Some(Arc::new(resources));
-                }
+                    let name = unsafe {
+                        std::str::from_utf8_unchecked(blobs.read(field_type, name_length)?)
+                    };
 
-                ResourceField::InMemoryPackageDistribution => {
-                    let resource_count = self
-                        .reader
-                        .read_u32::<LittleEndian>()
-                        .or_else(|_| Err("failed reading package distribution length"))?
+                    let resource_length = reader
+                        .read_u64::<LittleEndian>()
+                        .map_err(|_| "failed reading package distribution resource length")?
                         as usize;
 
-                    let mut resources = HashMap::with_capacity(resource_count);
+                    let resource_data = blobs.read(field_type, resource_length)?;
 
-                    for _ in 0..resource_count {
-                        let name_length = self
-                            .reader
-                            .read_u16::<LittleEndian>()
-                            .or_else(|_| Err("failed reading distribution metadata name"))?
-                            as usize;
+                    resources.insert(Cow::Borrowed(name), Cow::Borrowed(resource_data));
+                }
 
-                        let name = unsafe {
-                            std::str::from_utf8_unchecked(
-                                self.resolve_blob_data(field_type, name_length),
-                            )
-                        };
+                current_resource.in_memory_package_distribution = Some(resources);
+            }
 
-                        let resource_length =
-                            self.reader.read_u64::<LittleEndian>().or_else(|_| {
-                                Err("failed reading package distribution resource length")
-                            })? as usize;
+            ResourceField::InMemorySharedLibrary => {
+                let l = reader
+                    .read_u64::<LittleEndian>()
+                    .map_err(|_| "failed reading in-memory shared library length")?
+                    as usize;
 
-                        let resource_data = self.resolve_blob_data(field_type, resource_length);
+                current_resource.in_memory_shared_library =
+                    Some(Cow::Borrowed(blobs.read(field_type, l)?));
+            }
 
-                        resources.insert(Cow::Borrowed(name), Cow::Borrowed(resource_data));
-                    }
+            ResourceField::SharedLibraryDependencyNames => {
+                let names_count = reader
+                    .read_u16::<LittleEndian>()
+                    .map_err(|_| "failed reading shared library dependency names length")?
+                    as usize;
 
-                    current_resource.in_memory_package_distribution = Some(resources);
-                }
+                let mut names = Vec::new();
 
-                ResourceField::InMemorySharedLibrary => {
-                    let l = self
-                        .reader
-                        .read_u64::<LittleEndian>()
-                        .or_else(|_| Err("failed reading in-memory shared library length"))?
+                for _ in 0..names_count {
+                    let name_length = reader
+                        .read_u16::<LittleEndian>()
+                        .map_err(|_| "failed reading shared library dependency name length")?
                         as usize;
 
-                    current_resource.in_memory_shared_library =
-                        Some(Cow::Borrowed(self.resolve_blob_data(field_type, l)));
+                    let name = unsafe {
+                        std::str::from_utf8_unchecked(blobs.read(field_type, name_length)?)
+                    };
+
+                    names.push(Cow::Borrowed(name));
                 }
 
-                ResourceField::SharedLibraryDependencyNames => {
-                    let names_count =
-                        self.reader.read_u16::<LittleEndian>().or_else(|_| {
-                            Err("failed reading shared library dependency names length")
-                        })? as usize;
+                current_resource.shared_library_dependency_names = Some(names);
+            }
+            ResourceField::ContentDigest => {
+                let l = reader
+                    .read_u8()
+                    .map_err(|_| "failed reading content digest length")?
+                    as usize;
+
+                current_resource.content_digest = Some(Cow::Borrowed(blobs.read(field_type, l)?));
+            }
+        }
+    }
+}
 
-                    let mut names = Vec::new();
+#[derive(Clone)]
+pub struct ResourceParserIterator<'a> {
+    done: bool,
+    reader: Cursor<&'a [u8]>,
+    blobs: BlobSections<'a>,
+    claimed_resources_count: usize,
+    read_resources_count: usize,
+}
 
-                    for _ in 0..names_count {
-                        let name_length = self.reader.read_u16::<LittleEndian>().or_else(|_| {
-                            Err("failed reading shared library dependency name length")
-                        })? as usize;
+impl<'a> ResourceParserIterator<'a> {
+    fn parse_next(&mut self) -> Result<Option<Resource<'a, u8>>, &'static str> {
+        let res = parse_entry(&mut self.reader, &mut self.blobs);
 
-                        let name = unsafe {
-                            std::str::from_utf8_unchecked(
-                                self.resolve_blob_data(field_type, name_length),
-                            )
-                        };
+        match &res {
+            // Entries that failed to parse were read too.
+            Ok(Some(_)) | Err(_) => {
+                self.read_resources_count += 1;
 
-                        names.push(Cow::Borrowed(name));
-                    }
+                res
+            }
+            Ok(None) => {
+                self.done = true;
 
-                    current_resource.shared_library_dependency_names = Some(names);
+                if self.read_resources_count != self.claimed_resources_count {
+                    return Err("mismatch between advertised index count and actual");
                 }
+
+                Ok(None)
             }
         }
     }
@@ -314,28 +414,178 @@ pub fn load_resources<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>,
     let header = &data[0..8];
 
     if header == HEADER_V1 {
-        load_resources_v1(&data[8..])
+        Ok(parse_indices(&data[8..], 1)?.0)
+    } else if header == HEADER_V2 {
+        Ok(parse_indices(&data[8..], 2)?.0)
     } else {
         Err("unrecognized file format")
     }
 }
 
-fn load_resources_v1<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &'static str> {
+/// Random access to resources by name.
+///
+/// Resources are parsed when looked up, so lookups don't depend on the
+/// number of resources. Requires version 2 data, which has a name index.
+#[derive(Clone)]
+pub struct ResourceIndex<'a> {
+    /// Parser positioned at the start of the resources index.
+    parser: ResourceParserIterator<'a>,
+    resources_index_offset: usize,
+    name_index: &'a [u8],
+    names_offset: usize,
+}
+
+impl<'a> std::fmt::Debug for ResourceIndex<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ResourceIndex")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+impl<'a> ResourceIndex<'a> {
+    /// The number of resources.
+    pub fn len(&self) -> usize {
+        self.name_index.len() / NAME_INDEX_ENTRY_LENGTH
+    }
+
+    /// Whether there are no resources.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterate over all resources, in the order they were written.
+    pub fn iter(&self) -> ResourceParserIterator<'a> {
+        self.parser.clone()
+    }
+
+    /// Resolve the name and resources index offset of a name index entry.
+    fn name_entry(&self, i: usize) -> Result<(&'a [u8], usize), &'static str> {
+        let mut reader = Cursor::new(
+            &self.name_index[i * NAME_INDEX_ENTRY_LENGTH..(i + 1) * NAME_INDEX_ENTRY_LENGTH],
+        );
+
+        let name_offset = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|_| "failed reading name offset")? as usize;
+        let name_length = reader
+            .read_u16::<LittleEndian>()
+            .map_err(|_| "failed reading name length")? as usize;
+        let index_offset = reader
+            .read_u32::<LittleEndian>()
+            .map_err(|_| "failed reading resources index offset")?
+            as usize;
+
+        let start = self.names_offset + name_offset;
+        let name = self
+            .parser
+            .blobs
+            .data
+            .get(start..start + name_length)
+            .ok_or("name index entry out of bounds")?;
+
+        Ok((name, index_offset))
+    }
+
+    /// Look up a resource by name.
+    pub fn get(&self, name: &str) -> Result<Option<Resource<'a, u8>>, &'static str> {
+        // Names are sorted by their bytes, which is how `str` orders.
+        let mut low = 0;
+        let mut high = self.len();
+
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let (candidate, index_offset) = self.name_entry(mid)?;
+
+            match candidate.cmp(name.as_bytes()) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => {
+                    let mut reader = Cursor::new(*self.parser.reader.get_ref());
+                    reader.set_position((self.resources_index_offset + index_offset) as u64);
+
+                    let mut blobs = ResourceBlobs {
+                        sections: &self.parser.blobs,
+                        states: Vec::new(),
+                    };
+
+                    return match parse_entry(&mut reader, &mut blobs)? {
+                        Some(resource) => Ok(Some(resource)),
+                        None => Err("name index entry points past resources index"),
+                    };
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Load resources data for random access.
+///
+/// Only version 2 data can be loaded this way.
+pub fn load_resources_index<'a>(data: &'a [u8]) -> Result<ResourceIndex<'a>, &'static str> {
+    if data.len() < HEADER_V2.len() {
+        return Err("error reading 8 byte header");
+    }
+
+    let header = &data[0..8];
+
+    if header != HEADER_V2 {
+        return Err("random access requires version 2 resources data");
+    }
+
+    let (parser, name_index) = parse_indices(&data[8..], 2)?;
+
+    let names_offset = match &parser.blobs.sections[ResourceField::ModuleName as usize] {
+        Some(state) => state.start,
+        None => 0,
+    };
+
+    Ok(ResourceIndex {
+        resources_index_offset: parser.reader.position() as usize,
+        parser,
+        name_index,
+        names_offset,
+    })
+}
+
+/// Parse the global header and blob index of resources data.
+///
+/// Returns a parser positioned at the start of the resources index and the
+/// name index, which is empty before version 2.
+fn parse_indices<'a>(
+    data: &'a [u8],
+    version: u8,
+) -> Result<(ResourceParserIterator<'a>, &'a [u8]), &'static str> {
     let mut reader = Cursor::new(data);
 
     let blob_section_count = reader
         .read_u8()
-        .or_else(|_| Err("failed reading blob section count"))?;
+        .map_err(|_| "failed reading blob section count")?;
     let blob_index_length = reader
         .read_u32::<LittleEndian>()
-        .or_else(|_| Err("failed reading blob index length"))? as usize;
+        .map_err(|_| "failed reading blob index length")? as usize;
     let resources_count = reader
         .read_u32::<LittleEndian>()
-        .or_else(|_| Err("failed reading resources count"))? as usize;
+        .map_err(|_| "failed reading resources count")? as usize;
     let resources_index_length = reader
         .read_u32::<LittleEndian>()
-        .or_else(|_| Err("failed reading resources index length"))?
+        .map_err(|_| "failed reading resources index length")?
         as usize;
+    let name_index_length = if version >= 2 {
+        reader
+            .read_u32::<LittleEndian>()
+            .map_err(|_| "failed reading name index length")? as usize
+    } else {
+        0
+    };
+
+    if version >= 2 && name_index_length != resources_count * NAME_INDEX_ENTRY_LENGTH {
+        return Err("mismatch between name index length and resources count");
+    }
+
+    let global_header_length = reader.position() as usize;
 
     let mut current_blob_field = None;
     let mut current_blob_raw_payload_length = None;
@@ -347,7 +597,7 @@ fn load_resources_v1<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
         loop {
             let field_type = reader
                 .read_u8()
-                .or_else(|_| Err("failed reading blob section field type"))?;
+                .map_err(|_| "failed reading blob section field type")?;
 
             let field_type = BlobSectionField::try_from(field_type)?;
 
@@ -380,19 +630,19 @@ fn load_resources_v1<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
                 BlobSectionField::ResourceFieldType => {
                     let field = reader
                         .read_u8()
-                        .or_else(|_| Err("failed reading blob resource field value"))?;
+                        .map_err(|_| "failed reading blob resource field value")?;
                     current_blob_field = Some(field);
                 }
                 BlobSectionField::RawPayloadLength => {
                     let l = reader
                         .read_u64::<LittleEndian>()
-                        .or_else(|_| Err("failed reading raw payload length"))?;
+                        .map_err(|_| "failed reading raw payload length")?;
                     current_blob_raw_payload_length = Some(l as usize);
                 }
                 BlobSectionField::InteriorPadding => {
                     let padding = reader
                         .read_u8()
-                        .or_else(|_| Err("failed reading interior padding field value"))?;
+                        .map_err(|_| "failed reading interior padding field value")?;
 
                     current_blob_interior_padding = Some(match padding {
                         0x01 => BlobInteriorPadding::None,
@@ -408,22 +658,25 @@ fn load_resources_v1<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
         return Err("mismatch between blob sections count");
     }
 
+    let name_index_offset = reader.position() as usize;
+    let name_index = data
+        .get(name_index_offset..name_index_offset + name_index_length)
+        .ok_or("name index out of bounds")?;
+    reader.set_position((name_index_offset + name_index_length) as u64);
+
     // Array indexing resource field to current payload offset within that section.
     let mut blob_offsets: [Option<BlobSectionReadState>; 256] = [None; 256];
 
     // Global payload offset where blobs data starts.
     let blob_start_offset: usize =
-            // Global header.
-            1 + 4 + 4 + 4
-            + blob_index_length
-            + resources_index_length
-        ;
+        global_header_length + blob_index_length + name_index_length + resources_index_length;
     // Current offset from start of blobs data.
     let mut current_blob_offset = 0;
 
     for section in &blob_sections {
         let section_start_offset = blob_start_offset + current_blob_offset;
         blob_offsets[section.resource_field as usize] = Some(BlobSectionReadState {
+            start: section_start_offset,
             offset: section_start_offset,
             interior_padding: match section.interior_padding {
                 Some(padding) => padding,
@@ -433,14 +686,23 @@ fn load_resources_v1<'a>(data: &'a [u8]) -> Result<ResourceParserIterator<'a>, &
         current_blob_offset += section.raw_payload_length;
     }
 
-    Ok(ResourceParserIterator {
-        done: resources_index_length == 0 || resources_count == 0,
-        data,
-        reader,
-        blob_sections: blob_offsets,
-        claimed_resources_count: resources_count,
-        read_resources_count: 0,
-    })
+    if blob_start_offset + current_blob_offset > data.len() {
+        return Err("blob data out of bounds");
+    }
+
+    Ok((
+        ResourceParserIterator {
+            done: resources_index_length == 0 || resources_count == 0,
+            reader,
+            blobs: BlobSections {
+                data,
+                sections: blob_offsets,
+            },
+            claimed_resources_count: resources_count,
+            read_resources_count: 0,
+        },
+        name_index,
+    ))
 }
 
 #[cfg(test)]
@@ -448,7 +710,7 @@ mod tests {
     use {
         super::*,
         crate::data::{BlobInteriorPadding, Resource},
-        crate::writer::write_embedded_resources_v1,
+        crate::writer::{write_embedded_resources_v1, write_embedded_resources_v2},
         std::collections::BTreeMap,
     };
 
@@ -466,7 +728,7 @@ mod tests {
         let res = load_resources(data);
         assert_eq!(res.err(), Some("unrecognized file format"));
 
-        let data = b"pyembed\x02";
+        let data = b"pyembed\x03";
         let res = load_resources(data);
         assert_eq!(res.err(), Some("unrecognized file format"));
     }
@@ -495,6 +757,13 @@ mod tests {
         load_resources(data).unwrap();
     }
 
+    #[test]
+    fn test_blob_data_out_of_bounds() {
+        let data = b"pyembed\x01\x01\x0e\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x02\x01\x03\x64\x00\x00\x00\x00\x00\x00\x00\xff\x00";
+        let res = load_resources(data);
+        assert_eq!(res.err(), Some("blob data out of bounds"));
+    }
+
     #[test]
     fn test_index_count_mismatch() {
         let data = b"pyembed\x01\x00\x00\x00\x00\x00\x01\x00\x00\x00\x01\x00\x00\x00\x00";
@@ -946,6 +1215,9 @@ mod tests {
             in_memory_package_distribution: Some(distribution),
             in_memory_shared_library: Some(Cow::from(b"library".to_vec())),
             shared_library_dependency_names: Some(vec![Cow::from("libfoo"), Cow::from("depends")]),
+            content_digest: Some(Cow::from(b"digest".to_vec())),
+            is_encrypted: true,
+            file_emulation: Some(FileEmulation::ExtractOnAccess),
         };
 
         let mut data = Vec::new();
@@ -1001,5 +1273,171 @@ mod tests {
             entry.shared_library_dependency_names.as_ref().unwrap(),
             &vec!["libfoo", "depends"]
         );
+        assert_eq!(entry.content_digest.as_ref().unwrap().as_ref(), b"digest");
+        assert!(entry.is_encrypted);
+        assert_eq!(entry.file_emulation, Some(FileEmulation::ExtractOnAccess));
+    }
+
+    #[test]
+    fn test_content_digest() {
+        let mut resource = Resource {
+            name: Cow::from("foo"),
+            in_memory_source: Some(Cow::from(b"source".to_vec())),
+            in_memory_bytecode: Some(Cow::from(b"bytecode".to_vec())),
+            ..Resource::default()
+        };
+        assert!(!resource.verify_content_digest());
+
+        resource.content_digest = Some(Cow::from(resource.compute_content_digest()));
+        assert_eq!(resource.content_digest.as_ref().unwrap().len(), 32);
+
+        let mut data = Vec::new();
+        write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
+        let mut resources = load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+
+        assert_eq!(resources.len(), 1);
+        assert!(resources[0].verify_content_digest());
+
+        // Moving data between fields changes the digest.
+        resources[0].in_memory_source = Some(Cow::Borrowed(b"sourcebyte"));
+        resources[0].in_memory_bytecode = Some(Cow::Borrowed(b"code"));
+        assert!(!resources[0].verify_content_digest());
+    }
+
+    #[test]
+    fn test_encrypted() {
+        let key = [1; 32];
+
+        let mut resource = Resource {
+            name: Cow::from("foo"),
+            in_memory_source: Some(Cow::from(b"source".to_vec())),
+            in_memory_bytecode: Some(Cow::from(b"bytecode".to_vec())),
+            ..Resource::default()
+        };
+        resource.encrypt(&key);
+        resource.encrypt(&key);
+
+        let mut data = Vec::new();
+        write_embedded_resources_v1(&[resource], &mut data, None).unwrap();
+        let resources = load_resources(&data)
+            .unwrap()
+            .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+
+        assert_eq!(resources.len(), 1);
+
+        let entry = &resources[0];
+        assert!(entry.is_encrypted);
+        assert!(entry.in_memory_bytecode_opt1.is_none());
+        assert_eq!(
+            crate::encryption::decrypt(&key, entry.in_memory_source.as_ref().unwrap()).unwrap(),
+            b"source".to_vec()
+        );
+        assert_eq!(
+            crate::encryption::decrypt(&key, entry.in_memory_bytecode.as_ref().unwrap()).unwrap(),
+            b"bytecode".to_vec()
+        );
+    }
+
+    fn v2_test_resources() -> Vec<Resource<'static, u8>> {
+        let mut resources = Box::new(HashMap::new());
+        resources.insert(Cow::from("data.txt"), Cow::from(b"data".to_vec()));
+
+        vec![
+            Resource {
+                name: Cow::from("foo.bar"),
+                in_memory_bytecode: Some(Cow::from(b"foo.bar bytecode".to_vec())),
+                ..Resource::default()
+            },
+            Resource {
+                name: Cow::from("foo"),
+                is_package: true,
+                in_memory_source: Some(Cow::from(b"foo source".to_vec())),
+                in_memory_bytecode: Some(Cow::from(b"foo bytecode".to_vec())),
+                in_memory_resources: Some(Arc::new(resources)),
+                ..Resource::default()
+            },
+            Resource {
+                name: Cow::from("zlib"),
+                ..Resource::default()
+            },
+            Resource {
+                name: Cow::from("abc"),
+                in_memory_source: Some(Cow::from(b"abc source".to_vec())),
+                ..Resource::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_v2_iterate() {
+        let resources = v2_test_resources();
+
+        for padding in &[None, Some(BlobInteriorPadding::Null)] {
+            let mut data = Vec::new();
+            write_embedded_resources_v2(&resources, &mut data, *padding).unwrap();
+            assert_eq!(&data[0..8], b"pyembed\x02");
+
+            let parsed = load_resources(&data)
+                .unwrap()
+                .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+                .unwrap();
+
+            assert_eq!(parsed, resources);
+        }
+    }
+
+    #[test]
+    fn test_resource_index() {
+        let resources = v2_test_resources();
+
+        for padding in &[None, Some(BlobInteriorPadding::Null)] {
+            let mut data = Vec::new();
+            write_embedded_resources_v2(&resources, &mut data, *padding).unwrap();
+
+            let index = load_resources_index(&data).unwrap();
+            assert_eq!(index.len(), 4);
+
+            for resource in &resources {
+                assert_eq!(index.get(&resource.name).unwrap().as_ref(), Some(resource));
+            }
+
+            for name in &["", "a", "foo.ba", "foo.bar.baz", "zz"] {
+                assert_eq!(index.get(name).unwrap(), None);
+            }
+
+            assert_eq!(
+                index
+                    .iter()
+                    .collect::<Result<Vec<Resource<u8>>, &'static str>>()
+                    .unwrap(),
+                resources
+            );
+        }
+    }
+
+    #[test]
+    fn test_resource_index_empty() {
+        let mut data = Vec::new();
+        write_embedded_resources_v2(&[], &mut data, None).unwrap();
+
+        let index = load_resources_index(&data).unwrap();
+        assert!(index.is_empty());
+        assert_eq!(index.get("foo").unwrap(), None);
+        assert_eq!(index.iter().next(), None);
+    }
+
+    #[test]
+    fn test_resource_index_v1() {
+        let mut data = Vec::new();
+        write_embedded_resources_v1(&v2_test_resources(), &mut data, None).unwrap();
+
+        assert_eq!(
+            load_resources_index(&data).err(),
+            Some("random access requires version 2 resources data")
+        );
     }
 }
@@ -87,6 +87,36 @@ of different sections holding data for different fields for different resources.
 But there is no internal structure or separators: all the individual
 blobs are just laid out next to each other.
 
+## `pyembed\x02`
+
+Version 2 of the embedded resources data. It extends version 1 so readers
+can look up individual resources without parsing the entire resources index.
+This keeps lookups fast for data containing tens of thousands of resources.
+
+Version 2 differs from version 1 as follows.
+
+The global header has an additional `u32` after `resources_index_length`
+denoting the length of the *name index*, `name_index_length`. The global
+header is therefore 17 bytes.
+
+The *name index* follows the *blob index* and precedes the *resources index*.
+It consists of `resources_count` fixed size entries of 10 bytes, sorted by
+resource name (compared as bytes). Each entry consists of:
+
+* A `u32` denoting the offset of the resource name within the blob section
+  holding resource names.
+* A `u16` denoting the length of the resource name.
+* A `u32` denoting the offset of the resource's entry within the *resources
+  index*.
+
+Readers can binary search the name index to find a resource's entry. Resource
+names must be unique.
+
+Every entry in the *resources index* starts with a *blob offsets* field
+(`0x12`, see below) immediately following the *start of resource entry*
+marker. So an entry can be parsed without knowing the length of the data of
+the resources preceding it.
+
 ## Blob Field Types
 
 The Blob Index allows attributing a sparse set of metadata with every blob
@@ -192,6 +222,33 @@ is contained in a `u16` that immediately follows this byte. Following this
 each shared library dependency. Each described shared library dependency
 may or may not be described by other entries in this data structure.
 
+`0x0f` - Content digest. A `u8` denoting the length in bytes of a SHA-256
+digest of the resource's in-memory data immediately follows this byte. Every
+in-memory data field is digested in field type order, each preceded by its
+field type `u8` and its length as a `u64`. Entries of in-memory resources
+data and package distribution metadata are digested in name order, names
+and values each being digested as if they were a field. Readers can use
+the digest to detect tampering with resource data.
+
+`0x10` - Encrypted flag. If encountered, the resource's in-memory Python
+module source and bytecode are encrypted. See the `encryption` module for
+the encryption scheme. Readers need the encryption key to use this data.
+
+`0x11` - `__file__` emulation. A `u8` denoting how the module's `__file__`
+attribute is defined immediately follows this byte. `0x00` means `__file__`
+is not set. `0x01` means `__file__` is a path next to the executable that
+doesn't exist. `0x02` means `__file__` is a path in an extraction directory
+to which the module's source and resources are written on import. If this
+field is absent, the reader's default policy applies.
+
+`0x12` - Blob offsets. Locates the data of this resource in blob sections.
+A `u8` denoting the number of blob sections holding data for this resource
+immediately follows this byte. Following this `u8` is an array of `(u8, u64)`
+denoting the resource field type of each blob section and the offset of this
+resource's data within that blob section, interior padding included. Fields
+of this resource holding blob data are read starting at these offsets. This
+field is only present in version 2 data.
+
 ## Design Considerations
 
 The design of the embedded resources data format was influenced by a handful
@@ -201,6 +258,10 @@ Performance is a significant consideration. We want everything to be as fast
 as possible. Possible dimensions influencing performance include parse time,
 payload size, and I/O access patterns.
 
+Version 2 trades a larger index for lookups that don't depend on the number
+of resources. A reader can load just the name index at startup and parse
+resources as they are imported, instead of parsing every resource up front.
+
 The payload is designed such that the *index* data is at the beginning
 so a reader only has to read a contiguous slice of data to fully understand
 the data within. This is in opposition to jumping around the entire data
@@ -227,9 +288,9 @@ reader has the data structure in memory, we don't want to require it
 to copy memory in order to reference entries. In Rust speak, we should
 be able to hold `&[u8]` references everywhere.
 
-There is no checksumming of the data because we don't want to incur
-I/O overhead to read the entire blob. It could be added as an optional
-feature.
+Checksumming of data is optional and per resource, so readers don't have
+to incur I/O overhead to read the entire blob. A reader can verify the
+digest of an individual resource when it is accessed.
 
 A potential area for optimization is use of general compression. Various
 fields should compress well - either in streaming mode or by utilizing
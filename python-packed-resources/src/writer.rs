@@ -7,14 +7,46 @@ Embedded Python resources in a binary.
 */
 
 use {
-    super::data::{BlobInteriorPadding, BlobSectionField, Resource, ResourceField, HEADER_V1},
+    super::data::{
+        BlobInteriorPadding, BlobSectionField, Resource, ResourceField, HEADER_V1, HEADER_V2,
+        NAME_INDEX_ENTRY_LENGTH,
+    },
     anyhow::{anyhow, Context, Result},
     byteorder::{LittleEndian, WriteBytesExt},
-    std::collections::BTreeMap,
+    std::borrow::Cow,
+    std::collections::{BTreeMap, HashMap},
     std::convert::TryFrom,
-    std::io::Write,
+    std::io::{Cursor, Read, Seek, SeekFrom, Write},
 };
 
+/// Obtain the entries of a map of named data sorted by name.
+///
+/// `HashMap` iteration order is randomized. Sorting makes serialized
+/// resources deterministic.
+fn sorted_entries<'m, 'a, X: Clone>(
+    map: &'m HashMap<Cow<'a, str>, Cow<'a, [X]>>,
+) -> Vec<(&'m Cow<'a, str>, &'m Cow<'a, [X]>)> {
+    let mut entries = map.iter().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+
+    entries
+}
+
+/// Resource fields holding data in blob sections, in blob section order.
+const BLOB_FIELDS: &[ResourceField] = &[
+    ResourceField::ModuleName,
+    ResourceField::InMemorySource,
+    ResourceField::InMemoryBytecode,
+    ResourceField::InMemoryBytecodeOpt1,
+    ResourceField::InMemoryBytecodeOpt2,
+    ResourceField::InMemoryExtensionModuleSharedLibrary,
+    ResourceField::InMemoryResourcesData,
+    ResourceField::InMemoryPackageDistribution,
+    ResourceField::InMemorySharedLibrary,
+    ResourceField::SharedLibraryDependencyNames,
+    ResourceField::ContentDigest,
+];
+
 #[derive(Debug)]
 struct BlobSection {
     resource_field: ResourceField,
@@ -150,12 +182,33 @@ where
             index += 3 + 2 * names.len();
         }
 
+        if self.content_digest.is_some() {
+            index += 2;
+        }
+
+        if self.is_encrypted {
+            index += 1;
+        }
+
+        if self.file_emulation.is_some() {
+            index += 2;
+        }
+
         // End of index entry.
         index += 1;
 
         index
     }
 
+    /// Compute length of index entry for version 2 payload format.
+    ///
+    /// `blob_offsets_count` is the number of blob sections holding data for
+    /// this resource.
+    pub fn index_v2_length(&self, blob_offsets_count: usize) -> usize {
+        // Blob offsets field + count + field and u64 offset for each section.
+        self.index_v1_length() + 2 + 9 * blob_offsets_count
+    }
+
     /// Compute the length of a field.
     ///
     /// Interior padding is not part of the returned length.
@@ -164,9 +217,12 @@ where
             ResourceField::EndOfIndex => 0,
             ResourceField::StartOfEntry => 0,
             ResourceField::EndOfEntry => 0,
-            ResourceField::ModuleName => self.name.as_bytes().len(),
+            ResourceField::ModuleName => self.name.len(),
             ResourceField::IsPackage => 0,
             ResourceField::IsNamespacePackage => 0,
+            ResourceField::IsEncrypted => 0,
+            ResourceField::FileEmulation => 0,
+            ResourceField::BlobOffsets => 0,
             ResourceField::InMemorySource => {
                 if let Some(source) = &self.in_memory_source {
                     source.len()
@@ -206,7 +262,7 @@ where
                 if let Some(resources) = &self.in_memory_resources {
                     resources
                         .iter()
-                        .map(|(key, value)| key.as_bytes().len() + value.len())
+                        .map(|(key, value)| key.len() + value.len())
                         .sum()
                 } else {
                     0
@@ -216,7 +272,7 @@ where
                 if let Some(metadata) = &self.in_memory_package_distribution {
                     metadata
                         .iter()
-                        .map(|(key, value)| key.as_bytes().len() + value.len())
+                        .map(|(key, value)| key.len() + value.len())
                         .sum()
                 } else {
                     0
@@ -231,7 +287,14 @@ where
             }
             ResourceField::SharedLibraryDependencyNames => {
                 if let Some(names) = &self.shared_library_dependency_names {
-                    names.iter().map(|s| s.as_bytes().len()).sum()
+                    names.iter().map(|s| s.len()).sum()
+                } else {
+                    0
+                }
+            }
+            ResourceField::ContentDigest => {
+                if let Some(digest) = &self.content_digest {
+                    digest.len()
                 } else {
                     0
                 }
@@ -252,6 +315,9 @@ where
             ResourceField::ModuleName => 1,
             ResourceField::IsPackage => 0,
             ResourceField::IsNamespacePackage => 0,
+            ResourceField::IsEncrypted => 0,
+            ResourceField::FileEmulation => 0,
+            ResourceField::BlobOffsets => 0,
             ResourceField::InMemorySource => {
                 if self.in_memory_source.is_some() {
                     1
@@ -315,6 +381,13 @@ where
                     0
                 }
             }
+            ResourceField::ContentDigest => {
+                if self.content_digest.is_some() {
+                    1
+                } else {
+                    0
+                }
+            }
         };
 
         let overhead = match padding {
@@ -327,12 +400,46 @@ where
 
     /// Write the version 1 index entry for a module instance.
     pub fn write_index_v1<W: Write>(&self, dest: &mut W) -> Result<()> {
-        let name_len =
-            u16::try_from(self.name.as_bytes().len()).context("converting name to u16")?;
+        self.write_index(dest, None)
+    }
+
+    /// Write the version 2 index entry for a module instance.
+    ///
+    /// `blob_offsets` holds the offset of this resource's data within each
+    /// blob section holding data for it.
+    pub fn write_index_v2<W: Write>(
+        &self,
+        dest: &mut W,
+        blob_offsets: &[(ResourceField, u64)],
+    ) -> Result<()> {
+        self.write_index(dest, Some(blob_offsets))
+    }
+
+    fn write_index<W: Write>(
+        &self,
+        dest: &mut W,
+        blob_offsets: Option<&[(ResourceField, u64)]>,
+    ) -> Result<()> {
+        let name_len = u16::try_from(self.name.len()).context("converting name to u16")?;
 
         dest.write_u8(ResourceField::StartOfEntry.into())
             .context("writing start of index entry")?;
 
+        // Offsets must precede the fields whose data they locate.
+        if let Some(offsets) = blob_offsets {
+            let l = u8::try_from(offsets.len()).context("converting blob offsets count to u8")?;
+            dest.write_u8(ResourceField::BlobOffsets.into())
+                .context("writing blob offsets field")?;
+            dest.write_u8(l).context("writing blob offsets count")?;
+
+            for (field, offset) in offsets {
+                dest.write_u8((*field).into())
+                    .context("writing blob offset field type")?;
+                dest.write_u64::<LittleEndian>(*offset)
+                    .context("writing blob offset")?;
+            }
+        }
+
         dest.write_u8(ResourceField::ModuleName.into())
             .context("writing module name field")?;
 
@@ -349,6 +456,18 @@ where
                 .context("writing is_namespace field")?;
         }
 
+        if self.is_encrypted {
+            dest.write_u8(ResourceField::IsEncrypted.into())
+                .context("writing is_encrypted field")?;
+        }
+
+        if let Some(file_emulation) = self.file_emulation {
+            dest.write_u8(ResourceField::FileEmulation.into())
+                .context("writing file emulation field")?;
+            dest.write_u8(file_emulation.into())
+                .context("writing file emulation value")?;
+        }
+
         if let Some(source) = &self.in_memory_source {
             let l =
                 u32::try_from(source.len()).context("converting in-memory source length to u32")?;
@@ -402,9 +521,9 @@ where
             dest.write_u32::<LittleEndian>(l)
                 .context("writing in-memory resources data length")?;
 
-            for (name, value) in resources.iter() {
-                let name_length = u16::try_from(name.as_bytes().len())
-                    .context("converting resource name length to u16")?;
+            for (name, value) in sorted_entries(resources) {
+                let name_length =
+                    u16::try_from(name.len()).context("converting resource name length to u16")?;
                 dest.write_u16::<LittleEndian>(name_length)
                     .context("writing resource name length")?;
                 dest.write_u64::<LittleEndian>(value.len() as u64)
@@ -420,8 +539,8 @@ where
             dest.write_u32::<LittleEndian>(l)
                 .context("writing in-memory package distribution length")?;
 
-            for (name, value) in metadata {
-                let name_length = u16::try_from(name.as_bytes().len())
+            for (name, value) in sorted_entries(metadata) {
+                let name_length = u16::try_from(name.len())
                     .context("converting distribution name length to u16")?;
                 dest.write_u16::<LittleEndian>(name_length)
                     .context("writing distribution name length")?;
@@ -448,15 +567,22 @@ where
                 .context("writing shared library dependency names length")?;
 
             for name in names {
-                let name_length = u16::try_from(name.as_bytes().len())
+                let name_length = u16::try_from(name.len())
                     .context("converting shared library dependency name length to u16")?;
                 dest.write_u16::<LittleEndian>(name_length)
                     .context("writing shared library dependency name length")?;
             }
         }
 
+        if let Some(digest) = &self.content_digest {
+            let l = u8::try_from(digest.len()).context("converting content digest length to u8")?;
+            dest.write_u8(ResourceField::ContentDigest.into())
+                .context("writing content digest field")?;
+            dest.write_u8(l).context("writing content digest length")?;
+        }
+
         dest.write_u8(ResourceField::EndOfEntry.into())
-            .or_else(|_| Err(anyhow!("error writing end of index entry")))?;
+            .map_err(|_| anyhow!("error writing end of index entry"))?;
 
         Ok(())
     }
@@ -465,44 +591,53 @@ where
 /// Write an embedded resources blob, version 1.
 ///
 /// See the `pyembed` crate for the format of this data structure.
-#[allow(clippy::cognitive_complexity)]
-pub fn write_embedded_resources_v1<'a, W: Write>(
+pub fn write_embedded_resources_v1<W: Write>(
     modules: &[Resource<u8>],
     dest: &mut W,
     interior_padding: Option<BlobInteriorPadding>,
 ) -> Result<()> {
-    let mut blob_sections = BTreeMap::new();
+    write_embedded_resources(modules, dest, interior_padding, 1)
+}
 
-    let mut blob_section_count = 0;
-    // 1 for end of index field.
-    let mut blob_index_length = 1;
+/// Write an embedded resources blob, version 2.
+///
+/// Version 2 adds an index of resources sorted by name, allowing readers to
+/// look up individual resources without parsing all of them. Resource names
+/// must be unique.
+pub fn write_embedded_resources_v2<W: Write>(
+    modules: &[Resource<u8>],
+    dest: &mut W,
+    interior_padding: Option<BlobInteriorPadding>,
+) -> Result<()> {
+    write_embedded_resources(modules, dest, interior_padding, 2)
+}
 
-    // 1 for end of index field.
-    let mut module_index_length = 1;
+fn write_embedded_resources<W: Write>(
+    modules: &[Resource<u8>],
+    dest: &mut W,
+    interior_padding: Option<BlobInteriorPadding>,
+    version: u8,
+) -> Result<()> {
+    let mut writer =
+        ResourcesWriter::new(version, interior_padding, |_| Ok(Cursor::new(Vec::new())))?;
 
-    let process_field = |blob_sections: &mut BTreeMap<ResourceField, BlobSection>,
-                         resource: &Resource<u8>,
-                         field: ResourceField| {
-        let padding = match &interior_padding {
-            Some(padding) => *padding,
-            None => BlobInteriorPadding::None,
-        };
+    for module in modules {
+        writer.add(module)?;
+    }
 
-        let l = resource.field_blob_length(field)
-            + resource.field_blob_interior_padding_length(field, padding);
-        if l > 0 {
-            blob_sections
-                .entry(field)
-                .or_insert_with(|| BlobSection {
-                    resource_field: field,
-                    raw_payload_length: 0,
-                    interior_padding,
-                })
-                .raw_payload_length += l;
-        }
-    };
+    writer.finish(dest)
+}
+
+/// Write the data of a resource's field to its blob section.
+fn write_blob_field<W: Write>(
+    module: &Resource<u8>,
+    field: ResourceField,
+    dest: &mut W,
+    interior_padding: Option<BlobInteriorPadding>,
+) -> Result<()> {
+    let mut write = |data: &[u8]| -> Result<()> {
+        dest.write_all(data)?;
 
-    let add_interior_padding = |dest: &mut W| -> Result<()> {
         if interior_padding == Some(BlobInteriorPadding::Null) {
             dest.write_all(b"\0")?;
         }
@@ -510,158 +645,254 @@ pub fn write_embedded_resources_v1<'a, W: Write>(
         Ok(())
     };
 
-    for module in modules {
-        module_index_length += module.index_v1_length();
-
-        process_field(&mut blob_sections, module, ResourceField::ModuleName);
-        process_field(&mut blob_sections, module, ResourceField::InMemorySource);
-        process_field(&mut blob_sections, module, ResourceField::InMemoryBytecode);
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::InMemoryBytecodeOpt1,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::InMemoryBytecodeOpt2,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::InMemoryExtensionModuleSharedLibrary,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::InMemoryResourcesData,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::InMemoryPackageDistribution,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::InMemorySharedLibrary,
-        );
-        process_field(
-            &mut blob_sections,
-            module,
-            ResourceField::SharedLibraryDependencyNames,
-        );
-    }
+    let data = match field {
+        ResourceField::ModuleName => Some(module.name.as_bytes()),
+        ResourceField::InMemorySource => module.in_memory_source.as_ref().map(|x| &x[..]),
+        ResourceField::InMemoryBytecode => module.in_memory_bytecode.as_ref().map(|x| &x[..]),
+        ResourceField::InMemoryBytecodeOpt1 => {
+            module.in_memory_bytecode_opt1.as_ref().map(|x| &x[..])
+        }
+        ResourceField::InMemoryBytecodeOpt2 => {
+            module.in_memory_bytecode_opt2.as_ref().map(|x| &x[..])
+        }
+        ResourceField::InMemoryExtensionModuleSharedLibrary => module
+            .in_memory_extension_module_shared_library
+            .as_ref()
+            .map(|x| &x[..]),
+        ResourceField::InMemorySharedLibrary => {
+            module.in_memory_shared_library.as_ref().map(|x| &x[..])
+        }
+        ResourceField::ContentDigest => module.content_digest.as_ref().map(|x| &x[..]),
+        ResourceField::InMemoryResourcesData => {
+            if let Some(resources) = &module.in_memory_resources {
+                for (key, value) in sorted_entries(resources) {
+                    write(key.as_bytes())?;
+                    write(&value[..])?;
+                }
+            }
 
-    for section in blob_sections.values() {
-        blob_section_count += 1;
-        blob_index_length += section.index_v1_length();
-    }
+            None
+        }
+        ResourceField::InMemoryPackageDistribution => {
+            if let Some(resources) = &module.in_memory_package_distribution {
+                for (key, value) in sorted_entries(resources) {
+                    write(key.as_bytes())?;
+                    write(&value[..])?;
+                }
+            }
 
-    dest.write_all(HEADER_V1)?;
+            None
+        }
+        ResourceField::SharedLibraryDependencyNames => {
+            if let Some(names) = &module.shared_library_dependency_names {
+                for name in names {
+                    write(name.as_bytes())?;
+                }
+            }
 
-    dest.write_u8(blob_section_count)?;
-    dest.write_u32::<LittleEndian>(blob_index_length as u32)?;
-    dest.write_u32::<LittleEndian>(modules.len() as u32)?;
-    dest.write_u32::<LittleEndian>(module_index_length as u32)?;
+            None
+        }
+        _ => None,
+    };
 
-    // Write the blob index.
-    for section in blob_sections.values() {
-        section.write_index_v1(dest)?;
+    if let Some(data) = data {
+        write(data)?;
     }
-    dest.write_u8(ResourceField::EndOfIndex.into())?;
 
-    // Write the resources index.
-    for module in modules {
-        module.write_index_v1(dest)?;
-    }
-    dest.write_u8(ResourceField::EndOfIndex.into())?;
+    Ok(())
+}
 
-    // Write blob data, one field at a time.
-    for module in modules {
-        dest.write_all(module.name.as_bytes())?;
-        add_interior_padding(dest)?;
-    }
+/// Writes packed resources data one resource at a time.
+///
+/// The data of added resources is written to storage for each blob section
+/// obtained from a function, e.g. files in a scratch directory. Only the
+/// index is held in memory, so resources can be produced, written, and
+/// dropped one at a time. `finish()` writes the header and index, followed
+/// by the content of each blob section.
+///
+/// Resources are written in the order they are added. The data written is
+/// identical to what `write_embedded_resources_v1()` and
+/// `write_embedded_resources_v2()` write for resources in the same order.
+pub struct ResourcesWriter<S: Read + Write + Seek> {
+    version: u8,
+    interior_padding: Option<BlobInteriorPadding>,
+    new_section: Box<dyn FnMut(ResourceField) -> std::io::Result<S>>,
 
-    for module in modules {
-        if let Some(data) = &module.in_memory_source {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
+    /// Storage of blob sections holding data and the length of their data.
+    sections: BTreeMap<ResourceField, (S, usize)>,
+
+    /// Index entries of added resources.
+    index: Vec<u8>,
+
+    /// Name, offset within the name blob section, and offset of the index
+    /// entry of added resources.
+    names: Vec<(String, u64, usize)>,
+}
+
+impl<S: Read + Write + Seek> ResourcesWriter<S> {
+    /// Construct an instance writing packed resources data of a version.
+    ///
+    /// `new_section` is called to obtain empty storage for a blob section the
+    /// first time a resource holds data for it.
+    pub fn new<F>(
+        version: u8,
+        interior_padding: Option<BlobInteriorPadding>,
+        new_section: F,
+    ) -> Result<Self>
+    where
+        F: FnMut(ResourceField) -> std::io::Result<S> + 'static,
+    {
+        if version != 1 && version != 2 {
+            return Err(anyhow!("unsupported packed resources version {}", version));
         }
+
+        Ok(Self {
+            version,
+            interior_padding,
+            new_section: Box::new(new_section),
+            sections: BTreeMap::new(),
+            index: Vec::new(),
+            names: Vec::new(),
+        })
     }
 
-    for module in modules {
-        if let Some(data) = &module.in_memory_bytecode {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
+    /// Add a resource.
+    pub fn add(&mut self, module: &Resource<u8>) -> Result<()> {
+        let padding = match &self.interior_padding {
+            Some(padding) => *padding,
+            None => BlobInteriorPadding::None,
+        };
+
+        // Offset of the resource's data within each blob section.
+        let mut offsets = Vec::new();
+
+        for field in BLOB_FIELDS {
+            let l = module.field_blob_length(*field)
+                + module.field_blob_interior_padding_length(*field, padding);
+
+            if l == 0 {
+                continue;
+            }
+
+            if !self.sections.contains_key(field) {
+                let section = (self.new_section)(*field).context("creating blob section")?;
+                self.sections.insert(*field, (section, 0));
+            }
+
+            let (section, length) = self.sections.get_mut(field).unwrap();
+            offsets.push((*field, *length as u64));
+            write_blob_field(module, *field, section, self.interior_padding)
+                .context("writing blob section")?;
+            *length += l;
         }
-    }
 
-    for module in modules {
-        if let Some(data) = &module.in_memory_bytecode_opt1 {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
+        let index_offset = self.index.len();
+
+        if self.version == 2 {
+            module.write_index_v2(&mut self.index, &offsets)?;
+        } else {
+            module.write_index_v1(&mut self.index)?;
         }
+
+        let name_offset = offsets
+            .iter()
+            .find(|(field, _)| *field == ResourceField::ModuleName)
+            .map(|(_, offset)| *offset)
+            .unwrap_or(0);
+
+        self.names
+            .push((module.name.to_string(), name_offset, index_offset));
+
+        Ok(())
     }
 
-    for module in modules {
-        if let Some(data) = &module.in_memory_bytecode_opt2 {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
+    /// Write the packed resources data of added resources.
+    pub fn finish<W: Write>(mut self, dest: &mut W) -> Result<()> {
+        let blob_sections = self
+            .sections
+            .iter()
+            .map(|(field, (_, length))| BlobSection {
+                resource_field: *field,
+                raw_payload_length: *length,
+                interior_padding: self.interior_padding,
+            })
+            .collect::<Vec<_>>();
+
+        // 1 for end of index field.
+        let blob_index_length = 1 + blob_sections
+            .iter()
+            .map(|section| section.index_v1_length())
+            .sum::<usize>();
+        let module_index_length = 1 + self.index.len();
+
+        dest.write_all(if self.version == 2 {
+            HEADER_V2
+        } else {
+            HEADER_V1
+        })?;
+
+        dest.write_u8(blob_sections.len() as u8)?;
+        dest.write_u32::<LittleEndian>(blob_index_length as u32)?;
+        dest.write_u32::<LittleEndian>(self.names.len() as u32)?;
+        dest.write_u32::<LittleEndian>(module_index_length as u32)?;
+        if self.version == 2 {
+            dest.write_u32::<LittleEndian>((self.names.len() * NAME_INDEX_ENTRY_LENGTH) as u32)?;
         }
-    }
 
-    for module in modules {
-        if let Some(data) = &module.in_memory_extension_module_shared_library {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
+        // Write the blob index.
+        for section in &blob_sections {
+            section.write_index_v1(dest)?;
         }
-    }
+        dest.write_u8(ResourceField::EndOfIndex.into())?;
 
-    for module in modules {
-        if let Some(resources) = &module.in_memory_resources {
-            for (key, value) in resources.iter() {
-                dest.write_all(key.as_bytes())?;
-                add_interior_padding(dest)?;
-                dest.write_all(value)?;
-                add_interior_padding(dest)?;
+        // Write the name index, sorted by name for binary search.
+        if self.version == 2 {
+            let names = &self.names;
+            let mut order = (0..names.len()).collect::<Vec<_>>();
+            order.sort_by(|a, b| names[*a].0.cmp(&names[*b].0));
+
+            for pair in order.windows(2) {
+                if names[pair[0]].0 == names[pair[1]].0 {
+                    return Err(anyhow!("duplicate resource name: {}", names[pair[0]].0));
+                }
             }
-        }
-    }
 
-    for module in modules {
-        if let Some(resources) = &module.in_memory_package_distribution {
-            for (key, value) in resources {
-                dest.write_all(key.as_bytes())?;
-                add_interior_padding(dest)?;
-                dest.write_all(value)?;
-                add_interior_padding(dest)?;
+            for i in order {
+                let (name, name_offset, index_offset) = &names[i];
+
+                dest.write_u32::<LittleEndian>(
+                    u32::try_from(*name_offset).context("converting name offset to u32")?,
+                )?;
+                dest.write_u16::<LittleEndian>(
+                    u16::try_from(name.len()).context("converting name to u16")?,
+                )?;
+                dest.write_u32::<LittleEndian>(
+                    u32::try_from(*index_offset).context("converting index offset to u32")?,
+                )?;
             }
         }
-    }
 
-    for module in modules {
-        if let Some(data) = &module.in_memory_shared_library {
-            dest.write_all(data)?;
-            add_interior_padding(dest)?;
-        }
-    }
+        // Write the resources index.
+        dest.write_all(&self.index)?;
+        dest.write_u8(ResourceField::EndOfIndex.into())?;
 
-    for module in modules {
-        if let Some(names) = &module.shared_library_dependency_names {
-            for name in names {
-                dest.write_all(name.as_bytes())?;
-                add_interior_padding(dest)?;
-            }
+        // Write blob data, one section at a time.
+        for (section, _) in self.sections.values_mut() {
+            section.seek(SeekFrom::Start(0))?;
+            std::io::copy(section, dest).context("copying blob section")?;
         }
-    }
 
-    Ok(())
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use {super::*, std::borrow::Cow};
+    use {
+        super::*,
+        std::borrow::Cow,
+        std::{cell::RefCell, rc::Rc},
+    };
 
     #[test]
     fn test_write_empty() -> Result<()> {
@@ -726,4 +957,102 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_sorted_entries() {
+        let mut map: HashMap<Cow<str>, Cow<[u8]>> = HashMap::new();
+        for name in &["c", "a", "d", "b"] {
+            map.insert(Cow::Borrowed(*name), Cow::Borrowed(b"data"));
+        }
+
+        assert_eq!(
+            sorted_entries(&map)
+                .iter()
+                .map(|(k, _)| k.as_ref())
+                .collect::<Vec<_>>(),
+            vec!["a", "b", "c", "d"]
+        );
+    }
+
+    #[test]
+    fn test_write_v2_empty() -> Result<()> {
+        let mut data = Vec::new();
+        write_embedded_resources_v2(&[], &mut data, None)?;
+
+        let mut expected: Vec<u8> = b"pyembed\x02".to_vec();
+        // Number of blob sections.
+        expected.write_u8(0)?;
+        // Length of blob index (end of index marker).
+        expected.write_u32::<LittleEndian>(1)?;
+        // Number of modules.
+        expected.write_u32::<LittleEndian>(0)?;
+        // Length of index (end of index marker).
+        expected.write_u32::<LittleEndian>(1)?;
+        // Length of name index.
+        expected.write_u32::<LittleEndian>(0)?;
+        // End of index for blob and modules.
+        expected.write_u8(0)?;
+        expected.write_u8(0)?;
+
+        assert_eq!(data, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_v2_duplicate_name() {
+        let module = Resource {
+            name: Cow::Owned("foo".to_string()),
+            ..Resource::default()
+        };
+
+        let mut data = Vec::new();
+        let err = write_embedded_resources_v2(&[module.clone(), module], &mut data, None)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "duplicate resource name: foo");
+    }
+
+    #[test]
+    fn test_resources_writer() -> Result<()> {
+        let resources = vec![
+            Resource {
+                name: Cow::Owned("foo".to_string()),
+                in_memory_source: Some(Cow::Owned(b"import bar".to_vec())),
+                ..Resource::default()
+            },
+            Resource {
+                name: Cow::Owned("bar".to_string()),
+                ..Resource::default()
+            },
+        ];
+
+        let created = Rc::new(RefCell::new(Vec::new()));
+        let created_sections = created.clone();
+        let mut writer = ResourcesWriter::new(2, Some(BlobInteriorPadding::Null), move |field| {
+            created_sections.borrow_mut().push(field);
+            Ok(Cursor::new(Vec::new()))
+        })?;
+        for resource in &resources {
+            writer.add(resource)?;
+        }
+
+        let mut data = Vec::new();
+        writer.finish(&mut data)?;
+
+        assert_eq!(
+            *created.borrow(),
+            vec![ResourceField::ModuleName, ResourceField::InMemorySource]
+        );
+
+        let parsed = crate::parser::load_resources(&data)
+            .unwrap()
+            .collect::<std::result::Result<Vec<Resource<u8>>, &'static str>>()
+            .unwrap();
+        assert_eq!(parsed, resources);
+
+        assert!(ResourcesWriter::new(3, None, |_| Ok(Cursor::new(Vec::new()))).is_err());
+
+        Ok(())
+    }
 }